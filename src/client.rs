@@ -1,5 +1,10 @@
 //! Clients for high level interactions with TUF repositories.
 //!
+//! The client is asynchronous and does not depend on a particular executor. Its futures are `Send`
+//! as long as the repositories are, so [`Client::update`] and [`Client::fetch_target`] can be
+//! awaited from tasks on a multi-threaded runtime such as tokio's. Note that
+//! [`FileSystemRepository`](crate::repository::FileSystemRepository) performs blocking file I/O.
+//!
 //! # Example
 //!
 //! ```no_run
@@ -50,24 +55,55 @@
 //! # }
 //! ```
 
-use chrono::offset::Utc;
+use chrono::Duration;
 use futures_io::{AsyncRead, AsyncWrite};
-use futures_util::io::copy;
-use log::{error, warn};
-use std::future::Future;
-use std::pin::Pin;
-
-use crate::crypto::{self, HashAlgorithm, HashValue, KeyId, PublicKey};
+use futures_util::io::{copy, AllowStdIo, AsyncReadExt, Cursor};
+use futures_util::stream::{self, TryStreamExt};
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::crypto::{self, HashAlgorithm, HashValue, KeyId, PublicKey, SafeReader, SchemePolicy};
 use crate::error::Error;
-use crate::interchange::DataInterchange;
+use crate::interchange::{DataInterchange, JsonLimits};
 use crate::metadata::{
-    Metadata, MetadataPath, MetadataVersion, RawSignedMetadata, Role, RootMetadata, SignedMetadata,
-    SnapshotMetadata, TargetDescription, TargetPath, TargetsMetadata, VirtualTargetPath,
+    Metadata, MetadataDescription, MetadataPath, MetadataVersion, RawSignedMetadata, Role,
+    RootMetadata, SignedMetadata, TargetDescription, TargetPath, TargetsMetadata,
+    VirtualTargetPath,
 };
 use crate::repository::{Repository, RepositoryProvider, RepositoryStorage};
-use crate::tuf::Tuf;
+use crate::tuf::{AuditHook, Clock, DangerZone, SharedAuditHook, SharedClock, Tuf};
+use crate::util;
 use crate::Result;
 
+mod cancel;
+mod local_store;
+mod multi_repository;
+#[cfg(feature = "tokio")]
+mod refresh;
+mod retry;
+mod summary;
+mod versions;
+
+pub use self::cancel::CancellationToken;
+pub use self::local_store::{FileLocalStore, LocalStore};
+pub use self::multi_repository::{MapFile, Mapping, MultiRepositoryClient};
+#[cfg(feature = "tokio")]
+pub use self::refresh::{AutoRefresh, RefreshConfig, RefreshConfigBuilder, RefreshStatus};
+pub use self::retry::{RetryPolicy, RetryPolicyBuilder};
+pub use self::summary::{UpdateResult, UpdateSummary, UpdateWarning, VersionChange};
+pub use self::versions::{FileVersionStore, VersionStore};
+
+use self::cancel::CancellableRead;
+use self::local_store::{SharedLocalStore, SignedState};
+use self::retry::IdleTimeoutRead;
+use self::summary::TrustedState;
+use self::versions::SharedVersionStore;
+
 /// Translates real paths (where a file is stored) into virtual paths (how it is addressed in TUF)
 /// and back.
 ///
@@ -113,6 +149,141 @@ impl PathTranslator for DefaultTranslator {
     }
 }
 
+/// An additional check run on a target after its length and hashes have been verified against
+/// the TUF metadata, but before the client stores it or hands it to the caller.
+///
+/// Verifiers can be used to veto targets that are authentic but still undesirable, such as
+/// firmware images with an unexpected header. Because the whole target must be available to the
+/// verifier, configuring any verifiers causes targets to be buffered in memory while fetching.
+///
+/// Any `Fn(&TargetPath, &TargetDescription, &[u8]) -> Result<()>` closure is a `TargetVerifier`.
+pub trait TargetVerifier: Send + Sync {
+    /// Check the verified contents of `target`. Verifiers should reject a target by returning
+    /// [`Error::TargetRejected`].
+    fn verify(
+        &self,
+        path: &TargetPath,
+        description: &TargetDescription,
+        target: &[u8],
+    ) -> Result<()>;
+}
+
+impl<F> TargetVerifier for F
+where
+    F: Fn(&TargetPath, &TargetDescription, &[u8]) -> Result<()> + Send + Sync,
+{
+    fn verify(
+        &self,
+        path: &TargetPath,
+        description: &TargetDescription,
+        target: &[u8],
+    ) -> Result<()> {
+        self(path, description, target)
+    }
+}
+
+/// The list of `TargetVerifier`s in a `Config`.
+#[derive(Clone, Default)]
+struct TargetVerifiers(Vec<Arc<dyn TargetVerifier>>);
+
+impl fmt::Debug for TargetVerifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TargetVerifiers({})", self.0.len())
+    }
+}
+
+impl PartialEq for TargetVerifiers {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(other.0.iter())
+                .all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+/// Decides where in the remote repository a target is fetched from, for repositories whose
+/// targets are not laid out 1:1 with their paths in the TUF metadata, such as CDNs that shard
+/// targets by hash.
+///
+/// Without a `TargetLayout`, a target is fetched from the real path produced by the
+/// `PathTranslator`, prefixed with the target's hash when the root metadata enables consistent
+/// snapshots (TUF section 5.5.2). Whatever location a layout picks, the fetched bytes are still
+/// verified against the target's description.
+///
+/// Any `Fn(&VirtualTargetPath, &TargetDescription, bool) -> Result<TargetPath>` closure is a
+/// `TargetLayout`.
+///
+/// ```
+/// # use tuf::client::{Config, TargetLayout};
+/// # use tuf::crypto::HashAlgorithm;
+/// # use tuf::metadata::{TargetDescription, TargetPath, VirtualTargetPath};
+/// // Store targets under `by-hash/$HASH`, regardless of their name.
+/// let layout = |_: &VirtualTargetPath, description: &TargetDescription, _: bool| {
+///     let hash = &description.hashes()[&HashAlgorithm::Sha256];
+///     TargetPath::new(format!("by-hash/{}", hash))
+/// };
+///
+/// let path = VirtualTargetPath::new("foo/bar".into()).unwrap();
+/// let description =
+///     TargetDescription::from_reader(&b"bar"[..], &[HashAlgorithm::Sha256]).unwrap();
+/// assert_eq!(
+///     layout.remote_path(&path, &description, false).unwrap().value(),
+///     "by-hash/fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9",
+/// );
+///
+/// let config = Config::build().target_layout(layout).finish().unwrap();
+/// assert!(config.target_layout().is_some());
+/// ```
+pub trait TargetLayout: Send + Sync {
+    /// The path in the remote repository to fetch the target at `path` from.
+    /// `consistent_snapshot` is the value from the trusted root metadata.
+    fn remote_path(
+        &self,
+        path: &VirtualTargetPath,
+        description: &TargetDescription,
+        consistent_snapshot: bool,
+    ) -> Result<TargetPath>;
+}
+
+impl<F> TargetLayout for F
+where
+    F: Fn(&VirtualTargetPath, &TargetDescription, bool) -> Result<TargetPath> + Send + Sync,
+{
+    fn remote_path(
+        &self,
+        path: &VirtualTargetPath,
+        description: &TargetDescription,
+        consistent_snapshot: bool,
+    ) -> Result<TargetPath> {
+        self(path, description, consistent_snapshot)
+    }
+}
+
+/// The optional `TargetLayout` in a `Config`.
+#[derive(Clone, Default)]
+struct SharedTargetLayout(Option<Arc<dyn TargetLayout>>);
+
+impl fmt::Debug for SharedTargetLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "SharedTargetLayout(Some(..))"),
+            None => write!(f, "SharedTargetLayout(None)"),
+        }
+    }
+}
+
+impl PartialEq for SharedTargetLayout {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
 /// A client that interacts with TUF repositories.
 #[derive(Debug)]
 pub struct Client<D, L, R, T>
@@ -123,6 +294,8 @@ where
     T: PathTranslator,
 {
     tuf: Tuf<D>,
+    /// The signed metadata behind `tuf`, kept for the `LocalStore`.
+    signed: SignedState<D>,
     config: Config<T>,
     local: Repository<L, D>,
     remote: Repository<R, D>,
@@ -195,7 +368,10 @@ where
     /// # }
     /// ```
     pub async fn with_trusted_local(config: Config<T>, local: L, remote: R) -> Result<Self> {
-        let (local, remote) = (Repository::new(local), Repository::new(remote));
+        let (local, remote) = (
+            Repository::new(local).with_json_limits(config.json_limits),
+            Repository::new(remote).with_json_limits(config.json_limits),
+        );
         let root_path = MetadataPath::from_role(&Role::Root);
 
         // FIXME should this be MetadataVersion::None so we bootstrap with the latest version?
@@ -206,13 +382,7 @@ where
             .await?;
 
         let tuf = Tuf::from_trusted_root(root)?;
-
-        Ok(Client {
-            tuf,
-            config,
-            local,
-            remote,
-        })
+        Self::from_tuf(config, tuf, local, remote).await
     }
 
     /// Create a new TUF client. It will trust this initial root metadata.
@@ -269,15 +439,12 @@ where
         local: L,
         remote: R,
     ) -> Result<Self> {
-        let (local, remote) = (Repository::new(local), Repository::new(remote));
+        let (local, remote) = (
+            Repository::new(local).with_json_limits(config.json_limits),
+            Repository::new(remote).with_json_limits(config.json_limits),
+        );
         let tuf = Tuf::from_trusted_root(trusted_root)?;
-
-        Ok(Client {
-            tuf,
-            config,
-            local,
-            remote,
-        })
+        Self::from_tuf(config, tuf, local, remote).await
     }
 
     /// Create a new TUF client. It will attempt to load initial root metadata the local and remote
@@ -353,7 +520,10 @@ where
     where
         I: IntoIterator<Item = &'a KeyId>,
     {
-        let (local, remote) = (Repository::new(local), Repository::new(remote));
+        let (local, remote) = (
+            Repository::new(local).with_json_limits(config.json_limits),
+            Repository::new(remote).with_json_limits(config.json_limits),
+        );
 
         let root_path = MetadataPath::from_role(&Role::Root);
         let (fetched, raw_trusted_root, trusted_root) = fetch_metadata_from_local_or_else_remote(
@@ -363,6 +533,7 @@ where
             None,
             &local,
             &remote,
+            &config.retry_policy,
         )
         .await?;
 
@@ -376,16 +547,10 @@ where
 
             Tuf::from_root_with_trusted_keys(trusted_root, root_threshold, trusted_root_keys)?
         };
-
         // FIXME(#253) verify the trusted root version matches the provided version.
         let root_version = MetadataVersion::Number(tuf.root().version());
 
-        let mut client = Client {
-            tuf,
-            config,
-            local,
-            remote,
-        };
+        let mut client = Self::from_tuf(config, tuf, local, remote).await?;
 
         // Only store the metadata after we have validated it.
         if fetched {
@@ -470,7 +635,10 @@ where
     where
         I: IntoIterator<Item = &'a PublicKey>,
     {
-        let (local, remote) = (Repository::new(local), Repository::new(remote));
+        let (local, remote) = (
+            Repository::new(local).with_json_limits(config.json_limits),
+            Repository::new(remote).with_json_limits(config.json_limits),
+        );
 
         let root_path = MetadataPath::from_role(&Role::Root);
         let (fetched, raw_root, root) = fetch_metadata_from_local_or_else_remote(
@@ -480,20 +648,15 @@ where
             None,
             &local,
             &remote,
+            &config.retry_policy,
         )
         .await?;
 
         let tuf = Tuf::from_root_with_trusted_keys(root, root_threshold, trusted_root_keys)?;
-
         // FIXME(#253) verify the trusted root version matches the provided version.
         let root_version = MetadataVersion::Number(tuf.root().version());
 
-        let mut client = Client {
-            tuf,
-            config,
-            local,
-            remote,
-        };
+        let mut client = Self::from_tuf(config, tuf, local, remote).await?;
 
         // Only store the metadata after we have validated it.
         if fetched {
@@ -507,16 +670,162 @@ where
         Ok(client)
     }
 
+    /// Create a client around a newly trusted `tuf`, which is set up as `config` asks, and brought
+    /// up to date with any trusted state saved by the `LocalStore` and `VersionStore`.
+    async fn from_tuf(
+        config: Config<T>,
+        mut tuf: Tuf<D>,
+        local: Repository<L, D>,
+        remote: Repository<R, D>,
+    ) -> Result<Self> {
+        tuf.set_danger_zone(config.danger_zone.clone());
+        tuf.set_scheme_policy(config.scheme_policy.clone());
+        tuf.set_clock(config.clock.0.clone());
+        tuf.set_audit_hook(config.audit_hook.0.clone());
+        tuf.set_keep_root_history(config.keep_root_history);
+
+        let (mut tuf, signed) = restore_local_state(&config, tuf).await?;
+        load_trusted_versions(&config, &mut tuf).await?;
+
+        Ok(Client {
+            tuf,
+            signed,
+            config,
+            local,
+            remote,
+        })
+    }
+
+    /// An immutable reference to the trusted TUF metadata.
+    pub fn tuf(&self) -> &Tuf<D> {
+        &self.tuf
+    }
+
     /// Update TUF metadata from the remote repository.
     ///
-    /// Returns `true` if an update occurred and `false` otherwise.
-    pub async fn update(&mut self) -> Result<bool> {
-        let r = self.update_root().await?;
-        let ts = self.update_timestamp().await?;
-        let sn = self.update_snapshot().await?;
-        let ta = self.update_targets().await?;
+    /// This follows the detailed client workflow of the TUF specification:
+    ///
+    /// 1. Walk the chain of root metadata one version at a time, until the remote repository does
+    ///    not have the next version, and check that the final root has not expired.
+    /// 2. Update the timestamp metadata.
+    /// 3. Update the snapshot metadata if the timestamp lists a new version, checking it against
+    ///    the timestamp and that it has not expired.
+    /// 4. Update the top-level targets metadata if the snapshot lists a new version, checking it
+    ///    against the snapshot and that it has not expired.
+    ///
+    /// Delegated targets metadata is fetched on demand when a target is looked up, for example by
+    /// [`Client::fetch_target`], except for the roles in [`Config::prefetch_delegations`], which
+    /// are fetched last. Any failure aborts the update, leaving the metadata verified so far
+    /// trusted.
+    ///
+    /// Returns an `UpdateResult` describing which roles were updated and which targets changed.
+    /// Its summary is also logged at the `info` level, and any warnings at the `warn` level.
+    pub async fn update(&mut self) -> Result<UpdateResult> {
+        let before = TrustedState::capture(&self.tuf);
+        let mut downloaded = 0;
+
+        let token = self.config.cancellation_token.clone();
+        let root_chain = token.run(self.update_root(&mut downloaded)).await?;
+        // The other roles can only be checked against the newest root, so if the chain of root
+        // metadata was cut short, leave them for the next update.
+        if root_chain == RootChain::Complete {
+            token.run(self.update_timestamp(&mut downloaded)).await?;
+            token.run(self.update_snapshot(&mut downloaded)).await?;
+            token.run(self.update_targets(&mut downloaded)).await?;
+            token.run(self.prefetch_delegations()).await?;
+        }
+
+        let after = TrustedState::capture(&self.tuf);
+        let summary = UpdateSummary::new(&before, &after, downloaded);
+        info!("TUF update: {}", summary);
+
+        let mut warnings = Vec::new();
+        if root_chain == RootChain::Capped {
+            warnings.push(UpdateWarning::RootChainIncomplete {
+                version: self.tuf.root().version(),
+            });
+        }
+        warnings.extend(UpdateWarning::near_expiry(
+            &self.tuf,
+            self.tuf.now(),
+            self.config.expiration_warning,
+        ));
+        for warning in &warnings {
+            warn!("{}", warning);
+        }
+
+        self.store_trusted_versions().await?;
+        if summary.updated() {
+            self.store_local_state().await?;
+        }
+
+        Ok(UpdateResult::new(summary, warnings))
+    }
+
+    /// Fetch the roles in `Config::prefetch_delegations` with [`Tuf::fetch_delegations`], like
+    /// [`Client::lookup_target_description`].
+    async fn prefetch_delegations(&mut self) -> Result<()> {
+        if self.config.prefetch_delegations.is_empty() {
+            return Ok(());
+        }
+
+        let consistent_snapshot = self.tuf.root().consistent_snapshot();
+        let fetched = Mutex::new(Vec::new());
+
+        let Client {
+            tuf,
+            config,
+            local,
+            remote,
+            ..
+        } = self;
+        let res = tuf
+            .fetch_delegations(
+                &config.prefetch_delegations,
+                config.max_delegation_depth,
+                |role, description| {
+                    fetch_delegated_metadata(
+                        &*config,
+                        &*local,
+                        &*remote,
+                        consistent_snapshot,
+                        &fetched,
+                        role.clone(),
+                        description.clone(),
+                    )
+                },
+            )
+            .await;
 
-        Ok(r || ts || sn || ta)
+        self.store_fetched_delegations(fetched.into_inner()).await;
+        res
+    }
+
+    /// Save the signed metadata behind the trusted metadata to the configured `LocalStore`, if any.
+    async fn store_local_state(&mut self) -> Result<()> {
+        if let Some(ref store) = self.config.local_store.0 {
+            self.signed.retain_trusted(&self.tuf);
+            store.store(&self.signed.encode()?).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Save the trusted versions to the configured `VersionStore`, if they changed since they were
+    /// last loaded or stored.
+    async fn store_trusted_versions(&mut self) -> Result<()> {
+        let store = match self.config.version_store.0 {
+            Some(ref store) => store.clone(),
+            None => return Ok(()),
+        };
+
+        let versions = self.tuf.trusted_versions();
+        if &versions != self.tuf.minimum_versions() {
+            store.store(&versions).await?;
+            self.tuf.set_minimum_versions(versions);
+        }
+
+        Ok(())
     }
 
     /// Store the metadata in the local repository. This is just a local cache, so we ignore if it
@@ -542,91 +851,93 @@ where
         }
     }
 
-    /// Returns `true` if an update occurred and `false` otherwise.
-    async fn update_root(&mut self) -> Result<bool> {
+    /// Returns whether the whole chain of root metadata was walked, or only the first
+    /// `Config::max_root_rotations` versions of it.
+    async fn update_root(&mut self, downloaded: &mut u64) -> Result<RootChain> {
         let root_path = MetadataPath::from_role(&Role::Root);
 
-        let (raw_latest_root, latest_root) = self
-            .remote
-            .fetch_metadata(
-                &root_path,
-                &MetadataVersion::None,
-                self.config.max_root_length,
-                None,
-            )
-            .await?;
-
-        // Root metadata is signed by its own keys, but we should only trust it if it is also
-        // signed by the previous root metadata, which we can't check without knowing what version
-        // this root metadata claims to be.
-        let latest_version = latest_root.parse_version_untrusted()?;
-
-        if latest_version < self.tuf.root().version() {
-            return Err(Error::VerificationFailure(format!(
-                "Latest root version is lower than current root version: {} < {}",
-                latest_version,
-                self.tuf.root().version()
-            )));
-        } else if latest_version == self.tuf.root().version() {
-            return Ok(false);
-        }
-
-        let err_msg = "TUF claimed no update occurred when one should have. \
-                       This is a programming error. Please report this as a bug.";
+        // 5.3.2 - 5.3.9 Walk the chain of root metadata one version at a time, until the remote
+        // repository no longer has the next version. Every root must be signed by a threshold of
+        // keys from both the previous root and itself.
+        for rotations in 0.. {
+            if rotations == self.config.max_root_rotations {
+                // The root reached so far is not the final one, so it may well have expired since
+                // it was replaced. Only the final root is checked for a freeze attack.
+                return Ok(RootChain::Capped);
+            }
 
-        for i in (self.tuf.root().version() + 1)..latest_version {
-            let version = MetadataVersion::Number(i);
+            let next_version = MetadataVersion::Number(self.tuf.root().version() + 1);
 
-            let (raw_signed_root, signed_root) = self
-                .remote
-                .fetch_metadata(&root_path, &version, self.config.max_root_length, None)
-                .await?;
+            let remote = &self.remote;
+            let max_root_length = self.config.max_root_length;
+            let (raw_signed_root, signed_root) = match self
+                .config
+                .retry_policy
+                .retry(|| remote.fetch_metadata(&root_path, &next_version, max_root_length, None))
+                .await
+            {
+                Ok(root) => root,
+                Err(Error::NotFound) => break,
+                Err(err) => return Err(err),
+            };
+            *downloaded += raw_signed_root.as_bytes().len() as u64;
+
+            // The remote repository may have served a different version than the one requested.
+            let version = MetadataVersion::Number(signed_root.parse_version_untrusted()?);
+            if version != next_version {
+                return Err(Error::VerificationFailure(format!(
+                    "Attempted to fetch root metadata {:?} but received {:?}",
+                    next_version, version,
+                )));
+            }
 
-            if !self.tuf.update_root(signed_root)? {
+            if !self.tuf.update_root(signed_root.clone())? {
+                let err_msg = "TUF claimed no update occurred when one should have. \
+                               This is a programming error. Please report this as a bug.";
                 error!("{}", err_msg);
                 return Err(Error::Programming(err_msg.into()));
             }
 
+            self.signed.push_root(signed_root);
+
             self.store_metadata(&root_path, &version, &raw_signed_root)
                 .await;
+            self.store_metadata(&root_path, &MetadataVersion::None, &raw_signed_root)
+                .await;
         }
 
-        if !self.tuf.update_root(latest_root)? {
-            error!("{}", err_msg);
-            return Err(Error::Programming(err_msg.into()));
-        }
-
-        let latest_version = MetadataVersion::Number(latest_version);
-
-        self.store_metadata(&root_path, &latest_version, &raw_latest_root)
-            .await;
-        self.store_metadata(&root_path, &MetadataVersion::None, &raw_latest_root)
-            .await;
-
-        if self.tuf.root().expires() <= &Utc::now() {
+        // 5.3.10 Check for a freeze attack on the final root metadata.
+        if self.tuf.is_expired(self.tuf.root().expires()) {
             error!("Root metadata expired, potential freeze attack");
             return Err(Error::ExpiredMetadata(Role::Root));
         }
 
-        Ok(true)
+        Ok(RootChain::Complete)
     }
 
     /// Returns `true` if an update occurred and `false` otherwise.
-    async fn update_timestamp(&mut self) -> Result<bool> {
+    async fn update_timestamp(&mut self, downloaded: &mut u64) -> Result<bool> {
         let timestamp_path = MetadataPath::from_role(&Role::Timestamp);
 
+        let remote = &self.remote;
+        let max_timestamp_length = self.config.max_timestamp_length;
         let (raw_signed_timestamp, signed_timestamp) = self
-            .remote
-            .fetch_metadata(
-                &timestamp_path,
-                &MetadataVersion::None,
-                self.config.max_timestamp_length,
-                None,
-            )
+            .config
+            .retry_policy
+            .retry(|| {
+                remote.fetch_metadata(
+                    &timestamp_path,
+                    &MetadataVersion::None,
+                    max_timestamp_length,
+                    None,
+                )
+            })
             .await?;
+        *downloaded += raw_signed_timestamp.as_bytes().len() as u64;
 
-        if let Some(updated_timestamp) = self.tuf.update_timestamp(signed_timestamp)? {
+        if let Some(updated_timestamp) = self.tuf.update_timestamp(signed_timestamp.clone())? {
             let latest_version = MetadataVersion::Number(updated_timestamp.version());
+            self.signed.set_timestamp(signed_timestamp);
             self.store_metadata(&timestamp_path, &latest_version, &raw_signed_timestamp)
                 .await;
 
@@ -637,7 +948,7 @@ where
     }
 
     /// Returns `true` if an update occurred and `false` otherwise.
-    async fn update_snapshot(&mut self) -> Result<bool> {
+    async fn update_snapshot(&mut self, downloaded: &mut u64) -> Result<bool> {
         // 5.3.1 Check against timestamp metadata. The hashes and version number listed in the
         // timestamp metadata. If hashes and version do not match, discard the new snapshot
         // metadata, abort the update cycle, and report the failure.
@@ -651,7 +962,7 @@ where
             return Ok(false);
         }
 
-        let (alg, value) = crypto::hash_preference(snapshot_description.hashes())?;
+        let hash_data = preferred_hash(&snapshot_description)?;
 
         let version = if self.tuf.root().consistent_snapshot() {
             MetadataVersion::Number(snapshot_description.version())
@@ -660,30 +971,44 @@ where
         };
 
         let snapshot_path = MetadataPath::from_role(&Role::Snapshot);
-        let snapshot_length = Some(snapshot_description.length());
+        let snapshot_length = snapshot_description
+            .length()
+            .or(self.config.max_snapshot_length);
 
+        let remote = &self.remote;
         let (raw_signed_snapshot, signed_snapshot) = self
-            .remote
-            .fetch_metadata(
-                &snapshot_path,
-                &version,
-                snapshot_length,
-                Some((alg, value.clone())),
-            )
+            .config
+            .retry_policy
+            .retry(|| {
+                remote.fetch_metadata(&snapshot_path, &version, snapshot_length, hash_data.clone())
+            })
             .await?;
+        *downloaded += raw_signed_snapshot.as_bytes().len() as u64;
 
-        if self.tuf.update_snapshot(signed_snapshot)? {
-            self.store_metadata(&snapshot_path, &version, &raw_signed_snapshot)
-                .await;
+        if !self.tuf.update_snapshot(signed_snapshot.clone())? {
+            return Ok(false);
+        }
+        self.signed.set_snapshot(signed_snapshot);
 
-            Ok(true)
-        } else {
-            Ok(false)
+        self.store_metadata(&snapshot_path, &version, &raw_signed_snapshot)
+            .await;
+
+        // 5.5.6 Check for a freeze attack. The snapshot is still trusted so that it can be used to
+        // detect rollback attacks, but the update cycle is aborted.
+        if self
+            .tuf
+            .snapshot()
+            .is_some_and(|snapshot| self.tuf.is_expired(snapshot.expires()))
+        {
+            error!("Snapshot metadata expired, potential freeze attack");
+            return Err(Error::ExpiredMetadata(Role::Snapshot));
         }
+
+        Ok(true)
     }
 
     /// Returns `true` if an update occurred and `false` otherwise.
-    async fn update_targets(&mut self) -> Result<bool> {
+    async fn update_targets(&mut self, downloaded: &mut u64) -> Result<bool> {
         let targets_description = match self.tuf.snapshot() {
             Some(sn) => match sn.meta().get(&MetadataPath::from_role(&Role::Targets)) {
                 Some(d) => Ok(d),
@@ -701,7 +1026,7 @@ where
             return Ok(false);
         }
 
-        let (alg, value) = crypto::hash_preference(targets_description.hashes())?;
+        let hash_data = preferred_hash(&targets_description)?;
 
         let version = if self.tuf.root().consistent_snapshot() {
             MetadataVersion::Number(targets_description.version())
@@ -710,19 +1035,22 @@ where
         };
 
         let targets_path = MetadataPath::from_role(&Role::Targets);
-        let targets_length = Some(targets_description.length());
+        let targets_length = targets_description
+            .length()
+            .or(self.config.max_targets_length);
 
+        let remote = &self.remote;
         let (raw_signed_targets, signed_targets) = self
-            .remote
-            .fetch_metadata(
-                &targets_path,
-                &version,
-                targets_length,
-                Some((alg, value.clone())),
-            )
+            .config
+            .retry_policy
+            .retry(|| {
+                remote.fetch_metadata(&targets_path, &version, targets_length, hash_data.clone())
+            })
             .await?;
+        *downloaded += raw_signed_targets.as_bytes().len() as u64;
 
-        if self.tuf.update_targets(signed_targets)? {
+        if self.tuf.update_targets(signed_targets.clone())? {
+            self.signed.set_targets(signed_targets);
             self.store_metadata(&targets_path, &version, &raw_signed_targets)
                 .await;
 
@@ -732,13 +1060,74 @@ where
         }
     }
 
-    /// Fetch a target from the remote repo and write it to the local repo.
-    pub async fn fetch_target<'a>(&'a mut self, target: &'a TargetPath) -> Result<()> {
-        let read = self._fetch_target(target).await?;
-        self.local.store_target(read, target).await
+    /// Fetch a target from the remote repo and write it to the local repo. Returns the verified
+    /// description of the target, including any custom metadata its publisher recorded.
+    pub async fn fetch_target<'a>(
+        &'a mut self,
+        target: &'a TargetPath,
+    ) -> Result<TargetDescription> {
+        let target_description = self.fetch_target_description(target).await?;
+        self.store_target_with_description(target, &target_description)
+            .await?;
+        Ok(target_description)
+    }
+
+    /// Fetch several targets from the remote repo and write them to the local repo, like
+    /// [`Client::fetch_target`].
+    ///
+    /// The targets are looked up one after another, so that delegated targets metadata is only
+    /// fetched and verified once, and then up to [`Config::max_concurrent_fetches`] of them are
+    /// downloaded at the same time. Returns the first error encountered, after which the remaining
+    /// targets are not fetched.
+    pub async fn fetch_targets<'a>(&'a mut self, targets: &'a [TargetPath]) -> Result<()> {
+        let mut target_descriptions = Vec::with_capacity(targets.len());
+        for target in targets {
+            target_descriptions.push((target, self.fetch_target_description(target).await?));
+        }
+
+        let this = &*self;
+        stream::iter(target_descriptions.into_iter().map(Ok))
+            .try_for_each_concurrent(
+                this.config.max_concurrent_fetches,
+                |(target, target_description)| async move {
+                    this.store_target_with_description(target, &target_description)
+                        .await
+                },
+            )
+            .await
+    }
+
+    /// Fetch a target with a known description from the remote repo and write it to the local
+    /// repo.
+    async fn store_target_with_description<'a>(
+        &'a self,
+        target: &'a TargetPath,
+        target_description: &'a TargetDescription,
+    ) -> Result<()> {
+        if !self.config.target_verifiers.0.is_empty() {
+            let buf = self
+                .fetch_verified_target(target, target_description)
+                .await?;
+            return self.local.store_target(&buf[..], target).await;
+        }
+
+        let read = self
+            .fetch_target_with_description(target, target_description)
+            .await?;
+        self.local.store_target(read, target).await?;
+        self.audit_target_verified(target);
+        Ok(())
+    }
+
+    /// Tell the `AuditHook`, if any, that `target` was read in full and verified.
+    fn audit_target_verified(&self, target: &TargetPath) {
+        if let Ok(virt) = self.config.path_translator.real_to_virtual(target) {
+            self.tuf.audit_target_verified(&virt);
+        }
     }
 
-    /// Fetch a target from the remote repo and write it to the provided writer.
+    /// Fetch a target from the remote repo and write it to the provided writer. Returns the
+    /// verified description of the target, like [`Client::fetch_target`].
     ///
     /// It is **critical** that none of the bytes written to the `write` are used until this future
     /// returns `Ok`, as the hash of the target is not verified until all bytes are read from the
@@ -747,201 +1136,520 @@ where
         &'a mut self,
         target: &'a TargetPath,
         mut write: W,
-    ) -> Result<()>
+    ) -> Result<TargetDescription>
     where
         W: AsyncWrite + Send + Unpin,
     {
-        let read = self._fetch_target(&target).await?;
+        let target_description = self.fetch_target_description(target).await?;
+        let read = self
+            .target_stream_with_description(target, &target_description)
+            .await?;
         copy(read, &mut write).await?;
-        Ok(())
-    }
-
-    /// Fetch a target description from the remote repo and return it.
-    pub async fn fetch_target_description<'a>(
-        &'a mut self,
-        target: &'a TargetPath,
-    ) -> Result<TargetDescription> {
-        let virt = self.config.path_translator.real_to_virtual(target)?;
 
-        let snapshot = self
-            .tuf
-            .snapshot()
-            .ok_or_else(|| Error::MissingMetadata(Role::Snapshot))?
-            .clone();
-        let (_, target_description) = self
-            .lookup_target_description(false, 0, &virt, &snapshot, None)
-            .await;
-        target_description
+        // Buffered targets were already reported when they were verified.
+        if self.config.target_verifiers.0.is_empty() {
+            self.audit_target_verified(target);
+        }
+        Ok(target_description)
     }
 
-    // TODO this should check the local repo first
-    async fn _fetch_target<'a>(
+    /// Fetch a target from the remote repo and write it to the file at `path`. Returns the verified
+    /// description of the target, like [`Client::fetch_target`].
+    ///
+    /// The target is downloaded to `path` with `.part` appended, which is only synced to disk and
+    /// renamed over `path` once the whole target has been verified, so `path` is never left
+    /// half-written. If the download is interrupted, the partial download is kept, and the next
+    /// call resumes it by fetching only the rest of the target, through
+    /// [`RepositoryProvider::fetch_target_from`]. The whole target is still verified before it is
+    /// committed, and a partial download that fails verification is discarded.
+    ///
+    /// If any [`TargetVerifier`]s are configured, the target is read into memory so they can check
+    /// it, and interrupted downloads are not resumed.
+    ///
+    /// File operations block the thread that polls the returned future, like those of
+    /// [`FileSystemRepository`](crate::repository::FileSystemRepository). On an async runtime,
+    /// consider running it where blocking is allowed, such as with tokio's `spawn_blocking`.
+    pub async fn fetch_target_to_path<'a, P>(
         &'a mut self,
         target: &'a TargetPath,
-    ) -> Result<impl AsyncRead + Send + Unpin> {
+        path: P,
+    ) -> Result<TargetDescription>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
         let target_description = self.fetch_target_description(target).await?;
-
-        // According to TUF section 5.5.2, when consistent snapshot is enabled, target files should
-        // be found at `$HASH.FILENAME.EXT`. Otherwise it is stored at `FILENAME.EXT`.
-        if self.tuf.root().consistent_snapshot() {
-            let (_, value) = crypto::hash_preference(target_description.hashes())?;
-            let target = target.with_hash_prefix(value)?;
-            self.remote.fetch_target(&target, &target_description).await
-        } else {
-            self.remote.fetch_target(target, &target_description).await
+        if !self.config.target_verifiers.0.is_empty() {
+            let read = self
+                .target_stream_with_description(target, &target_description)
+                .await?;
+            util::copy_atomically(path, read).await?;
+            return Ok(target_description);
         }
-    }
 
-    async fn lookup_target_description<'a>(
-        &'a mut self,
-        default_terminate: bool,
-        current_depth: u32,
-        target: &'a VirtualTargetPath,
-        snapshot: &'a SnapshotMetadata,
-        targets: Option<(&'a TargetsMetadata, MetadataPath)>,
-    ) -> (bool, Result<TargetDescription>) {
-        if current_depth > self.config.max_delegation_depth {
-            warn!(
-                "Walking the delegation graph would have exceeded the configured max depth: {}",
-                self.config.max_delegation_depth
-            );
-            return (default_terminate, Err(Error::NotFound));
-        }
+        let remote_path = self.remote_target_path(target, &target_description)?;
 
-        // these clones are dumb, but we need immutable values and not references for update
-        // tuf in the loop below
-        let (targets, targets_role) = match targets {
-            Some((t, role)) => (t.clone(), role),
-            None => match self.tuf.targets() {
-                Some(t) => (t.clone(), MetadataPath::from_role(&Role::Targets)),
-                None => {
-                    return (
-                        default_terminate,
-                        Err(Error::MissingMetadata(Role::Targets)),
-                    );
-                }
-            },
-        };
+        let mut part_path = path.as_os_str().to_owned();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
 
-        if let Some(t) = targets.targets().get(target) {
-            return (default_terminate, Ok(t.clone()));
-        }
+        let mut offset = part_len(&part_path)?;
+        let mut attempt = 1;
+        loop {
+            match self
+                .download_part(&remote_path, &target_description, &part_path, offset)
+                .await
+            {
+                Ok(()) => {
+                    self.audit_target_verified(target);
+                    util::rename_durably(&part_path, path)?;
+                    return Ok(target_description);
+                }
+                Err(DownloadError::Interrupted(err)) => {
+                    // Pick up where the interrupted download left off.
+                    if self.config.retry_policy.wait_to_retry(attempt, &err).await {
+                        attempt += 1;
+                        offset = part_len(&part_path)?;
+                        continue;
+                    }
 
-        let delegations = match targets.delegations() {
-            Some(d) => d,
-            None => return (default_terminate, Err(Error::NotFound)),
-        };
+                    // There is no point keeping a partial download with nothing in it.
+                    if fs::metadata(&part_path).is_ok_and(|m| m.len() == 0) {
+                        let _ = fs::remove_file(&part_path);
+                    }
+                    return Err(err);
+                }
+                Err(DownloadError::Invalid(err)) => {
+                    let _ = fs::remove_file(&part_path);
 
-        for delegation in delegations.roles().iter() {
-            if !delegation.paths().iter().any(|p| target.is_child(p)) {
-                if delegation.terminating() {
-                    return (true, Err(Error::NotFound));
-                } else {
-                    continue;
+                    // The partial download may be of an older version of the target, so start
+                    // over from the beginning before giving up.
+                    if offset == 0 {
+                        return Err(err);
+                    }
+                    warn!(
+                        "Discarding partial download of {} that failed verification: {}",
+                        target.value(),
+                        err
+                    );
+                    offset = 0;
                 }
             }
+        }
+    }
 
-            let role_meta = match snapshot.meta().get(delegation.role()) {
-                Some(m) => m,
-                None if !delegation.terminating() => continue,
-                None => return (true, Err(Error::NotFound)),
-            };
+    /// Fetch a target from the remote repo as a stream of bytes.
+    ///
+    /// The bytes are checked against the length and every supported hash listed in the target's
+    /// trusted metadata as they are read, and reading fails as soon as more bytes than the listed
+    /// length arrive, so the target never has to be held in memory. The hashes can only be checked
+    /// once the whole target has been read, so it is **critical** that none of the bytes are used
+    /// until the stream has ended without an error.
+    ///
+    /// If any [`TargetVerifier`]s are configured, the target is instead read into memory and
+    /// checked by them before this returns.
+    pub async fn fetch_target_stream<'a>(
+        &'a mut self,
+        target: &'a TargetPath,
+    ) -> Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let target_description = self.fetch_target_description(target).await?;
+        self.target_stream_with_description(target, &target_description)
+            .await
+    }
 
-            let (alg, value) = match crypto::hash_preference(role_meta.hashes()) {
-                Ok(h) => h,
-                Err(e) => return (delegation.terminating(), Err(e)),
-            };
+    /// Fetch a target with a known description from the remote repo, like
+    /// [`Client::fetch_target_stream`].
+    async fn target_stream_with_description<'a>(
+        &'a self,
+        target: &'a TargetPath,
+        target_description: &'a TargetDescription,
+    ) -> Result<Box<dyn AsyncRead + Send + Unpin>> {
+        if !self.config.target_verifiers.0.is_empty() {
+            let buf = self
+                .fetch_verified_target(target, target_description)
+                .await?;
+            return Ok(Box::new(Cursor::new(buf)));
+        }
 
-            let version = if self.tuf.root().consistent_snapshot() {
-                MetadataVersion::Hash(value.clone())
-            } else {
-                MetadataVersion::None
-            };
+        // TODO this should check the local repo first
+        let read = self
+            .fetch_target_with_description(target, target_description)
+            .await?;
+        Ok(Box::new(read))
+    }
 
-            let role_length = Some(role_meta.length());
-            let raw_signed_meta = self
-                .local
-                .fetch_metadata(
-                    delegation.role(),
-                    &MetadataVersion::None,
-                    role_length,
-                    Some((alg, value.clone())),
-                )
-                .await;
+    /// Fetch a target description from the remote repo and return it.
+    pub async fn fetch_target_description<'a>(
+        &'a mut self,
+        target: &'a TargetPath,
+    ) -> Result<TargetDescription> {
+        let virt = self.config.path_translator.real_to_virtual(target)?;
 
-            let (raw_signed_meta, signed_meta) = match raw_signed_meta {
-                Ok(m) => m,
-                Err(_) => {
-                    match self
-                        .remote
-                        .fetch_metadata(
-                            delegation.role(),
-                            &version,
-                            role_length,
-                            Some((alg, value.clone())),
-                        )
-                        .await
-                    {
-                        Ok(m) => m,
-                        Err(ref e) if !delegation.terminating() => {
-                            warn!("Failed to fetch metadata {:?}: {:?}", delegation.role(), e);
-                            continue;
-                        }
-                        Err(e) => {
-                            warn!("Failed to fetch metadata {:?}: {:?}", delegation.role(), e);
-                            return (true, Err(e));
-                        }
+        self.lookup_target_description(&virt).await
+    }
+
+    /// Check that `read` holds `target`, without any network access, like [`Tuf::verify_target`].
+    /// Only the length and hashes of the target are checked; the configured `TargetVerifier`s
+    /// are not run.
+    pub fn verify_target<F: io::Read>(&self, target: &TargetPath, read: F) -> Result<()> {
+        let virt = self.config.path_translator.real_to_virtual(target)?;
+        self.tuf.verify_target(&virt, read)
+    }
+
+    /// Fetch a target into memory and run it through the configured `TargetVerifier`s.
+    async fn fetch_verified_target<'a>(
+        &'a self,
+        target: &'a TargetPath,
+        target_description: &'a TargetDescription,
+    ) -> Result<Vec<u8>> {
+        let mut read = self
+            .fetch_target_with_description(target, target_description)
+            .await?;
+        let mut buf = Vec::new();
+        read.read_to_end(&mut buf).await?;
+
+        for verifier in self.config.target_verifiers.0.iter() {
+            verifier.verify(target, target_description, &buf)?;
+        }
+
+        self.audit_target_verified(target);
+        Ok(buf)
+    }
+
+    async fn fetch_target_with_description<'a>(
+        &'a self,
+        target: &'a TargetPath,
+        target_description: &'a TargetDescription,
+    ) -> Result<CancellableRead<IdleTimeoutRead<SafeReader<Box<dyn AsyncRead + Send + Unpin>>>>>
+    {
+        let remote_path = self.remote_target_path(target, target_description)?;
+        let token = &self.config.cancellation_token;
+        let read = token
+            .run(
+                self.config
+                    .retry_policy
+                    .retry(|| self.remote.fetch_target(&remote_path, target_description)),
+            )
+            .await?;
+        let read = self.config.retry_policy.limit_idle(read);
+        Ok(CancellableRead::new(read, token))
+    }
+
+    /// Where `target` is stored in the remote repo.
+    fn remote_target_path(
+        &self,
+        target: &TargetPath,
+        target_description: &TargetDescription,
+    ) -> Result<TargetPath> {
+        if let Some(ref layout) = self.config.target_layout.0 {
+            let virt = self.config.path_translator.real_to_virtual(target)?;
+            return layout.remote_path(
+                &virt,
+                target_description,
+                self.tuf.root().consistent_snapshot(),
+            );
+        }
+
+        // According to TUF section 5.5.2, when consistent snapshot is enabled, target files should
+        // be found at `$HASH.FILENAME.EXT`. Otherwise it is stored at `FILENAME.EXT`.
+        if self.tuf.root().consistent_snapshot() {
+            let (_, value) = crypto::hash_preference(target_description.hashes())?;
+            target.with_hash_prefix(value)
+        } else {
+            Ok(target.clone())
+        }
+    }
+
+    /// Download the target at `remote_path` into the partial download at `part_path`, keeping its
+    /// first `offset` bytes and fetching only the rest. Writes to `part_path` block.
+    async fn download_part(
+        &self,
+        remote_path: &TargetPath,
+        target_description: &TargetDescription,
+        part_path: &Path,
+        offset: u64,
+    ) -> std::result::Result<(), DownloadError> {
+        let mut part = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(part_path)?;
+        part.set_len(offset)?;
+
+        let prefix = AllowStdIo::new(File::open(part_path)?);
+        let token = &self.config.cancellation_token;
+        let read = token
+            .run(
+                self.remote
+                    .fetch_target_after(remote_path, target_description, prefix, offset),
+            )
+            .await?;
+        let read = self.config.retry_policy.limit_idle(read);
+        let mut read = CancellableRead::new(read, token);
+
+        // The bytes read start with those already in the partial download, which are only read
+        // to verify the whole target.
+        let mut skip = offset;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let len = read.read(&mut buf).await?;
+            if len == 0 {
+                break;
+            }
+
+            let start = skip.min(len as u64) as usize;
+            skip -= start as u64;
+            part.write_all(&buf[start..len])?;
+        }
+
+        part.sync_all()?;
+        Ok(())
+    }
+
+    /// Look up the description of `target` with [`Tuf::find_target_description`]. Delegated
+    /// targets metadata is fetched from the local repo, or else the remote repo, and metadata
+    /// fetched from the remote repo is stored in the local repo once it has been verified.
+    async fn lookup_target_description(
+        &mut self,
+        target: &VirtualTargetPath,
+    ) -> Result<TargetDescription> {
+        let consistent_snapshot = self.tuf.root().consistent_snapshot();
+        let token = self.config.cancellation_token.clone();
+        let fetched = Mutex::new(Vec::new());
+
+        let Client {
+            tuf,
+            config,
+            local,
+            remote,
+            ..
+        } = self;
+        let res = token
+            .run(tuf.find_target_description(
+                target,
+                config.max_delegation_depth,
+                |role, description| {
+                    fetch_delegated_metadata(
+                        &*config,
+                        &*local,
+                        &*remote,
+                        consistent_snapshot,
+                        &fetched,
+                        role.clone(),
+                        description.clone(),
+                    )
+                },
+            ))
+            .await;
+
+        self.store_fetched_delegations(fetched.into_inner()).await;
+        res
+    }
+
+    /// Fetch and verify every delegated targets role, like [`Client::fetch_target_description`],
+    /// and return every target of the repository with its description, ordered by path.
+    ///
+    /// Delegated roles that cannot be fetched or verified are skipped, along with the targets
+    /// they list, as are targets whose paths cannot be translated.
+    pub async fn list_targets(&mut self) -> Result<Vec<(TargetPath, TargetDescription)>> {
+        let consistent_snapshot = self.tuf.root().consistent_snapshot();
+        let token = self.config.cancellation_token.clone();
+        let fetched = Mutex::new(Vec::new());
+
+        let Client {
+            tuf,
+            config,
+            local,
+            remote,
+            ..
+        } = self;
+        let res = token
+            .run(
+                tuf.fetch_all_delegations(config.max_delegation_depth, |role, description| {
+                    fetch_delegated_metadata(
+                        &*config,
+                        &*local,
+                        &*remote,
+                        consistent_snapshot,
+                        &fetched,
+                        role.clone(),
+                        description.clone(),
+                    )
+                }),
+            )
+            .await;
+
+        self.store_fetched_delegations(fetched.into_inner()).await;
+        res?;
+
+        let mut targets = self
+            .tuf
+            .all_targets()?
+            .filter_map(|(virt, description)| {
+                match self.config.path_translator.virtual_to_real(&virt) {
+                    Ok(path) => Some((path, description.clone())),
+                    Err(e) => {
+                        warn!("Failed to translate target path {:?}: {:?}", virt, e);
+                        None
                     }
                 }
-            };
+            })
+            .collect::<Vec<_>>();
+        targets.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(targets)
+    }
 
-            match self
-                .tuf
-                .update_delegation(&targets_role, delegation.role(), signed_meta)
+    /// Record the fetched delegated targets metadata that was verified and is still trusted, and
+    /// store the part of it that was fetched from the remote repo in the local repo.
+    async fn store_fetched_delegations(&mut self, fetched: FetchedDelegations<D>) {
+        for delegation in fetched {
+            let role = delegation.role;
+            if self.tuf.delegations().get(&role).map(|t| t.version()) != Some(delegation.version) {
+                continue;
+            }
+            self.signed
+                .insert_delegation(role.clone(), delegation.signed);
+
+            let raw_signed_meta = match delegation.raw {
+                Some(raw_signed_meta) => raw_signed_meta,
+                None => continue,
+            };
+            if let Err(e) = self
+                .local
+                .store_metadata(&role, &MetadataVersion::None, &raw_signed_meta)
+                .await
             {
-                Ok(_) => {
-                    match self
-                        .local
-                        .store_metadata(delegation.role(), &MetadataVersion::None, &raw_signed_meta)
-                        .await
-                    {
-                        Ok(_) => (),
-                        Err(e) => warn!(
-                            "Error storing metadata {:?} locally: {:?}",
-                            delegation.role(),
-                            e
-                        ),
-                    }
+                warn!("Error storing metadata {:?} locally: {:?}", role, e);
+            }
+        }
+    }
+}
 
-                    let meta = self
-                        .tuf
-                        .delegations()
-                        .get(delegation.role())
-                        .unwrap()
-                        .clone();
-                    let f: Pin<Box<dyn Future<Output = _>>> =
-                        Box::pin(self.lookup_target_description(
-                            delegation.terminating(),
-                            current_depth + 1,
-                            target,
-                            snapshot,
-                            Some((&meta, delegation.role().clone())),
-                        ));
-                    let (term, res) = f.await;
-
-                    if term && res.is_err() {
-                        return (true, res);
-                    }
+/// The length of the partial download at `part_path`, or 0 if there isn't one.
+fn part_len(part_path: &Path) -> io::Result<u64> {
+    match fs::metadata(part_path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err),
+    }
+}
 
-                    // TODO end recursion early
-                }
-                Err(_) if !delegation.terminating() => continue,
-                Err(e) => return (true, Err(e)),
-            };
+/// How much of the chain of root metadata `Client::update_root` walked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RootChain {
+    /// The newest root metadata in the remote repository is trusted.
+    Complete,
+    /// `Config::max_root_rotations` root metadata files were fetched, and there may be newer ones.
+    Capped,
+}
+
+/// Delegated targets metadata fetched while walking the delegations.
+struct FetchedDelegation<D: DataInterchange> {
+    role: MetadataPath,
+    /// The version the snapshot metadata lists for the role.
+    version: u32,
+    /// The metadata as fetched from the remote repo, or `None` if it came from the local repo.
+    raw: Option<RawSignedMetadata<D, TargetsMetadata>>,
+    signed: SignedMetadata<D, TargetsMetadata>,
+}
+
+type FetchedDelegations<D> = Vec<FetchedDelegation<D>>;
+
+/// Fetch the delegated targets metadata of `role` from `local`, or else from `remote`. The
+/// metadata is recorded in `fetched`, so that it can be saved to the `LocalStore`, and stored in
+/// `local` if it came from `remote`, once it has been verified.
+async fn fetch_delegated_metadata<D, L, R, T>(
+    config: &Config<T>,
+    local: &Repository<L, D>,
+    remote: &Repository<R, D>,
+    consistent_snapshot: bool,
+    fetched: &Mutex<FetchedDelegations<D>>,
+    role: MetadataPath,
+    description: MetadataDescription,
+) -> Result<SignedMetadata<D, TargetsMetadata>>
+where
+    D: DataInterchange + Sync,
+    L: RepositoryProvider<D> + RepositoryStorage<D>,
+    R: RepositoryProvider<D>,
+    T: PathTranslator,
+{
+    let hash_data = preferred_hash(&description)?;
+    let role_length = description.length().or(config.max_targets_length);
+
+    if let Ok((_, signed_meta)) = local
+        .fetch_metadata(
+            &role,
+            &MetadataVersion::None,
+            role_length,
+            hash_data.clone(),
+        )
+        .await
+    {
+        fetched.lock().push(FetchedDelegation {
+            role,
+            version: description.version(),
+            raw: None,
+            signed: signed_meta.clone(),
+        });
+        return Ok(signed_meta);
+    }
+
+    let version = if consistent_snapshot {
+        MetadataVersion::Number(description.version())
+    } else {
+        MetadataVersion::None
+    };
+    let (raw_signed_meta, signed_meta) = config
+        .retry_policy
+        .retry(|| remote.fetch_metadata(&role, &version, role_length, hash_data.clone()))
+        .await?;
+    fetched.lock().push(FetchedDelegation {
+        role,
+        version: description.version(),
+        raw: Some(raw_signed_meta),
+        signed: signed_meta.clone(),
+    });
+    Ok(signed_meta)
+}
+
+/// Why [`Client::download_part`] failed.
+enum DownloadError {
+    /// The download stopped early, and can be resumed from what was written so far.
+    Interrupted(Error),
+    /// What was downloaded is not the target, so it must be discarded.
+    Invalid(Error),
+}
+
+impl From<Error> for DownloadError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::VerificationFailure(_) => DownloadError::Invalid(err),
+            err => DownloadError::Interrupted(err),
+        }
+    }
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(err: io::Error) -> Self {
+        // These are how `SafeReader` reports a target that is too long or has the wrong hashes.
+        match err.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::FileTooLarge => {
+                DownloadError::Invalid(err.into())
+            }
+            _ => DownloadError::Interrupted(err.into()),
         }
+    }
+}
 
-        (default_terminate, Err(Error::NotFound))
+/// The preferred hash to check fetched metadata against, or `None` if `description` only lists
+/// the version of the metadata.
+fn preferred_hash(
+    description: &MetadataDescription,
+) -> Result<Option<(&'static HashAlgorithm, HashValue)>> {
+    if description.hashes().is_empty() {
+        return Ok(None);
     }
+    let (alg, value) = crypto::hash_preference(description.hashes())?;
+    Ok(Some((alg, value.clone())))
 }
 
 /// Helper function that first tries to fetch the metadata from the local store, and if it doesn't
@@ -953,6 +1661,7 @@ async fn fetch_metadata_from_local_or_else_remote<'a, D, L, R, M>(
     hash_data: Option<(&'static HashAlgorithm, HashValue)>,
     local: &'a Repository<L, D>,
     remote: &'a Repository<R, D>,
+    retry_policy: &'a RetryPolicy,
 ) -> Result<(bool, RawSignedMetadata<D, M>, SignedMetadata<D, M>)>
 where
     D: DataInterchange + Sync,
@@ -966,8 +1675,8 @@ where
     {
         Ok((raw_meta, meta)) => Ok((false, raw_meta, meta)),
         Err(Error::NotFound) => {
-            let (raw_meta, meta) = remote
-                .fetch_metadata(path, version, max_length, hash_data)
+            let (raw_meta, meta) = retry_policy
+                .retry(|| remote.fetch_metadata(path, version, max_length, hash_data.clone()))
                 .await?;
             Ok((true, raw_meta, meta))
         }
@@ -975,20 +1684,80 @@ where
     }
 }
 
+/// Update `tuf` with the metadata saved in the configured `LocalStore`, if any, and return it along
+/// with the part of the saved metadata that it trusts. The saved metadata is ignored if it fails
+/// verification.
+async fn restore_local_state<D, T>(
+    config: &Config<T>,
+    tuf: Tuf<D>,
+) -> Result<(Tuf<D>, SignedState<D>)>
+where
+    D: DataInterchange,
+    T: PathTranslator,
+{
+    let state = match config.local_store.0 {
+        Some(ref store) => store.load().await?,
+        None => None,
+    };
+    let state = match state {
+        Some(state) => state,
+        None => return Ok((tuf, SignedState::default())),
+    };
+
+    match SignedState::decode(&state).and_then(|state| state.verify(tuf.clone())) {
+        Ok(restored) => Ok(restored),
+        Err(e) => {
+            warn!(
+                "Ignoring the stored metadata, which failed verification: {}",
+                e
+            );
+            Ok((tuf, SignedState::default()))
+        }
+    }
+}
+
+/// Restore the versions saved in the configured `VersionStore`, if any, as the minimum versions
+/// `tuf` will accept.
+async fn load_trusted_versions<D, T>(config: &Config<T>, tuf: &mut Tuf<D>) -> Result<()>
+where
+    D: DataInterchange,
+    T: PathTranslator,
+{
+    if let Some(ref store) = config.version_store.0 {
+        if let Some(versions) = store.load().await? {
+            let versions = tuf.minimum_versions().merge(&versions);
+            tuf.set_minimum_versions(versions);
+        }
+    }
+
+    Ok(())
+}
+
 /// Configuration for a TUF `Client`.
 ///
 /// # Defaults
 ///
 /// The following values are considered reasonably safe defaults, however these values may change
 /// as this crate moves out of beta. If you are concered about them changing, you should use the
-/// `ConfigBuilder` and set your own values.
+/// `ConfigBuilder` and set your own values. Retries and timeouts of remote fetches are set
+/// together through [`ConfigBuilder::retry_policy`].
 ///
 /// ```
+/// # use chrono::Duration;
 /// # use tuf::client::{Config, DefaultTranslator};
+/// # use tuf::interchange::JsonLimits;
 /// let config = Config::default();
 /// assert_eq!(config.max_root_length(), &Some(1024 * 1024));
 /// assert_eq!(config.max_timestamp_length(), &Some(32 * 1024));
+/// assert_eq!(config.max_snapshot_length(), &Some(16 * 1024 * 1024));
+/// assert_eq!(config.max_targets_length(), &Some(16 * 1024 * 1024));
+/// assert_eq!(config.json_limits(), &JsonLimits::default());
+/// assert_eq!(config.max_root_rotations(), 32);
 /// assert_eq!(config.max_delegation_depth(), 8);
+/// assert_eq!(config.max_concurrent_fetches(), 4);
+/// assert_eq!(config.retry_policy().max_attempts(), 1);
+/// assert_eq!(config.retry_policy().timeout(), None);
+/// assert_eq!(config.expiration_warning(), Duration::hours(1));
 /// let _: &DefaultTranslator = config.path_translator();
 /// ```
 #[derive(Clone, Debug)]
@@ -998,8 +1767,26 @@ where
 {
     max_root_length: Option<usize>,
     max_timestamp_length: Option<usize>,
+    max_snapshot_length: Option<usize>,
+    max_targets_length: Option<usize>,
+    json_limits: JsonLimits,
+    max_root_rotations: u32,
     max_delegation_depth: u32,
+    max_concurrent_fetches: usize,
+    retry_policy: RetryPolicy,
+    expiration_warning: Duration,
     path_translator: T,
+    target_verifiers: TargetVerifiers,
+    target_layout: SharedTargetLayout,
+    version_store: SharedVersionStore,
+    local_store: SharedLocalStore,
+    danger_zone: DangerZone,
+    scheme_policy: SchemePolicy,
+    clock: SharedClock,
+    audit_hook: SharedAuditHook,
+    keep_root_history: bool,
+    prefetch_delegations: Vec<MetadataPath>,
+    cancellation_token: CancellationToken,
 }
 
 impl Config<DefaultTranslator> {
@@ -1023,15 +1810,107 @@ where
         &self.max_timestamp_length
     }
 
+    /// Return the optional maximum snapshot metadata size, used when the timestamp metadata does
+    /// not list its length.
+    pub fn max_snapshot_length(&self) -> &Option<usize> {
+        &self.max_snapshot_length
+    }
+
+    /// Return the optional maximum targets metadata size, used for top-level and delegated
+    /// targets metadata whose length is not listed in the snapshot metadata.
+    pub fn max_targets_length(&self) -> &Option<usize> {
+        &self.max_targets_length
+    }
+
+    /// The limits fetched metadata is checked against before it is parsed.
+    pub fn json_limits(&self) -> &JsonLimits {
+        &self.json_limits
+    }
+
+    /// The maximum number of root metadata files fetched in a single `Client::update`.
+    pub fn max_root_rotations(&self) -> u32 {
+        self.max_root_rotations
+    }
+
     /// The maximum number of steps used when walking the delegation graph.
     pub fn max_delegation_depth(&self) -> u32 {
         self.max_delegation_depth
     }
 
+    /// The maximum number of targets `Client::fetch_targets` downloads at the same time.
+    pub fn max_concurrent_fetches(&self) -> usize {
+        self.max_concurrent_fetches
+    }
+
+    /// How fetches from the remote repository are retried.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// How long before a role's metadata expires that `Client::update` starts warning about it.
+    pub fn expiration_warning(&self) -> Duration {
+        self.expiration_warning
+    }
+
     /// The `PathTranslator`.
     pub fn path_translator(&self) -> &T {
         &self.path_translator
     }
+
+    /// The `DangerZone` options used when verifying metadata.
+    pub fn danger_zone(&self) -> &DangerZone {
+        &self.danger_zone
+    }
+
+    /// The `SchemePolicy` signatures on fetched metadata must satisfy.
+    pub fn scheme_policy(&self) -> &SchemePolicy {
+        &self.scheme_policy
+    }
+
+    /// The `Clock` that metadata expirations are checked against.
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.clock.0
+    }
+
+    /// The `AuditHook` told about the client's security decisions, if any.
+    pub fn audit_hook(&self) -> Option<&dyn AuditHook> {
+        self.audit_hook.0.as_deref()
+    }
+
+    /// Whether the client keeps the chain of verified root metadata in its `Tuf`.
+    pub fn keep_root_history(&self) -> bool {
+        self.keep_root_history
+    }
+
+    /// The delegated targets roles that `Client::update` fetches ahead of time.
+    pub fn prefetch_delegations(&self) -> &[MetadataPath] {
+        &self.prefetch_delegations
+    }
+
+    /// The `CancellationToken` that aborts the client's operations.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation_token
+    }
+
+    /// The `VersionStore` used to persist the highest trusted metadata versions, if any.
+    pub fn version_store(&self) -> Option<&dyn VersionStore> {
+        self.version_store.0.as_deref()
+    }
+
+    /// The `LocalStore` used to persist the trusted metadata across restarts, if any.
+    pub fn local_store(&self) -> Option<&dyn LocalStore> {
+        self.local_store.0.as_deref()
+    }
+
+    /// The `TargetVerifier`s run on every fetched target, in order.
+    pub fn target_verifiers(&self) -> impl Iterator<Item = &dyn TargetVerifier> {
+        self.target_verifiers.0.iter().map(|v| &**v)
+    }
+
+    /// The `TargetLayout` used to locate targets in the remote repository, if any.
+    pub fn target_layout(&self) -> Option<&dyn TargetLayout> {
+        self.target_layout.0.as_deref()
+    }
 }
 
 impl Default for Config<DefaultTranslator> {
@@ -1039,8 +1918,26 @@ impl Default for Config<DefaultTranslator> {
         Config {
             max_root_length: Some(1024 * 1024),
             max_timestamp_length: Some(32 * 1024),
+            max_snapshot_length: Some(16 * 1024 * 1024),
+            max_targets_length: Some(16 * 1024 * 1024),
+            json_limits: JsonLimits::default(),
+            max_root_rotations: 32,
             max_delegation_depth: 8,
+            max_concurrent_fetches: 4,
+            retry_policy: RetryPolicy::default(),
+            expiration_warning: Duration::hours(1),
             path_translator: DefaultTranslator::new(),
+            target_verifiers: TargetVerifiers::default(),
+            target_layout: SharedTargetLayout::default(),
+            version_store: SharedVersionStore::default(),
+            local_store: SharedLocalStore::default(),
+            danger_zone: DangerZone::default(),
+            scheme_policy: SchemePolicy::default(),
+            clock: SharedClock::default(),
+            audit_hook: SharedAuditHook::default(),
+            keep_root_history: false,
+            prefetch_delegations: Vec::new(),
+            cancellation_token: CancellationToken::default(),
         }
     }
 }
@@ -1053,8 +1950,26 @@ where
 {
     max_root_length: Option<usize>,
     max_timestamp_length: Option<usize>,
+    max_snapshot_length: Option<usize>,
+    max_targets_length: Option<usize>,
+    json_limits: JsonLimits,
+    max_root_rotations: u32,
     max_delegation_depth: u32,
+    max_concurrent_fetches: usize,
+    retry_policy: RetryPolicy,
+    expiration_warning: Duration,
     path_translator: T,
+    target_verifiers: TargetVerifiers,
+    target_layout: SharedTargetLayout,
+    version_store: SharedVersionStore,
+    local_store: SharedLocalStore,
+    danger_zone: DangerZone,
+    scheme_policy: SchemePolicy,
+    clock: SharedClock,
+    audit_hook: SharedAuditHook,
+    keep_root_history: bool,
+    prefetch_delegations: Vec<MetadataPath>,
+    cancellation_token: CancellationToken,
 }
 
 impl<T> ConfigBuilder<T>
@@ -1063,32 +1978,218 @@ where
 {
     /// Validate this builder return a `Config` if validation succeeds.
     pub fn finish(self) -> Result<Config<T>> {
+        if self.max_concurrent_fetches == 0 {
+            return Err(Error::IllegalArgument(
+                "max_concurrent_fetches must be at least 1".into(),
+            ));
+        }
+        if self.max_root_rotations == 0 {
+            return Err(Error::IllegalArgument(
+                "max_root_rotations must be at least 1".into(),
+            ));
+        }
+
         Ok(Config {
             max_root_length: self.max_root_length,
             max_timestamp_length: self.max_timestamp_length,
+            max_snapshot_length: self.max_snapshot_length,
+            max_targets_length: self.max_targets_length,
+            json_limits: self.json_limits,
+            max_root_rotations: self.max_root_rotations,
             max_delegation_depth: self.max_delegation_depth,
+            max_concurrent_fetches: self.max_concurrent_fetches,
+            retry_policy: self.retry_policy,
+            expiration_warning: self.expiration_warning,
             path_translator: self.path_translator,
+            target_verifiers: self.target_verifiers,
+            target_layout: self.target_layout,
+            version_store: self.version_store,
+            local_store: self.local_store,
+            danger_zone: self.danger_zone,
+            scheme_policy: self.scheme_policy,
+            clock: self.clock,
+            audit_hook: self.audit_hook,
+            keep_root_history: self.keep_root_history,
+            prefetch_delegations: self.prefetch_delegations,
+            cancellation_token: self.cancellation_token,
         })
     }
 
-    /// Set the optional maximum download length for root metadata.
+    /// Set the optional maximum download length for root metadata. Defaults to 1 MiB.
+    ///
+    /// Metadata that is longer fails to download with `Error::MetadataLengthExceeded`. `None`
+    /// removes the limit, which lets a malicious repository send an endless stream of data.
     pub fn max_root_length(mut self, max: Option<usize>) -> Self {
         self.max_root_length = max;
         self
     }
 
-    /// Set the optional maximum download length for timestamp metadata.
+    /// Set the optional maximum download length for timestamp metadata. Defaults to 32 KiB.
     pub fn max_timestamp_length(mut self, max: Option<usize>) -> Self {
         self.max_timestamp_length = max;
         self
     }
 
+    /// Set the optional maximum download length for snapshot metadata whose length is not listed
+    /// in the timestamp metadata. Defaults to 16 MiB.
+    pub fn max_snapshot_length(mut self, max: Option<usize>) -> Self {
+        self.max_snapshot_length = max;
+        self
+    }
+
+    /// Set the optional maximum download length for targets metadata whose length is not listed
+    /// in the snapshot metadata. Defaults to 16 MiB.
+    pub fn max_targets_length(mut self, max: Option<usize>) -> Self {
+        self.max_targets_length = max;
+        self
+    }
+
+    /// Set the limits fetched metadata is checked against before it is parsed, so that a
+    /// malicious repository can't exhaust the client with deeply nested or enormous documents.
+    /// These apply on top of the length limits of each role, so raise the size limit as well when
+    /// allowing metadata larger than it. Defaults to [`JsonLimits::default`].
+    pub fn json_limits(mut self, limits: JsonLimits) -> Self {
+        self.json_limits = limits;
+        self
+    }
+
+    /// Set the maximum number of root metadata files fetched in a single `Client::update`, so that
+    /// a malicious repository cannot stall the client with an endless chain of root metadata.
+    /// Once the maximum is reached, the update trusts the newest root fetched so far, even if it
+    /// has expired, but skips the other roles and returns an
+    /// [`UpdateWarning::RootChainIncomplete`]. The next update continues walking the chain.
+    /// Must be at least 1, and defaults to 32.
+    pub fn max_root_rotations(mut self, max: u32) -> Self {
+        self.max_root_rotations = max;
+        self
+    }
+
     /// Set the maximum number of steps used when walking the delegation graph.
     pub fn max_delegation_depth(mut self, max: u32) -> Self {
         self.max_delegation_depth = max;
         self
     }
 
+    /// Set the maximum number of targets `Client::fetch_targets` downloads at the same time.
+    /// Must be at least 1. Defaults to 4.
+    pub fn max_concurrent_fetches(mut self, max: usize) -> Self {
+        self.max_concurrent_fetches = max;
+        self
+    }
+
+    /// Set how fetches from the remote repository are retried when they fail with a transient
+    /// error. Defaults to never retrying.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set how long before a role's metadata expires that `Client::update` starts warning about it.
+    pub fn expiration_warning(mut self, window: Duration) -> Self {
+        self.expiration_warning = window;
+        self
+    }
+
+    /// Add a `TargetVerifier` that is run on every fetched target. Verifiers run in the order they
+    /// were added, and the first rejection aborts the fetch.
+    pub fn target_verifier<V>(mut self, verifier: V) -> Self
+    where
+        V: TargetVerifier + 'static,
+    {
+        self.target_verifiers.0.push(Arc::new(verifier));
+        self
+    }
+
+    /// Set the `TargetLayout` used to locate targets in the remote repository.
+    pub fn target_layout<Y>(mut self, layout: Y) -> Self
+    where
+        Y: TargetLayout + 'static,
+    {
+        self.target_layout = SharedTargetLayout(Some(Arc::new(layout)));
+        self
+    }
+
+    /// Set the `VersionStore` used to persist the highest trusted metadata versions, so that
+    /// metadata rollbacks are detected across restarts.
+    pub fn version_store<S>(mut self, store: S) -> Self
+    where
+        S: VersionStore + 'static,
+    {
+        self.version_store = SharedVersionStore(Some(Arc::new(store)));
+        self
+    }
+
+    /// Set the `LocalStore` used to persist the trusted metadata, so that a restarted client
+    /// doesn't download unchanged metadata again and detects metadata rollbacks.
+    pub fn local_store<S>(mut self, store: S) -> Self
+    where
+        S: LocalStore + 'static,
+    {
+        self.local_store = SharedLocalStore(Some(Arc::new(store)));
+        self
+    }
+
+    /// Set the `DangerZone` options used when verifying metadata. These weaken TUF's security
+    /// guarantees and are only meant for tests and forensics.
+    pub fn danger_zone(mut self, danger_zone: DangerZone) -> Self {
+        self.danger_zone = danger_zone;
+        self
+    }
+
+    /// Set the `SchemePolicy` signatures on fetched metadata must satisfy, regardless of the keys
+    /// and schemes the repository lists. The initial trusted root is not checked against it.
+    pub fn scheme_policy(mut self, scheme_policy: SchemePolicy) -> Self {
+        self.scheme_policy = scheme_policy;
+        self
+    }
+
+    /// Set the `Clock` that metadata expirations are checked against. Defaults to
+    /// [`SystemClock`](crate::SystemClock).
+    pub fn clock<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        self.clock = SharedClock(Arc::new(clock));
+        self
+    }
+
+    /// Set an `AuditHook` to tell about every root rotation, rejected metadata and verified
+    /// target. Targets are reported once the client has read and verified the whole target, so
+    /// targets read through [`Client::fetch_target_stream`] are not reported unless
+    /// `TargetVerifier`s are configured. Defaults to none.
+    pub fn audit_hook<H>(mut self, hook: H) -> Self
+    where
+        H: AuditHook + 'static,
+    {
+        self.audit_hook = SharedAuditHook(Some(Arc::new(hook)));
+        self
+    }
+
+    /// Set whether the client keeps the chain of verified root metadata, starting from the root it
+    /// is initialized with. See [`Tuf::root_history`].
+    pub fn keep_root_history(mut self, keep: bool) -> Self {
+        self.keep_root_history = keep;
+        self
+    }
+
+    /// Set the delegated targets roles that [`Client::update`] fetches and verifies ahead of time,
+    /// instead of when a target they list is looked up. See [`Tuf::fetch_delegations`]. Defaults
+    /// to none.
+    pub fn prefetch_delegations<I>(mut self, roles: I) -> Self
+    where
+        I: IntoIterator<Item = MetadataPath>,
+    {
+        self.prefetch_delegations = roles.into_iter().collect();
+        self
+    }
+
+    /// Set the `CancellationToken` that aborts the client's updates, target lookups, and target
+    /// downloads once it is cancelled. Defaults to a token that is never cancelled.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = token;
+        self
+    }
+
     /// Set the `PathTranslator`.
     pub fn path_translator<TT>(self, path_translator: TT) -> ConfigBuilder<TT>
     where
@@ -1097,8 +2198,26 @@ where
         ConfigBuilder {
             max_root_length: self.max_root_length,
             max_timestamp_length: self.max_timestamp_length,
+            max_snapshot_length: self.max_snapshot_length,
+            max_targets_length: self.max_targets_length,
+            json_limits: self.json_limits,
+            max_root_rotations: self.max_root_rotations,
             max_delegation_depth: self.max_delegation_depth,
+            max_concurrent_fetches: self.max_concurrent_fetches,
+            retry_policy: self.retry_policy,
+            expiration_warning: self.expiration_warning,
             path_translator,
+            target_verifiers: self.target_verifiers,
+            target_layout: self.target_layout,
+            version_store: self.version_store,
+            local_store: self.local_store,
+            danger_zone: self.danger_zone,
+            scheme_policy: self.scheme_policy,
+            clock: self.clock,
+            audit_hook: self.audit_hook,
+            keep_root_history: self.keep_root_history,
+            prefetch_delegations: self.prefetch_delegations,
+            cancellation_token: self.cancellation_token,
         }
     }
 }
@@ -1109,8 +2228,26 @@ impl Default for ConfigBuilder<DefaultTranslator> {
         ConfigBuilder {
             max_root_length: cfg.max_root_length,
             max_timestamp_length: cfg.max_timestamp_length,
+            max_snapshot_length: cfg.max_snapshot_length,
+            max_targets_length: cfg.max_targets_length,
+            json_limits: cfg.json_limits,
+            max_root_rotations: cfg.max_root_rotations,
             max_delegation_depth: cfg.max_delegation_depth,
+            max_concurrent_fetches: cfg.max_concurrent_fetches,
+            retry_policy: cfg.retry_policy,
+            expiration_warning: cfg.expiration_warning,
             path_translator: cfg.path_translator,
+            target_verifiers: cfg.target_verifiers,
+            target_layout: cfg.target_layout,
+            version_store: cfg.version_store,
+            local_store: cfg.local_store,
+            danger_zone: cfg.danger_zone,
+            scheme_policy: cfg.scheme_policy,
+            clock: cfg.clock,
+            audit_hook: cfg.audit_hook,
+            keep_root_history: cfg.keep_root_history,
+            prefetch_delegations: cfg.prefetch_delegations,
+            cancellation_token: cfg.cancellation_token,
         }
     }
 }
@@ -1121,17 +2258,27 @@ mod test {
     use crate::crypto::{HashAlgorithm, KeyType, PrivateKey, SignatureScheme};
     use crate::interchange::Json;
     use crate::metadata::{
-        MetadataPath, MetadataVersion, RootMetadata, RootMetadataBuilder, SnapshotMetadataBuilder,
-        TargetsMetadataBuilder, TimestampMetadataBuilder,
+        Delegation, Delegations, HashedBins, HashedBinsBuilder, MetadataPath, MetadataVersion,
+        RootMetadata, RootMetadataBuilder, SnapshotMetadata, SnapshotMetadataBuilder,
+        TargetsMetadataBuilder, TimestampMetadata, TimestampMetadataBuilder,
+    };
+    use crate::repository::{
+        publish_hashed_bins, publish_metadata, EphemeralRepository, FileSystemRepository,
+        HttpRepository, Mirror, MirrorRepositoryBuilder,
     };
-    use crate::repository::EphemeralRepository;
+    use crate::tuf::AuditEvent;
     use chrono::prelude::*;
     use futures_executor::block_on;
+    use futures_util::future::{self, BoxFuture, FutureExt, TryFutureExt};
+    use futures_util::io::repeat;
     use lazy_static::lazy_static;
     use maplit::hashmap;
     use matches::assert_matches;
     use serde_json::json;
     use std::iter::once;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+    use std::task::{Context, Poll};
 
     lazy_static! {
         static ref KEYS: Vec<PrivateKey> = {
@@ -1149,8 +2296,115 @@ mod test {
         };
     }
 
-    #[test]
-    fn client_constructors_err_with_not_found() {
+    /// A root metadata builder that trusts `KEYS[0]` for every top-level role.
+    fn root_builder() -> RootMetadataBuilder {
+        RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+    }
+
+    /// Snapshot metadata that describes `targets`, and timestamp metadata that describes the
+    /// snapshot, both signed with `KEYS[0]`.
+    fn snapshot_and_timestamp(
+        targets: &SignedMetadata<Json, TargetsMetadata>,
+    ) -> (
+        SignedMetadata<Json, SnapshotMetadata>,
+        SignedMetadata<Json, TimestampMetadata>,
+    ) {
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+        (snapshot, timestamp)
+    }
+
+    /// Publish the top-level metadata to `repo`.
+    async fn publish_top_level(
+        repo: &EphemeralRepository<Json>,
+        root: &SignedMetadata<Json, RootMetadata>,
+        timestamp: &SignedMetadata<Json, TimestampMetadata>,
+        snapshot: &SignedMetadata<Json, SnapshotMetadata>,
+        targets: &SignedMetadata<Json, TargetsMetadata>,
+        consistent_snapshot: bool,
+    ) {
+        publish_metadata(
+            repo,
+            &MetadataPath::from_role(&Role::Root),
+            root,
+            consistent_snapshot,
+        )
+        .await
+        .unwrap();
+        publish_metadata(
+            repo,
+            &MetadataPath::from_role(&Role::Timestamp),
+            timestamp,
+            consistent_snapshot,
+        )
+        .await
+        .unwrap();
+        publish_metadata(
+            repo,
+            &MetadataPath::from_role(&Role::Snapshot),
+            snapshot,
+            consistent_snapshot,
+        )
+        .await
+        .unwrap();
+        publish_metadata(
+            repo,
+            &MetadataPath::from_role(&Role::Targets),
+            targets,
+            consistent_snapshot,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn client_futures_are_send() {
+        fn assert_send<T: Send>(_: T) {}
+
+        // Type checking this function is the test, so it is never called.
+        #[allow(dead_code)]
+        fn check<C>(
+            local: EphemeralRepository<Json>,
+            remote: HttpRepository<C, Json>,
+            root: SignedMetadata<Json, RootMetadata>,
+            mut client: Client<
+                Json,
+                FileSystemRepository<Json>,
+                HttpRepository<C, Json>,
+                DefaultTranslator,
+            >,
+            target: TargetPath,
+        ) where
+            C: hyper::client::connect::Connect + Sync + 'static,
+        {
+            assert_send(Client::with_trusted_root(
+                Config::default(),
+                root,
+                local,
+                remote,
+            ));
+            assert_send(client.update());
+            assert_send(client.fetch_target(&target));
+            assert_send(client.fetch_targets(std::slice::from_ref(&target)));
+            assert_send(client.fetch_target_to_writer(&target, Vec::new()));
+            assert_send(client.fetch_target_to_path(&target, "target"));
+        }
+    }
+
+    #[test]
+    fn client_constructors_err_with_not_found() {
         block_on(async {
             let local = EphemeralRepository::<Json>::new();
             let remote = EphemeralRepository::<Json>::new();
@@ -1422,7 +2676,7 @@ mod test {
             .await
             .unwrap();
 
-            assert_eq!(client.update().await, Ok(true));
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
             assert_eq!(client.tuf.root().version(), 1);
 
             assert_eq!(
@@ -1478,7 +2732,7 @@ mod test {
             ////
             // Finally, check that the update brings us to version 3.
 
-            assert_eq!(client.update().await, Ok(true));
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
             assert_eq!(client.tuf.root().version(), 3);
 
             assert_eq!(
@@ -1643,7 +2897,7 @@ mod test {
 
         ////
         // Ensure client doesn't fetch previous version (1).
-        assert_eq!(client.update().await, Ok(true));
+        assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
         assert_eq!(client.tuf.root().version(), 2);
 
         assert_eq!(
@@ -1658,147 +2912,2099 @@ mod test {
     }
 
     #[test]
-    fn test_fetch_target_description_standard() {
-        block_on(test_fetch_target_description(
-            "standard/metadata".to_string(),
-            TargetDescription::from_reader(
-                "target with no custom metadata".as_bytes(),
-                &[HashAlgorithm::Sha256],
-            )
-            .unwrap(),
-        ));
-    }
-
-    #[test]
-    fn test_fetch_target_description_custom_empty() {
-        block_on(test_fetch_target_description(
-            "custom-empty".to_string(),
-            TargetDescription::from_reader_with_custom(
-                "target with empty custom metadata".as_bytes(),
-                &[HashAlgorithm::Sha256],
-                hashmap!(),
-            )
-            .unwrap(),
-        ));
-    }
-
-    #[test]
-    fn test_fetch_target_description_custom() {
-        block_on(test_fetch_target_description(
-            "custom/metadata".to_string(),
-            TargetDescription::from_reader_with_custom(
-                "target with lots of custom metadata".as_bytes(),
-                &[HashAlgorithm::Sha256],
-                hashmap!(
-                    "string".to_string() => json!("string"),
-                    "bool".to_string() => json!(true),
-                    "int".to_string() => json!(42),
-                    "object".to_string() => json!({
-                        "string": json!("string"),
-                        "bool": json!(true),
-                        "int": json!(42),
-                    }),
-                    "array".to_string() => json!([1, 2, 3]),
-                ),
-            )
-            .unwrap(),
-        ));
-    }
+    fn consistent_snapshot_fetches_versioned_metadata_and_prefixed_targets() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
+            let mut remote = Repository::new(&repo);
+            let delegation_path = MetadataPath::new("delegation").unwrap();
 
-    async fn test_fetch_target_description(path: String, expected_description: TargetDescription) {
-        // Generate an ephemeral repository with a single target.
-        let repo = EphemeralRepository::<Json>::new();
-        let mut remote = Repository::new(&repo);
+            let root = root_builder()
+                .consistent_snapshot(true)
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
 
-        let root = RootMetadataBuilder::new()
-            .root_key(KEYS[0].public().clone())
-            .snapshot_key(KEYS[0].public().clone())
-            .targets_key(KEYS[0].public().clone())
-            .timestamp_key(KEYS[0].public().clone())
-            .signed::<Json>(&KEYS[0])
-            .unwrap();
+            let delegated = TargetsMetadataBuilder::new()
+                .version(3)
+                .insert_target_from_reader(
+                    VirtualTargetPath::new("c/d.txt".into()).unwrap(),
+                    &b"delegated"[..],
+                    &[HashAlgorithm::Sha256],
+                )
+                .unwrap()
+                .signed::<Json>(&KEYS[1])
+                .unwrap();
 
-        let targets = TargetsMetadataBuilder::new()
-            .insert_target_description(
-                VirtualTargetPath::new(path.clone()).unwrap(),
-                expected_description.clone(),
+            let delegations = Delegations::new(
+                hashmap! { KEYS[1].key_id().clone() => KEYS[1].public().clone() },
+                vec![Delegation::new(
+                    delegation_path.clone(),
+                    false,
+                    1,
+                    once(KEYS[1].key_id().clone()).collect(),
+                    once(VirtualTargetPath::new("c/".into()).unwrap()).collect(),
+                )
+                .unwrap()],
             )
-            .signed::<Json>(&KEYS[0])
-            .unwrap();
-
-        let snapshot = SnapshotMetadataBuilder::new()
-            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
-            .unwrap()
-            .signed::<Json>(&KEYS[0])
             .unwrap();
+            let targets = TargetsMetadataBuilder::new()
+                .version(2)
+                .delegations(delegations)
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
 
-        let timestamp =
-            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+            let snapshot = SnapshotMetadataBuilder::new()
+                .version(4)
+                .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .insert_metadata_with_path("delegation", &delegated, &[HashAlgorithm::Sha256])
                 .unwrap()
                 .signed::<Json>(&KEYS[0])
                 .unwrap();
+            let timestamp =
+                TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                    .unwrap()
+                    .signed::<Json>(&KEYS[0])
+                    .unwrap();
 
-        // Register the metadata in the remote repository.
-        let root_path = MetadataPath::from_role(&Role::Root);
-        let targets_path = MetadataPath::from_role(&Role::Targets);
-        let snapshot_path = MetadataPath::from_role(&Role::Snapshot);
-        let timestamp_path = MetadataPath::from_role(&Role::Timestamp);
-
-        remote
-            .store_metadata(
-                &root_path,
-                &MetadataVersion::Number(1),
-                &root.to_raw().unwrap(),
+            // Only store the file names a client uses when consistent snapshots are enabled, so
+            // the client fails if it asks for anything else.
+            publish_metadata(&repo, &MetadataPath::from_role(&Role::Root), &root, true)
+                .await
+                .unwrap();
+            publish_metadata(
+                &repo,
+                &MetadataPath::from_role(&Role::Timestamp),
+                &timestamp,
+                true,
             )
             .await
             .unwrap();
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Snapshot),
+                    &MetadataVersion::Number(4),
+                    &snapshot.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Targets),
+                    &MetadataVersion::Number(2),
+                    &targets.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
+            remote
+                .store_metadata(
+                    &delegation_path,
+                    &MetadataVersion::Number(3),
+                    &delegated.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
 
-        remote
-            .store_metadata(&root_path, &MetadataVersion::None, &root.to_raw().unwrap())
-            .await
-            .unwrap();
+            let target_path = TargetPath::new("c/d.txt".into()).unwrap();
+            let description = delegated
+                .assume_valid()
+                .unwrap()
+                .targets()
+                .values()
+                .next()
+                .unwrap()
+                .clone();
+            let (_, hash) = crypto::hash_preference(description.hashes()).unwrap();
+            remote
+                .store_target(
+                    &b"delegated"[..],
+                    &target_path.with_hash_prefix(hash).unwrap(),
+                )
+                .await
+                .unwrap();
 
-        remote
-            .store_metadata(
-                &targets_path,
-                &MetadataVersion::None,
-                &targets.to_raw().unwrap(),
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
             )
             .await
             .unwrap();
+            assert!(client.update().await.unwrap().updated());
 
-        remote
-            .store_metadata(
-                &snapshot_path,
-                &MetadataVersion::None,
-                &snapshot.to_raw().unwrap(),
+            let mut buf = Vec::new();
+            client
+                .fetch_target_to_writer(&target_path, &mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, b"delegated");
+        })
+    }
+
+    #[test]
+    fn version_only_snapshot_entries_skip_length_and_hash_checks() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
+            let delegation_path = MetadataPath::new("delegation").unwrap();
+
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+
+            let delegated = TargetsMetadataBuilder::new()
+                .insert_target_from_reader(
+                    VirtualTargetPath::new("c/d.txt".into()).unwrap(),
+                    &b"delegated"[..],
+                    &[HashAlgorithm::Sha256],
+                )
+                .unwrap()
+                .signed::<Json>(&KEYS[1])
+                .unwrap();
+            let delegations = Delegations::new(
+                hashmap! { KEYS[1].key_id().clone() => KEYS[1].public().clone() },
+                vec![Delegation::new(
+                    delegation_path.clone(),
+                    false,
+                    1,
+                    once(KEYS[1].key_id().clone()).collect(),
+                    once(VirtualTargetPath::new("c/".into()).unwrap()).collect(),
+                )
+                .unwrap()],
             )
-            .await
             .unwrap();
+            let targets = TargetsMetadataBuilder::new()
+                .delegations(delegations)
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
 
-        remote
-            .store_metadata(
-                &timestamp_path,
-                &MetadataVersion::None,
-                &timestamp.to_raw().unwrap(),
+            let snapshot = SnapshotMetadataBuilder::new()
+                .insert_metadata_version(&targets)
+                .unwrap()
+                .insert_metadata_version_with_path("delegation", &delegated)
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let timestamp =
+                TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                    .unwrap()
+                    .signed::<Json>(&KEYS[0])
+                    .unwrap();
+
+            publish_top_level(&repo, &root, &timestamp, &snapshot, &targets, false).await;
+            publish_metadata(&repo, &delegation_path, &delegated, false)
+                .await
+                .unwrap();
+            let target_path = TargetPath::new("c/d.txt".into()).unwrap();
+            Repository::<_, Json>::new(&repo)
+                .store_target(&b"delegated"[..], &target_path)
+                .await
+                .unwrap();
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &repo,
             )
             .await
             .unwrap();
+            assert!(client.update().await.unwrap().updated());
 
-        // Initialize and update client.
-        let mut client =
-            Client::with_trusted_root(Config::default(), root, EphemeralRepository::new(), repo)
+            let mut buf = Vec::new();
+            client
+                .fetch_target_to_writer(&target_path, &mut buf)
                 .await
                 .unwrap();
+            assert_eq!(buf, b"delegated");
 
-        assert_eq!(client.update().await, Ok(true));
+            // Without a listed length, the configured limit bounds the download.
+            let config = Config::build()
+                .max_targets_length(Some(16))
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert!(client.update().await.is_err());
+        })
+    }
 
-        // Verify fetch_target_description returns expected target metadata
-        let description = client
-            .fetch_target_description(&TargetPath::new(path).unwrap())
-            .await
-            .unwrap();
+    #[test]
+    fn version_only_timestamp_snapshot_entry() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
 
-        assert_eq!(description, expected_description);
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+            let targets = TargetsMetadataBuilder::new()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let snapshot = SnapshotMetadataBuilder::new()
+                .version(2)
+                .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let timestamp = TimestampMetadataBuilder::from_snapshot_version(&snapshot)
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+            publish_top_level(&repo, &root, &timestamp, &snapshot, &targets, false).await;
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert!(client.update().await.unwrap().updated());
+            assert_eq!(client.tuf().snapshot().map(|s| s.version()), Some(2));
+
+            // Without a listed length, the configured limit bounds the snapshot download.
+            let config = Config::build()
+                .max_snapshot_length(Some(16))
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert!(client.update().await.is_err());
+        })
+    }
+
+    #[test]
+    fn update_rejects_mismatched_root_version() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
+            let mut remote = Repository::new(&repo);
+
+            let root1 = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+            let root3 = RootMetadataBuilder::from(root1.assume_valid().unwrap())
+                .version(3)
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+            // Serve version 3 where version 2 should be, which would skip a root in the chain.
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Root),
+                    &MetadataVersion::Number(2),
+                    &root3.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root1,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert_matches!(client.update().await, Err(Error::VerificationFailure(_)));
+            assert_eq!(client.tuf().root().version(), 1);
+        })
+    }
+
+    #[test]
+    fn update_rejects_expired_snapshot() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
+
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+            let targets = TargetsMetadataBuilder::new()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let snapshot = SnapshotMetadataBuilder::new()
+                .expires(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap())
+                .allow_expired(true)
+                .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let timestamp =
+                TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                    .unwrap()
+                    .signed::<Json>(&KEYS[0])
+                    .unwrap();
+
+            publish_top_level(&repo, &root, &timestamp, &snapshot, &targets, false).await;
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert_matches!(
+                client.update().await,
+                Err(Error::ExpiredMetadata(Role::Snapshot))
+            );
+
+            // The expired snapshot is still trusted, so it can be used to detect rollbacks.
+            assert_eq!(client.tuf().snapshot().map(|s| s.version()), Some(1));
+            assert!(client.tuf().targets().is_none());
+        })
+    }
+
+    #[test]
+    fn update_caps_root_rotations() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
+
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+            let targets = TargetsMetadataBuilder::new()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let (snapshot, timestamp) = snapshot_and_timestamp(&targets);
+            publish_top_level(&repo, &root, &timestamp, &snapshot, &targets, false).await;
+            // Only the final root is still valid, the ones it replaced have all expired.
+            for version in 2..=5 {
+                let mut builder =
+                    RootMetadataBuilder::from(root.assume_valid().unwrap()).version(version);
+                if version < 5 {
+                    builder = builder
+                        .expires(Utc::now() - Duration::days(1))
+                        .allow_expired(true);
+                }
+                let root = builder.signed::<Json>(&KEYS[0]).unwrap();
+                publish_metadata(&repo, &MetadataPath::from_role(&Role::Root), &root, false)
+                    .await
+                    .unwrap();
+            }
+
+            let config = Config::build().max_root_rotations(2).finish().unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+
+            // Each update walks at most two more versions of the root chain, and leaves the other
+            // roles alone until it has reached the end of it.
+            let result = client.update().await.unwrap();
+            assert_eq!(client.tuf().root().version(), 3);
+            assert_eq!(
+                result.warnings()[0],
+                UpdateWarning::RootChainIncomplete { version: 3 }
+            );
+            assert!(client.tuf().timestamp().is_none());
+
+            let result = client.update().await.unwrap();
+            assert_eq!(client.tuf().root().version(), 5);
+            assert_eq!(
+                result.warnings()[0],
+                UpdateWarning::RootChainIncomplete { version: 5 }
+            );
+            assert!(client.tuf().timestamp().is_none());
+
+            let result = client.update().await.unwrap();
+            assert_eq!(client.tuf().root().version(), 5);
+            assert!(!result
+                .warnings()
+                .iter()
+                .any(|w| matches!(w, UpdateWarning::RootChainIncomplete { .. })));
+            assert!(client.tuf().timestamp().is_some());
+        })
+    }
+
+    /// Serves an endless stream of data in place of the timestamp metadata.
+    struct EndlessTimestampRepository(EphemeralRepository<Json>);
+
+    impl RepositoryProvider<Json> for EndlessTimestampRepository {
+        fn fetch_metadata<'a>(
+            &'a self,
+            meta_path: &'a MetadataPath,
+            version: &'a MetadataVersion,
+            max_length: Option<usize>,
+            hash_data: Option<(&'static HashAlgorithm, HashValue)>,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            if meta_path == &MetadataPath::from_role(&Role::Timestamp) {
+                let reader: Box<dyn AsyncRead + Send + Unpin> = Box::new(repeat(b' '));
+                return future::ready(Ok(reader)).boxed();
+            }
+            self.0
+                .fetch_metadata(meta_path, version, max_length, hash_data)
+        }
+
+        fn fetch_target<'a>(
+            &'a self,
+            target_path: &'a TargetPath,
+            target_description: &'a TargetDescription,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            self.0.fetch_target(target_path, target_description)
+        }
+    }
+
+    #[test]
+    fn update_stops_endless_metadata() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+            publish_metadata(&repo, &MetadataPath::from_role(&Role::Root), &root, false)
+                .await
+                .unwrap();
+
+            let config = Config::build()
+                .max_timestamp_length(Some(1024))
+                .finish()
+                .unwrap();
+            let mut client = Client::with_trusted_root(
+                config,
+                root,
+                EphemeralRepository::new(),
+                EndlessTimestampRepository(repo),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                client.update().await.unwrap_err(),
+                Error::MetadataLengthExceeded {
+                    path: MetadataPath::from_role(&Role::Timestamp),
+                    max_length: 1024,
+                }
+            );
+        })
+    }
+
+    #[test]
+    fn update_checks_metadata_against_json_limits() {
+        block_on(async {
+            let path = TargetPath::new("a".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, b"a").await;
+
+            // Every role's metadata nests deeper than this.
+            let config = Config::build()
+                .json_limits(JsonLimits::default().max_depth(Some(2)))
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root.clone(), EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert_matches!(client.update().await, Err(Error::MetadataTooLarge(_)));
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert!(client.update().await.unwrap().updated());
+        })
+    }
+
+    #[test]
+    fn fetch_targets_from_hashed_bins() {
+        block_on(async {
+            let paths = (0..50)
+                .map(|i| TargetPath::new(format!("pkg/file-{}", i)).unwrap())
+                .collect::<Vec<_>>();
+            let targets = paths
+                .iter()
+                .map(|path| (path, path.value().as_bytes()))
+                .collect::<Vec<_>>();
+            let (repo, root, bins) = repo_with_targets(&targets, Some(8)).await;
+            let bins = bins.unwrap();
+            assert_eq!(bins.bins().len(), 16);
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert!(client.update().await.unwrap().updated());
+
+            for path in &paths {
+                let mut buf = Vec::new();
+                client.fetch_target_to_writer(path, &mut buf).await.unwrap();
+                assert_eq!(buf, path.value().as_bytes());
+            }
+            // Only bins that some fetched target hashes into get loaded.
+            let non_empty_bins = bins
+                .bins()
+                .iter()
+                .filter(|bin| !bin.metadata().assume_valid().unwrap().targets().is_empty())
+                .count();
+            assert_eq!(client.tuf().delegations().len(), non_empty_bins);
+        })
+    }
+
+    #[test]
+    fn update_prefetches_delegations() {
+        block_on(async {
+            let paths = (0..20)
+                .map(|i| TargetPath::new(format!("pkg/file-{}", i)).unwrap())
+                .collect::<Vec<_>>();
+            let targets = paths
+                .iter()
+                .map(|path| (path, path.value().as_bytes()))
+                .collect::<Vec<_>>();
+            let (repo, root, bins) = repo_with_targets(&targets, Some(8)).await;
+            let bins = bins.unwrap();
+            let mut pinned = bins
+                .bins()
+                .iter()
+                .take(2)
+                .map(|bin| bin.role().clone())
+                .collect::<Vec<_>>();
+            pinned.sort();
+
+            let config = Config::build()
+                .prefetch_delegations(pinned.clone())
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root.clone(), EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert!(client.update().await.unwrap().updated());
+
+            let mut fetched = client.tuf().delegations().keys().collect::<Vec<_>>();
+            fetched.sort();
+            assert_eq!(fetched, pinned.iter().collect::<Vec<_>>());
+
+            // A role that nothing delegates to fails the update.
+            let config = Config::build()
+                .prefetch_delegations(vec![MetadataPath::new("missing").unwrap()])
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert_matches!(client.update().await, Err(Error::VerificationFailure(_)));
+        })
+    }
+
+    #[test]
+    fn list_targets_fetches_every_delegation() {
+        block_on(async {
+            let mut paths = (0..20)
+                .map(|i| TargetPath::new(format!("pkg/file-{}", i)).unwrap())
+                .collect::<Vec<_>>();
+            let targets = paths
+                .iter()
+                .map(|path| (path, path.value().as_bytes()))
+                .collect::<Vec<_>>();
+            let (repo, root, bins) = repo_with_targets(&targets, Some(8)).await;
+            let bins = bins.unwrap();
+
+            let local = EphemeralRepository::<Json>::new();
+            let mut client = Client::with_trusted_root(Config::default(), root, &local, &repo)
+                .await
+                .unwrap();
+            assert!(client.update().await.unwrap().updated());
+            assert!(client.tuf().delegations().is_empty());
+
+            let listed = client.list_targets().await.unwrap();
+            paths.sort();
+            assert_eq!(
+                listed.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+                paths.iter().collect::<Vec<_>>()
+            );
+            for (path, description) in &listed {
+                assert_eq!(
+                    client.fetch_target_description(path).await.as_ref(),
+                    Ok(description)
+                );
+            }
+
+            // Every bin was verified and stored locally.
+            assert_eq!(client.tuf().delegations().len(), bins.bins().len());
+            for bin in bins.bins() {
+                assert!(Repository::<_, Json>::new(&local)
+                    .fetch_metadata::<TargetsMetadata>(
+                        bin.role(),
+                        &MetadataVersion::None,
+                        None,
+                        None
+                    )
+                    .await
+                    .is_ok());
+            }
+        })
+    }
+
+    #[test]
+    fn test_fetch_target_description_standard() {
+        block_on(test_fetch_target_description(
+            "standard/metadata".to_string(),
+            TargetDescription::from_reader(
+                "target with no custom metadata".as_bytes(),
+                &[HashAlgorithm::Sha256],
+            )
+            .unwrap(),
+        ));
+    }
+
+    #[test]
+    fn test_fetch_target_description_custom_empty() {
+        block_on(test_fetch_target_description(
+            "custom-empty".to_string(),
+            TargetDescription::from_reader_with_custom(
+                "target with empty custom metadata".as_bytes(),
+                &[HashAlgorithm::Sha256],
+                hashmap!(),
+            )
+            .unwrap(),
+        ));
+    }
+
+    #[test]
+    fn test_fetch_target_description_custom() {
+        block_on(test_fetch_target_description(
+            "custom/metadata".to_string(),
+            TargetDescription::from_reader_with_custom(
+                "target with lots of custom metadata".as_bytes(),
+                &[HashAlgorithm::Sha256],
+                hashmap!(
+                    "string".to_string() => json!("string"),
+                    "bool".to_string() => json!(true),
+                    "int".to_string() => json!(42),
+                    "object".to_string() => json!({
+                        "string": json!("string"),
+                        "bool": json!(true),
+                        "int": json!(42),
+                    }),
+                    "array".to_string() => json!([1, 2, 3]),
+                ),
+            )
+            .unwrap(),
+        ));
+    }
+
+    #[test]
+    fn fetch_target_returns_custom_metadata() {
+        block_on(async {
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let data = b"firmware image";
+            let (repo, root) = repo_with_target(&path, data).await;
+
+            let description = TargetDescription::from_reader_with_custom(
+                &data[..],
+                &[HashAlgorithm::Sha256],
+                hashmap!("severity".to_string() => json!("critical")),
+            )
+            .unwrap();
+            publish_targets(
+                &mut Repository::new(&repo),
+                2,
+                TargetsMetadataBuilder::new().insert_target_description(
+                    VirtualTargetPath::new(path.value().into()).unwrap(),
+                    description.clone(),
+                ),
+            )
+            .await;
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert!(client.update().await.unwrap().updated());
+
+            assert_eq!(client.fetch_target(&path).await.as_ref(), Ok(&description));
+
+            let mut buf = Vec::new();
+            let fetched = client
+                .fetch_target_to_writer(&path, &mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, data);
+            assert_eq!(
+                fetched.custom_field::<String>("severity"),
+                Ok(Some("critical".into()))
+            );
+        })
+    }
+
+    async fn test_fetch_target_description(path: String, expected_description: TargetDescription) {
+        // Generate an ephemeral repository with a single target.
+        let repo = EphemeralRepository::<Json>::new();
+        let mut remote = Repository::new(&repo);
+
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let targets = TargetsMetadataBuilder::new()
+            .insert_target_description(
+                VirtualTargetPath::new(path.clone()).unwrap(),
+                expected_description.clone(),
+            )
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        // Register the metadata in the remote repository.
+        let root_path = MetadataPath::from_role(&Role::Root);
+        let targets_path = MetadataPath::from_role(&Role::Targets);
+        let snapshot_path = MetadataPath::from_role(&Role::Snapshot);
+        let timestamp_path = MetadataPath::from_role(&Role::Timestamp);
+
+        remote
+            .store_metadata(
+                &root_path,
+                &MetadataVersion::Number(1),
+                &root.to_raw().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        remote
+            .store_metadata(&root_path, &MetadataVersion::None, &root.to_raw().unwrap())
+            .await
+            .unwrap();
+
+        remote
+            .store_metadata(
+                &targets_path,
+                &MetadataVersion::None,
+                &targets.to_raw().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        remote
+            .store_metadata(
+                &snapshot_path,
+                &MetadataVersion::None,
+                &snapshot.to_raw().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        remote
+            .store_metadata(
+                &timestamp_path,
+                &MetadataVersion::None,
+                &timestamp.to_raw().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // Initialize and update client.
+        let mut client =
+            Client::with_trusted_root(Config::default(), root, EphemeralRepository::new(), repo)
+                .await
+                .unwrap();
+
+        assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
+
+        // Verify fetch_target_description returns expected target metadata
+        let description = client
+            .fetch_target_description(&TargetPath::new(path).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(description, expected_description);
+    }
+
+    #[test]
+    fn fetch_target_runs_target_verifiers() {
+        block_on(async {
+            let data: &[u8] = b"\x7fELF firmware";
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let repo = EphemeralRepository::<Json>::new();
+            let remote = Repository::new(&repo);
+
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+            let targets = TargetsMetadataBuilder::new()
+                .insert_target_from_reader(
+                    VirtualTargetPath::new(path.value().into()).unwrap(),
+                    data,
+                    &[HashAlgorithm::Sha256],
+                )
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let (snapshot, timestamp) = snapshot_and_timestamp(&targets);
+
+            publish_top_level(&repo, &root, &timestamp, &snapshot, &targets, false).await;
+            remote.store_target(data, &path).await.unwrap();
+
+            let config = Config::build()
+                .target_verifier(|_: &TargetPath, _: &TargetDescription, target: &[u8]| {
+                    assert_eq!(target, b"\x7fELF firmware");
+                    Ok(())
+                })
+                .target_verifier(|path: &TargetPath, _: &TargetDescription, target: &[u8]| {
+                    if target.starts_with(b"MZ") {
+                        Ok(())
+                    } else {
+                        Err(Error::TargetRejected {
+                            path: path.clone(),
+                            reason: "not a PE image".into(),
+                        })
+                    }
+                })
+                .finish()
+                .unwrap();
+            assert_eq!(config.target_verifiers().count(), 2);
+
+            let local = EphemeralRepository::<Json>::new();
+            let mut client = Client::with_trusted_root(config, root, &local, &repo)
+                .await
+                .unwrap();
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
+
+            let mut buf = Vec::new();
+            assert_eq!(
+                client.fetch_target_to_writer(&path, &mut buf).await,
+                Err(Error::TargetRejected {
+                    path: path.clone(),
+                    reason: "not a PE image".into(),
+                })
+            );
+            assert!(buf.is_empty());
+
+            assert_matches!(
+                client.fetch_target(&path).await,
+                Err(Error::TargetRejected { .. })
+            );
+            let description = client.fetch_target_description(&path).await.unwrap();
+            assert_eq!(
+                Repository::<_, Json>::new(&local)
+                    .fetch_target(&path, &description)
+                    .await
+                    .err(),
+                Some(Error::NotFound)
+            );
+        })
+    }
+
+    /// A repository that holds `data` at `path`, and the root metadata to trust it with.
+    async fn repo_with_target(
+        path: &TargetPath,
+        data: &[u8],
+    ) -> (
+        EphemeralRepository<Json>,
+        SignedMetadata<Json, RootMetadata>,
+    ) {
+        let (repo, root, _) = repo_with_targets(&[(path, data)], None).await;
+        (repo, root)
+    }
+
+    /// A repository that holds each `data` at its `path`, and the root metadata to trust it with.
+    ///
+    /// The targets are listed in the top-level targets metadata, unless `max_targets_per_bin` is
+    /// set, in which case the top-level targets metadata delegates them to hashed bins of at most
+    /// that many targets each, which are returned as well.
+    async fn repo_with_targets(
+        targets: &[(&TargetPath, &[u8])],
+        max_targets_per_bin: Option<usize>,
+    ) -> (
+        EphemeralRepository<Json>,
+        SignedMetadata<Json, RootMetadata>,
+        Option<HashedBins<Json>>,
+    ) {
+        let repo = EphemeralRepository::<Json>::new();
+
+        let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+        let descriptions = targets.iter().map(|(path, data)| {
+            let description = TargetDescription::from_reader(
+                *data,
+                &[HashAlgorithm::Sha256, HashAlgorithm::Sha512],
+            )
+            .unwrap();
+            (
+                VirtualTargetPath::new(path.value().into()).unwrap(),
+                description,
+            )
+        });
+
+        let (targets_metadata, bins) = match max_targets_per_bin {
+            None => {
+                let mut builder = TargetsMetadataBuilder::new();
+                for (path, description) in descriptions {
+                    builder = builder.insert_target_description(path, description);
+                }
+                (builder.signed::<Json>(&KEYS[0]).unwrap(), None)
+            }
+            Some(max_targets_per_bin) => {
+                let mut builder = HashedBinsBuilder::with_max_targets_per_bin(max_targets_per_bin)
+                    .key(KEYS[1].public().clone());
+                for (path, description) in descriptions {
+                    builder = builder.insert_target_description(path, description);
+                }
+                let bins = builder.signed::<Json>(&KEYS[1]).unwrap();
+                let targets_metadata = TargetsMetadataBuilder::new()
+                    .delegations(bins.delegations().clone())
+                    .signed::<Json>(&KEYS[0])
+                    .unwrap();
+                (targets_metadata, Some(bins))
+            }
+        };
+
+        let mut snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets_metadata, &[HashAlgorithm::Sha256])
+            .unwrap();
+        if let Some(bins) = &bins {
+            snapshot = bins
+                .insert_into_snapshot(snapshot, &[HashAlgorithm::Sha256])
+                .unwrap();
+        }
+        let snapshot = snapshot.signed::<Json>(&KEYS[0]).unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        publish_top_level(
+            &repo,
+            &root,
+            &timestamp,
+            &snapshot,
+            &targets_metadata,
+            false,
+        )
+        .await;
+        if let Some(bins) = &bins {
+            publish_hashed_bins(&repo, bins, false).await.unwrap();
+        }
+        for (path, data) in targets {
+            Repository::<_, Json>::new(&repo)
+                .store_target(*data, path)
+                .await
+                .unwrap();
+        }
+
+        (repo, root, bins)
+    }
+
+    #[test]
+    fn audit_hook_reports_fetched_targets() {
+        block_on(async {
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, b"firmware image").await;
+
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let recorded = Arc::clone(&events);
+            let config = Config::build()
+                .audit_hook(move |event: &AuditEvent| recorded.lock().unwrap().push(event.clone()))
+                .finish()
+                .unwrap();
+            let local = EphemeralRepository::<Json>::new();
+            let mut client = Client::with_trusted_root(config, root, &local, &repo)
+                .await
+                .unwrap();
+            client.update().await.unwrap();
+            client.fetch_target(&path).await.unwrap();
+
+            assert_eq!(
+                *events.lock().unwrap(),
+                vec![AuditEvent::TargetVerified {
+                    target: VirtualTargetPath::new("firmware.bin".into()).unwrap(),
+                    role: MetadataPath::from_role(&Role::Targets),
+                }]
+            );
+        })
+    }
+
+    #[test]
+    fn verify_target_checks_downloaded_file_offline() {
+        block_on(async {
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, b"firmware image").await;
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            client.update().await.unwrap();
+
+            let temp_dir = tempfile::Builder::new()
+                .prefix("rust-tuf")
+                .tempdir()
+                .unwrap();
+            let file_path = temp_dir.path().join("firmware.bin");
+            client
+                .fetch_target_to_path(&path, &file_path)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                client.verify_target(&path, File::open(&file_path).unwrap()),
+                Ok(())
+            );
+
+            fs::write(&file_path, b"tampered image").unwrap();
+            assert_matches!(
+                client.verify_target(&path, File::open(&file_path).unwrap()),
+                Err(Error::VerificationFailure(_))
+            );
+        })
+    }
+
+    #[test]
+    fn fetch_targets_stores_every_target() {
+        block_on(async {
+            let paths = (0..5)
+                .map(|i| TargetPath::new(format!("firmware-{}.bin", i)).unwrap())
+                .collect::<Vec<_>>();
+            let datas = (0..5)
+                .map(|i| format!("firmware image {}", i).into_bytes())
+                .collect::<Vec<_>>();
+            let targets = paths
+                .iter()
+                .zip(datas.iter())
+                .map(|(path, data)| (path, &data[..]))
+                .collect::<Vec<_>>();
+            let (repo, root, _) = repo_with_targets(&targets, None).await;
+
+            let config = Config::build().max_concurrent_fetches(2).finish().unwrap();
+            let local = EphemeralRepository::<Json>::new();
+            let mut client = Client::with_trusted_root(config, root, &local, &repo)
+                .await
+                .unwrap();
+            client.update().await.unwrap();
+
+            client.fetch_targets(&paths).await.unwrap();
+
+            for (path, data) in targets {
+                let description = client.fetch_target_description(path).await.unwrap();
+                let mut buf = Vec::new();
+                Repository::<_, Json>::new(&local)
+                    .fetch_target(path, &description)
+                    .await
+                    .unwrap()
+                    .read_to_end(&mut buf)
+                    .await
+                    .unwrap();
+                assert_eq!(buf, data);
+            }
+
+            // A target the repository does not list fails the whole batch.
+            let missing = TargetPath::new("missing.bin".into()).unwrap();
+            assert_eq!(
+                client
+                    .fetch_targets(&[paths[0].clone(), missing])
+                    .await
+                    .err(),
+                Some(Error::NotFound)
+            );
+        })
+    }
+
+    #[test]
+    fn config_rejects_zero_concurrent_fetches() {
+        assert_matches!(
+            Config::build().max_concurrent_fetches(0).finish(),
+            Err(Error::IllegalArgument(_))
+        );
+    }
+
+    #[test]
+    fn config_rejects_zero_root_rotations() {
+        assert_matches!(
+            Config::build().max_root_rotations(0).finish(),
+            Err(Error::IllegalArgument(_))
+        );
+    }
+
+    #[test]
+    fn fetch_target_stream_stops_at_listed_length() {
+        block_on(async {
+            let data: &[u8] = b"firmware image";
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, data).await;
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            client.update().await.unwrap();
+
+            let mut buf = Vec::new();
+            let mut read = client.fetch_target_stream(&path).await.unwrap();
+            read.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, data);
+
+            // A target that keeps going past its listed length is cut off.
+            let endless = vec![0; 1024 * 1024];
+            Repository::<_, Json>::new(&repo)
+                .store_target(&endless[..], &path)
+                .await
+                .unwrap();
+            let mut buf = Vec::new();
+            let mut read = client.fetch_target_stream(&path).await.unwrap();
+            let err = read.read_to_end(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::FileTooLarge);
+            assert!(buf.len() <= data.len());
+        })
+    }
+
+    #[test]
+    fn fetch_target_to_path_only_replaces_verified_targets() {
+        block_on(async {
+            let data: &[u8] = b"firmware image";
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, data).await;
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            client.update().await.unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            let dest = dir.path().join("firmware.bin");
+            client.fetch_target_to_path(&path, &dest).await.unwrap();
+            assert_eq!(std::fs::read(&dest).unwrap(), data);
+
+            // A tampered download doesn't overwrite the verified copy, nor leave anything behind.
+            Repository::<_, Json>::new(&repo)
+                .store_target(&b"firmware imagf"[..], &path)
+                .await
+                .unwrap();
+            assert!(client.fetch_target_to_path(&path, &dest).await.is_err());
+            assert_eq!(std::fs::read(&dest).unwrap(), data);
+            assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        })
+    }
+
+    /// A connection that always fails.
+    struct BrokenConnection;
+
+    impl AsyncRead for BrokenConnection {
+        fn poll_read(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context,
+            _: &mut [u8],
+        ) -> std::task::Poll<io::Result<usize>> {
+            std::task::Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        }
+    }
+
+    /// A connection that never delivers any data.
+    struct StalledConnection;
+
+    impl AsyncRead for StalledConnection {
+        fn poll_read(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context,
+            _: &mut [u8],
+        ) -> std::task::Poll<io::Result<usize>> {
+            std::task::Poll::Pending
+        }
+    }
+
+    /// Serves targets from an `EphemeralRepository`, but can break or stall the connection part
+    /// way through, and records where resumed downloads started.
+    struct FlakyRepository {
+        repo: EphemeralRepository<Json>,
+        fail_after: Mutex<Option<u64>>,
+        stall: bool,
+        offsets: Mutex<Vec<u64>>,
+    }
+
+    impl FlakyRepository {
+        fn flaky(
+            &self,
+            read: Box<dyn AsyncRead + Send + Unpin>,
+        ) -> Box<dyn AsyncRead + Send + Unpin> {
+            match *self.fail_after.lock().unwrap() {
+                Some(len) if self.stall => Box::new(read.take(len).chain(StalledConnection)),
+                Some(len) => Box::new(read.take(len).chain(BrokenConnection)),
+                None => read,
+            }
+        }
+    }
+
+    impl RepositoryProvider<Json> for FlakyRepository {
+        fn fetch_metadata<'a>(
+            &'a self,
+            meta_path: &'a MetadataPath,
+            version: &'a MetadataVersion,
+            max_length: Option<usize>,
+            hash_data: Option<(&'static HashAlgorithm, HashValue)>,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            self.repo
+                .fetch_metadata(meta_path, version, max_length, hash_data)
+        }
+
+        fn fetch_target<'a>(
+            &'a self,
+            target_path: &'a TargetPath,
+            target_description: &'a TargetDescription,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            self.repo
+                .fetch_target(target_path, target_description)
+                .map_ok(move |read| self.flaky(read))
+                .boxed()
+        }
+
+        fn fetch_target_from<'a>(
+            &'a self,
+            target_path: &'a TargetPath,
+            target_description: &'a TargetDescription,
+            offset: u64,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            self.offsets.lock().unwrap().push(offset);
+            self.repo
+                .fetch_target_from(target_path, target_description, offset)
+                .map_ok(move |read| self.flaky(read))
+                .boxed()
+        }
+    }
+
+    #[test]
+    fn fetch_target_to_path_resumes_interrupted_downloads() {
+        block_on(async {
+            let data = (0..=255).cycle().take(10_000).collect::<Vec<u8>>();
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, &data).await;
+            let remote = FlakyRepository {
+                repo,
+                fail_after: Mutex::new(Some(1000)),
+                stall: false,
+                offsets: Mutex::new(vec![]),
+            };
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &remote,
+            )
+            .await
+            .unwrap();
+            client.update().await.unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            let dest = dir.path().join("firmware.bin");
+            let part = dir.path().join("firmware.bin.part");
+
+            // The connection breaks, which leaves a partial download but not the target.
+            assert!(client.fetch_target_to_path(&path, &dest).await.is_err());
+            assert!(!dest.exists());
+            assert_eq!(std::fs::read(&part).unwrap(), &data[..1000]);
+
+            // Only the rest is fetched next time.
+            *remote.fail_after.lock().unwrap() = None;
+            client.fetch_target_to_path(&path, &dest).await.unwrap();
+            assert_eq!(std::fs::read(&dest).unwrap(), data);
+            assert!(!part.exists());
+            assert_eq!(*remote.offsets.lock().unwrap(), vec![1000]);
+
+            // A partial download that isn't part of the target is discarded, and the download
+            // starts over.
+            std::fs::write(&part, b"garbage").unwrap();
+            std::fs::remove_file(&dest).unwrap();
+            client.fetch_target_to_path(&path, &dest).await.unwrap();
+            assert_eq!(std::fs::read(&dest).unwrap(), data);
+            assert!(!part.exists());
+            assert_eq!(*remote.offsets.lock().unwrap(), vec![1000, 7]);
+        })
+    }
+
+    /// A retry policy that makes up to `max_attempts` attempts without waiting in between.
+    #[test]
+    fn target_downloads_time_out_when_the_connection_stalls() {
+        block_on(async {
+            let data = (0..=255).cycle().take(10_000).collect::<Vec<u8>>();
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, &data).await;
+            let remote = FlakyRepository {
+                repo,
+                fail_after: Mutex::new(Some(1000)),
+                stall: true,
+                offsets: Mutex::new(vec![]),
+            };
+
+            let policy = RetryPolicy::build()
+                .timeout(Some(std::time::Duration::from_millis(50)))
+                .finish()
+                .unwrap();
+            let config = Config::build().retry_policy(policy).finish().unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &remote)
+                    .await
+                    .unwrap();
+            client.update().await.unwrap();
+
+            let mut buf = Vec::new();
+            assert_matches!(
+                client.fetch_target_to_writer(&path, &mut buf).await,
+                Err(Error::Opaque(_))
+            );
+
+            // A stalled download to a path is kept, so it can be resumed.
+            let dir = tempfile::tempdir().unwrap();
+            let dest = dir.path().join("firmware.bin");
+            assert_matches!(
+                client.fetch_target_to_path(&path, &dest).await,
+                Err(Error::Opaque(_))
+            );
+            assert_eq!(
+                std::fs::read(dir.path().join("firmware.bin.part")).unwrap(),
+                &data[..1000]
+            );
+
+            *remote.fail_after.lock().unwrap() = None;
+            client.fetch_target_to_path(&path, &dest).await.unwrap();
+            assert_eq!(std::fs::read(&dest).unwrap(), data);
+            assert_eq!(*remote.offsets.lock().unwrap(), vec![1000]);
+        })
+    }
+
+    fn instant_retries(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::build()
+            .max_attempts(max_attempts)
+            .sleep(|_| future::ready(()).boxed())
+            .finish()
+            .unwrap()
+    }
+
+    #[test]
+    fn fetch_target_to_path_retries_interrupted_downloads() {
+        block_on(async {
+            let data = (0..=255).cycle().take(10_000).collect::<Vec<u8>>();
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, &data).await;
+            let remote = FlakyRepository {
+                repo,
+                fail_after: Mutex::new(Some(3000)),
+                stall: false,
+                offsets: Mutex::new(vec![]),
+            };
+
+            let config = Config::build()
+                .retry_policy(instant_retries(5))
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &remote)
+                    .await
+                    .unwrap();
+            client.update().await.unwrap();
+
+            // Every attempt gets a little further, so the download completes without the caller
+            // having to retry it. The connection also breaks after the last byte, so the final
+            // attempt only has to verify what was already downloaded.
+            let dir = tempfile::tempdir().unwrap();
+            let dest = dir.path().join("firmware.bin");
+            client.fetch_target_to_path(&path, &dest).await.unwrap();
+            assert_eq!(std::fs::read(&dest).unwrap(), data);
+            assert_eq!(*remote.offsets.lock().unwrap(), vec![3000, 6000, 9000]);
+        })
+    }
+
+    /// Fails the given number of metadata fetches with a transient error before serving metadata
+    /// from an `EphemeralRepository`.
+    struct UnreliableRepository {
+        repo: EphemeralRepository<Json>,
+        failures: Mutex<u32>,
+    }
+
+    impl RepositoryProvider<Json> for UnreliableRepository {
+        fn fetch_metadata<'a>(
+            &'a self,
+            meta_path: &'a MetadataPath,
+            version: &'a MetadataVersion,
+            max_length: Option<usize>,
+            hash_data: Option<(&'static HashAlgorithm, HashValue)>,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return future::err(Error::Opaque("connection reset".into())).boxed();
+            }
+            self.repo
+                .fetch_metadata(meta_path, version, max_length, hash_data)
+        }
+
+        fn fetch_target<'a>(
+            &'a self,
+            target_path: &'a TargetPath,
+            target_description: &'a TargetDescription,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            self.repo.fetch_target(target_path, target_description)
+        }
+    }
+
+    #[test]
+    fn update_retries_transient_errors() {
+        block_on(async {
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, b"firmware image").await;
+            let remote = UnreliableRepository {
+                repo,
+                failures: Mutex::new(0),
+            };
+
+            // Without retries, the first failure is returned.
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &remote,
+            )
+            .await
+            .unwrap();
+            *remote.failures.lock().unwrap() = 1;
+            assert_matches!(client.update().await, Err(Error::Opaque(_)));
+
+            let config = Config::build()
+                .retry_policy(instant_retries(3))
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &remote)
+                    .await
+                    .unwrap();
+            *remote.failures.lock().unwrap() = 2;
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
+
+            // Giving up once every attempt has failed.
+            *remote.failures.lock().unwrap() = 3;
+            assert_matches!(client.update().await, Err(Error::Opaque(_)));
+        })
+    }
+
+    /// An `AsyncWrite` that cancels `token` as soon as anything is written to it.
+    struct CancellingWriter {
+        token: CancellationToken,
+        written: Vec<u8>,
+    }
+
+    impl AsyncWrite for CancellingWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.token.cancel();
+            self.written.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn cancellation_token_aborts_operations() {
+        block_on(async {
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, &[0; 64 * 1024]).await;
+
+            let token = CancellationToken::new();
+            let config = Config::build()
+                .cancellation_token(token.clone())
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert!(client.update().await.unwrap().updated());
+
+            // The download stops at the first read after the token is cancelled.
+            let mut write = CancellingWriter {
+                token: token.clone(),
+                written: Vec::new(),
+            };
+            assert_eq!(
+                client.fetch_target_to_writer(&path, &mut write).await,
+                Err(Error::Cancelled)
+            );
+            assert!(!write.written.is_empty());
+            assert!(write.written.len() < 64 * 1024);
+
+            assert_eq!(client.update().await.err(), Some(Error::Cancelled));
+            assert_eq!(client.fetch_target(&path).await, Err(Error::Cancelled));
+        })
+    }
+
+    #[test]
+    fn client_fails_over_between_mirrors() {
+        block_on(async {
+            let data: &[u8] = b"firmware image";
+            let path = TargetPath::new("firmware.bin".into()).unwrap();
+            let (repo, root) = repo_with_target(&path, data).await;
+            let remote = MirrorRepositoryBuilder::new()
+                .mirror(Mirror::new("empty", EphemeralRepository::<Json>::new()))
+                .mirror(Mirror::new("backup", repo))
+                .build()
+                .unwrap();
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                remote,
+            )
+            .await
+            .unwrap();
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
+
+            let mut buf = Vec::new();
+            client
+                .fetch_target_to_writer(&path, &mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, data);
+        })
+    }
+
+    #[test]
+    fn fetch_target_uses_target_layout() {
+        block_on(async {
+            let data: &[u8] = b"cdn hosted";
+            let path = TargetPath::new("releases/app.tar.gz".into()).unwrap();
+            let repo = EphemeralRepository::<Json>::new();
+            let remote = Repository::new(&repo);
+
+            let root = root_builder()
+                .consistent_snapshot(true)
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let targets = TargetsMetadataBuilder::new()
+                .insert_target_from_reader(
+                    VirtualTargetPath::new(path.value().into()).unwrap(),
+                    data,
+                    &[HashAlgorithm::Sha256],
+                )
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+            let (snapshot, timestamp) = snapshot_and_timestamp(&targets);
+
+            publish_top_level(&repo, &root, &timestamp, &snapshot, &targets, true).await;
+
+            // Shard targets by the first two characters of their hash, without a name.
+            let description = targets.assume_valid().unwrap().targets()
+                [&VirtualTargetPath::new(path.value().into()).unwrap()]
+                .clone();
+            let hash = description.hashes()[&HashAlgorithm::Sha256].to_string();
+            let cdn_path = TargetPath::new(format!("cdn/{}/{}", &hash[..2], hash)).unwrap();
+            remote.store_target(data, &cdn_path).await.unwrap();
+
+            let config = Config::build()
+                .target_layout(
+                    |path: &VirtualTargetPath,
+                     description: &TargetDescription,
+                     consistent: bool| {
+                        assert_eq!(path.value(), "releases/app.tar.gz");
+                        assert!(consistent);
+                        let hash = description.hashes()[&HashAlgorithm::Sha256].to_string();
+                        TargetPath::new(format!("cdn/{}/{}", &hash[..2], hash))
+                    },
+                )
+                .finish()
+                .unwrap();
+
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
+
+            let mut buf = Vec::new();
+            client
+                .fetch_target_to_writer(&path, &mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, data);
+
+            // The layout doesn't bypass verification of what is found at its location.
+            remote
+                .store_target(&b"tampered"[..], &cdn_path)
+                .await
+                .unwrap();
+            let mut buf = Vec::new();
+            assert!(client
+                .fetch_target_to_writer(&path, &mut buf)
+                .await
+                .is_err());
+        })
+    }
+
+    async fn publish_targets(
+        remote: &mut Repository<&EphemeralRepository<Json>, Json>,
+        version: u32,
+        targets: TargetsMetadataBuilder,
+    ) {
+        let targets = targets.version(version).signed::<Json>(&KEYS[0]).unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .version(version)
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .version(version)
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        remote
+            .store_metadata(
+                &MetadataPath::from_role(&Role::Targets),
+                &MetadataVersion::None,
+                &targets.to_raw().unwrap(),
+            )
+            .await
+            .unwrap();
+        remote
+            .store_metadata(
+                &MetadataPath::from_role(&Role::Snapshot),
+                &MetadataVersion::None,
+                &snapshot.to_raw().unwrap(),
+            )
+            .await
+            .unwrap();
+        remote
+            .store_metadata(
+                &MetadataPath::from_role(&Role::Timestamp),
+                &MetadataVersion::None,
+                &timestamp.to_raw().unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn update_reports_changes() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
+            let mut remote = Repository::new(&repo);
+            let path = |p: &str| VirtualTargetPath::new(p.into()).unwrap();
+
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+            let raw_root = root.to_raw().unwrap();
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Root),
+                    &MetadataVersion::None,
+                    &raw_root,
+                )
+                .await
+                .unwrap();
+
+            publish_targets(
+                &mut remote,
+                1,
+                TargetsMetadataBuilder::new()
+                    .insert_target_from_reader(path("a.txt"), &b"a"[..], &[HashAlgorithm::Sha256])
+                    .unwrap()
+                    .insert_target_from_reader(path("b.txt"), &b"b"[..], &[HashAlgorithm::Sha256])
+                    .unwrap(),
+            )
+            .await;
+
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+
+            let result = client.update().await.unwrap();
+            assert!(result.updated());
+            assert!(!result.root_updated());
+            assert!(result.timestamp_updated());
+            assert!(result.snapshot_updated());
+            assert!(result.targets_updated());
+            assert_eq!(result.root_version(), 1);
+            assert_eq!(
+                result.changed_targets(),
+                vec![&path("a.txt"), &path("b.txt")]
+            );
+            assert!(result.warnings().is_empty());
+
+            let summary = result.summary();
+            assert!(!summary.root().is_updated());
+            assert_eq!(summary.root().current(), Some(1));
+            assert_eq!(summary.timestamp().previous(), None);
+            assert_eq!(summary.timestamp().current(), Some(1));
+            assert_eq!(summary.targets().current(), Some(1));
+            assert_eq!(summary.targets_added(), &[path("a.txt"), path("b.txt")]);
+            assert!(summary.targets_changed().is_empty());
+            assert!(summary.targets_removed().is_empty());
+            assert!(summary.bytes_downloaded() > raw_root.as_bytes().len() as u64);
+
+            let delegations = Delegations::new(
+                hashmap! { KEYS[1].key_id().clone() => KEYS[1].public().clone() },
+                vec![Delegation::new(
+                    MetadataPath::new("delegation").unwrap(),
+                    false,
+                    1,
+                    once(KEYS[1].key_id().clone()).collect(),
+                    once(path("c/")).collect(),
+                )
+                .unwrap()],
+            )
+            .unwrap();
+            publish_targets(
+                &mut remote,
+                2,
+                TargetsMetadataBuilder::new()
+                    .insert_target_from_reader(path("a.txt"), &b"A"[..], &[HashAlgorithm::Sha256])
+                    .unwrap()
+                    .insert_target_from_reader(path("c.txt"), &b"c"[..], &[HashAlgorithm::Sha256])
+                    .unwrap()
+                    .delegations(delegations),
+            )
+            .await;
+
+            let result = client.update().await.unwrap();
+            assert_eq!(
+                result.changed_targets(),
+                vec![&path("a.txt"), &path("c.txt")]
+            );
+
+            let summary = result.summary();
+            assert!(summary.updated());
+            assert_eq!(summary.snapshot().previous(), Some(1));
+            assert_eq!(summary.snapshot().current(), Some(2));
+            assert_eq!(summary.targets_added(), &[path("c.txt")]);
+            assert_eq!(summary.targets_changed(), &[path("a.txt")]);
+            assert_eq!(summary.targets_removed(), &[path("b.txt")]);
+            assert_eq!(
+                summary.delegations_added(),
+                &[MetadataPath::new("delegation").unwrap()]
+            );
+            assert!(summary.delegations_removed().is_empty());
+            assert_eq!(
+                summary.to_string(),
+                format!(
+                    "root v1, timestamp v1 -> v2, snapshot v1 -> v2, targets v1 -> v2; \
+                     targets: 1 added, 1 changed, 1 removed; \
+                     delegations: 1 added, 0 removed; \
+                     {} bytes downloaded",
+                    summary.bytes_downloaded()
+                )
+            );
+
+            let result = client.update().await.unwrap();
+            assert!(!result.updated());
+            assert!(result.changed_targets().is_empty());
+            assert_eq!(result.summary().timestamp().to_string(), "v2");
+        })
+    }
+
+    #[test]
+    fn update_warns_about_near_expiry() {
+        block_on(async {
+            let repo = EphemeralRepository::<Json>::new();
+            let mut remote = Repository::new(&repo);
+
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Root),
+                    &MetadataVersion::None,
+                    &root.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
+            publish_targets(&mut remote, 1, TargetsMetadataBuilder::new()).await;
+
+            // Timestamps expire after a day by default, while every other role lasts longer.
+            let config = Config::build()
+                .expiration_warning(Duration::days(2))
+                .finish()
+                .unwrap();
+            let mut client =
+                Client::with_trusted_root(config, root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+
+            let result = client.update().await.unwrap();
+            let expires = *client.tuf().timestamp().unwrap().expires();
+            assert_eq!(
+                result.warnings(),
+                &[UpdateWarning::NearExpiry {
+                    role: Role::Timestamp,
+                    expires,
+                }]
+            );
+        })
+    }
+
+    #[test]
+    fn version_store_prevents_rollback_across_restarts() {
+        block_on(async {
+            let temp_dir = tempfile::Builder::new()
+                .prefix("rust-tuf")
+                .tempdir()
+                .unwrap();
+            let versions_path = temp_dir.path().join("versions.json");
+            let config = || {
+                Config::build()
+                    .version_store(FileVersionStore::new(versions_path.clone()))
+                    .finish()
+                    .unwrap()
+            };
+
+            let root = root_builder().signed::<Json>(&KEYS[0]).unwrap();
+
+            // Keep a copy of the first version of the repository, for an attacker to replay.
+            let old_repo = EphemeralRepository::<Json>::new();
+            let repo = EphemeralRepository::<Json>::new();
+            for (r, version) in [(&old_repo, 1), (&repo, 2)].iter() {
+                let mut remote = Repository::new(*r);
+                remote
+                    .store_metadata(
+                        &MetadataPath::from_role(&Role::Root),
+                        &MetadataVersion::None,
+                        &root.to_raw().unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                publish_targets(&mut remote, *version, TargetsMetadataBuilder::new()).await;
+            }
+
+            let mut client = Client::with_trusted_root(
+                config(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
+            assert_eq!(client.tuf().minimum_versions().timestamp(), 2);
+
+            let stored = FileVersionStore::new(versions_path.clone())
+                .load()
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(stored, client.tuf().trusted_versions());
+            assert_eq!(stored.snapshot(), 2);
+            assert_eq!(stored.targets(), 2);
+
+            // A restarted client with an empty cache must still refuse the old metadata.
+            let mut client = Client::with_trusted_root(
+                config(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &old_repo,
+            )
+            .await
+            .unwrap();
+            assert_eq!(client.tuf().minimum_versions(), &stored);
+            assert_matches!(client.update().await, Err(Error::VerificationFailure(_)));
+
+            // Without the version store the rollback goes unnoticed.
+            let mut client = Client::with_trusted_root(
+                Config::default(),
+                root,
+                EphemeralRepository::new(),
+                &old_repo,
+            )
+            .await
+            .unwrap();
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
+        })
+    }
+
+    #[test]
+    fn local_store_restores_metadata_across_restarts() {
+        block_on(async {
+            let temp_dir = tempfile::Builder::new()
+                .prefix("rust-tuf")
+                .tempdir()
+                .unwrap();
+            let state_path = temp_dir.path().join("trust-state");
+            let config = || {
+                Config::build()
+                    .local_store(FileLocalStore::new(state_path.clone()))
+                    .finish()
+                    .unwrap()
+            };
+
+            // Both repositories share the same root, but only `repo` has moved on to version 2 of
+            // the other roles.
+            let (old_repo, root, _) = repo_with_targets(&[], None).await;
+            let (repo, _, _) = repo_with_targets(&[], None).await;
+            publish_targets(
+                &mut Repository::new(&repo),
+                2,
+                TargetsMetadataBuilder::new(),
+            )
+            .await;
+
+            let mut client = Client::with_trusted_root(
+                config(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(true));
+            assert!(state_path.exists());
+
+            // A restarted client trusts the same metadata before updating, so there is nothing new
+            // to fetch.
+            let mut client = Client::with_trusted_root(
+                config(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert_eq!(client.tuf().timestamp().map(|t| t.version()), Some(2));
+            assert_eq!(client.tuf().targets().map(|t| t.version()), Some(2));
+            assert_eq!(client.update().await.map(|r| r.updated()), Ok(false));
+
+            // It also refuses the older metadata.
+            let mut client = Client::with_trusted_root(
+                config(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &old_repo,
+            )
+            .await
+            .unwrap();
+            assert_matches!(client.update().await, Err(Error::VerificationFailure(_)));
+
+            // Stored metadata for a different root of the same version is ignored.
+            let other_root = RootMetadataBuilder::new()
+                .root_key(KEYS[1].public().clone())
+                .snapshot_key(KEYS[1].public().clone())
+                .targets_key(KEYS[1].public().clone())
+                .timestamp_key(KEYS[1].public().clone())
+                .signed::<Json>(&KEYS[1])
+                .unwrap();
+            let client =
+                Client::with_trusted_root(config(), other_root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert!(client.tuf().timestamp().is_none());
+        })
+    }
+
+    #[test]
+    fn local_store_verifies_stored_metadata() {
+        block_on(async {
+            let temp_dir = tempfile::Builder::new()
+                .prefix("rust-tuf")
+                .tempdir()
+                .unwrap();
+            let state_path = temp_dir.path().join("trust-state");
+            let config = || {
+                Config::build()
+                    .local_store(FileLocalStore::new(state_path.clone()))
+                    .finish()
+                    .unwrap()
+            };
+
+            let (repo, root, _) = repo_with_targets(&[], None).await;
+            let root2 = root_builder().version(2).signed::<Json>(&KEYS[0]).unwrap();
+            publish_metadata(&repo, &MetadataPath::from_role(&Role::Root), &root2, false)
+                .await
+                .unwrap();
+
+            let mut client = Client::with_trusted_root(
+                config(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert!(client.update().await.unwrap().updated());
+
+            // A restarted client walks the stored chain of root metadata from the root it is
+            // created with.
+            let client = Client::with_trusted_root(
+                config(),
+                root.clone(),
+                EphemeralRepository::new(),
+                &repo,
+            )
+            .await
+            .unwrap();
+            assert_eq!(client.tuf().root().version(), 2);
+            assert_eq!(client.tuf().timestamp().map(|t| t.version()), Some(1));
+
+            // Metadata that was written to the store by anyone else, and is signed with keys the
+            // trusted root does not delegate to, is ignored.
+            let forged_root = RootMetadataBuilder::new()
+                .version(2)
+                .root_key(KEYS[1].public().clone())
+                .snapshot_key(KEYS[1].public().clone())
+                .targets_key(KEYS[1].public().clone())
+                .timestamp_key(KEYS[1].public().clone())
+                .signed::<Json>(&KEYS[1])
+                .unwrap();
+            let forged_timestamp = TimestampMetadataBuilder::from_snapshot(
+                &SnapshotMetadataBuilder::new()
+                    .version(100)
+                    .signed::<Json>(&KEYS[1])
+                    .unwrap(),
+                &[HashAlgorithm::Sha256],
+            )
+            .unwrap()
+            .version(100)
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+            let mut forged = SignedState::default();
+            forged.push_root(forged_root);
+            forged.set_timestamp(forged_timestamp);
+            std::fs::write(&state_path, forged.encode().unwrap()).unwrap();
+
+            let client =
+                Client::with_trusted_root(config(), root, EphemeralRepository::new(), &repo)
+                    .await
+                    .unwrap();
+            assert_eq!(client.tuf().root().version(), 1);
+            assert!(client.tuf().timestamp().is_none());
+        })
     }
 }
@@ -0,0 +1,222 @@
+//! Aborting long-running client operations from outside of them.
+
+use futures_util::future::{self, Either};
+use futures_util::io::AsyncRead;
+use futures_util::pin_mut;
+use futures_util::task::AtomicWaker;
+use parking_lot::Mutex;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+
+use crate::error::Error;
+use crate::Result;
+
+/// A handle for aborting the operations of a [`Client`](super::Client).
+///
+/// Clones of a `CancellationToken` share its state, so a host application can keep one clone and
+/// give another to [`ConfigBuilder::cancellation_token`](super::ConfigBuilder::cancellation_token).
+/// Once [`cancel`](CancellationToken::cancel) is called, every operation of the client fails with
+/// [`Error::Cancelled`] as soon as it next waits on the network, including target downloads that
+/// are in progress, and every operation started afterwards fails right away. Metadata verified
+/// before cancellation stays trusted, and a partial download made by
+/// [`Client::fetch_target_to_path`](super::Client::fetch_target_to_path) is kept so it can be
+/// resumed.
+///
+/// Dropping the future of an operation aborts it just as well, at whichever point it was waiting,
+/// so a token is only needed when the future is out of reach, for example after it was spawned.
+///
+/// ```
+/// # use tuf::client::CancellationToken;
+/// let token = CancellationToken::new();
+/// let handle = token.clone();
+/// assert!(!token.is_cancelled());
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    /// The wakers of everything waiting on the token, which are woken once it is cancelled.
+    waiters: Mutex<Vec<Weak<AtomicWaker>>>,
+}
+
+impl CancellationToken {
+    /// Create a new `CancellationToken` that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every operation that uses this token. Cancelling a token more than once has no
+    /// further effect, and a cancelled token cannot be reset.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        for waiter in self.inner.waiters.lock().drain(..) {
+            if let Some(waker) = waiter.upgrade() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Whether [`CancellationToken::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with `Error::Cancelled` if the token has been cancelled.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Run `op`, abandoning it with `Error::Cancelled` as soon as the token is cancelled.
+    pub(crate) async fn run<F, T>(&self, op: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.check()?;
+
+        pin_mut!(op);
+        match future::select(op, Cancelled::new(self)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Err(Error::Cancelled),
+        }
+    }
+
+    /// Register `waker` to be woken once the token is cancelled.
+    fn register(&self, waker: &Arc<AtomicWaker>) {
+        let mut waiters = self.inner.waiters.lock();
+        waiters.retain(|w| w.strong_count() > 0);
+        waiters.push(Arc::downgrade(waker));
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+/// Resolves once a `CancellationToken` is cancelled.
+struct Cancelled {
+    token: CancellationToken,
+    waker: Arc<AtomicWaker>,
+}
+
+impl Cancelled {
+    fn new(token: &CancellationToken) -> Self {
+        let waker = Arc::new(AtomicWaker::new());
+        token.register(&waker);
+        Cancelled {
+            token: token.clone(),
+            waker,
+        }
+    }
+
+    fn poll_cancelled(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.waker.register(cx.waker());
+        if self.token.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.poll_cancelled(cx)
+    }
+}
+
+/// An `AsyncRead` that fails with `Error::Cancelled` once a `CancellationToken` is cancelled,
+/// even while the wrapped reader is waiting for data.
+pub(crate) struct CancellableRead<R> {
+    inner: R,
+    cancelled: Cancelled,
+}
+
+impl<R> CancellableRead<R> {
+    pub(crate) fn new(inner: R, token: &CancellationToken) -> Self {
+        CancellableRead {
+            inner,
+            cancelled: Cancelled::new(token),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CancellableRead<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.cancelled.poll_cancelled(cx).is_ready() {
+            return Poll::Ready(Err(io::Error::other(Error::Cancelled)));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_executor::block_on;
+    use futures_util::io::{AsyncReadExt, Cursor};
+
+    #[test]
+    fn run_fails_once_cancelled() {
+        block_on(async {
+            let token = CancellationToken::new();
+            assert_eq!(token.run(async { Ok(1) }).await, Ok(1));
+
+            // An operation that never finishes on its own is abandoned when the token is
+            // cancelled.
+            let handle = token.clone();
+            let pending = token.run(async {
+                handle.cancel();
+                future::pending::<Result<()>>().await
+            });
+            assert_eq!(pending.await, Err(Error::Cancelled));
+
+            assert_eq!(token.run(async { Ok(1) }).await, Err(Error::Cancelled));
+        })
+    }
+
+    #[test]
+    fn cancellable_read_stops_reading() {
+        block_on(async {
+            let token = CancellationToken::new();
+            let mut read = CancellableRead::new(Cursor::new(vec![0; 16]), &token);
+
+            let mut buf = [0; 8];
+            read.read_exact(&mut buf).await.unwrap();
+
+            token.cancel();
+            let err = read.read_exact(&mut buf).await.unwrap_err();
+            assert_eq!(Error::from(err), Error::Cancelled);
+        })
+    }
+}
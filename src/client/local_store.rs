@@ -0,0 +1,293 @@
+//! Persistence of the metadata trusted by a [`Client`](super::Client) across restarts.
+
+use futures_util::future::{BoxFuture, FutureExt};
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::interchange::DataInterchange;
+use crate::metadata::{
+    Metadata, MetadataPath, RootMetadata, SignedMetadata, SnapshotMetadata, TargetsMetadata,
+    TimestampMetadata,
+};
+use crate::tuf::Tuf;
+use crate::util;
+use crate::Result;
+
+/// Durable storage for the metadata trusted by a `Client`.
+///
+/// When a `Client` is configured with a `LocalStore`, it saves the signed metadata it trusts after
+/// every [`Client::update`](super::Client::update) that changes it, along with the chain of root
+/// metadata it walked to get there. When the client is created again, the stored metadata is
+/// verified like an update would, starting from the root the client is created with: the stored
+/// root chain is walked, then the timestamp, snapshot, targets and delegated targets metadata are
+/// checked against it. A restarted client therefore only downloads metadata that changed while it
+/// was stopped, and refuses any metadata older than what it trusted before the restart.
+///
+/// If the stored metadata fails verification, for example because it expired while the client was
+/// stopped, it is ignored and the client starts from the root it was created with. Use a
+/// [`VersionStore`](super::VersionStore) to detect rollbacks across restarts in that case too.
+pub trait LocalStore: Send + Sync {
+    /// Load the stored metadata, or `None` if no metadata has been stored yet.
+    fn load(&self) -> BoxFuture<'_, Result<Option<Vec<u8>>>>;
+
+    /// Durably store `state`, replacing any previously stored metadata.
+    fn store<'a>(&'a self, state: &'a [u8]) -> BoxFuture<'a, Result<()>>;
+}
+
+/// A `LocalStore` that keeps the metadata in a file.
+#[derive(Debug)]
+pub struct FileLocalStore {
+    path: PathBuf,
+}
+
+impl FileLocalStore {
+    /// Create a new `FileLocalStore` backed by the file at `path`. The file does not need to
+    /// exist yet, but its parent directory does.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileLocalStore { path: path.into() }
+    }
+
+    /// An immutable reference to the path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl LocalStore for FileLocalStore {
+    fn load(&self) -> BoxFuture<'_, Result<Option<Vec<u8>>>> {
+        async move {
+            match fs::read(&self.path) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(Error::from_io(&err, &self.path)),
+            }
+        }
+        .boxed()
+    }
+
+    fn store<'a>(&'a self, state: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        async move { util::write_atomically(&self.path, state) }.boxed()
+    }
+}
+
+/// The optional `LocalStore` in a `Config`.
+#[derive(Clone, Default)]
+pub(super) struct SharedLocalStore(pub(super) Option<Arc<dyn LocalStore>>);
+
+impl fmt::Debug for SharedLocalStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "SharedLocalStore(Some(..))"),
+            None => write!(f, "SharedLocalStore(None)"),
+        }
+    }
+}
+
+impl PartialEq for SharedLocalStore {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The signed metadata behind the metadata a `Client` trusts, which is what it saves to its
+/// `LocalStore`. It is serialized with serde as CBOR, like the trust state of a `Tuf`.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(super) struct SignedState<D: DataInterchange> {
+    /// The root metadata walked since the root the client was created with, oldest first.
+    roots: Vec<SignedMetadata<D, RootMetadata>>,
+    timestamp: Option<SignedMetadata<D, TimestampMetadata>>,
+    snapshot: Option<SignedMetadata<D, SnapshotMetadata>>,
+    targets: Option<SignedMetadata<D, TargetsMetadata>>,
+    delegations: BTreeMap<MetadataPath, SignedMetadata<D, TargetsMetadata>>,
+}
+
+impl<D: DataInterchange> Default for SignedState<D> {
+    fn default() -> Self {
+        SignedState {
+            roots: Vec::new(),
+            timestamp: None,
+            snapshot: None,
+            targets: None,
+            delegations: BTreeMap::new(),
+        }
+    }
+}
+
+impl<D: DataInterchange> fmt::Debug for SignedState<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SignedState")
+            .field("roots", &self.roots.len())
+            .field("timestamp", &self.timestamp.is_some())
+            .field("snapshot", &self.snapshot.is_some())
+            .field("targets", &self.targets.is_some())
+            .field("delegations", &self.delegations.keys())
+            .finish()
+    }
+}
+
+impl<D: DataInterchange> SignedState<D> {
+    pub(super) fn push_root(&mut self, root: SignedMetadata<D, RootMetadata>) {
+        self.roots.push(root);
+    }
+
+    pub(super) fn set_timestamp(&mut self, timestamp: SignedMetadata<D, TimestampMetadata>) {
+        self.timestamp = Some(timestamp);
+    }
+
+    pub(super) fn set_snapshot(&mut self, snapshot: SignedMetadata<D, SnapshotMetadata>) {
+        self.snapshot = Some(snapshot);
+    }
+
+    pub(super) fn set_targets(&mut self, targets: SignedMetadata<D, TargetsMetadata>) {
+        self.targets = Some(targets);
+    }
+
+    pub(super) fn insert_delegation(
+        &mut self,
+        role: MetadataPath,
+        delegation: SignedMetadata<D, TargetsMetadata>,
+    ) {
+        let _ = self.delegations.insert(role, delegation);
+    }
+
+    /// Drop the metadata that `tuf` no longer trusts, such as the timestamp after a root rotation,
+    /// or delegations that the latest snapshot lists at a newer version.
+    pub(super) fn retain_trusted(&mut self, tuf: &Tuf<D>) {
+        self.timestamp = self
+            .timestamp
+            .take()
+            .filter(|t| is_trusted(t, tuf.timestamp()));
+        self.snapshot = self
+            .snapshot
+            .take()
+            .filter(|s| is_trusted(s, tuf.snapshot()));
+        self.targets = self.targets.take().filter(|t| is_trusted(t, tuf.targets()));
+        self.delegations
+            .retain(|role, d| is_trusted(d, tuf.delegations().get(role).map(|d| &**d)));
+    }
+
+    pub(super) fn encode(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(self, &mut buf)
+            .map_err(|e| Error::Encoding(format!("Stored metadata: {}", e)))?;
+        Ok(buf)
+    }
+
+    pub(super) fn decode(bytes: &[u8]) -> Result<Self> {
+        ciborium::from_reader(bytes).map_err(|e| Error::Encoding(format!("Stored metadata: {}", e)))
+    }
+
+    /// Verify this metadata starting from `tuf`, the way `Client::update` would have when it
+    /// fetched it, and return `tuf` updated with it along with the part of it that was used.
+    ///
+    /// Stored roots that are not newer than the root of `tuf` are skipped. Delegated targets
+    /// metadata that cannot be verified, such as a role delegated to by more than one role, is
+    /// dropped, since it can be fetched again when it is needed.
+    pub(super) fn verify(mut self, mut tuf: Tuf<D>) -> Result<(Tuf<D>, Self)> {
+        let mut verified = SignedState::default();
+
+        for root in self.roots {
+            let version = root.parse_version_untrusted()?;
+            if version <= tuf.root().version() {
+                continue;
+            }
+            if version != tuf.root().version() + 1 {
+                return Err(Error::VerificationFailure(format!(
+                    "The stored root metadata skips from version {} to {}",
+                    tuf.root().version(),
+                    version
+                )));
+            }
+            let _ = tuf.update_root(root.clone())?;
+            verified.push_root(root);
+        }
+
+        if let Some(timestamp) = self.timestamp {
+            let _ = tuf.update_timestamp(timestamp.clone())?;
+            verified.set_timestamp(timestamp);
+        }
+        if let Some(snapshot) = self.snapshot {
+            let _ = tuf.update_snapshot(snapshot.clone())?;
+            verified.set_snapshot(snapshot);
+        }
+        if let Some(targets) = self.targets {
+            let _ = tuf.update_targets(targets.clone())?;
+            verified.set_targets(targets);
+        }
+
+        // Walk the delegations from the top-level targets metadata, so that every role is verified
+        // after the role that delegates to it.
+        let children = |targets: Option<&TargetsMetadata>| {
+            targets
+                .and_then(|t| t.delegations())
+                .map(|d| {
+                    d.roles()
+                        .iter()
+                        .map(|r| r.role().clone())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+        let mut queue = VecDeque::from(children(tuf.targets()));
+        while let Some(role) = queue.pop_front() {
+            let delegation = match self.delegations.remove(&role) {
+                Some(delegation) => delegation,
+                None => continue,
+            };
+            match tuf.update_delegation(&role, delegation.clone()) {
+                Ok(_) => {
+                    queue.extend(children(tuf.delegations().get(&role).map(|d| &**d)));
+                    verified.insert_delegation(role, delegation);
+                }
+                Err(e) => warn!("Ignoring stored metadata for {:?}: {}", role, e),
+            }
+        }
+
+        Ok((tuf, verified))
+    }
+}
+
+/// Whether `signed` is at the version of the `trusted` metadata.
+fn is_trusted<D, M>(signed: &SignedMetadata<D, M>, trusted: Option<&M>) -> bool
+where
+    D: DataInterchange,
+    M: Metadata,
+{
+    trusted.is_some_and(|trusted| signed.parse_version_untrusted().ok() == Some(trusted.version()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_executor::block_on;
+
+    #[test]
+    fn file_local_store_round_trip() {
+        block_on(async {
+            let temp_dir = tempfile::Builder::new()
+                .prefix("rust-tuf")
+                .tempdir()
+                .unwrap();
+            let store = FileLocalStore::new(temp_dir.path().join("trust-state"));
+
+            assert_eq!(store.load().await, Ok(None));
+
+            store.store(b"first").await.unwrap();
+            assert_eq!(store.load().await, Ok(Some(b"first".to_vec())));
+
+            store.store(b"second").await.unwrap();
+            assert_eq!(store.load().await, Ok(Some(b"second".to_vec())));
+        })
+    }
+}
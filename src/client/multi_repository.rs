@@ -0,0 +1,770 @@
+//! Trusting targets only when several independent repositories agree on them, as described by
+//! [TAP 4](https://github.com/theupdateframework/taps/blob/master/tap4.md).
+
+use futures_io::AsyncWrite;
+use futures_util::io::copy;
+use log::warn;
+use serde_derive::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::client::{Client, PathTranslator, UpdateResult};
+use crate::error::Error;
+use crate::interchange::DataInterchange;
+use crate::metadata::{TargetDescription, TargetPath};
+use crate::repository::{RepositoryProvider, RepositoryStorage};
+use crate::Result;
+
+/// Which repositories must agree on which targets, as described by the map file of TAP 4.
+///
+/// A map file names each repository, along with the URLs of its mirrors, and lists mappings from
+/// target paths to the repositories trusted for them. Mappings are searched in order, and the
+/// first one that matches a target and whose repositories agree on it is used.
+///
+/// ```
+/// # use tuf::client::MapFile;
+/// let map_file = MapFile::from_json(br#"{
+///     "repositories": {
+///         "director": ["https://director.example.com/"],
+///         "vendor": ["https://vendor.example.com/"]
+///     },
+///     "mapping": [
+///         {
+///             "paths": ["firmware/*"],
+///             "repositories": ["director", "vendor"],
+///             "threshold": 2,
+///             "terminating": true
+///         },
+///         {
+///             "paths": ["*"],
+///             "repositories": ["vendor"]
+///         }
+///     ]
+/// }"#).unwrap();
+///
+/// assert_eq!(map_file.mapping().len(), 2);
+/// assert_eq!(map_file.mapping()[0].threshold(), 2);
+/// assert!(!map_file.mapping()[1].terminating());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapFile {
+    repositories: BTreeMap<String, Vec<String>>,
+    mapping: Vec<Mapping>,
+}
+
+impl MapFile {
+    /// Create a new `MapFile`. `repositories` maps the name of each repository to the URLs of its
+    /// mirrors. Every repository named by a mapping must be listed in `repositories`.
+    pub fn new(repositories: BTreeMap<String, Vec<String>>, mapping: Vec<Mapping>) -> Result<Self> {
+        for m in &mapping {
+            for name in &m.repositories {
+                if !repositories.contains_key(name) {
+                    return Err(Error::IllegalArgument(format!(
+                        "Mapping refers to unknown repository {:?}",
+                        name
+                    )));
+                }
+            }
+        }
+
+        Ok(MapFile {
+            repositories,
+            mapping,
+        })
+    }
+
+    /// Parse a map file in the JSON format of TAP 4.
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        let shim: MapFileShim = serde_json::from_slice(bytes)?;
+        let mapping = shim
+            .mapping
+            .into_iter()
+            .map(|m| Mapping::new(m.paths, m.repositories, m.threshold, m.terminating))
+            .collect::<Result<Vec<_>>>()?;
+        MapFile::new(shim.repositories, mapping)
+    }
+
+    /// The URLs of the mirrors of each repository, by name.
+    pub fn repositories(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.repositories
+    }
+
+    /// The mappings, in the order they are searched.
+    pub fn mapping(&self) -> &[Mapping] {
+        &self.mapping
+    }
+
+    /// The mappings that apply to `target`, in the order they are searched.
+    pub fn mappings_for<'a>(&'a self, target: &'a TargetPath) -> impl Iterator<Item = &'a Mapping> {
+        self.mapping.iter().filter(move |m| m.matches(target))
+    }
+}
+
+/// Assigns target paths to the repositories that must agree on them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mapping {
+    paths: Vec<String>,
+    repositories: Vec<String>,
+    threshold: u32,
+    terminating: bool,
+}
+
+impl Mapping {
+    /// Create a new `Mapping`.
+    ///
+    /// `paths` are patterns of the target paths the mapping applies to, in which `*` matches any
+    /// run of characters, including `/`, and `?` matches any single character. At least
+    /// `threshold` of `repositories` must list identical descriptions of a target for it to be
+    /// trusted. If the mapping is `terminating` and the repositories do not agree on a target
+    /// that matches it, later mappings are not searched.
+    pub fn new(
+        paths: Vec<String>,
+        repositories: Vec<String>,
+        threshold: u32,
+        terminating: bool,
+    ) -> Result<Self> {
+        if paths.is_empty() {
+            return Err(Error::IllegalArgument(
+                "Mapping must have at least one path".into(),
+            ));
+        }
+
+        if repositories.iter().collect::<HashSet<_>>().len() != repositories.len() {
+            return Err(Error::IllegalArgument(
+                "Mapping cannot list a repository more than once".into(),
+            ));
+        }
+
+        if threshold < 1 || threshold as usize > repositories.len() {
+            return Err(Error::IllegalArgument(format!(
+                "Mapping threshold {} must be between 1 and the number of repositories ({})",
+                threshold,
+                repositories.len()
+            )));
+        }
+
+        Ok(Mapping {
+            paths,
+            repositories,
+            threshold,
+            terminating,
+        })
+    }
+
+    /// Create a new terminating `Mapping` that routes targets matching `pattern` to the single
+    /// repository called `repository`.
+    ///
+    /// A map file of routes sends each target to exactly one repository, and the first route that
+    /// matches a target decides where it comes from:
+    ///
+    /// ```
+    /// # use maplit::btreemap;
+    /// # use tuf::client::{MapFile, Mapping};
+    /// # use tuf::metadata::TargetPath;
+    /// let map_file = MapFile::new(
+    ///     btreemap! {
+    ///         "debug".into() => vec!["https://debug.example.com/".into()],
+    ///         "release".into() => vec!["https://release.example.com/".into()],
+    ///     },
+    ///     vec![Mapping::route("debug/*", "debug"), Mapping::route("*", "release")],
+    /// )
+    /// .unwrap();
+    ///
+    /// let target = TargetPath::new("debug/symbols.tar".into()).unwrap();
+    /// let route = map_file.mappings_for(&target).next().unwrap();
+    /// assert_eq!(route.repositories(), ["debug"]);
+    /// ```
+    pub fn route<P, R>(pattern: P, repository: R) -> Self
+    where
+        P: Into<String>,
+        R: Into<String>,
+    {
+        Mapping {
+            paths: vec![pattern.into()],
+            repositories: vec![repository.into()],
+            threshold: 1,
+            terminating: true,
+        }
+    }
+
+    /// The patterns of the target paths this mapping applies to.
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /// The names of the repositories trusted for targets that match this mapping.
+    pub fn repositories(&self) -> &[String] {
+        &self.repositories
+    }
+
+    /// How many of the repositories must agree on a target.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Whether later mappings are searched when the repositories do not agree on a target.
+    pub fn terminating(&self) -> bool {
+        self.terminating
+    }
+
+    /// Whether this mapping applies to `target`.
+    pub fn matches(&self, target: &TargetPath) -> bool {
+        self.paths
+            .iter()
+            .any(|pattern| glob_matches(pattern, target.value()))
+    }
+}
+
+#[derive(Deserialize)]
+struct MapFileShim {
+    repositories: BTreeMap<String, Vec<String>>,
+    mapping: Vec<MappingShim>,
+}
+
+#[derive(Deserialize)]
+struct MappingShim {
+    paths: Vec<String>,
+    repositories: Vec<String>,
+    #[serde(default = "default_threshold")]
+    threshold: u32,
+    #[serde(default)]
+    terminating: bool,
+}
+
+fn default_threshold() -> u32 {
+    1
+}
+
+/// Whether `path` matches `pattern`, where `*` matches any run of characters and `?` matches any
+/// single character.
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let path = path.chars().collect::<Vec<_>>();
+
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the last `*` if the rest of the pattern fails to match.
+    let mut backtrack = None;
+    while s < path.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, s));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == path[s] => {
+                p += 1;
+                s += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    s = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether two repositories describe the same target.
+fn same_target(a: &TargetDescription, b: &TargetDescription) -> bool {
+    a.length() == b.length() && a.hashes() == b.hashes()
+}
+
+/// A client that trusts a target only when a threshold of independent repositories agree on it,
+/// following a [`MapFile`].
+///
+/// Each repository is accessed through its own [`Client`], which verifies the repository's
+/// metadata on its own. A target is trusted once enough of the repositories assigned to it list
+/// the same length and hashes for it.
+#[derive(Debug)]
+pub struct MultiRepositoryClient<D, L, R, T>
+where
+    D: DataInterchange + Sync,
+    L: RepositoryProvider<D> + RepositoryStorage<D>,
+    R: RepositoryProvider<D>,
+    T: PathTranslator,
+{
+    map_file: MapFile,
+    clients: BTreeMap<String, Client<D, L, R, T>>,
+}
+
+impl<D, L, R, T> MultiRepositoryClient<D, L, R, T>
+where
+    D: DataInterchange + Sync,
+    L: RepositoryProvider<D> + RepositoryStorage<D>,
+    R: RepositoryProvider<D>,
+    T: PathTranslator,
+{
+    /// Create a new `MultiRepositoryClient` from a `map_file` and a client for each of the
+    /// repositories it names.
+    pub fn new<I>(map_file: MapFile, clients: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, Client<D, L, R, T>)>,
+    {
+        let clients = clients.into_iter().collect::<BTreeMap<_, _>>();
+
+        if let Some(name) = clients
+            .keys()
+            .find(|name| !map_file.repositories.contains_key(*name))
+        {
+            return Err(Error::IllegalArgument(format!(
+                "Repository {:?} is not in the map file",
+                name
+            )));
+        }
+
+        if let Some(name) = map_file
+            .repositories
+            .keys()
+            .find(|name| !clients.contains_key(*name))
+        {
+            return Err(Error::IllegalArgument(format!(
+                "No client for repository {:?}",
+                name
+            )));
+        }
+
+        Ok(MultiRepositoryClient { map_file, clients })
+    }
+
+    /// An immutable reference to the map file.
+    pub fn map_file(&self) -> &MapFile {
+        &self.map_file
+    }
+
+    /// An immutable reference to the client of the repository called `name`.
+    pub fn client(&self, name: &str) -> Option<&Client<D, L, R, T>> {
+        self.clients.get(name)
+    }
+
+    /// The repositories, along with their clients, that are asked about `target` first. These are
+    /// the repositories of the first mapping that applies to `target`, so for a map file of
+    /// [`Mapping::route`]s this resolves the one repository, and the root metadata it is trusted
+    /// with, that `target` is downloaded from. Returns an empty iterator if no mapping applies.
+    pub fn route<'a>(
+        &'a self,
+        target: &'a TargetPath,
+    ) -> impl Iterator<Item = (&'a str, &'a Client<D, L, R, T>)> {
+        self.map_file
+            .mappings_for(target)
+            .next()
+            .into_iter()
+            .flat_map(|m| m.repositories.iter())
+            .filter_map(move |name| self.clients.get(name).map(|client| (name.as_str(), client)))
+    }
+
+    /// Update the metadata of every repository, like [`Client::update`], in the order of their
+    /// names. Returns the result of each update by repository name, or the first error
+    /// encountered, after which the remaining repositories are not updated.
+    pub async fn update(&mut self) -> Result<BTreeMap<String, UpdateResult>> {
+        let mut results = BTreeMap::new();
+        for (name, client) in self.clients.iter_mut() {
+            let _ = results.insert(name.clone(), client.update().await?);
+        }
+        Ok(results)
+    }
+
+    /// Look up the description of `target` that the repositories assigned to it agree on.
+    ///
+    /// Returns `Error::TargetUnavailable` if no mapping that matches `target` has a threshold of
+    /// repositories that agree on it.
+    pub async fn fetch_target_description<'a>(
+        &'a mut self,
+        target: &'a TargetPath,
+    ) -> Result<TargetDescription> {
+        let (_, description) = self.find_consensus(target).await?;
+        Ok(description)
+    }
+
+    /// Fetch a target the repositories assigned to it agree on, and write it to the provided
+    /// writer. The target is downloaded from the first repository in its mapping that agreed on
+    /// it. Returns the description the repositories agreed on.
+    ///
+    /// It is **critical** that none of the bytes written to the `write` are used until this future
+    /// returns `Ok`, as the hash of the target is not verified until all bytes are read from the
+    /// repository.
+    pub async fn fetch_target_to_writer<'a, W>(
+        &'a mut self,
+        target: &'a TargetPath,
+        mut write: W,
+    ) -> Result<TargetDescription>
+    where
+        W: AsyncWrite + Send + Unpin,
+    {
+        let (name, description) = self.find_consensus(target).await?;
+        let client = self
+            .clients
+            .get(&name)
+            .ok_or_else(|| Error::Programming(format!("No client for repository {:?}", name)))?;
+
+        let read = client
+            .target_stream_with_description(target, &description)
+            .await?;
+        copy(read, &mut write).await?;
+
+        if client.config.target_verifiers.0.is_empty() {
+            client.audit_target_verified(target);
+        }
+        Ok(description)
+    }
+
+    /// Search the mappings in order for the first one that matches `target` and whose
+    /// repositories agree on it. Returns the name of the first repository that agreed, and the
+    /// description they agreed on.
+    async fn find_consensus(&mut self, target: &TargetPath) -> Result<(String, TargetDescription)> {
+        let MultiRepositoryClient { map_file, clients } = self;
+
+        for mapping in map_file.mappings_for(target) {
+            // Each distinct description, and the repositories that listed it.
+            let mut candidates: Vec<(TargetDescription, Vec<&str>)> = Vec::new();
+
+            for name in &mapping.repositories {
+                let client = clients.get_mut(name).ok_or_else(|| {
+                    Error::Programming(format!("No client for repository {:?}", name))
+                })?;
+
+                let description = match client.fetch_target_description(target).await {
+                    Ok(d) => d,
+                    Err(e) => {
+                        warn!(
+                            "Repository {:?} failed to describe target {:?}: {}",
+                            name,
+                            target.value(),
+                            e
+                        );
+                        continue;
+                    }
+                };
+
+                let agreed = match candidates
+                    .iter_mut()
+                    .find(|(d, _)| same_target(d, &description))
+                {
+                    Some((_, names)) => {
+                        names.push(name);
+                        names
+                    }
+                    None => {
+                        candidates.push((description, vec![name]));
+                        &candidates.last().unwrap().1
+                    }
+                };
+
+                if agreed.len() >= mapping.threshold as usize {
+                    let first = agreed[0].to_string();
+                    let (description, _) = candidates
+                        .into_iter()
+                        .find(|(_, names)| names[0] == first)
+                        .unwrap();
+                    return Ok((first, description));
+                }
+            }
+
+            warn!(
+                "Fewer than {} of the repositories {:?} agreed on target {:?}",
+                mapping.threshold,
+                mapping.repositories,
+                target.value()
+            );
+            if mapping.terminating {
+                break;
+            }
+        }
+
+        Err(Error::TargetUnavailable)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{Config, DefaultTranslator};
+    use crate::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
+    use crate::interchange::Json;
+    use crate::metadata::{
+        MetadataPath, Role, RootMetadataBuilder, SnapshotMetadataBuilder, TargetsMetadataBuilder,
+        TimestampMetadataBuilder, VirtualTargetPath,
+    };
+    use crate::repository::{publish_metadata, EphemeralRepository, Repository};
+    use futures_executor::block_on;
+    use maplit::btreemap;
+    use matches::assert_matches;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+
+    type TestClient =
+        Client<Json, EphemeralRepository<Json>, EphemeralRepository<Json>, DefaultTranslator>;
+
+    /// A client of a new repository that holds each `data` at its `path`.
+    async fn client_with_targets(targets: &[(&TargetPath, &[u8])]) -> TestClient {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let repo = EphemeralRepository::<Json>::new();
+
+        let root = RootMetadataBuilder::new()
+            .root_key(key.public().clone())
+            .snapshot_key(key.public().clone())
+            .targets_key(key.public().clone())
+            .timestamp_key(key.public().clone())
+            .signed::<Json>(&key)
+            .unwrap();
+        let mut builder = TargetsMetadataBuilder::new();
+        for (path, data) in targets {
+            builder = builder
+                .insert_target_from_reader(
+                    VirtualTargetPath::new(path.value().into()).unwrap(),
+                    *data,
+                    &[HashAlgorithm::Sha256],
+                )
+                .unwrap();
+        }
+        let targets_metadata = builder.signed::<Json>(&key).unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets_metadata, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&key)
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&key)
+                .unwrap();
+
+        let root_path = MetadataPath::from_role(&Role::Root);
+        publish_metadata(&repo, &root_path, &root, false)
+            .await
+            .unwrap();
+        let targets_path = MetadataPath::from_role(&Role::Targets);
+        publish_metadata(&repo, &targets_path, &targets_metadata, false)
+            .await
+            .unwrap();
+        let snapshot_path = MetadataPath::from_role(&Role::Snapshot);
+        publish_metadata(&repo, &snapshot_path, &snapshot, false)
+            .await
+            .unwrap();
+        let timestamp_path = MetadataPath::from_role(&Role::Timestamp);
+        publish_metadata(&repo, &timestamp_path, &timestamp, false)
+            .await
+            .unwrap();
+        for (path, data) in targets {
+            Repository::<_, Json>::new(&repo)
+                .store_target(*data, path)
+                .await
+                .unwrap();
+        }
+
+        let mut client =
+            Client::with_trusted_root(Config::default(), root, EphemeralRepository::new(), repo)
+                .await
+                .unwrap();
+        let _ = client.update().await.unwrap();
+        client
+    }
+
+    fn map_file(mapping: Vec<Mapping>) -> MapFile {
+        MapFile::new(
+            btreemap! {
+                "director".into() => vec![],
+                "vendor".into() => vec![],
+            },
+            mapping,
+        )
+        .unwrap()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_matches("*", "firmware/image.bin"));
+        assert!(glob_matches("firmware/*", "firmware/image.bin"));
+        assert!(glob_matches("firmware/*.bin", "firmware/a/image.bin"));
+        assert!(glob_matches("image-?.bin", "image-1.bin"));
+        assert!(glob_matches("*a*b", "xaybzab"));
+        assert!(!glob_matches("firmware/*", "config/firmware/image.bin"));
+        assert!(!glob_matches("image-?.bin", "image-10.bin"));
+        assert!(!glob_matches("*.bin", "image.bin.sig"));
+    }
+
+    #[test]
+    fn map_file_rejects_invalid_mappings() {
+        assert_matches!(
+            Mapping::new(vec![], names(&["vendor"]), 1, false),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            Mapping::new(names(&["*"]), names(&["vendor"]), 2, false),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            Mapping::new(names(&["*"]), names(&["vendor", "vendor"]), 1, false),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            MapFile::from_json(
+                br#"{
+                    "repositories": { "vendor": [] },
+                    "mapping": [ { "paths": ["*"], "repositories": ["director"] } ]
+                }"#
+            ),
+            Err(Error::IllegalArgument(_))
+        );
+    }
+
+    #[test]
+    fn new_requires_a_client_for_every_repository() {
+        block_on(async {
+            let map_file = map_file(vec![Mapping::new(
+                names(&["*"]),
+                names(&["director", "vendor"]),
+                2,
+                true,
+            )
+            .unwrap()]);
+            let vendor = client_with_targets(&[]).await;
+
+            assert_matches!(
+                MultiRepositoryClient::new(map_file, vec![("vendor".into(), vendor)]),
+                Err(Error::IllegalArgument(_))
+            );
+        })
+    }
+
+    #[test]
+    fn target_trusted_when_threshold_agrees() {
+        block_on(async {
+            let path = TargetPath::new("firmware/image.bin".into()).unwrap();
+            let director = client_with_targets(&[(&path, b"image")]).await;
+            let vendor = client_with_targets(&[(&path, b"image")]).await;
+
+            let map_file = map_file(vec![Mapping::new(
+                names(&["firmware/*"]),
+                names(&["director", "vendor"]),
+                2,
+                true,
+            )
+            .unwrap()]);
+            let mut client = MultiRepositoryClient::new(
+                map_file,
+                vec![("director".into(), director), ("vendor".into(), vendor)],
+            )
+            .unwrap();
+
+            assert_eq!(
+                client
+                    .fetch_target_description(&path)
+                    .await
+                    .unwrap()
+                    .length(),
+                5
+            );
+
+            let mut buf = Vec::new();
+            client
+                .fetch_target_to_writer(&path, &mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, b"image");
+        })
+    }
+
+    #[test]
+    fn routes_by_path() {
+        block_on(async {
+            let debug_path = TargetPath::new("debug/symbols.tar".into()).unwrap();
+            let release_path = TargetPath::new("image.bin".into()).unwrap();
+            let director = client_with_targets(&[(&debug_path, b"symbols")]).await;
+            let vendor = client_with_targets(&[(&release_path, b"image")]).await;
+
+            let mut client = MultiRepositoryClient::new(
+                map_file(vec![
+                    Mapping::route("debug/*", "director"),
+                    Mapping::route("*", "vendor"),
+                ]),
+                vec![("director".into(), director), ("vendor".into(), vendor)],
+            )
+            .unwrap();
+
+            let route = client.route(&debug_path).collect::<Vec<_>>();
+            assert_eq!(route.len(), 1);
+            assert_eq!(route[0].0, "director");
+            assert_eq!(
+                route[0].1.tuf().root(),
+                client.client("director").unwrap().tuf().root()
+            );
+            assert_eq!(
+                client
+                    .route(&release_path)
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+                vec!["vendor"]
+            );
+
+            let mut buf = Vec::new();
+            client
+                .fetch_target_to_writer(&debug_path, &mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, b"symbols");
+
+            let mut buf = Vec::new();
+            client
+                .fetch_target_to_writer(&release_path, &mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, b"image");
+
+            // Routes are terminating, so the release repository is never asked for debug targets.
+            let missing = TargetPath::new("debug/missing.tar".into()).unwrap();
+            assert_matches!(
+                client.fetch_target_description(&missing).await,
+                Err(Error::TargetUnavailable)
+            );
+        })
+    }
+
+    #[test]
+    fn terminating_mapping_without_consensus_stops_search() {
+        block_on(async {
+            let path = TargetPath::new("firmware/image.bin".into()).unwrap();
+            let director = client_with_targets(&[(&path, b"image")]).await;
+            let vendor = client_with_targets(&[(&path, b"other image")]).await;
+
+            let mapping = |terminating| {
+                vec![
+                    Mapping::new(
+                        names(&["firmware/*"]),
+                        names(&["director", "vendor"]),
+                        2,
+                        terminating,
+                    )
+                    .unwrap(),
+                    Mapping::new(names(&["*"]), names(&["vendor"]), 1, false).unwrap(),
+                ]
+            };
+
+            let mut client = MultiRepositoryClient::new(
+                map_file(mapping(true)),
+                vec![("director".into(), director), ("vendor".into(), vendor)],
+            )
+            .unwrap();
+            assert_matches!(
+                client.fetch_target_description(&path).await,
+                Err(Error::TargetUnavailable)
+            );
+
+            // Without terminating, the target falls through to the vendor repository alone.
+            let MultiRepositoryClient { clients, .. } = client;
+            let mut client = MultiRepositoryClient::new(map_file(mapping(false)), clients).unwrap();
+            let mut buf = Vec::new();
+            client
+                .fetch_target_to_writer(&path, &mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, b"other image");
+        })
+    }
+}
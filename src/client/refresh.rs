@@ -0,0 +1,494 @@
+//! Background refreshing of the metadata trusted by a [`Client`].
+//!
+//! This module requires the `tokio` feature. An [`AutoRefresh`] task owns a [`Client`] and
+//! periodically runs [`Client::update`], publishing the outcome of each run over a
+//! [`watch`](tokio::sync::watch) channel so applications can react when new targets become
+//! available instead of polling the repository themselves.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use tuf::client::{AutoRefresh, Client, Config, RefreshConfig};
+//! # use tuf::interchange::Json;
+//! # use tuf::metadata::{RootMetadata, SignedMetadata};
+//! # use tuf::repository::EphemeralRepository;
+//! # async fn run(root: SignedMetadata<Json, RootMetadata>) -> tuf::Result<()> {
+//! let client = Client::with_trusted_root(
+//!     Config::default(),
+//!     root,
+//!     EphemeralRepository::<Json>::new(),
+//!     EphemeralRepository::<Json>::new(),
+//! )
+//! .await?;
+//!
+//! let refresh = AutoRefresh::spawn(client, RefreshConfig::default());
+//! let mut status = refresh.subscribe();
+//!
+//! while status.changed().await.is_ok() {
+//!     if status.borrow().is_updated() {
+//!         let client = refresh.client().lock().await;
+//!         // Fetch any newly available targets with `client`.
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use futures_util::future;
+use futures_util::pin_mut;
+use log::warn;
+use std::cmp;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex, Notify};
+use tokio::task::JoinHandle;
+
+use crate::client::{Client, PathTranslator};
+use crate::error::Error;
+use crate::interchange::DataInterchange;
+use crate::repository::{RepositoryProvider, RepositoryStorage};
+use crate::Result;
+
+/// The outcome of the most recent background refresh.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RefreshStatus {
+    /// No refresh has completed yet.
+    Pending,
+    /// The refresh that completed at the given time found new metadata.
+    Updated(DateTime<Utc>),
+    /// The refresh that completed at the given time found that the metadata was unchanged.
+    Unchanged(DateTime<Utc>),
+    /// The refresh that completed at the given time failed with the given error.
+    Failed(DateTime<Utc>, String),
+}
+
+impl RefreshStatus {
+    /// Returns `true` if the most recent refresh found new metadata.
+    pub fn is_updated(&self) -> bool {
+        matches!(self, RefreshStatus::Updated(_))
+    }
+}
+
+/// Configuration for an [`AutoRefresh`] task.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tuf::client::RefreshConfig;
+/// let config = RefreshConfig::default();
+/// assert_eq!(config.interval(), Duration::from_secs(60 * 60));
+/// assert_eq!(config.retry_interval(), Duration::from_secs(5 * 60));
+/// assert_eq!(config.min_interval(), Duration::from_secs(60));
+/// assert_eq!(config.expiration_margin(), Duration::from_secs(5 * 60));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RefreshConfig {
+    interval: Duration,
+    retry_interval: Duration,
+    min_interval: Duration,
+    expiration_margin: Duration,
+}
+
+impl RefreshConfig {
+    /// Initialize a `RefreshConfigBuilder` with the default values.
+    pub fn build() -> RefreshConfigBuilder {
+        RefreshConfigBuilder::default()
+    }
+
+    /// The time to wait between successful refreshes.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// The time to wait before retrying a failed refresh.
+    pub fn retry_interval(&self) -> Duration {
+        self.retry_interval
+    }
+
+    /// The shortest time to wait between any two refreshes.
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    /// How long before the trusted metadata expires to schedule a refresh.
+    pub fn expiration_margin(&self) -> Duration {
+        self.expiration_margin
+    }
+
+    /// Calculate how long to wait before the next refresh, given the `delay` that would normally
+    /// be used and when the trusted metadata next expires.
+    fn next_delay(
+        &self,
+        delay: Duration,
+        next_expiration: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Duration {
+        let until_expiration = (next_expiration - now)
+            .to_std()
+            .unwrap_or_default()
+            .checked_sub(self.expiration_margin)
+            .unwrap_or_default();
+
+        cmp::max(cmp::min(delay, until_expiration), self.min_interval)
+    }
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        RefreshConfig {
+            interval: Duration::from_secs(60 * 60),
+            retry_interval: Duration::from_secs(5 * 60),
+            min_interval: Duration::from_secs(60),
+            expiration_margin: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Helper for building and validating a `RefreshConfig`.
+#[derive(Debug, PartialEq)]
+pub struct RefreshConfigBuilder {
+    interval: Duration,
+    retry_interval: Duration,
+    min_interval: Duration,
+    expiration_margin: Duration,
+}
+
+impl RefreshConfigBuilder {
+    /// Validate this builder return a `RefreshConfig` if validation succeeds.
+    pub fn finish(self) -> Result<RefreshConfig> {
+        if self.min_interval == Duration::from_secs(0) {
+            return Err(Error::IllegalArgument(
+                "The minimum refresh interval must be greater than zero".into(),
+            ));
+        }
+
+        if self.min_interval > self.interval || self.min_interval > self.retry_interval {
+            return Err(Error::IllegalArgument(format!(
+                "The minimum refresh interval {:?} exceeds the refresh interval {:?} or the \
+                 retry interval {:?}",
+                self.min_interval, self.interval, self.retry_interval
+            )));
+        }
+
+        Ok(RefreshConfig {
+            interval: self.interval,
+            retry_interval: self.retry_interval,
+            min_interval: self.min_interval,
+            expiration_margin: self.expiration_margin,
+        })
+    }
+
+    /// Set the time to wait between successful refreshes.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the time to wait before retrying a failed refresh.
+    pub fn retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    /// Set the shortest time to wait between any two refreshes.
+    pub fn min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Set how long before the trusted metadata expires to schedule a refresh.
+    pub fn expiration_margin(mut self, expiration_margin: Duration) -> Self {
+        self.expiration_margin = expiration_margin;
+        self
+    }
+}
+
+impl Default for RefreshConfigBuilder {
+    fn default() -> Self {
+        let cfg = RefreshConfig::default();
+        RefreshConfigBuilder {
+            interval: cfg.interval,
+            retry_interval: cfg.retry_interval,
+            min_interval: cfg.min_interval,
+            expiration_margin: cfg.expiration_margin,
+        }
+    }
+}
+
+/// A background task that keeps the metadata of a [`Client`] up to date.
+///
+/// The task runs on the current tokio runtime. It refreshes immediately after being spawned, then
+/// again after [`RefreshConfig::interval`], or sooner if the trusted metadata is about to expire.
+/// The task is aborted when the `AutoRefresh` is dropped.
+pub struct AutoRefresh<D, L, R, T>
+where
+    D: DataInterchange + Sync,
+    L: RepositoryProvider<D> + RepositoryStorage<D>,
+    R: RepositoryProvider<D>,
+    T: PathTranslator,
+{
+    client: Arc<Mutex<Client<D, L, R, T>>>,
+    status: watch::Receiver<RefreshStatus>,
+    trigger: Arc<Notify>,
+    handle: JoinHandle<()>,
+}
+
+impl<D, L, R, T> AutoRefresh<D, L, R, T>
+where
+    D: DataInterchange + Send + Sync + 'static,
+    D::RawData: Send + Sync,
+    L: RepositoryProvider<D> + RepositoryStorage<D> + Send + Sync + 'static,
+    R: RepositoryProvider<D> + Send + Sync + 'static,
+    T: PathTranslator + Send + Sync + 'static,
+{
+    /// Spawn a task on the current tokio runtime that refreshes `client` according to `config`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn spawn(client: Client<D, L, R, T>, config: RefreshConfig) -> Self {
+        let client = Arc::new(Mutex::new(client));
+        let (sender, status) = watch::channel(RefreshStatus::Pending);
+        let trigger = Arc::new(Notify::new());
+
+        let handle = tokio::spawn(run(
+            Arc::clone(&client),
+            config,
+            sender,
+            Arc::clone(&trigger),
+        ));
+
+        AutoRefresh {
+            client,
+            status,
+            trigger,
+            handle,
+        }
+    }
+}
+
+impl<D, L, R, T> AutoRefresh<D, L, R, T>
+where
+    D: DataInterchange + Sync,
+    L: RepositoryProvider<D> + RepositoryStorage<D>,
+    R: RepositoryProvider<D>,
+    T: PathTranslator,
+{
+    /// The client being refreshed. The background task holds the lock while a refresh is running.
+    pub fn client(&self) -> &Arc<Mutex<Client<D, L, R, T>>> {
+        &self.client
+    }
+
+    /// Subscribe to the outcome of each refresh.
+    pub fn subscribe(&self) -> watch::Receiver<RefreshStatus> {
+        self.status.clone()
+    }
+
+    /// The outcome of the most recent refresh.
+    pub fn status(&self) -> RefreshStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Wake the background task so that it refreshes without waiting for the next scheduled run.
+    pub fn refresh_now(&self) {
+        self.trigger.notify_one();
+    }
+}
+
+impl<D, L, R, T> Drop for AutoRefresh<D, L, R, T>
+where
+    D: DataInterchange + Sync,
+    L: RepositoryProvider<D> + RepositoryStorage<D>,
+    R: RepositoryProvider<D>,
+    T: PathTranslator,
+{
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn run<D, L, R, T>(
+    client: Arc<Mutex<Client<D, L, R, T>>>,
+    config: RefreshConfig,
+    sender: watch::Sender<RefreshStatus>,
+    trigger: Arc<Notify>,
+) where
+    D: DataInterchange + Sync,
+    L: RepositoryProvider<D> + RepositoryStorage<D>,
+    R: RepositoryProvider<D>,
+    T: PathTranslator,
+{
+    loop {
+        let (result, next_expiration, now) = {
+            let mut client = client.lock().await;
+            let result = client.update().await;
+            (result, client.tuf().next_expiration(), client.tuf().now())
+        };
+
+        let delay = match result {
+            Ok(result) if result.updated() => {
+                sender.send_replace(RefreshStatus::Updated(now));
+                config.interval
+            }
+            Ok(_) => {
+                sender.send_replace(RefreshStatus::Unchanged(now));
+                config.interval
+            }
+            Err(err) => {
+                warn!("background refresh failed: {}", err);
+                sender.send_replace(RefreshStatus::Failed(now, err.to_string()));
+                config.retry_interval
+            }
+        };
+
+        let sleep = tokio::time::sleep(config.next_delay(delay, next_expiration, now));
+        let notified = trigger.notified();
+        pin_mut!(sleep, notified);
+        future::select(sleep, notified).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::Config;
+    use crate::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
+    use crate::interchange::Json;
+    use crate::metadata::{
+        MetadataPath, MetadataVersion, Role, RootMetadataBuilder, SnapshotMetadataBuilder,
+        TargetsMetadataBuilder, TimestampMetadataBuilder,
+    };
+    use crate::repository::{EphemeralRepository, Repository};
+    use chrono::Duration as ChronoDuration;
+    use matches::assert_matches;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+
+    #[test]
+    fn next_delay_uses_interval_when_expiration_is_distant() {
+        let config = RefreshConfig::default();
+        let now = Utc::now();
+
+        assert_eq!(
+            config.next_delay(config.interval(), now + ChronoDuration::days(1), now),
+            config.interval()
+        );
+    }
+
+    #[test]
+    fn next_delay_refreshes_before_expiration() {
+        let config = RefreshConfig::default();
+        let now = Utc::now();
+
+        assert_eq!(
+            config.next_delay(config.interval(), now + ChronoDuration::minutes(15), now),
+            Duration::from_secs(10 * 60)
+        );
+    }
+
+    #[test]
+    fn next_delay_respects_min_interval() {
+        let config = RefreshConfig::default();
+        let now = Utc::now();
+
+        assert_eq!(
+            config.next_delay(config.interval(), now - ChronoDuration::days(1), now),
+            config.min_interval()
+        );
+    }
+
+    #[test]
+    fn refresh_config_builder_rejects_bad_intervals() {
+        assert_matches!(
+            RefreshConfig::build()
+                .min_interval(Duration::from_secs(0))
+                .finish(),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            RefreshConfig::build()
+                .interval(Duration::from_secs(10))
+                .finish(),
+            Err(Error::IllegalArgument(_))
+        );
+    }
+
+    #[tokio::test]
+    async fn auto_refresh_publishes_status() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+
+        let root = RootMetadataBuilder::new()
+            .root_key(key.public().clone())
+            .snapshot_key(key.public().clone())
+            .targets_key(key.public().clone())
+            .timestamp_key(key.public().clone())
+            .signed::<Json>(&key)
+            .unwrap();
+        let targets = TargetsMetadataBuilder::new().signed::<Json>(&key).unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&key)
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&key)
+                .unwrap();
+
+        let repo = EphemeralRepository::<Json>::new();
+        {
+            let mut remote = Repository::new(&repo);
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Root),
+                    &MetadataVersion::None,
+                    &root.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Targets),
+                    &MetadataVersion::None,
+                    &targets.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Snapshot),
+                    &MetadataVersion::None,
+                    &snapshot.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
+            remote
+                .store_metadata(
+                    &MetadataPath::from_role(&Role::Timestamp),
+                    &MetadataVersion::None,
+                    &timestamp.to_raw().unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let client =
+            Client::with_trusted_root(Config::default(), root, EphemeralRepository::new(), repo)
+                .await
+                .unwrap();
+
+        let refresh = AutoRefresh::spawn(client, RefreshConfig::default());
+        let mut status = refresh.subscribe();
+
+        status.changed().await.unwrap();
+        assert_matches!(*status.borrow(), RefreshStatus::Updated(_));
+
+        refresh.refresh_now();
+        status.changed().await.unwrap();
+        assert_matches!(*status.borrow(), RefreshStatus::Unchanged(_));
+
+        assert!(refresh.client().lock().await.tuf().timestamp().is_some());
+    }
+}
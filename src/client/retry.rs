@@ -0,0 +1,619 @@
+//! Retrying remote repository fetches that fail with transient errors.
+
+use futures_io::AsyncRead;
+use futures_util::future::{self, BoxFuture, Either, FutureExt};
+use futures_util::{pin_mut, ready};
+use log::warn;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::util::thread_sleep;
+use crate::Result;
+
+type RetryIf = Arc<dyn Fn(&Error) -> bool + Send + Sync>;
+type Sleep = Arc<dyn Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// How a [`Client`](super::Client) retries fetches from the remote repository.
+///
+/// A fetch that fails with an error the policy considers retryable is attempted again after a
+/// delay that starts at [`initial_backoff`](RetryPolicy::initial_backoff) and is multiplied by
+/// [`multiplier`](RetryPolicy::multiplier) after every failure, up to
+/// [`max_backoff`](RetryPolicy::max_backoff). With jitter enabled, each delay is picked at random
+/// between half of and the full backoff, so many clients that failed at the same time don't all
+/// retry at the same time. By default, only [`Error::Opaque`] is retried, which is what I/O and
+/// HTTP transport errors are reported as, along with [`Error::MirrorsFailed`] if any of the
+/// mirrors failed that way.
+///
+/// Each attempt can also be given a [`timeout`](RetryPolicy::timeout), after which it is abandoned
+/// and fails with `Error::Opaque`, so it is retried like any other transient failure. The same
+/// timeout also applies to target downloads, which fail once it passes without any data arriving.
+///
+/// The default policy makes a single attempt without a timeout, so it never retries.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tuf::client::RetryPolicy;
+/// let policy = RetryPolicy::default();
+/// assert_eq!(policy.max_attempts(), 1);
+/// assert_eq!(policy.initial_backoff(), Duration::from_millis(100));
+/// assert_eq!(policy.max_backoff(), Duration::from_secs(10));
+/// assert_eq!(policy.multiplier(), 2);
+/// assert!(policy.jitter());
+/// assert_eq!(policy.timeout(), None);
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: u32,
+    jitter: bool,
+    timeout: Option<Duration>,
+    retry_if: RetryIf,
+    sleep: Sleep,
+}
+
+impl RetryPolicy {
+    /// Initialize a `RetryPolicyBuilder` with the default values.
+    pub fn build() -> RetryPolicyBuilder {
+        RetryPolicyBuilder::default()
+    }
+
+    /// The maximum number of times a fetch is attempted, including the first attempt.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The delay before the first retry.
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff
+    }
+
+    /// The longest delay between two attempts.
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff
+    }
+
+    /// The factor the delay grows by after every failed attempt.
+    pub fn multiplier(&self) -> u32 {
+        self.multiplier
+    }
+
+    /// Whether delays are randomized.
+    pub fn jitter(&self) -> bool {
+        self.jitter
+    }
+
+    /// How long a single attempt may take before it is abandoned, if at all.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Whether a fetch that failed with `err` is worth retrying.
+    pub fn is_retryable(&self, err: &Error) -> bool {
+        (self.retry_if)(err)
+    }
+
+    /// The delay after the given failed `attempt`, counting from 1 and before any jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let mut backoff = self.initial_backoff;
+        for _ in 1..attempt {
+            backoff = match backoff.checked_mul(self.multiplier) {
+                Some(backoff) if backoff < self.max_backoff => backoff,
+                _ => return self.max_backoff,
+            };
+        }
+        backoff.min(self.max_backoff)
+    }
+
+    /// Run `op` until it succeeds, fails with an error that isn't retryable, or has been attempted
+    /// `max_attempts` times.
+    pub(super) async fn retry<F, Fut, T>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match self.attempt(op()).await {
+                Err(err) if self.wait_to_retry(attempt, &err).await => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+
+    /// Run a single attempt, abandoning it if it outlasts the timeout.
+    async fn attempt<Fut, T>(&self, op: Fut) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return op.await,
+        };
+
+        pin_mut!(op);
+        match future::select(op, (self.sleep)(timeout)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Err(Error::Opaque(format!(
+                "Attempt timed out after {:?}",
+                timeout
+            ))),
+        }
+    }
+
+    /// Wrap `read` so that it fails with an `io::ErrorKind::TimedOut` error once a whole
+    /// [`timeout`](Self::timeout) passes without any data arriving, if there is a timeout.
+    pub(super) fn limit_idle<R>(&self, read: R) -> IdleTimeoutRead<R> {
+        IdleTimeoutRead {
+            inner: read,
+            timeout: self.timeout,
+            sleep: Arc::clone(&self.sleep),
+            timer: None,
+            progressed: false,
+        }
+    }
+
+    /// Wait before retrying after the given failed `attempt`, counting from 1, failed with `err`.
+    /// Returns `false` without waiting if the operation should not be retried.
+    pub(super) async fn wait_to_retry(&self, attempt: u32, err: &Error) -> bool {
+        // A cancelled operation is never retried, whatever the policy says.
+        if attempt >= self.max_attempts || *err == Error::Cancelled || !self.is_retryable(err) {
+            return false;
+        }
+
+        let backoff = self.backoff(attempt);
+        let delay = if self.jitter {
+            backoff / 2 + backoff.mul_f64(random_fraction() / 2.0)
+        } else {
+            backoff
+        };
+
+        warn!(
+            "Attempt {} of {} failed, retrying in {:?}: {}",
+            attempt, self.max_attempts, delay, err
+        );
+        (self.sleep)(delay).await;
+        true
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2,
+            jitter: true,
+            timeout: None,
+            retry_if: Arc::new(is_transient),
+            sleep: Arc::new(default_sleep),
+        }
+    }
+}
+
+/// An `AsyncRead` that fails once it has waited a whole timeout for data without receiving any.
+pub(crate) struct IdleTimeoutRead<R> {
+    inner: R,
+    timeout: Option<Duration>,
+    sleep: Sleep,
+    /// Runs while reads are waiting for data.
+    timer: Option<BoxFuture<'static, ()>>,
+    /// Whether any data arrived since the timer was started.
+    progressed: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for IdleTimeoutRead<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if let Poll::Ready(result) = Pin::new(&mut self.inner).poll_read(cx, buf) {
+            self.progressed = true;
+            return Poll::Ready(result);
+        }
+
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Poll::Pending,
+        };
+
+        // Rather than starting a new timer for every read, a timer that expires after data arrived
+        // is started again, so the read only fails after a whole timeout without any data.
+        loop {
+            if self.timer.is_none() {
+                self.timer = Some((self.sleep)(timeout));
+                self.progressed = false;
+            }
+            ready!(self.timer.as_mut().unwrap().poll_unpin(cx));
+            self.timer = None;
+
+            if !self.progressed {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("No data received for {:?}", timeout),
+                )));
+            }
+        }
+    }
+}
+
+/// Wait for `delay` on the current tokio runtime, if there is one, or else on a separate thread.
+fn default_sleep(delay: Duration) -> BoxFuture<'static, ()> {
+    #[cfg(feature = "tokio")]
+    {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Box::pin(tokio::time::sleep(delay));
+        }
+    }
+
+    thread_sleep(delay)
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl PartialEq for RetryPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_attempts == other.max_attempts
+            && self.initial_backoff == other.initial_backoff
+            && self.max_backoff == other.max_backoff
+            && self.multiplier == other.multiplier
+            && self.jitter == other.jitter
+            && self.timeout == other.timeout
+            && Arc::ptr_eq(&self.retry_if, &other.retry_if)
+            && Arc::ptr_eq(&self.sleep, &other.sleep)
+    }
+}
+
+/// Helper for building and validating a `RetryPolicy`.
+#[derive(Debug, Default, PartialEq)]
+pub struct RetryPolicyBuilder {
+    policy: RetryPolicy,
+}
+
+impl RetryPolicyBuilder {
+    /// Validate this builder return a `RetryPolicy` if validation succeeds.
+    pub fn finish(self) -> Result<RetryPolicy> {
+        if self.policy.max_attempts == 0 {
+            return Err(Error::IllegalArgument(
+                "max_attempts must be at least 1".into(),
+            ));
+        }
+
+        if self.policy.multiplier == 0 {
+            return Err(Error::IllegalArgument(
+                "The backoff multiplier must be at least 1".into(),
+            ));
+        }
+
+        if self.policy.initial_backoff > self.policy.max_backoff {
+            return Err(Error::IllegalArgument(format!(
+                "The initial backoff {:?} exceeds the maximum backoff {:?}",
+                self.policy.initial_backoff, self.policy.max_backoff
+            )));
+        }
+
+        if self.policy.timeout == Some(Duration::from_secs(0)) {
+            return Err(Error::IllegalArgument(
+                "The timeout must be greater than zero".into(),
+            ));
+        }
+
+        Ok(self.policy)
+    }
+
+    /// Set the maximum number of times a fetch is attempted, including the first attempt. Must be
+    /// at least 1. Defaults to 1, which disables retries.
+    pub fn max_attempts(mut self, max: u32) -> Self {
+        self.policy.max_attempts = max;
+        self
+    }
+
+    /// Set the delay before the first retry. Defaults to 100 milliseconds.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.policy.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the longest delay between two attempts. Defaults to 10 seconds.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.policy.max_backoff = backoff;
+        self
+    }
+
+    /// Set the factor the delay grows by after every failed attempt. Must be at least 1. Defaults
+    /// to 2.
+    pub fn multiplier(mut self, multiplier: u32) -> Self {
+        self.policy.multiplier = multiplier;
+        self
+    }
+
+    /// Set whether delays are randomized. Defaults to `true`.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.policy.jitter = jitter;
+        self
+    }
+
+    /// Set how long a single attempt may take before it is abandoned. An attempt covers fetching
+    /// and parsing a piece of metadata, or starting to download a target. Reading the target
+    /// itself may take longer, but fails once a whole timeout passes without any data arriving.
+    /// Must be greater than zero. Defaults to `None`, which never abandons an attempt.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.policy.timeout = timeout;
+        self
+    }
+
+    /// Set which errors are worth retrying a fetch for. Defaults to [`Error::Opaque`], and
+    /// [`Error::MirrorsFailed`] if any of the mirrors failed with `Error::Opaque`.
+    /// [`Error::Cancelled`] is never retried.
+    pub fn retry_if<F>(mut self, retry_if: F) -> Self
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        self.policy.retry_if = Arc::new(retry_if);
+        self
+    }
+
+    /// Set how to wait out a delay before retrying, and the [`timeout`](Self::timeout) of each
+    /// attempt.
+    ///
+    /// With the `tokio` feature, delays created on a tokio runtime use `tokio::time::sleep`, so
+    /// the runtime must have its timer enabled. Otherwise, every delay spawns an OS thread that
+    /// sleeps until it is over, which works with any executor but costs a thread per retry, and
+    /// per attempt when a timeout is set. Applications using another async runtime should pass its
+    /// timer here instead.
+    pub fn sleep<F>(mut self, sleep: F) -> Self
+    where
+        F: Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+    {
+        self.policy.sleep = Arc::new(sleep);
+        self
+    }
+}
+
+/// Whether `err` may go away if the fetch is tried again.
+fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::Opaque(_) => true,
+        Error::MirrorsFailed(failures) => failures.iter().any(|(_, err)| is_transient(err)),
+        _ => false,
+    }
+}
+
+/// A number between 0 and 1 that is random enough to spread out retries.
+fn random_fraction() -> f64 {
+    let value = RandomState::new().build_hasher().finish();
+    value as f64 / u64::MAX as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_executor::block_on;
+    use futures_util::future::FutureExt;
+    use matches::assert_matches;
+    use std::sync::Mutex;
+
+    /// A policy that records its delays instead of waiting them out.
+    fn recording_policy(max_attempts: u32) -> (RetryPolicy, Arc<Mutex<Vec<Duration>>>) {
+        let delays = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&delays);
+        let policy = RetryPolicy::build()
+            .max_attempts(max_attempts)
+            .initial_backoff(Duration::from_secs(1))
+            .max_backoff(Duration::from_secs(5))
+            .jitter(false)
+            .sleep(move |delay| {
+                recorded.lock().unwrap().push(delay);
+                async {}.boxed()
+            })
+            .finish()
+            .unwrap();
+        (policy, delays)
+    }
+
+    #[test]
+    fn backoff_grows_up_to_max() {
+        let (policy, _) = recording_policy(10);
+        let backoffs = (1..=5).map(|a| policy.backoff(a)).collect::<Vec<_>>();
+        assert_eq!(
+            backoffs,
+            [1, 2, 4, 5, 5]
+                .iter()
+                .map(|s| Duration::from_secs(*s))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn retries_transient_errors_until_success() {
+        block_on(async {
+            let (policy, delays) = recording_policy(5);
+            let mut calls = 0;
+            let result = policy
+                .retry(|| {
+                    calls += 1;
+                    let calls = calls;
+                    async move {
+                        if calls < 3 {
+                            Err(Error::Opaque("connection reset".into()))
+                        } else {
+                            Ok(calls)
+                        }
+                    }
+                })
+                .await;
+            assert_eq!(result, Ok(3));
+            assert_eq!(
+                *delays.lock().unwrap(),
+                vec![Duration::from_secs(1), Duration::from_secs(2)]
+            );
+        })
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        block_on(async {
+            let (policy, delays) = recording_policy(3);
+            let mut calls = 0;
+            let result: Result<()> = policy
+                .retry(|| {
+                    calls += 1;
+                    async { Err(Error::Opaque("connection reset".into())) }
+                })
+                .await;
+            assert_eq!(result, Err(Error::Opaque("connection reset".into())));
+            assert_eq!(calls, 3);
+            assert_eq!(delays.lock().unwrap().len(), 2);
+        })
+    }
+
+    #[test]
+    fn does_not_retry_permanent_errors() {
+        block_on(async {
+            let (policy, delays) = recording_policy(3);
+            let mut calls = 0;
+            let result: Result<()> = policy
+                .retry(|| {
+                    calls += 1;
+                    async { Err(Error::NotFound) }
+                })
+                .await;
+            assert_eq!(result, Err(Error::NotFound));
+            assert_eq!(calls, 1);
+            assert!(delays.lock().unwrap().is_empty());
+        })
+    }
+
+    #[test]
+    fn abandons_attempts_that_time_out() {
+        block_on(async {
+            let policy = RetryPolicy::build()
+                .max_attempts(3)
+                .timeout(Some(Duration::from_millis(10)))
+                .sleep(|delay| thread_sleep(delay.min(Duration::from_millis(10))))
+                .finish()
+                .unwrap();
+
+            // The first two attempts hang, the third succeeds right away.
+            let mut calls = 0;
+            let result = policy
+                .retry(|| {
+                    calls += 1;
+                    let calls = calls;
+                    async move {
+                        if calls < 3 {
+                            future::pending::<()>().await;
+                        }
+                        Ok(calls)
+                    }
+                })
+                .await;
+            assert_eq!(result, Ok(3));
+
+            let policy = RetryPolicy::build()
+                .timeout(Some(Duration::from_millis(10)))
+                .finish()
+                .unwrap();
+            let result: Result<()> = policy.retry(future::pending).await;
+            assert_matches!(result, Err(Error::Opaque(_)));
+        })
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn default_sleep_uses_the_tokio_timer() {
+        let policy = RetryPolicy::build()
+            .max_attempts(2)
+            .initial_backoff(Duration::from_secs(3600))
+            .max_backoff(Duration::from_secs(3600))
+            .jitter(false)
+            .finish()
+            .unwrap();
+
+        // The paused clock skips the hour long backoff, which a thread sleeping in real time
+        // would not.
+        let start = tokio::time::Instant::now();
+        let err = Error::Opaque("timed out".into());
+        assert!(policy.wait_to_retry(1, &err).await);
+        assert_eq!(start.elapsed(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn classifies_transient_errors() {
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable(&Error::Opaque("connection reset".into())));
+        assert!(!policy.is_retryable(&Error::NotFound));
+        assert!(!policy.is_retryable(&Error::VerificationFailure("bad hash".into())));
+        assert!(policy.is_retryable(&Error::MirrorsFailed(vec![
+            ("primary".into(), Error::NotFound),
+            ("backup".into(), Error::Opaque("timed out".into())),
+        ])));
+        assert!(!policy.is_retryable(&Error::MirrorsFailed(vec![(
+            "primary".into(),
+            Error::IllegalArgument("bad path".into())
+        )])));
+    }
+
+    #[test]
+    fn jitter_stays_within_backoff() {
+        block_on(async {
+            let delays = Arc::new(Mutex::new(Vec::new()));
+            let recorded = Arc::clone(&delays);
+            let policy = RetryPolicy::build()
+                .max_attempts(2)
+                .initial_backoff(Duration::from_secs(4))
+                .sleep(move |delay| {
+                    recorded.lock().unwrap().push(delay);
+                    async {}.boxed()
+                })
+                .finish()
+                .unwrap();
+
+            for _ in 0..20 {
+                let err = Error::Opaque("timed out".into());
+                assert!(policy.wait_to_retry(1, &err).await);
+            }
+            for delay in delays.lock().unwrap().iter() {
+                assert!(*delay >= Duration::from_secs(2));
+                assert!(*delay <= Duration::from_secs(4));
+            }
+        })
+    }
+
+    #[test]
+    fn builder_rejects_invalid_policies() {
+        assert!(RetryPolicy::build().max_attempts(0).finish().is_err());
+        assert!(RetryPolicy::build().multiplier(0).finish().is_err());
+        assert!(RetryPolicy::build()
+            .initial_backoff(Duration::from_secs(20))
+            .finish()
+            .is_err());
+        assert!(RetryPolicy::build()
+            .timeout(Some(Duration::from_secs(0)))
+            .finish()
+            .is_err());
+    }
+}
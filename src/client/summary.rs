@@ -0,0 +1,384 @@
+//! The outcome of a [`Client::update`](super::Client::update).
+
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::interchange::DataInterchange;
+use crate::metadata::{Metadata, MetadataPath, Role, TargetsMetadata, VirtualTargetPath};
+use crate::tuf::Tuf;
+
+/// The result of a successful [`Client::update`](super::Client::update).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpdateResult {
+    summary: UpdateSummary,
+    warnings: Vec<UpdateWarning>,
+}
+
+impl UpdateResult {
+    pub(super) fn new(summary: UpdateSummary, warnings: Vec<UpdateWarning>) -> Self {
+        UpdateResult { summary, warnings }
+    }
+
+    /// Returns `true` if any of the top level roles were updated.
+    pub fn updated(&self) -> bool {
+        self.summary.updated()
+    }
+
+    /// Returns `true` if a new root was trusted.
+    pub fn root_updated(&self) -> bool {
+        self.summary.root.is_updated()
+    }
+
+    /// Returns `true` if a new timestamp was trusted.
+    pub fn timestamp_updated(&self) -> bool {
+        self.summary.timestamp.is_updated()
+    }
+
+    /// Returns `true` if a new snapshot was trusted.
+    pub fn snapshot_updated(&self) -> bool {
+        self.summary.snapshot.is_updated()
+    }
+
+    /// Returns `true` if new top level targets metadata was trusted.
+    pub fn targets_updated(&self) -> bool {
+        self.summary.targets.is_updated()
+    }
+
+    /// The version of the root metadata trusted after the update.
+    pub fn root_version(&self) -> u32 {
+        // The client always trusts some root, so this is only `None` for a default summary.
+        self.summary.root.current.unwrap_or(0)
+    }
+
+    /// The targets that were added to or changed in the top level targets metadata, in sorted
+    /// order. These are the targets an application may want to fetch after the update.
+    pub fn changed_targets(&self) -> Vec<&VirtualTargetPath> {
+        let mut changed = self
+            .summary
+            .targets_added
+            .iter()
+            .chain(self.summary.targets_changed.iter())
+            .collect::<Vec<_>>();
+        changed.sort();
+        changed
+    }
+
+    /// A detailed summary of what changed.
+    pub fn summary(&self) -> &UpdateSummary {
+        &self.summary
+    }
+
+    /// Problems noticed during the update that did not cause it to fail.
+    pub fn warnings(&self) -> &[UpdateWarning] {
+        &self.warnings
+    }
+}
+
+/// A problem noticed during an update that did not cause it to fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpdateWarning {
+    /// The trusted metadata for a role will expire soon. Until the repository publishes new
+    /// metadata for the role, targets can't be verified after it expires.
+    NearExpiry {
+        /// The role whose metadata expires soon.
+        role: Role,
+        /// When the metadata expires.
+        expires: DateTime<Utc>,
+    },
+    /// The update fetched the maximum number of root metadata files allowed by
+    /// `Config::max_root_rotations`, so it may not have reached the newest root, and the other
+    /// roles were not updated. Updating again continues walking the chain of root metadata.
+    RootChainIncomplete {
+        /// The version of the root metadata the update stopped at.
+        version: u32,
+    },
+}
+
+impl UpdateWarning {
+    /// Warn about every top level role in `tuf` that expires before `now + window`.
+    pub(super) fn near_expiry<D: DataInterchange>(
+        tuf: &Tuf<D>,
+        now: DateTime<Utc>,
+        window: Duration,
+    ) -> Vec<Self> {
+        let deadline = now + window;
+        let roles = vec![
+            (Role::Root, Some(tuf.root().expires())),
+            (Role::Timestamp, tuf.timestamp().map(|m| m.expires())),
+            (Role::Snapshot, tuf.snapshot().map(|m| m.expires())),
+            (Role::Targets, tuf.targets().map(|m| m.expires())),
+        ];
+
+        roles
+            .into_iter()
+            .filter_map(|(role, expires)| match expires {
+                Some(expires) if *expires < deadline => Some(UpdateWarning::NearExpiry {
+                    role,
+                    expires: *expires,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for UpdateWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpdateWarning::NearExpiry { role, expires } => {
+                write!(
+                    f,
+                    "{} metadata expires soon, at {}",
+                    role,
+                    expires.to_rfc3339()
+                )
+            }
+            UpdateWarning::RootChainIncomplete { version } => {
+                write!(
+                    f,
+                    "stopped at root metadata version {} after fetching the maximum number of \
+                     root metadata files, other roles were not updated",
+                    version
+                )
+            }
+        }
+    }
+}
+
+/// How the trusted version of a single role changed during an update.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VersionChange {
+    previous: Option<u32>,
+    current: Option<u32>,
+}
+
+impl VersionChange {
+    /// The version that was trusted before the update, if any.
+    pub fn previous(&self) -> Option<u32> {
+        self.previous
+    }
+
+    /// The version that is trusted after the update, if any.
+    pub fn current(&self) -> Option<u32> {
+        self.current
+    }
+
+    /// Returns `true` if the trusted version changed.
+    pub fn is_updated(&self) -> bool {
+        self.previous != self.current
+    }
+}
+
+impl fmt::Display for VersionChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn version(v: Option<u32>) -> String {
+            v.map(|v| format!("v{}", v))
+                .unwrap_or_else(|| "none".into())
+        }
+
+        if self.is_updated() {
+            write!(f, "{} -> {}", version(self.previous), version(self.current))
+        } else {
+            write!(f, "{}", version(self.current))
+        }
+    }
+}
+
+/// A structured record of a single update cycle, suitable for logging.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+    root: VersionChange,
+    timestamp: VersionChange,
+    snapshot: VersionChange,
+    targets: VersionChange,
+    delegations_added: Vec<MetadataPath>,
+    delegations_removed: Vec<MetadataPath>,
+    targets_added: Vec<VirtualTargetPath>,
+    targets_changed: Vec<VirtualTargetPath>,
+    targets_removed: Vec<VirtualTargetPath>,
+    bytes_downloaded: u64,
+}
+
+impl UpdateSummary {
+    pub(super) fn new(before: &TrustedState, after: &TrustedState, bytes_downloaded: u64) -> Self {
+        // Unless the targets metadata was replaced, no targets or delegations changed, so skip
+        // comparing every target.
+        let unchanged = match (&before.targets, &after.targets) {
+            (Some(before), Some(after)) => Arc::ptr_eq(before, after),
+            (None, None) => true,
+            _ => false,
+        };
+        let empty = HashMap::new();
+        let (before_targets, after_targets) = if unchanged {
+            (&empty, &empty)
+        } else {
+            (
+                before.targets.as_ref().map_or(&empty, |t| t.targets()),
+                after.targets.as_ref().map_or(&empty, |t| t.targets()),
+            )
+        };
+
+        let mut targets_added = Vec::new();
+        let mut targets_changed = Vec::new();
+        for (path, description) in after_targets {
+            match before_targets.get(path) {
+                None => targets_added.push(path.clone()),
+                Some(previous) if previous != description => targets_changed.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        let mut targets_removed = before_targets
+            .keys()
+            .filter(|path| !after_targets.contains_key(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let before_delegations = delegated_roles(&before.targets);
+        let after_delegations = delegated_roles(&after.targets);
+        let mut delegations_added = after_delegations
+            .iter()
+            .filter(|role| !before_delegations.contains(role))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut delegations_removed = before_delegations
+            .iter()
+            .filter(|role| !after_delegations.contains(role))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        targets_added.sort();
+        targets_changed.sort();
+        targets_removed.sort();
+        delegations_added.sort();
+        delegations_removed.sort();
+
+        let change = |previous, current| VersionChange { previous, current };
+
+        UpdateSummary {
+            root: change(before.root, after.root),
+            timestamp: change(before.timestamp, after.timestamp),
+            snapshot: change(before.snapshot, after.snapshot),
+            targets: change(
+                before.targets.as_ref().map(|t| t.version()),
+                after.targets.as_ref().map(|t| t.version()),
+            ),
+            delegations_added,
+            delegations_removed,
+            targets_added,
+            targets_changed,
+            targets_removed,
+            bytes_downloaded,
+        }
+    }
+
+    /// Returns `true` if any of the top level roles were updated.
+    pub fn updated(&self) -> bool {
+        self.root.is_updated()
+            || self.timestamp.is_updated()
+            || self.snapshot.is_updated()
+            || self.targets.is_updated()
+    }
+
+    /// How the root version changed.
+    pub fn root(&self) -> &VersionChange {
+        &self.root
+    }
+
+    /// How the timestamp version changed.
+    pub fn timestamp(&self) -> &VersionChange {
+        &self.timestamp
+    }
+
+    /// How the snapshot version changed.
+    pub fn snapshot(&self) -> &VersionChange {
+        &self.snapshot
+    }
+
+    /// How the top level targets version changed.
+    pub fn targets(&self) -> &VersionChange {
+        &self.targets
+    }
+
+    /// Roles newly delegated to by the top level targets metadata, in sorted order.
+    pub fn delegations_added(&self) -> &[MetadataPath] {
+        &self.delegations_added
+    }
+
+    /// Roles no longer delegated to by the top level targets metadata, in sorted order.
+    pub fn delegations_removed(&self) -> &[MetadataPath] {
+        &self.delegations_removed
+    }
+
+    /// Targets newly listed in the top level targets metadata, in sorted order.
+    pub fn targets_added(&self) -> &[VirtualTargetPath] {
+        &self.targets_added
+    }
+
+    /// Targets in the top level targets metadata whose description changed, in sorted order.
+    pub fn targets_changed(&self) -> &[VirtualTargetPath] {
+        &self.targets_changed
+    }
+
+    /// Targets no longer listed in the top level targets metadata, in sorted order.
+    pub fn targets_removed(&self) -> &[VirtualTargetPath] {
+        &self.targets_removed
+    }
+
+    /// The total size of the metadata downloaded from the remote repository.
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.bytes_downloaded
+    }
+}
+
+impl fmt::Display for UpdateSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "root {}, timestamp {}, snapshot {}, targets {}; \
+             targets: {} added, {} changed, {} removed; \
+             delegations: {} added, {} removed; \
+             {} bytes downloaded",
+            self.root,
+            self.timestamp,
+            self.snapshot,
+            self.targets,
+            self.targets_added.len(),
+            self.targets_changed.len(),
+            self.targets_removed.len(),
+            self.delegations_added.len(),
+            self.delegations_removed.len(),
+            self.bytes_downloaded,
+        )
+    }
+}
+
+/// The parts of a `Tuf` that an `UpdateSummary` compares. The targets metadata is shared with the
+/// `Tuf`, so capturing the state does not copy it.
+pub(super) struct TrustedState {
+    root: Option<u32>,
+    timestamp: Option<u32>,
+    snapshot: Option<u32>,
+    targets: Option<Arc<TargetsMetadata>>,
+}
+
+impl TrustedState {
+    pub(super) fn capture<D: DataInterchange>(tuf: &Tuf<D>) -> Self {
+        TrustedState {
+            root: Some(tuf.root().version()),
+            timestamp: tuf.timestamp().map(|t| t.version()),
+            snapshot: tuf.snapshot().map(|s| s.version()),
+            targets: tuf.shared_targets().cloned(),
+        }
+    }
+}
+
+fn delegated_roles(targets: &Option<Arc<TargetsMetadata>>) -> Vec<MetadataPath> {
+    targets
+        .as_ref()
+        .and_then(|t| t.delegations())
+        .map(|d| d.roles().iter().map(|r| r.role().clone()).collect())
+        .unwrap_or_default()
+}
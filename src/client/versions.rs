@@ -0,0 +1,137 @@
+//! Persistence of the highest metadata versions trusted by a [`Client`](super::Client).
+
+use futures_util::future::{BoxFuture, FutureExt};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+use crate::error::Error;
+use crate::tuf::TrustedVersions;
+use crate::Result;
+
+/// Durable storage for the [`TrustedVersions`] of a `Client`.
+///
+/// When a `Client` is configured with a `VersionStore`, it loads the stored versions when it is
+/// created and refuses any metadata older than them, and it saves the new versions after every
+/// [`Client::update`](super::Client::update). This protects against rollback attacks across
+/// restarts, even if the local metadata cache was tampered with or deleted, so the versions
+/// should be kept somewhere other than the local repository.
+pub trait VersionStore: Send + Sync {
+    /// Load the stored versions, or `None` if no versions have been stored yet.
+    fn load(&self) -> BoxFuture<'_, Result<Option<TrustedVersions>>>;
+
+    /// Durably store `versions`, replacing any previously stored versions.
+    fn store<'a>(&'a self, versions: &'a TrustedVersions) -> BoxFuture<'a, Result<()>>;
+}
+
+/// A `VersionStore` that keeps the versions in a JSON file.
+#[derive(Debug)]
+pub struct FileVersionStore {
+    path: PathBuf,
+}
+
+impl FileVersionStore {
+    /// Create a new `FileVersionStore` backed by the file at `path`. The file does not need to
+    /// exist yet, but its parent directory does.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileVersionStore { path: path.into() }
+    }
+
+    /// An immutable reference to the path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl VersionStore for FileVersionStore {
+    fn load(&self) -> BoxFuture<'_, Result<Option<TrustedVersions>>> {
+        async move {
+            let bytes = match fs::read(&self.path) {
+                Ok(bytes) => bytes,
+                Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(Error::from_io(&err, &self.path)),
+            };
+
+            Ok(Some(serde_json::from_slice(&bytes)?))
+        }
+        .boxed()
+    }
+
+    fn store<'a>(&'a self, versions: &'a TrustedVersions) -> BoxFuture<'a, Result<()>> {
+        async move {
+            // Write to a temporary file and rename it into place, so a crash can't leave a
+            // truncated file behind.
+            let dir = match self.path.parent() {
+                Some(parent) if parent != Path::new("") => parent,
+                _ => Path::new("."),
+            };
+            let mut temp_file = NamedTempFile::new_in(dir)?;
+            serde_json::to_writer(&mut temp_file, versions)?;
+            temp_file.as_file().sync_all()?;
+            temp_file.persist(&self.path)?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// The optional `VersionStore` in a `Config`.
+#[derive(Clone, Default)]
+pub(super) struct SharedVersionStore(pub(super) Option<Arc<dyn VersionStore>>);
+
+impl fmt::Debug for SharedVersionStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "SharedVersionStore(Some(..))"),
+            None => write!(f, "SharedVersionStore(None)"),
+        }
+    }
+}
+
+impl PartialEq for SharedVersionStore {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metadata::MetadataPath;
+    use futures_executor::block_on;
+
+    #[test]
+    fn file_version_store_round_trip() {
+        block_on(async {
+            let temp_dir = tempfile::Builder::new()
+                .prefix("rust-tuf")
+                .tempdir()
+                .unwrap();
+            let store = FileVersionStore::new(temp_dir.path().join("versions.json"));
+
+            assert_eq!(store.load().await, Ok(None));
+
+            let versions: TrustedVersions = serde_json::from_value(serde_json::json!({
+                "root": 2,
+                "timestamp": 5,
+                "snapshot": 4,
+                "targets": 3,
+                "delegations": { "foo": 7 },
+            }))
+            .unwrap();
+            store.store(&versions).await.unwrap();
+
+            let loaded = store.load().await.unwrap().unwrap();
+            assert_eq!(loaded, versions);
+            assert_eq!(loaded.delegation(&MetadataPath::new("foo").unwrap()), 7);
+            assert_eq!(loaded.delegation(&MetadataPath::new("bar").unwrap()), 0);
+        })
+    }
+}
@@ -0,0 +1,578 @@
+//! Signing with asymmetric keys stored in [AWS KMS](https://aws.amazon.com/kms/).
+//!
+//! This module requires the `aws-kms` feature. An [`AwsKmsSigner`] fetches the public key from KMS
+//! when it is built, derives the TUF [`KeyId`] from it, and asks KMS to sign the digest of every
+//! message. Only keys with the `SIGN_VERIFY` key usage and an RSA or NIST P-256/P-384 key spec can
+//! be used.
+//!
+//! ```no_run
+//! # use tuf::crypto::aws_kms::{AwsCredentials, AwsKmsSignerBuilder};
+//! # use tuf::interchange::Json;
+//! # use tuf::metadata::SnapshotMetadataBuilder;
+//! # fn main() -> tuf::Result<()> {
+//! let signer = AwsKmsSignerBuilder::new(
+//!     "us-east-1",
+//!     "alias/tuf-timestamp",
+//!     AwsCredentials::from_env()?,
+//! )
+//! .build()?;
+//!
+//! println!("{:?}", signer.public().key_id());
+//! let snapshot = SnapshotMetadataBuilder::new().signed::<Json>(&signer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::{DateTime, Utc};
+use data_encoding::{BASE64, HEXLOWER};
+use ring::digest::{self, SHA256};
+use ring::hmac;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use url::Url;
+
+use super::remote;
+use super::{KeyId, PublicKey, SignatureScheme, SignatureValue, Signer};
+use crate::error::Error;
+use crate::Result;
+
+const SERVICE: &str = "kms";
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// Credentials for an AWS IAM principal that is allowed to call `kms:GetPublicKey` and
+/// `kms:Sign`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    /// Create long-term credentials from an access key.
+    pub fn new<I, S>(access_key_id: I, secret_access_key: S) -> Self
+    where
+        I: Into<String>,
+        S: Into<String>,
+    {
+        AwsCredentials {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+        }
+    }
+
+    /// Add the session token of temporary credentials.
+    pub fn session_token<T: Into<String>>(mut self, session_token: T) -> Self {
+        self.session_token = Some(session_token.into());
+        self
+    }
+
+    /// Read the credentials from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, and optional
+    /// `AWS_SESSION_TOKEN` environment variables.
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| {
+            env::var(name).map_err(|_| {
+                Error::IllegalArgument(format!("Environment variable {} is not set", name))
+            })
+        };
+
+        let credentials = Self::new(var("AWS_ACCESS_KEY_ID")?, var("AWS_SECRET_ACCESS_KEY")?);
+        Ok(match env::var("AWS_SESSION_TOKEN") {
+            Ok(token) => credentials.session_token(token),
+            Err(_) => credentials,
+        })
+    }
+}
+
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builder for an [`AwsKmsSigner`].
+#[derive(Debug)]
+pub struct AwsKmsSignerBuilder {
+    region: String,
+    kms_key_id: String,
+    credentials: AwsCredentials,
+    endpoint: Option<String>,
+    rsa_scheme: SignatureScheme,
+}
+
+impl AwsKmsSignerBuilder {
+    /// Create a new builder for the KMS key `kms_key_id` in `region`. The key can be named by its
+    /// ID, ARN, alias name, or alias ARN.
+    pub fn new<R, K>(region: R, kms_key_id: K, credentials: AwsCredentials) -> Self
+    where
+        R: Into<String>,
+        K: Into<String>,
+    {
+        AwsKmsSignerBuilder {
+            region: region.into(),
+            kms_key_id: kms_key_id.into(),
+            credentials,
+            endpoint: None,
+            rsa_scheme: SignatureScheme::RsaSsaPssSha256,
+        }
+    }
+
+    /// Send requests to `endpoint` instead of `https://kms.{region}.amazonaws.com`, e.g. to use a
+    /// VPC endpoint.
+    pub fn endpoint<E: Into<String>>(mut self, endpoint: E) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Set the signature scheme used with RSA keys. Defaults to `RsaSsaPssSha256`. The scheme of
+    /// ECDSA keys is determined by the key.
+    pub fn rsa_scheme(mut self, scheme: SignatureScheme) -> Self {
+        self.rsa_scheme = scheme;
+        self
+    }
+
+    /// Fetch the public key from KMS and build the `AwsKmsSigner`.
+    pub fn build(self) -> Result<AwsKmsSigner> {
+        match self.rsa_scheme {
+            SignatureScheme::RsaSsaPssSha256
+            | SignatureScheme::RsaSsaPssSha512
+            | SignatureScheme::RsaSsaPkcs1v15Sha256 => (),
+            s => {
+                return Err(Error::IllegalArgument(format!(
+                    "{:?} is not an RSA signature scheme",
+                    s
+                )));
+            }
+        }
+
+        let endpoint = match self.endpoint {
+            Some(endpoint) => endpoint,
+            None => format!("https://kms.{}.amazonaws.com", self.region),
+        };
+        let endpoint = Url::parse(&endpoint)
+            .map_err(|e| Error::IllegalArgument(format!("Bad KMS endpoint: {:?}", e)))?;
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => {
+                return Err(Error::IllegalArgument(format!(
+                    "KMS endpoint {} has no host",
+                    endpoint
+                )));
+            }
+        };
+
+        let client = KmsClient {
+            agent: remote::agent(),
+            endpoint,
+            host,
+            region: self.region,
+            credentials: self.credentials,
+        };
+
+        let response: GetPublicKeyResponse = client.call(
+            "TrentService.GetPublicKey",
+            &GetPublicKeyRequest {
+                key_id: &self.kms_key_id,
+            },
+        )?;
+
+        if response.key_usage != "SIGN_VERIFY" {
+            return Err(Error::IllegalArgument(format!(
+                "KMS key {} has key usage {}, not SIGN_VERIFY",
+                self.kms_key_id, response.key_usage
+            )));
+        }
+
+        let scheme = match response.key_spec.as_str() {
+            "ECC_NIST_P256" => SignatureScheme::EcdsaP256Sha256,
+            "ECC_NIST_P384" => SignatureScheme::EcdsaP384Sha384,
+            spec if spec.starts_with("RSA_") => self.rsa_scheme,
+            spec => {
+                return Err(Error::IllegalArgument(format!(
+                    "KMS key {} has unsupported key spec {}",
+                    self.kms_key_id, spec
+                )));
+            }
+        };
+
+        let algorithm = signing_algorithm(&scheme);
+        if !response.signing_algorithms.iter().any(|a| a == algorithm) {
+            return Err(Error::IllegalArgument(format!(
+                "KMS key {} does not support {}",
+                self.kms_key_id, algorithm
+            )));
+        }
+
+        let der = BASE64
+            .decode(response.public_key.as_bytes())
+            .map_err(|e| Error::Encoding(format!("KMS public key: {:?}", e)))?;
+        let public = PublicKey::from_spki(&der, scheme)?;
+
+        Ok(AwsKmsSigner {
+            client,
+            kms_key_id: response.key_id,
+            algorithm,
+            public,
+        })
+    }
+}
+
+/// A `Signer` for an asymmetric key stored in AWS KMS.
+pub struct AwsKmsSigner {
+    client: KmsClient,
+    kms_key_id: String,
+    algorithm: &'static str,
+    public: PublicKey,
+}
+
+impl AwsKmsSigner {
+    /// An immutable reference to the public key.
+    pub fn public(&self) -> &PublicKey {
+        &self.public
+    }
+
+    /// The ARN of the KMS key.
+    pub fn kms_key_id(&self) -> &str {
+        &self.kms_key_id
+    }
+}
+
+impl Signer for AwsKmsSigner {
+    fn key_id(&self) -> &KeyId {
+        self.public.key_id()
+    }
+
+    fn scheme(&self) -> &SignatureScheme {
+        self.public.scheme()
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<SignatureValue> {
+        let digest = remote::digest(self.public.scheme(), msg)?;
+        let response: SignResponse = self.client.call(
+            "TrentService.Sign",
+            &SignRequest {
+                key_id: &self.kms_key_id,
+                message: BASE64.encode(digest.as_ref()),
+                message_type: "DIGEST",
+                signing_algorithm: self.algorithm,
+            },
+        )?;
+
+        let sig = BASE64
+            .decode(response.signature.as_bytes())
+            .map_err(|e| Error::Encoding(format!("KMS signature: {:?}", e)))?;
+        Ok(SignatureValue::new(sig))
+    }
+}
+
+impl fmt::Debug for AwsKmsSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AwsKmsSigner")
+            .field("kms_key_id", &self.kms_key_id)
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+fn signing_algorithm(scheme: &SignatureScheme) -> &'static str {
+    match scheme {
+        SignatureScheme::EcdsaP256Sha256 => "ECDSA_SHA_256",
+        SignatureScheme::EcdsaP384Sha384 => "ECDSA_SHA_384",
+        SignatureScheme::RsaSsaPssSha256 => "RSASSA_PSS_SHA_256",
+        SignatureScheme::RsaSsaPssSha512 => "RSASSA_PSS_SHA_512",
+        SignatureScheme::RsaSsaPkcs1v15Sha256 => "RSASSA_PKCS1_V1_5_SHA_256",
+        // `build` only ever picks one of the schemes above.
+        s => unreachable!("no KMS signing algorithm for {:?}", s),
+    }
+}
+
+struct KmsClient {
+    agent: ureq::Agent,
+    endpoint: Url,
+    host: String,
+    region: String,
+    credentials: AwsCredentials,
+}
+
+impl KmsClient {
+    /// Call the KMS API `target` with a SigV4-signed request.
+    fn call<Req, Resp>(&self, target: &str, request: &Req) -> Result<Resp>
+    where
+        Req: serde::Serialize,
+        Resp: serde::de::DeserializeOwned,
+    {
+        let body = serde_json::to_string(request)?;
+        let amz_date = amz_date(&Utc::now());
+
+        let mut headers = vec![
+            ("content-type", CONTENT_TYPE),
+            ("host", self.host.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+            ("x-amz-target", target),
+        ];
+        if let Some(ref token) = self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.as_str()));
+        }
+        headers.sort();
+
+        let authorization = authorization(
+            &self.credentials,
+            &self.region,
+            SERVICE,
+            "POST",
+            self.endpoint.path(),
+            &headers,
+            body.as_bytes(),
+            &amz_date,
+        );
+
+        let mut request = self.agent.request_url("POST", &self.endpoint);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        let response = request
+            .set("authorization", &authorization)
+            .send_string(&body);
+
+        remote::read_json("AWS KMS", response)
+    }
+}
+
+/// Format `time` as the `X-Amz-Date` header, e.g. `20150830T123600Z`.
+fn amz_date(time: &DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Compute the `Authorization` header of a request signed with AWS Signature Version 4. The
+/// `headers` must be lowercase, sorted by name, and include `host` and `x-amz-date`. The request
+/// has no query string.
+#[allow(clippy::too_many_arguments)]
+fn authorization(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    payload: &[u8],
+    amz_date: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect::<String>();
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method,
+        path,
+        canonical_headers,
+        signed_headers,
+        HEXLOWER.encode(digest::digest(&SHA256, payload).as_ref()),
+    );
+
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        HEXLOWER.encode(digest::digest(&SHA256, canonical_request.as_bytes()).as_ref()),
+    );
+
+    let key = format!("AWS4{}", credentials.secret_access_key);
+    let key = [date, region, service, "aws4_request"]
+        .iter()
+        .fold(key.into_bytes(), |key, part| {
+            hmac_sha256(&key, part.as_bytes())
+        });
+    let signature = HEXLOWER.encode(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data).as_ref().to_vec()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetPublicKeyRequest<'a> {
+    key_id: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetPublicKeyResponse {
+    key_id: String,
+    public_key: String,
+    key_spec: String,
+    key_usage: String,
+    #[serde(default)]
+    signing_algorithms: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SignRequest<'a> {
+    key_id: &'a str,
+    message: String,
+    message_type: &'static str,
+    signing_algorithm: &'static str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SignResponse {
+    signature: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::remote::test_server::TestServer;
+    use crate::crypto::{self, PrivateKey};
+    use serde_json::json;
+
+    const ECDSA_P256_PK8: &[u8] = include_bytes!("../../tests/ecdsa/ecdsa-p256.pk8.der");
+    const ECDSA_P256_SPKI: &[u8] = include_bytes!("../../tests/ecdsa/ecdsa-p256.spki.der");
+
+    // The `get-vanilla` case of the AWS Signature Version 4 test suite.
+    #[test]
+    fn sigv4_test_vector() {
+        let credentials =
+            AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+        let headers = [
+            ("host", "example.amazonaws.com"),
+            ("x-amz-date", "20150830T123600Z"),
+        ];
+        assert_eq!(
+            authorization(
+                &credentials,
+                "us-east-1",
+                "service",
+                "GET",
+                "/",
+                &headers,
+                b"",
+                "20150830T123600Z",
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn credentials_debug_hides_secrets() {
+        let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret").session_token("token");
+        let debug = format!("{:?}", credentials);
+        assert!(debug.contains("AKIDEXAMPLE"));
+        assert!(!debug.contains("secret"));
+        assert!(!debug.contains("token\""));
+    }
+
+    #[test]
+    fn sign_with_kms() {
+        let msg = b"hello kms";
+        let key = PrivateKey::from_pkcs8(ECDSA_P256_PK8, SignatureScheme::EcdsaP256Sha256).unwrap();
+        let sig = key.sign(msg).unwrap();
+        let arn = "arn:aws:kms:us-east-1:111122223333:key/1234abcd";
+
+        let server = TestServer::new(vec![
+            (
+                200,
+                json!({
+                    "KeyId": arn,
+                    "PublicKey": BASE64.encode(ECDSA_P256_SPKI),
+                    "KeySpec": "ECC_NIST_P256",
+                    "KeyUsage": "SIGN_VERIFY",
+                    "SigningAlgorithms": ["ECDSA_SHA_256"],
+                }),
+            ),
+            (
+                200,
+                json!({
+                    "KeyId": arn,
+                    "Signature": BASE64.encode(sig.value().as_bytes()),
+                    "SigningAlgorithm": "ECDSA_SHA_256",
+                }),
+            ),
+        ]);
+
+        let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret").session_token("token");
+        let signer = AwsKmsSignerBuilder::new("us-east-1", "alias/tuf", credentials)
+            .endpoint(server.url())
+            .build()
+            .unwrap();
+        assert_eq!(signer.key_id(), key.key_id());
+        assert_eq!(signer.kms_key_id(), arn);
+
+        let kms_sig = crypto::sign_with(&signer, msg).unwrap();
+        signer.public().verify(msg, &kms_sig).unwrap();
+
+        let requests = server.finish();
+        assert_eq!(requests.len(), 2);
+
+        let get_public_key = &requests[0];
+        assert_eq!(get_public_key.method, "POST");
+        assert_eq!(get_public_key.path, "/");
+        assert_eq!(
+            get_public_key.header("x-amz-target"),
+            Some("TrentService.GetPublicKey")
+        );
+        assert_eq!(get_public_key.json(), json!({ "KeyId": "alias/tuf" }));
+
+        let sign = &requests[1];
+        assert_eq!(sign.header("x-amz-target"), Some("TrentService.Sign"));
+        assert_eq!(sign.header("x-amz-security-token"), Some("token"));
+        assert!(sign
+            .header("authorization")
+            .unwrap()
+            .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert_eq!(
+            sign.json(),
+            json!({
+                "KeyId": arn,
+                "Message": BASE64.encode(digest::digest(&SHA256, msg).as_ref()),
+                "MessageType": "DIGEST",
+                "SigningAlgorithm": "ECDSA_SHA_256",
+            })
+        );
+    }
+
+    #[test]
+    fn reject_encryption_keys() {
+        let server = TestServer::new(vec![(
+            200,
+            json!({
+                "KeyId": "arn:aws:kms:us-east-1:111122223333:key/1234abcd",
+                "PublicKey": BASE64.encode(ECDSA_P256_SPKI),
+                "KeySpec": "RSA_2048",
+                "KeyUsage": "ENCRYPT_DECRYPT",
+            }),
+        )]);
+
+        let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret");
+        let err = AwsKmsSignerBuilder::new("us-east-1", "alias/tuf", credentials)
+            .endpoint(server.url())
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::IllegalArgument(_)));
+        server.finish();
+    }
+}
@@ -0,0 +1,589 @@
+//! Signing with keys stored in [Azure Key Vault](https://azure.microsoft.com/products/key-vault/).
+//!
+//! This module requires the `azure-key-vault` feature. An [`AzureKeyVaultSigner`] signs with a
+//! single version of an RSA or EC key. It fetches the public key when it is built, derives the TUF
+//! [`KeyId`] from it, and asks Key Vault to sign the digest of every message.
+//!
+//! Key Vault is accessed with a Microsoft Entra ID access token, which is obtained either for the
+//! managed identity of the Azure resource the code is running on, or for an app registration with
+//! a client secret. The identity needs the `keys/get` and `keys/sign` permissions.
+//!
+//! ```no_run
+//! # use tuf::crypto::azure_key_vault::{AzureCredentials, AzureKeyVaultSignerBuilder};
+//! # use tuf::interchange::Json;
+//! # use tuf::metadata::SnapshotMetadataBuilder;
+//! # fn main() -> tuf::Result<()> {
+//! let signer = AzureKeyVaultSignerBuilder::new(
+//!     "https://my-vault.vault.azure.net",
+//!     "tuf-snapshot",
+//!     AzureCredentials::managed_identity(),
+//! )
+//! .build()?;
+//!
+//! println!("{:?}", signer.public().key_id());
+//! let snapshot = SnapshotMetadataBuilder::new().signed::<Json>(&signer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use data_encoding::BASE64URL_NOPAD;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use super::remote;
+use super::{
+    ecdsa_signature_to_der, python_tuf_compatibility_keyid_hash_algorithms, write_pkcs1, KeyId,
+    KeyType, PublicKey, SignatureScheme, SignatureValue, Signer,
+};
+use crate::error::Error;
+use crate::Result;
+
+const API_VERSION: &str = "7.4";
+const RESOURCE: &str = "https://vault.azure.net";
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+const AUTHORITY: &str = "https://login.microsoftonline.com";
+
+/// How an [`AzureKeyVaultSigner`] obtains access tokens for Key Vault. Tokens are cached until
+/// shortly before they expire.
+pub struct AzureCredentials {
+    kind: CredentialsKind,
+    cached: remote::CachedToken,
+}
+
+enum CredentialsKind {
+    ManagedIdentity {
+        url: String,
+        client_id: Option<String>,
+    },
+    ClientSecret {
+        authority: String,
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+}
+
+impl AzureCredentials {
+    /// Use the system-assigned managed identity of the Azure resource the code is running on.
+    pub fn managed_identity() -> Self {
+        Self::new(CredentialsKind::ManagedIdentity {
+            url: IMDS_TOKEN_URL.into(),
+            client_id: None,
+        })
+    }
+
+    /// Use the user-assigned managed identity with the client ID `client_id`.
+    pub fn user_assigned_identity<C: Into<String>>(client_id: C) -> Self {
+        Self::new(CredentialsKind::ManagedIdentity {
+            url: IMDS_TOKEN_URL.into(),
+            client_id: Some(client_id.into()),
+        })
+    }
+
+    /// Use the app registration `client_id` in the tenant `tenant_id`, authenticated with
+    /// `client_secret`.
+    pub fn client_secret<T, C, S>(tenant_id: T, client_id: C, client_secret: S) -> Self
+    where
+        T: Into<String>,
+        C: Into<String>,
+        S: Into<String>,
+    {
+        Self::new(CredentialsKind::ClientSecret {
+            authority: AUTHORITY.into(),
+            tenant_id: tenant_id.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+        })
+    }
+
+    fn new(kind: CredentialsKind) -> Self {
+        AzureCredentials {
+            kind,
+            cached: remote::CachedToken::default(),
+        }
+    }
+
+    fn token(&self, agent: &ureq::Agent) -> Result<String> {
+        self.cached.get(|| {
+            let response: TokenResponse = match self.kind {
+                CredentialsKind::ManagedIdentity {
+                    ref url,
+                    ref client_id,
+                } => {
+                    let mut request = agent
+                        .get(url)
+                        .set("Metadata", "true")
+                        .query("api-version", "2018-02-01")
+                        .query("resource", RESOURCE);
+                    if let Some(client_id) = client_id {
+                        request = request.query("client_id", client_id);
+                    }
+                    remote::read_json("Azure managed identity", request.call())?
+                }
+                CredentialsKind::ClientSecret {
+                    ref authority,
+                    ref tenant_id,
+                    ref client_id,
+                    ref client_secret,
+                } => {
+                    let url = format!("{}/{}/oauth2/v2.0/token", authority, tenant_id);
+                    let scope = format!("{}/.default", RESOURCE);
+                    remote::read_json(
+                        "Microsoft Entra ID",
+                        agent.post(&url).send_form(&[
+                            ("grant_type", "client_credentials"),
+                            ("client_id", client_id),
+                            ("client_secret", client_secret),
+                            ("scope", &scope),
+                        ]),
+                    )?
+                }
+            };
+
+            // The managed identity endpoint returns the lifetime as a string.
+            let expires_in = match response.expires_in {
+                serde_json::Value::Number(ref n) => n.as_u64(),
+                serde_json::Value::String(ref s) => s.parse().ok(),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                Error::Encoding(format!(
+                    "Bad access token lifetime: {}",
+                    response.expires_in
+                ))
+            })?;
+
+            Ok((response.access_token, Duration::from_secs(expires_in)))
+        })
+    }
+}
+
+impl fmt::Debug for AzureCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            CredentialsKind::ManagedIdentity { ref client_id, .. } => f
+                .debug_struct("AzureCredentials::ManagedIdentity")
+                .field("client_id", client_id)
+                .finish(),
+            CredentialsKind::ClientSecret {
+                ref tenant_id,
+                ref client_id,
+                ..
+            } => f
+                .debug_struct("AzureCredentials::ClientSecret")
+                .field("tenant_id", tenant_id)
+                .field("client_id", client_id)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Builder for an [`AzureKeyVaultSigner`].
+#[derive(Debug)]
+pub struct AzureKeyVaultSignerBuilder {
+    vault_url: String,
+    key_name: String,
+    key_version: Option<String>,
+    credentials: AzureCredentials,
+    rsa_scheme: SignatureScheme,
+}
+
+impl AzureKeyVaultSignerBuilder {
+    /// Create a new builder for the key `key_name` in the vault at `vault_url`, e.g.
+    /// `https://my-vault.vault.azure.net`.
+    pub fn new<V, K>(vault_url: V, key_name: K, credentials: AzureCredentials) -> Self
+    where
+        V: Into<String>,
+        K: Into<String>,
+    {
+        AzureKeyVaultSignerBuilder {
+            vault_url: vault_url.into(),
+            key_name: key_name.into(),
+            key_version: None,
+            credentials,
+            rsa_scheme: SignatureScheme::RsaSsaPssSha256,
+        }
+    }
+
+    /// Sign with the key version `key_version`. Defaults to the version that is current when the
+    /// signer is built. Either way, the signer keeps using that version after the key is rotated.
+    pub fn key_version<V: Into<String>>(mut self, key_version: V) -> Self {
+        self.key_version = Some(key_version.into());
+        self
+    }
+
+    /// Set the signature scheme used with RSA keys. Defaults to `RsaSsaPssSha256`. The scheme of
+    /// EC keys is determined by the key.
+    pub fn rsa_scheme(mut self, scheme: SignatureScheme) -> Self {
+        self.rsa_scheme = scheme;
+        self
+    }
+
+    /// Fetch the public key from Key Vault and build the `AzureKeyVaultSigner`.
+    pub fn build(self) -> Result<AzureKeyVaultSigner> {
+        match self.rsa_scheme {
+            SignatureScheme::RsaSsaPssSha256
+            | SignatureScheme::RsaSsaPssSha512
+            | SignatureScheme::RsaSsaPkcs1v15Sha256 => (),
+            s => {
+                return Err(Error::IllegalArgument(format!(
+                    "{:?} is not an RSA signature scheme",
+                    s
+                )));
+            }
+        }
+
+        let agent = remote::agent();
+        let key_url = format!(
+            "{}/keys/{}",
+            self.vault_url.trim_end_matches('/'),
+            self.key_name
+        );
+        let url = match self.key_version {
+            Some(ref version) => format!("{}/{}", key_url, version),
+            None => key_url.clone(),
+        };
+
+        let token = self.credentials.token(&agent)?;
+        let response: KeyBundle = remote::read_json(
+            "Azure Key Vault",
+            agent
+                .get(&url)
+                .query("api-version", API_VERSION)
+                .set("Authorization", &format!("Bearer {}", token))
+                .call(),
+        )?;
+        let jwk = response.key;
+
+        if !jwk.key_ops.iter().any(|op| op == "sign") {
+            return Err(Error::IllegalArgument(format!(
+                "Key Vault key {} does not allow signing",
+                jwk.kid
+            )));
+        }
+
+        // The key ID is `{vault}/keys/{name}/{version}`.
+        let version = match self.key_version {
+            Some(version) => version,
+            None => jwk
+                .kid
+                .rsplit('/')
+                .next()
+                .filter(|version| !version.is_empty())
+                .ok_or_else(|| {
+                    Error::Encoding(format!("Key Vault key ID {} has no version", jwk.kid))
+                })?
+                .to_string(),
+        };
+
+        let public = public_key(&jwk, self.rsa_scheme)?;
+
+        Ok(AzureKeyVaultSigner {
+            agent,
+            sign_url: format!("{}/{}/sign", key_url, version),
+            kid: jwk.kid,
+            credentials: self.credentials,
+            public,
+        })
+    }
+}
+
+/// A `Signer` for a key version stored in Azure Key Vault.
+pub struct AzureKeyVaultSigner {
+    agent: ureq::Agent,
+    sign_url: String,
+    kid: String,
+    credentials: AzureCredentials,
+    public: PublicKey,
+}
+
+impl AzureKeyVaultSigner {
+    /// An immutable reference to the public key.
+    pub fn public(&self) -> &PublicKey {
+        &self.public
+    }
+
+    /// The Key Vault identifier of the key version, `{vault}/keys/{name}/{version}`.
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+}
+
+impl Signer for AzureKeyVaultSigner {
+    fn key_id(&self) -> &KeyId {
+        self.public.key_id()
+    }
+
+    fn scheme(&self) -> &SignatureScheme {
+        self.public.scheme()
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<SignatureValue> {
+        let scheme = self.public.scheme();
+        let digest = remote::digest(scheme, msg)?;
+
+        let token = self.credentials.token(&self.agent)?;
+        let response: SignResponse = remote::read_json(
+            "Azure Key Vault",
+            self.agent
+                .post(&self.sign_url)
+                .query("api-version", API_VERSION)
+                .set("Authorization", &format!("Bearer {}", token))
+                .send_json(&SignRequest {
+                    alg: algorithm(scheme),
+                    value: BASE64URL_NOPAD.encode(digest.as_ref()),
+                }),
+        )?;
+
+        let sig = BASE64URL_NOPAD
+            .decode(response.value.as_bytes())
+            .map_err(|e| Error::Encoding(format!("Key Vault signature: {:?}", e)))?;
+        match scheme {
+            SignatureScheme::EcdsaP256Sha256 | SignatureScheme::EcdsaP384Sha384 => {
+                Ok(SignatureValue::new(ecdsa_signature_to_der(&sig)?))
+            }
+            _ => Ok(SignatureValue::new(sig)),
+        }
+    }
+}
+
+impl fmt::Debug for AzureKeyVaultSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AzureKeyVaultSigner")
+            .field("kid", &self.kid)
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Convert a JSON Web Key to a `PublicKey`.
+fn public_key(jwk: &JsonWebKey, rsa_scheme: SignatureScheme) -> Result<PublicKey> {
+    let field = |name: &str, value: &Option<String>| {
+        let value = value
+            .as_ref()
+            .ok_or_else(|| Error::Encoding(format!("Key Vault key {} has no {}", jwk.kid, name)))?;
+        BASE64URL_NOPAD
+            .decode(value.as_bytes())
+            .map_err(|e| Error::Encoding(format!("Key Vault key {}: {:?}", name, e)))
+    };
+
+    let (typ, scheme, value) = match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+        ("RSA", _) | ("RSA-HSM", _) => {
+            let value = write_pkcs1(&field("n", &jwk.n)?, &field("e", &jwk.e)?)?;
+            (KeyType::Rsa, rsa_scheme, value)
+        }
+        ("EC", Some(crv)) | ("EC-HSM", Some(crv)) => {
+            let (typ, scheme) = match crv {
+                "P-256" => (KeyType::EcdsaP256, SignatureScheme::EcdsaP256Sha256),
+                "P-384" => (KeyType::EcdsaP384, SignatureScheme::EcdsaP384Sha384),
+                _ => {
+                    return Err(Error::UnknownKeyType(format!("Key Vault EC curve {}", crv)));
+                }
+            };
+            let mut point = vec![0x04];
+            point.extend(field("x", &jwk.x)?);
+            point.extend(field("y", &jwk.y)?);
+            (typ, scheme, point)
+        }
+        (kty, _) => {
+            return Err(Error::UnknownKeyType(format!("Key Vault key type {}", kty)));
+        }
+    };
+
+    PublicKey::new(
+        typ,
+        scheme,
+        python_tuf_compatibility_keyid_hash_algorithms(),
+        value,
+    )
+}
+
+fn algorithm(scheme: &SignatureScheme) -> &'static str {
+    match scheme {
+        SignatureScheme::EcdsaP256Sha256 => "ES256",
+        SignatureScheme::EcdsaP384Sha384 => "ES384",
+        SignatureScheme::RsaSsaPssSha256 => "PS256",
+        SignatureScheme::RsaSsaPssSha512 => "PS512",
+        SignatureScheme::RsaSsaPkcs1v15Sha256 => "RS256",
+        // `public_key` only ever picks one of the schemes above.
+        s => unreachable!("no Key Vault signing algorithm for {:?}", s),
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct KeyBundle {
+    key: JsonWebKey,
+}
+
+#[derive(Deserialize)]
+struct JsonWebKey {
+    kid: String,
+    kty: String,
+    #[serde(default)]
+    key_ops: Vec<String>,
+    crv: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    alg: &'static str,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    value: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::remote::test_server::TestServer;
+    use crate::crypto::{self, PrivateKey};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::json;
+
+    const ECDSA_P256_PK8: &[u8] = include_bytes!("../../tests/ecdsa/ecdsa-p256.pk8.der");
+    const RSA_2048_PK8: &[u8] = include_bytes!("../../tests/rsa/rsa-2048.pk8.der");
+
+    const KID: &str = "https://vault.example/keys/tuf/0123456789abcdef";
+
+    fn token() -> serde_json::Value {
+        json!({ "access_token": "t0k3n", "expires_in": "3599", "token_type": "Bearer" })
+    }
+
+    #[test]
+    fn sign_with_key_vault() {
+        let msg = b"hello key vault";
+        let ring_key =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, ECDSA_P256_PK8).unwrap();
+        let fixed = ring_key.sign(&SystemRandom::new(), msg).unwrap();
+        let key = PrivateKey::from_pkcs8(ECDSA_P256_PK8, SignatureScheme::EcdsaP256Sha256).unwrap();
+
+        let point = ring_key.public_key().as_ref();
+        let server = TestServer::new(vec![
+            (200, token()),
+            (
+                200,
+                json!({
+                    "key": {
+                        "kid": KID,
+                        "kty": "EC-HSM",
+                        "key_ops": ["sign", "verify"],
+                        "crv": "P-256",
+                        "x": BASE64URL_NOPAD.encode(&point[1..33]),
+                        "y": BASE64URL_NOPAD.encode(&point[33..]),
+                    },
+                }),
+            ),
+            (
+                200,
+                json!({ "kid": KID, "value": BASE64URL_NOPAD.encode(fixed.as_ref()) }),
+            ),
+        ]);
+
+        let credentials = AzureCredentials::new(CredentialsKind::ManagedIdentity {
+            url: format!("{}/metadata/identity/oauth2/token", server.url()),
+            client_id: Some("client".into()),
+        });
+        let signer = AzureKeyVaultSignerBuilder::new(server.url(), "tuf", credentials)
+            .build()
+            .unwrap();
+        assert_eq!(signer.key_id(), key.key_id());
+        assert_eq!(signer.kid(), KID);
+
+        let kv_sig = crypto::sign_with(&signer, msg).unwrap();
+        signer.public().verify(msg, &kv_sig).unwrap();
+
+        let requests = server.finish();
+        assert_eq!(requests.len(), 3);
+
+        let token = &requests[0];
+        assert_eq!(token.header("metadata"), Some("true"));
+        assert!(token
+            .path
+            .contains("resource=https%3A%2F%2Fvault.azure.net"));
+        assert!(token.path.contains("client_id=client"));
+
+        assert_eq!(requests[1].path, "/keys/tuf?api-version=7.4");
+        assert_eq!(requests[1].header("authorization"), Some("Bearer t0k3n"));
+
+        // The token is reused, and the signer is pinned to the version that was current.
+        let sign = &requests[2];
+        assert_eq!(sign.path, "/keys/tuf/0123456789abcdef/sign?api-version=7.4");
+        assert_eq!(sign.header("authorization"), Some("Bearer t0k3n"));
+        assert_eq!(
+            sign.json(),
+            json!({
+                "alg": "ES256",
+                "value": BASE64URL_NOPAD.encode(
+                    ring::digest::digest(&ring::digest::SHA256, msg).as_ref()
+                ),
+            })
+        );
+    }
+
+    #[test]
+    fn client_secret_token() {
+        let server = TestServer::new(vec![(
+            200,
+            json!({ "access_token": "t0k3n", "expires_in": 3599, "token_type": "Bearer" }),
+        )]);
+
+        let credentials = AzureCredentials::new(CredentialsKind::ClientSecret {
+            authority: server.url().into(),
+            tenant_id: "tenant".into(),
+            client_id: "client".into(),
+            client_secret: "s3cr3t".into(),
+        });
+        assert!(!format!("{:?}", credentials).contains("s3cr3t"));
+        assert_eq!(credentials.token(&remote::agent()).unwrap(), "t0k3n");
+
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/tenant/oauth2/v2.0/token");
+        let body = String::from_utf8(requests[0].body.clone()).unwrap();
+        assert!(body.contains("grant_type=client_credentials"));
+        assert!(body.contains("client_secret=s3cr3t"));
+        assert!(body.contains("scope=https%3A%2F%2Fvault.azure.net%2F.default"));
+    }
+
+    #[test]
+    fn rsa_json_web_key() {
+        let key = PrivateKey::from_pkcs8(RSA_2048_PK8, SignatureScheme::RsaSsaPssSha256).unwrap();
+        let (n, e) = untrusted::Input::from(key.public().as_bytes())
+            .read_all(derp::Error::Read, |input| {
+                derp::nested(input, derp::Tag::Sequence, |input| {
+                    let n = derp::positive_integer(input)?;
+                    let e = derp::positive_integer(input)?;
+                    Ok((n.as_slice_less_safe(), e.as_slice_less_safe()))
+                })
+            })
+            .unwrap();
+
+        let jwk = JsonWebKey {
+            kid: KID.into(),
+            kty: "RSA".into(),
+            key_ops: vec!["sign".into()],
+            crv: None,
+            n: Some(BASE64URL_NOPAD.encode(n)),
+            e: Some(BASE64URL_NOPAD.encode(e)),
+            x: None,
+            y: None,
+        };
+        let public = public_key(&jwk, SignatureScheme::RsaSsaPssSha256).unwrap();
+        assert_eq!(&public, key.public());
+    }
+}
@@ -0,0 +1,111 @@
+//! The cryptography library `crypto` is built on.
+//!
+//! `ring` is used by default. The `rustcrypto` feature swaps it for pure Rust implementations from
+//! the RustCrypto project, for targets that `ring` doesn't build on. The rest of the crate only
+//! uses the [`Backend`] trait, so it works the same with either.
+//!
+//! `ring` is a default feature, so it is still built when `rustcrypto` is enabled on top of the
+//! default features. Leaving `ring` out takes `default-features = false`, and with that one of the
+//! two features has to be enabled explicitly.
+
+use std::num::NonZeroU32;
+
+use super::{KeyType, SignatureScheme};
+use crate::Result;
+
+#[cfg(all(feature = "ring", not(feature = "rustcrypto")))]
+mod ring;
+#[cfg(feature = "rustcrypto")]
+mod rustcrypto;
+
+#[cfg(not(any(feature = "ring", feature = "rustcrypto")))]
+compile_error!("tuf needs a crypto backend, enable either the `ring` or `rustcrypto` feature");
+
+/// The backend in use. `ring` is on by default, so `rustcrypto` takes precedence.
+#[cfg(all(feature = "ring", not(feature = "rustcrypto")))]
+pub(crate) type Selected = self::ring::Ring;
+#[cfg(feature = "rustcrypto")]
+pub(crate) type Selected = self::rustcrypto::RustCrypto;
+
+/// A key pair parsed by the selected backend.
+pub(crate) type SelectedKeyPair = <Selected as Backend>::KeyPair;
+
+/// The SHA-2 hash functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Sha2 {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+/// The pseudorandom functions PBKDF2 can be used with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pbkdf2Prf {
+    HmacSha1,
+    HmacSha2(Sha2),
+}
+
+/// An in-progress SHA-2 hash.
+pub(crate) trait Sha2Context: Send {
+    /// Add `data` to the hash.
+    fn update(&mut self, data: &[u8]);
+
+    /// Finish the hash and return the digest.
+    fn finish(self) -> Vec<u8>;
+}
+
+/// A private key, ready to sign with.
+pub(crate) trait KeyPair: Send + Sync {
+    /// The key's type.
+    fn key_type(&self) -> KeyType;
+
+    /// The public half of the key, in the form `PublicKey` holds it: the raw key for Ed25519, an
+    /// uncompressed point for ECDSA, and a PKCS#1 `RSAPublicKey` for RSA.
+    fn public_key(&self) -> Vec<u8>;
+
+    /// Sign `msg` with `scheme`, failing if the key can't be used with the scheme. ECDSA
+    /// signatures are ASN.1 DER encoded.
+    fn sign(&self, scheme: &SignatureScheme, msg: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// The operations `crypto` needs from a cryptography library.
+pub(crate) trait Backend {
+    type Sha2Context: Sha2Context;
+    type KeyPair: KeyPair;
+
+    /// Start a SHA-2 hash.
+    fn sha2(alg: Sha2) -> Self::Sha2Context;
+
+    /// Fill `buf` with random bytes from the operating system.
+    fn fill_random(buf: &mut [u8]) -> Result<()>;
+
+    /// Derive `out` from `password` with PBKDF2.
+    fn pbkdf2(prf: Pbkdf2Prf, iterations: NonZeroU32, salt: &[u8], password: &[u8], out: &mut [u8]);
+
+    /// Generate a new Ed25519 or ECDSA key, encoded as PKCS#8.
+    fn generate_pkcs8(key_type: &KeyType) -> Result<Vec<u8>>;
+
+    /// Parse an Ed25519 key from a PKCS#8 v1 or v2 `PrivateKeyInfo`.
+    fn ed25519_from_pkcs8(der_key: &[u8]) -> Result<Self::KeyPair>;
+
+    /// Create an Ed25519 key from its seed and the public key it derives.
+    fn ed25519_from_seed_and_public_key(seed: &[u8], public: &[u8]) -> Result<Self::KeyPair>;
+
+    /// Parse a P-256 or P-384 ECDSA key from a PKCS#8 `PrivateKeyInfo`.
+    fn ecdsa_from_pkcs8(der_key: &[u8]) -> Result<Self::KeyPair>;
+
+    /// Parse an RSA key from a PKCS#8 `PrivateKeyInfo`, rejecting moduli under 2048 bits.
+    fn rsa_from_pkcs8(der_key: &[u8]) -> Result<Self::KeyPair>;
+
+    /// Verify `sig` over `msg` with `public_key`, which is in the form [`KeyPair::public_key`]
+    /// returns.
+    fn verify(scheme: &SignatureScheme, public_key: &[u8], msg: &[u8], sig: &[u8]) -> Result<()>;
+}
+
+/// The error for signing or verifying with a scheme that doesn't suit the key.
+fn unsupported_scheme(key_type: &KeyType, scheme: &SignatureScheme) -> crate::Error {
+    crate::Error::IllegalArgument(format!(
+        "Key {:?} can't be used with scheme {:?}",
+        key_type, scheme
+    ))
+}
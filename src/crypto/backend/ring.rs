@@ -0,0 +1,203 @@
+//! The `ring` backend.
+
+use ring::digest::{self, SHA256, SHA384, SHA512};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{
+    EcdsaKeyPair, Ed25519KeyPair, KeyPair as _, RsaKeyPair, UnparsedPublicKey,
+    VerificationAlgorithm, ECDSA_P256_SHA256_ASN1, ECDSA_P256_SHA256_ASN1_SIGNING,
+    ECDSA_P384_SHA384_ASN1, ECDSA_P384_SHA384_ASN1_SIGNING, ED25519, RSA_PKCS1_2048_8192_SHA256,
+    RSA_PKCS1_SHA256, RSA_PSS_2048_8192_SHA256, RSA_PSS_2048_8192_SHA512, RSA_PSS_SHA256,
+    RSA_PSS_SHA512,
+};
+use std::num::NonZeroU32;
+
+use super::{unsupported_scheme, Backend, Pbkdf2Prf, Sha2};
+use crate::crypto::{KeyType, SignatureScheme};
+use crate::error::Error;
+use crate::Result;
+
+pub(crate) struct Ring;
+
+pub(crate) enum KeyPair {
+    Ed25519(Ed25519KeyPair),
+    Ecdsa(EcdsaKeyPair, KeyType),
+    Rsa(RsaKeyPair),
+}
+
+impl super::Sha2Context for digest::Context {
+    fn update(&mut self, data: &[u8]) {
+        digest::Context::update(self, data)
+    }
+
+    fn finish(self) -> Vec<u8> {
+        digest::Context::finish(self).as_ref().to_vec()
+    }
+}
+
+impl super::KeyPair for KeyPair {
+    fn key_type(&self) -> KeyType {
+        match self {
+            KeyPair::Ed25519(_) => KeyType::Ed25519,
+            KeyPair::Ecdsa(_, key_type) => key_type.clone(),
+            KeyPair::Rsa(_) => KeyType::Rsa,
+        }
+    }
+
+    fn public_key(&self) -> Vec<u8> {
+        match self {
+            KeyPair::Ed25519(key) => key.public_key().as_ref().to_vec(),
+            KeyPair::Ecdsa(key, _) => key.public_key().as_ref().to_vec(),
+            KeyPair::Rsa(key) => key.public_key().as_ref().to_vec(),
+        }
+    }
+
+    fn sign(&self, scheme: &SignatureScheme, msg: &[u8]) -> Result<Vec<u8>> {
+        let rng = SystemRandom::new();
+        match (self, scheme) {
+            (KeyPair::Ed25519(key), SignatureScheme::Ed25519) => Ok(key.sign(msg).as_ref().into()),
+            (KeyPair::Ecdsa(key, KeyType::EcdsaP256), SignatureScheme::EcdsaP256Sha256)
+            | (KeyPair::Ecdsa(key, KeyType::EcdsaP384), SignatureScheme::EcdsaP384Sha384) => key
+                .sign(&rng, msg)
+                .map(|sig| sig.as_ref().into())
+                .map_err(|_| Error::Opaque("Failed to sign message.".into())),
+            (KeyPair::Rsa(key), SignatureScheme::RsaSsaPssSha256) => {
+                let mut buf = vec![0; key.public_modulus_len()];
+                key.sign(&RSA_PSS_SHA256, &rng, msg, &mut buf)
+                    .map_err(|_| Error::Opaque("Failed to sign message.".into()))?;
+                Ok(buf)
+            }
+            (KeyPair::Rsa(key), SignatureScheme::RsaSsaPssSha512) => {
+                let mut buf = vec![0; key.public_modulus_len()];
+                key.sign(&RSA_PSS_SHA512, &rng, msg, &mut buf)
+                    .map_err(|_| Error::Opaque("Failed to sign message.".into()))?;
+                Ok(buf)
+            }
+            (KeyPair::Rsa(key), SignatureScheme::RsaSsaPkcs1v15Sha256) => {
+                let mut buf = vec![0; key.public_modulus_len()];
+                key.sign(&RSA_PKCS1_SHA256, &rng, msg, &mut buf)
+                    .map_err(|_| Error::Opaque("Failed to sign message.".into()))?;
+                Ok(buf)
+            }
+            (key, scheme) => Err(unsupported_scheme(&key.key_type(), scheme)),
+        }
+    }
+}
+
+impl Backend for Ring {
+    type Sha2Context = digest::Context;
+    type KeyPair = KeyPair;
+
+    fn sha2(alg: Sha2) -> digest::Context {
+        digest::Context::new(sha2_algorithm(alg))
+    }
+
+    fn fill_random(buf: &mut [u8]) -> Result<()> {
+        SystemRandom::new()
+            .fill(buf)
+            .map_err(|_| Error::Opaque("Failed to generate random bytes".into()))
+    }
+
+    fn pbkdf2(
+        prf: Pbkdf2Prf,
+        iterations: NonZeroU32,
+        salt: &[u8],
+        password: &[u8],
+        out: &mut [u8],
+    ) {
+        let prf = match prf {
+            Pbkdf2Prf::HmacSha1 => pbkdf2::PBKDF2_HMAC_SHA1,
+            Pbkdf2Prf::HmacSha2(Sha2::Sha256) => pbkdf2::PBKDF2_HMAC_SHA256,
+            Pbkdf2Prf::HmacSha2(Sha2::Sha384) => pbkdf2::PBKDF2_HMAC_SHA384,
+            Pbkdf2Prf::HmacSha2(Sha2::Sha512) => pbkdf2::PBKDF2_HMAC_SHA512,
+        };
+        pbkdf2::derive(prf, iterations, salt, password, out)
+    }
+
+    fn generate_pkcs8(key_type: &KeyType) -> Result<Vec<u8>> {
+        let rng = SystemRandom::new();
+        let pkcs8 = match key_type {
+            KeyType::Ed25519 => Ed25519KeyPair::generate_pkcs8(&rng),
+            KeyType::EcdsaP256 => {
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            }
+            KeyType::EcdsaP384 => {
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P384_SHA384_ASN1_SIGNING, &rng)
+            }
+            key_type => {
+                return Err(Error::IllegalArgument(format!(
+                    "Cannot generate {:?} keys",
+                    key_type
+                )))
+            }
+        };
+        pkcs8
+            .map(|bytes| bytes.as_ref().to_vec())
+            .map_err(|_| Error::Opaque(format!("Failed to generate {:?} key", key_type)))
+    }
+
+    fn ed25519_from_pkcs8(der_key: &[u8]) -> Result<KeyPair> {
+        // OpenSSL writes Ed25519 keys as PKCS#8 v1, without the public key.
+        Ed25519KeyPair::from_pkcs8_maybe_unchecked(der_key)
+            .map(KeyPair::Ed25519)
+            .map_err(|_| Error::Encoding("Could not parse key as PKCS#8".into()))
+    }
+
+    fn ed25519_from_seed_and_public_key(seed: &[u8], public: &[u8]) -> Result<KeyPair> {
+        Ed25519KeyPair::from_seed_and_public_key(seed, public)
+            .map(KeyPair::Ed25519)
+            .map_err(|err| Error::Encoding(err.to_string()))
+    }
+
+    fn ecdsa_from_pkcs8(der_key: &[u8]) -> Result<KeyPair> {
+        match EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, der_key) {
+            Ok(key) => Ok(KeyPair::Ecdsa(key, KeyType::EcdsaP256)),
+            Err(_) => EcdsaKeyPair::from_pkcs8(&ECDSA_P384_SHA384_ASN1_SIGNING, der_key)
+                .map(|key| KeyPair::Ecdsa(key, KeyType::EcdsaP384))
+                .map_err(|_| Error::Encoding("Could not parse key as PKCS#8v2".into())),
+        }
+    }
+
+    fn rsa_from_pkcs8(der_key: &[u8]) -> Result<KeyPair> {
+        let key = RsaKeyPair::from_pkcs8(der_key)
+            .map_err(|_| Error::Encoding("Could not parse key as PKCS#8v2".into()))?;
+
+        if key.public_modulus_len() < 256 {
+            return Err(Error::IllegalArgument(format!(
+                "RSA public modulus must be 2048 or greater. Found {}",
+                key.public_modulus_len() * 8
+            )));
+        }
+
+        Ok(KeyPair::Rsa(key))
+    }
+
+    fn verify(scheme: &SignatureScheme, public_key: &[u8], msg: &[u8], sig: &[u8]) -> Result<()> {
+        let alg: &dyn VerificationAlgorithm = match scheme {
+            SignatureScheme::Ed25519 => &ED25519,
+            SignatureScheme::RsaSsaPssSha256 => &RSA_PSS_2048_8192_SHA256,
+            SignatureScheme::RsaSsaPssSha512 => &RSA_PSS_2048_8192_SHA512,
+            SignatureScheme::RsaSsaPkcs1v15Sha256 => &RSA_PKCS1_2048_8192_SHA256,
+            SignatureScheme::EcdsaP256Sha256 => &ECDSA_P256_SHA256_ASN1,
+            SignatureScheme::EcdsaP384Sha384 => &ECDSA_P384_SHA384_ASN1,
+            SignatureScheme::Unknown(s) => {
+                return Err(Error::IllegalArgument(format!(
+                    "Unknown signature scheme: {}",
+                    s
+                )));
+            }
+        };
+
+        UnparsedPublicKey::new(alg, public_key)
+            .verify(msg, sig)
+            .map_err(|_| Error::BadSignature)
+    }
+}
+
+fn sha2_algorithm(alg: Sha2) -> &'static digest::Algorithm {
+    match alg {
+        Sha2::Sha256 => &SHA256,
+        Sha2::Sha384 => &SHA384,
+        Sha2::Sha512 => &SHA512,
+    }
+}
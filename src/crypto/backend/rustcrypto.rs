@@ -0,0 +1,382 @@
+//! The RustCrypto backend, for targets `ring` doesn't build on.
+
+use derp::{self, Tag};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+use ed25519_dalek::{Signer, Verifier};
+use rand_core::{OsRng, RngCore};
+use rsa::pkcs1::{DecodeRsaPublicKey, EncodeRsaPublicKey};
+use rsa::traits::PublicKeyParts;
+use rsa::{Pkcs1v15Sign, Pss, RsaPrivateKey, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::convert::TryInto;
+use std::num::NonZeroU32;
+use untrusted::Input;
+
+use super::{unsupported_scheme, Backend, Pbkdf2Prf, Sha2};
+use crate::crypto::{write_ed25519_pkcs8, KeyType, SignatureScheme, ED25519_SPKI_OID};
+use crate::error::Error;
+use crate::Result;
+
+/// The range of RSA modulus sizes that are accepted, in bits. This matches `ring`.
+const RSA_MODULUS_BITS: std::ops::RangeInclusive<usize> = 2048..=8192;
+
+pub(crate) struct RustCrypto;
+
+pub(crate) enum Sha2Context {
+    Sha256(Sha256),
+    Sha384(Sha384),
+    Sha512(Sha512),
+}
+
+pub(crate) enum KeyPair {
+    Ed25519(ed25519_dalek::SigningKey),
+    EcdsaP256(p256::ecdsa::SigningKey),
+    EcdsaP384(p384::ecdsa::SigningKey),
+    Rsa(RsaPrivateKey),
+}
+
+impl super::Sha2Context for Sha2Context {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Sha2Context::Sha256(context) => context.update(data),
+            Sha2Context::Sha384(context) => context.update(data),
+            Sha2Context::Sha512(context) => context.update(data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Sha2Context::Sha256(context) => context.finalize().to_vec(),
+            Sha2Context::Sha384(context) => context.finalize().to_vec(),
+            Sha2Context::Sha512(context) => context.finalize().to_vec(),
+        }
+    }
+}
+
+impl super::KeyPair for KeyPair {
+    fn key_type(&self) -> KeyType {
+        match self {
+            KeyPair::Ed25519(_) => KeyType::Ed25519,
+            KeyPair::EcdsaP256(_) => KeyType::EcdsaP256,
+            KeyPair::EcdsaP384(_) => KeyType::EcdsaP384,
+            KeyPair::Rsa(_) => KeyType::Rsa,
+        }
+    }
+
+    fn public_key(&self) -> Vec<u8> {
+        match self {
+            KeyPair::Ed25519(key) => key.verifying_key().as_bytes().to_vec(),
+            KeyPair::EcdsaP256(key) => key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+            KeyPair::EcdsaP384(key) => key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+            KeyPair::Rsa(key) => key
+                .to_public_key()
+                .to_pkcs1_der()
+                .map(|der| der.as_bytes().to_vec())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn sign(&self, scheme: &SignatureScheme, msg: &[u8]) -> Result<Vec<u8>> {
+        match (self, scheme) {
+            (KeyPair::Ed25519(key), SignatureScheme::Ed25519) => {
+                Ok(key.sign(msg).to_bytes().to_vec())
+            }
+            (KeyPair::EcdsaP256(key), SignatureScheme::EcdsaP256Sha256) => {
+                let sig: p256::ecdsa::Signature = key.sign(msg);
+                Ok(sig.to_der().as_bytes().to_vec())
+            }
+            (KeyPair::EcdsaP384(key), SignatureScheme::EcdsaP384Sha384) => {
+                let sig: p384::ecdsa::Signature = key.sign(msg);
+                Ok(sig.to_der().as_bytes().to_vec())
+            }
+            (KeyPair::Rsa(key), SignatureScheme::RsaSsaPssSha256) => key
+                .sign_with_rng(&mut OsRng, Pss::new::<Sha256>(), &Sha256::digest(msg))
+                .map_err(|_| Error::Opaque("Failed to sign message.".into())),
+            (KeyPair::Rsa(key), SignatureScheme::RsaSsaPssSha512) => key
+                .sign_with_rng(&mut OsRng, Pss::new::<Sha512>(), &Sha512::digest(msg))
+                .map_err(|_| Error::Opaque("Failed to sign message.".into())),
+            (KeyPair::Rsa(key), SignatureScheme::RsaSsaPkcs1v15Sha256) => key
+                .sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(msg))
+                .map_err(|_| Error::Opaque("Failed to sign message.".into())),
+            (key, scheme) => Err(unsupported_scheme(&super::KeyPair::key_type(key), scheme)),
+        }
+    }
+}
+
+impl Backend for RustCrypto {
+    type Sha2Context = Sha2Context;
+    type KeyPair = KeyPair;
+
+    fn sha2(alg: Sha2) -> Sha2Context {
+        match alg {
+            Sha2::Sha256 => Sha2Context::Sha256(Sha256::new()),
+            Sha2::Sha384 => Sha2Context::Sha384(Sha384::new()),
+            Sha2::Sha512 => Sha2Context::Sha512(Sha512::new()),
+        }
+    }
+
+    fn fill_random(buf: &mut [u8]) -> Result<()> {
+        OsRng
+            .try_fill_bytes(buf)
+            .map_err(|_| Error::Opaque("Failed to generate random bytes".into()))
+    }
+
+    fn pbkdf2(
+        prf: Pbkdf2Prf,
+        iterations: NonZeroU32,
+        salt: &[u8],
+        password: &[u8],
+        out: &mut [u8],
+    ) {
+        let iterations = iterations.get();
+        match prf {
+            Pbkdf2Prf::HmacSha1 => pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, iterations, out),
+            Pbkdf2Prf::HmacSha2(Sha2::Sha256) => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, out)
+            }
+            Pbkdf2Prf::HmacSha2(Sha2::Sha384) => {
+                pbkdf2::pbkdf2_hmac::<Sha384>(password, salt, iterations, out)
+            }
+            Pbkdf2Prf::HmacSha2(Sha2::Sha512) => {
+                pbkdf2::pbkdf2_hmac::<Sha512>(password, salt, iterations, out)
+            }
+        }
+    }
+
+    fn generate_pkcs8(key_type: &KeyType) -> Result<Vec<u8>> {
+        let pkcs8 = match key_type {
+            // `ring` can't read RFC 5958 Ed25519 keys, so write them as PKCS#8 v1 like OpenSSL.
+            KeyType::Ed25519 => {
+                let key = ed25519_dalek::SigningKey::generate(&mut OsRng);
+                return Ok(write_ed25519_pkcs8(key.as_bytes()).to_vec());
+            }
+            KeyType::EcdsaP256 => p256::SecretKey::random(&mut OsRng).to_pkcs8_der(),
+            KeyType::EcdsaP384 => p384::SecretKey::random(&mut OsRng).to_pkcs8_der(),
+            key_type => {
+                return Err(Error::IllegalArgument(format!(
+                    "Cannot generate {:?} keys",
+                    key_type
+                )))
+            }
+        };
+        pkcs8
+            .map(|der| der.as_bytes().to_vec())
+            .map_err(|_| Error::Opaque(format!("Failed to generate {:?} key", key_type)))
+    }
+
+    fn ed25519_from_pkcs8(der_key: &[u8]) -> Result<KeyPair> {
+        if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_der(der_key) {
+            return Ok(KeyPair::Ed25519(key));
+        }
+        let (seed, public) = read_ring_ed25519_pkcs8(der_key)
+            .map_err(|_| Error::Encoding("Could not parse key as PKCS#8".into()))?;
+        Self::ed25519_from_seed_and_public_key(seed, public)
+    }
+
+    fn ed25519_from_seed_and_public_key(seed: &[u8], public: &[u8]) -> Result<KeyPair> {
+        let seed = seed
+            .try_into()
+            .map_err(|_| Error::Encoding("Ed25519 seeds must be 32 bytes long".into()))?;
+        let key = ed25519_dalek::SigningKey::from_bytes(seed);
+        if key.verifying_key().as_bytes() != public {
+            return Err(Error::Encoding(
+                "Ed25519 public key does not match the seed".into(),
+            ));
+        }
+        Ok(KeyPair::Ed25519(key))
+    }
+
+    fn ecdsa_from_pkcs8(der_key: &[u8]) -> Result<KeyPair> {
+        match p256::ecdsa::SigningKey::from_pkcs8_der(der_key) {
+            Ok(key) => Ok(KeyPair::EcdsaP256(key)),
+            Err(_) => p384::ecdsa::SigningKey::from_pkcs8_der(der_key)
+                .map(KeyPair::EcdsaP384)
+                .map_err(|_| Error::Encoding("Could not parse key as PKCS#8v2".into())),
+        }
+    }
+
+    fn rsa_from_pkcs8(der_key: &[u8]) -> Result<KeyPair> {
+        let key = RsaPrivateKey::from_pkcs8_der(der_key)
+            .map_err(|_| Error::Encoding("Could not parse key as PKCS#8v2".into()))?;
+
+        if key.n().bits() < *RSA_MODULUS_BITS.start() {
+            return Err(Error::IllegalArgument(format!(
+                "RSA public modulus must be 2048 or greater. Found {}",
+                key.n().bits()
+            )));
+        }
+
+        Ok(KeyPair::Rsa(key))
+    }
+
+    fn verify(scheme: &SignatureScheme, public_key: &[u8], msg: &[u8], sig: &[u8]) -> Result<()> {
+        let res = match scheme {
+            SignatureScheme::Ed25519 => {
+                let key = public_key
+                    .try_into()
+                    .ok()
+                    .and_then(|key| ed25519_dalek::VerifyingKey::from_bytes(key).ok())
+                    .ok_or(Error::BadSignature)?;
+                let sig =
+                    ed25519_dalek::Signature::from_slice(sig).map_err(|_| Error::BadSignature)?;
+                key.verify_strict(msg, &sig).is_ok()
+            }
+            SignatureScheme::EcdsaP256Sha256 => {
+                let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+                    .map_err(|_| Error::BadSignature)?;
+                let sig = p256::ecdsa::Signature::from_der(sig).map_err(|_| Error::BadSignature)?;
+                key.verify(msg, &sig).is_ok()
+            }
+            SignatureScheme::EcdsaP384Sha384 => {
+                let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+                    .map_err(|_| Error::BadSignature)?;
+                let sig = p384::ecdsa::Signature::from_der(sig).map_err(|_| Error::BadSignature)?;
+                key.verify(msg, &sig).is_ok()
+            }
+            SignatureScheme::RsaSsaPssSha256 => rsa_public_key(public_key)?
+                .verify(Pss::new::<Sha256>(), &Sha256::digest(msg), sig)
+                .is_ok(),
+            SignatureScheme::RsaSsaPssSha512 => rsa_public_key(public_key)?
+                .verify(Pss::new::<Sha512>(), &Sha512::digest(msg), sig)
+                .is_ok(),
+            SignatureScheme::RsaSsaPkcs1v15Sha256 => rsa_public_key(public_key)?
+                .verify(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(msg), sig)
+                .is_ok(),
+            SignatureScheme::Unknown(s) => {
+                return Err(Error::IllegalArgument(format!(
+                    "Unknown signature scheme: {}",
+                    s
+                )));
+            }
+        };
+
+        if res {
+            Ok(())
+        } else {
+            Err(Error::BadSignature)
+        }
+    }
+}
+
+/// Read the seed and public key of an Ed25519 key written by `ring`. `ring` wraps the public key
+/// of PKCS#8 v2 keys in an explicit `[1]` tag, where RFC 5958 makes it implicit, so the `pkcs8`
+/// crate rejects them.
+fn read_ring_ed25519_pkcs8(der_key: &[u8]) -> std::result::Result<(&[u8], &[u8]), derp::Error> {
+    Input::from(der_key).read_all(derp::Error::Read, |input| {
+        derp::nested(input, Tag::Sequence, |input| {
+            if derp::small_nonnegative_integer(input)? != 1 {
+                return Err(derp::Error::WrongValue);
+            }
+            derp::nested(input, Tag::Sequence, |input| {
+                let alg_id = derp::expect_tag_and_get_value(input, Tag::Oid)?;
+                if alg_id.as_slice_less_safe() != ED25519_SPKI_OID {
+                    return Err(derp::Error::WrongValue);
+                }
+                Ok(())
+            })?;
+            let seed = derp::nested(input, Tag::OctetString, |input| {
+                derp::expect_tag_and_get_value(input, Tag::OctetString)
+            })?;
+            let public = derp::nested(input, Tag::ContextSpecificConstructed1, |input| {
+                derp::bit_string_with_no_unused_bits(input)
+            })?;
+            Ok((seed.as_slice_less_safe(), public.as_slice_less_safe()))
+        })
+    })
+}
+
+/// Parse a PKCS#1 `RSAPublicKey`, rejecting moduli `ring` wouldn't verify with.
+fn rsa_public_key(public_key: &[u8]) -> Result<RsaPublicKey> {
+    let key = RsaPublicKey::from_pkcs1_der(public_key).map_err(|_| Error::BadSignature)?;
+    if !RSA_MODULUS_BITS.contains(&key.n().bits()) {
+        return Err(Error::BadSignature);
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::backend::KeyPair as _;
+    use ring::rand::SystemRandom;
+    use ring::signature::{
+        EcdsaKeyPair, Ed25519KeyPair, KeyPair as _, RsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING,
+        RSA_PSS_SHA256,
+    };
+
+    const ED25519_PK8: &[u8] = include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der");
+    const ECDSA_P256_PK8: &[u8] = include_bytes!("../../../tests/ecdsa/ecdsa-p256.pk8.der");
+    const RSA_2048_PK8: &[u8] = include_bytes!("../../../tests/rsa/rsa-2048.pk8.der");
+
+    #[test]
+    fn verify_ring_signatures() {
+        let msg = b"test";
+        let rng = SystemRandom::new();
+
+        let ring_key = Ed25519KeyPair::from_pkcs8(ED25519_PK8).unwrap();
+        let key = RustCrypto::ed25519_from_pkcs8(ED25519_PK8).unwrap();
+        assert_eq!(key.public_key(), ring_key.public_key().as_ref());
+        let sig = ring_key.sign(msg);
+        assert_eq!(
+            RustCrypto::verify(
+                &SignatureScheme::Ed25519,
+                &key.public_key(),
+                msg,
+                sig.as_ref()
+            ),
+            Ok(())
+        );
+
+        let ring_key =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, ECDSA_P256_PK8).unwrap();
+        let key = RustCrypto::ecdsa_from_pkcs8(ECDSA_P256_PK8).unwrap();
+        assert_eq!(key.public_key(), ring_key.public_key().as_ref());
+        let sig = ring_key.sign(&rng, msg).unwrap();
+        assert_eq!(
+            RustCrypto::verify(
+                &SignatureScheme::EcdsaP256Sha256,
+                &key.public_key(),
+                msg,
+                sig.as_ref()
+            ),
+            Ok(())
+        );
+
+        let ring_key = RsaKeyPair::from_pkcs8(RSA_2048_PK8).unwrap();
+        let key = RustCrypto::rsa_from_pkcs8(RSA_2048_PK8).unwrap();
+        assert_eq!(key.public_key(), ring_key.public_key().as_ref());
+        let mut sig = vec![0; ring_key.public_modulus_len()];
+        ring_key.sign(&RSA_PSS_SHA256, &rng, msg, &mut sig).unwrap();
+        assert_eq!(
+            RustCrypto::verify(
+                &SignatureScheme::RsaSsaPssSha256,
+                &key.public_key(),
+                msg,
+                &sig
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn ring_reads_generated_keys() {
+        let pkcs8 = RustCrypto::generate_pkcs8(&KeyType::Ed25519).unwrap();
+        let key = RustCrypto::ed25519_from_pkcs8(&pkcs8).unwrap();
+        let ring_key = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&pkcs8).unwrap();
+        assert_eq!(key.public_key(), ring_key.public_key().as_ref());
+
+        let pkcs8 = RustCrypto::generate_pkcs8(&KeyType::EcdsaP256).unwrap();
+        let key = RustCrypto::ecdsa_from_pkcs8(&pkcs8).unwrap();
+        let ring_key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &pkcs8).unwrap();
+        assert_eq!(key.public_key(), ring_key.public_key().as_ref());
+    }
+}
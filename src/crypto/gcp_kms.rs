@@ -0,0 +1,442 @@
+//! Signing with asymmetric keys stored in [Google Cloud KMS](https://cloud.google.com/kms).
+//!
+//! This module requires the `gcp-kms` feature. A [`GcpKmsSigner`] signs with a single version of
+//! an `ASYMMETRIC_SIGN` key. It fetches the public key when it is built, derives the TUF
+//! [`KeyId`] from it, and asks Cloud KMS to sign every message.
+//!
+//! ```no_run
+//! # use tuf::crypto::gcp_kms::{GcpCredentials, GcpKmsSignerBuilder};
+//! # use tuf::interchange::Json;
+//! # use tuf::metadata::SnapshotMetadataBuilder;
+//! # fn main() -> tuf::Result<()> {
+//! let signer = GcpKmsSignerBuilder::new(
+//!     "projects/my-project/locations/global/keyRings/tuf/cryptoKeys/snapshot/cryptoKeyVersions/1",
+//!     GcpCredentials::metadata_server(),
+//! )
+//! .build()?;
+//!
+//! println!("{:?}", signer.public().key_id());
+//! let snapshot = SnapshotMetadataBuilder::new().signed::<Json>(&signer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use data_encoding::BASE64;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::time::Duration;
+
+use super::remote;
+use super::{KeyId, PublicKey, SignatureScheme, SignatureValue, Signer};
+use crate::error::Error;
+use crate::Result;
+
+const DEFAULT_ENDPOINT: &str = "https://cloudkms.googleapis.com";
+const METADATA_SERVER_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// How a [`GcpKmsSigner`] obtains OAuth 2.0 access tokens for Cloud KMS.
+pub struct GcpCredentials(CredentialsKind);
+
+enum CredentialsKind {
+    AccessToken(String),
+    MetadataServer {
+        url: String,
+        cached: remote::CachedToken,
+    },
+}
+
+impl GcpCredentials {
+    /// Use a fixed access token, e.g. the output of `gcloud auth print-access-token`. Tokens
+    /// expire, so this is best suited to short-lived signing sessions.
+    pub fn access_token<T: Into<String>>(token: T) -> Self {
+        GcpCredentials(CredentialsKind::AccessToken(token.into()))
+    }
+
+    /// Read a fixed access token from the `GOOGLE_OAUTH_ACCESS_TOKEN` environment variable.
+    pub fn from_env() -> Result<Self> {
+        env::var("GOOGLE_OAUTH_ACCESS_TOKEN")
+            .map(Self::access_token)
+            .map_err(|_| {
+                Error::IllegalArgument(
+                    "Environment variable GOOGLE_OAUTH_ACCESS_TOKEN is not set".into(),
+                )
+            })
+    }
+
+    /// Fetch access tokens for the default service account from the metadata server, when
+    /// running on Compute Engine, GKE, Cloud Run, and the like. Tokens are cached until shortly
+    /// before they expire.
+    pub fn metadata_server() -> Self {
+        Self::metadata_server_at(METADATA_SERVER_TOKEN_URL)
+    }
+
+    fn metadata_server_at(url: &str) -> Self {
+        GcpCredentials(CredentialsKind::MetadataServer {
+            url: url.into(),
+            cached: remote::CachedToken::default(),
+        })
+    }
+
+    fn token(&self, agent: &ureq::Agent) -> Result<String> {
+        let (url, cached) = match self.0 {
+            CredentialsKind::AccessToken(ref token) => return Ok(token.clone()),
+            CredentialsKind::MetadataServer {
+                ref url,
+                ref cached,
+            } => (url, cached),
+        };
+
+        cached.get(|| {
+            let response: TokenResponse = remote::read_json(
+                "GCP metadata server",
+                agent.get(url).set("Metadata-Flavor", "Google").call(),
+            )?;
+            Ok((
+                response.access_token,
+                Duration::from_secs(response.expires_in),
+            ))
+        })
+    }
+}
+
+impl fmt::Debug for GcpCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            CredentialsKind::AccessToken(_) => f.write_str("GcpCredentials::AccessToken"),
+            CredentialsKind::MetadataServer { ref url, .. } => f
+                .debug_struct("GcpCredentials::MetadataServer")
+                .field("url", url)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Builder for a [`GcpKmsSigner`].
+#[derive(Debug)]
+pub struct GcpKmsSignerBuilder {
+    key_version: String,
+    credentials: GcpCredentials,
+    endpoint: String,
+}
+
+impl GcpKmsSignerBuilder {
+    /// Create a new builder for the key version `key_version`, which is the full resource name
+    /// `projects/*/locations/*/keyRings/*/cryptoKeys/*/cryptoKeyVersions/*`.
+    pub fn new<K: Into<String>>(key_version: K, credentials: GcpCredentials) -> Self {
+        GcpKmsSignerBuilder {
+            key_version: key_version.into(),
+            credentials,
+            endpoint: DEFAULT_ENDPOINT.into(),
+        }
+    }
+
+    /// Send requests to `endpoint` instead of `https://cloudkms.googleapis.com`, e.g. to use a
+    /// Private Service Connect endpoint.
+    pub fn endpoint<E: Into<String>>(mut self, endpoint: E) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// Fetch the public key from Cloud KMS and build the `GcpKmsSigner`.
+    pub fn build(self) -> Result<GcpKmsSigner> {
+        let agent = remote::agent();
+        let url = format!(
+            "{}/v1/{}",
+            self.endpoint.trim_end_matches('/'),
+            self.key_version
+        );
+
+        let token = self.credentials.token(&agent)?;
+        let response: PublicKeyResponse = remote::read_json(
+            "GCP KMS",
+            agent
+                .get(&format!("{}/publicKey", url))
+                .set("Authorization", &format!("Bearer {}", token))
+                .call(),
+        )?;
+
+        let scheme = scheme(&response.algorithm).ok_or_else(|| {
+            Error::IllegalArgument(format!(
+                "KMS key {} has unsupported algorithm {}",
+                self.key_version, response.algorithm
+            ))
+        })?;
+        let public = PublicKey::from_pem(&response.pem, scheme)?;
+
+        Ok(GcpKmsSigner {
+            agent,
+            url,
+            credentials: self.credentials,
+            public,
+        })
+    }
+}
+
+/// A `Signer` for an asymmetric key version stored in Google Cloud KMS.
+pub struct GcpKmsSigner {
+    agent: ureq::Agent,
+    url: String,
+    credentials: GcpCredentials,
+    public: PublicKey,
+}
+
+impl GcpKmsSigner {
+    /// An immutable reference to the public key.
+    pub fn public(&self) -> &PublicKey {
+        &self.public
+    }
+}
+
+impl Signer for GcpKmsSigner {
+    fn key_id(&self) -> &KeyId {
+        self.public.key_id()
+    }
+
+    fn scheme(&self) -> &SignatureScheme {
+        self.public.scheme()
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<SignatureValue> {
+        // Ed25519 keys sign the message itself, everything else signs its digest.
+        let request = match self.public.scheme() {
+            SignatureScheme::Ed25519 => SignRequest {
+                data: Some(BASE64.encode(msg)),
+                digest: None,
+            },
+            scheme => {
+                let digest = BASE64.encode(remote::digest(scheme, msg)?.as_ref());
+                let digest = match scheme {
+                    SignatureScheme::EcdsaP384Sha384 => Digest::Sha384(digest),
+                    SignatureScheme::RsaSsaPssSha512 => Digest::Sha512(digest),
+                    _ => Digest::Sha256(digest),
+                };
+                SignRequest {
+                    data: None,
+                    digest: Some(digest),
+                }
+            }
+        };
+
+        let token = self.credentials.token(&self.agent)?;
+        let response: SignResponse = remote::read_json(
+            "GCP KMS",
+            self.agent
+                .post(&format!("{}:asymmetricSign", self.url))
+                .set("Authorization", &format!("Bearer {}", token))
+                .send_json(&request),
+        )?;
+
+        let sig = BASE64
+            .decode(response.signature.as_bytes())
+            .map_err(|e| Error::Encoding(format!("KMS signature: {:?}", e)))?;
+        Ok(SignatureValue::new(sig))
+    }
+}
+
+impl fmt::Debug for GcpKmsSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GcpKmsSigner")
+            .field("url", &self.url)
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Map a `CryptoKeyVersionAlgorithm` to the signature scheme it implements.
+fn scheme(algorithm: &str) -> Option<SignatureScheme> {
+    let scheme = match algorithm {
+        "EC_SIGN_ED25519" => SignatureScheme::Ed25519,
+        "EC_SIGN_P256_SHA256" => SignatureScheme::EcdsaP256Sha256,
+        "EC_SIGN_P384_SHA384" => SignatureScheme::EcdsaP384Sha384,
+        "RSA_SIGN_PSS_2048_SHA256" | "RSA_SIGN_PSS_3072_SHA256" | "RSA_SIGN_PSS_4096_SHA256" => {
+            SignatureScheme::RsaSsaPssSha256
+        }
+        "RSA_SIGN_PSS_4096_SHA512" => SignatureScheme::RsaSsaPssSha512,
+        "RSA_SIGN_PKCS1_2048_SHA256"
+        | "RSA_SIGN_PKCS1_3072_SHA256"
+        | "RSA_SIGN_PKCS1_4096_SHA256" => SignatureScheme::RsaSsaPkcs1v15Sha256,
+        _ => return None,
+    };
+    Some(scheme)
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct PublicKeyResponse {
+    pem: String,
+    algorithm: String,
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest: Option<Digest>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Digest {
+    Sha256(String),
+    Sha384(String),
+    Sha512(String),
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::remote::test_server::TestServer;
+    use crate::crypto::{self, PrivateKey};
+    use ring::digest::{self as ring_digest, SHA384};
+    use serde_json::json;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+    const ED25519_1_SPKI: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.spki.der");
+    const ECDSA_P384_PK8: &[u8] = include_bytes!("../../tests/ecdsa/ecdsa-p384.pk8.der");
+    const ECDSA_P384_SPKI: &[u8] = include_bytes!("../../tests/ecdsa/ecdsa-p384.spki.der");
+
+    const KEY_VERSION: &str =
+        "projects/p/locations/global/keyRings/r/cryptoKeys/k/cryptoKeyVersions/1";
+
+    fn pem(der: &[u8]) -> String {
+        format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            BASE64.encode(der)
+        )
+    }
+
+    #[test]
+    fn sign_digest_with_kms() {
+        let msg = b"hello kms";
+        let key = PrivateKey::from_pkcs8(ECDSA_P384_PK8, SignatureScheme::EcdsaP384Sha384).unwrap();
+        let sig = key.sign(msg).unwrap();
+
+        let server = TestServer::new(vec![
+            (
+                200,
+                json!({
+                    "pem": pem(ECDSA_P384_SPKI),
+                    "algorithm": "EC_SIGN_P384_SHA384",
+                }),
+            ),
+            (
+                200,
+                json!({ "signature": BASE64.encode(sig.value().as_bytes()) }),
+            ),
+        ]);
+
+        let signer = GcpKmsSignerBuilder::new(KEY_VERSION, GcpCredentials::access_token("t0k3n"))
+            .endpoint(server.url())
+            .build()
+            .unwrap();
+        assert_eq!(signer.key_id(), key.key_id());
+        assert_eq!(signer.scheme(), &SignatureScheme::EcdsaP384Sha384);
+
+        let kms_sig = crypto::sign_with(&signer, msg).unwrap();
+        signer.public().verify(msg, &kms_sig).unwrap();
+
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, format!("/v1/{}/publicKey", KEY_VERSION));
+        assert_eq!(requests[0].header("authorization"), Some("Bearer t0k3n"));
+
+        assert_eq!(requests[1].method, "POST");
+        assert_eq!(
+            requests[1].path,
+            format!("/v1/{}:asymmetricSign", KEY_VERSION)
+        );
+        assert_eq!(
+            requests[1].json(),
+            json!({
+                "digest": {
+                    "sha384": BASE64.encode(ring_digest::digest(&SHA384, msg).as_ref()),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn sign_ed25519_message_with_kms() {
+        let msg = b"hello kms";
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let sig = key.sign(msg).unwrap();
+
+        let server = TestServer::new(vec![
+            (
+                200,
+                json!({
+                    "pem": pem(ED25519_1_SPKI),
+                    "algorithm": "EC_SIGN_ED25519",
+                }),
+            ),
+            (
+                200,
+                json!({ "signature": BASE64.encode(sig.value().as_bytes()) }),
+            ),
+        ]);
+
+        let signer = GcpKmsSignerBuilder::new(KEY_VERSION, GcpCredentials::access_token("t0k3n"))
+            .endpoint(server.url())
+            .build()
+            .unwrap();
+        assert_eq!(signer.key_id(), key.key_id());
+
+        let kms_sig = crypto::sign_with(&signer, msg).unwrap();
+        signer.public().verify(msg, &kms_sig).unwrap();
+
+        let requests = server.finish();
+        assert_eq!(requests[1].json(), json!({ "data": BASE64.encode(msg) }));
+    }
+
+    #[test]
+    fn metadata_server_token_is_cached() {
+        let server = TestServer::new(vec![(
+            200,
+            json!({
+                "access_token": "from-metadata",
+                "expires_in": 3599,
+                "token_type": "Bearer",
+            }),
+        )]);
+
+        let credentials = GcpCredentials::metadata_server_at(&format!("{}/token", server.url()));
+        let agent = remote::agent();
+        assert_eq!(credentials.token(&agent).unwrap(), "from-metadata");
+        assert_eq!(credentials.token(&agent).unwrap(), "from-metadata");
+
+        let requests = server.finish();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].header("metadata-flavor"), Some("Google"));
+    }
+
+    #[test]
+    fn reject_unsupported_algorithm() {
+        let server = TestServer::new(vec![(
+            200,
+            json!({
+                "pem": pem(ECDSA_P384_SPKI),
+                "algorithm": "EC_SIGN_SECP256K1_SHA256",
+            }),
+        )]);
+
+        let err = GcpKmsSignerBuilder::new(KEY_VERSION, GcpCredentials::access_token("t0k3n"))
+            .endpoint(server.url())
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::IllegalArgument(_)));
+        server.finish();
+    }
+}
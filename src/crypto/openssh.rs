@@ -0,0 +1,219 @@
+//! The OpenSSH private key format (`openssh-key-v1`), which `ssh-keygen` writes by default. See
+//! `PROTOCOL.key` in the OpenSSH sources.
+//!
+//! Only Ed25519 keys are supported. Keys protected with a passphrase must use the bcrypt KDF with
+//! aes256-ctr, which is what `ssh-keygen` uses, or aes256-cbc.
+
+use aes::cipher::block_padding::NoPadding;
+use aes::cipher::{BlockDecryptMut, KeyIvInit, StreamCipher};
+use aes::Aes256;
+use zeroize::Zeroizing;
+
+use super::{pem, ED25519_KEYPAIR_LENGTH, ED25519_PUBLIC_KEY_LENGTH};
+use crate::error::Error;
+use crate::Result;
+
+const LABEL: &str = "OPENSSH PRIVATE KEY";
+const MAGIC: &[u8] = b"openssh-key-v1\0";
+const ED25519_KEY_TYPE: &[u8] = b"ssh-ed25519";
+const KEY_LENGTH: usize = 32;
+const IV_LENGTH: usize = 16;
+
+/// The ciphers that can protect the private section of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cipher {
+    None,
+    Aes256Ctr,
+    Aes256Cbc,
+}
+
+impl Cipher {
+    fn from_name(name: &[u8]) -> Result<Self> {
+        match name {
+            b"none" => Ok(Cipher::None),
+            b"aes256-ctr" => Ok(Cipher::Aes256Ctr),
+            b"aes256-cbc" => Ok(Cipher::Aes256Cbc),
+            name => Err(Error::Encoding(format!(
+                "Unsupported OpenSSH key cipher {}",
+                String::from_utf8_lossy(name)
+            ))),
+        }
+    }
+}
+
+/// Reads the big-endian integers and length-prefixed strings of the SSH wire format (RFC 4251).
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn u32(&mut self) -> Result<u32> {
+        if self.0.len() < 4 {
+            return Err(truncated());
+        }
+        let (int, rest) = self.0.split_at(4);
+        self.0 = rest;
+        Ok(u32::from_be_bytes([int[0], int[1], int[2], int[3]]))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        if self.0.len() < len {
+            return Err(truncated());
+        }
+        let (string, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(string)
+    }
+}
+
+fn truncated() -> Error {
+    Error::Encoding("OpenSSH key is truncated".into())
+}
+
+/// Decode an OpenSSH private key file holding a single Ed25519 key, returning the 32 byte seed
+/// followed by the 32 byte public key. `passphrase` is only called if the key is encrypted.
+pub(super) fn decode_ed25519<F>(pem: &str, passphrase: F) -> Result<Zeroizing<Vec<u8>>>
+where
+    F: FnOnce() -> Result<String>,
+{
+    let (label, data) = pem::decode(pem)?;
+    // Unencrypted keys hold the private key in the clear.
+    let data = Zeroizing::new(data);
+    if label != LABEL {
+        return Err(Error::Encoding(format!(
+            "Expected an {} PEM block, found {}",
+            LABEL, label
+        )));
+    }
+    if !data.starts_with(MAGIC) {
+        return Err(Error::Encoding("Not an openssh-key-v1 key".into()));
+    }
+
+    let mut reader = Reader(&data[MAGIC.len()..]);
+    let cipher = Cipher::from_name(reader.string()?)?;
+    let kdf = reader.string()?;
+    let kdf_options = reader.string()?;
+    let count = reader.u32()?;
+    if count != 1 {
+        return Err(Error::Encoding(format!(
+            "OpenSSH key files with {} keys are not supported",
+            count
+        )));
+    }
+    let _public = reader.string()?;
+    let private = reader.string()?;
+
+    let private = match (cipher, kdf) {
+        (Cipher::None, b"none") => Zeroizing::new(private.to_vec()),
+        (Cipher::None, _) => {
+            return Err(Error::Encoding(
+                "Unencrypted OpenSSH key names a KDF".into(),
+            ))
+        }
+        (cipher, b"bcrypt") => {
+            let passphrase = Zeroizing::new(passphrase()?);
+            decrypt(cipher, kdf_options, private, passphrase.as_bytes())?
+        }
+        (_, kdf) => {
+            return Err(Error::Encoding(format!(
+                "Unsupported OpenSSH key KDF {}",
+                String::from_utf8_lossy(kdf)
+            )))
+        }
+    };
+
+    let mut reader = Reader(&private);
+    // The two check integers are random, but identical if decryption succeeded.
+    if reader.u32()? != reader.u32()? {
+        return Err(Error::IllegalArgument(
+            "Could not decrypt OpenSSH key, the passphrase is probably wrong".into(),
+        ));
+    }
+
+    let key_type = reader.string()?;
+    if key_type != ED25519_KEY_TYPE {
+        return Err(Error::UnknownKeyType(
+            String::from_utf8_lossy(key_type).into_owned(),
+        ));
+    }
+    let public = reader.string()?;
+    let keypair = reader.string()?;
+    if public.len() != ED25519_PUBLIC_KEY_LENGTH
+        || keypair.len() != ED25519_KEYPAIR_LENGTH
+        || &keypair[ED25519_KEYPAIR_LENGTH - ED25519_PUBLIC_KEY_LENGTH..] != public
+    {
+        return Err(Error::Encoding("Malformed OpenSSH Ed25519 key".into()));
+    }
+
+    Ok(Zeroizing::new(keypair.to_vec()))
+}
+
+fn decrypt(
+    cipher: Cipher,
+    kdf_options: &[u8],
+    data: &[u8],
+    passphrase: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    let mut options = Reader(kdf_options);
+    let salt = options.string()?;
+    let rounds = options.u32()?;
+
+    let mut key_iv = Zeroizing::new([0; KEY_LENGTH + IV_LENGTH]);
+    bcrypt_pbkdf::bcrypt_pbkdf(passphrase, salt, rounds, &mut *key_iv)
+        .map_err(|e| Error::IllegalArgument(format!("OpenSSH key bcrypt KDF: {}", e)))?;
+    let (key, iv) = key_iv.split_at(KEY_LENGTH);
+
+    let mut buf = Zeroizing::new(data.to_vec());
+    match cipher {
+        Cipher::Aes256Ctr => {
+            ctr::Ctr128BE::<Aes256>::new(key.into(), iv.into()).apply_keystream(&mut buf)
+        }
+        Cipher::Aes256Cbc => {
+            cbc::Decryptor::<Aes256>::new(key.into(), iv.into())
+                .decrypt_padded_mut::<NoPadding>(&mut buf)
+                .map_err(|_| {
+                    Error::Encoding("OpenSSH key is not a whole number of blocks".into())
+                })?;
+        }
+        Cipher::None => (),
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OPENSSH_KEY: &str = include_str!("../../tests/ed25519/ed25519-openssh");
+    const OPENSSH_KEY_ENC: &str = include_str!("../../tests/ed25519/ed25519-openssh.enc");
+
+    #[test]
+    fn decode_unencrypted() {
+        let keypair =
+            decode_ed25519(OPENSSH_KEY, || panic!("passphrase should not be needed")).unwrap();
+        assert_eq!(keypair.len(), ED25519_KEYPAIR_LENGTH);
+    }
+
+    #[test]
+    fn decode_encrypted() {
+        let keypair = decode_ed25519(OPENSSH_KEY_ENC, || Ok("hunter2".into())).unwrap();
+        let expected = decode_ed25519(OPENSSH_KEY, || unreachable!()).unwrap();
+        assert_eq!(keypair, expected);
+
+        match decode_ed25519(OPENSSH_KEY_ENC, || Ok("hunter3".into())) {
+            Err(Error::IllegalArgument(_)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn reject_other_blocks() {
+        let pem = pem::encode("PRIVATE KEY", MAGIC);
+        assert!(decode_ed25519(&pem, || unreachable!()).is_err());
+
+        let pem = pem::encode(LABEL, b"openssh-key-v2\0");
+        assert!(decode_ed25519(&pem, || unreachable!()).is_err());
+
+        let pem = pem::encode(LABEL, MAGIC);
+        assert!(decode_ed25519(&pem, || unreachable!()).is_err());
+    }
+}
@@ -0,0 +1,453 @@
+//! Signing with keys stored on a PKCS#11 token, such as a hardware security module.
+//!
+//! This module requires the `pkcs11` feature. The private keys never leave the token: a
+//! [`Pkcs11Token`] lists the public halves of the signing keys it holds, identified by their TUF
+//! [`KeyId`]s, and hands out [`Pkcs11Signer`]s that ask the token to sign on their behalf.
+//!
+//! ```no_run
+//! # use tuf::crypto::pkcs11::{Pkcs11Slot, Pkcs11Token};
+//! # use tuf::interchange::Json;
+//! # use tuf::metadata::SnapshotMetadataBuilder;
+//! # fn main() -> tuf::Result<()> {
+//! let token = Pkcs11Token::open(
+//!     "/usr/lib/softhsm/libsofthsm2.so",
+//!     Pkcs11Slot::TokenLabel("tuf".into()),
+//!     "1234",
+//! )?;
+//!
+//! for key in token.public_keys()? {
+//!     println!("{:?} {:?}", key.key_id(), key.typ());
+//! }
+//!
+//! let key_id = token.public_keys()?[0].key_id().clone();
+//! let signer = token.signer(&key_id)?;
+//! let snapshot = SnapshotMetadataBuilder::new().signed::<Json>(&signer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+use cryptoki::mechanism::eddsa::{EddsaParams, EddsaSignatureScheme};
+use cryptoki::mechanism::rsa::{PkcsMgfType, PkcsPssParams};
+use cryptoki::mechanism::{Mechanism, MechanismType};
+use cryptoki::object::{
+    Attribute, AttributeType, KeyType as Pkcs11KeyType, ObjectClass, ObjectHandle,
+};
+use cryptoki::session::{Session, UserType};
+use cryptoki::slot::Slot;
+use cryptoki::types::AuthPin;
+use derp::{self, Der, Tag};
+use parking_lot::Mutex;
+use std::fmt;
+use std::path::Path;
+use untrusted::Input;
+
+use super::{
+    ecdsa_signature_to_der, python_tuf_compatibility_keyid_hash_algorithms, strip_leading_zeros,
+    KeyId, KeyType, PublicKey, SignatureScheme, SignatureValue, Signer, ECDSA_P256_CURVE_OID,
+    ECDSA_P384_CURVE_OID, ED25519_SPKI_OID,
+};
+use crate::error::Error;
+use crate::Result;
+
+/// How to find the token to use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pkcs11Slot {
+    /// The token in the slot with this ID.
+    Id(u64),
+    /// The token with this label.
+    TokenLabel(String),
+}
+
+/// A logged in session with a PKCS#11 token.
+pub struct Pkcs11Token {
+    // Keep the module loaded for as long as the session is open.
+    _context: Pkcs11,
+    session: Mutex<Session>,
+    rsa_scheme: SignatureScheme,
+}
+
+impl Pkcs11Token {
+    /// Load the PKCS#11 module at `module`, and log in to the token in `slot` as the normal user
+    /// with `pin`.
+    pub fn open<P: AsRef<Path>>(module: P, slot: Pkcs11Slot, pin: &str) -> Result<Self> {
+        let context = Pkcs11::new(module.as_ref())?;
+        context.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK))?;
+
+        let slot = find_slot(&context, &slot)?;
+        let session = context.open_ro_session(slot)?;
+        session.login(UserType::User, Some(&AuthPin::from(pin.to_string())))?;
+
+        Ok(Pkcs11Token {
+            _context: context,
+            session: Mutex::new(session),
+            rsa_scheme: SignatureScheme::RsaSsaPssSha256,
+        })
+    }
+
+    /// Set the signature scheme used with RSA keys. Defaults to `RsaSsaPssSha256`. The scheme of
+    /// Ed25519 and ECDSA keys is determined by the key.
+    pub fn rsa_scheme(mut self, scheme: SignatureScheme) -> Result<Self> {
+        match scheme {
+            SignatureScheme::RsaSsaPssSha256
+            | SignatureScheme::RsaSsaPssSha512
+            | SignatureScheme::RsaSsaPkcs1v15Sha256 => {
+                self.rsa_scheme = scheme;
+                Ok(self)
+            }
+            s => Err(Error::IllegalArgument(format!(
+                "{:?} is not an RSA signature scheme",
+                s
+            ))),
+        }
+    }
+
+    /// The public keys of all the signing keys on the token.
+    pub fn public_keys(&self) -> Result<Vec<PublicKey>> {
+        let session = self.session.lock();
+        let mut keys = Vec::new();
+        for private in signing_keys(&session)? {
+            keys.push(self.public_key(&session, private)?);
+        }
+        Ok(keys)
+    }
+
+    /// Create a `Signer` for the signing key on the token whose public key has `key_id`.
+    pub fn signer(&self, key_id: &KeyId) -> Result<Pkcs11Signer<'_>> {
+        let session = self.session.lock();
+        for private in signing_keys(&session)? {
+            let public = self.public_key(&session, private)?;
+            if public.key_id() == key_id {
+                return Ok(Pkcs11Signer {
+                    token: self,
+                    private,
+                    public,
+                    hook: None,
+                });
+            }
+        }
+        Err(Error::NotFound)
+    }
+
+    /// Create a `Signer` for the signing key on the token with the PKCS#11 `CKA_ID` `id`, which
+    /// calls `hook` before every signature.
+    #[cfg(feature = "yubikey")]
+    pub(super) fn signer_for_object_id<'a>(
+        &'a self,
+        id: &[u8],
+        hook: &'a dyn SignHook,
+    ) -> Result<Pkcs11Signer<'a>> {
+        let session = self.session.lock();
+        let private = session
+            .find_objects(&[
+                Attribute::Class(ObjectClass::PRIVATE_KEY),
+                Attribute::Sign(true),
+                Attribute::Id(id.to_vec()),
+            ])?
+            .into_iter()
+            .next()
+            .ok_or(Error::NotFound)?;
+        let public = self.public_key(&session, private)?;
+
+        Ok(Pkcs11Signer {
+            token: self,
+            private,
+            public,
+            hook: Some(hook),
+        })
+    }
+
+    /// Derive the TUF public key of `private` from its matching public key object, which shares
+    /// its `CKA_ID`.
+    fn public_key(&self, session: &Session, private: ObjectHandle) -> Result<PublicKey> {
+        let id = match session.get_attributes(private, &[AttributeType::Id])?.pop() {
+            Some(Attribute::Id(id)) => id,
+            _ => {
+                return Err(Error::Encoding("PKCS#11 private key has no CKA_ID".into()));
+            }
+        };
+        let public = session
+            .find_objects(&[Attribute::Class(ObjectClass::PUBLIC_KEY), Attribute::Id(id)])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                Error::Encoding("PKCS#11 private key has no matching public key".into())
+            })?;
+
+        let attributes = session.get_attributes(
+            public,
+            &[
+                AttributeType::KeyType,
+                AttributeType::Modulus,
+                AttributeType::PublicExponent,
+                AttributeType::EcParams,
+                AttributeType::EcPoint,
+            ],
+        )?;
+
+        let mut key_type = None;
+        let (mut modulus, mut exponent, mut params, mut point) = (None, None, None, None);
+        for attribute in attributes {
+            match attribute {
+                Attribute::KeyType(t) => key_type = Some(t),
+                Attribute::Modulus(m) => modulus = Some(m),
+                Attribute::PublicExponent(e) => exponent = Some(e),
+                Attribute::EcParams(p) => params = Some(p),
+                Attribute::EcPoint(p) => point = Some(p),
+                _ => (),
+            }
+        }
+
+        match (key_type, modulus, exponent, params, point) {
+            (Some(t), Some(modulus), Some(exponent), _, _) if t == Pkcs11KeyType::RSA => {
+                PublicKey::new(
+                    KeyType::Rsa,
+                    self.rsa_scheme.clone(),
+                    python_tuf_compatibility_keyid_hash_algorithms(),
+                    rsa_public_key(&modulus, &exponent)?,
+                )
+            }
+            (Some(t), _, _, Some(params), Some(point))
+                if t == Pkcs11KeyType::EC || t == Pkcs11KeyType::EC_EDWARDS =>
+            {
+                let (typ, scheme, value) = ec_public_key(&params, &point)?;
+                PublicKey::new(
+                    typ,
+                    scheme,
+                    python_tuf_compatibility_keyid_hash_algorithms(),
+                    value,
+                )
+            }
+            (t, ..) => Err(Error::UnknownKeyType(format!("PKCS#11 key type {:?}", t))),
+        }
+    }
+}
+
+impl fmt::Debug for Pkcs11Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pkcs11Token")
+            .field("rsa_scheme", &self.rsa_scheme)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Lets a wrapper around a `Pkcs11Signer` interact with the user while signing.
+pub(super) trait SignHook {
+    /// Called after the signing operation on `private` was started, and before the message is
+    /// signed.
+    fn before_sign(&self, session: &Session, private: ObjectHandle) -> Result<()>;
+}
+
+/// A `Signer` backed by a key on a PKCS#11 token.
+pub struct Pkcs11Signer<'a> {
+    token: &'a Pkcs11Token,
+    private: ObjectHandle,
+    public: PublicKey,
+    hook: Option<&'a dyn SignHook>,
+}
+
+impl<'a> Pkcs11Signer<'a> {
+    /// An immutable reference to the public key.
+    pub fn public(&self) -> &PublicKey {
+        &self.public
+    }
+}
+
+impl<'a> Signer for Pkcs11Signer<'a> {
+    fn key_id(&self) -> &KeyId {
+        self.public.key_id()
+    }
+
+    fn scheme(&self) -> &SignatureScheme {
+        self.public.scheme()
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<SignatureValue> {
+        let mechanism = match self.public.scheme() {
+            SignatureScheme::Ed25519 => {
+                Mechanism::Eddsa(EddsaParams::new(EddsaSignatureScheme::Pure))
+            }
+            SignatureScheme::EcdsaP256Sha256 => Mechanism::EcdsaSha256,
+            SignatureScheme::EcdsaP384Sha384 => Mechanism::EcdsaSha384,
+            SignatureScheme::RsaSsaPssSha256 => Mechanism::Sha256RsaPkcsPss(PkcsPssParams {
+                hash_alg: MechanismType::SHA256,
+                mgf: PkcsMgfType::MGF1_SHA256,
+                s_len: 32.into(),
+            }),
+            SignatureScheme::RsaSsaPssSha512 => Mechanism::Sha512RsaPkcsPss(PkcsPssParams {
+                hash_alg: MechanismType::SHA512,
+                mgf: PkcsMgfType::MGF1_SHA512,
+                s_len: 64.into(),
+            }),
+            SignatureScheme::RsaSsaPkcs1v15Sha256 => Mechanism::Sha256RsaPkcs,
+            SignatureScheme::Unknown(s) => {
+                return Err(Error::IllegalArgument(format!(
+                    "Unknown signature scheme: {}",
+                    s
+                )));
+            }
+        };
+
+        let session = self.token.session.lock();
+        let sig = match self.hook {
+            None => session.sign(&mechanism, self.private, msg)?,
+            Some(hook) => {
+                session.sign_init(&mechanism, self.private)?;
+                hook.before_sign(&session, self.private)?;
+                session.sign_update(msg)?;
+                session.sign_final()?
+            }
+        };
+
+        match self.public.scheme() {
+            SignatureScheme::EcdsaP256Sha256 | SignatureScheme::EcdsaP384Sha384 => {
+                Ok(SignatureValue::new(ecdsa_signature_to_der(&sig)?))
+            }
+            _ => Ok(SignatureValue::new(sig)),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Pkcs11Signer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pkcs11Signer")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+fn find_slot(context: &Pkcs11, slot: &Pkcs11Slot) -> Result<Slot> {
+    for candidate in context.get_slots_with_token()? {
+        let matches = match slot {
+            Pkcs11Slot::Id(id) => candidate.id() == *id,
+            Pkcs11Slot::TokenLabel(label) => {
+                context.get_token_info(candidate)?.label().trim_end() == label
+            }
+        };
+        if matches {
+            return Ok(candidate);
+        }
+    }
+    Err(Error::IllegalArgument(format!(
+        "No PKCS#11 token found for {:?}",
+        slot
+    )))
+}
+
+fn signing_keys(session: &Session) -> Result<Vec<ObjectHandle>> {
+    Ok(session.find_objects(&[
+        Attribute::Class(ObjectClass::PRIVATE_KEY),
+        Attribute::Sign(true),
+    ])?)
+}
+
+/// Encode an RSA public key as a PKCS#1 `RSAPublicKey`, the format `PublicKey` uses for RSA keys.
+fn rsa_public_key(modulus: &[u8], exponent: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    Der::new(&mut output).sequence(|der| {
+        der.positive_integer(strip_leading_zeros(modulus))?;
+        der.positive_integer(strip_leading_zeros(exponent))
+    })?;
+    Ok(output)
+}
+
+/// Convert the `CKA_EC_PARAMS` and `CKA_EC_POINT` of an EC or Edwards curve public key object to
+/// a key type, its signature scheme, and the raw public key.
+fn ec_public_key(params: &[u8], point: &[u8]) -> Result<(KeyType, SignatureScheme, Vec<u8>)> {
+    let (typ, scheme) = match Input::from(params)
+        .read_all(derp::Error::Read, |input| {
+            derp::expect_tag_and_get_value(input, Tag::Oid)
+        })
+        .map(|oid| oid.as_slice_less_safe())
+    {
+        Ok(oid) if oid == ECDSA_P256_CURVE_OID => {
+            (KeyType::EcdsaP256, SignatureScheme::EcdsaP256Sha256)
+        }
+        Ok(oid) if oid == ECDSA_P384_CURVE_OID => {
+            (KeyType::EcdsaP384, SignatureScheme::EcdsaP384Sha384)
+        }
+        Ok(oid) if oid == ED25519_SPKI_OID => (KeyType::Ed25519, SignatureScheme::Ed25519),
+        // Older tokens name the curve instead of using its OID.
+        _ if params.ends_with(b"edwards25519") => (KeyType::Ed25519, SignatureScheme::Ed25519),
+        _ => {
+            return Err(Error::UnknownKeyType(format!(
+                "PKCS#11 EC parameters {:?}",
+                params
+            )));
+        }
+    };
+
+    // The point should be wrapped in an OCTET STRING, but some tokens return it bare.
+    let value = Input::from(point)
+        .read_all(derp::Error::Read, |input| {
+            derp::expect_tag_and_get_value(input, Tag::OctetString)
+        })
+        .map(|value| value.as_slice_less_safe().to_vec())
+        .unwrap_or_else(|_| point.to_vec());
+
+    Ok((typ, scheme, value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::PrivateKey;
+
+    const RSA_2048_SPKI: &[u8] = include_bytes!("../../tests/rsa/rsa-2048.spki.der");
+    const ECDSA_P256_PK8: &[u8] = include_bytes!("../../tests/ecdsa/ecdsa-p256.pk8.der");
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+
+    #[test]
+    fn rsa_public_key_matches_spki() {
+        let key = PublicKey::from_spki(RSA_2048_SPKI, SignatureScheme::RsaSsaPssSha256).unwrap();
+
+        let (modulus, exponent) = Input::from(key.as_bytes())
+            .read_all(derp::Error::Read, |input| {
+                derp::nested(input, Tag::Sequence, |input| {
+                    let modulus = derp::positive_integer(input)?;
+                    let exponent = derp::positive_integer(input)?;
+                    Ok((modulus, exponent))
+                })
+            })
+            .unwrap();
+
+        let encoded =
+            rsa_public_key(modulus.as_slice_less_safe(), exponent.as_slice_less_safe()).unwrap();
+        assert_eq!(encoded, key.as_bytes());
+    }
+
+    #[test]
+    fn ec_public_key_from_attributes() {
+        let key = PrivateKey::from_pkcs8(ECDSA_P256_PK8, SignatureScheme::EcdsaP256Sha256).unwrap();
+
+        let mut params = Vec::new();
+        Der::new(&mut params).oid(ECDSA_P256_CURVE_OID).unwrap();
+        let mut point = Vec::new();
+        Der::new(&mut point)
+            .octet_string(key.public().as_bytes())
+            .unwrap();
+
+        let (typ, scheme, value) = ec_public_key(&params, &point).unwrap();
+        assert_eq!(typ, KeyType::EcdsaP256);
+        assert_eq!(scheme, SignatureScheme::EcdsaP256Sha256);
+        assert_eq!(value, key.public().as_bytes());
+
+        // Bare points are accepted too.
+        let (_, _, value) = ec_public_key(&params, key.public().as_bytes()).unwrap();
+        assert_eq!(value, key.public().as_bytes());
+
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        // A PrintableString of "edwards25519".
+        let mut params = vec![0x13, 0x0c];
+        params.extend_from_slice(b"edwards25519");
+        let mut point = Vec::new();
+        Der::new(&mut point)
+            .octet_string(key.public().as_bytes())
+            .unwrap();
+
+        let (typ, scheme, value) = ec_public_key(&params, &point).unwrap();
+        assert_eq!(typ, KeyType::Ed25519);
+        assert_eq!(scheme, SignatureScheme::Ed25519);
+        assert_eq!(value, key.public().as_bytes());
+    }
+}
@@ -0,0 +1,338 @@
+//! Passphrase-based encryption of PKCS#8 private keys, as `EncryptedPrivateKeyInfo` structures
+//! protected with PBES2 (RFC 8018).
+//!
+//! Keys are decrypted if they use PBKDF2 with HMAC-SHA1/SHA256/SHA384/SHA512 and AES-CBC, which
+//! covers what OpenSSL has produced by default for years. Keys are encrypted with PBKDF2-HMAC-SHA256
+//! and AES-256-CBC.
+
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{
+    BlockCipher, BlockDecrypt, BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit,
+};
+use derp::{self, Der, Tag};
+use std::num::NonZeroU32;
+use untrusted::{Input, Reader};
+use zeroize::Zeroizing;
+
+#[cfg(any(feature = "ring", feature = "rustcrypto"))]
+use super::backend::Backend;
+use super::backend::{Pbkdf2Prf, Selected, Sha2};
+use crate::error::Error;
+use crate::Result;
+
+/// 1.2.840.113549.1.5.13 id-PBES2
+const PBES2_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+/// 1.2.840.113549.1.5.12 id-PBKDF2
+const PBKDF2_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+/// 1.2.840.113549.2.7 hmacWithSHA1
+const HMAC_SHA1_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x07];
+/// 1.2.840.113549.2.9 hmacWithSHA256
+const HMAC_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+/// 1.2.840.113549.2.10 hmacWithSHA384
+const HMAC_SHA384_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0a];
+/// 1.2.840.113549.2.11 hmacWithSHA512
+const HMAC_SHA512_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0b];
+/// 2.16.840.1.101.3.4.1.2 aes128-CBC
+const AES_128_CBC_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x02];
+/// 2.16.840.1.101.3.4.1.22 aes192-CBC
+const AES_192_CBC_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x16];
+/// 2.16.840.1.101.3.4.1.42 aes256-CBC
+const AES_256_CBC_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+
+/// The PBKDF2 iteration count used when encrypting keys.
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LENGTH: usize = 16;
+const AES_BLOCK_LENGTH: usize = 16;
+
+/// The AES-CBC variants of PBES2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cipher {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl Cipher {
+    fn key_length(self) -> usize {
+        match self {
+            Cipher::Aes128 => 16,
+            Cipher::Aes192 => 24,
+            Cipher::Aes256 => 32,
+        }
+    }
+}
+
+struct Pbes2Params<'a> {
+    salt: &'a [u8],
+    iterations: NonZeroU32,
+    prf: Pbkdf2Prf,
+    cipher: Cipher,
+    iv: &'a [u8],
+}
+
+/// Decrypt the `EncryptedPrivateKeyInfo` `der` with `passphrase`, returning the PKCS#8
+/// `PrivateKeyInfo` it contains.
+pub(super) fn decrypt(der: &[u8], passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let (params, data) = Input::from(der)
+        .read_all(derp::Error::Read, |input| {
+            derp::nested(input, Tag::Sequence, |input| {
+                let params = derp::nested(input, Tag::Sequence, read_pbes2)?;
+                let data = derp::expect_tag_and_get_value(input, Tag::OctetString)?;
+                Ok((params, data.as_slice_less_safe()))
+            })
+        })
+        .map_err(|e| {
+            Error::Encoding(format!(
+                "Unsupported or malformed encrypted PKCS#8 key: {:?}",
+                e
+            ))
+        })?;
+
+    if params.iv.len() != AES_BLOCK_LENGTH {
+        return Err(Error::Encoding(format!(
+            "AES-CBC IV must be {} bytes, not {}",
+            AES_BLOCK_LENGTH,
+            params.iv.len()
+        )));
+    }
+
+    let mut key = Zeroizing::new(vec![0; params.cipher.key_length()]);
+    Selected::pbkdf2(
+        params.prf,
+        params.iterations,
+        params.salt,
+        passphrase,
+        &mut key,
+    );
+
+    let decrypted = match params.cipher {
+        Cipher::Aes128 => cbc_decrypt::<aes::Aes128>(&key, params.iv, data),
+        Cipher::Aes192 => cbc_decrypt::<aes::Aes192>(&key, params.iv, data),
+        Cipher::Aes256 => cbc_decrypt::<aes::Aes256>(&key, params.iv, data),
+    };
+    decrypted.map(Zeroizing::new).ok_or_else(|| {
+        Error::IllegalArgument(
+            "Could not decrypt the PKCS#8 key. The passphrase is probably wrong.".into(),
+        )
+    })
+}
+
+/// Encrypt the PKCS#8 `PrivateKeyInfo` `pkcs8` with `passphrase`, returning an
+/// `EncryptedPrivateKeyInfo`.
+pub(super) fn encrypt(pkcs8: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LENGTH];
+    let mut iv = [0; AES_BLOCK_LENGTH];
+    Selected::fill_random(&mut salt)
+        .and_then(|()| Selected::fill_random(&mut iv))
+        .map_err(|_| Error::Opaque("Failed to generate random salt and IV".into()))?;
+
+    encrypt_with(pkcs8, passphrase, &salt, &iv, PBKDF2_ITERATIONS)
+}
+
+fn encrypt_with(
+    pkcs8: &[u8],
+    passphrase: &[u8],
+    salt: &[u8],
+    iv: &[u8],
+    iterations: u32,
+) -> Result<Vec<u8>> {
+    let mut key = Zeroizing::new([0; 32]);
+    Selected::pbkdf2(
+        Pbkdf2Prf::HmacSha2(Sha2::Sha256),
+        NonZeroU32::new(iterations)
+            .ok_or_else(|| Error::Programming("PBKDF2 needs at least one iteration".into()))?,
+        salt,
+        passphrase,
+        &mut *key,
+    );
+    let encrypted = cbc::Encryptor::<aes::Aes256>::new_from_slices(&*key, iv)
+        .map_err(|_| Error::Programming("Bad AES-256-CBC key or IV length".into()))?
+        .encrypt_padded_vec_mut::<Pkcs7>(pkcs8);
+
+    let iterations = iterations.to_be_bytes();
+    let iterations = &iterations[leading_zero_bytes(&iterations)..];
+
+    let mut output = Vec::new();
+    {
+        let mut der = Der::new(&mut output);
+        der.sequence(|der| {
+            der.sequence(|der| {
+                der.oid(PBES2_OID)?;
+                der.sequence(|der| {
+                    der.sequence(|der| {
+                        der.oid(PBKDF2_OID)?;
+                        der.sequence(|der| {
+                            der.octet_string(salt)?;
+                            der.positive_integer(iterations)?;
+                            der.sequence(|der| {
+                                der.oid(HMAC_SHA256_OID)?;
+                                der.null()
+                            })
+                        })
+                    })?;
+                    der.sequence(|der| {
+                        der.oid(AES_256_CBC_OID)?;
+                        der.octet_string(iv)
+                    })
+                })
+            })?;
+            der.octet_string(&encrypted)
+        })?;
+    }
+
+    Ok(output)
+}
+
+/// Read the PBES2 `AlgorithmIdentifier` of an `EncryptedPrivateKeyInfo`.
+fn read_pbes2<'a>(input: &mut Reader<'a>) -> std::result::Result<Pbes2Params<'a>, derp::Error> {
+    expect_oid(input, PBES2_OID)?;
+    derp::nested(input, Tag::Sequence, |input| {
+        let (salt, iterations, prf) = derp::nested(input, Tag::Sequence, |input| {
+            expect_oid(input, PBKDF2_OID)?;
+            derp::nested(input, Tag::Sequence, read_pbkdf2)
+        })?;
+        let (cipher, iv) = derp::nested(input, Tag::Sequence, |input| {
+            let cipher = match derp::expect_tag_and_get_value(input, Tag::Oid)?.as_slice_less_safe()
+            {
+                AES_128_CBC_OID => Cipher::Aes128,
+                AES_192_CBC_OID => Cipher::Aes192,
+                AES_256_CBC_OID => Cipher::Aes256,
+                _ => return Err(derp::Error::WrongValue),
+            };
+            let iv = derp::expect_tag_and_get_value(input, Tag::OctetString)?;
+            Ok((cipher, iv.as_slice_less_safe()))
+        })?;
+
+        Ok(Pbes2Params {
+            salt,
+            iterations,
+            prf,
+            cipher,
+            iv,
+        })
+    })
+}
+
+/// Read `PBKDF2-params`.
+fn read_pbkdf2<'a>(
+    input: &mut Reader<'a>,
+) -> std::result::Result<(&'a [u8], NonZeroU32, Pbkdf2Prf), derp::Error> {
+    let salt = derp::expect_tag_and_get_value(input, Tag::OctetString)?;
+    let iterations = read_u32(input)?;
+    let iterations = NonZeroU32::new(iterations).ok_or(derp::Error::WrongValue)?;
+
+    // The key length is implied by the cipher.
+    if input.peek(Tag::Integer as u8) {
+        let _ = read_u32(input)?;
+    }
+
+    let prf = if input.peek(Tag::Sequence as u8) {
+        derp::nested(input, Tag::Sequence, |input| {
+            let prf = match derp::expect_tag_and_get_value(input, Tag::Oid)?.as_slice_less_safe() {
+                HMAC_SHA1_OID => Pbkdf2Prf::HmacSha1,
+                HMAC_SHA256_OID => Pbkdf2Prf::HmacSha2(Sha2::Sha256),
+                HMAC_SHA384_OID => Pbkdf2Prf::HmacSha2(Sha2::Sha384),
+                HMAC_SHA512_OID => Pbkdf2Prf::HmacSha2(Sha2::Sha512),
+                _ => return Err(derp::Error::WrongValue),
+            };
+            if !input.at_end() {
+                derp::read_null(input)?;
+            }
+            Ok(prf)
+        })?
+    } else {
+        Pbkdf2Prf::HmacSha1
+    };
+
+    Ok((salt.as_slice_less_safe(), iterations, prf))
+}
+
+fn expect_oid(input: &mut Reader, oid: &[u8]) -> std::result::Result<(), derp::Error> {
+    if derp::expect_tag_and_get_value(input, Tag::Oid)?.as_slice_less_safe() == oid {
+        Ok(())
+    } else {
+        Err(derp::Error::WrongValue)
+    }
+}
+
+fn read_u32(input: &mut Reader) -> std::result::Result<u32, derp::Error> {
+    let bytes = derp::positive_integer(input)?.as_slice_less_safe();
+    let bytes = match bytes.split_first() {
+        Some((0, rest)) => rest,
+        _ => bytes,
+    };
+    if bytes.len() > 4 {
+        return Err(derp::Error::WrongValue);
+    }
+    Ok(bytes.iter().fold(0, |n, b| (n << 8) | u32::from(*b)))
+}
+
+/// DER integers must be minimally encoded.
+fn leading_zero_bytes(bytes: &[u8]) -> usize {
+    let zeros = bytes.iter().take_while(|b| **b == 0).count();
+    zeros.min(bytes.len().saturating_sub(1))
+}
+
+fn cbc_decrypt<C>(key: &[u8], iv: &[u8], data: &[u8]) -> Option<Vec<u8>>
+where
+    C: BlockCipher + BlockDecrypt + KeyInit,
+{
+    cbc::Decryptor::<C>::new_from_slices(key, iv)
+        .ok()?
+        .decrypt_padded_vec_mut::<Pkcs7>(data)
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+    const ED25519_1_PK8_ENC: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.enc.der");
+    const RSA_2048_PK8: &[u8] = include_bytes!("../../tests/rsa/rsa-2048.pk8.der");
+    const RSA_2048_PK8_ENC: &[u8] = include_bytes!("../../tests/rsa/rsa-2048.pk8.enc.der");
+
+    #[test]
+    fn decrypt_openssl_keys() {
+        // OpenSSL drops the public key when it re-encodes an Ed25519 key, leaving the private key
+        // as the tail of the PKCS#8 v1 structure.
+        let decrypted = decrypt(ED25519_1_PK8_ENC, b"hunter2").unwrap();
+        assert_eq!(&decrypted[decrypted.len() - 32..], &ED25519_1_PK8[16..48]);
+
+        assert_eq!(
+            *decrypt(RSA_2048_PK8_ENC, b"hunter2").unwrap(),
+            RSA_2048_PK8
+        );
+    }
+
+    #[test]
+    fn decrypt_with_wrong_passphrase() {
+        assert!(matches!(
+            decrypt(ED25519_1_PK8_ENC, b"hunter3"),
+            Err(Error::IllegalArgument(_))
+        ));
+    }
+
+    #[test]
+    fn decrypt_unencrypted_key() {
+        assert!(matches!(
+            decrypt(ED25519_1_PK8, b"hunter2"),
+            Err(Error::Encoding(_))
+        ));
+    }
+
+    #[test]
+    fn encrypt_round_trip() {
+        let encrypted = encrypt(ED25519_1_PK8, b"hunter2").unwrap();
+        assert_ne!(encrypted, encrypt(ED25519_1_PK8, b"hunter2").unwrap());
+        assert_eq!(*decrypt(&encrypted, b"hunter2").unwrap(), ED25519_1_PK8);
+    }
+
+    #[test]
+    fn encrypt_iteration_count() {
+        let encrypted = encrypt_with(ED25519_1_PK8, b"pw", &[1; 16], &[2; 16], 0x0100).unwrap();
+        // The iteration count is minimally encoded.
+        assert!(encrypted.windows(4).any(|w| w == [0x02, 0x02, 0x01, 0x00]));
+        assert_eq!(*decrypt(&encrypted, b"pw").unwrap(), ED25519_1_PK8);
+    }
+}
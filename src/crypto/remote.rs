@@ -0,0 +1,213 @@
+//! Plumbing shared by the signers that call out to a remote key management service.
+
+#[cfg(any(feature = "gcp-kms", feature = "azure-key-vault"))]
+use parking_lot::Mutex;
+#[cfg(any(feature = "aws-kms", feature = "gcp-kms", feature = "azure-key-vault"))]
+use ring::digest::{self, SHA256, SHA384, SHA512};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+#[cfg(any(feature = "gcp-kms", feature = "azure-key-vault"))]
+use std::time::Instant;
+
+#[cfg(any(feature = "aws-kms", feature = "gcp-kms", feature = "azure-key-vault"))]
+use super::SignatureScheme;
+use crate::error::Error;
+use crate::Result;
+
+/// How long to wait on a key management service before giving up on a request.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Cached access tokens are refreshed this long before they expire.
+#[cfg(any(feature = "gcp-kms", feature = "azure-key-vault"))]
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Create the HTTP agent used to talk to a key management service.
+pub(super) fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(TIMEOUT).build()
+}
+
+/// Read the JSON body of a successful response, or describe the failed request. `service` names
+/// the remote service in error messages.
+pub(super) fn read_json<T: DeserializeOwned>(
+    service: &str,
+    response: std::result::Result<ureq::Response, ureq::Error>,
+) -> Result<T> {
+    match response {
+        Ok(response) => response
+            .into_json()
+            .map_err(|e| Error::Encoding(format!("{} returned invalid JSON: {:?}", service, e))),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            Err(Error::Opaque(format!(
+                "{} returned HTTP {}: {}",
+                service, status, body
+            )))
+        }
+        Err(err) => Err(Error::Opaque(format!("{}: {}", service, err))),
+    }
+}
+
+/// An OAuth 2.0 access token that is fetched on demand and reused until shortly before it expires.
+#[cfg(any(feature = "gcp-kms", feature = "azure-key-vault"))]
+#[derive(Default)]
+pub(super) struct CachedToken(Mutex<Option<(String, Instant)>>);
+
+#[cfg(any(feature = "gcp-kms", feature = "azure-key-vault"))]
+impl CachedToken {
+    /// Return the cached token, or call `fetch` for a new token and its lifetime if there is none
+    /// or it is about to expire.
+    pub(super) fn get<F>(&self, fetch: F) -> Result<String>
+    where
+        F: FnOnce() -> Result<(String, Duration)>,
+    {
+        let mut cached = self.0.lock();
+        if let Some((ref token, refresh_at)) = *cached {
+            if Instant::now() < refresh_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let (token, lifetime) = fetch()?;
+        let refresh_at = Instant::now() + lifetime.saturating_sub(TOKEN_REFRESH_MARGIN);
+        *cached = Some((token.clone(), refresh_at));
+        Ok(token)
+    }
+}
+
+/// Services that sign a precomputed digest need the message hashed with the hash function of the
+/// signature scheme.
+#[cfg(any(feature = "aws-kms", feature = "gcp-kms", feature = "azure-key-vault"))]
+pub(super) fn digest(scheme: &SignatureScheme, msg: &[u8]) -> Result<digest::Digest> {
+    let alg = match scheme {
+        SignatureScheme::RsaSsaPssSha256
+        | SignatureScheme::RsaSsaPkcs1v15Sha256
+        | SignatureScheme::EcdsaP256Sha256 => &SHA256,
+        SignatureScheme::EcdsaP384Sha384 => &SHA384,
+        SignatureScheme::RsaSsaPssSha512 => &SHA512,
+        s => {
+            return Err(Error::IllegalArgument(format!(
+                "Cannot sign a digest with {:?}",
+                s
+            )));
+        }
+    };
+    Ok(digest::digest(alg, msg))
+}
+
+#[cfg(test)]
+pub(super) mod test_server {
+    //! A minimal HTTP server that answers requests with canned responses.
+
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread::{self, JoinHandle};
+
+    /// A request received by a `TestServer`.
+    #[derive(Debug)]
+    pub struct Request {
+        pub method: String,
+        pub path: String,
+        pub headers: Vec<(String, String)>,
+        pub body: Vec<u8>,
+    }
+
+    impl Request {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+
+        pub fn json(&self) -> serde_json::Value {
+            serde_json::from_slice(&self.body).unwrap()
+        }
+    }
+
+    pub struct TestServer {
+        url: String,
+        requests: Receiver<Request>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl TestServer {
+        /// Serve one request for each of `responses`, in order, answering with the status code
+        /// and JSON body.
+        pub fn new(responses: Vec<(u16, serde_json::Value)>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let (tx, requests) = mpsc::channel();
+
+            let thread = thread::spawn(move || {
+                for (status, response) in responses {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let mut parts = line.split_whitespace();
+                    let method = parts.next().unwrap().to_string();
+                    let path = parts.next().unwrap().to_string();
+
+                    let mut headers = Vec::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        let (name, value) = line.split_at(line.find(':').unwrap());
+                        headers.push((name.to_string(), value[1..].trim().to_string()));
+                    }
+
+                    let len = headers
+                        .iter()
+                        .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+                        .map(|(_, v)| v.parse().unwrap())
+                        .unwrap_or(0);
+                    let mut body = vec![0; len];
+                    reader.read_exact(&mut body).unwrap();
+
+                    tx.send(Request {
+                        method,
+                        path,
+                        headers,
+                        body,
+                    })
+                    .unwrap();
+
+                    let body = serde_json::to_vec(&response).unwrap();
+                    let mut stream = stream;
+                    write!(
+                        stream,
+                        "HTTP/1.1 {} Test\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    )
+                    .unwrap();
+                    stream.write_all(&body).unwrap();
+                }
+            });
+
+            TestServer {
+                url,
+                requests,
+                thread: Some(thread),
+            }
+        }
+
+        /// The base URL of the server, without a trailing slash.
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+
+        /// All the requests the server received, after it answered all of them.
+        pub fn finish(mut self) -> Vec<Request> {
+            self.thread.take().unwrap().join().unwrap();
+            self.requests.try_iter().collect()
+        }
+    }
+}
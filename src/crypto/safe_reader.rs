@@ -0,0 +1,394 @@
+//! A reader that verifies the length and hashes of a byte stream as it is read.
+
+use futures_io::AsyncRead;
+use futures_util::ready;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read};
+use std::marker::Unpin;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::{HashAlgorithm, HashContext, HashValue};
+use crate::error::Error;
+use crate::Result;
+
+/// Wrapper to verify a byte stream as it is read.
+///
+/// Wraps a `Read` or `AsyncRead` to ensure that the consumer can't read more than a capped maximum
+/// number of bytes, failing with an `Err` of kind `FileTooLarge` otherwise. The data is hashed as
+/// it flows through, and when the underlying reader is fully consumed the digests are compared
+/// against the expected hashes. If any of them do not match, the final read returns an `Err` of
+/// kind `InvalidData`. Consumers of a `SafeReader` should purge and untrust all read bytes if this
+/// ever returns an `Err`.
+///
+/// It is **critical** that none of the bytes from this struct are used until it has been fully
+/// consumed as the data is untrusted.
+///
+/// ```
+/// use std::io::Read;
+/// use tuf::crypto::{calculate_hashes, HashAlgorithm, SafeReader};
+///
+/// let data: &[u8] = b"hello world";
+/// let (length, hashes) = calculate_hashes(data, &[HashAlgorithm::Sha256]).unwrap();
+///
+/// let mut reader = SafeReader::new(data, length, &hashes).unwrap();
+/// let mut buf = Vec::new();
+/// reader.read_to_end(&mut buf).unwrap();
+///
+/// let mut reader = SafeReader::new(&b"hello there"[..], length, &hashes).unwrap();
+/// assert!(reader.read_to_end(&mut Vec::new()).is_err());
+/// ```
+pub struct SafeReader<R> {
+    inner: R,
+    max_size: u64,
+    hashers: Vec<(HashContext, HashValue)>,
+    bytes_read: u64,
+}
+
+impl<R> SafeReader<R> {
+    /// Create a new `SafeReader` that reads at most `max_size` bytes from `read`, and checks the
+    /// data against every hash in `hashes` with a supported algorithm.
+    ///
+    /// Hashes with unknown algorithms are skipped. If `hashes` is not empty but none of its
+    /// algorithms are supported, this returns `Error::NoSupportedHashAlgorithm`. If `hashes` is
+    /// empty, only the length is checked.
+    pub fn new(read: R, max_size: u64, hashes: &HashMap<HashAlgorithm, HashValue>) -> Result<Self> {
+        let mut hashers = Vec::with_capacity(hashes.len());
+        for (alg, value) in hashes {
+            match alg {
+                HashAlgorithm::Unknown(_) => continue,
+                alg => hashers.push((alg.digest_context()?, value.clone())),
+            }
+        }
+
+        if hashers.is_empty() && !hashes.is_empty() {
+            return Err(Error::NoSupportedHashAlgorithm);
+        }
+
+        Ok(SafeReader {
+            inner: read,
+            max_size,
+            hashers,
+            bytes_read: 0,
+        })
+    }
+
+    /// Create a new `SafeReader` that checks at most one hash.
+    ///
+    /// The argument `hash_data` takes a `HashAlgorithm` and expected `HashValue`. The given
+    /// algorithm is used to hash the data as it is read. At the end of the stream, the digest is
+    /// calculated and compared against `HashValue`. If the two are not equal, it means the data
+    /// stream has been corrupted or tampered with in some way.
+    pub(crate) fn with_hash(
+        read: R,
+        max_size: u64,
+        hash_data: Option<(&HashAlgorithm, HashValue)>,
+    ) -> Result<Self> {
+        let hashers = match hash_data {
+            Some((alg, value)) => vec![(alg.digest_context()?, value)],
+            None => vec![],
+        };
+
+        Ok(SafeReader {
+            inner: read,
+            max_size,
+            hashers,
+            bytes_read: 0,
+        })
+    }
+
+    /// The number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Account for `buf`, the bytes just read from the inner reader. An empty `buf` marks the end
+    /// of the stream, which is when the hashes are checked.
+    fn verify(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            for (context, expected_hash) in self.hashers.drain(..) {
+                if context.finish() != expected_hash {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "Calculated hash did not match the required hash.",
+                    ));
+                }
+            }
+
+            return Ok(0);
+        }
+
+        match self.bytes_read.checked_add(buf.len() as u64) {
+            Some(sum) if sum <= self.max_size => self.bytes_read = sum,
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::FileTooLarge,
+                    "Read exceeded the maximum allowed bytes.",
+                ));
+            }
+        }
+
+        for (context, _) in self.hashers.iter_mut() {
+            context.update(buf);
+        }
+
+        Ok(buf.len())
+    }
+}
+
+impl<R: Read> Read for SafeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_bytes = self.inner.read(buf)?;
+        self.verify(&buf[..read_bytes])
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for SafeReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read_bytes = ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        Poll::Ready(self.verify(&buf[..read_bytes]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // Not `super::*`, so that `read_to_end` unambiguously refers to `AsyncReadExt`.
+    use super::{HashAlgorithm, HashValue, SafeReader};
+    use crate::crypto::{calculate_hash, calculate_hashes};
+    use crate::error::Error;
+    use futures_executor::block_on;
+    use futures_util::io::AsyncReadExt;
+    use ring::digest::{self, SHA256};
+    use std::collections::HashMap;
+    use std::io::{self, ErrorKind};
+
+    #[test]
+    fn valid_read() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
+            let mut reader = SafeReader::with_hash(bytes, bytes.len() as u64, None).unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_ok());
+            assert_eq!(buf, bytes);
+        })
+    }
+
+    #[test]
+    fn valid_read_large_data() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00; 64 * 1024];
+            let mut reader = SafeReader::with_hash(bytes, bytes.len() as u64, None).unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_ok());
+            assert_eq!(buf, bytes);
+        })
+    }
+
+    #[test]
+    fn valid_read_below_max_size() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
+            let mut reader = SafeReader::with_hash(bytes, (bytes.len() as u64) + 1, None).unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_ok());
+            assert_eq!(buf, bytes);
+        })
+    }
+
+    #[test]
+    fn invalid_read_above_max_size() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
+            let mut reader = SafeReader::with_hash(bytes, (bytes.len() as u64) - 1, None).unwrap();
+            let mut buf = Vec::new();
+            let err = reader.read_to_end(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::FileTooLarge);
+        })
+    }
+
+    #[test]
+    fn invalid_read_above_max_size_large_data() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00; 64 * 1024];
+            let mut reader = SafeReader::with_hash(bytes, (bytes.len() as u64) - 1, None).unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_err());
+        })
+    }
+
+    #[test]
+    fn valid_read_good_hash() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
+            let mut context = digest::Context::new(&SHA256);
+            context.update(bytes);
+            let hash_value = HashValue::new(context.finish().as_ref().to_vec());
+            let mut reader = SafeReader::with_hash(
+                bytes,
+                bytes.len() as u64,
+                Some((&HashAlgorithm::Sha256, hash_value)),
+            )
+            .unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_ok());
+            assert_eq!(buf, bytes);
+        })
+    }
+
+    #[test]
+    fn invalid_read_bad_hash() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
+            let mut context = digest::Context::new(&SHA256);
+            context.update(bytes);
+            context.update(&[0xFF]); // evil bytes
+            let hash_value = HashValue::new(context.finish().as_ref().to_vec());
+            let mut reader = SafeReader::with_hash(
+                bytes,
+                bytes.len() as u64,
+                Some((&HashAlgorithm::Sha256, hash_value)),
+            )
+            .unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_err());
+        })
+    }
+
+    #[test]
+    fn valid_read_good_hash_large_data() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00; 64 * 1024];
+            let mut context = digest::Context::new(&SHA256);
+            context.update(bytes);
+            let hash_value = HashValue::new(context.finish().as_ref().to_vec());
+            let mut reader = SafeReader::with_hash(
+                bytes,
+                bytes.len() as u64,
+                Some((&HashAlgorithm::Sha256, hash_value)),
+            )
+            .unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_ok());
+            assert_eq!(buf, bytes);
+        })
+    }
+
+    #[test]
+    fn invalid_read_bad_hash_large_data() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00; 64 * 1024];
+            let mut context = digest::Context::new(&SHA256);
+            context.update(bytes);
+            context.update(&[0xFF]); // evil bytes
+            let hash_value = HashValue::new(context.finish().as_ref().to_vec());
+            let mut reader = SafeReader::with_hash(
+                bytes,
+                bytes.len() as u64,
+                Some((&HashAlgorithm::Sha256, hash_value)),
+            )
+            .unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_err());
+        })
+    }
+
+    #[test]
+    fn valid_read_good_hash_other_algorithms() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
+            for alg in &[
+                HashAlgorithm::Sha3_256,
+                HashAlgorithm::Sha3_512,
+                HashAlgorithm::Blake2b256,
+            ] {
+                let hash_value = calculate_hash(bytes, alg.clone());
+                let mut reader =
+                    SafeReader::with_hash(bytes, bytes.len() as u64, Some((alg, hash_value)))
+                        .unwrap();
+                let mut buf = Vec::new();
+                assert!(reader.read_to_end(&mut buf).await.is_ok());
+                assert_eq!(buf, bytes);
+
+                let hash_value = calculate_hash(b"evil bytes", alg.clone());
+                let mut reader =
+                    SafeReader::with_hash(bytes, bytes.len() as u64, Some((alg, hash_value)))
+                        .unwrap();
+                let mut buf = Vec::new();
+                assert!(reader.read_to_end(&mut buf).await.is_err());
+            }
+        })
+    }
+
+    #[test]
+    fn sync_read_checks_all_hashes() {
+        let bytes: &[u8] = &[0x00; 64 * 1024];
+        let (length, hashes) =
+            calculate_hashes(bytes, &[HashAlgorithm::Sha256, HashAlgorithm::Sha512]).unwrap();
+
+        let mut reader = SafeReader::new(bytes, length, &hashes).unwrap();
+        let mut buf = Vec::new();
+        io::Read::read_to_end(&mut reader, &mut buf).unwrap();
+        assert_eq!(buf, bytes);
+        assert_eq!(reader.bytes_read(), length);
+
+        // A single bad hash fails the read, whichever algorithm it is.
+        for alg in &[HashAlgorithm::Sha256, HashAlgorithm::Sha512] {
+            let mut bad_hashes = hashes.clone();
+            let _ = bad_hashes.insert(alg.clone(), calculate_hash(b"evil bytes", alg.clone()));
+            let mut reader = SafeReader::new(bytes, length, &bad_hashes).unwrap();
+            let err = io::Read::read_to_end(&mut reader, &mut Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+
+        let mut reader = SafeReader::new(bytes, length - 1, &hashes).unwrap();
+        assert!(io::Read::read_to_end(&mut reader, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn async_read_checks_all_hashes() {
+        block_on(async {
+            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
+            let (length, mut hashes) =
+                calculate_hashes(bytes, &[HashAlgorithm::Sha256, HashAlgorithm::Sha3_256]).unwrap();
+
+            let mut reader = SafeReader::new(bytes, length, &hashes).unwrap();
+            let mut buf = Vec::new();
+            assert!(reader.read_to_end(&mut buf).await.is_ok());
+            assert_eq!(buf, bytes);
+
+            let _ = hashes.insert(
+                HashAlgorithm::Sha3_256,
+                calculate_hash(b"evil bytes", HashAlgorithm::Sha3_256),
+            );
+            let mut reader = SafeReader::new(bytes, length, &hashes).unwrap();
+            assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+        })
+    }
+
+    #[test]
+    fn unknown_hash_algorithms_are_skipped() {
+        let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
+        let (length, mut hashes) = calculate_hashes(bytes, &[HashAlgorithm::Sha256]).unwrap();
+        let _ = hashes.insert(
+            HashAlgorithm::Unknown("md5".into()),
+            HashValue::new(vec![0x00]),
+        );
+
+        let mut reader = SafeReader::new(bytes, length, &hashes).unwrap();
+        assert!(io::Read::read_to_end(&mut reader, &mut Vec::new()).is_ok());
+
+        let _ = hashes.remove(&HashAlgorithm::Sha256);
+        match SafeReader::new(bytes, length, &hashes) {
+            Err(Error::NoSupportedHashAlgorithm) => (),
+            _ => panic!("expected NoSupportedHashAlgorithm"),
+        }
+
+        // Without any hashes, only the length is checked.
+        let mut reader = SafeReader::new(bytes, length, &HashMap::new()).unwrap();
+        assert!(io::Read::read_to_end(&mut reader, &mut Vec::new()).is_ok());
+    }
+}
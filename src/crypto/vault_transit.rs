@@ -0,0 +1,608 @@
+//! Signing with keys held by the [transit secrets engine](https://developer.hashicorp.com/vault/docs/secrets/transit)
+//! of HashiCorp Vault.
+//!
+//! This module requires the `vault-transit` feature. A [`VaultTransitSigner`] signs with a single
+//! version of an `ed25519`, `ecdsa-p256`, `ecdsa-p384`, or `rsa-*` transit key. Each key version
+//! has its own key pair and therefore its own TUF [`KeyId`], so rotating a key in Vault is a TUF
+//! key rotation: list the new version with [`VaultTransitSigner::versions`], add its public key to
+//! the root metadata, and only then build a signer pinned to it.
+//!
+//! The Vault token is renewed automatically shortly before it expires, as long as it is
+//! renewable.
+//!
+//! ```no_run
+//! # use tuf::crypto::vault_transit::VaultTransitSignerBuilder;
+//! # use tuf::interchange::Json;
+//! # use tuf::metadata::SnapshotMetadataBuilder;
+//! # fn main() -> tuf::Result<()> {
+//! let signer = VaultTransitSignerBuilder::from_env("tuf-snapshot")?
+//!     .key_version(3)
+//!     .build()?;
+//!
+//! println!("{:?}", signer.public().key_id());
+//! let snapshot = SnapshotMetadataBuilder::new().signed::<Json>(&signer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use data_encoding::BASE64;
+use parking_lot::Mutex;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::remote;
+use super::{
+    python_tuf_compatibility_keyid_hash_algorithms, KeyId, PublicKey, SignatureScheme,
+    SignatureValue, Signer,
+};
+use crate::error::Error;
+use crate::Result;
+
+/// Tokens are renewed once less than this much of their TTL is left.
+const RENEW_MARGIN: Duration = Duration::from_secs(300);
+
+/// Builder for a [`VaultTransitSigner`].
+pub struct VaultTransitSignerBuilder {
+    address: String,
+    token: String,
+    key_name: String,
+    mount: String,
+    namespace: Option<String>,
+    key_version: Option<u64>,
+    rsa_scheme: SignatureScheme,
+}
+
+impl VaultTransitSignerBuilder {
+    /// Create a new builder for the transit key `key_name` on the Vault server at `address`, e.g.
+    /// `https://vault.example.com:8200`, authenticated with `token`.
+    pub fn new<A, T, K>(address: A, token: T, key_name: K) -> Self
+    where
+        A: Into<String>,
+        T: Into<String>,
+        K: Into<String>,
+    {
+        VaultTransitSignerBuilder {
+            address: address.into(),
+            token: token.into(),
+            key_name: key_name.into(),
+            mount: "transit".into(),
+            namespace: None,
+            key_version: None,
+            rsa_scheme: SignatureScheme::RsaSsaPssSha256,
+        }
+    }
+
+    /// Create a new builder for the transit key `key_name`, reading the server address, token,
+    /// and optional namespace from the `VAULT_ADDR`, `VAULT_TOKEN`, and `VAULT_NAMESPACE`
+    /// environment variables like the Vault CLI does.
+    pub fn from_env<K: Into<String>>(key_name: K) -> Result<Self> {
+        let var = |name: &str| {
+            env::var(name).map_err(|_| {
+                Error::IllegalArgument(format!("Environment variable {} is not set", name))
+            })
+        };
+
+        let builder = Self::new(var("VAULT_ADDR")?, var("VAULT_TOKEN")?, key_name);
+        Ok(match env::var("VAULT_NAMESPACE") {
+            Ok(namespace) => builder.namespace(namespace),
+            Err(_) => builder,
+        })
+    }
+
+    /// Use the transit engine mounted at `mount`. Defaults to `transit`.
+    pub fn mount<M: Into<String>>(mut self, mount: M) -> Self {
+        self.mount = mount.into();
+        self
+    }
+
+    /// Send requests to the Vault Enterprise namespace `namespace`.
+    pub fn namespace<N: Into<String>>(mut self, namespace: N) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Sign with the key version `key_version`. Defaults to the latest version when the signer is
+    /// built. Either way, the signer keeps using that version after the key is rotated.
+    pub fn key_version(mut self, key_version: u64) -> Self {
+        self.key_version = Some(key_version);
+        self
+    }
+
+    /// Set the signature scheme used with RSA keys. Defaults to `RsaSsaPssSha256`. The scheme of
+    /// Ed25519 and ECDSA keys is determined by the key.
+    pub fn rsa_scheme(mut self, scheme: SignatureScheme) -> Self {
+        self.rsa_scheme = scheme;
+        self
+    }
+
+    /// Look up the token and the transit key in Vault and build the `VaultTransitSigner`.
+    pub fn build(self) -> Result<VaultTransitSigner> {
+        match self.rsa_scheme {
+            SignatureScheme::RsaSsaPssSha256
+            | SignatureScheme::RsaSsaPssSha512
+            | SignatureScheme::RsaSsaPkcs1v15Sha256 => (),
+            s => {
+                return Err(Error::IllegalArgument(format!(
+                    "{:?} is not an RSA signature scheme",
+                    s
+                )));
+            }
+        }
+
+        let client = VaultClient {
+            agent: remote::agent(),
+            address: self.address.trim_end_matches('/').into(),
+            namespace: self.namespace,
+            token: Mutex::new(Token {
+                value: self.token,
+                renew_at: None,
+            }),
+        };
+
+        let lookup: VaultResponse<TokenLookup> =
+            client.call("GET", "auth/token/lookup-self", None)?;
+        client.token.lock().renew_at = renew_at(lookup.data.renewable, lookup.data.ttl);
+
+        let mount = self.mount.trim_matches('/').to_string();
+        let key_name = self.key_name;
+        let requested = self.key_version;
+        let versions = fetch_versions(&client, &mount, &key_name, &self.rsa_scheme)?;
+        let (key_version, public) = match requested {
+            Some(version) => versions.into_iter().find(|(v, _)| *v == version),
+            None => versions.into_iter().next_back(),
+        }
+        .ok_or_else(|| {
+            Error::IllegalArgument(format!(
+                "Transit key {} has no version {}",
+                key_name,
+                requested.unwrap_or_default()
+            ))
+        })?;
+
+        Ok(VaultTransitSigner {
+            client,
+            mount,
+            key_name,
+            rsa_scheme: self.rsa_scheme,
+            key_version,
+            public,
+        })
+    }
+}
+
+impl fmt::Debug for VaultTransitSignerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VaultTransitSignerBuilder")
+            .field("address", &self.address)
+            .field("key_name", &self.key_name)
+            .field("mount", &self.mount)
+            .field("namespace", &self.namespace)
+            .field("key_version", &self.key_version)
+            .field("rsa_scheme", &self.rsa_scheme)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A `Signer` for a version of a key in Vault's transit secrets engine.
+pub struct VaultTransitSigner {
+    client: VaultClient,
+    mount: String,
+    key_name: String,
+    rsa_scheme: SignatureScheme,
+    key_version: u64,
+    public: PublicKey,
+}
+
+impl VaultTransitSigner {
+    /// An immutable reference to the public key.
+    pub fn public(&self) -> &PublicKey {
+        &self.public
+    }
+
+    /// The version of the transit key this signer signs with.
+    pub fn key_version(&self) -> u64 {
+        self.key_version
+    }
+
+    /// Fetch the public keys of all the versions of the transit key that can still be used for
+    /// signing, in ascending order of version.
+    pub fn versions(&self) -> Result<BTreeMap<u64, PublicKey>> {
+        fetch_versions(&self.client, &self.mount, &self.key_name, &self.rsa_scheme)
+    }
+
+    /// Renew the Vault token now, rather than waiting until it is about to expire.
+    pub fn renew_token(&self) -> Result<()> {
+        self.client.renew_token()
+    }
+}
+
+impl Signer for VaultTransitSigner {
+    fn key_id(&self) -> &KeyId {
+        self.public().key_id()
+    }
+
+    fn scheme(&self) -> &SignatureScheme {
+        self.public().scheme()
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<SignatureValue> {
+        let scheme = self.public().scheme();
+        let (hash_algorithm, signature_algorithm, salt_length) = match scheme {
+            SignatureScheme::Ed25519 => (None, None, None),
+            SignatureScheme::EcdsaP256Sha256 => (Some("sha2-256"), None, None),
+            SignatureScheme::EcdsaP384Sha384 => (Some("sha2-384"), None, None),
+            SignatureScheme::RsaSsaPssSha256 => (Some("sha2-256"), Some("pss"), Some("hash")),
+            SignatureScheme::RsaSsaPssSha512 => (Some("sha2-512"), Some("pss"), Some("hash")),
+            SignatureScheme::RsaSsaPkcs1v15Sha256 => (Some("sha2-256"), Some("pkcs1v15"), None),
+            s => {
+                return Err(Error::IllegalArgument(format!(
+                    "Cannot sign with {:?} in Vault",
+                    s
+                )));
+            }
+        };
+
+        let request = SignRequest {
+            input: BASE64.encode(msg),
+            key_version: self.key_version,
+            hash_algorithm,
+            signature_algorithm,
+            salt_length,
+        };
+        let response: VaultResponse<SignResponse> = self.client.call(
+            "POST",
+            &format!("{}/sign/{}", self.mount, self.key_name),
+            Some(serde_json::to_value(&request)?),
+        )?;
+
+        // Signatures look like `vault:v{version}:{base64}`.
+        let prefix = format!("vault:v{}:", self.key_version);
+        let sig = response
+            .data
+            .signature
+            .strip_prefix(&prefix)
+            .ok_or_else(|| {
+                Error::Encoding(format!("Transit signature does not start with {}", prefix))
+            })?;
+        let sig = BASE64
+            .decode(sig.as_bytes())
+            .map_err(|e| Error::Encoding(format!("Transit signature: {:?}", e)))?;
+        Ok(SignatureValue::new(sig))
+    }
+}
+
+impl fmt::Debug for VaultTransitSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VaultTransitSigner")
+            .field("address", &self.client.address)
+            .field("mount", &self.mount)
+            .field("key_name", &self.key_name)
+            .field("key_version", &self.key_version)
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+struct VaultClient {
+    agent: ureq::Agent,
+    address: String,
+    namespace: Option<String>,
+    token: Mutex<Token>,
+}
+
+struct Token {
+    value: String,
+    /// When to renew the token, or `None` if it does not expire or cannot be renewed.
+    renew_at: Option<Instant>,
+}
+
+impl VaultClient {
+    /// Call the Vault API at `/v1/{path}`, renewing the token first if it is about to expire.
+    fn call<T>(&self, method: &str, path: &str, body: Option<serde_json::Value>) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let renew_at = self.token.lock().renew_at;
+        if matches!(renew_at, Some(renew_at) if Instant::now() >= renew_at) {
+            self.renew_token()?;
+        }
+
+        let token = self.token.lock().value.clone();
+        self.send(method, path, &token, body)
+    }
+
+    fn renew_token(&self) -> Result<()> {
+        let mut token = self.token.lock();
+        let response: RenewResponse =
+            self.send("POST", "auth/token/renew-self", &token.value, None)?;
+        token.value = response.auth.client_token;
+        token.renew_at = renew_at(response.auth.renewable, response.auth.lease_duration);
+        Ok(())
+    }
+
+    fn send<T>(
+        &self,
+        method: &str,
+        path: &str,
+        token: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut request = self
+            .agent
+            .request(method, &format!("{}/v1/{}", self.address, path))
+            .set("X-Vault-Token", token);
+        if let Some(ref namespace) = self.namespace {
+            request = request.set("X-Vault-Namespace", namespace);
+        }
+        let response = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+        remote::read_json("Vault", response)
+    }
+}
+
+/// Fetch the public keys of the versions of a transit key that can still be used for signing.
+fn fetch_versions(
+    client: &VaultClient,
+    mount: &str,
+    key_name: &str,
+    rsa_scheme: &SignatureScheme,
+) -> Result<BTreeMap<u64, PublicKey>> {
+    let response: VaultResponse<TransitKey> =
+        client.call("GET", &format!("{}/keys/{}", mount, key_name), None)?;
+    let key = response.data;
+
+    let scheme = match key.typ.as_str() {
+        "ed25519" => SignatureScheme::Ed25519,
+        "ecdsa-p256" => SignatureScheme::EcdsaP256Sha256,
+        "ecdsa-p384" => SignatureScheme::EcdsaP384Sha384,
+        typ if typ.starts_with("rsa-") => rsa_scheme.clone(),
+        typ => {
+            return Err(Error::IllegalArgument(format!(
+                "Transit key {} has type {}, which cannot sign TUF metadata",
+                key_name, typ
+            )));
+        }
+    };
+
+    let mut versions = BTreeMap::new();
+    for (version, value) in key.keys {
+        let version = version
+            .parse::<u64>()
+            .map_err(|_| Error::Encoding(format!("Bad transit key version {:?}", version)))?;
+        if version < key.min_available_version.unwrap_or(0) {
+            continue;
+        }
+
+        let public = match scheme {
+            SignatureScheme::Ed25519 => {
+                let raw = BASE64
+                    .decode(value.public_key.as_bytes())
+                    .map_err(|e| Error::Encoding(format!("Transit public key: {:?}", e)))?;
+                PublicKey::from_ed25519_with_keyid_hash_algorithms(
+                    raw,
+                    python_tuf_compatibility_keyid_hash_algorithms(),
+                )?
+            }
+            ref scheme => PublicKey::from_pem(&value.public_key, scheme.clone())?,
+        };
+        versions.insert(version, public);
+    }
+    Ok(versions)
+}
+
+/// When to renew a token that has `ttl` seconds left.
+fn renew_at(renewable: bool, ttl: u64) -> Option<Instant> {
+    if !renewable || ttl == 0 {
+        return None;
+    }
+    Some(Instant::now() + Duration::from_secs(ttl).saturating_sub(RENEW_MARGIN))
+}
+
+#[derive(Deserialize)]
+struct VaultResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct TokenLookup {
+    #[serde(default)]
+    renewable: bool,
+    ttl: u64,
+}
+
+#[derive(Deserialize)]
+struct RenewResponse {
+    auth: RenewAuth,
+}
+
+#[derive(Deserialize)]
+struct RenewAuth {
+    client_token: String,
+    lease_duration: u64,
+    renewable: bool,
+}
+
+#[derive(Deserialize)]
+struct TransitKey {
+    #[serde(rename = "type")]
+    typ: String,
+    keys: HashMap<String, TransitKeyVersion>,
+    min_available_version: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TransitKeyVersion {
+    public_key: String,
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    input: String,
+    key_version: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash_algorithm: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature_algorithm: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    salt_length: Option<&'static str>,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::remote::test_server::TestServer;
+    use crate::crypto::{self, PrivateKey};
+    use serde_json::json;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+    const ED25519_2_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-2.pk8.der");
+
+    fn transit_key(keys: &[&PrivateKey]) -> serde_json::Value {
+        let versions = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                (
+                    (i + 1).to_string(),
+                    json!({ "public_key": BASE64.encode(key.public().as_bytes()) }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+        json!({
+            "data": {
+                "type": "ed25519",
+                "latest_version": keys.len(),
+                "min_available_version": 0,
+                "keys": versions,
+            },
+        })
+    }
+
+    #[test]
+    fn sign_with_pinned_version() {
+        let msg = b"hello vault";
+        let v1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let v2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let sig = v1.sign(msg).unwrap();
+
+        let server = TestServer::new(vec![
+            (200, json!({ "data": { "renewable": true, "ttl": 3600 } })),
+            (200, transit_key(&[&v1, &v2])),
+            (
+                200,
+                json!({
+                    "data": {
+                        "signature": format!("vault:v1:{}", BASE64.encode(sig.value().as_bytes())),
+                        "key_version": 1,
+                    },
+                }),
+            ),
+        ]);
+
+        let signer = VaultTransitSignerBuilder::new(server.url(), "s.t0k3n", "tuf")
+            .namespace("ns")
+            .key_version(1)
+            .build()
+            .unwrap();
+        assert_eq!(signer.key_version(), 1);
+        assert_eq!(signer.key_id(), v1.key_id());
+
+        let vault_sig = crypto::sign_with(&signer, msg).unwrap();
+        signer.public().verify(msg, &vault_sig).unwrap();
+
+        let requests = server.finish();
+        assert_eq!(requests[0].path, "/v1/auth/token/lookup-self");
+        assert_eq!(requests[0].header("x-vault-token"), Some("s.t0k3n"));
+        assert_eq!(requests[0].header("x-vault-namespace"), Some("ns"));
+        assert_eq!(requests[1].path, "/v1/transit/keys/tuf");
+        assert_eq!(requests[2].path, "/v1/transit/sign/tuf");
+        assert_eq!(
+            requests[2].json(),
+            json!({ "input": BASE64.encode(msg), "key_version": 1 })
+        );
+    }
+
+    #[test]
+    fn defaults_to_latest_version() {
+        let v1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let v2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+
+        let server = TestServer::new(vec![
+            (200, json!({ "data": { "renewable": false, "ttl": 0 } })),
+            (200, transit_key(&[&v1, &v2])),
+            (200, transit_key(&[&v1, &v2])),
+        ]);
+
+        let signer = VaultTransitSignerBuilder::new(server.url(), "s.t0k3n", "tuf")
+            .build()
+            .unwrap();
+        assert_eq!(signer.key_version(), 2);
+        assert_eq!(signer.key_id(), v2.key_id());
+
+        let versions = signer.versions().unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[&1].key_id(), v1.key_id());
+        assert_eq!(versions[&2].key_id(), v2.key_id());
+        server.finish();
+    }
+
+    #[test]
+    fn renews_token_before_it_expires() {
+        let v1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+
+        let server = TestServer::new(vec![
+            // Less than the renewal margin is left, so the token is renewed right away.
+            (200, json!({ "data": { "renewable": true, "ttl": 60 } })),
+            (
+                200,
+                json!({
+                    "auth": {
+                        "client_token": "s.r3n3w3d",
+                        "lease_duration": 3600,
+                        "renewable": true,
+                    },
+                }),
+            ),
+            (200, transit_key(&[&v1])),
+        ]);
+
+        VaultTransitSignerBuilder::new(server.url(), "s.t0k3n", "tuf")
+            .build()
+            .unwrap();
+
+        let requests = server.finish();
+        assert_eq!(requests[1].path, "/v1/auth/token/renew-self");
+        assert_eq!(requests[1].header("x-vault-token"), Some("s.t0k3n"));
+        assert_eq!(requests[2].header("x-vault-token"), Some("s.r3n3w3d"));
+    }
+
+    #[test]
+    fn missing_version_is_rejected() {
+        let v1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+
+        let server = TestServer::new(vec![
+            (200, json!({ "data": { "renewable": false, "ttl": 0 } })),
+            (200, transit_key(&[&v1])),
+        ]);
+
+        let err = VaultTransitSignerBuilder::new(server.url(), "s.t0k3n", "tuf")
+            .key_version(7)
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::IllegalArgument(_)));
+        server.finish();
+    }
+}
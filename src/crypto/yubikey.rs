@@ -0,0 +1,161 @@
+//! Signing with keys stored in the PIV slots of a YubiKey.
+//!
+//! This module requires the `yubikey` feature. It talks to the YubiKey through Yubico's PKCS#11
+//! module, `libykcs11`, which is installed with
+//! [yubico-piv-tool](https://developers.yubico.com/yubico-piv-tool/). Keys are generated and
+//! loaded with the usual PIV tooling; this crate only ever asks the YubiKey to sign, so it is
+//! suitable for signing root metadata on an air-gapped machine.
+//!
+//! The user is asked for their PIN, and told to touch the YubiKey, through a [`PivPrompt`].
+//!
+//! ```no_run
+//! # use tuf::crypto::yubikey::{PivPrompt, PivSlot, YubiKey};
+//! # use tuf::interchange::Json;
+//! # use tuf::metadata::RootMetadataBuilder;
+//! struct Terminal;
+//!
+//! impl PivPrompt for Terminal {
+//!     fn pin(&self) -> tuf::Result<String> {
+//!         // Read the PIN from the terminal without echoing it.
+//! #       unimplemented!()
+//!     }
+//!
+//!     fn touch(&self) {
+//!         eprintln!("Touch your YubiKey to sign");
+//!     }
+//! }
+//!
+//! # fn main() -> tuf::Result<()> {
+//! let yubikey = YubiKey::open("/usr/lib/libykcs11.so", 12345678, Terminal)?;
+//! let signer = yubikey.signer(PivSlot::Signature)?;
+//! # let root = RootMetadataBuilder::new();
+//! let root = root.signed::<Json>(&signer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use cryptoki::object::{Attribute, AttributeType, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use std::fmt;
+use std::path::Path;
+
+use super::pkcs11::{Pkcs11Signer, Pkcs11Slot, Pkcs11Token, SignHook};
+use crate::error::Error;
+use crate::Result;
+
+/// The PIV slots that hold private keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PivSlot {
+    /// Slot 9a, PIV Authentication.
+    Authentication,
+    /// Slot 9c, Digital Signature. This is the slot intended for signing, and by default it
+    /// requires the PIN for every signature.
+    Signature,
+    /// Slot 9d, Key Management.
+    KeyManagement,
+    /// Slot 9e, Card Authentication.
+    CardAuthentication,
+}
+
+impl PivSlot {
+    /// The `CKA_ID` that `libykcs11` gives the key in this slot.
+    fn object_id(self) -> u8 {
+        match self {
+            PivSlot::Authentication => 1,
+            PivSlot::Signature => 2,
+            PivSlot::KeyManagement => 3,
+            PivSlot::CardAuthentication => 4,
+        }
+    }
+}
+
+/// Interaction with the person holding the YubiKey.
+pub trait PivPrompt {
+    /// Ask for the PIV PIN. This is called when opening the YubiKey, and again before every
+    /// signature made with a key whose PIN policy is "always".
+    fn pin(&self) -> Result<String>;
+
+    /// Called right before the YubiKey is asked to sign. If the key's touch policy requires it,
+    /// the YubiKey blinks until it is touched, so this should tell the user to touch it.
+    fn touch(&self) {}
+}
+
+/// A YubiKey that is logged in to its PIV application.
+pub struct YubiKey<P> {
+    token: Pkcs11Token,
+    prompt: P,
+}
+
+impl<P: PivPrompt> YubiKey<P> {
+    /// Load `libykcs11` from `module`, and log in to the YubiKey with the serial number `serial`
+    /// with a PIN obtained from `prompt`.
+    pub fn open<M: AsRef<Path>>(module: M, serial: u32, prompt: P) -> Result<Self> {
+        let pin = prompt.pin()?;
+        let token = Pkcs11Token::open(module, token_slot(serial), &pin)?;
+        Ok(YubiKey { token, prompt })
+    }
+
+    /// Create a `Signer` for the key in `slot`.
+    pub fn signer(&self, slot: PivSlot) -> Result<Pkcs11Signer<'_>> {
+        self.token
+            .signer_for_object_id(&[slot.object_id()], self)
+            .map_err(|err| match err {
+                Error::NotFound => {
+                    Error::IllegalArgument(format!("The YubiKey has no key in slot {:?}", slot))
+                }
+                err => err,
+            })
+    }
+}
+
+impl<P: PivPrompt> SignHook for YubiKey<P> {
+    fn before_sign(&self, session: &Session, private: ObjectHandle) -> Result<()> {
+        let always_authenticate = session
+            .get_attributes(private, &[AttributeType::AlwaysAuthenticate])?
+            .into_iter()
+            .any(|attribute| matches!(attribute, Attribute::AlwaysAuthenticate(true)));
+
+        if always_authenticate {
+            let pin = AuthPin::from(self.prompt.pin()?);
+            session.login(UserType::ContextSpecific, Some(&pin))?;
+        }
+
+        self.prompt.touch();
+        Ok(())
+    }
+}
+
+impl<P> fmt::Debug for YubiKey<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("YubiKey")
+            .field("token", &self.token)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `libykcs11` labels each YubiKey's token with its serial number.
+fn token_slot(serial: u32) -> Pkcs11Slot {
+    Pkcs11Slot::TokenLabel(format!("YubiKey PIV #{}", serial))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn piv_slot_object_ids() {
+        assert_eq!(PivSlot::Authentication.object_id(), 1);
+        assert_eq!(PivSlot::Signature.object_id(), 2);
+        assert_eq!(PivSlot::KeyManagement.object_id(), 3);
+        assert_eq!(PivSlot::CardAuthentication.object_id(), 4);
+    }
+
+    #[test]
+    fn token_slot_uses_serial() {
+        assert_eq!(
+            token_slot(12345678),
+            Pkcs11Slot::TokenLabel("YubiKey PIV #12345678".into())
+        );
+    }
+}
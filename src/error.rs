@@ -5,15 +5,37 @@ use std::io;
 use std::path::Path;
 use thiserror::Error;
 
-use crate::metadata::Role;
+use crate::crypto::KeyId;
+use crate::metadata::{MetadataPath, Role, TargetPath};
 
 /// Error type for all TUF related errors.
-#[derive(Error, Debug, PartialEq, Eq)]
+#[derive(Clone, Error, Debug, PartialEq, Eq)]
 pub enum Error {
+    /// A key ID prefix matched more than one key.
+    #[error("key ID prefix {prefix:?} is ambiguous: {} keys match", .candidates.len())]
+    AmbiguousKeyId {
+        /// The prefix that was looked up.
+        prefix: String,
+        /// The IDs of all the keys that matched the prefix.
+        candidates: Vec<KeyId>,
+    },
+
     /// The metadata had a bad signature.
     #[error("bad signature")]
     BadSignature,
 
+    /// Some signatures in a batch were bad.
+    #[error("bad signatures at batch indices {indices:?}")]
+    BadSignatures {
+        /// The positions in the batch of the signatures that failed to verify.
+        indices: Vec<usize>,
+    },
+
+    /// The operation was aborted through a
+    /// [`CancellationToken`](crate::client::CancellationToken).
+    #[error("cancelled")]
+    Cancelled,
+
     /// There was a problem encoding or decoding.
     #[error("encoding: {0}")]
     Encoding(String),
@@ -26,6 +48,25 @@ pub enum Error {
     #[error("illegal argument: {0}")]
     IllegalArgument(String),
 
+    /// Downloaded metadata was longer than allowed, either by the length listed for it in trusted
+    /// metadata or by the client's configured maximum for its role.
+    #[error("metadata {path} exceeded the maximum length of {max_length} bytes")]
+    MetadataLengthExceeded {
+        /// The path of the metadata.
+        path: MetadataPath,
+        /// The maximum length that was exceeded.
+        max_length: usize,
+    },
+
+    /// The metadata exceeded a size, nesting depth, or string length limit while being parsed.
+    #[error("metadata too large: {0}")]
+    MetadataTooLarge(String),
+
+    /// Every mirror failed to serve the metadata or target. Holds the name of each mirror that
+    /// was tried, in order, and the error it failed with.
+    #[error("every mirror failed: {}", format_mirror_failures(.0))]
+    MirrorsFailed(Vec<(String, Error)>),
+
     /// The metadata was missing, so an operation could not be completed.
     #[error("missing {0} metadata")]
     MissingMetadata(Role),
@@ -47,6 +88,16 @@ pub enum Error {
     #[error("programming: {0}")]
     Programming(String),
 
+    /// A [`TargetVerifier`](crate::client::TargetVerifier) rejected a target that otherwise passed
+    /// TUF verification.
+    #[error("target {} rejected: {reason}", .path.value())]
+    TargetRejected {
+        /// The path of the rejected target.
+        path: TargetPath,
+        /// Why the target was rejected.
+        reason: String,
+    },
+
     /// The target is unavailable. This may mean it is either not in the metadata or the metadata
     /// chain to the target cannot be fully verified.
     #[error("target unavailable")]
@@ -71,6 +122,14 @@ impl From<serde_json::error::Error> for Error {
     }
 }
 
+fn format_mirror_failures(failures: &[(String, Error)]) -> String {
+    failures
+        .iter()
+        .map(|(mirror, err)| format!("{}: {}", mirror, err))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 impl Error {
     /// Helper to include the path that causd the error for FS I/O errors.
     pub fn from_io(err: &io::Error, path: &Path) -> Error {
@@ -80,6 +139,12 @@ impl Error {
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
+        // Errors of this crate that were passed through an I/O interface, such as
+        // `Error::Cancelled`, are unwrapped.
+        if let Some(err) = err.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            return err.clone();
+        }
+
         match err.kind() {
             std::io::ErrorKind::NotFound => Error::NotFound,
             _ => Error::Opaque(format!("IO: {:?}", err)),
@@ -111,6 +176,13 @@ impl From<derp::Error> for Error {
     }
 }
 
+#[cfg(feature = "pkcs11")]
+impl From<cryptoki::error::Error> for Error {
+    fn from(err: cryptoki::error::Error) -> Error {
+        Error::Opaque(format!("PKCS#11: {:?}", err))
+    }
+}
+
 impl From<tempfile::PersistError> for Error {
     fn from(err: tempfile::PersistError) -> Error {
         Error::Opaque(format!("Error persisting temp file: {:?}", err))
@@ -130,4 +202,16 @@ mod tests {
         let err = Error::from(io::Error::from(std::io::ErrorKind::PermissionDenied));
         assert_eq!(err.to_string(), "opaque: IO: Kind(PermissionDenied)");
     }
+
+    #[test]
+    fn verify_mirrors_failed_display_string() {
+        let err = Error::MirrorsFailed(vec![
+            ("primary".into(), Error::Opaque("timed out".into())),
+            ("backup".into(), Error::NotFound),
+        ]);
+        assert_eq!(
+            err.to_string(),
+            "every mirror failed: primary: opaque: timed out; backup: not found"
+        );
+    }
 }
@@ -0,0 +1,206 @@
+use crate::error::Error;
+use crate::Result;
+
+/// Bounds on the JSON documents accepted by [Json](super::Json) when deserializing.
+///
+/// The document is scanned against these limits before it is handed to `serde_json`, so an
+/// oversized or deeply nested document is rejected with
+/// [Error::MetadataTooLarge](crate::Error::MetadataTooLarge) without building any values.
+///
+/// ```
+/// # use tuf::interchange::{Json, JsonLimits};
+/// # use tuf::Error;
+/// # use std::collections::HashMap;
+/// let limits = JsonLimits::default().max_size(Some(16));
+/// let jsn: &[u8] = br#"{"foo": "bar", "baz": "quux"}"#;
+/// let res: Result<HashMap<String, String>, _> = Json::from_slice_with_limits(jsn, &limits);
+/// assert!(matches!(res, Err(Error::MetadataTooLarge(_))));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonLimits {
+    max_size: Option<usize>,
+    max_depth: Option<usize>,
+    max_string_length: Option<usize>,
+}
+
+impl JsonLimits {
+    /// The default maximum size of a document in bytes. This matches the default length limit
+    /// of targets metadata in the client's [Config](crate::client::Config).
+    pub const DEFAULT_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+    /// The default maximum nesting depth of arrays and objects. TUF metadata nests no more than a
+    /// handful of levels, so this leaves plenty of room for custom fields.
+    pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+    /// The default maximum length of a single string or object key, in bytes.
+    pub const DEFAULT_MAX_STRING_LENGTH: usize = 1024 * 1024;
+
+    /// Limits that accept any document `serde_json` can parse.
+    pub fn unlimited() -> Self {
+        JsonLimits {
+            max_size: None,
+            max_depth: None,
+            max_string_length: None,
+        }
+    }
+
+    /// Set the maximum size of a document in bytes.
+    pub fn max_size(mut self, max: Option<usize>) -> Self {
+        self.max_size = max;
+        self
+    }
+
+    /// Set the maximum nesting depth of arrays and objects.
+    pub fn max_depth(mut self, max: Option<usize>) -> Self {
+        self.max_depth = max;
+        self
+    }
+
+    /// Set the maximum length in bytes of a single string or object key, as it appears (escaped)
+    /// in the document.
+    pub fn max_string_length(mut self, max: Option<usize>) -> Self {
+        self.max_string_length = max;
+        self
+    }
+
+    /// The maximum size of a document in bytes.
+    pub fn size_limit(&self) -> Option<usize> {
+        self.max_size
+    }
+
+    /// The maximum nesting depth of arrays and objects.
+    pub fn depth_limit(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// The maximum length in bytes of a single string or object key.
+    pub fn string_length_limit(&self) -> Option<usize> {
+        self.max_string_length
+    }
+
+    /// Check `bytes` against these limits. Malformed JSON is not reported here and is left for
+    /// the parser to reject.
+    pub(crate) fn check(&self, bytes: &[u8]) -> Result<()> {
+        if let Some(max) = self.max_size {
+            if bytes.len() > max {
+                return Err(Error::MetadataTooLarge(format!(
+                    "document is {} bytes, more than the limit of {}",
+                    bytes.len(),
+                    max
+                )));
+            }
+        }
+
+        if self.max_depth.is_none() && self.max_string_length.is_none() {
+            return Ok(());
+        }
+
+        let mut depth = 0usize;
+        let mut string_len: Option<usize> = None;
+        let mut escaped = false;
+
+        for &b in bytes {
+            if let Some(len) = string_len.as_mut() {
+                if escaped {
+                    escaped = false;
+                } else if b == b'\\' {
+                    escaped = true;
+                } else if b == b'"' {
+                    string_len = None;
+                    continue;
+                }
+
+                *len += 1;
+                if let Some(max) = self.max_string_length {
+                    if *len > max {
+                        return Err(Error::MetadataTooLarge(format!(
+                            "string is longer than the limit of {} bytes",
+                            max
+                        )));
+                    }
+                }
+                continue;
+            }
+
+            match b {
+                b'"' => string_len = Some(0),
+                b'[' | b'{' => {
+                    depth += 1;
+                    if let Some(max) = self.max_depth {
+                        if depth > max {
+                            return Err(Error::MetadataTooLarge(format!(
+                                "document is nested deeper than the limit of {}",
+                                max
+                            )));
+                        }
+                    }
+                }
+                b']' | b'}' => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for JsonLimits {
+    /// Documents of at most [JsonLimits::DEFAULT_MAX_SIZE] bytes, a nesting depth of
+    /// [JsonLimits::DEFAULT_MAX_DEPTH], and strings of at most
+    /// [JsonLimits::DEFAULT_MAX_STRING_LENGTH] bytes.
+    fn default() -> Self {
+        JsonLimits {
+            max_size: Some(Self::DEFAULT_MAX_SIZE),
+            max_depth: Some(Self::DEFAULT_MAX_DEPTH),
+            max_string_length: Some(Self::DEFAULT_MAX_STRING_LENGTH),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use matches::assert_matches;
+
+    #[test]
+    fn check_size() {
+        let limits = JsonLimits::unlimited().max_size(Some(4));
+        limits.check(b"[12]").unwrap();
+        assert_matches!(limits.check(b"[123]"), Err(Error::MetadataTooLarge(_)));
+    }
+
+    #[test]
+    fn check_depth() {
+        let limits = JsonLimits::unlimited().max_depth(Some(2));
+        limits.check(br#"{"a": [1, 2], "b": {}}"#).unwrap();
+        limits.check(br#"[[], [], {"a": 1}]"#).unwrap();
+        assert_matches!(
+            limits.check(br#"{"a": [{}]}"#),
+            Err(Error::MetadataTooLarge(_))
+        );
+
+        // Brackets inside strings don't count.
+        limits.check(br#"{"a": "[[[{{{"}"#).unwrap();
+    }
+
+    #[test]
+    fn check_string_length() {
+        let limits = JsonLimits::unlimited().max_string_length(Some(4));
+        limits.check(br#"{"abcd": "wxyz"}"#).unwrap();
+        limits.check(br#"["a\"b"]"#).unwrap();
+        assert_matches!(
+            limits.check(br#"{"abcde": 1}"#),
+            Err(Error::MetadataTooLarge(_))
+        );
+        assert_matches!(
+            limits.check(br#"["ab\"cd"]"#),
+            Err(Error::MetadataTooLarge(_))
+        );
+
+        // An escaped quote does not end the string.
+        assert_matches!(
+            limits.check(br#"["\"\"\"", 1]"#),
+            Err(Error::MetadataTooLarge(_))
+        );
+    }
+}
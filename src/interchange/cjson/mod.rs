@@ -3,19 +3,37 @@ use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_json;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::io::{Read, Write};
 
 use crate::error::Error;
 use crate::interchange::DataInterchange;
+use crate::metadata::{
+    MetadataDescription, TargetDescription, TargetsMetadataHeader, VirtualTargetPath,
+};
 use crate::Result;
 
+pub(crate) mod limits;
 pub(crate) mod pretty;
 pub(crate) mod shims;
+mod stream;
 
+pub use limits::JsonLimits;
 pub use pretty::JsonPretty;
 
 /// JSON data interchange.
 ///
+/// # Canonical Form
+///
+/// Signatures and key IDs are computed over the [OLPC canonical
+/// JSON](http://wiki.laptop.org/go/Canonical_JSON) encoding of the metadata, as required by the
+/// TUF specification and produced by python-tuf:
+///
+/// - object keys are sorted by their Unicode code points,
+/// - no insignificant whitespace is emitted,
+/// - strings escape only `"` and `\` and contain every other character verbatim,
+/// - numbers must be integers.
+///
 /// # Schema
 ///
 /// This doesn't use JSON Schema because that specification language is rage inducing. Here's
@@ -292,6 +310,8 @@ impl DataInterchange for Json {
         Ok(())
     }
 
+    /// Documents are checked against [JsonLimits::default].
+    ///
     /// ```
     /// # use tuf::interchange::{DataInterchange, Json};
     /// # use std::collections::HashMap;
@@ -303,9 +323,11 @@ impl DataInterchange for Json {
         R: Read,
         T: DeserializeOwned,
     {
-        Ok(serde_json::from_reader(rdr)?)
+        Self::from_reader_with_limits(rdr, &JsonLimits::default())
     }
 
+    /// Documents are checked against [JsonLimits::default].
+    ///
     /// ```
     /// # use tuf::interchange::{DataInterchange, Json};
     /// # use std::collections::HashMap;
@@ -316,6 +338,101 @@ impl DataInterchange for Json {
     where
         T: DeserializeOwned,
     {
+        Self::from_slice_with_limits(slice, &JsonLimits::default())
+    }
+
+    fn from_slice_with_limits<T>(slice: &[u8], limits: &JsonLimits) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        Json::from_slice_with_limits(slice, limits)
+    }
+}
+
+impl Json {
+    /// Read a struct from a stream, rejecting documents that exceed `limits`. At most one byte
+    /// more than the size limit is read from `rdr`.
+    pub fn from_reader_with_limits<R, T>(mut rdr: R, limits: &JsonLimits) -> Result<T>
+    where
+        R: Read,
+        T: DeserializeOwned,
+    {
+        let mut buf = Vec::new();
+        match limits.size_limit() {
+            Some(max) => {
+                let max = u64::try_from(max).unwrap_or(u64::MAX);
+                let _ = rdr
+                    .by_ref()
+                    .take(max.saturating_add(1))
+                    .read_to_end(&mut buf)?;
+            }
+            None => {
+                let _ = rdr.read_to_end(&mut buf)?;
+            }
+        }
+        Self::from_slice_with_limits(&buf, limits)
+    }
+
+    /// Incrementally parse signed targets metadata from `rdr`, calling `f` with each target as it
+    /// is read instead of collecting them into a map, so peak memory does not grow with the number
+    /// of targets.
+    ///
+    /// Signatures are not checked. Instead the bytes are authenticated against `description`,
+    /// which must come from trusted snapshot metadata: the hash is computed while parsing and
+    /// reading stops as soon as the document exceeds the described length. Targets passed to `f`
+    /// are therefore only trustworthy once this returns `Ok`, and the caller is responsible for
+    /// checking the returned expiration.
+    ///
+    /// ```
+    /// # use tuf::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
+    /// # use tuf::interchange::Json;
+    /// # use tuf::metadata::{
+    /// #     MetadataDescription, TargetDescription, TargetsMetadataBuilder, VirtualTargetPath,
+    /// # };
+    /// # let key = PrivateKey::from_pkcs8(
+    /// #     include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der"),
+    /// #     SignatureScheme::Ed25519,
+    /// # ).unwrap();
+    /// let targets = TargetsMetadataBuilder::new()
+    ///     .insert_target_description(
+    ///         VirtualTargetPath::new("foo".into()).unwrap(),
+    ///         TargetDescription::from_reader(&b"foo"[..], &[HashAlgorithm::Sha256]).unwrap(),
+    ///     )
+    ///     .signed::<Json>(&key)
+    ///     .unwrap()
+    ///     .to_raw()
+    ///     .unwrap();
+    /// let description =
+    ///     MetadataDescription::from_reader(targets.as_bytes(), 1, &[HashAlgorithm::Sha256])
+    ///         .unwrap();
+    ///
+    /// let mut paths = Vec::new();
+    /// let header = Json::stream_targets(targets.as_bytes(), &description, |path, _| {
+    ///     paths.push(path);
+    ///     Ok(())
+    /// })
+    /// .unwrap();
+    /// assert_eq!(header.version(), 1);
+    /// assert_eq!(paths, vec![VirtualTargetPath::new("foo".into()).unwrap()]);
+    /// ```
+    pub fn stream_targets<R, F>(
+        rdr: R,
+        description: &MetadataDescription,
+        f: F,
+    ) -> Result<TargetsMetadataHeader>
+    where
+        R: Read,
+        F: FnMut(VirtualTargetPath, TargetDescription) -> Result<()>,
+    {
+        stream::stream_targets(rdr, description, f)
+    }
+
+    /// Read a struct from a slice of bytes, rejecting documents that exceed `limits`.
+    pub fn from_slice_with_limits<T>(slice: &[u8], limits: &JsonLimits) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        limits.check(slice)?;
         Ok(serde_json::from_slice(slice)?)
     }
 }
@@ -358,10 +475,7 @@ impl Value {
                 .map(|_| ())
                 .map_err(|err| format!("Write error: {}", err)),
             Value::String(ref s) => {
-                // this mess is abusing serde_json to get json escaping
-                let s = serde_json::Value::String(s.clone());
-                let s = serde_json::to_string(&s).map_err(|e| format!("{:?}", e))?;
-                buf.extend(s.as_bytes());
+                write_str(buf, s);
                 Ok(())
             }
             Value::Array(ref arr) => {
@@ -386,11 +500,7 @@ impl Value {
                     }
                     first = false;
 
-                    // this mess is abusing serde_json to get json escaping
-                    let k = serde_json::Value::String(k.clone());
-                    let k = serde_json::to_string(&k).map_err(|e| format!("{:?}", e))?;
-                    buf.extend(k.as_bytes());
-
+                    write_str(buf, k);
                     buf.push(b':');
                     v.write(&mut buf)?;
                 }
@@ -401,6 +511,20 @@ impl Value {
     }
 }
 
+/// Write a string as it appears in OLPC canonical JSON. Unlike regular JSON, only `"` and `\` are
+/// escaped and every other character, including control characters, is written verbatim. This
+/// matches the `encode_canonical` function used by python-tuf.
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    buf.push(b'"');
+    for b in s.bytes() {
+        if b == b'"' || b == b'\\' {
+            buf.push(b'\\');
+        }
+        buf.push(b);
+    }
+    buf.push(b'"');
+}
+
 enum Number {
     I64(i64),
     U64(u64),
@@ -437,6 +561,8 @@ fn convert(jsn: &serde_json::Value) -> std::result::Result<Value, String> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use matches::assert_matches;
+    use std::str;
 
     #[test]
     fn write_str() {
@@ -469,6 +595,77 @@ mod test {
         let jsn = Value::Object(map);
         let mut out = Vec::new();
         jsn.write(&mut out).unwrap();
-        assert_eq!(&out, &b"{\"lol\":[\"haha\",\"new\\nline\"]}");
+        assert_eq!(&out, &b"{\"lol\":[\"haha\",\"new\nline\"]}");
+    }
+
+    // Expected outputs were generated with python-tuf's
+    // `securesystemslib.formats.encode_canonical`.
+    #[test]
+    fn canonicalize_matches_python_tuf() {
+        let jsn = serde_json::json!({
+            "z": 1,
+            "a": [-1, 0, 18446744073709551615u64, true, false, null],
+            "escapes": "quote \" backslash \\ slash / newline \n tab \t bell \u{7}",
+            "unicode": "caf\u{e9} \u{1f512}",
+            "nested": {"b": {}, "a": [], "B": "upper"},
+            "\u{e9}": "sorts after ascii",
+            "": "empty key",
+        });
+        let out = Json::canonicalize(&jsn).unwrap();
+        let expected = "{\"\":\"empty key\",\
+                        \"a\":[-1,0,18446744073709551615,true,false,null],\
+                        \"escapes\":\"quote \\\" backslash \\\\ slash / newline \n tab \t bell \u{7}\",\
+                        \"nested\":{\"B\":\"upper\",\"a\":[],\"b\":{}},\
+                        \"unicode\":\"caf\u{e9} \u{1f512}\",\
+                        \"z\":1,\
+                        \"\u{e9}\":\"sorts after ascii\"}";
+        assert_eq!(str::from_utf8(&out).unwrap(), expected);
+    }
+
+    #[test]
+    fn canonicalize_sorts_keys_by_code_point() {
+        // U+FB01 sorts after U+1F512 by UTF-16 code unit but before it by code point.
+        let jsn = serde_json::json!({"\u{1f512}": 1, "\u{fb01}": 2});
+        let out = Json::canonicalize(&jsn).unwrap();
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "{\"\u{fb01}\":2,\"\u{1f512}\":1}"
+        );
+    }
+
+    #[test]
+    fn from_reader_with_limits() {
+        let jsn: &[u8] = br#"{"foo": "bar", "baz": "quux"}"#;
+        let limits = JsonLimits::default().max_size(Some(jsn.len()));
+        let map: BTreeMap<String, String> = Json::from_reader_with_limits(jsn, &limits).unwrap();
+        assert_eq!(map.len(), 2);
+
+        let limits = limits.max_size(Some(jsn.len() - 1));
+        assert_matches!(
+            Json::from_reader_with_limits::<_, BTreeMap<String, String>>(jsn, &limits),
+            Err(Error::MetadataTooLarge(_))
+        );
+    }
+
+    #[test]
+    fn from_slice_rejects_deep_nesting_by_default() {
+        let depth = JsonLimits::DEFAULT_MAX_DEPTH;
+        let jsn = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        let _: serde_json::Value = Json::from_slice(jsn.as_bytes()).unwrap();
+
+        let jsn = format!("{}{}", "[".repeat(depth + 1), "]".repeat(depth + 1));
+        assert_matches!(
+            Json::from_slice::<serde_json::Value>(jsn.as_bytes()),
+            Err(Error::MetadataTooLarge(_))
+        );
+
+        let limits = JsonLimits::unlimited();
+        let _: serde_json::Value = Json::from_slice_with_limits(jsn.as_bytes(), &limits).unwrap();
+    }
+
+    #[test]
+    fn canonicalize_rejects_floats() {
+        let jsn = serde_json::json!({"a": 1.5});
+        assert!(Json::canonicalize(&jsn).is_err());
     }
 }
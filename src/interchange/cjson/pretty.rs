@@ -3,14 +3,16 @@ use serde::ser::Serialize;
 use serde_json;
 use std::io::{Read, Write};
 
-use super::Json;
+use super::{Json, JsonLimits};
 use crate::interchange::DataInterchange;
 use crate::Result;
 
 /// Pretty JSON data interchange.
 ///
 /// This is identical to [Json] in all manners except for the `to_writer` method. Instead of
-/// writing the metadata in the canonical format, it instead pretty prints the metadata.
+/// writing the metadata in the canonical format, it instead pretty prints the metadata with
+/// object keys sorted, so the output is stable across runs. Signatures are still computed over
+/// the canonical bytes, so metadata written by either interchange verifies identically.
 #[derive(Debug, Clone, PartialEq)]
 pub struct JsonPretty;
 
@@ -126,7 +128,7 @@ impl DataInterchange for JsonPretty {
     {
         Ok(serde_json::to_writer_pretty(
             writer,
-            &Self::serialize(value)?,
+            &sorted(Self::serialize(value)?),
         )?)
     }
 
@@ -156,4 +158,64 @@ impl DataInterchange for JsonPretty {
     {
         Json::from_slice(slice)
     }
+
+    fn from_slice_with_limits<T>(slice: &[u8], limits: &JsonLimits) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        Json::from_slice_with_limits(slice, limits)
+    }
+}
+
+/// Recursively rebuild `value` with object keys inserted in sorted order, so the pretty printed
+/// output is deterministic even if `serde_json` was built with the `preserve_order` feature.
+fn sorted(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Array(arr) => {
+            serde_json::Value::Array(arr.into_iter().map(sorted).collect())
+        }
+        serde_json::Value::Object(obj) => {
+            let mut entries = obj.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(entries.into_iter().map(|(k, v)| (k, sorted(v))).collect())
+        }
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::metadata::{SignedMetadata, SnapshotMetadataBuilder};
+    use chrono::prelude::*;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der");
+
+    #[test]
+    fn pretty_metadata_is_deterministic_and_verifies() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .expires(Utc.with_ymd_and_hms(2038, 1, 1, 0, 0, 0).unwrap())
+            .build()
+            .unwrap();
+
+        let pretty = SignedMetadata::<JsonPretty, _>::new(&snapshot, &key).unwrap();
+        let compact = SignedMetadata::<Json, _>::new(&snapshot, &key).unwrap();
+        assert_eq!(
+            pretty.signatures()[0].value(),
+            compact.signatures()[0].value()
+        );
+
+        let raw = pretty.to_raw().unwrap();
+        assert_eq!(raw, pretty.to_raw().unwrap());
+        let text = std::str::from_utf8(raw.as_bytes()).unwrap();
+        assert!(text.starts_with("{\n  \"signatures\": ["), "{}", text);
+        assert_ne!(raw.as_bytes(), compact.to_raw().unwrap().as_bytes());
+
+        // The pretty printed metadata verifies with the canonical signature.
+        let parsed = raw.parse().unwrap();
+        parsed.verify(1, Some(key.public())).unwrap();
+        assert_eq!(parsed.assume_valid().unwrap(), snapshot);
+    }
 }
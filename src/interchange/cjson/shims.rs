@@ -1,30 +1,65 @@
 use chrono::offset::Utc;
 use chrono::prelude::*;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::crypto;
 use crate::error::Error;
+use crate::interchange::DatetimeProfile;
 use crate::metadata::{self, Metadata};
 use crate::Result;
 
-const SPEC_VERSION: &str = "1.0";
+pub(crate) const SPEC_VERSION: &str = "1.0";
 
-fn parse_datetime(ts: &str) -> Result<DateTime<Utc>> {
-    Utc.datetime_from_str(ts, "%FT%TZ")
-        .map_err(|e| Error::Encoding(format!("Can't parse DateTime: {:?}", e)))
+/// Parse an RFC 3339 timestamp. The spec requires `YYYY-MM-DDTHH:MM:SSZ`, but other
+/// implementations also emit fractional seconds and numeric offsets, so accept those too and
+/// normalize to UTC.
+pub(crate) fn parse_datetime(ts: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(ts)
+        .map(|ts| ts.with_timezone(&Utc))
+        .map_err(|e| Error::Encoding(format!("Can't parse DateTime {:?}: {:?}", ts, e)))
+}
+
+thread_local! {
+    static DATETIME_PROFILE: Cell<Option<DatetimeProfile>> = const { Cell::new(None) };
+}
+
+/// Run `f` with datetimes serialized according to `profile` on the current thread.
+pub(crate) fn with_datetime_profile<F, R>(profile: DatetimeProfile, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct Restore(Option<DatetimeProfile>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DATETIME_PROFILE.with(|p| p.set(self.0));
+        }
+    }
+
+    let _restore = Restore(DATETIME_PROFILE.with(|p| p.replace(Some(profile))));
+    f()
+}
+
+/// Run `f` with datetimes serialized according to `profile`, unless `f` is already running
+/// inside [`with_datetime_profile`].
+pub(crate) fn with_default_datetime_profile<F, R>(profile: DatetimeProfile, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    if DATETIME_PROFILE.with(Cell::get).is_some() {
+        f()
+    } else {
+        with_datetime_profile(profile, f)
+    }
 }
 
 fn format_datetime(ts: &DateTime<Utc>) -> String {
-    format!(
-        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
-        ts.year(),
-        ts.month(),
-        ts.day(),
-        ts.hour(),
-        ts.minute(),
-        ts.second()
-    )
+    DATETIME_PROFILE
+        .with(Cell::get)
+        .unwrap_or_default()
+        .format(ts)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,6 +93,11 @@ impl RootMetadata {
                 snapshot: meta.snapshot().clone(),
                 targets: meta.targets().clone(),
                 timestamp: meta.timestamp().clone(),
+                additional: meta
+                    .additional_roles()
+                    .iter()
+                    .map(|(name, role)| (name.clone(), role.clone()))
+                    .collect(),
             },
         })
     }
@@ -79,11 +119,15 @@ impl RootMetadata {
 
         // Ignore all keys with incorrect key IDs. We should give an error if the key ID is not
         // correct according to TUF spec. However, due to backward compatibility, we may receive
-        // metadata with key IDs generated by TUF 0.9. We simply ignore those old keys.
+        // metadata with key IDs generated by TUF 0.9. We simply ignore those old keys. Keys whose
+        // IDs were derived with another supported `KeyIdScheme` keep those IDs.
         let keys_with_correct_key_id = self
             .keys
             .into_iter()
-            .filter(|(key_id, pkey)| key_id == pkey.key_id())
+            .filter_map(|(key_id, pkey)| {
+                let pkey = pkey.with_matching_key_id(&key_id)?;
+                Some((key_id, pkey))
+            })
             .collect();
 
         metadata::RootMetadata::new(
@@ -95,7 +139,8 @@ impl RootMetadata {
             self.roles.snapshot,
             self.roles.targets,
             self.roles.timestamp,
-        )
+        )?
+        .with_additional_roles(self.roles.additional.into_iter().collect())
     }
 }
 
@@ -105,6 +150,8 @@ struct RoleDefinitions {
     snapshot: metadata::RoleDefinition,
     targets: metadata::RoleDefinition,
     timestamp: metadata::RoleDefinition,
+    #[serde(flatten)]
+    additional: BTreeMap<String, metadata::RoleDefinition>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -377,7 +424,10 @@ pub struct Delegation {
     threshold: u32,
     #[serde(rename = "keyids")]
     key_ids: Vec<crypto::KeyId>,
-    paths: Vec<metadata::VirtualTargetPath>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    paths: Option<Vec<metadata::VirtualTargetPath>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path_hash_prefixes: Option<Vec<String>>,
 }
 
 impl Delegation {
@@ -388,6 +438,12 @@ impl Delegation {
             .cloned()
             .collect::<Vec<metadata::VirtualTargetPath>>();
         paths.sort();
+        let mut path_hash_prefixes = meta
+            .path_hash_prefixes()
+            .iter()
+            .cloned()
+            .collect::<Vec<String>>();
+        path_hash_prefixes.sort();
         let mut key_ids = meta
             .key_ids()
             .iter()
@@ -400,20 +456,16 @@ impl Delegation {
             terminating: meta.terminating(),
             threshold: meta.threshold(),
             key_ids,
-            paths,
+            paths: if paths.is_empty() { None } else { Some(paths) },
+            path_hash_prefixes: if path_hash_prefixes.is_empty() {
+                None
+            } else {
+                Some(path_hash_prefixes)
+            },
         }
     }
 
     pub fn try_into(self) -> Result<metadata::Delegation> {
-        let paths = self
-            .paths
-            .iter()
-            .cloned()
-            .collect::<HashSet<metadata::VirtualTargetPath>>();
-        if paths.len() != self.paths.len() {
-            return Err(Error::Encoding("Non-unique delegation paths.".into()));
-        }
-
         let key_ids = self
             .key_ids
             .iter()
@@ -423,7 +475,46 @@ impl Delegation {
             return Err(Error::Encoding("Non-unique delegation key IDs.".into()));
         }
 
-        metadata::Delegation::new(self.role, self.terminating, self.threshold, key_ids, paths)
+        match (self.paths, self.path_hash_prefixes) {
+            (Some(paths), None) => {
+                let len = paths.len();
+                let paths = paths.into_iter().collect::<HashSet<_>>();
+                if paths.len() != len {
+                    return Err(Error::Encoding("Non-unique delegation paths.".into()));
+                }
+
+                metadata::Delegation::new(
+                    self.role,
+                    self.terminating,
+                    self.threshold,
+                    key_ids,
+                    paths,
+                )
+            }
+            (None, Some(prefixes)) => {
+                let len = prefixes.len();
+                let prefixes = prefixes.into_iter().collect::<HashSet<_>>();
+                if prefixes.len() != len {
+                    return Err(Error::Encoding(
+                        "Non-unique delegation path hash prefixes.".into(),
+                    ));
+                }
+
+                metadata::Delegation::with_path_hash_prefixes(
+                    self.role,
+                    self.terminating,
+                    self.threshold,
+                    key_ids,
+                    prefixes,
+                )
+            }
+            (Some(_), Some(_)) => Err(Error::Encoding(
+                "Delegation cannot have both paths and path hash prefixes.".into(),
+            )),
+            (None, None) => Err(Error::Encoding(
+                "Delegation must have either paths or path hash prefixes.".into(),
+            )),
+        }
     }
 }
 
@@ -447,7 +538,15 @@ impl Delegations {
     }
 
     pub fn try_into(self) -> Result<metadata::Delegations> {
-        metadata::Delegations::new(self.keys.into_iter().collect(), self.roles)
+        let keys = self
+            .keys
+            .into_iter()
+            .map(|(key_id, pkey)| {
+                let pkey = pkey.with_matching_key_id(&key_id).unwrap_or(pkey);
+                (key_id, pkey)
+            })
+            .collect();
+        metadata::Delegations::new(keys, self.roles)
     }
 }
 
@@ -486,17 +585,24 @@ impl TargetDescription {
 #[derive(Deserialize)]
 pub struct MetadataDescription {
     version: u32,
-    length: usize,
-    hashes: BTreeMap<crypto::HashAlgorithm, crypto::HashValue>,
+    #[serde(default)]
+    length: Option<usize>,
+    #[serde(default)]
+    hashes: Option<BTreeMap<crypto::HashAlgorithm, crypto::HashValue>>,
 }
 
 impl MetadataDescription {
     pub fn try_into(self) -> Result<metadata::MetadataDescription> {
-        metadata::MetadataDescription::new(
-            self.version,
-            self.length,
-            self.hashes.into_iter().collect(),
-        )
+        let hashes = match self.hashes {
+            Some(hashes) if hashes.is_empty() => {
+                return Err(Error::IllegalArgument(
+                    "Cannot have empty set of hashes".into(),
+                ));
+            }
+            Some(hashes) => hashes.into_iter().collect(),
+            None => HashMap::new(),
+        };
+        metadata::MetadataDescription::from_parts(self.version, self.length, hashes)
     }
 }
 
@@ -548,3 +654,138 @@ mod deserialize_reject_duplicates {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::interchange::{DataInterchange, Json};
+    use crate::metadata::{SignedMetadata, SignedMetadataBuilder, SnapshotMetadataBuilder};
+    use serde::de::DeserializeOwned;
+    use serde::ser::Serialize;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn parse_datetime_accepts_spec_format() {
+        let ts = parse_datetime("2017-01-01T12:30:15Z").unwrap();
+        assert_eq!(ts.timestamp(), 1_483_273_815);
+        assert_eq!(ts.timestamp_subsec_nanos(), 0);
+    }
+
+    #[test]
+    fn parse_datetime_accepts_other_implementations() {
+        let expected = parse_datetime("2017-01-01T12:30:15Z").unwrap();
+
+        for ts in &[
+            "2017-01-01T12:30:15.25Z",
+            "2017-01-01T12:30:15.250000000Z",
+            "2017-01-01T14:30:15.25+02:00",
+        ] {
+            let parsed = parse_datetime(ts).unwrap();
+            assert_eq!(parsed.timestamp(), expected.timestamp());
+            assert_eq!(parsed.timestamp_subsec_millis(), 250);
+        }
+
+        for ts in &["2017-01-01T12:30:15+00:00", "2017-01-01T07:30:15-05:00"] {
+            assert_eq!(parse_datetime(ts), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn parse_datetime_rejects_garbage() {
+        assert!(parse_datetime("2017-01-01").is_err());
+        assert!(parse_datetime("2017-01-01T00:00:00").is_err());
+        assert!(parse_datetime("yesterday").is_err());
+    }
+
+    #[test]
+    fn format_datetime_is_canonical() {
+        let ts = parse_datetime("2017-01-01T14:30:15.25+02:00").unwrap();
+        assert_eq!(format_datetime(&ts), "2017-01-01T12:30:15Z");
+    }
+
+    #[test]
+    fn format_datetime_go_tuf_profile() {
+        let ts = parse_datetime("2017-01-01T14:30:15.25+02:00").unwrap();
+        let whole = parse_datetime("2017-01-01T12:30:15Z").unwrap();
+
+        with_datetime_profile(DatetimeProfile::GoTuf, || {
+            assert_eq!(format_datetime(&ts), "2017-01-01T12:30:15.25Z");
+            assert_eq!(format_datetime(&whole), "2017-01-01T12:30:15Z");
+        });
+
+        // The previous profile is restored afterwards.
+        assert_eq!(format_datetime(&ts), "2017-01-01T12:30:15Z");
+    }
+
+    /// `Json`, except that datetimes are written the way go-tuf writes them.
+    #[derive(Debug, Clone, PartialEq)]
+    struct GoTufJson;
+
+    impl DataInterchange for GoTufJson {
+        type RawData = serde_json::Value;
+
+        fn extension() -> &'static str {
+            Json::extension()
+        }
+
+        fn datetime_profile() -> DatetimeProfile {
+            DatetimeProfile::GoTuf
+        }
+
+        fn canonicalize(raw_data: &Self::RawData) -> Result<Vec<u8>> {
+            Json::canonicalize(raw_data)
+        }
+
+        fn deserialize<T: DeserializeOwned>(raw_data: &Self::RawData) -> Result<T> {
+            Json::deserialize(raw_data)
+        }
+
+        fn serialize<T: Serialize>(data: &T) -> Result<Self::RawData> {
+            Json::serialize(data)
+        }
+
+        fn to_writer<W: Write, T: Serialize>(writer: W, value: &T) -> Result<()> {
+            Json::to_writer(writer, value)
+        }
+
+        fn from_reader<R: Read, T: DeserializeOwned>(rdr: R) -> Result<T> {
+            Json::from_reader(rdr)
+        }
+
+        fn from_slice<T: DeserializeOwned>(slice: &[u8]) -> Result<T> {
+            Json::from_slice(slice)
+        }
+    }
+
+    #[test]
+    fn datetime_profile_per_interchange() {
+        let key = PrivateKey::from_pkcs8(
+            include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der"),
+            SignatureScheme::Ed25519,
+        )
+        .unwrap();
+        let expires = Utc.timestamp_opt(1_893_456_000, 500_000_000).unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .expires(expires)
+            .build()
+            .unwrap();
+
+        let go = SignedMetadata::<GoTufJson, _>::new(&snapshot, &key).unwrap();
+        let spec = SignedMetadata::<Json, _>::new(&snapshot, &key).unwrap();
+        assert!(String::from_utf8(go.canonical_bytes().unwrap())
+            .unwrap()
+            .contains("\"expires\":\"2030-01-01T00:00:00.5Z\""));
+        assert!(String::from_utf8(spec.canonical_bytes().unwrap())
+            .unwrap()
+            .contains("\"expires\":\"2030-01-01T00:00:00Z\""));
+
+        // An explicit profile takes precedence over the interchange's.
+        let go = with_datetime_profile(DatetimeProfile::Spec, || {
+            SignedMetadataBuilder::<GoTufJson, _>::from_metadata(&snapshot)
+                .unwrap()
+                .build()
+        });
+        assert_eq!(go.canonical_bytes(), spec.canonical_bytes());
+    }
+}
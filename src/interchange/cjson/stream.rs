@@ -0,0 +1,439 @@
+use serde::de::{
+    DeserializeSeed, Deserializer, Error as DeserializeError, IgnoredAny, MapAccess, Visitor,
+};
+use std::fmt;
+use std::io::{self, Read};
+
+use super::shims::{parse_datetime, SPEC_VERSION};
+use crate::crypto::{self, HashContext};
+use crate::error::Error;
+use crate::metadata::{
+    Delegations, MetadataDescription, Role, TargetDescription, TargetsMetadataHeader,
+    VirtualTargetPath,
+};
+use crate::Result;
+
+/// Parse signed targets metadata from `rdr`, calling `f` with each target as it is read, and
+/// check the consumed bytes against `description`.
+pub(crate) fn stream_targets<R, F>(
+    rdr: R,
+    description: &MetadataDescription,
+    mut f: F,
+) -> Result<TargetsMetadataHeader>
+where
+    R: Read,
+    F: FnMut(VirtualTargetPath, TargetDescription) -> Result<()>,
+{
+    // Signatures are not checked here, so the description must pin both the length and a hash.
+    let length = description.length().ok_or_else(|| {
+        Error::VerificationFailure("Streaming targets metadata requires a known length".into())
+    })?;
+    if description.hashes().is_empty() {
+        return Err(Error::VerificationFailure(
+            "Streaming targets metadata requires a known hash".into(),
+        ));
+    }
+    let (alg, expected_hash) = crypto::hash_preference(description.hashes())?;
+    let mut rdr = VerifyingReader {
+        inner: rdr,
+        remaining: length,
+        exceeded: false,
+        context: alg.digest_context()?,
+    };
+
+    let mut callback_error = None;
+    let res = {
+        let mut de = serde_json::Deserializer::from_reader(&mut rdr);
+        let seed = SignedSeed {
+            f: &mut f,
+            error: &mut callback_error,
+        };
+        seed.deserialize(&mut de).and_then(|header| {
+            de.end()?;
+            Ok(header)
+        })
+    };
+
+    if let Some(err) = callback_error {
+        return Err(err);
+    }
+    if rdr.exceeded {
+        return Err(Error::VerificationFailure(format!(
+            "Targets metadata exceeded the expected length of {} bytes",
+            length
+        )));
+    }
+    let header = res?;
+
+    if rdr.remaining != 0 {
+        return Err(Error::VerificationFailure(format!(
+            "Targets metadata was {} bytes shorter than the expected {}",
+            rdr.remaining, length
+        )));
+    }
+
+    if &rdr.context.finish() != expected_hash {
+        return Err(Error::VerificationFailure(
+            "Targets metadata did not match the expected hash".into(),
+        ));
+    }
+
+    if header.version() != description.version() {
+        return Err(Error::VerificationFailure(format!(
+            "Targets metadata version {} did not match the expected version {}",
+            header.version(),
+            description.version()
+        )));
+    }
+
+    Ok(header)
+}
+
+/// Hashes everything read through it and refuses to read past the expected length.
+struct VerifyingReader<R> {
+    inner: R,
+    remaining: usize,
+    exceeded: bool,
+    context: HashContext,
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Read one byte past the expected length so oversized metadata is detected.
+        let len = buf.len().min(self.remaining.saturating_add(1));
+        let n = self.inner.read(&mut buf[..len])?;
+        if n > self.remaining {
+            self.exceeded = true;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Targets metadata exceeded the expected length",
+            ));
+        }
+        self.remaining -= n;
+        self.context.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Visits the `{"signatures": ..., "signed": ...}` envelope. Signatures are skipped since the
+/// metadata is authenticated by its hash.
+struct SignedSeed<'a, F> {
+    f: &'a mut F,
+    error: &'a mut Option<Error>,
+}
+
+impl<'de, 'a, F> DeserializeSeed<'de> for SignedSeed<'a, F>
+where
+    F: FnMut(VirtualTargetPath, TargetDescription) -> Result<()>,
+{
+    type Value = TargetsMetadataHeader;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        de: D,
+    ) -> ::std::result::Result<Self::Value, D::Error> {
+        de.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a, F> Visitor<'de> for SignedSeed<'a, F>
+where
+    F: FnMut(VirtualTargetPath, TargetDescription) -> Result<()>,
+{
+    type Value = TargetsMetadataHeader;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("signed targets metadata")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> ::std::result::Result<Self::Value, A::Error> {
+        let SignedSeed { f, error } = self;
+        let mut seed = Some(TargetsSeed { f, error });
+        let mut header = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match (key.as_str(), seed.take()) {
+                ("signed", Some(seed)) => header = Some(map.next_value_seed(seed)?),
+                ("signed", None) => return Err(A::Error::duplicate_field("signed")),
+                (_, s) => {
+                    seed = s;
+                    let _ = map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        header.ok_or_else(|| A::Error::missing_field("signed"))
+    }
+}
+
+/// Visits the signed targets metadata, streaming the `targets` map.
+struct TargetsSeed<'a, F> {
+    f: &'a mut F,
+    error: &'a mut Option<Error>,
+}
+
+impl<'de, 'a, F> DeserializeSeed<'de> for TargetsSeed<'a, F>
+where
+    F: FnMut(VirtualTargetPath, TargetDescription) -> Result<()>,
+{
+    type Value = TargetsMetadataHeader;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        de: D,
+    ) -> ::std::result::Result<Self::Value, D::Error> {
+        de.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a, F> Visitor<'de> for TargetsSeed<'a, F>
+where
+    F: FnMut(VirtualTargetPath, TargetDescription) -> Result<()>,
+{
+    type Value = TargetsMetadataHeader;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("targets metadata")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> ::std::result::Result<Self::Value, A::Error> {
+        let mut typ = None;
+        let mut spec_version = None;
+        let mut version = None;
+        let mut expires = None;
+        let mut delegations = None;
+        let mut seen_targets = false;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "_type" => typ = Some(map.next_value::<Role>()?),
+                "spec_version" => spec_version = Some(map.next_value::<String>()?),
+                "version" => version = Some(map.next_value::<u32>()?),
+                "expires" => expires = Some(map.next_value::<String>()?),
+                "delegations" => delegations = map.next_value::<Option<Delegations>>()?,
+                "targets" => {
+                    if seen_targets {
+                        return Err(A::Error::duplicate_field("targets"));
+                    }
+                    seen_targets = true;
+                    map.next_value_seed(TargetMapSeed {
+                        f: &mut *self.f,
+                        error: &mut *self.error,
+                    })?;
+                }
+                _ => {
+                    let _ = map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        if !seen_targets {
+            return Err(A::Error::missing_field("targets"));
+        }
+
+        let typ = typ.ok_or_else(|| A::Error::missing_field("_type"))?;
+        if typ != Role::Targets {
+            return Err(A::Error::custom(format!(
+                "Attempted to decode targets metdata labeled as {:?}",
+                typ
+            )));
+        }
+
+        let spec_version = spec_version.ok_or_else(|| A::Error::missing_field("spec_version"))?;
+        if spec_version != SPEC_VERSION {
+            return Err(A::Error::custom(format!(
+                "Unknown spec version {}",
+                spec_version
+            )));
+        }
+
+        let version = version.ok_or_else(|| A::Error::missing_field("version"))?;
+        let expires = expires.ok_or_else(|| A::Error::missing_field("expires"))?;
+        let expires = parse_datetime(&expires).map_err(|e| A::Error::custom(format!("{:?}", e)))?;
+
+        TargetsMetadataHeader::new(version, expires, delegations)
+            .map_err(|e| A::Error::custom(format!("{:?}", e)))
+    }
+}
+
+/// Visits the `targets` map, handing each entry to the callback instead of collecting them.
+struct TargetMapSeed<'a, F> {
+    f: &'a mut F,
+    error: &'a mut Option<Error>,
+}
+
+impl<'de, 'a, F> DeserializeSeed<'de> for TargetMapSeed<'a, F>
+where
+    F: FnMut(VirtualTargetPath, TargetDescription) -> Result<()>,
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        de: D,
+    ) -> ::std::result::Result<Self::Value, D::Error> {
+        de.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a, F> Visitor<'de> for TargetMapSeed<'a, F>
+where
+    F: FnMut(VirtualTargetPath, TargetDescription) -> Result<()>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of target paths to descriptions")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> ::std::result::Result<(), A::Error> {
+        while let Some(path) = map.next_key::<VirtualTargetPath>()? {
+            let description = map.next_value::<TargetDescription>()?;
+            if let Err(err) = (self.f)(path, description) {
+                // Stash the callback's error so it can be returned unchanged rather than as a
+                // stringly typed JSON error.
+                *self.error = Some(err);
+                return Err(A::Error::custom("target callback failed"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
+    use crate::interchange::Json;
+    use crate::metadata::{
+        Metadata, RawSignedMetadata, SignedMetadata, TargetsMetadata, TargetsMetadataBuilder,
+    };
+    use chrono::prelude::*;
+    use matches::assert_matches;
+    use std::collections::HashMap;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../../tests/ed25519/ed25519-1.pk8.der");
+
+    fn targets_metadata(
+        count: usize,
+    ) -> (TargetsMetadata, RawSignedMetadata<Json, TargetsMetadata>) {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let mut builder = TargetsMetadataBuilder::new()
+            .version(3)
+            .expires(Utc.with_ymd_and_hms(2038, 1, 1, 0, 0, 0).unwrap());
+        for i in 0..count {
+            let data = format!("target {}", i);
+            builder = builder.insert_target_description(
+                VirtualTargetPath::new(format!("targets/{}", i)).unwrap(),
+                TargetDescription::from_reader(data.as_bytes(), &[HashAlgorithm::Sha256]).unwrap(),
+            );
+        }
+        let targets = builder.build().unwrap();
+        let raw = SignedMetadata::<Json, _>::new(&targets, &key)
+            .unwrap()
+            .to_raw()
+            .unwrap();
+        (targets, raw)
+    }
+
+    fn describe(bytes: &[u8], version: u32) -> MetadataDescription {
+        MetadataDescription::from_reader(bytes, version, &[HashAlgorithm::Sha256]).unwrap()
+    }
+
+    #[test]
+    fn stream_targets() {
+        let (targets, raw) = targets_metadata(100);
+        let description = describe(raw.as_bytes(), 3);
+
+        let mut streamed = HashMap::new();
+        let header = Json::stream_targets(raw.as_bytes(), &description, |path, desc| {
+            assert!(streamed.insert(path, desc).is_none());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(header.version(), 3);
+        assert_eq!(header.expires(), targets.expires());
+        assert_eq!(header.delegations(), None);
+        assert_eq!(&streamed, targets.targets());
+    }
+
+    #[test]
+    fn stream_targets_rejects_wrong_hash() {
+        let (_, raw) = targets_metadata(2);
+        let mut bytes = raw.as_bytes().to_vec();
+        let description = describe(&bytes, 3);
+
+        // Same length and still valid JSON, but different content.
+        let pos = bytes.windows(9).position(|w| w == b"targets/0").unwrap();
+        bytes[pos + 8] = b'9';
+        assert_matches!(
+            Json::stream_targets(&bytes[..], &description, |_, _| Ok(())),
+            Err(Error::VerificationFailure(_))
+        );
+    }
+
+    #[test]
+    fn stream_targets_rejects_wrong_length() {
+        let (_, raw) = targets_metadata(2);
+        let bytes = raw.as_bytes();
+
+        let description = describe(&bytes[..bytes.len() - 1], 3);
+        assert_matches!(
+            Json::stream_targets(bytes, &description, |_, _| Ok(())),
+            Err(Error::VerificationFailure(_))
+        );
+
+        let mut longer = bytes.to_vec();
+        longer.push(b' ');
+        let description = describe(&longer, 3);
+        assert_matches!(
+            Json::stream_targets(bytes, &description, |_, _| Ok(())),
+            Err(Error::VerificationFailure(_))
+        );
+    }
+
+    #[test]
+    fn stream_targets_rejects_wrong_version() {
+        let (_, raw) = targets_metadata(2);
+        let description = describe(raw.as_bytes(), 4);
+        assert_matches!(
+            Json::stream_targets(raw.as_bytes(), &description, |_, _| Ok(())),
+            Err(Error::VerificationFailure(_))
+        );
+    }
+
+    #[test]
+    fn stream_targets_rejects_version_only_description() {
+        let (_, raw) = targets_metadata(2);
+        let description = MetadataDescription::from_version(3).unwrap();
+        assert_matches!(
+            Json::stream_targets(raw.as_bytes(), &description, |_, _| Ok(())),
+            Err(Error::VerificationFailure(_))
+        );
+    }
+
+    #[test]
+    fn stream_targets_returns_callback_error() {
+        let (_, raw) = targets_metadata(10);
+        let description = describe(raw.as_bytes(), 3);
+
+        let mut seen = 0;
+        let res = Json::stream_targets(raw.as_bytes(), &description, |_, _| {
+            seen += 1;
+            if seen == 3 {
+                Err(Error::TargetUnavailable)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(res, Err(Error::TargetUnavailable));
+        assert_eq!(seen, 3);
+    }
+}
@@ -1,8 +1,9 @@
 //! Structures and functions to aid in various TUF data interchange formats.
 
 pub(crate) mod cjson;
-pub use cjson::{Json, JsonPretty};
+pub use cjson::{Json, JsonLimits, JsonPretty};
 
+use chrono::prelude::*;
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use std::fmt::Debug;
@@ -10,6 +11,89 @@ use std::io::{Read, Write};
 
 use crate::Result;
 
+/// Conventions for writing the datetimes in metadata, such as `expires`.
+///
+/// Datetimes are always parsed leniently, but some tools expect re-serialized metadata to match
+/// what their own ecosystem would emit byte for byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DatetimeProfile {
+    /// `YYYY-MM-DDTHH:MM:SSZ`, truncated to whole seconds. This is the format required by the spec
+    /// and emitted by python-tuf.
+    #[default]
+    Spec,
+    /// RFC 3339 in UTC with as many fractional digits as needed, up to nanoseconds, as emitted by
+    /// go-tuf.
+    GoTuf,
+}
+
+impl DatetimeProfile {
+    /// Format `ts` according to this profile.
+    ///
+    /// ```
+    /// # use chrono::prelude::*;
+    /// # use tuf::interchange::DatetimeProfile;
+    /// let ts = Utc.timestamp_opt(1_893_456_000, 250_000_000).unwrap();
+    /// assert_eq!(DatetimeProfile::Spec.format(&ts), "2030-01-01T00:00:00Z");
+    /// assert_eq!(DatetimeProfile::GoTuf.format(&ts), "2030-01-01T00:00:00.25Z");
+    /// ```
+    pub fn format(self, ts: &DateTime<Utc>) -> String {
+        let seconds = format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            ts.year(),
+            ts.month(),
+            ts.day(),
+            ts.hour(),
+            ts.minute(),
+            ts.second()
+        );
+
+        match self {
+            DatetimeProfile::Spec => format!("{}Z", seconds),
+            DatetimeProfile::GoTuf => {
+                // Match Go's `time.RFC3339Nano`, which trims trailing zeros from the fraction and
+                // omits it entirely for whole seconds.
+                let nanos = ts.timestamp_subsec_nanos() % 1_000_000_000;
+                if nanos == 0 {
+                    format!("{}Z", seconds)
+                } else {
+                    let fraction = format!("{:09}", nanos);
+                    format!("{}.{}Z", seconds, fraction.trim_end_matches('0'))
+                }
+            }
+        }
+    }
+}
+
+/// Serialize datetimes according to `profile` for any metadata serialized by `f` on the current
+/// thread, regardless of the [`DataInterchange::datetime_profile`] of the interchange in use.
+///
+/// ```
+/// # use chrono::prelude::*;
+/// # use tuf::crypto::{PrivateKey, SignatureScheme};
+/// # use tuf::interchange::{self, DatetimeProfile, Json};
+/// # use tuf::metadata::{Metadata, SnapshotMetadataBuilder};
+/// # let key = PrivateKey::from_pkcs8(
+/// #     include_bytes!("../../tests/ed25519/ed25519-1.pk8.der"),
+/// #     SignatureScheme::Ed25519,
+/// # ).unwrap();
+/// let expires = Utc.timestamp_opt(1_893_456_000, 500_000_000).unwrap();
+/// let snapshot = interchange::with_datetime_profile(DatetimeProfile::GoTuf, || {
+///     SnapshotMetadataBuilder::new()
+///         .expires(expires)
+///         .signed::<Json>(&key)
+/// })
+/// .unwrap();
+///
+/// let raw = snapshot.to_raw().unwrap();
+/// assert!(String::from_utf8_lossy(raw.as_bytes()).contains("2030-01-01T00:00:00.5Z"));
+/// ```
+pub fn with_datetime_profile<F, R>(profile: DatetimeProfile, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    cjson::shims::with_datetime_profile(profile, f)
+}
+
 /// The format used for data interchange, serialization, and deserialization.
 pub trait DataInterchange: Debug + PartialEq + Clone {
     /// The type of data that is contained in the `signed` portion of metadata.
@@ -18,6 +102,14 @@ pub trait DataInterchange: Debug + PartialEq + Clone {
     /// The data interchange's extension.
     fn extension() -> &'static str;
 
+    /// The convention for writing datetimes, such as `expires`, when metadata is serialized into
+    /// this interchange. Since datetimes are part of the signed bytes, every implementation
+    /// signing the same metadata must agree on it. Defaults to [`DatetimeProfile::Spec`], and can
+    /// be overridden for a single operation with [`with_datetime_profile`].
+    fn datetime_profile() -> DatetimeProfile {
+        DatetimeProfile::Spec
+    }
+
     /// A function that canonicalizes data to allow for deterministic signatures.
     fn canonicalize(raw_data: &Self::RawData) -> Result<Vec<u8>>;
 
@@ -47,4 +139,14 @@ pub trait DataInterchange: Debug + PartialEq + Clone {
     fn from_slice<T>(slice: &[u8]) -> Result<T>
     where
         T: DeserializeOwned;
+
+    /// Read a struct from a slice of bytes, rejecting documents that exceed `limits`. The default
+    /// implementation ignores `limits`, for interchanges that aren't JSON.
+    fn from_slice_with_limits<T>(slice: &[u8], limits: &JsonLimits) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let _ = limits;
+        Self::from_slice(slice)
+    }
 }
@@ -0,0 +1,314 @@
+//! Utilities for generating keys for tests and examples.
+//!
+//! This module requires the `keygen` feature. Rather than checking static key files into a
+//! repository, tests can mint a fresh [`RoleKeys`] set, build root metadata from it, and optionally
+//! write the keys to disk in every format this crate can read back.
+//!
+//! ```
+//! # use tuf::crypto::KeyType;
+//! # use tuf::interchange::Json;
+//! # use tuf::keygen::RoleKeys;
+//! let keys = RoleKeys::generate(KeyType::Ed25519, 1).unwrap();
+//! let root = keys
+//!     .root_metadata_builder()
+//!     .signed::<Json>(keys.root()[0].private_key())
+//!     .unwrap();
+//! ```
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::crypto::{self, KeyType, PrivateKey, PublicKey, SignatureScheme};
+use crate::error::Error;
+use crate::interchange::DataInterchange;
+use crate::metadata::RootMetadataBuilder;
+use crate::Result;
+
+/// A freshly generated private key along with its PKCS#8 encoding.
+pub struct GeneratedKey {
+    pkcs8: Vec<u8>,
+    private_key: PrivateKey,
+}
+
+impl GeneratedKey {
+    /// Generate a new key of type `key_type` that signs with `scheme`.
+    ///
+    /// Note: For RSA keys, `openssl` needs to be on the `$PATH`.
+    pub fn generate(key_type: KeyType, scheme: SignatureScheme) -> Result<Self> {
+        let private_key = PrivateKey::generate_with_scheme(key_type, scheme)?;
+        let pkcs8 = private_key
+            .pkcs8_der()
+            .ok_or_else(|| Error::Programming("Generated key has no PKCS#8 encoding".into()))?
+            .to_vec();
+        Ok(GeneratedKey { pkcs8, private_key })
+    }
+
+    /// Generate a new key of type `key_type` using its default signature scheme.
+    pub fn generate_default(key_type: KeyType) -> Result<Self> {
+        let scheme = crypto::default_scheme(&key_type)?;
+        Self::generate(key_type, scheme)
+    }
+
+    /// An immutable reference to the private key.
+    pub fn private_key(&self) -> &PrivateKey {
+        &self.private_key
+    }
+
+    /// An immutable reference to the public key.
+    pub fn public_key(&self) -> &PublicKey {
+        self.private_key.public()
+    }
+
+    /// The private key as PKCS#8 DER bytes.
+    pub fn pkcs8_der(&self) -> &[u8] {
+        &self.pkcs8
+    }
+
+    /// The public key as SubjectPublicKeyInfo DER bytes.
+    pub fn spki_der(&self) -> Result<Vec<u8>> {
+        self.public_key().as_spki()
+    }
+
+    /// Consume this `GeneratedKey` and return the private key.
+    pub fn into_private_key(self) -> PrivateKey {
+        self.private_key
+    }
+
+    /// Write the key to `dir` as `{name}.pk8.der`, `{name}.spki.der`, and `{name}.pub.{ext}`,
+    /// where the last file is the public key serialized with the data interchange `D`.
+    ///
+    /// On unix, the private key file is only readable and writable by its owner.
+    pub fn write_to<D, P>(&self, dir: P, name: &str) -> Result<()>
+    where
+        D: DataInterchange,
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        write_private_file(&dir.join(format!("{}.pk8.der", name)), &self.pkcs8)?;
+        write_file(&dir.join(format!("{}.spki.der", name)), &self.spki_der()?)?;
+
+        let mut public = Vec::new();
+        D::to_writer(&mut public, self.public_key())?;
+        write_file(
+            &dir.join(format!("{}.pub.{}", name, D::extension())),
+            &public,
+        )?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for GeneratedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GeneratedKey")
+            .field("public_key", self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A set of freshly generated keys for each of the top level roles.
+#[derive(Debug)]
+pub struct RoleKeys {
+    root: Vec<GeneratedKey>,
+    snapshot: Vec<GeneratedKey>,
+    targets: Vec<GeneratedKey>,
+    timestamp: Vec<GeneratedKey>,
+}
+
+impl RoleKeys {
+    /// Generate `keys_per_role` distinct keys of type `key_type` for each top level role.
+    pub fn generate(key_type: KeyType, keys_per_role: usize) -> Result<Self> {
+        if keys_per_role == 0 {
+            return Err(Error::IllegalArgument(
+                "Each role needs at least one key".into(),
+            ));
+        }
+
+        let generate = || {
+            (0..keys_per_role)
+                .map(|_| GeneratedKey::generate_default(key_type.clone()))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        Ok(RoleKeys {
+            root: generate()?,
+            snapshot: generate()?,
+            targets: generate()?,
+            timestamp: generate()?,
+        })
+    }
+
+    /// The root keys.
+    pub fn root(&self) -> &[GeneratedKey] {
+        &self.root
+    }
+
+    /// The snapshot keys.
+    pub fn snapshot(&self) -> &[GeneratedKey] {
+        &self.snapshot
+    }
+
+    /// The targets keys.
+    pub fn targets(&self) -> &[GeneratedKey] {
+        &self.targets
+    }
+
+    /// The timestamp keys.
+    pub fn timestamp(&self) -> &[GeneratedKey] {
+        &self.timestamp
+    }
+
+    /// Create a `RootMetadataBuilder` that trusts all of these keys for their respective roles.
+    /// Thresholds are left at their defaults.
+    pub fn root_metadata_builder(&self) -> RootMetadataBuilder {
+        let mut builder = RootMetadataBuilder::new();
+        for key in &self.root {
+            builder = builder.root_key(key.public_key().clone());
+        }
+        for key in &self.snapshot {
+            builder = builder.snapshot_key(key.public_key().clone());
+        }
+        for key in &self.targets {
+            builder = builder.targets_key(key.public_key().clone());
+        }
+        for key in &self.timestamp {
+            builder = builder.timestamp_key(key.public_key().clone());
+        }
+        builder
+    }
+
+    /// Write every key to `dir` with [`GeneratedKey::write_to`], named `{role}-{n}` where `n`
+    /// starts at 1.
+    pub fn write_to<D, P>(&self, dir: P) -> Result<()>
+    where
+        D: DataInterchange,
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        let roles = [
+            ("root", &self.root),
+            ("snapshot", &self.snapshot),
+            ("targets", &self.targets),
+            ("timestamp", &self.timestamp),
+        ];
+
+        for (role, keys) in roles.iter() {
+            for (i, key) in keys.iter().enumerate() {
+                key.write_to::<D, _>(dir, &format!("{}-{}", role, i + 1))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    fs::write(path, bytes).map_err(|e| Error::from_io(&e, path))
+}
+
+/// Like `write_file`, but on unix the file is only accessible by its owner, even if it already
+/// existed with wider permissions.
+fn write_private_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path).map_err(|e| Error::from_io(&e, path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| Error::from_io(&e, path))?;
+    }
+    file.write_all(bytes).map_err(|e| Error::from_io(&e, path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interchange::Json;
+    use std::collections::HashSet;
+
+    #[test]
+    fn role_keys_are_distinct() {
+        let keys = RoleKeys::generate(KeyType::Ed25519, 2).unwrap();
+        let key_ids = keys
+            .root()
+            .iter()
+            .chain(keys.snapshot())
+            .chain(keys.targets())
+            .chain(keys.timestamp())
+            .map(|k| k.public_key().key_id().clone())
+            .collect::<HashSet<_>>();
+        assert_eq!(key_ids.len(), 8);
+
+        let root = keys.root_metadata_builder().build().unwrap();
+        assert_eq!(root.keys().len(), 8);
+        assert_eq!(root.root().key_ids().len(), 2);
+    }
+
+    #[test]
+    fn role_keys_rejects_zero_keys() {
+        assert!(RoleKeys::generate(KeyType::Ed25519, 0).is_err());
+    }
+
+    #[test]
+    fn generated_key_round_trips_through_files() {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("rust-tuf")
+            .tempdir()
+            .unwrap();
+        let keys = RoleKeys::generate(KeyType::Ed25519, 1).unwrap();
+        keys.write_to::<Json, _>(temp_dir.path()).unwrap();
+
+        let expected = keys.timestamp()[0].public_key();
+        let dir = temp_dir.path();
+
+        let pkcs8 = fs::read(dir.join("timestamp-1.pk8.der")).unwrap();
+        let private = PrivateKey::from_pkcs8(&pkcs8, SignatureScheme::Ed25519).unwrap();
+        assert_eq!(private.public(), expected);
+
+        let spki = fs::read(dir.join("timestamp-1.spki.der")).unwrap();
+        let public = PublicKey::from_spki(&spki, SignatureScheme::Ed25519).unwrap();
+        assert_eq!(&public, expected);
+
+        let json = fs::read(dir.join("timestamp-1.pub.json")).unwrap();
+        let public: PublicKey = Json::from_slice(&json).unwrap();
+        assert_eq!(&public, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_key_files_are_only_readable_by_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::Builder::new()
+            .prefix("rust-tuf")
+            .tempdir()
+            .unwrap();
+        let dir = temp_dir.path();
+
+        // A key file left behind with wider permissions is tightened when it is overwritten.
+        fs::write(dir.join("root-1.pk8.der"), b"stale").unwrap();
+        fs::set_permissions(
+            dir.join("root-1.pk8.der"),
+            fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+
+        let keys = RoleKeys::generate(KeyType::Ed25519, 1).unwrap();
+        keys.write_to::<Json, _>(dir).unwrap();
+
+        let mode = |name: &str| fs::metadata(dir.join(name)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("root-1.pk8.der"), 0o600);
+        assert_eq!(mode("timestamp-1.pk8.der"), 0o600);
+    }
+}
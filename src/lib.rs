@@ -114,6 +114,8 @@ pub mod client;
 pub mod crypto;
 pub mod error;
 pub mod interchange;
+#[cfg(feature = "keygen")]
+pub mod keygen;
 pub mod metadata;
 pub mod repository;
 pub mod tuf;
@@ -2,22 +2,34 @@
 
 use chrono::offset::Utc;
 use chrono::{DateTime, Duration};
+use futures_io::AsyncRead;
 use log::{debug, warn};
 use serde::de::{Deserialize, DeserializeOwned, Deserializer, Error as DeserializeError};
 use serde::ser::{Error as SerializeError, Serialize, Serializer};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Debug, Display};
+use std::fs::File;
 use std::io::Read;
 use std::marker::PhantomData;
+use std::path::{Component, Path};
 use std::str;
 
-use crate::crypto::{self, HashAlgorithm, HashValue, KeyId, PrivateKey, PublicKey, Signature};
+use crate::crypto::{
+    self, HashAlgorithm, HashValue, KeyId, PublicKey, SchemePolicy, Signature, Signer,
+};
 use crate::error::Error;
 use crate::interchange::cjson::shims;
-use crate::interchange::DataInterchange;
+use crate::interchange::{DataInterchange, JsonLimits};
+use crate::util;
 use crate::Result;
 
+mod graph;
+mod validate;
+
+pub use self::graph::{DelegationGraph, DelegationGraphEdge, DelegationGraphRole};
+pub use self::validate::{validate, Finding};
+
 #[rustfmt::skip]
 static PATH_ILLEGAL_COMPONENTS: &'static [&str] = &[
     ".", // current dir
@@ -145,8 +157,13 @@ fn safe_path(path: &str) -> Result<()> {
     Ok(())
 }
 
-/// The TUF role.
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A top-level TUF role.
+///
+/// Delegated targets roles are not listed here. They are identified by their `MetadataPath`,
+/// which may contain slashes to namespace them (e.g. `projects/foo`). New top-level roles may be
+/// added to this enum in the future, so it cannot be matched exhaustively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Role {
     /// The root role.
     #[serde(rename = "root")]
@@ -160,6 +177,9 @@ pub enum Role {
     /// The timestamp role.
     #[serde(rename = "timestamp")]
     Timestamp,
+    /// The mirrors role.
+    #[serde(rename = "mirrors")]
+    Mirrors,
 }
 
 impl Role {
@@ -175,15 +195,15 @@ impl Role {
     ///
     /// assert!(!Role::Root.fuzzy_matches_path(&MetadataPath::from_role(&Role::Snapshot)));
     /// assert!(!Role::Root.fuzzy_matches_path(&MetadataPath::new("wat").unwrap()));
+    ///
+    /// // Delegated targets roles are targets metadata.
+    /// assert!(Role::Targets.fuzzy_matches_path(&MetadataPath::new("projects/foo").unwrap()));
+    /// assert!(!Role::Targets.fuzzy_matches_path(&MetadataPath::from_role(&Role::Timestamp)));
     /// ```
     pub fn fuzzy_matches_path(&self, path: &MetadataPath) -> bool {
-        match *self {
-            Role::Root if &path.0 == "root" => true,
-            Role::Snapshot if &path.0 == "snapshot" => true,
-            Role::Timestamp if &path.0 == "timestamp" => true,
-            Role::Targets if &path.0 == "targets" => true,
-            Role::Targets if !&["root", "snapshot", "targets"].contains(&path.0.as_str()) => true,
-            _ => false,
+        match path.role() {
+            Some(role) => role == *self,
+            None => *self == Role::Targets,
         }
     }
 
@@ -194,6 +214,26 @@ impl Role {
             Role::Snapshot => "snapshot",
             Role::Targets => "targets",
             Role::Timestamp => "timestamp",
+            Role::Mirrors => "mirrors",
+        }
+    }
+
+    /// Look up the top-level role with the given name.
+    ///
+    /// ```
+    /// # use tuf::metadata::Role;
+    /// assert_eq!(Role::from_name("root"), Some(Role::Root));
+    /// assert_eq!(Role::from_name("mirrors"), Some(Role::Mirrors));
+    /// assert_eq!(Role::from_name("projects/foo"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Role> {
+        match name {
+            "root" => Some(Role::Root),
+            "snapshot" => Some(Role::Snapshot),
+            "targets" => Some(Role::Targets),
+            "timestamp" => Some(Role::Timestamp),
+            "mirrors" => Some(Role::Mirrors),
+            _ => None,
         }
     }
 }
@@ -268,6 +308,11 @@ where
     pub fn parse(&self) -> Result<SignedMetadata<D, M>> {
         D::from_slice(&self.bytes)
     }
+
+    /// Parse this metadata, rejecting documents that exceed `limits`.
+    pub(crate) fn parse_with_limits(&self, limits: &JsonLimits) -> Result<SignedMetadata<D, M>> {
+        D::from_slice_with_limits(&self.bytes, limits)
+    }
 }
 
 /// Helper to construct `SignedMetadata`.
@@ -282,6 +327,15 @@ where
     _marker: PhantomData<M>,
 }
 
+/// Serialize `metadata` with the datetime profile of `D`.
+fn serialize_metadata<D, M>(metadata: &M) -> Result<D::RawData>
+where
+    D: DataInterchange,
+    M: Metadata,
+{
+    shims::with_default_datetime_profile(D::datetime_profile(), || D::serialize(metadata))
+}
+
 impl<D, M> SignedMetadataBuilder<D, M>
 where
     D: DataInterchange,
@@ -289,7 +343,7 @@ where
 {
     /// Create a new `SignedMetadataBuilder` from a given `Metadata`.
     pub fn from_metadata(metadata: &M) -> Result<Self> {
-        let metadata = D::serialize(metadata)?;
+        let metadata = serialize_metadata::<D, M>(metadata)?;
         Self::from_raw_metadata(metadata)
     }
 
@@ -306,19 +360,34 @@ where
         })
     }
 
-    /// Sign the metadata using the given `private_key`, replacing any existing signatures with the
-    /// same `KeyId`.
+    /// Sign the metadata using the given `signer`, replacing any existing signatures with the same
+    /// `KeyId`.
     ///
     /// **WARNING**: You should never have multiple TUF private keys on the same machine, so if
     /// you're using this to append several signatures at once, you are doing something wrong. The
     /// preferred method is to generate your copy of the metadata locally and use
     /// `SignedMetadata::merge_signatures` to perform the "append" operations.
-    pub fn sign(mut self, private_key: &PrivateKey) -> Result<Self> {
-        let sig = private_key.sign(&self.metadata_bytes)?;
+    pub fn sign(mut self, signer: &dyn Signer) -> Result<Self> {
+        let sig = crypto::sign_with(signer, &self.metadata_bytes)?;
         let _ = self.signatures.insert(sig.key_id().clone(), sig);
         Ok(self)
     }
 
+    /// The canonical bytes of the metadata, which is what signers must sign.
+    pub fn canonical_bytes(&self) -> &[u8] {
+        &self.metadata_bytes
+    }
+
+    /// Add a signature that was computed elsewhere over [`Self::canonical_bytes`], replacing any
+    /// existing signature with the same `KeyId`. The signature is not checked until the metadata is
+    /// verified.
+    pub fn insert_signature(mut self, signature: Signature) -> Self {
+        let _ = self
+            .signatures
+            .insert(signature.key_id().clone(), signature);
+        self
+    }
+
     /// Construct a new `SignedMetadata` using the included signatures, sorting the signatures by
     /// `KeyId`.
     pub fn build(self) -> SignedMetadata<D, M> {
@@ -337,12 +406,131 @@ where
     }
 }
 
+/// A request to sign metadata with a key that is not available locally, such as a root key kept on
+/// an air-gapped machine.
+///
+/// The request carries the metadata along with any signatures it already has, and the canonical
+/// bytes a signer must sign. It can be written to a file with [`SigningRequest::to_file`], carried
+/// to the signer, signed there with [`SigningRequest::sign`], and the resulting signatures
+/// imported with [`SignedMetadata::import_signatures`].
+///
+/// ```
+/// # use tuf::crypto::{PrivateKey, SignatureScheme};
+/// # use tuf::interchange::Json;
+/// # use tuf::metadata::{SignedMetadataBuilder, SigningRequest, SnapshotMetadata, SnapshotMetadataBuilder};
+/// #
+/// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+/// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let path = dir.path().join("snapshot.request.json");
+/// let snapshot = SnapshotMetadataBuilder::new().build().unwrap();
+/// let mut snapshot = SignedMetadataBuilder::<Json, _>::from_metadata(&snapshot)
+///     .unwrap()
+///     .build();
+/// snapshot.signing_request().unwrap().to_file(&path).unwrap();
+///
+/// // On the air-gapped machine.
+/// let request = SigningRequest::<Json, SnapshotMetadata>::from_file(&path).unwrap();
+/// let signature = request.sign(&key).unwrap();
+///
+/// // Back on the machine that publishes the metadata.
+/// snapshot
+///     .import_signatures(vec![signature], vec![key.public()])
+///     .unwrap();
+/// snapshot.verify(1, Some(key.public())).unwrap();
+/// ```
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct SigningRequest<D, M>
+where
+    D: DataInterchange,
+{
+    metadata: SignedMetadata<D, M>,
+    #[serde(with = "crate::format_hex")]
+    canonical_bytes: Vec<u8>,
+}
+
+impl<D, M> SigningRequest<D, M>
+where
+    D: DataInterchange,
+    M: Metadata,
+{
+    /// Create a new `SigningRequest` for `metadata`, which may already be partially signed.
+    pub fn new(metadata: SignedMetadata<D, M>) -> Result<Self> {
+        let _ensure_metadata_parses = metadata.assume_valid()?;
+        let canonical_bytes = metadata.canonical_bytes()?;
+        Ok(SigningRequest {
+            metadata,
+            canonical_bytes,
+        })
+    }
+
+    /// Read a `SigningRequest` from the file at `path`, checking that its canonical bytes match
+    /// its metadata.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let request: Self = D::from_reader(File::open(path)?)?;
+        request.check()?;
+        Ok(request)
+    }
+
+    /// Atomically write this request to the file at `path`.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut bytes = Vec::new();
+        D::to_writer(&mut bytes, self)?;
+        util::write_atomically(path.as_ref(), &bytes)
+    }
+
+    /// The metadata to be signed, with the signatures it had when the request was created.
+    pub fn metadata(&self) -> &SignedMetadata<D, M> {
+        &self.metadata
+    }
+
+    /// The canonical bytes of the metadata, which is what signers must sign.
+    pub fn canonical_bytes(&self) -> &[u8] {
+        &self.canonical_bytes
+    }
+
+    /// Sign the canonical bytes of the metadata with `signer`. Fails if the canonical bytes do not
+    /// match the metadata, so a signer never signs something other than what it was shown.
+    pub fn sign(&self, signer: &dyn Signer) -> Result<Signature> {
+        self.check()?;
+        crypto::sign_with(signer, &self.canonical_bytes)
+    }
+
+    fn check(&self) -> Result<()> {
+        let _ensure_metadata_parses = self.metadata.assume_valid()?;
+        if self.metadata.canonical_bytes()? != self.canonical_bytes {
+            return Err(Error::VerificationFailure(
+                "Signing request canonical bytes do not match its metadata".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<D, M> Debug for SigningRequest<D, M>
+where
+    D: DataInterchange,
+    SignedMetadata<D, M>: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SigningRequest")
+            .field("metadata", &self.metadata)
+            .field("canonical_bytes", &self.canonical_bytes)
+            .finish()
+    }
+}
+
 /// Serialized metadata with attached unverified signatures.
+///
+/// Signatures are kept sorted by `KeyId`, so serializing the same metadata with the same
+/// signatures is byte-identical no matter the order they were added or parsed in.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SignedMetadata<D, M>
 where
     D: DataInterchange,
 {
+    #[serde(deserialize_with = "deserialize_sorted_signatures")]
     signatures: Vec<Signature>,
     #[serde(rename = "signed")]
     metadata: D::RawData,
@@ -350,13 +538,26 @@ where
     _marker: PhantomData<M>,
 }
 
+/// Deserialize signatures, sorting them by `KeyId`. The sort is stable, so duplicate signatures
+/// stay in place to be rejected during verification.
+fn deserialize_sorted_signatures<'de, De>(
+    de: De,
+) -> ::std::result::Result<Vec<Signature>, De::Error>
+where
+    De: Deserializer<'de>,
+{
+    let mut signatures = Vec::<Signature>::deserialize(de)?;
+    signatures.sort_by(|a, b| a.key_id().cmp(b.key_id()));
+    Ok(signatures)
+}
+
 impl<D, M> SignedMetadata<D, M>
 where
     D: DataInterchange,
     M: Metadata,
 {
-    /// Create a new `SignedMetadata`. The supplied private key is used to sign the canonicalized
-    /// bytes of the provided metadata with the provided scheme.
+    /// Create a new `SignedMetadata`. The supplied signer is used to sign the canonicalized bytes
+    /// of the provided metadata with the signer's scheme.
     ///
     /// ```
     /// # use chrono::prelude::*;
@@ -372,10 +573,10 @@ where
     /// SignedMetadata::<Json, _>::new(&snapshot, &key).unwrap();
     /// # }
     /// ```
-    pub fn new(metadata: &M, private_key: &PrivateKey) -> Result<Self> {
-        let raw = D::serialize(metadata)?;
+    pub fn new(metadata: &M, signer: &dyn Signer) -> Result<Self> {
+        let raw = serialize_metadata::<D, M>(metadata)?;
         let bytes = D::canonicalize(&raw)?;
-        let sig = private_key.sign(&bytes)?;
+        let sig = crypto::sign_with(signer, &bytes)?;
         Ok(Self {
             signatures: vec![sig],
             metadata: raw,
@@ -383,9 +584,11 @@ where
         })
     }
 
-    /// Serialize this metadata to canonical bytes suitable for serialization. Note that this
-    /// method is only intended to serialize signed metadata generated by this crate, not to
-    /// re-serialize metadata that was originally obtained from a remote source.
+    /// Serialize this metadata to bytes suitable for serialization using `D::to_writer`. For
+    /// [Json](crate::interchange::Json) these are the canonical bytes, while
+    /// [JsonPretty](crate::interchange::JsonPretty) produces indented output with sorted keys.
+    /// Note that this method is only intended to serialize signed metadata generated by this
+    /// crate, not to re-serialize metadata that was originally obtained from a remote source.
     ///
     /// TUF metadata hashes are on the raw bytes of the metadata, so it is not guaranteed that the
     /// hash of the returned bytes will match a hash included in, for example, a snapshot metadata
@@ -396,10 +599,43 @@ where
     /// a signature. Metadata obtained from a remote source may have included different whitespace
     /// or ordered fields in a way that is not preserved when parsing that metadata.
     pub fn to_raw(&self) -> Result<RawSignedMetadata<D, M>> {
-        let bytes = D::canonicalize(&D::serialize(self)?)?;
+        let mut bytes = Vec::new();
+        D::to_writer(&mut bytes, self)?;
         Ok(RawSignedMetadata::new(bytes))
     }
 
+    /// Read and parse signed metadata from the file at `path`. The signatures are not verified.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        RawSignedMetadata::<D, M>::new(bytes).parse()
+    }
+
+    /// Atomically write this metadata to the file at `path`, as serialized by
+    /// [`SignedMetadata::to_raw`].
+    ///
+    /// The metadata is written to a temporary file next to `path`, synced to disk and then renamed
+    /// over `path`, so a crash never leaves truncated metadata behind.
+    ///
+    /// ```
+    /// # use tuf::crypto::{PrivateKey, SignatureScheme};
+    /// # use tuf::interchange::Json;
+    /// # use tuf::metadata::{SignedMetadata, SnapshotMetadata, SnapshotMetadataBuilder};
+    /// #
+    /// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+    /// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// let snapshot = SnapshotMetadataBuilder::new().signed::<Json>(&key).unwrap();
+    ///
+    /// let path = dir.path().join("snapshot.json");
+    /// snapshot.to_file(&path).unwrap();
+    ///
+    /// let read = SignedMetadata::<Json, SnapshotMetadata>::from_file(&path).unwrap();
+    /// assert_eq!(read, snapshot);
+    /// ```
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        util::write_atomically(path.as_ref(), self.to_raw()?.as_bytes())
+    }
+
     /// Append a signature to this signed metadata. Will overwrite signature by keys with the same
     /// ID.
     ///
@@ -433,38 +669,94 @@ where
     /// assert_eq!(snapshot.signatures().len(), 2);
     /// # }
     /// ```
-    pub fn add_signature(&mut self, private_key: &PrivateKey) -> Result<()> {
+    pub fn add_signature(&mut self, signer: &dyn Signer) -> Result<()> {
         let bytes = D::canonicalize(&self.metadata)?;
-        let sig = private_key.sign(&bytes)?;
-        self.signatures
-            .retain(|s| s.key_id() != private_key.key_id());
-        self.signatures.push(sig);
+        let sig = crypto::sign_with(signer, &bytes)?;
+        self.insert_signature(sig);
         Ok(())
     }
 
-    /// Merge the singatures from `other` into `self` if and only if
-    /// `self.as_ref() == other.as_ref()`. If `self` and `other` contain signatures from the same
-    /// key ID, then the signatures from `self` will replace the signatures from `other`.
+    /// The canonical bytes of the metadata, which is what signers must sign. External signing
+    /// services can sign these bytes and the result can be attached with
+    /// [`SignedMetadata::insert_signature`].
+    ///
+    /// ```
+    /// # use tuf::crypto::{PrivateKey, SignatureScheme};
+    /// # use tuf::interchange::Json;
+    /// # use tuf::metadata::{SignedMetadataBuilder, SnapshotMetadataBuilder};
+    /// #
+    /// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+    /// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+    /// let snapshot = SnapshotMetadataBuilder::new().build().unwrap();
+    /// let mut snapshot = SignedMetadataBuilder::<Json, _>::from_metadata(&snapshot)
+    ///     .unwrap()
+    ///     .build();
+    ///
+    /// // Hand the bytes to a signer that may live on another machine.
+    /// let signature = key.sign(&snapshot.canonical_bytes().unwrap()).unwrap();
+    ///
+    /// snapshot.insert_signature(signature);
+    /// snapshot.verify(1, Some(key.public())).unwrap();
+    /// ```
+    pub fn canonical_bytes(&self) -> Result<Vec<u8>> {
+        D::canonicalize(&self.metadata)
+    }
+
+    /// Attach a signature that was computed elsewhere over [`SignedMetadata::canonical_bytes`],
+    /// replacing any existing signature with the same `KeyId`. The signature is not checked until
+    /// the metadata is verified.
+    pub fn insert_signature(&mut self, signature: Signature) {
+        self.signatures.retain(|s| s.key_id() != signature.key_id());
+        let index = self
+            .signatures
+            .partition_point(|s| s.key_id() < signature.key_id());
+        self.signatures.insert(index, signature);
+    }
+
+    /// Merge the signatures from `other` into `self` if and only if both sign byte-identical
+    /// canonical metadata. If `self` and `other` contain signatures from the same key ID, the
+    /// signature from `self` is kept.
+    ///
+    /// This is how copies of the same metadata signed independently by several key holders are
+    /// combined.
+    ///
+    /// ```
+    /// # use tuf::crypto::{PrivateKey, SignatureScheme};
+    /// # use tuf::interchange::Json;
+    /// # use tuf::metadata::{SignedMetadataBuilder, SnapshotMetadataBuilder};
+    /// #
+    /// # let key_1: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+    /// # let key_1 = PrivateKey::from_pkcs8(&key_1, SignatureScheme::Ed25519).unwrap();
+    /// # let key_2: &[u8] = include_bytes!("../tests/ed25519/ed25519-2.pk8.der");
+    /// # let key_2 = PrivateKey::from_pkcs8(&key_2, SignatureScheme::Ed25519).unwrap();
+    /// let snapshot = SnapshotMetadataBuilder::new().build().unwrap();
+    /// let builder = SignedMetadataBuilder::<Json, _>::from_metadata(&snapshot).unwrap();
+    ///
+    /// // Each key holder signs their own copy.
+    /// let mut signed_1 = builder.clone().sign(&key_1).unwrap().build();
+    /// let signed_2 = builder.sign(&key_2).unwrap().build();
+    ///
+    /// signed_1.merge_signatures(&signed_2).unwrap();
+    /// signed_1.verify(2, vec![key_1.public(), key_2.public()]).unwrap();
+    /// ```
     pub fn merge_signatures(&mut self, other: &Self) -> Result<()> {
-        if self.metadata != other.metadata {
+        if D::canonicalize(&self.metadata)? != D::canonicalize(&other.metadata)? {
             return Err(Error::IllegalArgument(
                 "Attempted to merge unequal metadata".into(),
             ));
         }
 
-        let key_ids = self
+        let mut key_ids = self
             .signatures
             .iter()
             .map(|s| s.key_id().clone())
             .collect::<HashSet<KeyId>>();
 
-        self.signatures.extend(
-            other
-                .signatures
-                .iter()
-                .filter(|s| !key_ids.contains(s.key_id()))
-                .cloned(),
-        );
+        for sig in &other.signatures {
+            if key_ids.insert(sig.key_id().clone()) {
+                self.insert_signature(sig.clone());
+            }
+        }
 
         Ok(())
     }
@@ -474,6 +766,108 @@ where
         &self.signatures
     }
 
+    /// Create a [`SigningRequest`] for this metadata, to be signed on another machine.
+    pub fn signing_request(&self) -> Result<SigningRequest<D, M>> {
+        SigningRequest::new(SignedMetadata {
+            signatures: self.signatures.clone(),
+            metadata: self.metadata.clone(),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Import signatures that were produced elsewhere, for example by [`SigningRequest::sign`],
+    /// replacing any existing signatures with the same `KeyId`.
+    ///
+    /// Every signature must have been made by one of `authorized_keys` over
+    /// [`SignedMetadata::canonical_bytes`]. If any signature fails this check, none are imported.
+    pub fn import_signatures<'a, S, I>(&mut self, signatures: S, authorized_keys: I) -> Result<()>
+    where
+        S: IntoIterator<Item = Signature>,
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
+        let authorized_keys = authorized_keys
+            .into_iter()
+            .map(|k| (k.key_id(), k))
+            .collect::<HashMap<_, _>>();
+        let bytes = D::canonicalize(&self.metadata)?;
+
+        let signatures = signatures.into_iter().collect::<Vec<_>>();
+        for sig in &signatures {
+            let key = authorized_keys.get(sig.key_id()).ok_or_else(|| {
+                Error::VerificationFailure(format!(
+                    "Signature by key {:?} is not from an authorized key",
+                    sig.key_id()
+                ))
+            })?;
+            key.verify(&bytes, sig).map_err(|e| {
+                Error::VerificationFailure(format!(
+                    "Signature by key {:?} does not match the metadata: {}",
+                    sig.key_id(),
+                    e
+                ))
+            })?;
+        }
+
+        for sig in signatures {
+            self.insert_signature(sig);
+        }
+
+        Ok(())
+    }
+
+    /// Remove the signature made by the key with ID `key_id`, returning it if there was one. The
+    /// metadata itself is left untouched.
+    ///
+    /// ```
+    /// # use tuf::crypto::{PrivateKey, SignatureScheme};
+    /// # use tuf::interchange::Json;
+    /// # use tuf::metadata::{SignedMetadata, SnapshotMetadataBuilder};
+    /// #
+    /// # let key_1: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+    /// # let key_1 = PrivateKey::from_pkcs8(&key_1, SignatureScheme::Ed25519).unwrap();
+    /// # let key_2: &[u8] = include_bytes!("../tests/ed25519/ed25519-2.pk8.der");
+    /// # let key_2 = PrivateKey::from_pkcs8(&key_2, SignatureScheme::Ed25519).unwrap();
+    /// let snapshot = SnapshotMetadataBuilder::new().build().unwrap();
+    /// let mut snapshot = SignedMetadata::<Json, _>::new(&snapshot, &key_1).unwrap();
+    /// snapshot.add_signature(&key_2).unwrap();
+    ///
+    /// assert!(snapshot.remove_signature(key_1.key_id()).is_some());
+    /// assert!(snapshot.remove_signature(key_1.key_id()).is_none());
+    /// assert_eq!(snapshot.signatures().len(), 1);
+    /// assert_eq!(snapshot.signatures()[0].key_id(), key_2.key_id());
+    /// ```
+    pub fn remove_signature(&mut self, key_id: &KeyId) -> Option<Signature> {
+        let index = self.signatures.iter().position(|s| s.key_id() == key_id)?;
+        Some(self.signatures.remove(index))
+    }
+
+    /// Replace the signature made by the key with ID `old_key_id` with a new signature from
+    /// `signer`, returning the removed signature. This is useful when resigning metadata after a
+    /// key rotation.
+    ///
+    /// Fails without modifying the signatures if there is no signature by `old_key_id`, or if
+    /// `signer` fails to sign.
+    pub fn replace_signature(
+        &mut self,
+        old_key_id: &KeyId,
+        signer: &dyn Signer,
+    ) -> Result<Signature> {
+        let index = self
+            .signatures
+            .iter()
+            .position(|s| s.key_id() == old_key_id)
+            .ok_or_else(|| {
+                Error::IllegalArgument(format!("Metadata has no signature by key {:?}", old_key_id))
+            })?;
+
+        let bytes = D::canonicalize(&self.metadata)?;
+        let sig = crypto::sign_with(signer, &bytes)?;
+
+        let old = self.signatures.remove(index);
+        self.insert_signature(sig);
+        Ok(old)
+    }
+
     /// Parse the version number of this metadata without verifying signatures.
     ///
     /// This operation is generally unsafe to do with metadata obtained from an untrusted source,
@@ -498,6 +892,8 @@ where
 
     /// Verify this metadata.
     ///
+    /// With the `parallel` feature, the signatures are checked in parallel.
+    ///
     /// ```
     /// # use chrono::prelude::*;
     /// # use tuf::crypto::{PrivateKey, SignatureScheme, HashAlgorithm};
@@ -538,6 +934,20 @@ where
     /// ).is_err());
     /// # }
     pub fn verify<'a, I>(&self, threshold: u32, authorized_keys: I) -> Result<M>
+    where
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
+        self.verify_with_policy(threshold, authorized_keys, &SchemePolicy::default())
+    }
+
+    /// Verify this metadata like [`SignedMetadata::verify`], only counting signatures that
+    /// `policy` permits towards the threshold.
+    pub fn verify_with_policy<'a, I>(
+        &self,
+        threshold: u32,
+        authorized_keys: I,
+        policy: &SchemePolicy,
+    ) -> Result<M>
     where
         I: IntoIterator<Item = &'a PublicKey>,
     {
@@ -560,24 +970,16 @@ where
 
         let canonical_bytes = D::canonicalize(&self.metadata)?;
 
-        let mut signatures_needed = threshold;
         // Create a key_id->signature map to deduplicate the key_ids.
         let signatures = self
             .signatures
             .iter()
             .map(|sig| (sig.key_id(), sig))
             .collect::<HashMap<&KeyId, &Signature>>();
+        let mut candidates = Vec::with_capacity(signatures.len());
         for (key_id, sig) in signatures {
             match authorized_keys.get(key_id) {
-                Some(ref pub_key) => match pub_key.verify(&canonical_bytes, sig) {
-                    Ok(()) => {
-                        debug!("Good signature from key ID {:?}", pub_key.key_id());
-                        signatures_needed -= 1;
-                    }
-                    Err(e) => {
-                        warn!("Bad signature from key ID {:?}: {:?}", pub_key.key_id(), e);
-                    }
-                },
+                Some(pub_key) => candidates.push((*pub_key, sig)),
                 None => {
                     warn!(
                         "Key ID {:?} was not found in the set of authorized keys.",
@@ -585,15 +987,14 @@ where
                     );
                 }
             }
-            if signatures_needed == 0 {
-                break;
-            }
         }
-        if signatures_needed > 0 {
+
+        let good_signatures =
+            count_good_signatures(&candidates, &canonical_bytes, threshold, policy);
+        if good_signatures < threshold {
             return Err(Error::VerificationFailure(format!(
                 "Signature threshold not met: {}/{}",
-                threshold - signatures_needed,
-                threshold
+                good_signatures, threshold
             )));
         }
 
@@ -602,10 +1003,90 @@ where
     }
 }
 
+/// Verify `sig` over `msg` with `pub_key`, logging the outcome.
+fn check_signature(
+    pub_key: &PublicKey,
+    msg: &[u8],
+    sig: &Signature,
+    policy: &SchemePolicy,
+) -> bool {
+    match pub_key.verify_with_policy(msg, sig, policy) {
+        Ok(()) => {
+            debug!("Good signature from key ID {:?}", pub_key.key_id());
+            true
+        }
+        Err(e) => {
+            warn!("Bad signature from key ID {:?}: {:?}", pub_key.key_id(), e);
+            false
+        }
+    }
+}
+
+/// Count the good signatures in `candidates`, stopping once `threshold` of them are found.
+#[cfg(not(feature = "parallel"))]
+fn count_good_signatures(
+    candidates: &[(&PublicKey, &Signature)],
+    msg: &[u8],
+    threshold: u32,
+    policy: &SchemePolicy,
+) -> u32 {
+    let mut good_signatures = 0;
+    for (pub_key, sig) in candidates {
+        if check_signature(pub_key, msg, sig, policy) {
+            good_signatures += 1;
+        }
+        if good_signatures == threshold {
+            break;
+        }
+    }
+    good_signatures
+}
+
+/// Count the good signatures in `candidates`, stopping once `threshold` of them are found. The
+/// signatures are checked in parallel, so a few more than `threshold` may be checked.
+#[cfg(feature = "parallel")]
+fn count_good_signatures(
+    candidates: &[(&PublicKey, &Signature)],
+    msg: &[u8],
+    threshold: u32,
+    policy: &SchemePolicy,
+) -> u32 {
+    use rayon::prelude::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let good_signatures = AtomicU32::new(0);
+    // `try_for_each` stops scheduling work at the first `Err`, which is used to signal that the
+    // threshold has been met.
+    let _ = candidates.par_iter().try_for_each(|(pub_key, sig)| {
+        if good_signatures.load(Ordering::SeqCst) >= threshold {
+            return Err(());
+        }
+        if check_signature(pub_key, msg, sig, policy)
+            && good_signatures.fetch_add(1, Ordering::SeqCst) + 1 >= threshold
+        {
+            return Err(());
+        }
+        Ok(())
+    });
+    good_signatures.load(Ordering::SeqCst).min(threshold)
+}
+
+/// Reject an expiration that has already passed, unless `allow_expired` is set.
+fn check_expires(expires: &DateTime<Utc>, allow_expired: bool) -> Result<()> {
+    if !allow_expired && *expires <= Utc::now() {
+        return Err(Error::IllegalArgument(format!(
+            "Metadata would already be expired at {}",
+            expires
+        )));
+    }
+    Ok(())
+}
+
 /// Helper to construct `RootMetadata`.
 pub struct RootMetadataBuilder {
     version: u32,
     expires: DateTime<Utc>,
+    allow_expired: bool,
     consistent_snapshot: bool,
     keys: HashMap<KeyId, PublicKey>,
     root_threshold: u32,
@@ -616,6 +1097,7 @@ pub struct RootMetadataBuilder {
     targets_key_ids: Vec<KeyId>,
     timestamp_threshold: u32,
     timestamp_key_ids: Vec<KeyId>,
+    additional_roles: HashMap<String, (u32, Vec<KeyId>)>,
 }
 
 impl RootMetadataBuilder {
@@ -629,6 +1111,7 @@ impl RootMetadataBuilder {
         RootMetadataBuilder {
             version: 1,
             expires: Utc::now() + Duration::days(365),
+            allow_expired: false,
             consistent_snapshot: false,
             keys: HashMap::new(),
             root_threshold: 1,
@@ -639,6 +1122,7 @@ impl RootMetadataBuilder {
             targets_key_ids: Vec::new(),
             timestamp_threshold: 1,
             timestamp_key_ids: Vec::new(),
+            additional_roles: HashMap::new(),
         }
     }
 
@@ -654,6 +1138,19 @@ impl RootMetadataBuilder {
         self
     }
 
+    /// Set this metadata to expire `duration` from the current time.
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.expires = Utc::now() + duration;
+        self
+    }
+
+    /// Allow building metadata that has already expired, which is otherwise rejected. This is
+    /// only useful for tests and for reproducing old metadata.
+    pub fn allow_expired(mut self, allow: bool) -> Self {
+        self.allow_expired = allow;
+        self
+    }
+
     /// Set this metadata to have a consistent snapshot.
     pub fn consistent_snapshot(mut self, consistent_snapshot: bool) -> Self {
         self.consistent_snapshot = consistent_snapshot;
@@ -668,9 +1165,21 @@ impl RootMetadataBuilder {
 
     /// Add a root public key.
     pub fn root_key(mut self, public_key: PublicKey) -> Self {
-        let key_id = public_key.key_id().clone();
-        self.keys.insert(key_id.clone(), public_key);
-        self.root_key_ids.push(key_id);
+        let key_id = self.insert_key(public_key);
+        if !self.root_key_ids.contains(&key_id) {
+            self.root_key_ids.push(key_id);
+        }
+        self
+    }
+
+    /// Set the root role to be signed by `threshold` of `keys`, replacing any keys previously added
+    /// to it.
+    pub fn root_role<I>(mut self, keys: I, threshold: u32) -> Self
+    where
+        I: IntoIterator<Item = PublicKey>,
+    {
+        self.root_key_ids = self.insert_keys(keys);
+        self.root_threshold = threshold;
         self
     }
 
@@ -682,9 +1191,21 @@ impl RootMetadataBuilder {
 
     /// Add a snapshot public key.
     pub fn snapshot_key(mut self, public_key: PublicKey) -> Self {
-        let key_id = public_key.key_id().clone();
-        self.keys.insert(key_id.clone(), public_key);
-        self.snapshot_key_ids.push(key_id);
+        let key_id = self.insert_key(public_key);
+        if !self.snapshot_key_ids.contains(&key_id) {
+            self.snapshot_key_ids.push(key_id);
+        }
+        self
+    }
+
+    /// Set the snapshot role to be signed by `threshold` of `keys`, replacing any keys previously added
+    /// to it.
+    pub fn snapshot_role<I>(mut self, keys: I, threshold: u32) -> Self
+    where
+        I: IntoIterator<Item = PublicKey>,
+    {
+        self.snapshot_key_ids = self.insert_keys(keys);
+        self.snapshot_threshold = threshold;
         self
     }
 
@@ -696,9 +1217,21 @@ impl RootMetadataBuilder {
 
     /// Add a targets public key.
     pub fn targets_key(mut self, public_key: PublicKey) -> Self {
-        let key_id = public_key.key_id().clone();
-        self.keys.insert(key_id.clone(), public_key);
-        self.targets_key_ids.push(key_id);
+        let key_id = self.insert_key(public_key);
+        if !self.targets_key_ids.contains(&key_id) {
+            self.targets_key_ids.push(key_id);
+        }
+        self
+    }
+
+    /// Set the targets role to be signed by `threshold` of `keys`, replacing any keys previously added
+    /// to it.
+    pub fn targets_role<I>(mut self, keys: I, threshold: u32) -> Self
+    where
+        I: IntoIterator<Item = PublicKey>,
+    {
+        self.targets_key_ids = self.insert_keys(keys);
+        self.targets_threshold = threshold;
         self
     }
 
@@ -710,56 +1243,175 @@ impl RootMetadataBuilder {
 
     /// Add a timestamp public key.
     pub fn timestamp_key(mut self, public_key: PublicKey) -> Self {
-        let key_id = public_key.key_id().clone();
-        self.keys.insert(key_id.clone(), public_key);
-        self.timestamp_key_ids.push(key_id);
+        let key_id = self.insert_key(public_key);
+        if !self.timestamp_key_ids.contains(&key_id) {
+            self.timestamp_key_ids.push(key_id);
+        }
         self
     }
 
-    /// Construct a new `RootMetadata`.
-    pub fn build(self) -> Result<RootMetadata> {
-        RootMetadata::new(
-            self.version,
-            self.expires,
-            self.consistent_snapshot,
-            self.keys,
-            RoleDefinition::new(self.root_threshold, self.root_key_ids)?,
-            RoleDefinition::new(self.snapshot_threshold, self.snapshot_key_ids)?,
-            RoleDefinition::new(self.targets_threshold, self.targets_key_ids)?,
-            RoleDefinition::new(self.timestamp_threshold, self.timestamp_key_ids)?,
-        )
-    }
-
-    /// Construct a new `SignedMetadata<D, RootMetadata>`.
-    pub fn signed<D>(self, private_key: &PrivateKey) -> Result<SignedMetadata<D, RootMetadata>>
+    /// Set the timestamp role to be signed by `threshold` of `keys`, replacing any keys previously added
+    /// to it.
+    pub fn timestamp_role<I>(mut self, keys: I, threshold: u32) -> Self
     where
-        D: DataInterchange,
+        I: IntoIterator<Item = PublicKey>,
     {
-        SignedMetadata::new(&self.build()?, private_key)
+        self.timestamp_key_ids = self.insert_keys(keys);
+        self.timestamp_threshold = threshold;
+        self
     }
-}
 
-impl Default for RootMetadataBuilder {
-    fn default() -> Self {
-        RootMetadataBuilder::new()
+    /// Set the threshold of the role `name`, which is defined alongside the top-level roles, such
+    /// as the `mirrors` role or an organization-specific role. Its threshold defaults to 1.
+    pub fn role_threshold(mut self, name: &str, threshold: u32) -> Self {
+        self.additional_roles
+            .entry(name.into())
+            .or_insert_with(|| (1, Vec::new()))
+            .0 = threshold;
+        self
     }
-}
 
-impl From<RootMetadata> for RootMetadataBuilder {
-    fn from(metadata: RootMetadata) -> Self {
-        RootMetadataBuilder {
-            version: metadata.version,
-            expires: metadata.expires,
-            consistent_snapshot: metadata.consistent_snapshot,
-            keys: metadata.keys,
-            root_threshold: metadata.root.threshold,
-            root_key_ids: metadata.root.key_ids,
-            snapshot_threshold: metadata.snapshot.threshold,
+    /// Add a public key of the role `name`, which is defined alongside the top-level roles. See
+    /// [`RootMetadataBuilder::role_threshold`].
+    ///
+    /// ```
+    /// # use tuf::crypto::{PrivateKey, SignatureScheme};
+    /// # use tuf::metadata::RootMetadataBuilder;
+    /// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+    /// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+    /// let root = RootMetadataBuilder::new()
+    ///     .root_key(key.public().clone())
+    ///     .snapshot_key(key.public().clone())
+    ///     .targets_key(key.public().clone())
+    ///     .timestamp_key(key.public().clone())
+    ///     .role_key("mirrors", key.public().clone())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let mirrors = root.role_definition("mirrors").unwrap();
+    /// assert_eq!(mirrors.threshold(), 1);
+    /// assert_eq!(mirrors.key_ids(), &[key.key_id().clone()]);
+    /// ```
+    pub fn role_key(mut self, name: &str, public_key: PublicKey) -> Self {
+        let key_id = self.insert_key(public_key);
+        let key_ids = &mut self
+            .additional_roles
+            .entry(name.into())
+            .or_insert_with(|| (1, Vec::new()))
+            .1;
+        if !key_ids.contains(&key_id) {
+            key_ids.push(key_id);
+        }
+        self
+    }
+
+    /// Set the role `name`, which is defined alongside the top-level roles, to be signed by
+    /// `threshold` of `keys`, replacing any keys previously added to it.
+    pub fn role<I>(mut self, name: &str, keys: I, threshold: u32) -> Self
+    where
+        I: IntoIterator<Item = PublicKey>,
+    {
+        let key_ids = self.insert_keys(keys);
+        let _ = self
+            .additional_roles
+            .insert(name.into(), (threshold, key_ids));
+        self
+    }
+
+    /// Add `public_key` to the set of keys, returning its ID.
+    fn insert_key(&mut self, public_key: PublicKey) -> KeyId {
+        let key_id = public_key.key_id().clone();
+        self.keys.insert(key_id.clone(), public_key);
+        key_id
+    }
+
+    /// Add `keys` to the set of keys, returning their distinct IDs in order.
+    fn insert_keys<I>(&mut self, keys: I) -> Vec<KeyId>
+    where
+        I: IntoIterator<Item = PublicKey>,
+    {
+        let mut key_ids = Vec::new();
+        for key in keys {
+            let key_id = self.insert_key(key);
+            if !key_ids.contains(&key_id) {
+                key_ids.push(key_id);
+            }
+        }
+        key_ids
+    }
+
+    /// Construct a new `RootMetadata`.
+    pub fn build(self) -> Result<RootMetadata> {
+        check_expires(&self.expires, self.allow_expired)?;
+        let additional_roles = self
+            .additional_roles
+            .into_iter()
+            .map(|(name, (threshold, key_ids))| {
+                let role = role_definition(&name, threshold, key_ids)?;
+                Ok((name, role))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        RootMetadata::new(
+            self.version,
+            self.expires,
+            self.consistent_snapshot,
+            self.keys,
+            role_definition("root", self.root_threshold, self.root_key_ids)?,
+            role_definition("snapshot", self.snapshot_threshold, self.snapshot_key_ids)?,
+            role_definition("targets", self.targets_threshold, self.targets_key_ids)?,
+            role_definition(
+                "timestamp",
+                self.timestamp_threshold,
+                self.timestamp_key_ids,
+            )?,
+        )?
+        .with_additional_roles(additional_roles)
+    }
+
+    /// Construct a new `SignedMetadata<D, RootMetadata>`.
+    pub fn signed<D>(self, signer: &dyn Signer) -> Result<SignedMetadata<D, RootMetadata>>
+    where
+        D: DataInterchange,
+    {
+        SignedMetadata::new(&self.build()?, signer)
+    }
+}
+
+/// Create a `RoleDefinition`, naming the role in any error.
+fn role_definition(name: &str, threshold: u32, key_ids: Vec<KeyId>) -> Result<RoleDefinition> {
+    RoleDefinition::new(threshold, key_ids).map_err(|e| match e {
+        Error::IllegalArgument(msg) => Error::IllegalArgument(format!("Role {}: {}", name, msg)),
+        e => e,
+    })
+}
+
+impl Default for RootMetadataBuilder {
+    fn default() -> Self {
+        RootMetadataBuilder::new()
+    }
+}
+
+impl From<RootMetadata> for RootMetadataBuilder {
+    fn from(metadata: RootMetadata) -> Self {
+        RootMetadataBuilder {
+            version: metadata.version,
+            expires: metadata.expires,
+            allow_expired: false,
+            consistent_snapshot: metadata.consistent_snapshot,
+            keys: metadata.keys,
+            root_threshold: metadata.root.threshold,
+            root_key_ids: metadata.root.key_ids,
+            snapshot_threshold: metadata.snapshot.threshold,
             snapshot_key_ids: metadata.snapshot.key_ids,
             targets_threshold: metadata.targets.threshold,
             targets_key_ids: metadata.targets.key_ids,
             timestamp_threshold: metadata.timestamp.threshold,
             timestamp_key_ids: metadata.timestamp.key_ids,
+            additional_roles: metadata
+                .additional_roles
+                .into_iter()
+                .map(|(name, role)| (name, (role.threshold, role.key_ids)))
+                .collect(),
         }
     }
 }
@@ -775,6 +1427,7 @@ pub struct RootMetadata {
     snapshot: RoleDefinition,
     targets: RoleDefinition,
     timestamp: RoleDefinition,
+    additional_roles: HashMap<String, RoleDefinition>,
 }
 
 impl RootMetadata {
@@ -805,9 +1458,37 @@ impl RootMetadata {
             snapshot,
             targets,
             timestamp,
+            additional_roles: HashMap::new(),
         })
     }
 
+    /// Define `roles` alongside the top-level roles, replacing any previously defined. Their names
+    /// must not be those of the top-level roles.
+    pub fn with_additional_roles(mut self, roles: HashMap<String, RoleDefinition>) -> Result<Self> {
+        for name in roles.keys() {
+            match Role::from_name(name) {
+                Some(Role::Root)
+                | Some(Role::Snapshot)
+                | Some(Role::Targets)
+                | Some(Role::Timestamp) => {
+                    return Err(Error::IllegalArgument(format!(
+                        "Role {} is already defined by the root metadata",
+                        name
+                    )));
+                }
+                _ if name.is_empty() => {
+                    return Err(Error::IllegalArgument(
+                        "Cannot define a role with an empty name".into(),
+                    ));
+                }
+                _ => (),
+            }
+        }
+
+        self.additional_roles = roles;
+        Ok(self)
+    }
+
     /// Whether or not this repository is currently implementing that TUF consistent snapshot
     /// feature.
     pub fn consistent_snapshot(&self) -> bool {
@@ -838,6 +1519,23 @@ impl RootMetadata {
     pub fn timestamp(&self) -> &RoleDefinition {
         &self.timestamp
     }
+
+    /// The definitions of the roles besides the top-level roles, such as the `mirrors` role, keyed
+    /// by name.
+    pub fn additional_roles(&self) -> &HashMap<String, RoleDefinition> {
+        &self.additional_roles
+    }
+
+    /// The definition of the role `name`, whether it is a top-level role or not.
+    pub fn role_definition(&self, name: &str) -> Option<&RoleDefinition> {
+        match Role::from_name(name) {
+            Some(Role::Root) => Some(&self.root),
+            Some(Role::Snapshot) => Some(&self.snapshot),
+            Some(Role::Targets) => Some(&self.targets),
+            Some(Role::Timestamp) => Some(&self.timestamp),
+            _ => self.additional_roles.get(name),
+        }
+    }
 }
 
 impl Metadata for RootMetadata {
@@ -985,7 +1683,23 @@ impl MetadataPath {
     ///            MetadataPath::new("timestamp").unwrap());
     /// ```
     pub fn from_role(role: &Role) -> Self {
-        Self::new(format!("{}", role)).unwrap()
+        MetadataPath(role.name().into())
+    }
+
+    /// The top-level role this path names, or `None` if it names a delegated targets role.
+    ///
+    /// ```
+    /// # use tuf::metadata::{MetadataPath, Role};
+    /// assert_eq!(MetadataPath::new("snapshot").unwrap().role(), Some(Role::Snapshot));
+    /// assert_eq!(MetadataPath::new("projects/foo").unwrap().role(), None);
+    /// ```
+    pub fn role(&self) -> Option<Role> {
+        Role::from_name(&self.0)
+    }
+
+    /// The path as a string, which is also the name of the role.
+    pub fn as_str(&self) -> &str {
+        &self.0
     }
 
     /// Split `MetadataPath` into components that can be joined to create URL paths, Unix paths, or
@@ -1033,6 +1747,7 @@ impl<'de> Deserialize<'de> for MetadataPath {
 pub struct TimestampMetadataBuilder {
     version: u32,
     expires: DateTime<Utc>,
+    allow_expired: bool,
     snapshot: MetadataDescription,
 }
 
@@ -1060,6 +1775,42 @@ impl TimestampMetadataBuilder {
         Ok(Self::from_metadata_description(description))
     }
 
+    /// Create a new `TimestampMetadataBuilder` that only lists the version of a given snapshot,
+    /// leaving out its length and hashes to keep the timestamp metadata small. Clients then only
+    /// check the version of the snapshot metadata, and bound its download with their own limit.
+    /// It defaults to:
+    ///
+    /// * version: 1
+    /// * expires: 1 day from the current time.
+    ///
+    /// ```
+    /// # use tuf::crypto::{PrivateKey, SignatureScheme};
+    /// # use tuf::interchange::Json;
+    /// # use tuf::metadata::{SnapshotMetadataBuilder, TimestampMetadataBuilder};
+    /// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+    /// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+    /// let snapshot = SnapshotMetadataBuilder::new()
+    ///     .version(3)
+    ///     .signed::<Json>(&key)
+    ///     .unwrap();
+    /// let timestamp = TimestampMetadataBuilder::from_snapshot_version(&snapshot)
+    ///     .unwrap()
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(timestamp.snapshot().version(), 3);
+    /// assert_eq!(timestamp.snapshot().length(), None);
+    /// assert!(timestamp.snapshot().hashes().is_empty());
+    /// ```
+    pub fn from_snapshot_version<D, M>(snapshot: &SignedMetadata<D, M>) -> Result<Self>
+    where
+        D: DataInterchange,
+        M: Metadata,
+    {
+        let description = MetadataDescription::from_version(snapshot.parse_version_untrusted()?)?;
+        Ok(Self::from_metadata_description(description))
+    }
+
     /// Create a new `TimestampMetadataBuilder` from a given
     /// `MetadataDescription`. It defaults to:
     ///
@@ -1069,6 +1820,7 @@ impl TimestampMetadataBuilder {
         TimestampMetadataBuilder {
             version: 1,
             expires: Utc::now() + Duration::days(1),
+            allow_expired: false,
             snapshot: description,
         }
     }
@@ -1085,17 +1837,31 @@ impl TimestampMetadataBuilder {
         self
     }
 
+    /// Set this metadata to expire `duration` from the current time.
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.expires = Utc::now() + duration;
+        self
+    }
+
+    /// Allow building metadata that has already expired, which is otherwise rejected. This is
+    /// only useful for tests and for reproducing old metadata.
+    pub fn allow_expired(mut self, allow: bool) -> Self {
+        self.allow_expired = allow;
+        self
+    }
+
     /// Construct a new `TimestampMetadata`.
     pub fn build(self) -> Result<TimestampMetadata> {
+        check_expires(&self.expires, self.allow_expired)?;
         TimestampMetadata::new(self.version, self.expires, self.snapshot)
     }
 
     /// Construct a new `SignedMetadata<D, TimestampMetadata>`.
-    pub fn signed<D>(self, private_key: &PrivateKey) -> Result<SignedMetadata<D, TimestampMetadata>>
+    pub fn signed<D>(self, signer: &dyn Signer) -> Result<SignedMetadata<D, TimestampMetadata>>
     where
         D: DataInterchange,
     {
-        SignedMetadata::new(&self.build()?, private_key)
+        SignedMetadata::new(&self.build()?, signer)
     }
 }
 
@@ -1167,10 +1933,15 @@ impl<'de> Deserialize<'de> for TimestampMetadata {
 }
 
 /// Description of a piece of metadata, used in verification.
+///
+/// The length and hashes are optional. A description without them only pins the version of the
+/// metadata, in which case clients skip the length and hash checks.
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct MetadataDescription {
     version: u32,
-    length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length: Option<usize>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
     hashes: HashMap<HashAlgorithm, HashValue>,
 }
 
@@ -1197,7 +1968,7 @@ impl MetadataDescription {
 
         Ok(MetadataDescription {
             version,
-            length: length as usize,
+            length: Some(length as usize),
             hashes,
         })
     }
@@ -1221,6 +1992,40 @@ impl MetadataDescription {
             ));
         }
 
+        Ok(MetadataDescription {
+            version,
+            length: Some(length),
+            hashes,
+        })
+    }
+
+    /// Create a new `MetadataDescription` that only records the version of the metadata.
+    ///
+    /// ```
+    /// # use tuf::metadata::MetadataDescription;
+    /// let description = MetadataDescription::from_version(3).unwrap();
+    /// assert_eq!(description.version(), 3);
+    /// assert_eq!(description.length(), None);
+    /// assert!(description.hashes().is_empty());
+    ///
+    /// assert!(MetadataDescription::from_version(0).is_err());
+    /// ```
+    pub fn from_version(version: u32) -> Result<Self> {
+        Self::from_parts(version, None, HashMap::new())
+    }
+
+    pub(crate) fn from_parts(
+        version: u32,
+        length: Option<usize>,
+        hashes: HashMap<HashAlgorithm, HashValue>,
+    ) -> Result<Self> {
+        if version < 1 {
+            return Err(Error::IllegalArgument(format!(
+                "Metadata version must be greater than zero. Found: {}",
+                version
+            )));
+        }
+
         Ok(MetadataDescription {
             version,
             length,
@@ -1233,12 +2038,13 @@ impl MetadataDescription {
         self.version
     }
 
-    /// The length of the described metadata.
-    pub fn length(&self) -> usize {
+    /// The length of the described metadata, if known.
+    pub fn length(&self) -> Option<usize> {
         self.length
     }
 
-    /// An immutable reference to the hashes of the described metadata.
+    /// An immutable reference to the hashes of the described metadata. This is empty if the
+    /// description only records the version.
     pub fn hashes(&self) -> &HashMap<HashAlgorithm, HashValue> {
         &self.hashes
     }
@@ -1257,6 +2063,7 @@ impl<'de> Deserialize<'de> for MetadataDescription {
 pub struct SnapshotMetadataBuilder {
     version: u32,
     expires: DateTime<Utc>,
+    allow_expired: bool,
     meta: HashMap<MetadataPath, MetadataDescription>,
 }
 
@@ -1269,6 +2076,7 @@ impl SnapshotMetadataBuilder {
         SnapshotMetadataBuilder {
             version: 1,
             expires: Utc::now() + Duration::days(7),
+            allow_expired: false,
             meta: HashMap::new(),
         }
     }
@@ -1285,6 +2093,19 @@ impl SnapshotMetadataBuilder {
         self
     }
 
+    /// Set this metadata to expire `duration` from the current time.
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.expires = Utc::now() + duration;
+        self
+    }
+
+    /// Allow building metadata that has already expired, which is otherwise rejected. This is
+    /// only useful for tests and for reproducing old metadata.
+    pub fn allow_expired(mut self, allow: bool) -> Self {
+        self.allow_expired = allow;
+        self
+    }
+
     /// Add metadata to this snapshot metadata using the default path.
     pub fn insert_metadata<D, M>(
         self,
@@ -1321,6 +2142,32 @@ impl SnapshotMetadataBuilder {
         Ok(self.insert_metadata_description(path, description))
     }
 
+    /// Add a version-only description of `metadata` to this snapshot metadata using the default
+    /// path. Clients only check the version of such metadata, not its length or hashes.
+    pub fn insert_metadata_version<D, M>(self, metadata: &SignedMetadata<D, M>) -> Result<Self>
+    where
+        M: Metadata,
+        D: DataInterchange,
+    {
+        self.insert_metadata_version_with_path(M::ROLE.name(), metadata)
+    }
+
+    /// Add a version-only description of `metadata` to this snapshot metadata using a custom path.
+    pub fn insert_metadata_version_with_path<P, D, M>(
+        self,
+        path: P,
+        metadata: &SignedMetadata<D, M>,
+    ) -> Result<Self>
+    where
+        P: Into<String>,
+        M: Metadata,
+        D: DataInterchange,
+    {
+        let description = MetadataDescription::from_version(metadata.parse_version_untrusted()?)?;
+        let path = MetadataPath::new(path)?;
+        Ok(self.insert_metadata_description(path, description))
+    }
+
     /// Add `MetadataDescription` to this snapshot metadata using a custom path.
     pub fn insert_metadata_description(
         mut self,
@@ -1333,15 +2180,16 @@ impl SnapshotMetadataBuilder {
 
     /// Construct a new `SnapshotMetadata`.
     pub fn build(self) -> Result<SnapshotMetadata> {
+        check_expires(&self.expires, self.allow_expired)?;
         SnapshotMetadata::new(self.version, self.expires, self.meta)
     }
 
     /// Construct a new `SignedMetadata<D, SnapshotMetadata>`.
-    pub fn signed<D>(self, private_key: &PrivateKey) -> Result<SignedMetadata<D, SnapshotMetadata>>
+    pub fn signed<D>(self, signer: &dyn Signer) -> Result<SignedMetadata<D, SnapshotMetadata>>
     where
         D: DataInterchange,
     {
-        SignedMetadata::new(&self.build()?, private_key)
+        SignedMetadata::new(&self.build()?, signer)
     }
 }
 
@@ -1356,6 +2204,7 @@ impl From<SnapshotMetadata> for SnapshotMetadataBuilder {
         SnapshotMetadataBuilder {
             version: meta.version,
             expires: meta.expires,
+            allow_expired: false,
             meta: meta.meta,
         }
     }
@@ -1649,6 +2498,20 @@ impl TargetDescription {
         })
     }
 
+    /// Read from the given `AsyncRead` and calculate the length and hash values, like
+    /// [`TargetDescription::from_reader`].
+    pub async fn from_async_reader<R>(read: R, hash_algs: &[HashAlgorithm]) -> Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let (length, hashes) = crypto::calculate_hashes_async(read, hash_algs).await?;
+        Ok(TargetDescription {
+            length,
+            hashes,
+            custom: None,
+        })
+    }
+
     /// Read the from the given reader and custom metadata and calculate the length and hash
     /// values.
     ///
@@ -1714,6 +2577,215 @@ impl TargetDescription {
     pub fn custom(&self) -> Option<&HashMap<String, serde_json::Value>> {
         self.custom.as_ref()
     }
+
+    /// Replace the custom metadata.
+    pub fn with_custom(mut self, custom: HashMap<String, serde_json::Value>) -> Self {
+        self.custom = Some(custom);
+        self
+    }
+
+    /// Set a single custom metadata field to the serialized `value`, replacing any existing
+    /// value for `key`.
+    ///
+    /// ```
+    /// # use serde_derive::{Deserialize, Serialize};
+    /// # use tuf::crypto::HashAlgorithm;
+    /// # use tuf::metadata::TargetDescription;
+    /// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    /// #[serde(rename_all = "lowercase")]
+    /// enum Severity {
+    ///     Low,
+    ///     Critical,
+    /// }
+    ///
+    /// let description = TargetDescription::from_reader(&b"package"[..], &[HashAlgorithm::Sha256])
+    ///     .unwrap()
+    ///     .with_custom_field("version", "1.2.3")
+    ///     .unwrap()
+    ///     .with_custom_field("severity", Severity::Critical)
+    ///     .unwrap();
+    ///
+    /// let version: Option<String> = description.custom_field("version").unwrap();
+    /// assert_eq!(version.as_deref(), Some("1.2.3"));
+    /// assert_eq!(description.custom_field("severity").unwrap(), Some(Severity::Critical));
+    /// assert_eq!(description.custom_field::<String>("platform").unwrap(), None);
+    /// ```
+    pub fn with_custom_field<T>(mut self, key: &str, value: T) -> Result<Self>
+    where
+        T: Serialize,
+    {
+        let value = serde_json::to_value(value)?;
+        let _ = self
+            .custom
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value);
+        Ok(self)
+    }
+
+    /// Deserialize the custom metadata field `key`, returning `None` if it is absent, and an
+    /// `Error::Encoding` if it does not have the expected type.
+    pub fn custom_field<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        match self.custom.as_ref().and_then(|custom| custom.get(key)) {
+            Some(value) => Ok(Some(T::deserialize(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Deserialize all of the custom metadata into `T`, returning `None` if there is none.
+    ///
+    /// ```
+    /// # use serde_derive::Deserialize;
+    /// # use serde_json::json;
+    /// # use tuf::crypto::HashAlgorithm;
+    /// # use tuf::metadata::TargetDescription;
+    /// #[derive(Deserialize, Debug, PartialEq)]
+    /// struct Package {
+    ///     version: String,
+    ///     platform: String,
+    /// }
+    ///
+    /// let description = TargetDescription::from_reader(&b"package"[..], &[HashAlgorithm::Sha256])
+    ///     .unwrap()
+    ///     .with_custom_field("version", "1.2.3")
+    ///     .unwrap()
+    ///     .with_custom_field("platform", "x86_64")
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     description.custom_as::<Package>().unwrap(),
+    ///     Some(Package { version: "1.2.3".into(), platform: "x86_64".into() }),
+    /// );
+    /// ```
+    pub fn custom_as<T>(&self) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        match self.custom.as_ref() {
+            Some(custom) => {
+                let value = serde_json::Value::Object(
+                    custom.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                );
+                Ok(Some(serde_json::from_value(value)?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Helper to construct `TargetDescription`s that record exactly the chosen hash algorithms.
+///
+/// A builder can describe any number of targets, so publishers can configure it once and use it
+/// for every target they add. Descriptions always serialize their hashes sorted by algorithm.
+///
+/// ```
+/// # use tuf::crypto::HashAlgorithm;
+/// # use tuf::metadata::{TargetDescriptionBuilder, TargetsMetadataBuilder, VirtualTargetPath};
+/// let builder = TargetDescriptionBuilder::new().hash_algorithm(HashAlgorithm::Sha512);
+///
+/// let description = builder.build_from_reader(&b"foo"[..]).unwrap();
+/// assert_eq!(description.length(), 3);
+/// assert_eq!(
+///     description.hashes().keys().collect::<Vec<_>>(),
+///     vec![&HashAlgorithm::Sha512],
+/// );
+///
+/// let targets = TargetsMetadataBuilder::new()
+///     .insert_target_description(VirtualTargetPath::new("foo".into()).unwrap(), description)
+///     .build()
+///     .unwrap();
+///
+/// // At least one hash algorithm is required.
+/// assert!(TargetDescriptionBuilder::new().build_from_reader(&b"foo"[..]).is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TargetDescriptionBuilder {
+    hash_algs: Vec<HashAlgorithm>,
+    custom: Option<HashMap<String, serde_json::Value>>,
+}
+
+impl TargetDescriptionBuilder {
+    /// Create a new `TargetDescriptionBuilder` that does not record any hashes yet.
+    pub fn new() -> Self {
+        TargetDescriptionBuilder::default()
+    }
+
+    /// Record the hash `alg` of each target. Adding an algorithm more than once has no effect.
+    pub fn hash_algorithm(mut self, alg: HashAlgorithm) -> Self {
+        if !self.hash_algs.contains(&alg) {
+            self.hash_algs.push(alg);
+        }
+        self
+    }
+
+    /// Record the hashes `algs` of each target, in addition to any already added.
+    pub fn hash_algorithms(self, algs: &[HashAlgorithm]) -> Self {
+        algs.iter()
+            .fold(self, |builder, alg| builder.hash_algorithm(alg.clone()))
+    }
+
+    /// The hash algorithms recorded for each target.
+    pub fn hash_algs(&self) -> &[HashAlgorithm] {
+        &self.hash_algs
+    }
+
+    /// Set the custom metadata recorded for each target.
+    pub fn custom(mut self, custom: HashMap<String, serde_json::Value>) -> Self {
+        self.custom = Some(custom);
+        self
+    }
+
+    /// Read the target from `read` and describe it.
+    pub fn build_from_reader<R>(&self, read: R) -> Result<TargetDescription>
+    where
+        R: Read,
+    {
+        self.check_hash_algs()?;
+        let (length, hashes) = crypto::calculate_hashes(read, &self.hash_algs)?;
+        Ok(TargetDescription {
+            length,
+            hashes,
+            custom: self.custom.clone(),
+        })
+    }
+
+    /// Read the target from the `AsyncRead` `read` and describe it.
+    pub async fn build_from_async_reader<R>(&self, read: R) -> Result<TargetDescription>
+    where
+        R: AsyncRead + Unpin,
+    {
+        self.check_hash_algs()?;
+        let (length, hashes) = crypto::calculate_hashes_async(read, &self.hash_algs).await?;
+        Ok(TargetDescription {
+            length,
+            hashes,
+            custom: self.custom.clone(),
+        })
+    }
+
+    /// Check the chosen hash algorithms before reading anything.
+    fn check_hash_algs(&self) -> Result<()> {
+        if self.hash_algs.is_empty() {
+            return Err(Error::IllegalArgument(
+                "At least one hash algorithm must be chosen".into(),
+            ));
+        }
+
+        if let Some(alg) = self
+            .hash_algs
+            .iter()
+            .find(|alg| matches!(alg, HashAlgorithm::Unknown(_)))
+        {
+            return Err(Error::IllegalArgument(format!(
+                "Cannot calculate hashes with unknown algorithm {:?}",
+                alg
+            )));
+        }
+
+        Ok(())
+    }
 }
 
 impl Serialize for TargetDescription {
@@ -1809,23 +2881,70 @@ impl<'de> Deserialize<'de> for TargetsMetadata {
     }
 }
 
-/// Helper to construct `TargetsMetadata`.
-pub struct TargetsMetadataBuilder {
+/// Everything in `TargetsMetadata` except the targets themselves, as returned by
+/// [Json::stream_targets](crate::interchange::Json::stream_targets).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetsMetadataHeader {
     version: u32,
     expires: DateTime<Utc>,
-    targets: HashMap<VirtualTargetPath, TargetDescription>,
     delegations: Option<Delegations>,
 }
 
-impl TargetsMetadataBuilder {
-    /// Create a new `TargetsMetadataBuilder`. It defaults to:
-    ///
-    /// * version: 1
-    /// * expires: 90 days from the current time.
+impl TargetsMetadataHeader {
+    pub(crate) fn new(
+        version: u32,
+        expires: DateTime<Utc>,
+        delegations: Option<Delegations>,
+    ) -> Result<Self> {
+        if version < 1 {
+            return Err(Error::IllegalArgument(format!(
+                "Metadata version must be greater than zero. Found: {}",
+                version
+            )));
+        }
+
+        Ok(TargetsMetadataHeader {
+            version,
+            expires,
+            delegations,
+        })
+    }
+
+    /// The version number.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// An immutable reference to the metadata's expiration `DateTime`.
+    pub fn expires(&self) -> &DateTime<Utc> {
+        &self.expires
+    }
+
+    /// An immutable reference to the optional delegations.
+    pub fn delegations(&self) -> Option<&Delegations> {
+        self.delegations.as_ref()
+    }
+}
+
+/// Helper to construct `TargetsMetadata`.
+pub struct TargetsMetadataBuilder {
+    version: u32,
+    expires: DateTime<Utc>,
+    allow_expired: bool,
+    targets: HashMap<VirtualTargetPath, TargetDescription>,
+    delegations: Option<Delegations>,
+}
+
+impl TargetsMetadataBuilder {
+    /// Create a new `TargetsMetadataBuilder`. It defaults to:
+    ///
+    /// * version: 1
+    /// * expires: 90 days from the current time.
     pub fn new() -> Self {
         TargetsMetadataBuilder {
             version: 1,
             expires: Utc::now() + Duration::days(90),
+            allow_expired: false,
             targets: HashMap::new(),
             delegations: None,
         }
@@ -1843,6 +2962,19 @@ impl TargetsMetadataBuilder {
         self
     }
 
+    /// Set this metadata to expire `duration` from the current time.
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.expires = Utc::now() + duration;
+        self
+    }
+
+    /// Allow building metadata that has already expired, which is otherwise rejected. This is
+    /// only useful for tests and for reproducing old metadata.
+    pub fn allow_expired(mut self, allow: bool) -> Self {
+        self.allow_expired = allow;
+        self
+    }
+
     /// Add target to the target metadata.
     pub fn insert_target_from_reader<R>(
         self,
@@ -1857,6 +2989,91 @@ impl TargetsMetadataBuilder {
         Ok(self.insert_target_description(path, description))
     }
 
+    /// Add target to the target metadata, along with custom metadata for it.
+    pub fn insert_target_from_reader_with_custom<R>(
+        self,
+        path: VirtualTargetPath,
+        read: R,
+        hash_algs: &[HashAlgorithm],
+        custom: HashMap<String, serde_json::Value>,
+    ) -> Result<Self>
+    where
+        R: Read,
+    {
+        let description = TargetDescription::from_reader_with_custom(read, hash_algs, custom)?;
+        Ok(self.insert_target_description(path, description))
+    }
+
+    /// Add target to the target metadata, reading it from an `AsyncRead` such as a network stream
+    /// or an artifact being generated on the fly.
+    pub async fn insert_target_from_async_reader<R>(
+        self,
+        path: VirtualTargetPath,
+        read: R,
+        hash_algs: &[HashAlgorithm],
+    ) -> Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let description = TargetDescription::from_async_reader(read, hash_algs).await?;
+        Ok(self.insert_target_description(path, description))
+    }
+
+    /// Add the file at `file` to the target metadata as `path`, reading it to compute its length
+    /// and `hash_algs` hashes.
+    pub fn insert_target_from_path<P>(
+        self,
+        path: VirtualTargetPath,
+        file: P,
+        hash_algs: &[HashAlgorithm],
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let read = File::open(file)?;
+        self.insert_target_from_reader(path, read, hash_algs)
+    }
+
+    /// Add the file at `base_dir.join(relative)` to the target metadata, reading it to compute its
+    /// length and `hash_algs` hashes. The target path is `relative` with its components joined by
+    /// `/`, so `relative` must not contain `..`, be absolute, or be anything but UTF-8.
+    ///
+    /// ```no_run
+    /// # use tuf::crypto::HashAlgorithm;
+    /// # use tuf::metadata::TargetsMetadataBuilder;
+    /// // Adds the target `packages/foo.tgz`.
+    /// let builder = TargetsMetadataBuilder::new()
+    ///     .insert_target_from_dir("/srv/repo/targets", "packages/foo.tgz", &[HashAlgorithm::Sha256])
+    ///     .unwrap();
+    /// ```
+    pub fn insert_target_from_dir<P, Q>(
+        self,
+        base_dir: P,
+        relative: Q,
+        hash_algs: &[HashAlgorithm],
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let relative = relative.as_ref();
+        let components = relative
+            .components()
+            .map(|component| match component {
+                Component::Normal(name) => name.to_str().ok_or_else(|| {
+                    Error::IllegalArgument(format!("Target path {:?} is not UTF-8", relative))
+                }),
+                _ => Err(Error::IllegalArgument(format!(
+                    "Target path {:?} must be a relative path without `.` or `..`",
+                    relative
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let path = VirtualTargetPath::new(components.join("/"))?;
+
+        self.insert_target_from_path(path, base_dir.as_ref().join(relative), hash_algs)
+    }
+
     /// Add `TargetDescription` to this target metadata target description.
     pub fn insert_target_description(
         mut self,
@@ -1875,15 +3092,16 @@ impl TargetsMetadataBuilder {
 
     /// Construct a new `TargetsMetadata`.
     pub fn build(self) -> Result<TargetsMetadata> {
+        check_expires(&self.expires, self.allow_expired)?;
         TargetsMetadata::new(self.version, self.expires, self.targets, self.delegations)
     }
 
     /// Construct a new `SignedMetadata<D, TargetsMetadata>`.
-    pub fn signed<D>(self, private_key: &PrivateKey) -> Result<SignedMetadata<D, TargetsMetadata>>
+    pub fn signed<D>(self, signer: &dyn Signer) -> Result<SignedMetadata<D, TargetsMetadata>>
     where
         D: DataInterchange,
     {
-        SignedMetadata::new(&self.build()?, private_key)
+        SignedMetadata::new(&self.build()?, signer)
     }
 }
 
@@ -1893,6 +3111,354 @@ impl Default for TargetsMetadataBuilder {
     }
 }
 
+/// Helper to split a large set of targets across delegated roles, or "bins", using the hashed bin
+/// layout recommended by the TUF specification.
+///
+/// Each target is assigned to a bin based on the leading hex digits of the SHA-256 hash of its
+/// path, and each bin covers an equal range of those prefixes. Bins are named after the prefixes
+/// they cover, such as `00-0f`, or just `0a` when a bin covers a single prefix.
+///
+/// ```
+/// # use tuf::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
+/// # use tuf::interchange::Json;
+/// # use tuf::metadata::{HashedBinsBuilder, TargetsMetadataBuilder, VirtualTargetPath};
+/// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+/// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+/// let mut builder = HashedBinsBuilder::new(16).key(key.public().clone());
+/// for i in 0..100 {
+///     builder = builder
+///         .insert_target_from_reader(
+///             VirtualTargetPath::new(format!("file-{}", i)).unwrap(),
+///             format!("contents {}", i).as_bytes(),
+///             &[HashAlgorithm::Sha256],
+///         )
+///         .unwrap();
+/// }
+/// let bins = builder.signed::<Json>(&key).unwrap();
+///
+/// // The delegations go in the parent targets metadata, and each bin is published as a role.
+/// let targets = TargetsMetadataBuilder::new()
+///     .delegations(bins.delegations().clone())
+///     .signed::<Json>(&key)
+///     .unwrap();
+/// assert!(bins.bins().len() <= 16);
+/// for bin in bins.bins() {
+///     assert_eq!(bin.hash_prefixes().len(), 1);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HashedBinsBuilder {
+    number_of_bins: NumberOfBins,
+    version: u32,
+    expires: DateTime<Utc>,
+    allow_expired: bool,
+    keys: HashMap<KeyId, PublicKey>,
+    threshold: u32,
+    targets: HashMap<VirtualTargetPath, TargetDescription>,
+}
+
+impl HashedBinsBuilder {
+    /// Create a new `HashedBinsBuilder` that splits targets across `number_of_bins` bins, which
+    /// must be a power of two greater than 1. It defaults to:
+    ///
+    /// * version: 1
+    /// * expires: 90 days from the current time.
+    /// * threshold: 1
+    pub fn new(number_of_bins: u32) -> Self {
+        Self::with_number_of_bins(NumberOfBins::Fixed(number_of_bins))
+    }
+
+    /// Create a new `HashedBinsBuilder` that picks the number of bins when signing, using the
+    /// fewest bins such that no bin holds more than `max_targets_per_bin` targets. This keeps the
+    /// metadata of each bin small no matter how many targets the repository has. Since targets
+    /// are assigned to bins by hash, this may be more bins than an even split would need. Signing
+    /// fails if more than 65536 bins would be needed. The defaults are the same as
+    /// [`HashedBinsBuilder::new`].
+    ///
+    /// ```
+    /// # use tuf::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
+    /// # use tuf::interchange::Json;
+    /// # use tuf::metadata::{HashedBinsBuilder, SnapshotMetadataBuilder, VirtualTargetPath};
+    /// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+    /// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+    /// let mut builder = HashedBinsBuilder::with_max_targets_per_bin(10).key(key.public().clone());
+    /// for i in 0..100 {
+    ///     builder = builder
+    ///         .insert_target_from_reader(
+    ///             VirtualTargetPath::new(format!("file-{}", i)).unwrap(),
+    ///             format!("contents {}", i).as_bytes(),
+    ///             &[HashAlgorithm::Sha256],
+    ///         )
+    ///         .unwrap();
+    /// }
+    /// let bins = builder.signed::<Json>(&key).unwrap();
+    /// assert_eq!(bins.bins().len(), 32);
+    ///
+    /// let snapshot = bins
+    ///     .insert_into_snapshot(SnapshotMetadataBuilder::new(), &[HashAlgorithm::Sha256])
+    ///     .unwrap()
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(snapshot.meta().len(), 32);
+    /// ```
+    pub fn with_max_targets_per_bin(max_targets_per_bin: usize) -> Self {
+        Self::with_number_of_bins(NumberOfBins::MaxTargetsPerBin(max_targets_per_bin))
+    }
+
+    fn with_number_of_bins(number_of_bins: NumberOfBins) -> Self {
+        HashedBinsBuilder {
+            number_of_bins,
+            version: 1,
+            expires: Utc::now() + Duration::days(90),
+            allow_expired: false,
+            keys: HashMap::new(),
+            threshold: 1,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Set the version number for the metadata of every bin.
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Set the time the metadata of every bin expires.
+    pub fn expires(mut self, expires: DateTime<Utc>) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    /// Set the metadata of every bin to expire `duration` from the current time.
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.expires = Utc::now() + duration;
+        self
+    }
+
+    /// Allow building bins that have already expired, which is otherwise rejected.
+    pub fn allow_expired(mut self, allow: bool) -> Self {
+        self.allow_expired = allow;
+        self
+    }
+
+    /// Add a key that is trusted to sign the bins.
+    pub fn key(mut self, public_key: PublicKey) -> Self {
+        let _ = self.keys.insert(public_key.key_id().clone(), public_key);
+        self
+    }
+
+    /// Set the number of signatures required on each bin.
+    pub fn threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Add a target to the bins.
+    pub fn insert_target_from_reader<R>(
+        self,
+        path: VirtualTargetPath,
+        read: R,
+        hash_algs: &[HashAlgorithm],
+    ) -> Result<Self>
+    where
+        R: Read,
+    {
+        let description = TargetDescription::from_reader(read, hash_algs)?;
+        Ok(self.insert_target_description(path, description))
+    }
+
+    /// Add a `TargetDescription` to the bins.
+    pub fn insert_target_description(
+        mut self,
+        path: VirtualTargetPath,
+        description: TargetDescription,
+    ) -> Self {
+        let _ = self.targets.insert(path, description);
+        self
+    }
+
+    /// Assign the targets to bins, and sign the metadata of each bin with `signer`. Every bin is
+    /// included, even if no target is assigned to it, so targets can later be added to any bin
+    /// without changing the delegations.
+    pub fn signed<D>(self, signer: &dyn Signer) -> Result<HashedBins<D>>
+    where
+        D: DataInterchange,
+    {
+        let number_of_bins = match self.number_of_bins {
+            NumberOfBins::Fixed(number_of_bins) => number_of_bins,
+            NumberOfBins::MaxTargetsPerBin(0) => {
+                return Err(Error::IllegalArgument(
+                    "Bins must be able to hold at least one target".into(),
+                ));
+            }
+            NumberOfBins::MaxTargetsPerBin(max) => {
+                // Targets aren't spread evenly across bins, so start from the average and keep
+                // splitting the bins until none of them is over the limit.
+                let mut needed = self.targets.len().div_ceil(max).max(2).next_power_of_two();
+                loop {
+                    if needed > MAX_NUMBER_OF_BINS as usize {
+                        return Err(Error::IllegalArgument(format!(
+                            "{} targets would need more than {} bins of {} targets",
+                            self.targets.len(),
+                            MAX_NUMBER_OF_BINS,
+                            max
+                        )));
+                    }
+                    let mut counts = vec![0; needed];
+                    for path in self.targets.keys() {
+                        counts[bin_index(path, needed as u32)?] += 1;
+                    }
+                    if counts.into_iter().all(|count| count <= max) {
+                        break needed as u32;
+                    }
+                    needed *= 2;
+                }
+            }
+        };
+
+        if number_of_bins < 2 || !number_of_bins.is_power_of_two() {
+            return Err(Error::IllegalArgument(format!(
+                "Number of bins must be a power of two greater than 1. Found: {}",
+                number_of_bins
+            )));
+        }
+
+        let (prefix_len, bin_size) = bin_prefix_len_and_size(number_of_bins);
+
+        let mut bins = vec![HashMap::new(); number_of_bins as usize];
+        for (path, description) in self.targets {
+            let index = bin_index(&path, number_of_bins)?;
+            let _ = bins[index].insert(path, description);
+        }
+
+        let key_ids = self.keys.keys().cloned().collect::<HashSet<_>>();
+        let mut roles = Vec::new();
+        let mut signed_bins = Vec::new();
+        for (index, targets) in bins.into_iter().enumerate() {
+            let low = index as u64 * bin_size;
+            let high = low + bin_size - 1;
+            let hash_prefixes = (low..=high)
+                .map(|prefix| format!("{:0width$x}", prefix, width = prefix_len))
+                .collect::<Vec<_>>();
+            let role = if low == high {
+                hash_prefixes[0].clone()
+            } else {
+                format!("{:0width$x}-{:0width$x}", low, high, width = prefix_len)
+            };
+            let role = MetadataPath::new(role)?;
+
+            roles.push(Delegation::with_path_hash_prefixes(
+                role.clone(),
+                false,
+                self.threshold,
+                key_ids.clone(),
+                hash_prefixes.iter().cloned().collect(),
+            )?);
+
+            let mut builder = TargetsMetadataBuilder::new()
+                .version(self.version)
+                .expires(self.expires)
+                .allow_expired(self.allow_expired);
+            for (path, description) in targets {
+                builder = builder.insert_target_description(path, description);
+            }
+
+            signed_bins.push(HashedBin {
+                role,
+                hash_prefixes,
+                metadata: builder.signed(signer)?,
+            });
+        }
+
+        Ok(HashedBins {
+            delegations: Delegations::new(self.keys, roles)?,
+            bins: signed_bins,
+        })
+    }
+}
+
+/// The number of hex digits in the hash prefixes of `number_of_bins` bins, and how many prefixes
+/// each bin covers. This uses the fewest hex digits that give every bin at least one prefix.
+fn bin_prefix_len_and_size(number_of_bins: u32) -> (usize, u64) {
+    let prefix_len = (number_of_bins.trailing_zeros() as usize).div_ceil(4);
+    let bin_size = (1u64 << (4 * prefix_len)) / u64::from(number_of_bins);
+    (prefix_len, bin_size)
+}
+
+/// The index of the bin `path` is assigned to when the targets are split into `number_of_bins`.
+fn bin_index(path: &VirtualTargetPath, number_of_bins: u32) -> Result<usize> {
+    let (prefix_len, bin_size) = bin_prefix_len_and_size(number_of_bins);
+    let hash = crypto::calculate_hash(path.value().as_bytes(), HashAlgorithm::Sha256);
+    let prefix = u64::from_str_radix(&hash.to_hex()[..prefix_len], 16)
+        .map_err(|e| Error::Programming(format!("Bad hash prefix: {:?}", e)))?;
+    Ok((prefix / bin_size) as usize)
+}
+
+/// The largest number of bins [`HashedBinsBuilder::with_max_targets_per_bin`] picks.
+const MAX_NUMBER_OF_BINS: u32 = 1 << 16;
+
+/// How a [`HashedBinsBuilder`] decides how many bins to use.
+#[derive(Debug, Clone, Copy)]
+enum NumberOfBins {
+    Fixed(u32),
+    MaxTargetsPerBin(usize),
+}
+
+/// The delegations and signed metadata produced by a [`HashedBinsBuilder`].
+#[derive(Clone)]
+pub struct HashedBins<D: DataInterchange> {
+    delegations: Delegations,
+    bins: Vec<HashedBin<D>>,
+}
+
+impl<D: DataInterchange> HashedBins<D> {
+    /// The delegations to the bins, to be included in the parent targets metadata.
+    pub fn delegations(&self) -> &Delegations {
+        &self.delegations
+    }
+
+    /// The bins, in order of the hash prefixes they cover.
+    pub fn bins(&self) -> &[HashedBin<D>] {
+        &self.bins
+    }
+
+    /// Describe the metadata of every bin in `snapshot`, with `hash_algs` hashes.
+    pub fn insert_into_snapshot(
+        &self,
+        snapshot: SnapshotMetadataBuilder,
+        hash_algs: &[HashAlgorithm],
+    ) -> Result<SnapshotMetadataBuilder> {
+        self.bins.iter().try_fold(snapshot, |snapshot, bin| {
+            snapshot.insert_metadata_with_path(bin.role.as_str(), &bin.metadata, hash_algs)
+        })
+    }
+}
+
+/// A single bin produced by a [`HashedBinsBuilder`].
+#[derive(Clone)]
+pub struct HashedBin<D: DataInterchange> {
+    role: MetadataPath,
+    hash_prefixes: Vec<String>,
+    metadata: SignedMetadata<D, TargetsMetadata>,
+}
+
+impl<D: DataInterchange> HashedBin<D> {
+    /// The name of the delegated role for this bin.
+    pub fn role(&self) -> &MetadataPath {
+        &self.role
+    }
+
+    /// The hex encoded prefixes of the SHA-256 hashes of target paths that belong in this bin.
+    pub fn hash_prefixes(&self) -> &[String] {
+        &self.hash_prefixes
+    }
+
+    /// The signed targets metadata for this bin.
+    pub fn metadata(&self) -> &SignedMetadata<D, TargetsMetadata> {
+        &self.metadata
+    }
+}
+
 /// Wrapper to described a collections of delegations.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Delegations {
@@ -1925,6 +3491,13 @@ impl Delegations {
             ));
         }
 
+        if let Some(role) = roles.iter().find(|r| r.role.role().is_some()) {
+            return Err(Error::IllegalArgument(format!(
+                "Delegated role cannot use the name of the top-level role {:?}.",
+                role.role.as_str()
+            )));
+        }
+
         Ok(Delegations { keys, roles })
     }
 
@@ -1939,22 +3512,187 @@ impl Delegations {
     }
 }
 
-impl Serialize for Delegations {
-    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        shims::Delegations::from(self).serialize(ser)
-    }
+/// Helper to construct `Delegations`.
+///
+/// Roles are delegated to in the order they are added, which is the order clients consult them
+/// in. Problems are reported by [`DelegationsBuilder::build`], which checks that:
+///
+/// * every key ID used by a role is one of the added keys,
+/// * every threshold can be met by the keys of its role,
+/// * every path is valid once normalized. Paths are normalized by removing any leading `/` and
+///   collapsing repeated `/`s.
+///
+/// ```
+/// # use tuf::crypto::{PrivateKey, SignatureScheme};
+/// # use tuf::metadata::{DelegationsBuilder, VirtualTargetPath};
+/// # let key: &[u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
+/// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+/// let delegations = DelegationsBuilder::new()
+///     .key(key.public().clone())
+///     .role("projects/foo", true, 1, vec![key.key_id().clone()], vec!["/foo//"])
+///     .build()
+///     .unwrap();
+///
+/// let paths = delegations.roles()[0].paths();
+/// assert!(paths.contains(&VirtualTargetPath::new("foo/".into()).unwrap()));
+///
+/// // The threshold can't be met by a single key.
+/// assert!(DelegationsBuilder::new()
+///     .key(key.public().clone())
+///     .role("projects/foo", true, 2, vec![key.key_id().clone()], vec!["foo/"])
+///     .build()
+///     .is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DelegationsBuilder {
+    keys: HashMap<KeyId, PublicKey>,
+    roles: Vec<DelegationsBuilderRole>,
 }
 
-impl<'de> Deserialize<'de> for Delegations {
-    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
-        let intermediate: shims::Delegations = Deserialize::deserialize(de)?;
-        intermediate
-            .try_into()
-            .map_err(|e| DeserializeError::custom(format!("{:?}", e)))
-    }
+#[derive(Debug, Clone)]
+struct DelegationsBuilderRole {
+    name: String,
+    terminating: bool,
+    threshold: u32,
+    key_ids: Vec<KeyId>,
+    paths: Vec<String>,
+    path_hash_prefixes: Vec<String>,
+}
+
+impl DelegationsBuilder {
+    /// Create a new `DelegationsBuilder` with no keys or roles.
+    pub fn new() -> Self {
+        DelegationsBuilder::default()
+    }
+
+    /// Add a key that delegated roles may use.
+    pub fn key(mut self, key: PublicKey) -> Self {
+        let _ = self.keys.insert(key.key_id().clone(), key);
+        self
+    }
+
+    /// Delegate the targets under `paths` to the role `name`.
+    pub fn role<K, P, S>(
+        mut self,
+        name: &str,
+        terminating: bool,
+        threshold: u32,
+        key_ids: K,
+        paths: P,
+    ) -> Self
+    where
+        K: IntoIterator<Item = KeyId>,
+        P: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.roles.push(DelegationsBuilderRole {
+            name: name.into(),
+            terminating,
+            threshold,
+            key_ids: key_ids.into_iter().collect(),
+            paths: paths.into_iter().map(|p| p.as_ref().into()).collect(),
+            path_hash_prefixes: Vec::new(),
+        });
+        self
+    }
+
+    /// Delegate the targets whose path hashes start with one of `path_hash_prefixes` to the role
+    /// `name`. See [`Delegation::with_path_hash_prefixes`].
+    pub fn role_with_path_hash_prefixes<K, P, S>(
+        mut self,
+        name: &str,
+        terminating: bool,
+        threshold: u32,
+        key_ids: K,
+        path_hash_prefixes: P,
+    ) -> Self
+    where
+        K: IntoIterator<Item = KeyId>,
+        P: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.roles.push(DelegationsBuilderRole {
+            name: name.into(),
+            terminating,
+            threshold,
+            key_ids: key_ids.into_iter().collect(),
+            paths: Vec::new(),
+            path_hash_prefixes: path_hash_prefixes
+                .into_iter()
+                .map(|p| p.as_ref().into())
+                .collect(),
+        });
+        self
+    }
+
+    /// Validate the keys and roles and construct the `Delegations`.
+    pub fn build(self) -> Result<Delegations> {
+        let DelegationsBuilder {
+            keys,
+            roles: pending,
+        } = self;
+        let mut roles = Vec::with_capacity(pending.len());
+        for role in pending {
+            let name = MetadataPath::new(role.name)?;
+
+            if let Some(key_id) = role.key_ids.iter().find(|k| !keys.contains_key(k)) {
+                return Err(Error::IllegalArgument(format!(
+                    "Delegated role {} uses key {:?}, which was not added",
+                    name, key_id
+                )));
+            }
+            let key_ids = role.key_ids.into_iter().collect::<HashSet<_>>();
+
+            let delegation = if role.path_hash_prefixes.is_empty() {
+                let paths = role
+                    .paths
+                    .iter()
+                    .map(|p| VirtualTargetPath::new(normalize_path(p)))
+                    .collect::<Result<HashSet<_>>>()?;
+                Delegation::new(name, role.terminating, role.threshold, key_ids, paths)?
+            } else {
+                Delegation::with_path_hash_prefixes(
+                    name,
+                    role.terminating,
+                    role.threshold,
+                    key_ids,
+                    role.path_hash_prefixes.into_iter().collect(),
+                )?
+            };
+            roles.push(delegation);
+        }
+
+        Delegations::new(keys, roles)
+    }
+}
+
+/// Remove any leading `/` from `path` and collapse repeated `/`s.
+fn normalize_path(path: &str) -> String {
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.trim_start_matches('/').chars() {
+        if c != '/' || !normalized.ends_with('/') {
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
+impl Serialize for Delegations {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        shims::Delegations::from(self).serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for Delegations {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> ::std::result::Result<Self, D::Error> {
+        let intermediate: shims::Delegations = Deserialize::deserialize(de)?;
+        intermediate
+            .try_into()
+            .map_err(|e| DeserializeError::custom(format!("{:?}", e)))
+    }
 }
 
 /// A delegated targets role.
@@ -1965,10 +3703,11 @@ pub struct Delegation {
     threshold: u32,
     key_ids: HashSet<KeyId>,
     paths: HashSet<VirtualTargetPath>,
+    path_hash_prefixes: HashSet<String>,
 }
 
 impl Delegation {
-    /// Create a new delegation.
+    /// Create a new delegation of the targets under `paths`.
     pub fn new(
         role: MetadataPath,
         terminating: bool,
@@ -1976,14 +3715,63 @@ impl Delegation {
         key_ids: HashSet<KeyId>,
         paths: HashSet<VirtualTargetPath>,
     ) -> Result<Self> {
-        if key_ids.is_empty() {
-            return Err(Error::IllegalArgument("Cannot have empty key IDs".into()));
-        }
-
         if paths.is_empty() {
             return Err(Error::IllegalArgument("Cannot have empty paths".into()));
         }
 
+        Self::build(role, terminating, threshold, key_ids, paths, HashSet::new())
+    }
+
+    /// Create a new delegation of the targets whose paths have a SHA-256 hash, in lowercase hex,
+    /// that starts with one of `path_hash_prefixes`. This is how hashed bin delegations are
+    /// expressed.
+    pub fn with_path_hash_prefixes(
+        role: MetadataPath,
+        terminating: bool,
+        threshold: u32,
+        key_ids: HashSet<KeyId>,
+        path_hash_prefixes: HashSet<String>,
+    ) -> Result<Self> {
+        if path_hash_prefixes.is_empty() {
+            return Err(Error::IllegalArgument(
+                "Cannot have empty path hash prefixes".into(),
+            ));
+        }
+
+        for prefix in &path_hash_prefixes {
+            if !prefix
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+            {
+                return Err(Error::IllegalArgument(format!(
+                    "Path hash prefix {:?} is not lowercase hex",
+                    prefix
+                )));
+            }
+        }
+
+        Self::build(
+            role,
+            terminating,
+            threshold,
+            key_ids,
+            HashSet::new(),
+            path_hash_prefixes,
+        )
+    }
+
+    fn build(
+        role: MetadataPath,
+        terminating: bool,
+        threshold: u32,
+        key_ids: HashSet<KeyId>,
+        paths: HashSet<VirtualTargetPath>,
+        path_hash_prefixes: HashSet<String>,
+    ) -> Result<Self> {
+        if key_ids.is_empty() {
+            return Err(Error::IllegalArgument("Cannot have empty key IDs".into()));
+        }
+
         if threshold < 1 {
             return Err(Error::IllegalArgument("Cannot have threshold < 1".into()));
         }
@@ -2000,6 +3788,7 @@ impl Delegation {
             threshold,
             key_ids,
             paths,
+            path_hash_prefixes,
         })
     }
 
@@ -2023,10 +3812,63 @@ impl Delegation {
         self.threshold
     }
 
-    /// An immutable reference to the delegation's authorized paths.
+    /// An immutable reference to the delegation's authorized paths. This is empty if the
+    /// delegation uses path hash prefixes instead.
     pub fn paths(&self) -> &HashSet<VirtualTargetPath> {
         &self.paths
     }
+
+    /// An immutable reference to the delegation's authorized path hash prefixes. This is empty if
+    /// the delegation uses paths instead.
+    pub fn path_hash_prefixes(&self) -> &HashSet<String> {
+        &self.path_hash_prefixes
+    }
+
+    /// Whether `target` is one of the targets this delegation is trusted for, either because it
+    /// is or is under one of the delegation's paths, or because the hash of its path starts with
+    /// one of the delegation's path hash prefixes.
+    ///
+    /// ```
+    /// # use tuf::metadata::{Delegation, MetadataPath, VirtualTargetPath};
+    /// # use tuf::crypto::KeyId;
+    /// # use std::str::FromStr;
+    /// # let key_id = KeyId::from_str(&"ab".repeat(32)).unwrap();
+    /// let delegation = Delegation::new(
+    ///     MetadataPath::new("foo").unwrap(),
+    ///     false,
+    ///     1,
+    ///     vec![key_id.clone()].into_iter().collect(),
+    ///     vec![VirtualTargetPath::new("foo/".into()).unwrap()].into_iter().collect(),
+    /// )
+    /// .unwrap();
+    /// assert!(delegation.matches(&VirtualTargetPath::new("foo/bar".into()).unwrap()));
+    /// assert!(!delegation.matches(&VirtualTargetPath::new("bar".into()).unwrap()));
+    ///
+    /// // sha256("bar") starts with "fcde".
+    /// let delegation = Delegation::with_path_hash_prefixes(
+    ///     MetadataPath::new("bin").unwrap(),
+    ///     false,
+    ///     1,
+    ///     vec![key_id].into_iter().collect(),
+    ///     vec!["fc".into()].into_iter().collect(),
+    /// )
+    /// .unwrap();
+    /// assert!(delegation.matches(&VirtualTargetPath::new("bar".into()).unwrap()));
+    /// ```
+    pub fn matches(&self, target: &VirtualTargetPath) -> bool {
+        if !self.paths.is_empty() {
+            return self
+                .paths
+                .iter()
+                .any(|path| path == target || target.is_child(path));
+        }
+
+        let hash = crypto::calculate_hash(target.value().as_bytes(), HashAlgorithm::Sha256);
+        let hash = hash.to_hex();
+        self.path_hash_prefixes
+            .iter()
+            .any(|prefix| hash.starts_with(prefix.as_str()))
+    }
 }
 
 impl Serialize for Delegation {
@@ -2050,13 +3892,15 @@ impl<'de> Deserialize<'de> for Delegation {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::crypto::SignatureScheme;
-    use crate::interchange::Json;
+    use crate::crypto::{PrivateKey, SignatureScheme, SignatureValue};
+    use crate::interchange::{Json, JsonPretty};
     use chrono::prelude::*;
+    use data_encoding::HEXLOWER;
     use maplit::{hashmap, hashset};
     use matches::assert_matches;
     use pretty_assertions::assert_eq;
     use serde_json::json;
+    use std::cell::{Cell, RefCell};
     use std::str::FromStr;
 
     const ED25519_1_PK8: &'static [u8] = include_bytes!("../tests/ed25519/ed25519-1.pk8.der");
@@ -2148,6 +3992,40 @@ mod test {
         assert_eq!(serde_json::to_value(m).unwrap(), json!("foo/bar"));
     }
 
+    #[test]
+    fn metadata_path_roles() {
+        for role in &[
+            Role::Root,
+            Role::Snapshot,
+            Role::Targets,
+            Role::Timestamp,
+            Role::Mirrors,
+        ] {
+            let path = MetadataPath::from_role(role);
+            assert_eq!(path.role(), Some(*role));
+            assert_eq!(Role::from_name(path.as_str()), Some(*role));
+            assert!(role.fuzzy_matches_path(&path));
+            assert_eq!(
+                Role::Targets.fuzzy_matches_path(&path),
+                *role == Role::Targets
+            );
+        }
+
+        let path = MetadataPath::new("projects/foo").unwrap();
+        assert_eq!(path.role(), None);
+        assert!(Role::Targets.fuzzy_matches_path(&path));
+        assert!(!Role::Mirrors.fuzzy_matches_path(&path));
+        assert_eq!(
+            path.components::<Json>(&MetadataVersion::Number(2)),
+            ["projects".to_string(), "2.foo.json".to_string()]
+        );
+
+        assert_eq!(
+            serde_json::to_value(Role::Mirrors).unwrap(),
+            json!("mirrors")
+        );
+    }
+
     #[test]
     fn serde_target_description() {
         let s: &[u8] = b"from water does all life begin";
@@ -2165,6 +4043,116 @@ mod test {
         assert_eq!(parsed_str, parsed_jsn);
     }
 
+    #[test]
+    fn deserialize_target_description_with_base64_hashes() {
+        let s: &[u8] = b"from water does all life begin";
+        let description = TargetDescription::from_reader(s, &[HashAlgorithm::Sha256]).unwrap();
+
+        for encoded in &[
+            "FC5D745C712BC86EA9A31264DAC0C956EEB53857F677EED05829BB71013CAE18",
+            "/F10XHEryG6poxJk2sDJVu61OFf2d+7QWCm7cQE8rhg=",
+            "_F10XHEryG6poxJk2sDJVu61OFf2d-7QWCm7cQE8rhg",
+        ] {
+            let jsn = json!({
+                "length": 30,
+                "hashes": { "sha256": encoded },
+            });
+            let parsed: TargetDescription = serde_json::from_value(jsn).unwrap();
+            assert_eq!(parsed, description);
+        }
+
+        // Parsed digests are written back as lowercase hex.
+        assert_eq!(
+            serde_json::to_value(&description).unwrap()["hashes"]["sha256"],
+            json!("fc5d745c712bc86ea9a31264dac0c956eeb53857f677eed05829bb71013cae18")
+        );
+
+        let jsn = json!({
+            "length": 30,
+            "hashes": { "sha256": "not a digest!" },
+        });
+        assert!(serde_json::from_value::<TargetDescription>(jsn).is_err());
+    }
+
+    #[test]
+    fn serde_target_description_sha3() {
+        let s: &[u8] = b"from water does all life begin";
+        let description =
+            TargetDescription::from_reader(s, &[HashAlgorithm::Sha3_256, HashAlgorithm::Sha3_512])
+                .unwrap();
+        let jsn = json!({
+            "length": 30,
+            "hashes": {
+                "sha3_256": "de934520c147f45bf5d57472a8c13b57b4d5ff63c41015b77566e9538078f60e",
+                "sha3_512": "f8dbef5074ca26949e7102878dd729ebae1f145d78930acc7b11474cbd460f08\
+                    76a6fbd80a0221bda29b9ade9d077eec9d06a7000713b8c02082ae0c44b8b1e3",
+            },
+        });
+        assert_eq!(serde_json::to_value(&description).unwrap(), jsn);
+        let parsed: TargetDescription = serde_json::from_value(jsn).unwrap();
+        assert_eq!(parsed, description);
+    }
+
+    #[test]
+    fn serde_target_description_blake2b() {
+        let s: &[u8] = b"from water does all life begin";
+        let description = TargetDescription::from_reader(s, &[HashAlgorithm::Blake2b256]).unwrap();
+        let jsn = json!({
+            "length": 30,
+            "hashes": {
+                "blake2b-256": "7701c4ac6fa58f2c3d72decdad43ff25f62e50f4774088cd2680d76983d3fc62",
+            },
+        });
+        assert_eq!(serde_json::to_value(&description).unwrap(), jsn);
+        let parsed: TargetDescription = serde_json::from_value(jsn).unwrap();
+        assert_eq!(parsed, description);
+    }
+
+    #[test]
+    fn target_description_builder() {
+        let s: &[u8] = b"from water does all life begin";
+
+        let sha512 = TargetDescriptionBuilder::new().hash_algorithm(HashAlgorithm::Sha512);
+        let description = sha512.build_from_reader(s).unwrap();
+        assert_eq!(
+            description.hashes().keys().collect::<Vec<_>>(),
+            vec![&HashAlgorithm::Sha512]
+        );
+        assert_eq!(description.custom(), None);
+
+        // The order algorithms are chosen in doesn't affect the output.
+        let forward = TargetDescriptionBuilder::new()
+            .hash_algorithms(&[HashAlgorithm::Sha512, HashAlgorithm::Sha256])
+            .hash_algorithm(HashAlgorithm::Sha512);
+        let backward = TargetDescriptionBuilder::new()
+            .hash_algorithms(&[HashAlgorithm::Sha256, HashAlgorithm::Sha512]);
+        assert_eq!(forward.hash_algs().len(), 2);
+        let forward = serde_json::to_string(&forward.build_from_reader(s).unwrap()).unwrap();
+        let backward = serde_json::to_string(&backward.build_from_reader(s).unwrap()).unwrap();
+        assert_eq!(forward, backward);
+        assert!(forward.find("sha256").unwrap() < forward.find("sha512").unwrap());
+
+        let description = sha512
+            .custom(hashmap! { "type".into() => json!("firmware") })
+            .build_from_reader(s)
+            .unwrap();
+        assert_eq!(
+            description.custom_field("type").unwrap(),
+            Some("firmware".to_string())
+        );
+
+        assert_matches!(
+            TargetDescriptionBuilder::new().build_from_reader(s),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            TargetDescriptionBuilder::new()
+                .hash_algorithm(HashAlgorithm::Unknown("md5".into()))
+                .build_from_reader(s),
+            Err(Error::IllegalArgument(_))
+        );
+    }
+
     #[test]
     fn serde_role_definition() {
         // keyid ordering must be preserved.
@@ -2222,6 +4210,7 @@ mod test {
 
         let root = RootMetadataBuilder::new()
             .expires(Utc.ymd(2017, 1, 1).and_hms(0, 0, 0))
+            .allow_expired(true)
             .root_key(root_key.public().clone())
             .snapshot_key(snapshot_key.public().clone())
             .targets_key(targets_key.public().clone())
@@ -2295,6 +4284,127 @@ mod test {
         assert_eq!(decoded, root);
     }
 
+    #[test]
+    fn serde_root_metadata_additional_roles() {
+        let key1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let key2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let builder = || {
+            RootMetadataBuilder::new()
+                .expires(Utc.with_ymd_and_hms(2038, 1, 1, 0, 0, 0).unwrap())
+                .root_key(key1.public().clone())
+                .snapshot_key(key1.public().clone())
+                .targets_key(key1.public().clone())
+                .timestamp_key(key1.public().clone())
+        };
+
+        let root = builder()
+            .role_key("mirrors", key2.public().clone())
+            .role_key("acme-release", key1.public().clone())
+            .role_key("acme-release", key2.public().clone())
+            .role_threshold("acme-release", 2)
+            .build()
+            .unwrap();
+
+        assert_eq!(root.additional_roles().len(), 2);
+        assert_eq!(
+            root.role_definition("mirrors").unwrap().key_ids(),
+            &[key2.key_id().clone()]
+        );
+        assert_eq!(root.role_definition("acme-release").unwrap().threshold(), 2);
+        assert_eq!(root.role_definition("root"), Some(root.root()));
+        assert_eq!(root.role_definition("missing"), None);
+
+        let encoded = serde_json::to_value(&root).unwrap();
+        assert_eq!(
+            encoded["roles"]["acme-release"],
+            json!({
+                "threshold": 2,
+                "keyids": [key1.key_id(), key2.key_id()],
+            })
+        );
+        assert_eq!(
+            encoded["roles"]["mirrors"],
+            json!({ "threshold": 1, "keyids": [key2.key_id()] })
+        );
+        let decoded: RootMetadata = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, root);
+        assert_eq!(RootMetadataBuilder::from(decoded).build().unwrap(), root);
+
+        // Additional roles can't shadow top-level roles or have unsatisfiable thresholds.
+        assert_matches!(
+            builder().role_key("targets", key2.public().clone()).build(),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            builder()
+                .role_key("mirrors", key2.public().clone())
+                .role_threshold("mirrors", 2)
+                .build(),
+            Err(Error::IllegalArgument(_))
+        );
+    }
+
+    #[test]
+    fn root_metadata_builder_roles() {
+        let keys = [
+            ED25519_1_PK8,
+            ED25519_2_PK8,
+            ED25519_3_PK8,
+            ED25519_4_PK8,
+            include_bytes!("../tests/ed25519/ed25519-5.pk8.der"),
+        ]
+        .iter()
+        .map(|der| PrivateKey::from_pkcs8(der, SignatureScheme::Ed25519).unwrap())
+        .collect::<Vec<_>>();
+        let public = |range: std::ops::Range<usize>| {
+            keys[range]
+                .iter()
+                .map(|k| k.public().clone())
+                .collect::<Vec<_>>()
+        };
+        let builder = || {
+            RootMetadataBuilder::new()
+                .root_key(keys[4].public().clone())
+                .root_role(public(0..5), 3)
+                .snapshot_role(public(0..1), 1)
+                .targets_role(public(1..3), 2)
+                .timestamp_role(public(0..1), 1)
+        };
+
+        let root = builder().role("mirrors", public(3..5), 1).build().unwrap();
+        assert_eq!(root.keys().len(), 5);
+        assert_eq!(root.root().threshold(), 3);
+        assert_eq!(
+            root.root().key_ids(),
+            &keys.iter().map(|k| k.key_id().clone()).collect::<Vec<_>>()[..]
+        );
+        assert_eq!(root.targets().threshold(), 2);
+        assert_eq!(root.role_definition("mirrors").unwrap().key_ids().len(), 2);
+
+        // Thresholds must not exceed the number of distinct keys of the role.
+        assert_matches!(
+            builder().snapshot_role(public(0..2), 3).build(),
+            Err(Error::IllegalArgument(ref msg)) if msg.starts_with("Role snapshot:")
+        );
+        assert_matches!(
+            builder()
+                .timestamp_role(vec![keys[0].public().clone(); 2], 2)
+                .build(),
+            Err(Error::IllegalArgument(ref msg)) if msg.starts_with("Role timestamp:")
+        );
+        assert_matches!(
+            builder()
+                .snapshot_key(keys[0].public().clone())
+                .snapshot_threshold(2)
+                .build(),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            builder().role("mirrors", public(3..4), 2).build(),
+            Err(Error::IllegalArgument(ref msg)) if msg.starts_with("Role mirrors:")
+        );
+    }
+
     fn jsn_root_metadata_without_keyid_hash_algos() -> serde_json::Value {
         json!({
             "_type": "root",
@@ -2354,38 +4464,975 @@ mod test {
     }
 
     #[test]
-    fn de_ser_root_metadata_without_keyid_hash_algorithms() {
-        let jsn = jsn_root_metadata_without_keyid_hash_algos();
-        let decoded: RootMetadata = serde_json::from_value(jsn.clone()).unwrap();
-        let encoded = serde_json::to_value(decoded).unwrap();
+    fn de_ser_root_metadata_without_keyid_hash_algorithms() {
+        let jsn = jsn_root_metadata_without_keyid_hash_algos();
+        let decoded: RootMetadata = serde_json::from_value(jsn.clone()).unwrap();
+        let encoded = serde_json::to_value(decoded).unwrap();
+
+        assert_eq!(jsn, encoded);
+    }
+
+    #[test]
+    fn de_ser_root_metadata_wrong_key_id() {
+        let jsn = jsn_root_metadata_without_keyid_hash_algos();
+        let mut jsn_str = str::from_utf8(&Json::canonicalize(&jsn).unwrap())
+            .unwrap()
+            .to_owned();
+        // Replace the key id to something else.
+        jsn_str = jsn_str.replace(
+            "12435b260b6172bd750aeb102f54a347c56b109e0524ab1f144593c07af66356",
+            "00435b260b6172bd750aeb102f54a347c56b109e0524ab1f144593c07af66356",
+        );
+        let decoded: RootMetadata = serde_json::from_str(&jsn_str).unwrap();
+        assert_eq!(3, decoded.keys.len());
+    }
+
+    #[test]
+    fn de_ser_root_metadata_sha512_key_id() {
+        let jsn = jsn_root_metadata_without_keyid_hash_algos();
+        let sha512_key_id = "59a64db2a280c357946224c6f525a56bb970c8bcdc3703da1169d127dada7bb6\
+                             34da751e9997fd276744527890c42ea46e07e4e5230b3bd43d9afe6c587066fe";
+        let jsn_str = str::from_utf8(&Json::canonicalize(&jsn).unwrap())
+            .unwrap()
+            .replace(
+                "e0294a3f17cc8563c3ed5fceb3bd8d3f6bfeeaca499b5c9572729ae015566554",
+                sha512_key_id,
+            );
+
+        // The key is kept under the ID the metadata used, rather than ignored.
+        let decoded: RootMetadata = serde_json::from_str(&jsn_str).unwrap();
+        assert_eq!(4, decoded.keys.len());
+        let key_id = KeyId::from_str(sha512_key_id).unwrap();
+        let key = &decoded.keys[&key_id];
+        assert_eq!(key.key_id(), &key_id);
+        assert_eq!(
+            key.key_id_scheme(),
+            crypto::KeyIdScheme::Sha512CanonicalJson
+        );
+        assert_eq!(decoded.root().key_ids(), &[key_id]);
+    }
+
+    #[test]
+    fn verify_root_metadata_with_keyid_hash_algorithms_after_round_trip() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519)
+            .unwrap()
+            .with_keyid_hash_algorithms(Some(vec!["sha512".into()]))
+            .unwrap();
+        let root = RootMetadataBuilder::new()
+            .expires(Utc.with_ymd_and_hms(2038, 1, 1, 0, 0, 0).unwrap())
+            .root_key(key.public().clone())
+            .snapshot_key(key.public().clone())
+            .targets_key(key.public().clone())
+            .timestamp_key(key.public().clone())
+            .build()
+            .unwrap();
+        let signed = SignedMetadata::<Json, _>::new(&root, &key).unwrap();
+
+        let jsn = serde_json::to_value(&signed).unwrap();
+        let key_id = serde_json::to_value(key.key_id()).unwrap();
+        assert_eq!(
+            jsn["signed"]["keys"][key_id.as_str().unwrap()]["keyid_hash_algorithms"],
+            json!(["sha512"])
+        );
+
+        // Parsing and re-emitting the metadata preserves the field, so the signature still covers
+        // the same bytes.
+        let parsed: SignedMetadata<Json, RootMetadata> = serde_json::from_value(jsn).unwrap();
+        let parsed_key = parsed.assume_valid().unwrap().keys()[key.key_id()].clone();
+        let decoded = parsed.verify(1, &[parsed_key]).unwrap();
+        assert_eq!(decoded, root);
+        assert_eq!(
+            SignedMetadata::<Json, _>::new(&decoded, &key).unwrap(),
+            signed
+        );
+    }
+
+    #[test]
+    fn verify_root_metadata_with_unsupported_keys() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let root = RootMetadataBuilder::new()
+            .expires(Utc.with_ymd_and_hms(2038, 1, 1, 0, 0, 0).unwrap())
+            .root_key(key.public().clone())
+            .snapshot_key(key.public().clone())
+            .targets_key(key.public().clone())
+            .timestamp_key(key.public().clone())
+            .build()
+            .unwrap();
+
+        let mut jsn = serde_json::to_value(&root).unwrap();
+        for unsupported in &[
+            json!({
+                "keytype": "sphincs",
+                "scheme": "sphincs-shake-256f",
+                "keyval": { "public": "4a0c3e13b6f4c5f2" },
+            }),
+            json!({
+                "keytype": "ed25519",
+                "scheme": "ed25519ph",
+                "keyval": { "public": HEXLOWER.encode(key.public().as_bytes()) },
+            }),
+        ] {
+            let unsupported: PublicKey = serde_json::from_value(unsupported.clone()).unwrap();
+            let key_id = serde_json::to_value(unsupported.key_id()).unwrap();
+            jsn["keys"][key_id.as_str().unwrap()] = serde_json::to_value(&unsupported).unwrap();
+            jsn["roles"]["root"]["keyids"]
+                .as_array_mut()
+                .unwrap()
+                .push(key_id);
+        }
+
+        let root: RootMetadata = serde_json::from_value(jsn.clone()).unwrap();
+        assert_eq!(root.keys().len(), 3);
+        assert_eq!(root.root().key_ids().len(), 3);
+        assert_eq!(serde_json::to_value(&root).unwrap(), jsn);
+
+        // The supported key still meets a threshold of one, but the others never count.
+        let signed = SignedMetadata::<Json, _>::new(&root, &key).unwrap();
+        let authorized_keys = root
+            .root()
+            .key_ids()
+            .iter()
+            .map(|key_id| &root.keys()[key_id])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            signed.verify(1, authorized_keys.iter().cloned()).unwrap(),
+            root
+        );
+        assert!(signed.verify(2, authorized_keys).is_err());
+    }
+
+    #[test]
+    fn sign_and_verify_root_metadata() {
+        let jsn = jsn_root_metadata_without_keyid_hash_algos();
+        let root_key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let decoded: RootMetadata = serde_json::from_value(jsn).unwrap();
+
+        let signed: SignedMetadata<crate::interchange::cjson::Json, _> =
+            SignedMetadata::new(&decoded, &root_key).unwrap();
+        signed.verify(1, &[root_key.public().clone()]).unwrap();
+    }
+
+    /// A `Signer` that keeps its key out of reach of the caller, like an HSM would.
+    struct CountingSigner {
+        key: PrivateKey,
+        count: Cell<usize>,
+    }
+
+    impl Signer for CountingSigner {
+        fn key_id(&self) -> &KeyId {
+            self.key.key_id()
+        }
+
+        fn scheme(&self) -> &SignatureScheme {
+            self.key.public().scheme()
+        }
+
+        fn sign(&self, msg: &[u8]) -> Result<SignatureValue> {
+            self.count.set(self.count.get() + 1);
+            Ok(self.key.sign(msg)?.value().clone())
+        }
+    }
+
+    #[test]
+    fn sign_with_custom_signer() {
+        let root_key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let public = root_key.public().clone();
+        let signer = CountingSigner {
+            key: root_key,
+            count: Cell::new(0),
+        };
+
+        let mut signed = RootMetadataBuilder::new()
+            .root_key(public.clone())
+            .snapshot_key(public.clone())
+            .targets_key(public.clone())
+            .timestamp_key(public.clone())
+            .signed::<Json>(&signer)
+            .unwrap();
+        assert_eq!(signer.count.get(), 1);
+        assert_eq!(signed.signatures()[0].key_id(), public.key_id());
+        signed.verify(1, std::slice::from_ref(&public)).unwrap();
+
+        // Signing again with the same signer replaces its signature.
+        signed.add_signature(&signer).unwrap();
+        assert_eq!(signer.count.get(), 2);
+        assert_eq!(signed.signatures().len(), 1);
+        signed.verify(1, &[public]).unwrap();
+    }
+
+    #[test]
+    fn typed_custom_fields() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Release {
+            version: String,
+            severity: u8,
+        }
+
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let path = VirtualTargetPath::new("package".into()).unwrap();
+        let description = TargetDescription::from_reader(&b"package"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .with_custom_field("version", "1.2.3")
+            .unwrap()
+            .with_custom_field("severity", 3)
+            .unwrap();
+        let raw = TargetsMetadataBuilder::new()
+            .insert_target_description(path.clone(), description)
+            .signed::<Json>(&key)
+            .unwrap()
+            .to_raw()
+            .unwrap();
+
+        let targets = raw.parse().unwrap().verify(1, Some(key.public())).unwrap();
+        let description = &targets.targets()[&path];
+        assert_eq!(
+            description.custom_field::<String>("version").unwrap(),
+            Some("1.2.3".into())
+        );
+        assert_eq!(description.custom_field::<u8>("severity").unwrap(), Some(3));
+        assert_eq!(
+            description.custom_as::<Release>().unwrap(),
+            Some(Release {
+                version: "1.2.3".into(),
+                severity: 3,
+            })
+        );
+        assert_matches!(
+            description.custom_field::<u8>("version"),
+            Err(Error::Encoding(_))
+        );
+
+        let plain = TargetDescription::from_reader(&b""[..], &[HashAlgorithm::Sha256]).unwrap();
+        assert_eq!(plain.custom_field::<String>("version").unwrap(), None);
+        assert_eq!(plain.custom_as::<Release>().unwrap(), None);
+    }
+
+    #[test]
+    fn builders_validate_expiration() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let past = Utc::now() - Duration::seconds(1);
+
+        let before = Utc::now();
+        let targets = TargetsMetadataBuilder::new()
+            .expires_in(Duration::days(3))
+            .build()
+            .unwrap();
+        assert!(*targets.expires() >= before + Duration::days(3));
+        assert!(*targets.expires() <= Utc::now() + Duration::days(3));
+
+        assert_matches!(
+            RootMetadataBuilder::new()
+                .expires(past)
+                .root_key(key.public().clone())
+                .snapshot_key(key.public().clone())
+                .targets_key(key.public().clone())
+                .timestamp_key(key.public().clone())
+                .build(),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            SnapshotMetadataBuilder::new()
+                .expires_in(Duration::days(-1))
+                .build(),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            TargetsMetadataBuilder::new().expires(past).build(),
+            Err(Error::IllegalArgument(_))
+        );
+        let snapshot = SnapshotMetadataBuilder::new().signed::<Json>(&key).unwrap();
+        assert_matches!(
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .expires(past)
+                .build(),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_matches!(
+            HashedBinsBuilder::new(2)
+                .key(key.public().clone())
+                .expires(past)
+                .signed::<Json>(&key)
+                .err(),
+            Some(Error::IllegalArgument(_))
+        );
+
+        let targets = TargetsMetadataBuilder::new()
+            .expires(past)
+            .allow_expired(true)
+            .build()
+            .unwrap();
+        assert_eq!(targets.expires(), &past);
+    }
+
+    #[test]
+    fn signed_metadata_file_round_trip() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("targets.json");
+
+        assert_matches!(
+            SignedMetadata::<Json, TargetsMetadata>::from_file(&path),
+            Err(Error::NotFound)
+        );
+
+        let targets = TargetsMetadataBuilder::new().signed::<Json>(&key).unwrap();
+        targets.to_file(&path).unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            targets.to_raw().unwrap().as_bytes()
+        );
+
+        // Overwriting replaces the previous metadata.
+        let targets = TargetsMetadataBuilder::new()
+            .version(2)
+            .signed::<Json>(&key)
+            .unwrap();
+        targets.to_file(&path).unwrap();
+        let read = SignedMetadata::<Json, TargetsMetadata>::from_file(&path).unwrap();
+        assert_eq!(read, targets);
+        assert_eq!(read.parse_version_untrusted().unwrap(), 2);
+    }
+
+    #[test]
+    fn insert_target_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("packages")).unwrap();
+        std::fs::write(dir.path().join("packages").join("foo.tgz"), b"foo").unwrap();
+
+        let hash_algs = &[HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+        let targets = TargetsMetadataBuilder::new()
+            .insert_target_from_dir(dir.path(), Path::new("packages").join("foo.tgz"), hash_algs)
+            .unwrap()
+            .insert_target_from_path(
+                VirtualTargetPath::new("renamed".into()).unwrap(),
+                dir.path().join("packages").join("foo.tgz"),
+                hash_algs,
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let expected = TargetDescription::from_reader(&b"foo"[..], hash_algs).unwrap();
+        assert_eq!(
+            targets.targets(),
+            &hashmap! {
+                VirtualTargetPath::new("packages/foo.tgz".into()).unwrap() => expected.clone(),
+                VirtualTargetPath::new("renamed".into()).unwrap() => expected,
+            }
+        );
+
+        for bad in &["../foo.tgz", "./packages/foo.tgz"] {
+            assert_matches!(
+                TargetsMetadataBuilder::new()
+                    .insert_target_from_dir(dir.path(), bad, hash_algs)
+                    .err(),
+                Some(Error::IllegalArgument(_))
+            );
+        }
+        assert_matches!(
+            TargetsMetadataBuilder::new()
+                .insert_target_from_dir(dir.path(), "missing", hash_algs)
+                .err(),
+            Some(Error::NotFound)
+        );
+    }
+
+    #[test]
+    fn insert_target_from_async_reader() {
+        let hash_algs = &[HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+        let path = VirtualTargetPath::new("generated".into()).unwrap();
+        let data: &[u8] = &[0xa5; 3000];
+
+        let targets = futures_executor::block_on(
+            TargetsMetadataBuilder::new().insert_target_from_async_reader(
+                path.clone(),
+                data,
+                hash_algs,
+            ),
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+        assert_eq!(
+            targets.targets(),
+            &hashmap! { path => TargetDescription::from_reader(data, hash_algs).unwrap() }
+        );
+    }
+
+    #[test]
+    fn hashed_bins() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let mut builder = HashedBinsBuilder::new(4)
+            .key(key.public().clone())
+            .version(2);
+        for i in 0..64 {
+            builder = builder
+                .insert_target_from_reader(
+                    VirtualTargetPath::new(format!("targets/{}", i)).unwrap(),
+                    &b"data"[..],
+                    &[HashAlgorithm::Sha256],
+                )
+                .unwrap();
+        }
+        let bins = builder.signed::<Json>(&key).unwrap();
+
+        let roles = bins
+            .bins()
+            .iter()
+            .map(|bin| bin.role().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(roles, vec!["0-3", "4-7", "8-b", "c-f"]);
+        assert_eq!(bins.bins()[2].hash_prefixes(), &["8", "9", "a", "b"]);
+        assert_eq!(
+            bins.delegations().keys().keys().collect::<Vec<_>>(),
+            vec![key.key_id()]
+        );
+
+        let mut count = 0;
+        for (bin, delegation) in bins.bins().iter().zip(bins.delegations().roles()) {
+            assert_eq!(bin.role(), delegation.role());
+            bin.metadata().verify(1, Some(key.public())).unwrap();
+
+            let targets = bin.metadata().assume_valid().unwrap();
+            assert_eq!(targets.version(), 2);
+            for path in targets.targets().keys() {
+                let hash = crypto::calculate_hash(path.value().as_bytes(), HashAlgorithm::Sha256);
+                assert!(bin
+                    .hash_prefixes()
+                    .contains(&hash.to_hex()[..1].to_string()));
+                assert!(delegation.matches(path));
+                count += 1;
+            }
+        }
+        assert_eq!(count, 64);
+
+        let delegation = &bins.delegations().roles()[2];
+        assert!(delegation.paths().is_empty());
+        assert_eq!(
+            delegation.path_hash_prefixes(),
+            &hashset! {"8".into(), "9".into(), "a".into(), "b".into()}
+        );
+
+        // Bins without targets are still delegated to.
+        let bins = HashedBinsBuilder::new(16)
+            .key(key.public().clone())
+            .insert_target_from_reader(
+                VirtualTargetPath::new("lonely".into()).unwrap(),
+                &b"data"[..],
+                &[HashAlgorithm::Sha256],
+            )
+            .unwrap()
+            .signed::<Json>(&key)
+            .unwrap();
+        assert_eq!(bins.bins().len(), 16);
+        assert_eq!(bins.delegations().roles().len(), 16);
+        let count = bins
+            .bins()
+            .iter()
+            .map(|bin| bin.metadata().assume_valid().unwrap().targets().len())
+            .sum::<usize>();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn hashed_bins_names() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let names = |number_of_bins| {
+            let mut builder = HashedBinsBuilder::new(number_of_bins).key(key.public().clone());
+            for i in 0..2048 {
+                builder = builder.insert_target_description(
+                    VirtualTargetPath::new(format!("{}", i)).unwrap(),
+                    TargetDescription::from_reader(&b""[..], &[HashAlgorithm::Sha256]).unwrap(),
+                );
+            }
+            builder
+                .signed::<Json>(&key)
+                .unwrap()
+                .bins()
+                .iter()
+                .map(|bin| bin.role().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(2), vec!["0-7", "8-f"]);
+        let names_32 = names(32);
+        assert_eq!(names_32.len(), 32);
+        assert_eq!(names_32[0], "00-07");
+        assert_eq!(names_32[31], "f8-ff");
+        let names_256 = names(256);
+        assert_eq!(names_256.len(), 256);
+        assert_eq!(names_256[0], "00");
+        assert_eq!(names_256[255], "ff");
+
+        for bad in &[0, 1, 3, 24] {
+            assert_matches!(
+                HashedBinsBuilder::new(*bad)
+                    .key(key.public().clone())
+                    .signed::<Json>(&key)
+                    .err(),
+                Some(Error::IllegalArgument(_))
+            );
+        }
+    }
+
+    #[test]
+    fn hashed_bins_with_max_targets_per_bin() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let bins = |number_of_targets, max_targets_per_bin| {
+            let mut builder = HashedBinsBuilder::with_max_targets_per_bin(max_targets_per_bin)
+                .key(key.public().clone());
+            for i in 0..number_of_targets {
+                builder = builder.insert_target_description(
+                    VirtualTargetPath::new(format!("{}", i)).unwrap(),
+                    TargetDescription::from_reader(&b""[..], &[HashAlgorithm::Sha256]).unwrap(),
+                );
+            }
+            builder.signed::<Json>(&key)
+        };
+
+        let targets_per_bin = |bins: &HashedBins<Json>| {
+            bins.bins()
+                .iter()
+                .map(|bin| bin.metadata().assume_valid().unwrap().targets().len())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(bins(0, 10).unwrap().bins().len(), 2);
+        for (number_of_targets, max_targets_per_bin) in
+            [(20usize, 10usize), (21, 10), (100, 10), (1000, 10)]
+        {
+            let counts = targets_per_bin(&bins(number_of_targets, max_targets_per_bin).unwrap());
+            assert!(counts.len() >= number_of_targets.div_ceil(max_targets_per_bin));
+            assert!(counts.iter().all(|&count| count <= max_targets_per_bin));
+
+            // Halving the bins merges neighbouring bins, and would put too many targets in one.
+            if counts.len() > 2 {
+                assert!(counts
+                    .chunks(2)
+                    .any(|pair| pair[0] + pair[1] > max_targets_per_bin));
+            }
+        }
+        assert_matches!(bins(1, 0).err(), Some(Error::IllegalArgument(_)));
+
+        // Every bin is described by the snapshot.
+        let bins = bins(100, 10).unwrap();
+        let snapshot = bins
+            .insert_into_snapshot(SnapshotMetadataBuilder::new(), &[HashAlgorithm::Sha256])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(snapshot.meta().len(), bins.bins().len());
+        for bin in bins.bins() {
+            assert_eq!(
+                snapshot.meta()[bin.role()].version(),
+                bin.metadata().parse_version_untrusted().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn insert_external_signatures() {
+        let key_1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let key_2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let public_keys = [key_1.public(), key_2.public()];
+        let snapshot = SnapshotMetadataBuilder::new().build().unwrap();
+
+        let builder = SignedMetadataBuilder::<Json, _>::from_metadata(&snapshot).unwrap();
+        let bytes = builder.canonical_bytes().to_vec();
+        let value = key_1.sign(&bytes).unwrap().value().clone();
+        let sig_1 = Signature::new(key_1.key_id().clone(), value);
+        let mut signed = builder.insert_signature(sig_1).build();
+        assert_eq!(signed.canonical_bytes().unwrap(), bytes);
+        signed.verify(1, public_keys.iter().copied()).unwrap();
+
+        // A signature over the wrong bytes is accepted but does not count.
+        signed.insert_signature(key_2.sign(b"wrong").unwrap());
+        assert_eq!(signed.signatures().len(), 2);
+        assert_matches!(
+            signed.verify(2, public_keys.iter().copied()),
+            Err(Error::VerificationFailure(_))
+        );
+
+        // Inserting a signature for the same key replaces the bad one.
+        signed.insert_signature(key_2.sign(&bytes).unwrap());
+        assert_eq!(signed.signatures().len(), 2);
+        signed.verify(2, public_keys.iter().copied()).unwrap();
+    }
+
+    /// Check that serializing, parsing, and serializing `signed` again produces the same bytes.
+    fn assert_serialization_fixed_point<D, M>(signed: &SignedMetadata<D, M>) -> Vec<u8>
+    where
+        D: DataInterchange,
+        M: Metadata,
+        SignedMetadata<D, M>: Debug,
+    {
+        let raw = signed.to_raw().unwrap();
+        let parsed = raw.parse().unwrap();
+        assert_eq!(&parsed, signed);
+        let reserialized = parsed.to_raw().unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(reserialized.as_bytes()),
+            String::from_utf8_lossy(raw.as_bytes())
+        );
+        raw.as_bytes().to_vec()
+    }
+
+    #[test]
+    fn serialization_is_deterministic() {
+        let keys = [ED25519_1_PK8, ED25519_2_PK8, ED25519_3_PK8, ED25519_4_PK8]
+            .iter()
+            .map(|der| PrivateKey::from_pkcs8(der, SignatureScheme::Ed25519).unwrap())
+            .collect::<Vec<_>>();
+        let expires = Utc.with_ymd_and_hms(2038, 1, 1, 0, 0, 0).unwrap();
+
+        // Build the same metadata twice, adding everything in a different order each time.
+        let build = |order: &[usize]| {
+            let public = order
+                .iter()
+                .map(|&i| keys[i].public().clone())
+                .collect::<Vec<_>>();
+
+            let root = RootMetadataBuilder::new()
+                .expires(expires)
+                .root_role(public.clone(), 2)
+                .snapshot_role(public.clone(), 1)
+                .targets_role(public.clone(), 1)
+                .timestamp_role(public.clone(), 1)
+                .build()
+                .unwrap();
+
+            let mut targets = TargetsMetadataBuilder::new().expires(expires);
+            for &i in order {
+                targets = targets
+                    .insert_target_from_reader(
+                        VirtualTargetPath::new(format!("target-{}", i)).unwrap(),
+                        format!("contents {}", i).as_bytes(),
+                        &[HashAlgorithm::Sha256, HashAlgorithm::Sha512],
+                    )
+                    .unwrap();
+            }
+            let delegations = DelegationsBuilder::new()
+                .key(keys[0].public().clone())
+                .key(keys[1].public().clone())
+                .role(
+                    "delegated",
+                    false,
+                    1,
+                    order
+                        .iter()
+                        .filter(|&&i| i < 2)
+                        .map(|&i| keys[i].key_id().clone()),
+                    order.iter().map(|i| format!("dir-{}/", i)),
+                )
+                .build()
+                .unwrap();
+            let targets = targets.delegations(delegations).build().unwrap();
+
+            let mut snapshot = SnapshotMetadataBuilder::new().expires(expires);
+            for &i in order {
+                snapshot = snapshot.insert_metadata_description(
+                    MetadataPath::new(format!("role-{}", i)).unwrap(),
+                    MetadataDescription::from_version(i as u32 + 1).unwrap(),
+                );
+            }
+            let snapshot = snapshot.build().unwrap();
+
+            let timestamp = TimestampMetadataBuilder::from_metadata_description(
+                MetadataDescription::from_version(1).unwrap(),
+            )
+            .expires(expires)
+            .build()
+            .unwrap();
+
+            fn sign<M: Metadata>(
+                builder: SignedMetadataBuilder<Json, M>,
+                keys: &[PrivateKey],
+                order: &[usize],
+            ) -> SignedMetadata<Json, M> {
+                let mut signed = builder.build();
+                for &i in order {
+                    signed.add_signature(&keys[i]).unwrap();
+                }
+                signed
+            }
+
+            (
+                assert_serialization_fixed_point(&sign(
+                    SignedMetadataBuilder::from_metadata(&root).unwrap(),
+                    &keys,
+                    order,
+                )),
+                assert_serialization_fixed_point(&sign(
+                    SignedMetadataBuilder::from_metadata(&targets).unwrap(),
+                    &keys,
+                    order,
+                )),
+                assert_serialization_fixed_point(&sign(
+                    SignedMetadataBuilder::from_metadata(&snapshot).unwrap(),
+                    &keys,
+                    order,
+                )),
+                assert_serialization_fixed_point(&sign(
+                    SignedMetadataBuilder::from_metadata(&timestamp).unwrap(),
+                    &keys,
+                    order,
+                )),
+                assert_serialization_fixed_point(
+                    &SignedMetadata::<JsonPretty, _>::new(&targets, &keys[order[0]]).unwrap(),
+                ),
+            )
+        };
+
+        let forward = build(&[0, 1, 2, 3]);
+        let backward = build(&[3, 2, 1, 0]);
+        // Root role definitions keep their key IDs in the order they were added, so only rebuilding
+        // the root metadata the same way is byte-identical.
+        assert_eq!(forward.0, build(&[0, 1, 2, 3]).0);
+        assert_ne!(forward.0, backward.0);
+        assert_eq!(forward.1, backward.1);
+        assert_eq!(forward.2, backward.2);
+        assert_eq!(forward.3, backward.3);
+        assert_serialization_fixed_point(
+            &RawSignedMetadata::<JsonPretty, TargetsMetadata>::new(backward.4)
+                .parse()
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn parsed_signatures_are_sorted() {
+        let key_1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let key_2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let snapshot = SnapshotMetadataBuilder::new().build().unwrap();
+        let signed = SignedMetadataBuilder::<Json, _>::from_metadata(&snapshot)
+            .unwrap()
+            .sign(&key_1)
+            .unwrap()
+            .sign(&key_2)
+            .unwrap()
+            .build();
+
+        let mut jsn = serde_json::to_value(&signed).unwrap();
+        jsn["signatures"].as_array_mut().unwrap().reverse();
+        let parsed: SignedMetadata<Json, SnapshotMetadata> = serde_json::from_value(jsn).unwrap();
+        assert_eq!(parsed, signed);
+
+        let mut key_ids = vec![key_1.key_id(), key_2.key_id()];
+        key_ids.sort();
+        assert_eq!(
+            parsed
+                .signatures()
+                .iter()
+                .map(|s| s.key_id())
+                .collect::<Vec<_>>(),
+            key_ids
+        );
+    }
 
-        assert_eq!(jsn, encoded);
+    #[test]
+    fn remove_and_replace_signatures() {
+        let key_1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let key_2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let key_3 = PrivateKey::from_pkcs8(ED25519_3_PK8, SignatureScheme::Ed25519).unwrap();
+
+        let mut signed = SnapshotMetadataBuilder::new()
+            .signed::<Json>(&key_1)
+            .unwrap();
+        signed.add_signature(&key_2).unwrap();
+        let original = signed.clone();
+
+        assert_matches!(
+            signed.replace_signature(key_3.key_id(), &key_3),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_eq!(signed, original);
+
+        // Rotate key 1 out in favor of key 3. The payload is untouched.
+        let old = signed.replace_signature(key_1.key_id(), &key_3).unwrap();
+        assert_eq!(old.key_id(), key_1.key_id());
+        assert_eq!(
+            signed.canonical_bytes().unwrap(),
+            original.canonical_bytes().unwrap()
+        );
+        assert_eq!(
+            signed
+                .signatures()
+                .iter()
+                .map(|s| s.key_id().clone())
+                .collect::<HashSet<_>>(),
+            hashset! { key_2.key_id().clone(), key_3.key_id().clone() }
+        );
+        signed
+            .verify(2, vec![key_2.public(), key_3.public()])
+            .unwrap();
+
+        // Replacing a signature with one from a key that already signed doesn't duplicate it.
+        signed.replace_signature(key_2.key_id(), &key_3).unwrap();
+        assert_eq!(signed.signatures().len(), 1);
+
+        assert_eq!(
+            signed.remove_signature(key_3.key_id()).unwrap().key_id(),
+            key_3.key_id()
+        );
+        assert!(signed.signatures().is_empty());
+        assert_eq!(signed.remove_signature(key_3.key_id()), None);
     }
 
     #[test]
-    fn de_ser_root_metadata_wrong_key_id() {
-        let jsn = jsn_root_metadata_without_keyid_hash_algos();
-        let mut jsn_str = str::from_utf8(&Json::canonicalize(&jsn).unwrap())
-            .unwrap()
-            .to_owned();
-        // Replace the key id to something else.
-        jsn_str = jsn_str.replace(
-            "12435b260b6172bd750aeb102f54a347c56b109e0524ab1f144593c07af66356",
-            "00435b260b6172bd750aeb102f54a347c56b109e0524ab1f144593c07af66356",
+    fn signing_requests() {
+        let key_1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let key_2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let key_3 = PrivateKey::from_pkcs8(ED25519_3_PK8, SignatureScheme::Ed25519).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("request.json");
+
+        // A partially signed request keeps its existing signatures.
+        let mut signed = SnapshotMetadataBuilder::new()
+            .signed::<Json>(&key_1)
+            .unwrap();
+        let request = signed.signing_request().unwrap();
+        assert_eq!(request.metadata(), &signed);
+        assert_eq!(
+            request.canonical_bytes(),
+            &signed.canonical_bytes().unwrap()[..]
         );
-        let decoded: RootMetadata = serde_json::from_str(&jsn_str).unwrap();
-        assert_eq!(3, decoded.keys.len());
+        request.to_file(&path).unwrap();
+
+        let request = SigningRequest::<Json, SnapshotMetadata>::from_file(&path).unwrap();
+        assert_eq!(request.metadata(), &signed);
+        let sig_2 = request.sign(&key_2).unwrap();
+        let sig_3 = request.sign(&key_3).unwrap();
+
+        // Nothing is imported if any signature is from an unauthorized key.
+        assert_matches!(
+            signed.import_signatures(
+                vec![sig_2.clone(), sig_3.clone()],
+                vec![key_1.public(), key_2.public()]
+            ),
+            Err(Error::VerificationFailure(_))
+        );
+        assert_eq!(signed.signatures().len(), 1);
+
+        // Or if a signature is over the wrong bytes.
+        let bad = key_3.sign(b"wrong").unwrap();
+        assert_matches!(
+            signed.import_signatures(vec![bad], vec![key_3.public()]),
+            Err(Error::VerificationFailure(_))
+        );
+        assert_eq!(signed.signatures().len(), 1);
+
+        signed
+            .import_signatures(
+                vec![sig_2, sig_3],
+                vec![key_1.public(), key_2.public(), key_3.public()],
+            )
+            .unwrap();
+        signed
+            .verify(3, vec![key_1.public(), key_2.public(), key_3.public()])
+            .unwrap();
+
+        // A request whose bytes don't match its metadata is refused.
+        let mut jsn: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        jsn["canonical_bytes"] = json!(HEXLOWER.encode(b"something else"));
+        std::fs::write(&path, serde_json::to_vec(&jsn).unwrap()).unwrap();
+        assert_matches!(
+            SigningRequest::<Json, SnapshotMetadata>::from_file(&path),
+            Err(Error::VerificationFailure(_))
+        );
+        let request: SigningRequest<Json, SnapshotMetadata> = serde_json::from_value(jsn).unwrap();
+        assert_matches!(request.sign(&key_2), Err(Error::VerificationFailure(_)));
     }
 
     #[test]
-    fn sign_and_verify_root_metadata() {
-        let jsn = jsn_root_metadata_without_keyid_hash_algos();
+    fn merge_signatures() {
+        let key_1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let key_2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let key_3 = PrivateKey::from_pkcs8(ED25519_3_PK8, SignatureScheme::Ed25519).unwrap();
+        let keys = [key_1.public(), key_2.public(), key_3.public()];
+
+        let root = RootMetadataBuilder::new()
+            .root_key(key_1.public().clone())
+            .root_key(key_2.public().clone())
+            .root_key(key_3.public().clone())
+            .snapshot_key(key_1.public().clone())
+            .targets_key(key_1.public().clone())
+            .timestamp_key(key_1.public().clone())
+            .build()
+            .unwrap();
+        let builder = SignedMetadataBuilder::<Json, _>::from_metadata(&root).unwrap();
+
+        let mut merged = builder.clone().sign(&key_1).unwrap().build();
+        let signed_2 = builder.clone().sign(&key_2).unwrap().build();
+        let signed_3 = builder.clone().sign(&key_3).unwrap().build();
+
+        merged.merge_signatures(&signed_2).unwrap();
+        merged.merge_signatures(&signed_3).unwrap();
+        // Merging the same signatures again doesn't duplicate them.
+        merged.merge_signatures(&signed_2).unwrap();
+        assert_eq!(merged.signatures().len(), 3);
+        merged.verify(3, keys.iter().copied()).unwrap();
+
+        // The signatures of `self` win when both copies are signed by the same key.
+        let mut bad = builder
+            .clone()
+            .insert_signature(key_1.sign(b"wrong").unwrap())
+            .build();
+        bad.merge_signatures(&merged).unwrap();
+        assert_eq!(bad.signatures().len(), 3);
+        assert_matches!(
+            bad.verify(3, keys.iter().copied()),
+            Err(Error::VerificationFailure(_))
+        );
+
+        // Copies of different metadata can't be merged.
+        let other = RootMetadataBuilder::from(root)
+            .version(2)
+            .signed::<Json>(&key_2)
+            .unwrap();
+        assert_matches!(
+            merged.merge_signatures(&other),
+            Err(Error::IllegalArgument(_))
+        );
+        assert_eq!(merged.signatures().len(), 3);
+    }
+
+    #[test]
+    fn sign_with_prompt() {
         let root_key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
-        let decoded: RootMetadata = serde_json::from_value(jsn).unwrap();
+        let public = root_key.public().clone();
+        let builder = || {
+            RootMetadataBuilder::new()
+                .root_key(public.clone())
+                .snapshot_key(public.clone())
+                .targets_key(public.clone())
+                .timestamp_key(public.clone())
+        };
+
+        // The operator isn't ready, so the key is never used.
+        let signer = CountingSigner {
+            key: root_key,
+            count: Cell::new(0),
+        }
+        .with_prompt(|_: &KeyId| Err(Error::Opaque("ceremony aborted".into())));
+        assert_eq!(
+            builder().signed::<Json>(&signer).unwrap_err(),
+            Error::Opaque("ceremony aborted".into())
+        );
+        let signer = signer.into_inner();
+        assert_eq!(signer.count.get(), 0);
 
-        let signed: SignedMetadata<crate::interchange::cjson::Json, _> =
-            SignedMetadata::new(&decoded, &root_key).unwrap();
-        signed.verify(1, &[root_key.public().clone()]).unwrap();
+        let prompted = RefCell::new(Vec::new());
+        let signer = signer.with_prompt(|key_id: &KeyId| {
+            prompted.borrow_mut().push(key_id.clone());
+            Ok(())
+        });
+        let signed = builder().signed::<Json>(&signer).unwrap();
+        assert_eq!(&*prompted.borrow(), &[public.key_id().clone()]);
+        assert_eq!(signer.into_inner().count.get(), 1);
+        signed.verify(1, &[public]).unwrap();
     }
 
     #[test]
@@ -2427,6 +5474,69 @@ mod test {
         decoded.verify(1, &[root_key.public().clone()]).unwrap();
     }
 
+    #[test]
+    fn verify_threshold_of_many_signatures() {
+        let keys = [ED25519_1_PK8, ED25519_2_PK8, ED25519_3_PK8, ED25519_4_PK8]
+            .iter()
+            .map(|der| PrivateKey::from_pkcs8(der, SignatureScheme::Ed25519).unwrap())
+            .collect::<Vec<_>>();
+        let public_keys = keys.iter().map(|k| k.public()).collect::<Vec<_>>();
+
+        let snapshot = SnapshotMetadataBuilder::new().build().unwrap();
+        let mut signed = SignedMetadata::<Json, _>::new(&snapshot, &keys[0]).unwrap();
+        for key in &keys[1..] {
+            signed.add_signature(key).unwrap();
+        }
+
+        signed.verify(4, public_keys.iter().copied()).unwrap();
+        assert_matches!(
+            signed.verify(5, public_keys.iter().copied()),
+            Err(Error::VerificationFailure(_))
+        );
+
+        // Signatures from keys that aren't authorized don't count.
+        signed.verify(2, public_keys[..2].iter().copied()).unwrap();
+        assert_matches!(
+            signed.verify(3, public_keys[..2].iter().copied()),
+            Err(Error::VerificationFailure(_))
+        );
+
+        // Neither do bad signatures.
+        let other = SnapshotMetadataBuilder::new().version(2).build().unwrap();
+        let other = SignedMetadata::<Json, _>::new(&other, &keys[3]).unwrap();
+        let mut tampered = SignedMetadata::<Json, _>::new(&snapshot, &keys[0]).unwrap();
+        for key in &keys[1..3] {
+            tampered.add_signature(key).unwrap();
+        }
+        tampered.signatures.push(other.signatures[0].clone());
+        tampered.verify(3, public_keys.iter().copied()).unwrap();
+        assert_matches!(
+            tampered.verify(4, public_keys.iter().copied()),
+            Err(Error::VerificationFailure(_))
+        );
+    }
+
+    #[test]
+    fn verify_with_scheme_policy() {
+        let ed25519_key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let ecdsa_key = PrivateKey::generate(crate::crypto::KeyType::EcdsaP256).unwrap();
+        let public_keys = [ed25519_key.public(), ecdsa_key.public()];
+
+        let snapshot = SnapshotMetadataBuilder::new().build().unwrap();
+        let mut signed = SignedMetadata::<Json, _>::new(&snapshot, &ed25519_key).unwrap();
+        signed.add_signature(&ecdsa_key).unwrap();
+
+        let policy = SchemePolicy::default().allow_schemes(vec![SignatureScheme::Ed25519]);
+        signed
+            .verify_with_policy(1, public_keys.iter().copied(), &policy)
+            .unwrap();
+        assert_matches!(
+            signed.verify_with_policy(2, public_keys.iter().copied(), &policy),
+            Err(Error::VerificationFailure(_))
+        );
+        signed.verify(2, public_keys.iter().copied()).unwrap();
+    }
+
     fn verify_signature_with_unknown_fields<M>(mut metadata: serde_json::Value)
     where
         M: Metadata,
@@ -2510,6 +5620,7 @@ mod test {
 
         let timestamp = TimestampMetadataBuilder::from_metadata_description(description)
             .expires(Utc.ymd(2017, 1, 1).and_hms(0, 0, 0))
+            .allow_expired(true)
             .build()
             .unwrap();
 
@@ -2535,6 +5646,34 @@ mod test {
         assert_eq!(decoded, timestamp);
     }
 
+    #[test]
+    fn serde_timestamp_metadata_version_only() {
+        let description = MetadataDescription::from_version(2).unwrap();
+        let timestamp = TimestampMetadataBuilder::from_metadata_description(description)
+            .expires(Utc.with_ymd_and_hms(2017, 1, 1, 0, 0, 0).unwrap())
+            .allow_expired(true)
+            .build()
+            .unwrap();
+
+        let jsn = json!({
+            "_type": "timestamp",
+            "spec_version": "1.0",
+            "version": 1,
+            "expires": "2017-01-01T00:00:00Z",
+            "meta": {
+                "snapshot.json": {
+                    "version": 2,
+                },
+            }
+        });
+
+        let encoded = serde_json::to_value(&timestamp).unwrap();
+        assert_eq!(encoded, jsn);
+        let decoded: TimestampMetadata = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, timestamp);
+        assert_eq!(decoded.snapshot().length(), None);
+    }
+
     #[test]
     fn serde_timestamp_metadata_missing_snapshot() {
         let jsn = json!({
@@ -2587,6 +5726,7 @@ mod test {
     fn serde_snapshot_metadata() {
         let snapshot = SnapshotMetadataBuilder::new()
             .expires(Utc.ymd(2017, 1, 1).and_hms(0, 0, 0))
+            .allow_expired(true)
             .insert_metadata_description(
                 MetadataPath::new("targets").unwrap(),
                 MetadataDescription::new(
@@ -2621,10 +5761,70 @@ mod test {
         assert_eq!(decoded, snapshot);
     }
 
+    #[test]
+    fn serde_snapshot_metadata_version_only() {
+        let snapshot = SnapshotMetadataBuilder::new()
+            .expires(Utc.with_ymd_and_hms(2017, 1, 1, 0, 0, 0).unwrap())
+            .allow_expired(true)
+            .insert_metadata_description(
+                MetadataPath::new("targets").unwrap(),
+                MetadataDescription::from_version(2).unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        let jsn = json!({
+            "_type": "snapshot",
+            "spec_version": "1.0",
+            "version": 1,
+            "expires": "2017-01-01T00:00:00Z",
+            "meta": {
+                "targets.json": {
+                    "version": 2,
+                },
+            },
+        });
+
+        let encoded = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(encoded, jsn);
+        let decoded: SnapshotMetadata = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        let description = &decoded.meta()[&MetadataPath::new("targets").unwrap()];
+        assert_eq!(description.version(), 2);
+        assert_eq!(description.length(), None);
+        assert!(description.hashes().is_empty());
+
+        // Hashes may be omitted, but not listed empty.
+        let jsn = json!({ "version": 2, "hashes": {} });
+        assert!(serde_json::from_value::<MetadataDescription>(jsn).is_err());
+    }
+
+    #[test]
+    fn snapshot_builder_inserts_metadata_version() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .version(3)
+            .signed::<Json>(&key)
+            .unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata_version(&targets)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            snapshot.meta()[&MetadataPath::new("targets").unwrap()],
+            MetadataDescription::from_version(3).unwrap()
+        );
+    }
+
     #[test]
     fn serde_targets_metadata() {
         let targets = TargetsMetadataBuilder::new()
             .expires(Utc.ymd(2017, 1, 1).and_hms(0, 0, 0))
+            .allow_expired(true)
             .insert_target_description(
                 VirtualTargetPath::new("foo".into()).unwrap(),
                 TargetDescription::from_reader(&b"foo"[..], &[HashAlgorithm::Sha256]).unwrap(),
@@ -2712,6 +5912,7 @@ mod test {
 
         let targets = TargetsMetadataBuilder::new()
             .expires(Utc.ymd(2017, 1, 1).and_hms(0, 0, 0))
+            .allow_expired(true)
             .delegations(delegations)
             .build()
             .unwrap();
@@ -2756,6 +5957,7 @@ mod test {
     fn serde_signed_metadata() {
         let snapshot = SnapshotMetadataBuilder::new()
             .expires(Utc.ymd(2017, 1, 1).and_hms(0, 0, 0))
+            .allow_expired(true)
             .insert_metadata_description(
                 MetadataPath::new("targets").unwrap(),
                 MetadataDescription::new(
@@ -2862,6 +6064,7 @@ mod test {
 
         let timestamp = TimestampMetadataBuilder::from_metadata_description(description)
             .expires(Utc.ymd(2017, 1, 1).and_hms(0, 0, 0))
+            .allow_expired(true)
             .build()
             .unwrap();
 
@@ -3256,7 +6459,7 @@ mod test {
         delegations
             .as_object_mut()
             .unwrap()
-            .get_mut("keys".into())
+            .get_mut("keys")
             .unwrap()
             .as_object_mut()
             .unwrap()
@@ -3264,6 +6467,59 @@ mod test {
         assert!(serde_json::from_value::<Delegations>(delegations).is_err());
     }
 
+    #[test]
+    fn verify_delegated_targets_with_mixed_signature_schemes() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let unknown_scheme_key: PublicKey = serde_json::from_value(json!({
+            "keytype": "rsa",
+            "scheme": "rsassa-pss-sha1",
+            "keyval": { "public": "not a key we can use" },
+        }))
+        .unwrap();
+        let unknown_key_id = serde_json::to_value(unknown_scheme_key.key_id()).unwrap();
+
+        let mut jsn = make_delegations();
+        jsn["keys"][unknown_key_id.as_str().unwrap()] =
+            serde_json::to_value(&unknown_scheme_key).unwrap();
+        jsn["roles"][0]["keyids"]
+            .as_array_mut()
+            .unwrap()
+            .push(unknown_key_id.clone());
+
+        let delegations: Delegations = serde_json::from_value(jsn).unwrap();
+        assert_eq!(
+            delegations.keys()[unknown_scheme_key.key_id()].scheme(),
+            &SignatureScheme::Unknown("rsassa-pss-sha1".into())
+        );
+        let delegation = &delegations.roles()[0];
+        assert_eq!(delegation.key_ids().len(), 2);
+        let authorized_keys = delegation
+            .key_ids()
+            .iter()
+            .map(|key_id| &delegations.keys()[key_id])
+            .collect::<Vec<_>>();
+
+        // A signature claiming to be from the key with the unknown scheme is never good.
+        let targets = TargetsMetadataBuilder::new()
+            .expires(Utc.with_ymd_and_hms(2038, 1, 1, 0, 0, 0).unwrap())
+            .build()
+            .unwrap();
+        let signed = SignedMetadata::<Json, _>::new(&targets, &key).unwrap();
+        let mut signed_jsn = serde_json::to_value(&signed).unwrap();
+        signed_jsn["signatures"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "keyid": unknown_key_id, "sig": "abcd" }));
+        let signed: SignedMetadata<Json, TargetsMetadata> =
+            serde_json::from_value(signed_jsn).unwrap();
+
+        assert_eq!(
+            signed.verify(1, authorized_keys.iter().cloned()).unwrap(),
+            targets
+        );
+        assert!(signed.verify(2, authorized_keys).is_err());
+    }
+
     // Refuse to deserialize delegations with no roles
     #[test]
     fn deserialize_json_delegations_no_roles() {
@@ -3271,7 +6527,7 @@ mod test {
         delegations
             .as_object_mut()
             .unwrap()
-            .get_mut("roles".into())
+            .get_mut("roles")
             .unwrap()
             .as_array_mut()
             .unwrap()
@@ -3286,7 +6542,7 @@ mod test {
         let dupe = delegations
             .as_object()
             .unwrap()
-            .get("roles".into())
+            .get("roles")
             .unwrap()
             .as_array()
             .unwrap()[0]
@@ -3294,7 +6550,7 @@ mod test {
         delegations
             .as_object_mut()
             .unwrap()
-            .get_mut("roles".into())
+            .get_mut("roles")
             .unwrap()
             .as_array_mut()
             .unwrap()
@@ -3302,6 +6558,95 @@ mod test {
         assert!(serde_json::from_value::<Delegations>(delegations).is_err());
     }
 
+    // Refuse to deserialize delegations to roles named like top-level roles
+    #[test]
+    fn deserialize_json_delegations_top_level_role_name() {
+        for name in &["root", "snapshot", "targets", "timestamp", "mirrors"] {
+            let mut delegations = make_delegations();
+            delegations["roles"][0]["role"] = json!(name);
+            assert!(serde_json::from_value::<Delegations>(delegations).is_err());
+        }
+
+        // Namespaced role names are fine, even if a component matches a top-level role.
+        let mut delegations = make_delegations();
+        delegations["roles"][0]["role"] = json!("projects/targets");
+        let delegations = serde_json::from_value::<Delegations>(delegations).unwrap();
+        assert_eq!(delegations.roles()[0].role().role(), None);
+    }
+
+    #[test]
+    fn delegations_builder() {
+        let key1 = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let key2 = PrivateKey::from_pkcs8(ED25519_2_PK8, SignatureScheme::Ed25519).unwrap();
+        let builder = || {
+            DelegationsBuilder::new()
+                .key(key1.public().clone())
+                .key(key2.public().clone())
+        };
+
+        let delegations = builder()
+            .role(
+                "foo",
+                false,
+                2,
+                vec![key1.key_id().clone(), key2.key_id().clone()],
+                vec!["/foo//bar", "foo/bar", "baz/"],
+            )
+            .role_with_path_hash_prefixes("bar", true, 1, vec![key2.key_id().clone()], vec!["ab"])
+            .build()
+            .unwrap();
+
+        assert_eq!(delegations.keys().len(), 2);
+        let names = delegations
+            .roles()
+            .iter()
+            .map(|r| r.role().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["foo", "bar"]);
+        assert_eq!(
+            delegations.roles()[0].paths(),
+            &hashset! {
+                VirtualTargetPath::new("foo/bar".into()).unwrap(),
+                VirtualTargetPath::new("baz/".into()).unwrap(),
+            }
+        );
+        assert!(delegations.roles()[1].terminating());
+
+        // Keys must have been added.
+        assert_matches!(
+            DelegationsBuilder::new()
+                .key(key1.public().clone())
+                .role("foo", false, 1, vec![key2.key_id().clone()], vec!["foo"])
+                .build(),
+            Err(Error::IllegalArgument(_))
+        );
+
+        // Thresholds must be satisfiable.
+        assert!(builder()
+            .role("foo", false, 2, vec![key1.key_id().clone()], vec!["foo"])
+            .build()
+            .is_err());
+
+        // Paths must still be valid once normalized.
+        assert!(builder()
+            .role(
+                "foo",
+                false,
+                1,
+                vec![key1.key_id().clone()],
+                vec!["foo/../bar"]
+            )
+            .build()
+            .is_err());
+
+        // Roles must be unique.
+        assert!(builder()
+            .role("foo", false, 1, vec![key1.key_id().clone()], vec!["foo"])
+            .role("foo", false, 1, vec![key2.key_id().clone()], vec!["bar"])
+            .build()
+            .is_err());
+    }
+
     // Refuse to deserialize a delegation with insufficient threshold
     #[test]
     fn deserialize_json_delegation_bad_threshold() {
@@ -3321,7 +6666,7 @@ mod test {
         let dupe = delegation
             .as_object()
             .unwrap()
-            .get("keyids".into())
+            .get("keyids")
             .unwrap()
             .as_array()
             .unwrap()[0]
@@ -3329,7 +6674,7 @@ mod test {
         delegation
             .as_object_mut()
             .unwrap()
-            .get_mut("keyids".into())
+            .get_mut("keyids")
             .unwrap()
             .as_array_mut()
             .unwrap()
@@ -3344,7 +6689,7 @@ mod test {
         let dupe = delegation
             .as_object()
             .unwrap()
-            .get("paths".into())
+            .get("paths")
             .unwrap()
             .as_array()
             .unwrap()[0]
@@ -3352,7 +6697,7 @@ mod test {
         delegation
             .as_object_mut()
             .unwrap()
-            .get_mut("paths".into())
+            .get_mut("paths")
             .unwrap()
             .as_array_mut()
             .unwrap()
@@ -3360,6 +6705,85 @@ mod test {
         assert!(serde_json::from_value::<Delegation>(delegation).is_err());
     }
 
+    #[test]
+    fn serde_delegation_path_hash_prefixes() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let delegation = Delegation::with_path_hash_prefixes(
+            MetadataPath::new("bin").unwrap(),
+            false,
+            1,
+            hashset!(key.key_id().clone()),
+            hashset!("0f".into(), "0a".into()),
+        )
+        .unwrap();
+
+        let jsn = json!({
+            "role": "bin",
+            "terminating": false,
+            "threshold": 1,
+            "keyids": [key.key_id()],
+            "path_hash_prefixes": ["0a", "0f"],
+        });
+        assert_eq!(serde_json::to_value(&delegation).unwrap(), jsn);
+        assert_eq!(
+            serde_json::from_value::<Delegation>(jsn.clone()).unwrap(),
+            delegation
+        );
+
+        // `paths` and `path_hash_prefixes` are mutually exclusive, and one is required.
+        let mut both = jsn.clone();
+        both["paths"] = json!(["foo"]);
+        assert!(serde_json::from_value::<Delegation>(both).is_err());
+
+        let mut neither = jsn.clone();
+        let _ = neither
+            .as_object_mut()
+            .unwrap()
+            .remove("path_hash_prefixes");
+        assert!(serde_json::from_value::<Delegation>(neither).is_err());
+
+        let mut dupes = jsn.clone();
+        dupes["path_hash_prefixes"] = json!(["0a", "0a"]);
+        assert!(serde_json::from_value::<Delegation>(dupes).is_err());
+
+        let mut not_hex = jsn;
+        not_hex["path_hash_prefixes"] = json!(["0A"]);
+        assert!(serde_json::from_value::<Delegation>(not_hex).is_err());
+    }
+
+    #[test]
+    fn delegation_matches_path_hash_prefixes() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+        let path = VirtualTargetPath::new("foo/bar".into()).unwrap();
+        let hash = crypto::calculate_hash(path.value().as_bytes(), HashAlgorithm::Sha256).to_hex();
+        let delegation = |prefixes: HashSet<String>| {
+            Delegation::with_path_hash_prefixes(
+                MetadataPath::new("bin").unwrap(),
+                false,
+                1,
+                hashset!(key.key_id().clone()),
+                prefixes,
+            )
+            .unwrap()
+        };
+
+        let other = if hash.starts_with('0') { "1" } else { "0" };
+        assert!(delegation(hashset!(hash[..1].into())).matches(&path));
+        assert!(delegation(hashset!(hash[..3].into(), other.into())).matches(&path));
+        assert!(delegation(hashset!(hash.clone())).matches(&path));
+        assert!(!delegation(hashset!(other.into())).matches(&path));
+        assert_matches!(
+            Delegation::with_path_hash_prefixes(
+                MetadataPath::new("bin").unwrap(),
+                false,
+                1,
+                hashset!(key.key_id().clone()),
+                hashset!(),
+            ),
+            Err(Error::IllegalArgument(_))
+        );
+    }
+
     // Refuse to deserialize a Delegations struct with duplicate keys
     #[test]
     fn deserialize_json_delegations_duplicate_keys() {
@@ -0,0 +1,387 @@
+//! The graph of delegations between targets roles. See [`DelegationGraph`].
+
+use serde_derive::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write;
+
+use crate::crypto::KeyId;
+use crate::metadata::{MetadataPath, Role, TargetsMetadata, VirtualTargetPath};
+
+/// The delegations between targets roles, starting from the top-level targets role, for auditing
+/// and visualizing delegation trees.
+///
+/// The graph serializes to a structured value, and can be rendered in the Graphviz DOT language
+/// with [`DelegationGraph::to_dot`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DelegationGraph {
+    roles: Vec<DelegationGraphRole>,
+    delegations: Vec<DelegationGraphEdge>,
+}
+
+/// A targets role in a [`DelegationGraph`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DelegationGraphRole {
+    name: MetadataPath,
+    loaded: bool,
+}
+
+impl DelegationGraphRole {
+    /// The name of the role.
+    pub fn name(&self) -> &MetadataPath {
+        &self.name
+    }
+
+    /// Whether the metadata of this role was available, and so whether its own delegations are
+    /// part of the graph.
+    pub fn loaded(&self) -> bool {
+        self.loaded
+    }
+}
+
+/// A delegation from one targets role to another in a [`DelegationGraph`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DelegationGraphEdge {
+    delegator: MetadataPath,
+    role: MetadataPath,
+    terminating: bool,
+    threshold: u32,
+    key_ids: Vec<KeyId>,
+    paths: Vec<VirtualTargetPath>,
+    path_hash_prefixes: Vec<String>,
+}
+
+impl DelegationGraphEdge {
+    /// The role that made the delegation.
+    pub fn delegator(&self) -> &MetadataPath {
+        &self.delegator
+    }
+
+    /// The delegated role.
+    pub fn role(&self) -> &MetadataPath {
+        &self.role
+    }
+
+    /// Whether the delegation is terminating.
+    pub fn terminating(&self) -> bool {
+        self.terminating
+    }
+
+    /// The number of signatures the delegated role requires.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// The keys trusted for the delegated role, in sorted order.
+    pub fn key_ids(&self) -> &[KeyId] {
+        &self.key_ids
+    }
+
+    /// The target path patterns the delegated role is trusted for, in sorted order.
+    pub fn paths(&self) -> &[VirtualTargetPath] {
+        &self.paths
+    }
+
+    /// The target path hash prefixes the delegated role is trusted for, in sorted order.
+    pub fn path_hash_prefixes(&self) -> &[String] {
+        &self.path_hash_prefixes
+    }
+}
+
+impl DelegationGraph {
+    /// Walk the delegations starting from the top-level `targets` metadata. `lookup` returns the
+    /// metadata of a delegated role, or `None` if it is not available, in which case the
+    /// delegations of that role are not part of the graph.
+    ///
+    /// Roles are listed in the order they are first reached, and each role's delegations are
+    /// listed in priority order. A role delegated to more than once is only walked once.
+    ///
+    /// ```
+    /// # use tuf::crypto::{PrivateKey, SignatureScheme};
+    /// # use tuf::metadata::{
+    /// #     Delegation, DelegationGraph, Delegations, MetadataPath, TargetsMetadataBuilder,
+    /// #     VirtualTargetPath,
+    /// # };
+    /// # let key: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+    /// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+    /// let delegations = Delegations::new(
+    ///     vec![(key.key_id().clone(), key.public().clone())].into_iter().collect(),
+    ///     vec![Delegation::new(
+    ///         MetadataPath::new("projects/foo").unwrap(),
+    ///         true,
+    ///         1,
+    ///         vec![key.key_id().clone()].into_iter().collect(),
+    ///         vec![VirtualTargetPath::new("foo/".into()).unwrap()].into_iter().collect(),
+    ///     )
+    ///     .unwrap()],
+    /// )
+    /// .unwrap();
+    /// let targets = TargetsMetadataBuilder::new()
+    ///     .delegations(delegations)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let graph = DelegationGraph::new(&targets, |_| None);
+    /// assert_eq!(graph.roles().len(), 2);
+    /// assert_eq!(
+    ///     graph.to_dot(),
+    ///     "digraph delegations {\n\
+    ///     \x20   \"targets\";\n\
+    ///     \x20   \"projects/foo\" [style=dashed];\n\
+    ///     \x20   \"targets\" -> \"projects/foo\" [label=\"threshold 1, terminating\\npaths: foo/\"];\n\
+    ///     }\n"
+    /// );
+    /// ```
+    pub fn new<'a, F>(targets: &'a TargetsMetadata, mut lookup: F) -> Self
+    where
+        F: FnMut(&MetadataPath) -> Option<&'a TargetsMetadata>,
+    {
+        let top = MetadataPath::from_role(&Role::Targets);
+        let mut roles = vec![DelegationGraphRole {
+            name: top.clone(),
+            loaded: true,
+        }];
+        let mut delegations = Vec::new();
+        let mut seen = HashSet::new();
+        let _ = seen.insert(top.clone());
+        let mut queue = VecDeque::new();
+        queue.push_back((top, targets));
+
+        while let Some((delegator, metadata)) = queue.pop_front() {
+            let metadata_delegations = match metadata.delegations() {
+                Some(d) => d,
+                None => continue,
+            };
+
+            for delegation in metadata_delegations.roles() {
+                let mut key_ids = delegation.key_ids().iter().cloned().collect::<Vec<_>>();
+                key_ids.sort();
+                let mut paths = delegation.paths().iter().cloned().collect::<Vec<_>>();
+                paths.sort();
+                let mut path_hash_prefixes = delegation
+                    .path_hash_prefixes()
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                path_hash_prefixes.sort();
+
+                delegations.push(DelegationGraphEdge {
+                    delegator: delegator.clone(),
+                    role: delegation.role().clone(),
+                    terminating: delegation.terminating(),
+                    threshold: delegation.threshold(),
+                    key_ids,
+                    paths,
+                    path_hash_prefixes,
+                });
+
+                if seen.insert(delegation.role().clone()) {
+                    let child = lookup(delegation.role());
+                    roles.push(DelegationGraphRole {
+                        name: delegation.role().clone(),
+                        loaded: child.is_some(),
+                    });
+                    if let Some(child) = child {
+                        queue.push_back((delegation.role().clone(), child));
+                    }
+                }
+            }
+        }
+
+        DelegationGraph { roles, delegations }
+    }
+
+    /// The roles in the graph, starting with the top-level targets role.
+    pub fn roles(&self) -> &[DelegationGraphRole] {
+        &self.roles
+    }
+
+    /// The delegations in the graph.
+    pub fn delegations(&self) -> &[DelegationGraphEdge] {
+        &self.delegations
+    }
+
+    /// Render the graph in the Graphviz DOT language. Roles whose metadata was not available are
+    /// drawn dashed, and each delegation is labelled with its threshold, whether it is
+    /// terminating, and the paths or path hash prefixes it covers.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph delegations {\n");
+
+        for role in &self.roles {
+            let style = if role.loaded { "" } else { " [style=dashed]" };
+            let _ = writeln!(dot, "    {}{};", quote(role.name.as_str()), style);
+        }
+
+        for delegation in &self.delegations {
+            let mut label = format!("threshold {}", delegation.threshold);
+            if delegation.terminating {
+                label.push_str(", terminating");
+            }
+            if !delegation.paths.is_empty() {
+                let paths = delegation
+                    .paths
+                    .iter()
+                    .map(|p| p.value())
+                    .collect::<Vec<_>>();
+                let _ = write!(label, "\npaths: {}", paths.join(", "));
+            }
+            if !delegation.path_hash_prefixes.is_empty() {
+                let _ = write!(
+                    label,
+                    "\npath hash prefixes: {}",
+                    delegation.path_hash_prefixes.join(", ")
+                );
+            }
+
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [label={}];",
+                quote(delegation.delegator.as_str()),
+                quote(delegation.role.as_str()),
+                quote(&label)
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Quote `s` as a DOT string.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::metadata::{Delegation, Delegations, TargetsMetadataBuilder};
+    use maplit::hashmap;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::iter::once;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+
+    fn path(p: &str) -> MetadataPath {
+        MetadataPath::new(p).unwrap()
+    }
+
+    fn delegating_to(key: &PrivateKey, roles: &[(&str, bool)]) -> TargetsMetadata {
+        let delegations = Delegations::new(
+            hashmap! { key.key_id().clone() => key.public().clone() },
+            roles
+                .iter()
+                .map(|(name, terminating)| {
+                    Delegation::new(
+                        path(name),
+                        *terminating,
+                        1,
+                        once(key.key_id().clone()).collect(),
+                        once(VirtualTargetPath::new(format!("{}/", name)).unwrap()).collect(),
+                    )
+                    .unwrap()
+                })
+                .collect(),
+        )
+        .unwrap();
+        TargetsMetadataBuilder::new()
+            .delegations(delegations)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn walks_loaded_roles_once() {
+        let key = PrivateKey::from_pkcs8(ED25519_1_PK8, SignatureScheme::Ed25519).unwrap();
+
+        // targets -> a, b; a -> c; b -> c, a; c -> a. d is never loaded.
+        let targets = delegating_to(&key, &[("a", false), ("b", true)]);
+        let metadata = hashmap! {
+            path("a") => delegating_to(&key, &[("c", false)]),
+            path("b") => delegating_to(&key, &[("c", false), ("a", false)]),
+            path("c") => delegating_to(&key, &[("a", false), ("d", false)]),
+        };
+
+        let mut looked_up = HashMap::new();
+        let graph = DelegationGraph::new(&targets, |role| {
+            *looked_up.entry(role.clone()).or_insert(0) += 1;
+            metadata.get(role)
+        });
+
+        assert_eq!(
+            graph
+                .roles()
+                .iter()
+                .map(|r| (r.name().as_str(), r.loaded()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("targets", true),
+                ("a", true),
+                ("b", true),
+                ("c", true),
+                ("d", false)
+            ]
+        );
+        assert!(looked_up.values().all(|count| *count == 1));
+
+        assert_eq!(
+            graph
+                .delegations()
+                .iter()
+                .map(|d| (d.delegator().as_str(), d.role().as_str(), d.terminating()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("targets", "a", false),
+                ("targets", "b", true),
+                ("a", "c", false),
+                ("b", "c", false),
+                ("b", "a", false),
+                ("c", "a", false),
+                ("c", "d", false),
+            ]
+        );
+
+        let edge = &graph.delegations()[1];
+        assert_eq!(edge.threshold(), 1);
+        assert_eq!(edge.key_ids(), &[key.key_id().clone()]);
+        assert_eq!(
+            edge.paths(),
+            &[VirtualTargetPath::new("b/".into()).unwrap()]
+        );
+        assert!(edge.path_hash_prefixes().is_empty());
+
+        assert_eq!(
+            serde_json::to_value(&graph).unwrap()["delegations"][1],
+            json!({
+                "delegator": "targets",
+                "role": "b",
+                "terminating": true,
+                "threshold": 1,
+                "key_ids": [key.key_id()],
+                "paths": ["b/"],
+                "path_hash_prefixes": [],
+            })
+        );
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("    \"d\" [style=dashed];\n"));
+        assert!(dot.contains("    \"c\" -> \"a\" [label=\"threshold 1\\npaths: a/\"];\n"));
+    }
+
+    #[test]
+    fn quotes_dot_strings() {
+        assert_eq!(quote("a\"b\\c\nd"), "\"a\\\"b\\\\c\\nd\"");
+    }
+}
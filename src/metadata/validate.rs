@@ -0,0 +1,459 @@
+//! Structural checks on a single metadata file. See [`validate`].
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::interchange::DataInterchange;
+use crate::metadata::{
+    MetadataPath, RawSignedMetadata, Role, RootMetadata, SnapshotMetadata, TargetsMetadata,
+    TimestampMetadata, VirtualTargetPath,
+};
+use crate::Result;
+
+/// A problem found by [`validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Finding {
+    /// The metadata could not be parsed, or is not the kind of metadata expected for its role.
+    Malformed(String),
+    /// The metadata has expired.
+    Expired(DateTime<Utc>),
+    /// A target path or role name is not a valid path.
+    InvalidPath {
+        /// The offending path.
+        path: String,
+        /// Why the path is invalid.
+        reason: String,
+    },
+    /// A key ID is listed more than once.
+    DuplicateKeyId {
+        /// Where the key ID is listed, e.g. `signatures` or `root role`.
+        location: String,
+        /// The duplicated key ID.
+        key_id: String,
+    },
+    /// A role requires more signatures than it has keys, so it can never be satisfied.
+    ThresholdExceedsKeys {
+        /// The name of the role.
+        role: String,
+        /// The threshold of the role.
+        threshold: u64,
+        /// The number of distinct keys of the role.
+        keys: usize,
+    },
+    /// A targets role delegates to itself.
+    SelfDelegation(String),
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Finding::Malformed(reason) => write!(f, "malformed metadata: {}", reason),
+            Finding::Expired(expires) => write!(f, "metadata expired at {}", expires.to_rfc3339()),
+            Finding::InvalidPath { path, reason } => {
+                write!(f, "invalid path {:?}: {}", path, reason)
+            }
+            Finding::DuplicateKeyId { location, key_id } => {
+                write!(
+                    f,
+                    "key ID {} is listed more than once in {}",
+                    key_id, location
+                )
+            }
+            Finding::ThresholdExceedsKeys {
+                role,
+                threshold,
+                keys,
+            } => write!(
+                f,
+                "role {} has a threshold of {} but only {} keys",
+                role, threshold, keys
+            ),
+            Finding::SelfDelegation(role) => write!(f, "role {} delegates to itself", role),
+        }
+    }
+}
+
+/// Check the signed metadata in `bytes` for the role `role` for structural problems, without
+/// verifying any signatures or needing the rest of the repository's metadata.
+///
+/// Unlike parsing the metadata, this reports every problem it finds rather than stopping at the
+/// first one. An empty result means no problems were found.
+///
+/// ```
+/// # use chrono::prelude::*;
+/// # use tuf::crypto::{PrivateKey, SignatureScheme};
+/// # use tuf::interchange::Json;
+/// # use tuf::metadata::{self, Finding, MetadataPath, Role, TargetsMetadataBuilder};
+/// # let key: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+/// # let key = PrivateKey::from_pkcs8(&key, SignatureScheme::Ed25519).unwrap();
+/// let targets = TargetsMetadataBuilder::new()
+///     .expires(Utc.with_ymd_and_hms(2017, 1, 1, 0, 0, 0).unwrap())
+///     .allow_expired(true)
+///     .signed::<Json>(&key)
+///     .unwrap()
+///     .to_raw()
+///     .unwrap();
+///
+/// let findings =
+///     metadata::validate::<Json>(&MetadataPath::from_role(&Role::Targets), targets.as_bytes());
+/// assert_eq!(
+///     findings,
+///     vec![Finding::Expired(Utc.with_ymd_and_hms(2017, 1, 1, 0, 0, 0).unwrap())]
+/// );
+/// ```
+pub fn validate<D: DataInterchange>(role: &MetadataPath, bytes: &[u8]) -> Vec<Finding> {
+    let value: Value = match D::from_slice(bytes) {
+        Ok(value) => value,
+        Err(e) => return vec![Finding::Malformed(e.to_string())],
+    };
+
+    let mut findings = Vec::new();
+
+    match value.get("signatures").and_then(Value::as_array) {
+        Some(signatures) => check_key_ids(
+            &mut findings,
+            "signatures",
+            signatures.iter().map(|s| s.get("keyid")),
+        ),
+        None => findings.push(Finding::Malformed("missing signatures".into())),
+    }
+
+    let signed = match value.get("signed") {
+        Some(signed) if signed.is_object() => signed,
+        _ => {
+            findings.push(Finding::Malformed("missing signed metadata".into()));
+            return findings;
+        }
+    };
+
+    let expected = role.role().unwrap_or(Role::Targets);
+    let typ = match signed.get("_type").and_then(Value::as_str) {
+        Some(typ) if typ == expected.name() => expected,
+        typ => {
+            findings.push(Finding::Malformed(format!(
+                "expected {} metadata for role {}, found {:?}",
+                expected, role, typ
+            )));
+            return findings;
+        }
+    };
+
+    match typ {
+        Role::Root => check_root(&mut findings, signed),
+        Role::Targets => check_targets(&mut findings, role, signed),
+        _ => (),
+    }
+
+    // The checks above explain most parse failures more precisely, so only report the parse error
+    // itself if nothing else was found.
+    let parsed = match typ {
+        Role::Root => parses::<D, RootMetadata>(bytes),
+        Role::Snapshot => parses::<D, SnapshotMetadata>(bytes),
+        Role::Targets => parses::<D, TargetsMetadata>(bytes),
+        Role::Timestamp => parses::<D, TimestampMetadata>(bytes),
+        _ => Ok(()),
+    };
+    if let Err(e) = parsed {
+        if findings.is_empty() {
+            findings.push(Finding::Malformed(e.to_string()));
+        }
+    }
+
+    match signed.get("expires").and_then(Value::as_str) {
+        Some(expires) => match DateTime::parse_from_rfc3339(expires) {
+            Ok(expires) if expires < Utc::now() => {
+                findings.push(Finding::Expired(expires.with_timezone(&Utc)))
+            }
+            Ok(_) => (),
+            Err(e) => findings.push(Finding::Malformed(format!(
+                "invalid expiration {:?}: {}",
+                expires, e
+            ))),
+        },
+        None => findings.push(Finding::Malformed("missing expiration".into())),
+    }
+
+    findings
+}
+
+fn parses<D, M>(bytes: &[u8]) -> Result<()>
+where
+    D: DataInterchange,
+    M: crate::metadata::Metadata,
+{
+    RawSignedMetadata::<D, M>::new(bytes.to_vec())
+        .parse()?
+        .assume_valid()
+        .map(|_| ())
+}
+
+fn check_root(findings: &mut Vec<Finding>, signed: &Value) {
+    if let Some(roles) = signed.get("roles").and_then(Value::as_object) {
+        for (name, definition) in roles {
+            check_role(findings, name, &format!("{} role", name), definition);
+        }
+    }
+}
+
+fn check_targets(findings: &mut Vec<Finding>, role: &MetadataPath, signed: &Value) {
+    if let Some(targets) = signed.get("targets").and_then(Value::as_object) {
+        for path in targets.keys() {
+            check_target_path(findings, path);
+        }
+    }
+
+    let delegations = signed
+        .get("delegations")
+        .and_then(|d| d.get("roles"))
+        .and_then(Value::as_array);
+    for delegation in delegations.into_iter().flatten() {
+        let name = match delegation.get("role").and_then(Value::as_str) {
+            Some(name) => name,
+            None => {
+                findings.push(Finding::Malformed("delegation without a role name".into()));
+                continue;
+            }
+        };
+
+        if let Err(e) = MetadataPath::new(name) {
+            findings.push(Finding::InvalidPath {
+                path: name.into(),
+                reason: e.to_string(),
+            });
+        }
+        if name == role.as_str() {
+            findings.push(Finding::SelfDelegation(name.into()));
+        }
+
+        check_role(findings, name, &format!("delegation {}", name), delegation);
+
+        let paths = delegation.get("paths").and_then(Value::as_array);
+        for path in paths.into_iter().flatten() {
+            match path.as_str() {
+                Some(path) => check_target_path(findings, path),
+                None => findings.push(Finding::Malformed(format!(
+                    "delegation {} has a non-string path",
+                    name
+                ))),
+            }
+        }
+    }
+}
+
+/// Check the key IDs and threshold of a root role definition or a delegation.
+fn check_role(findings: &mut Vec<Finding>, name: &str, location: &str, definition: &Value) {
+    let key_ids = definition
+        .get("keyids")
+        .and_then(Value::as_array)
+        .map(|ids| ids.as_slice())
+        .unwrap_or(&[]);
+    check_key_ids(findings, location, key_ids.iter().map(Some));
+
+    let keys = key_ids.iter().collect::<HashSet<_>>().len();
+    if let Some(threshold) = definition.get("threshold").and_then(Value::as_u64) {
+        if threshold as usize > keys {
+            findings.push(Finding::ThresholdExceedsKeys {
+                role: name.into(),
+                threshold,
+                keys,
+            });
+        }
+    }
+}
+
+fn check_key_ids<'a, I>(findings: &mut Vec<Finding>, location: &str, key_ids: I)
+where
+    I: Iterator<Item = Option<&'a Value>>,
+{
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+    for key_id in key_ids.flatten().filter_map(Value::as_str) {
+        if !seen.insert(key_id) && reported.insert(key_id) {
+            findings.push(Finding::DuplicateKeyId {
+                location: location.into(),
+                key_id: key_id.into(),
+            });
+        }
+    }
+}
+
+fn check_target_path(findings: &mut Vec<Finding>, path: &str) {
+    if let Err(e) = VirtualTargetPath::new(path.into()) {
+        findings.push(Finding::InvalidPath {
+            path: path.into(),
+            reason: e.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::interchange::Json;
+    use crate::metadata::{
+        Delegation, Delegations, RootMetadataBuilder, SnapshotMetadataBuilder,
+        TargetsMetadataBuilder,
+    };
+    use chrono::TimeZone;
+    use maplit::hashmap;
+    use matches::assert_matches;
+    use serde_json::json;
+    use std::iter::once;
+
+    const ED25519_1_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-1.pk8.der");
+    const ED25519_2_PK8: &[u8] = include_bytes!("../../tests/ed25519/ed25519-2.pk8.der");
+
+    fn key(pk8: &[u8]) -> PrivateKey {
+        PrivateKey::from_pkcs8(pk8, SignatureScheme::Ed25519).unwrap()
+    }
+
+    #[test]
+    fn valid_metadata_has_no_findings() {
+        let key = key(ED25519_1_PK8);
+        let root = RootMetadataBuilder::new()
+            .root_key(key.public().clone())
+            .snapshot_key(key.public().clone())
+            .targets_key(key.public().clone())
+            .timestamp_key(key.public().clone())
+            .signed::<Json>(&key)
+            .unwrap();
+        let snapshot = SnapshotMetadataBuilder::new().signed::<Json>(&key).unwrap();
+
+        assert_eq!(
+            validate::<Json>(
+                &MetadataPath::from_role(&Role::Root),
+                root.to_raw().unwrap().as_bytes()
+            ),
+            vec![]
+        );
+        assert_eq!(
+            validate::<Json>(
+                &MetadataPath::from_role(&Role::Snapshot),
+                snapshot.to_raw().unwrap().as_bytes()
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn reports_every_root_finding() {
+        let key_1 = key(ED25519_1_PK8);
+        let key_2 = key(ED25519_2_PK8);
+        let root = RootMetadataBuilder::new()
+            .root_key(key_1.public().clone())
+            .snapshot_key(key_1.public().clone())
+            .targets_key(key_1.public().clone())
+            .timestamp_key(key_1.public().clone())
+            .signed::<Json>(&key_1)
+            .unwrap();
+
+        let key_id_1 = json!(key_1.key_id());
+        let key_id_2 = json!(key_2.key_id());
+        let mut jsn = serde_json::to_value(&root).unwrap();
+        jsn["signatures"] = json!([jsn["signatures"][0], jsn["signatures"][0]]);
+        jsn["signed"]["expires"] = json!("2017-01-01T00:00:00Z");
+        jsn["signed"]["roles"]["root"]["keyids"] = json!([key_id_1, key_id_1]);
+        jsn["signed"]["roles"]["targets"]["keyids"] = json!([key_id_1, key_id_2]);
+        jsn["signed"]["roles"]["targets"]["threshold"] = json!(3);
+        let bytes = serde_json::to_vec(&jsn).unwrap();
+
+        let key_id = key_id_1.as_str().unwrap().to_string();
+        let findings = validate::<Json>(&MetadataPath::from_role(&Role::Root), &bytes);
+        assert_eq!(
+            findings,
+            vec![
+                Finding::DuplicateKeyId {
+                    location: "signatures".into(),
+                    key_id: key_id.clone(),
+                },
+                Finding::DuplicateKeyId {
+                    location: "root role".into(),
+                    key_id,
+                },
+                Finding::ThresholdExceedsKeys {
+                    role: "targets".into(),
+                    threshold: 3,
+                    keys: 2,
+                },
+                Finding::Expired(Utc.with_ymd_and_hms(2017, 1, 1, 0, 0, 0).unwrap()),
+            ]
+        );
+
+        // Metadata for a different role is reported as malformed.
+        assert_matches!(
+            &validate::<Json>(&MetadataPath::from_role(&Role::Timestamp), &bytes)[..],
+            [Finding::DuplicateKeyId { .. }, Finding::Malformed(_)]
+        );
+    }
+
+    #[test]
+    fn reports_every_targets_finding() {
+        let key_1 = key(ED25519_1_PK8);
+        let key_2 = key(ED25519_2_PK8);
+        let delegations = Delegations::new(
+            hashmap! { key_2.key_id().clone() => key_2.public().clone() },
+            vec![Delegation::new(
+                MetadataPath::new("other").unwrap(),
+                false,
+                1,
+                once(key_2.key_id().clone()).collect(),
+                once(VirtualTargetPath::new("foo/".into()).unwrap()).collect(),
+            )
+            .unwrap()],
+        )
+        .unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .insert_target_from_reader(
+                VirtualTargetPath::new("foo/bar".into()).unwrap(),
+                &b"bar"[..],
+                &[crate::crypto::HashAlgorithm::Sha256],
+            )
+            .unwrap()
+            .delegations(delegations)
+            .signed::<Json>(&key_1)
+            .unwrap();
+
+        let mut jsn = serde_json::to_value(&targets).unwrap();
+        let description = jsn["signed"]["targets"]["foo/bar"].clone();
+        jsn["signed"]["targets"]["../bar"] = description;
+        let delegation = &mut jsn["signed"]["delegations"]["roles"][0];
+        delegation["role"] = json!("projects/foo");
+        delegation["paths"] = json!(["foo/", "/abs"]);
+        let bytes = serde_json::to_vec(&jsn).unwrap();
+
+        let role = MetadataPath::new("projects/foo").unwrap();
+        let findings = validate::<Json>(&role, &bytes);
+        assert_eq!(findings.len(), 3, "{:?}", findings);
+        assert_matches!(
+            &findings[0],
+            Finding::InvalidPath { path, .. } if path == "../bar"
+        );
+        assert_eq!(findings[1], Finding::SelfDelegation("projects/foo".into()));
+        assert_matches!(
+            &findings[2],
+            Finding::InvalidPath { path, .. } if path == "/abs"
+        );
+
+        // Findings render as readable messages.
+        assert_eq!(
+            findings[1].to_string(),
+            "role projects/foo delegates to itself"
+        );
+    }
+
+    #[test]
+    fn unparseable_bytes_are_malformed() {
+        assert_matches!(
+            &validate::<Json>(&MetadataPath::from_role(&Role::Root), b"{")[..],
+            [Finding::Malformed(_)]
+        );
+        assert_matches!(
+            &validate::<Json>(&MetadataPath::from_role(&Role::Root), b"{}")[..],
+            [Finding::Malformed(_), Finding::Malformed(_)]
+        );
+    }
+}
@@ -1,29 +1,41 @@
 //! Interfaces for interacting with different types of TUF repositories.
 
-use crate::crypto::{self, HashAlgorithm, HashValue};
-use crate::interchange::DataInterchange;
+use crate::crypto::{self, HashAlgorithm, HashValue, SafeReader};
+use crate::interchange::{DataInterchange, JsonLimits};
 use crate::metadata::{
-    Metadata, MetadataPath, MetadataVersion, RawSignedMetadata, SignedMetadata, TargetDescription,
-    TargetPath,
+    HashedBins, Metadata, MetadataPath, MetadataVersion, RawSignedMetadata, Role, SignedMetadata,
+    TargetDescription, TargetPath,
 };
 use crate::util::SafeAsyncRead;
 use crate::{Error, Result};
 
 use futures_io::AsyncRead;
-use futures_util::future::BoxFuture;
-use futures_util::io::AsyncReadExt;
+use futures_util::future::{BoxFuture, FutureExt, TryFutureExt};
+use futures_util::io::{copy, empty, sink, AsyncReadExt};
+use std::io;
 use std::marker::PhantomData;
 
 mod file_system;
 pub use self::file_system::{FileSystemRepository, FileSystemRepositoryBuilder};
 
 mod http;
-pub use self::http::{HttpRepository, HttpRepositoryBuilder};
+#[cfg(unix)]
+pub use self::http::UnixConnector;
+pub use self::http::{HttpAuth, HttpRepository, HttpRepositoryBuilder, RequestHook, TokenProvider};
 
 mod ephemeral;
 pub use self::ephemeral::EphemeralRepository;
 
+mod mirror;
+pub use self::mirror::{Mirror, MirrorRepository, MirrorRepositoryBuilder};
+
+mod rate_limit;
+pub use self::rate_limit::RateLimiter;
+
 /// A readable TUF repository.
+///
+/// Operations return futures rather than blocking, so implementations should avoid blocking the
+/// thread that polls them.
 pub trait RepositoryProvider<D>
 where
     D: DataInterchange + Sync,
@@ -36,6 +48,9 @@ where
     /// invalid metadata and fail the fetch operation before streaming all of the bytes of the
     /// metadata.
     ///
+    /// When provided, `max_length` comes from trusted metadata or the client configuration rather
+    /// than from the server, so implementations may rely on it to cap reads or to size buffers.
+    ///
     /// [extension]: crate::interchange::DataInterchange::extension
     fn fetch_metadata<'a>(
         &'a self,
@@ -51,11 +66,46 @@ where
     /// [`Repository`] will verify these constraints itself. However, it may be more efficient for
     /// an implementation to detect invalid targets and fail the fetch operation before streaming
     /// all of the bytes.
+    ///
+    /// The length in `target_description` comes from trusted metadata, so implementations may rely
+    /// on it to cap reads or to size buffers.
     fn fetch_target<'a>(
         &'a self,
         target_path: &'a TargetPath,
         target_description: &'a TargetDescription,
     ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>>;
+
+    /// Fetch the given target without its first `offset` bytes, so that an interrupted download
+    /// can be resumed.
+    ///
+    /// The same caveats as for [`RepositoryProvider::fetch_target`] apply. The default
+    /// implementation fetches the whole target and discards the first `offset` bytes, so
+    /// implementations that can fetch part of a target directly should override it.
+    fn fetch_target_from<'a>(
+        &'a self,
+        target_path: &'a TargetPath,
+        target_description: &'a TargetDescription,
+        offset: u64,
+    ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+        self.fetch_target(target_path, target_description)
+            .and_then(move |read| skip_bytes(read, offset))
+            .boxed()
+    }
+}
+
+/// Discard the first `count` bytes of `read`.
+pub(crate) async fn skip_bytes(
+    mut read: Box<dyn AsyncRead + Send + Unpin>,
+    count: u64,
+) -> Result<Box<dyn AsyncRead + Send + Unpin>> {
+    let skipped = copy((&mut read).take(count), &mut sink()).await?;
+    if skipped != count {
+        return Err(Error::VerificationFailure(format!(
+            "Target ended after {} bytes, before the {} bytes to skip",
+            skipped, count
+        )));
+    }
+    Ok(read)
 }
 
 /// A writable TUF repository. Most implementors of this trait should also implement
@@ -110,6 +160,15 @@ where
     ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
         (**self).fetch_target(target_path, target_description)
     }
+
+    fn fetch_target_from<'a>(
+        &'a self,
+        target_path: &'a TargetPath,
+        target_description: &'a TargetDescription,
+        offset: u64,
+    ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+        (**self).fetch_target_from(target_path, target_description, offset)
+    }
 }
 
 impl<T, D> RepositoryStorage<D> for &T
@@ -147,6 +206,7 @@ where
 #[derive(Debug, Clone)]
 pub(crate) struct Repository<R, D> {
     repository: R,
+    json_limits: JsonLimits,
     _interchange: PhantomData<D>,
 }
 
@@ -155,10 +215,17 @@ impl<R, D> Repository<R, D> {
     pub(crate) fn new(repository: R) -> Self {
         Self {
             repository,
+            json_limits: JsonLimits::default(),
             _interchange: PhantomData,
         }
     }
 
+    /// Reject fetched metadata that exceeds `json_limits` when parsing it.
+    pub(crate) fn with_json_limits(mut self, json_limits: JsonLimits) -> Self {
+        self.json_limits = json_limits;
+        self
+    }
+
     /// Perform a sanity check that `M`, `Role`, and `MetadataPath` all describe the same entity.
     fn check<M>(meta_path: &MetadataPath) -> Result<()>
     where
@@ -202,16 +269,16 @@ where
         let raw_signed_meta = self
             .fetch_raw_metadata(meta_path, version, max_length, hash_data)
             .await?;
-        let signed_meta = raw_signed_meta.parse()?;
+        let signed_meta = raw_signed_meta.parse_with_limits(&self.json_limits)?;
 
         Ok((raw_signed_meta, signed_meta))
     }
 
     /// Fetch metadata identified by `meta_path`, `version`, and [`D::extension()`][extension].
     ///
-    /// If `max_length` is provided, this method will return an error if the metadata exceeds
-    /// `max_length` bytes. If `hash_data` is provided, this method will return and error if the
-    /// hashed bytes of the metadata do not match `hash_data`.
+    /// If `max_length` is provided, this method will return `Error::MetadataLengthExceeded` as soon
+    /// as more than `max_length` bytes are read. If `hash_data` is provided, this method will
+    /// return and error if the hashed bytes of the metadata do not match `hash_data`.
     ///
     /// [extension]: crate::interchange::DataInterchange::extension
     async fn fetch_raw_metadata<'a, M>(
@@ -235,7 +302,16 @@ where
             .check_length_and_hash(max_length.unwrap_or(::std::usize::MAX) as u64, hash_data)?;
 
         let mut buf = Vec::new();
-        reader.read_to_end(&mut buf).await?;
+        match (reader.read_to_end(&mut buf).await, max_length) {
+            (Ok(_), _) => {}
+            (Err(ref err), Some(max_length)) if err.kind() == io::ErrorKind::FileTooLarge => {
+                return Err(Error::MetadataLengthExceeded {
+                    path: meta_path.clone(),
+                    max_length,
+                });
+            }
+            (Err(err), _) => return Err(err.into()),
+        }
 
         let raw_signed_meta = RawSignedMetadata::new(buf);
 
@@ -243,9 +319,9 @@ where
     }
 
     /// Fetch the target identified by `target_path` through the returned `AsyncRead`, verifying
-    /// that the target matches the preferred hash specified in `target_description` and that it is
-    /// the expected length. Such verification errors will be provided by a read failure on the
-    /// provided `AsyncRead`.
+    /// that the target matches every supported hash specified in `target_description` and that it
+    /// is the expected length. Such verification errors will be provided by a read failure on the
+    /// provided `AsyncRead`, which fails as soon as the expected length is exceeded.
     ///
     /// It is **critical** that none of the bytes from the returned `AsyncRead` are used until it
     /// has been fully consumed as the data is untrusted.
@@ -253,13 +329,46 @@ where
         &'a self,
         target_path: &'a TargetPath,
         target_description: &'a TargetDescription,
-    ) -> Result<impl AsyncRead + Send + Unpin> {
-        let (hash_alg, value) = crypto::hash_preference(target_description.hashes())?;
+    ) -> Result<SafeReader<Box<dyn AsyncRead + Send + Unpin>>> {
+        self.fetch_target_after(target_path, target_description, empty(), 0)
+            .await
+    }
 
-        self.repository
-            .fetch_target(target_path, target_description)
-            .await?
-            .check_length_and_hash(target_description.length(), Some((hash_alg, value.clone())))
+    /// Like [`Repository::fetch_target`], but resumes a download of which the first
+    /// `prefix_length` bytes, read from `prefix`, were already fetched. Only the rest of the
+    /// target is fetched from the repository, but the returned `AsyncRead` yields and verifies the
+    /// whole target, starting with the bytes from `prefix`.
+    pub(crate) async fn fetch_target_after<'a, P>(
+        &'a self,
+        target_path: &'a TargetPath,
+        target_description: &'a TargetDescription,
+        prefix: P,
+        prefix_length: u64,
+    ) -> Result<SafeReader<Box<dyn AsyncRead + Send + Unpin>>>
+    where
+        P: AsyncRead + Send + Unpin + 'static,
+    {
+        // Make sure at least one of the hashes uses an algorithm we prefer.
+        let _ = crypto::hash_preference(target_description.hashes())?;
+
+        let rest: Box<dyn AsyncRead + Send + Unpin> = if prefix_length == 0 {
+            self.repository
+                .fetch_target(target_path, target_description)
+                .await?
+        } else if prefix_length < target_description.length() {
+            self.repository
+                .fetch_target_from(target_path, target_description, prefix_length)
+                .await?
+        } else {
+            // Everything was already fetched, so it only needs to be verified.
+            Box::new(empty())
+        };
+
+        SafeReader::new(
+            Box::new(prefix.take(prefix_length).chain(rest)),
+            target_description.length(),
+            target_description.hashes(),
+        )
     }
 }
 
@@ -289,11 +398,7 @@ where
     }
 
     /// Store the provided `target` in a location identified by `target_path`.
-    pub async fn store_target<'a, S>(
-        &'a mut self,
-        target: S,
-        target_path: &'a TargetPath,
-    ) -> Result<()>
+    pub async fn store_target<'a, S>(&'a self, target: S, target_path: &'a TargetPath) -> Result<()>
     where
         S: AsyncRead + Send + Unpin + 'a,
     {
@@ -301,13 +406,118 @@ where
     }
 }
 
+/// Store `metadata` in `repository` under every name a client may fetch it by.
+///
+/// Root metadata is stored as both `VERSION.root` and `root`, since clients walk the chain of
+/// root metadata by version number. When `consistent_snapshot` is set, snapshot, targets and
+/// delegated targets metadata are likewise stored as `VERSION.ROLE` as well as `ROLE`, so clients
+/// can fetch the exact version a snapshot describes while the repository is being updated.
+/// Timestamp metadata is only ever stored unversioned.
+///
+/// The versioned copy is written first so the unversioned copy never refers to a file that does
+/// not exist yet.
+pub async fn publish_metadata<R, D, M>(
+    repository: &R,
+    path: &MetadataPath,
+    metadata: &SignedMetadata<D, M>,
+    consistent_snapshot: bool,
+) -> Result<()>
+where
+    R: RepositoryStorage<D>,
+    D: DataInterchange + Sync,
+    M: Metadata + Sync,
+{
+    let mut repository = Repository::<_, D>::new(repository);
+    let raw = metadata.to_raw()?;
+
+    let versioned = match M::ROLE {
+        Role::Root => true,
+        Role::Timestamp => false,
+        Role::Snapshot | Role::Targets | Role::Mirrors => consistent_snapshot,
+    };
+    if versioned {
+        let version = MetadataVersion::Number(metadata.parse_version_untrusted()?);
+        repository.store_metadata(path, &version, &raw).await?;
+    }
+
+    repository
+        .store_metadata(path, &MetadataVersion::None, &raw)
+        .await
+}
+
+/// Store the metadata of every bin in `bins` in `repository`, like [`publish_metadata`].
+///
+/// The parent targets metadata, which must include [`HashedBins::delegations`], and the snapshot
+/// metadata describing the bins are published separately.
+pub async fn publish_hashed_bins<R, D>(
+    repository: &R,
+    bins: &HashedBins<D>,
+    consistent_snapshot: bool,
+) -> Result<()>
+where
+    R: RepositoryStorage<D>,
+    D: DataInterchange + Sync,
+{
+    for bin in bins.bins() {
+        publish_metadata(repository, bin.role(), bin.metadata(), consistent_snapshot).await?;
+    }
+    Ok(())
+}
+
+/// Store the target `data` described by `target_description` in `repository` under every name a
+/// client may fetch it by.
+///
+/// The target is always stored at `target_path`. When `consistent_snapshot` is set, it is also
+/// stored at `HASH.FILENAME` for each hash in `target_description`, which is where clients fetch
+/// it from in that case.
+///
+/// Returns an error if `data` does not match the length and hashes in `target_description`.
+pub async fn publish_target<R, D>(
+    repository: &R,
+    target_path: &TargetPath,
+    target_description: &TargetDescription,
+    data: &[u8],
+    consistent_snapshot: bool,
+) -> Result<()>
+where
+    R: RepositoryStorage<D>,
+    D: DataInterchange + Sync,
+{
+    let hash_algs = target_description
+        .hashes()
+        .keys()
+        .cloned()
+        .collect::<Vec<_>>();
+    let (length, hashes) = crypto::calculate_hashes(data, &hash_algs)?;
+    if length != target_description.length() || &hashes != target_description.hashes() {
+        return Err(Error::IllegalArgument(format!(
+            "Target {:?} does not match its description",
+            target_path
+        )));
+    }
+
+    if consistent_snapshot {
+        for value in target_description.hashes().values() {
+            let path = target_path.with_hash_prefix(value)?;
+            repository.store_target(data, &path).await?;
+        }
+    }
+
+    repository.store_target(data, target_path).await
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
     use crate::interchange::Json;
-    use crate::metadata::{MetadataPath, MetadataVersion, Role, RootMetadata, SnapshotMetadata};
+    use crate::metadata::{
+        MetadataPath, MetadataVersion, Role, RootMetadata, SnapshotMetadata,
+        SnapshotMetadataBuilder, TimestampMetadata, TimestampMetadataBuilder,
+    };
     use crate::repository::EphemeralRepository;
     use futures_executor::block_on;
+    use futures_util::io::Cursor;
     use matches::assert_matches;
 
     #[test]
@@ -455,7 +665,7 @@ mod test {
                 client
                     .fetch_metadata::<RootMetadata>(&path, &version, Some(4), None)
                     .await,
-                Err(_)
+                Err(Error::MetadataLengthExceeded { max_length: 4, .. })
             );
         })
     }
@@ -464,7 +674,7 @@ mod test {
     fn repository_rejects_corrupt_targets() {
         block_on(async {
             let repo = EphemeralRepository::new();
-            let mut client = Repository::<_, Json>::new(repo);
+            let client = Repository::<_, Json>::new(repo);
 
             let data: &[u8] = b"like tears in the rain";
             let target_description =
@@ -489,4 +699,193 @@ mod test {
             assert!(read.read_to_end(&mut buf).await.is_err());
         })
     }
+
+    #[test]
+    fn repository_checks_every_target_hash() {
+        block_on(async {
+            let repo = EphemeralRepository::new();
+            let client = Repository::<_, Json>::new(repo);
+
+            let data: &[u8] = b"like tears in the rain";
+            let path = TargetPath::new("batty".into()).unwrap();
+            client.store_target(data, &path).await.unwrap();
+
+            // Corrupt the hash that is not preferred, which must still be checked.
+            let description = TargetDescription::from_reader(
+                data,
+                &[HashAlgorithm::Sha256, HashAlgorithm::Sha512],
+            )
+            .unwrap();
+            let mut hashes = description.hashes().clone();
+            let _ = hashes.insert(
+                HashAlgorithm::Sha256,
+                crypto::calculate_hash(b"you're in a desert", HashAlgorithm::Sha256),
+            );
+            let description = TargetDescription::new(description.length(), hashes, None).unwrap();
+
+            let mut read = client.fetch_target(&path, &description).await.unwrap();
+            assert!(read.read_to_end(&mut Vec::new()).await.is_err());
+        })
+    }
+
+    #[test]
+    fn repository_resumes_targets() {
+        block_on(async {
+            let repo = EphemeralRepository::new();
+            let client = Repository::<_, Json>::new(repo);
+
+            let data: &[u8] = b"like tears in the rain";
+            let description =
+                TargetDescription::from_reader(data, &[HashAlgorithm::Sha256]).unwrap();
+            let path = TargetPath::new("batty".into()).unwrap();
+            client.store_target(data, &path).await.unwrap();
+
+            // Only the rest is fetched, but the whole target comes out and is verified.
+            for prefix_length in &[0, 5, data.len()] {
+                let prefix = &data[..*prefix_length];
+                let mut read = client
+                    .fetch_target_after(&path, &description, prefix, *prefix_length as u64)
+                    .await
+                    .unwrap();
+                let mut buf = Vec::new();
+                read.read_to_end(&mut buf).await.unwrap();
+                assert_eq!(buf, data);
+            }
+
+            // A prefix that doesn't match the target fails verification.
+            let mut read = client
+                .fetch_target_after(&path, &description, &b"LIKE "[..], 5)
+                .await
+                .unwrap();
+            assert!(read.read_to_end(&mut Vec::new()).await.is_err());
+
+            // A prefix can't be longer than the target.
+            let long = [data, b"!"].concat();
+            let mut read = client
+                .fetch_target_after(
+                    &path,
+                    &description,
+                    Cursor::new(long.clone()),
+                    long.len() as u64,
+                )
+                .await
+                .unwrap();
+            assert!(read.read_to_end(&mut Vec::new()).await.is_err());
+        })
+    }
+
+    #[test]
+    fn publish_metadata_stores_consistent_snapshot_names() {
+        block_on(async {
+            let key = PrivateKey::from_pkcs8(
+                include_bytes!("../tests/ed25519/ed25519-1.pk8.der"),
+                SignatureScheme::Ed25519,
+            )
+            .unwrap();
+            let snapshot = SnapshotMetadataBuilder::new()
+                .version(3)
+                .signed::<Json>(&key)
+                .unwrap();
+            let timestamp =
+                TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                    .unwrap()
+                    .version(4)
+                    .signed::<Json>(&key)
+                    .unwrap();
+            let snapshot_path = MetadataPath::from_role(&Role::Snapshot);
+            let timestamp_path = MetadataPath::from_role(&Role::Timestamp);
+
+            let repo = EphemeralRepository::<Json>::new();
+            publish_metadata(&repo, &snapshot_path, &snapshot, false)
+                .await
+                .unwrap();
+            let client = Repository::<_, Json>::new(&repo);
+            assert_matches!(
+                client
+                    .fetch_raw_metadata::<SnapshotMetadata>(
+                        &snapshot_path,
+                        &MetadataVersion::Number(3),
+                        None,
+                        None
+                    )
+                    .await,
+                Err(Error::NotFound)
+            );
+
+            publish_metadata(&repo, &snapshot_path, &snapshot, true)
+                .await
+                .unwrap();
+            publish_metadata(&repo, &timestamp_path, &timestamp, true)
+                .await
+                .unwrap();
+            for version in &[MetadataVersion::None, MetadataVersion::Number(3)] {
+                assert_eq!(
+                    client
+                        .fetch_raw_metadata::<SnapshotMetadata>(&snapshot_path, version, None, None)
+                        .await,
+                    Ok(snapshot.to_raw().unwrap())
+                );
+            }
+            assert_eq!(
+                client
+                    .fetch_raw_metadata::<TimestampMetadata>(
+                        &timestamp_path,
+                        &MetadataVersion::None,
+                        None,
+                        None
+                    )
+                    .await,
+                Ok(timestamp.to_raw().unwrap())
+            );
+            assert_matches!(
+                client
+                    .fetch_raw_metadata::<TimestampMetadata>(
+                        &timestamp_path,
+                        &MetadataVersion::Number(4),
+                        None,
+                        None
+                    )
+                    .await,
+                Err(Error::NotFound)
+            );
+        })
+    }
+
+    #[test]
+    fn publish_target_stores_hash_prefixed_names() {
+        block_on(async {
+            let data: &[u8] = b"target data";
+            let path = TargetPath::new("foo/bar.txt".into()).unwrap();
+            let description = TargetDescription::from_reader(
+                data,
+                &[HashAlgorithm::Sha256, HashAlgorithm::Sha512],
+            )
+            .unwrap();
+
+            let repo = EphemeralRepository::<Json>::new();
+            publish_target(&repo, &path, &description, data, true)
+                .await
+                .unwrap();
+
+            let mut paths = vec![path.clone()];
+            for value in description.hashes().values() {
+                paths.push(path.with_hash_prefix(value).unwrap());
+            }
+            for path in &paths {
+                let mut buf = Vec::new();
+                repo.fetch_target(path, &description)
+                    .await
+                    .unwrap()
+                    .read_to_end(&mut buf)
+                    .await
+                    .unwrap();
+                assert_eq!(buf, data);
+            }
+
+            assert_matches!(
+                publish_target(&repo, &path, &description, b"other data", true).await,
+                Err(Error::IllegalArgument(_))
+            );
+        })
+    }
 }
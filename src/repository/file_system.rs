@@ -83,6 +83,9 @@ where
 }
 
 /// A repository contained on the local file system.
+///
+/// File operations block the thread that polls them. On an async runtime, consider running them
+/// where blocking is allowed, such as with tokio's `spawn_blocking`.
 pub struct FileSystemRepository<D>
 where
     D: DataInterchange,
@@ -171,7 +174,9 @@ where
 
             let mut temp_file = AllowStdIo::new(create_temp_file(&path)?);
             copy(metadata, &mut temp_file).await?;
-            temp_file.into_inner().persist(&path)?;
+            let temp_file = temp_file.into_inner();
+            temp_file.as_file().sync_all()?;
+            temp_file.persist(&path)?;
 
             Ok(())
         }
@@ -196,7 +201,9 @@ where
 
             let mut temp_file = AllowStdIo::new(create_temp_file(&path)?);
             copy(read, &mut temp_file).await?;
-            temp_file.into_inner().persist(&path)?;
+            let temp_file = temp_file.into_inner();
+            temp_file.as_file().sync_all()?;
+            temp_file.persist(&path)?;
 
             Ok(())
         }
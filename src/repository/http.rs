@@ -1,27 +1,118 @@
 //! Read-only Repository implementation backed by a web server.
 
+#[cfg(unix)]
+use futures01::Future as Future01;
 use futures_io::AsyncRead;
 use futures_util::compat::{Future01CompatExt, Stream01CompatExt};
 use futures_util::future::{BoxFuture, FutureExt};
 use futures_util::stream::TryStreamExt;
-use http::{Response, StatusCode, Uri};
+use http::header::{
+    HeaderName, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, RANGE, USER_AGENT,
+};
+use http::{HeaderMap, Response, StatusCode, Uri};
 use hyper::body::Body;
-use hyper::client::connect::Connect;
+use hyper::client::connect::{Connect, Connected, Destination};
 use hyper::Client;
 use hyper::Request;
 use percent_encoding::utf8_percent_encode;
+use std::fmt;
 use std::io;
 use std::marker::PhantomData;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use url::Url;
 
 use crate::crypto::{HashAlgorithm, HashValue};
 use crate::error::Error;
 use crate::interchange::DataInterchange;
 use crate::metadata::{MetadataPath, MetadataVersion, TargetDescription, TargetPath};
-use crate::repository::RepositoryProvider;
+use crate::repository::{skip_bytes, RateLimiter, RepositoryProvider};
 use crate::util::SafeAsyncRead;
 use crate::Result;
 
+/// A callback that produces a fresh bearer token before each request.
+pub type TokenProvider = Arc<dyn Fn() -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+/// Credentials used to authenticate requests to an [`HttpRepository`].
+#[derive(Clone)]
+pub enum HttpAuth {
+    /// Send a static bearer token in the `Authorization` header.
+    Bearer(String),
+    /// Send a bearer token in the `Authorization` header, asking the provider for the current
+    /// token before every request. This supports tokens that are rotated while the repository is
+    /// in use.
+    BearerProvider(TokenProvider),
+    /// Send HTTP basic authentication credentials in the `Authorization` header.
+    Basic {
+        /// The user name.
+        username: String,
+        /// The optional password.
+        password: Option<String>,
+    },
+}
+
+impl HttpAuth {
+    /// Compute the value of the `Authorization` header for these credentials.
+    async fn header_value(&self) -> Result<HeaderValue> {
+        let value = match self {
+            HttpAuth::Bearer(token) => format!("Bearer {}", token),
+            HttpAuth::BearerProvider(provider) => format!("Bearer {}", provider().await?),
+            HttpAuth::Basic { username, password } => {
+                let credentials = match password {
+                    Some(password) => format!("{}:{}", username, password),
+                    None => format!("{}:", username),
+                };
+                format!(
+                    "Basic {}",
+                    data_encoding::BASE64.encode(credentials.as_bytes())
+                )
+            }
+        };
+
+        let mut value = HeaderValue::from_str(&value).map_err(|_| {
+            Error::IllegalArgument("Credentials contain invalid header characters".into())
+        })?;
+        value.set_sensitive(true);
+
+        Ok(value)
+    }
+}
+
+impl fmt::Debug for HttpAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Never print the credentials themselves.
+        match self {
+            HttpAuth::Bearer(_) => f.write_str("Bearer(..)"),
+            HttpAuth::BearerProvider(_) => f.write_str("BearerProvider(..)"),
+            HttpAuth::Basic { username, .. } => {
+                f.debug_struct("Basic").field("username", username).finish()
+            }
+        }
+    }
+}
+
+/// A hook that is given every request an [`HttpRepository`] makes, right before it is sent.
+///
+/// Hooks run after the User-Agent, custom, and authentication headers have been added, so they can
+/// implement request signing schemes such as AWS SigV4 or HMAC that cover the final headers.
+///
+/// Any `Fn(&mut Request<Body>) -> Result<()>` closure is a `RequestHook`.
+pub trait RequestHook: Send + Sync {
+    /// Inspect or modify `request`. Returning an error aborts the fetch with that error.
+    fn on_request<'a>(&'a self, request: &'a mut Request<Body>) -> BoxFuture<'a, Result<()>>;
+}
+
+impl<F> RequestHook for F
+where
+    F: Fn(&mut Request<Body>) -> Result<()> + Send + Sync,
+{
+    fn on_request<'a>(&'a self, request: &'a mut Request<Body>) -> BoxFuture<'a, Result<()>> {
+        let result = self(request);
+        async move { result }.boxed()
+    }
+}
+
 /// A builder to create a repository accessible over HTTP.
 pub struct HttpRepositoryBuilder<C, D>
 where
@@ -31,9 +122,13 @@ where
     uri: Uri,
     client: Client<C>,
     user_agent: Option<String>,
+    auth: Option<HttpAuth>,
+    headers: HeaderMap,
+    request_hooks: Vec<Arc<dyn RequestHook>>,
     metadata_prefix: Option<Vec<String>>,
     targets_prefix: Option<Vec<String>>,
     min_bytes_per_second: u32,
+    rate_limiter: Option<RateLimiter>,
     _interchange: PhantomData<D>,
 }
 
@@ -48,9 +143,13 @@ where
             uri: url.to_string().parse::<Uri>().unwrap(), // This is dangerous, but will only exist for a short time as we migrate APIs.
             client: client,
             user_agent: None,
+            auth: None,
+            headers: HeaderMap::new(),
+            request_hooks: Vec::new(),
             metadata_prefix: None,
             targets_prefix: None,
             min_bytes_per_second: 4096,
+            rate_limiter: None,
             _interchange: PhantomData,
         }
     }
@@ -61,9 +160,13 @@ where
             uri: uri,
             client: client,
             user_agent: None,
+            auth: None,
+            headers: HeaderMap::new(),
+            request_hooks: Vec::new(),
             metadata_prefix: None,
             targets_prefix: None,
             min_bytes_per_second: 4096,
+            rate_limiter: None,
             _interchange: PhantomData,
         }
     }
@@ -78,6 +181,58 @@ where
         self
     }
 
+    /// Authenticate every request with the given credentials.
+    pub fn auth(mut self, auth: HttpAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Authenticate every request with a static bearer token.
+    pub fn bearer_token<T: Into<String>>(self, token: T) -> Self {
+        self.auth(HttpAuth::Bearer(token.into()))
+    }
+
+    /// Authenticate every request with a bearer token returned by `provider`. The provider is
+    /// called before each request, so it can refresh tokens that expire.
+    pub fn bearer_token_provider<F>(self, provider: F) -> Self
+    where
+        F: Fn() -> BoxFuture<'static, Result<String>> + Send + Sync + 'static,
+    {
+        self.auth(HttpAuth::BearerProvider(Arc::new(provider)))
+    }
+
+    /// Authenticate every request with HTTP basic authentication.
+    pub fn basic_auth<U, P>(self, username: U, password: Option<P>) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        self.auth(HttpAuth::Basic {
+            username: username.into(),
+            password: password.map(Into::into),
+        })
+    }
+
+    /// Add a custom header to every request, such as an API key expected by a CDN.
+    ///
+    /// Since custom headers often carry credentials, `value` is marked as sensitive, so it is
+    /// hidden from `Debug` output.
+    pub fn header(mut self, name: HeaderName, mut value: HeaderValue) -> Self {
+        value.set_sensitive(true);
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Add a hook that can inspect or modify every request before it is sent. Hooks run in the
+    /// order they were added.
+    pub fn request_hook<H>(mut self, hook: H) -> Self
+    where
+        H: RequestHook + 'static,
+    {
+        self.request_hooks.push(Arc::new(hook));
+        self
+    }
+
     /// The argument `metadata_prefix` is used to provide an alternate path where metadata is
     /// stored on the repository. If `None`, this defaults to `/`. For example, if there is a TUF
     /// repository at `https://tuf.example.com/`, but all metadata is stored at `/meta/`, then
@@ -104,6 +259,18 @@ where
         self
     }
 
+    /// Limit the bandwidth used to download metadata and targets with `rate_limiter`. Clones of
+    /// the limiter share its budget, so it can be shared with other repositories to cap their
+    /// combined bandwidth.
+    ///
+    /// Throttled downloads are subject to the minimum bitrate as well, so it should be lowered
+    /// below the share of the rate limit each concurrent download gets, or disabled by setting it
+    /// to 0.
+    pub fn rate_limit(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
     /// Build a `HttpRepository`.
     pub fn build(self) -> HttpRepository<C, D> {
         let user_agent = match self.user_agent {
@@ -115,14 +282,106 @@ where
             uri: self.uri,
             client: self.client,
             user_agent: user_agent,
+            auth: self.auth,
+            headers: self.headers,
+            request_hooks: self.request_hooks,
             metadata_prefix: self.metadata_prefix,
             targets_prefix: self.targets_prefix,
             min_bytes_per_second: self.min_bytes_per_second,
+            rate_limiter: self.rate_limiter,
             _interchange: PhantomData,
         }
     }
 }
 
+impl<C, D> fmt::Debug for HttpRepositoryBuilder<C, D>
+where
+    C: Connect + Sync + 'static,
+    D: DataInterchange,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The credentials and custom headers are redacted by their own `Debug` impls.
+        f.debug_struct("HttpRepositoryBuilder")
+            .field("uri", &self.uri)
+            .field("user_agent", &self.user_agent)
+            .field("auth", &self.auth)
+            .field("headers", &self.headers)
+            .field("request_hooks", &self.request_hooks.len())
+            .field("metadata_prefix", &self.metadata_prefix)
+            .field("targets_prefix", &self.targets_prefix)
+            .field("min_bytes_per_second", &self.min_bytes_per_second)
+            .field("rate_limiter", &self.rate_limiter)
+            .finish()
+    }
+}
+
+#[cfg(unix)]
+impl<D> HttpRepositoryBuilder<UnixConnector, D>
+where
+    D: DataInterchange,
+{
+    /// Create a new repository that sends every request over the Unix domain socket at
+    /// `socket_path`, such as one served by a local proxy daemon. The scheme and authority of
+    /// `uri` are only used to build the request, and the path is used as usual.
+    ///
+    /// ```
+    /// # use tuf::interchange::Json;
+    /// use tuf::repository::{HttpRepository, HttpRepositoryBuilder, UnixConnector};
+    ///
+    /// let repo: HttpRepository<UnixConnector, Json> = HttpRepositoryBuilder::new_with_unix_socket(
+    ///     "/run/tuf-proxy.sock",
+    ///     "http://localhost/".parse().unwrap(),
+    /// )
+    /// .build();
+    /// ```
+    pub fn new_with_unix_socket<P: Into<PathBuf>>(socket_path: P, uri: Uri) -> Self {
+        let client = Client::builder().build(UnixConnector::new(socket_path));
+        Self::new_with_uri(uri, client)
+    }
+}
+
+/// A hyper connector that ignores the request's destination and instead connects to a Unix domain
+/// socket.
+///
+/// Any other custom transport can be used by implementing hyper's
+/// [`Connect`](hyper::client::connect::Connect) trait and passing the resulting `Client` to
+/// [`HttpRepositoryBuilder::new_with_uri`].
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct UnixConnector {
+    socket_path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixConnector {
+    /// Create a new `UnixConnector` that connects to the socket at `socket_path`.
+    pub fn new<P: Into<PathBuf>>(socket_path: P) -> Self {
+        UnixConnector {
+            socket_path: socket_path.into(),
+        }
+    }
+
+    /// The path of the socket this connector connects to.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+}
+
+#[cfg(unix)]
+impl Connect for UnixConnector {
+    type Transport = tokio_uds::UnixStream;
+    type Error = io::Error;
+    type Future =
+        Box<dyn Future01<Item = (tokio_uds::UnixStream, Connected), Error = io::Error> + Send>;
+
+    fn connect(&self, _dst: Destination) -> Self::Future {
+        Box::new(
+            tokio_uds::UnixStream::connect(&self.socket_path)
+                .map(|stream| (stream, Connected::new())),
+        )
+    }
+}
+
 /// A repository accessible over HTTP.
 pub struct HttpRepository<C, D>
 where
@@ -132,9 +391,13 @@ where
     uri: Uri,
     client: Client<C>,
     user_agent: String,
+    auth: Option<HttpAuth>,
+    headers: HeaderMap,
+    request_hooks: Vec<Arc<dyn RequestHook>>,
     metadata_prefix: Option<Vec<String>>,
     targets_prefix: Option<Vec<String>>,
     min_bytes_per_second: u32,
+    rate_limiter: Option<RateLimiter>,
     _interchange: PhantomData<D>,
 }
 
@@ -209,18 +472,45 @@ where
     C: Connect + Sync + 'static,
     D: DataInterchange,
 {
+    async fn build_request(&self, uri: Uri) -> Result<Request<Body>> {
+        let mut req = Request::builder()
+            .uri(uri)
+            .header(USER_AGENT, &*self.user_agent)
+            .body(Body::default())?;
+
+        let headers = req.headers_mut();
+        for (name, value) in self.headers.iter() {
+            headers.append(name, value.clone());
+        }
+
+        if let Some(ref auth) = self.auth {
+            headers.insert(AUTHORIZATION, auth.header_value().await?);
+        }
+
+        for hook in self.request_hooks.iter() {
+            hook.on_request(&mut req).await?;
+        }
+
+        Ok(req)
+    }
+
+    /// Send a GET request for `components` under `prefix`. If `range_start` is provided, only the
+    /// bytes from that offset onwards are requested, which the server may ignore.
     async fn get<'a>(
         &'a self,
         prefix: &'a Option<Vec<String>>,
         components: &'a [String],
+        range_start: Option<u64>,
     ) -> Result<Response<Body>> {
         let base_uri = self.uri.clone();
         let uri = extend_uri(base_uri, prefix, components)?;
+        let mut req = self.build_request(uri).await?;
 
-        let req = Request::builder()
-            .uri(uri)
-            .header("User-Agent", &*self.user_agent)
-            .body(Body::default())?;
+        if let Some(range_start) = range_start {
+            let range = HeaderValue::from_str(&format!("bytes={}-", range_start))
+                .map_err(|err| Error::Opaque(format!("Invalid Range header: {:?}", err)))?;
+            req.headers_mut().insert(RANGE, range);
+        }
 
         let resp = self.client.request(req).compat().await?;
         let status = resp.status();
@@ -238,6 +528,54 @@ where
             Ok(resp)
         }
     }
+
+    /// Read the body of `resp`, failing if it is longer than `max_length` or arrives too slowly.
+    fn target_body(
+        &self,
+        resp: Response<Body>,
+        max_length: u64,
+    ) -> Result<Box<dyn AsyncRead + Send + Unpin>> {
+        if let Some(content_length) = oversized_content_length(&resp, max_length) {
+            return Err(Error::Opaque(format!(
+                "Content-Length {} exceeds the maximum allowed length {}",
+                content_length, max_length
+            )));
+        }
+
+        let reader = resp
+            .into_body()
+            .compat()
+            .map_err(io::Error::other)
+            .into_async_read()
+            .check_length_and_hash(max_length, None)?
+            .enforce_minimum_bitrate(self.min_bytes_per_second);
+
+        Ok(self.throttle(reader))
+    }
+
+    /// Apply the rate limit, if any, to `reader`.
+    fn throttle<R>(&self, reader: R) -> Box<dyn AsyncRead + Send + Unpin>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        match self.rate_limiter {
+            Some(ref rate_limiter) => Box::new(rate_limiter.throttle(reader)),
+            None => Box::new(reader),
+        }
+    }
+}
+
+/// The `Content-Length` the server announced for `resp`, if it is larger than the trusted
+/// `max_length`, so that the request can fail early.
+///
+/// The header is only a hint from an untrusted server, so callers must still cap the number of
+/// bytes read from the body.
+fn oversized_content_length(resp: &Response<Body>, max_length: u64) -> Option<u64> {
+    resp.headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|content_length| *content_length > max_length)
 }
 
 impl<C, D> RepositoryProvider<D> for HttpRepository<C, D>
@@ -249,24 +587,33 @@ where
         &'a self,
         meta_path: &'a MetadataPath,
         version: &'a MetadataVersion,
-        _max_length: Option<usize>,
+        max_length: Option<usize>,
         _hash_data: Option<(&'static HashAlgorithm, HashValue)>,
     ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
         let components = meta_path.components::<D>(&version);
         async move {
-            let resp = self.get(&self.metadata_prefix, &components).await?;
+            let resp = self.get(&self.metadata_prefix, &components, None).await?;
 
-            // TODO(#278) check content length if known and fail early if the payload is too large.
+            if let Some(max_length) = max_length {
+                if oversized_content_length(&resp, max_length as u64).is_some() {
+                    return Err(Error::MetadataLengthExceeded {
+                        path: meta_path.clone(),
+                        max_length,
+                    });
+                }
+            }
+
+            let max_length = max_length.map(|l| l as u64);
 
             let reader = resp
                 .into_body()
                 .compat()
                 .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
                 .into_async_read()
+                .check_length_and_hash(max_length.unwrap_or(u64::MAX), None)?
                 .enforce_minimum_bitrate(self.min_bytes_per_second);
 
-            let reader: Box<dyn AsyncRead + Send + Unpin> = Box::new(reader);
-            Ok(reader)
+            Ok(self.throttle(reader))
         }
         .boxed()
     }
@@ -274,30 +621,67 @@ where
     fn fetch_target<'a>(
         &'a self,
         target_path: &'a TargetPath,
-        _target_description: &'a TargetDescription,
+        target_description: &'a TargetDescription,
     ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
         async move {
             let components = target_path.components();
-            let resp = self.get(&self.targets_prefix, &components).await?;
+            let resp = self.get(&self.targets_prefix, &components, None).await?;
+            self.target_body(resp, target_description.length())
+        }
+        .boxed()
+    }
 
-            // TODO(#278) check content length if known and fail early if the payload is too large.
+    fn fetch_target_from<'a>(
+        &'a self,
+        target_path: &'a TargetPath,
+        target_description: &'a TargetDescription,
+        offset: u64,
+    ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+        async move {
+            let components = target_path.components();
+            let resp = self
+                .get(&self.targets_prefix, &components, Some(offset))
+                .await?;
 
-            let reader = resp
-                .into_body()
-                .compat()
-                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
-                .into_async_read()
-                .enforce_minimum_bitrate(self.min_bytes_per_second);
+            // The server may ignore the Range header and send the whole target instead.
+            if resp.status() != StatusCode::PARTIAL_CONTENT {
+                let reader = self.target_body(resp, target_description.length())?;
+                return skip_bytes(reader, offset).await;
+            }
+
+            match content_range_start(&resp) {
+                Some(start) if start == offset => {}
+                start => {
+                    return Err(Error::Opaque(format!(
+                        "Requested {} from byte {}, but the server sent a range starting at {:?}",
+                        target_path.value(),
+                        offset,
+                        start
+                    )));
+                }
+            }
 
-            Ok(Box::new(reader) as Box<dyn AsyncRead + Send + Unpin>)
+            self.target_body(resp, target_description.length().saturating_sub(offset))
         }
         .boxed()
     }
 }
 
+/// The offset of the first byte in a partial response, from its `Content-Range` header.
+fn content_range_start(resp: &Response<Body>) -> Option<u64> {
+    let range = resp.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = range.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::interchange::Json;
+    use futures_executor::block_on;
+    use matches::assert_matches;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     // Old behavior of the `HttpRepository::get` extension
     // functionality
@@ -440,4 +824,377 @@ mod test {
             "http://[aaaa::aaaa:aaaa:aaaa:1234%252]:80/prefix/componenents_one/components_two"
         );
     }
+
+    fn build_request(
+        builder: HttpRepositoryBuilder<hyper::client::HttpConnector, Json>,
+    ) -> Request<Body> {
+        let repo = builder.build();
+        block_on(repo.build_request("http://example.com/".parse().unwrap())).unwrap()
+    }
+
+    fn new_builder() -> HttpRepositoryBuilder<hyper::client::HttpConnector, Json> {
+        HttpRepositoryBuilder::new_with_uri("http://example.com/".parse().unwrap(), Client::new())
+    }
+
+    #[test]
+    fn http_repository_request_without_auth() {
+        let req = build_request(new_builder());
+        assert_eq!(req.headers().get(USER_AGENT).unwrap(), "rust-tuf");
+        assert!(req.headers().get(AUTHORIZATION).is_none());
+    }
+
+    #[test]
+    fn http_repository_bearer_token() {
+        let req = build_request(new_builder().bearer_token("secret"));
+        assert_eq!(req.headers().get(AUTHORIZATION).unwrap(), "Bearer secret");
+    }
+
+    #[test]
+    fn http_repository_basic_auth() {
+        let req = build_request(new_builder().basic_auth("Aladdin", Some("open sesame")));
+        assert_eq!(
+            req.headers().get(AUTHORIZATION).unwrap(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+
+    #[test]
+    fn http_repository_bearer_token_provider_is_called_per_request() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider_calls = Arc::clone(&calls);
+        let repo = new_builder()
+            .bearer_token_provider(move || {
+                let n = provider_calls.fetch_add(1, Ordering::SeqCst);
+                async move { Ok(format!("token-{}", n)) }.boxed()
+            })
+            .build();
+
+        for expected in &["Bearer token-0", "Bearer token-1"] {
+            let req = block_on(repo.build_request("http://example.com/".parse().unwrap())).unwrap();
+            assert_eq!(req.headers().get(AUTHORIZATION).unwrap(), expected);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn http_repository_custom_headers() {
+        let req = build_request(new_builder().header(
+            HeaderName::from_static("x-api-key"),
+            HeaderValue::from_static("hunter2"),
+        ));
+        assert_eq!(req.headers().get("x-api-key").unwrap(), "hunter2");
+        assert!(req.headers().get("x-api-key").unwrap().is_sensitive());
+    }
+
+    #[test]
+    fn http_repository_request_hooks_run_in_order() {
+        let req = build_request(
+            new_builder()
+                .bearer_token("secret")
+                .request_hook(|req: &mut Request<Body>| {
+                    let auth = req.headers()[AUTHORIZATION].to_str().unwrap().to_owned();
+                    req.headers_mut().insert(
+                        HeaderName::from_static("x-signature"),
+                        HeaderValue::from_str(&format!("signed({})", auth)).unwrap(),
+                    );
+                    Ok(())
+                })
+                .request_hook(|req: &mut Request<Body>| {
+                    req.headers_mut().append(
+                        HeaderName::from_static("x-hook"),
+                        HeaderValue::from_static("2"),
+                    );
+                    Ok(())
+                }),
+        );
+        assert_eq!(
+            req.headers().get("x-signature").unwrap(),
+            "signed(Bearer secret)"
+        );
+        assert_eq!(req.headers().get("x-hook").unwrap(), "2");
+    }
+
+    #[test]
+    fn http_repository_request_hook_error_aborts() {
+        let repo = new_builder()
+            .request_hook(|_: &mut Request<Body>| Err(Error::Opaque("denied".into())))
+            .build();
+        assert_eq!(
+            block_on(repo.build_request("http://example.com/".parse().unwrap())).err(),
+            Some(Error::Opaque("denied".into()))
+        );
+    }
+
+    /// Accept a single connection on `listener`, and answer it with `response`. Returns the lines
+    /// of the request head.
+    #[cfg(unix)]
+    fn serve_once(
+        listener: std::os::unix::net::UnixListener,
+        response: &'static [u8],
+    ) -> std::thread::JoinHandle<Vec<String>> {
+        use std::io::{BufRead, BufReader, Write};
+
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push(line);
+            }
+            reader.get_mut().write_all(response).unwrap();
+            head
+        })
+    }
+
+    /// Fetch the target "greeting", which is "hello", from the server answering on `socket_path`,
+    /// skipping its first `offset` bytes.
+    #[cfg(unix)]
+    fn fetch_greeting(socket_path: &Path, offset: u64) -> Result<Vec<u8>> {
+        fetch_greeting_with_rate_limit(socket_path, offset, None)
+    }
+
+    /// Like `fetch_greeting`, but limits the download to the bandwidth of `rate_limiter`.
+    #[cfg(unix)]
+    fn fetch_greeting_with_rate_limit(
+        socket_path: &Path,
+        offset: u64,
+        rate_limiter: Option<RateLimiter>,
+    ) -> Result<Vec<u8>> {
+        use crate::crypto::HashAlgorithm;
+        use futures_util::compat::Compat;
+        use futures_util::io::AsyncReadExt;
+
+        let mut builder = HttpRepositoryBuilder::<_, Json>::new_with_unix_socket(
+            socket_path,
+            "http://localhost/repo".parse().unwrap(),
+        )
+        .targets_prefix(vec!["targets".into()]);
+        if let Some(rate_limiter) = rate_limiter {
+            builder = builder.rate_limit(rate_limiter).min_bytes_per_second(0);
+        }
+        let repo = builder.build();
+
+        let fetch = async move {
+            let path = TargetPath::new("greeting".into())?;
+            let description =
+                TargetDescription::from_reader(&b"hello"[..], &[HashAlgorithm::Sha256])?;
+            let mut reader = if offset == 0 {
+                repo.fetch_target(&path, &description).await?
+            } else {
+                repo.fetch_target_from(&path, &description, offset).await?
+            };
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).await?;
+            Ok::<_, Error>(buf)
+        };
+
+        let mut runtime = tokio01::runtime::current_thread::Runtime::new().unwrap();
+        runtime.block_on(Compat::new(fetch.boxed()))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http_repository_over_unix_socket() {
+        use std::os::unix::net::UnixListener;
+
+        let temp_dir = tempfile::Builder::new()
+            .prefix("rust-tuf")
+            .tempdir()
+            .unwrap();
+        let socket_path = temp_dir.path().join("repo.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let server = serve_once(
+            listener,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+        );
+        let body = fetch_greeting(&socket_path, 0).unwrap();
+
+        assert_eq!(body, b"hello");
+        assert_eq!(
+            server.join().unwrap()[0],
+            "GET /repo/targets/greeting HTTP/1.1\r\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http_repository_limits_bandwidth() {
+        use std::os::unix::net::UnixListener;
+        use std::time::{Duration, Instant};
+
+        let temp_dir = tempfile::Builder::new()
+            .prefix("rust-tuf")
+            .tempdir()
+            .unwrap();
+        let socket_path = temp_dir.path().join("repo.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let server = serve_once(
+            listener,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+        );
+
+        // Only 4 of the 5 bytes fit in the first second.
+        let start = Instant::now();
+        let body =
+            fetch_greeting_with_rate_limit(&socket_path, 0, Some(RateLimiter::new(4).unwrap()))
+                .unwrap();
+        assert_eq!(body, b"hello");
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http_repository_resumes_targets_with_range_requests() {
+        use std::os::unix::net::UnixListener;
+
+        let temp_dir = tempfile::Builder::new()
+            .prefix("rust-tuf")
+            .tempdir()
+            .unwrap();
+        let socket_path = temp_dir.path().join("repo.sock");
+        let serve = |response| serve_once(UnixListener::bind(&socket_path).unwrap(), response);
+        let reset = || std::fs::remove_file(&socket_path).unwrap();
+
+        // The server sends just the requested range.
+        let server = serve(
+            b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 2-4/5\r\n\
+              Content-Length: 3\r\n\r\nllo",
+        );
+        assert_eq!(fetch_greeting(&socket_path, 2), Ok(b"llo".to_vec()));
+        let head = server.join().unwrap();
+        assert!(head
+            .iter()
+            .any(|line| line.eq_ignore_ascii_case("range: bytes=2-\r\n")));
+        reset();
+
+        // The server ignores the range and sends the whole target.
+        let server = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+        assert_eq!(fetch_greeting(&socket_path, 2), Ok(b"llo".to_vec()));
+        server.join().unwrap();
+        reset();
+
+        // The server sends a different range than the one requested.
+        let server = serve(
+            b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 1-4/5\r\n\
+              Content-Length: 4\r\n\r\nello",
+        );
+        assert_matches!(fetch_greeting(&socket_path, 2), Err(Error::Opaque(_)));
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http_repository_cuts_off_bodies_longer_than_the_target() {
+        use std::os::unix::net::UnixListener;
+
+        let temp_dir = tempfile::Builder::new()
+            .prefix("rust-tuf")
+            .tempdir()
+            .unwrap();
+        let socket_path = temp_dir.path().join("repo.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Without a Content-Length, only the read cap stops the body.
+        let server = serve_once(
+            listener,
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n8\r\n, world!\r\n0\r\n\r\n",
+        );
+        assert_matches!(fetch_greeting(&socket_path, 0), Err(Error::Opaque(_)));
+        server.join().unwrap();
+    }
+
+    fn response_with_content_length(length: &str) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_LENGTH, length)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn http_repository_rejects_oversized_content_length() {
+        assert_eq!(
+            oversized_content_length(&response_with_content_length("10"), 10),
+            None
+        );
+        assert_eq!(
+            oversized_content_length(&response_with_content_length("11"), 10),
+            Some(11)
+        );
+        // A missing or malformed header is not trusted either way, so the read cap applies.
+        assert_eq!(
+            oversized_content_length(&response_with_content_length("lots"), 10),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn http_repository_rejects_metadata_with_oversized_content_length() {
+        use crate::metadata::{MetadataPath, MetadataVersion, Role, RootMetadata};
+        use crate::repository::Repository;
+        use futures_util::compat::Compat;
+        use std::os::unix::net::UnixListener;
+
+        let temp_dir = tempfile::Builder::new()
+            .prefix("rust-tuf")
+            .tempdir()
+            .unwrap();
+        let socket_path = temp_dir.path().join("repo.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // The announced length alone fails the fetch, before any of the body is read.
+        let server = serve_once(listener, b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n");
+        let repo = HttpRepositoryBuilder::<_, Json>::new_with_unix_socket(
+            &socket_path,
+            "http://localhost/".parse().unwrap(),
+        )
+        .build();
+        let path = MetadataPath::from_role(&Role::Root);
+        let fetch = async {
+            Repository::<_, Json>::new(repo)
+                .fetch_raw_metadata::<RootMetadata>(&path, &MetadataVersion::None, Some(10), None)
+                .await
+                .map(|_| ())
+        };
+
+        let mut runtime = tokio01::runtime::current_thread::Runtime::new().unwrap();
+        assert_eq!(
+            runtime.block_on(Compat::new(fetch.boxed())),
+            Err(Error::MetadataLengthExceeded {
+                path: MetadataPath::from_role(&Role::Root),
+                max_length: 10,
+            })
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn http_auth_debug_hides_secrets() {
+        let auth = HttpAuth::Basic {
+            username: "user".into(),
+            password: Some("hunter2".into()),
+        };
+        assert!(!format!("{:?}", auth).contains("hunter2"));
+        assert!(!format!("{:?}", HttpAuth::Bearer("hunter2".into())).contains("hunter2"));
+
+        let builder = new_builder()
+            .bearer_token("hunter2")
+            .header(AUTHORIZATION, HeaderValue::from_static("Token hunter2"))
+            .header(
+                HeaderName::from_static("x-api-key"),
+                HeaderValue::from_static("hunter2"),
+            );
+        let debug = format!("{:?}", builder);
+        assert!(debug.contains("x-api-key"));
+        assert!(!debug.contains("hunter2"));
+    }
 }
@@ -0,0 +1,386 @@
+//! Repository implementation that fails over between mirrors of the same repository.
+
+use futures_io::AsyncRead;
+use futures_util::future::{BoxFuture, FutureExt};
+use log::warn;
+use std::fmt;
+
+use crate::crypto::{HashAlgorithm, HashValue};
+use crate::error::Error;
+use crate::interchange::DataInterchange;
+use crate::metadata::{MetadataPath, MetadataVersion, TargetDescription, TargetPath};
+use crate::repository::RepositoryProvider;
+use crate::Result;
+
+type Provider<D> = Box<dyn RepositoryProvider<D> + Send + Sync>;
+
+/// One mirror of a repository, as used by a [`MirrorRepository`].
+pub struct Mirror<D>
+where
+    D: DataInterchange + Sync,
+{
+    name: String,
+    repository: Provider<D>,
+    priority: u32,
+    metadata_content: bool,
+    target_content: bool,
+}
+
+impl<D> Mirror<D>
+where
+    D: DataInterchange + Sync,
+{
+    /// Create a new mirror called `name` that serves both metadata and targets from `repository`.
+    /// The name is used to identify the mirror in logs and errors.
+    pub fn new<N, R>(name: N, repository: R) -> Self
+    where
+        N: Into<String>,
+        R: RepositoryProvider<D> + Send + Sync + 'static,
+    {
+        Mirror {
+            name: name.into(),
+            repository: Box::new(repository),
+            priority: 0,
+            metadata_content: true,
+            target_content: true,
+        }
+    }
+
+    /// Set the priority of this mirror. Mirrors with a lower priority are tried first, and
+    /// mirrors with the same priority are tried in the order they were added. Defaults to 0.
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Set whether metadata is fetched from this mirror. Defaults to `true`.
+    pub fn metadata_content(mut self, metadata_content: bool) -> Self {
+        self.metadata_content = metadata_content;
+        self
+    }
+
+    /// Set whether targets are fetched from this mirror. Defaults to `true`.
+    pub fn target_content(mut self, target_content: bool) -> Self {
+        self.target_content = target_content;
+        self
+    }
+
+    /// The name of this mirror.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<D> fmt::Debug for Mirror<D>
+where
+    D: DataInterchange + Sync,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mirror")
+            .field("name", &self.name)
+            .field("priority", &self.priority)
+            .field("metadata_content", &self.metadata_content)
+            .field("target_content", &self.target_content)
+            .finish()
+    }
+}
+
+/// A builder to create a [`MirrorRepository`].
+#[derive(Debug)]
+pub struct MirrorRepositoryBuilder<D>
+where
+    D: DataInterchange + Sync,
+{
+    mirrors: Vec<Mirror<D>>,
+}
+
+impl<D> MirrorRepositoryBuilder<D>
+where
+    D: DataInterchange + Sync,
+{
+    /// Create a new builder without any mirrors.
+    pub fn new() -> Self {
+        MirrorRepositoryBuilder {
+            mirrors: Vec::new(),
+        }
+    }
+
+    /// Add a mirror.
+    pub fn mirror(mut self, mirror: Mirror<D>) -> Self {
+        self.mirrors.push(mirror);
+        self
+    }
+
+    /// Build a `MirrorRepository`. Fails unless at least one mirror serves metadata and at least
+    /// one mirror serves targets.
+    pub fn build(mut self) -> Result<MirrorRepository<D>> {
+        if !self.mirrors.iter().any(|m| m.metadata_content) {
+            return Err(Error::IllegalArgument("No mirror serves metadata".into()));
+        }
+        if !self.mirrors.iter().any(|m| m.target_content) {
+            return Err(Error::IllegalArgument("No mirror serves targets".into()));
+        }
+
+        // The sort is stable, so mirrors with the same priority keep the order they were added in.
+        self.mirrors.sort_by_key(|m| m.priority);
+
+        Ok(MirrorRepository {
+            mirrors: self.mirrors,
+        })
+    }
+}
+
+impl<D> Default for MirrorRepositoryBuilder<D>
+where
+    D: DataInterchange + Sync,
+{
+    fn default() -> Self {
+        MirrorRepositoryBuilder::new()
+    }
+}
+
+/// A repository that serves metadata and targets from the first of several mirrors that
+/// succeeds.
+///
+/// Mirrors are tried in order of their priority. If a mirror fails, the next one is tried, and if
+/// every mirror fails, the fetch fails with [`Error::MirrorsFailed`] listing why each mirror
+/// failed. If every mirror reports [`Error::NotFound`], the fetch fails with `Error::NotFound`
+/// instead, so that the client can still tell that the metadata or target does not exist.
+///
+/// Failing over only happens when a mirror fails to start serving a request. A mirror that serves
+/// bad data is caught when the client verifies it, which fails the fetch.
+#[derive(Debug)]
+pub struct MirrorRepository<D>
+where
+    D: DataInterchange + Sync,
+{
+    mirrors: Vec<Mirror<D>>,
+}
+
+impl<D> MirrorRepository<D>
+where
+    D: DataInterchange + Sync,
+{
+    /// The mirrors, in the order they are tried.
+    pub fn mirrors(&self) -> &[Mirror<D>] {
+        &self.mirrors
+    }
+
+    /// Run `fetch` against each mirror that `serves` the content, in order, until one succeeds.
+    async fn fetch_from_mirrors<'a, S, F>(
+        &'a self,
+        serves: S,
+        fetch: F,
+    ) -> Result<Box<dyn AsyncRead + Send + Unpin>>
+    where
+        S: Fn(&Mirror<D>) -> bool,
+        F: Fn(&'a Provider<D>) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>>,
+    {
+        let mut failures = Vec::new();
+        for mirror in self.mirrors.iter().filter(|m| serves(m)) {
+            match fetch(&mirror.repository).await {
+                Ok(read) => return Ok(read),
+                Err(err) => {
+                    if err != Error::NotFound {
+                        warn!("Mirror {} failed: {}", mirror.name, err);
+                    }
+                    failures.push((mirror.name.clone(), err));
+                }
+            }
+        }
+
+        if failures.iter().all(|(_, err)| *err == Error::NotFound) {
+            Err(Error::NotFound)
+        } else {
+            Err(Error::MirrorsFailed(failures))
+        }
+    }
+}
+
+impl<D> RepositoryProvider<D> for MirrorRepository<D>
+where
+    D: DataInterchange + Sync,
+{
+    fn fetch_metadata<'a>(
+        &'a self,
+        meta_path: &'a MetadataPath,
+        version: &'a MetadataVersion,
+        max_length: Option<usize>,
+        hash_data: Option<(&'static HashAlgorithm, HashValue)>,
+    ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+        self.fetch_from_mirrors(
+            |m| m.metadata_content,
+            move |repository| {
+                repository.fetch_metadata(meta_path, version, max_length, hash_data.clone())
+            },
+        )
+        .boxed()
+    }
+
+    fn fetch_target<'a>(
+        &'a self,
+        target_path: &'a TargetPath,
+        target_description: &'a TargetDescription,
+    ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+        self.fetch_from_mirrors(
+            |m| m.target_content,
+            move |repository| repository.fetch_target(target_path, target_description),
+        )
+        .boxed()
+    }
+
+    fn fetch_target_from<'a>(
+        &'a self,
+        target_path: &'a TargetPath,
+        target_description: &'a TargetDescription,
+        offset: u64,
+    ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+        self.fetch_from_mirrors(
+            |m| m.target_content,
+            move |repository| repository.fetch_target_from(target_path, target_description, offset),
+        )
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interchange::Json;
+    use crate::repository::{EphemeralRepository, RepositoryStorage};
+    use futures_executor::block_on;
+    use futures_util::future;
+    use futures_util::io::AsyncReadExt;
+
+    /// A mirror that can't be reached.
+    struct Offline;
+
+    impl RepositoryProvider<Json> for Offline {
+        fn fetch_metadata<'a>(
+            &'a self,
+            _: &'a MetadataPath,
+            _: &'a MetadataVersion,
+            _: Option<usize>,
+            _: Option<(&'static HashAlgorithm, HashValue)>,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            future::err(Error::Opaque("connection refused".into())).boxed()
+        }
+
+        fn fetch_target<'a>(
+            &'a self,
+            _: &'a TargetPath,
+            _: &'a TargetDescription,
+        ) -> BoxFuture<'a, Result<Box<dyn AsyncRead + Send + Unpin>>> {
+            future::err(Error::Opaque("connection refused".into())).boxed()
+        }
+    }
+
+    async fn repo_with_target(path: &TargetPath, data: &[u8]) -> EphemeralRepository<Json> {
+        let repo = EphemeralRepository::new();
+        repo.store_target(data, path).await.unwrap();
+        repo
+    }
+
+    async fn fetch(repo: &MirrorRepository<Json>, path: &TargetPath) -> Result<Vec<u8>> {
+        let description = TargetDescription::from_reader(&b""[..], &[HashAlgorithm::Sha256])?;
+        let mut read = repo.fetch_target(path, &description).await?;
+        let mut buf = Vec::new();
+        read.read_to_end(&mut buf).await?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn fails_over_to_next_mirror() {
+        block_on(async {
+            let path = TargetPath::new("foo".into()).unwrap();
+            let repo = MirrorRepositoryBuilder::new()
+                .mirror(Mirror::new("primary", Offline))
+                .mirror(Mirror::new(
+                    "backup",
+                    repo_with_target(&path, b"backup").await,
+                ))
+                .build()
+                .unwrap();
+
+            assert_eq!(fetch(&repo, &path).await, Ok(b"backup".to_vec()));
+        })
+    }
+
+    #[test]
+    fn tries_mirrors_by_priority() {
+        block_on(async {
+            let path = TargetPath::new("foo".into()).unwrap();
+            let repo = MirrorRepositoryBuilder::new()
+                .mirror(Mirror::new("far", repo_with_target(&path, b"far").await).priority(10))
+                .mirror(Mirror::new("near", repo_with_target(&path, b"near").await).priority(1))
+                .mirror(Mirror::new("nearby", repo_with_target(&path, b"nearby").await).priority(1))
+                .build()
+                .unwrap();
+
+            let names = repo.mirrors().iter().map(|m| m.name()).collect::<Vec<_>>();
+            assert_eq!(names, vec!["near", "nearby", "far"]);
+            assert_eq!(fetch(&repo, &path).await, Ok(b"near".to_vec()));
+        })
+    }
+
+    #[test]
+    fn skips_mirrors_without_content() {
+        block_on(async {
+            let path = TargetPath::new("foo".into()).unwrap();
+            let repo = MirrorRepositoryBuilder::new()
+                .mirror(
+                    Mirror::new("metadata", repo_with_target(&path, b"metadata").await)
+                        .target_content(false),
+                )
+                .mirror(Mirror::new(
+                    "targets",
+                    repo_with_target(&path, b"targets").await,
+                ))
+                .build()
+                .unwrap();
+
+            assert_eq!(fetch(&repo, &path).await, Ok(b"targets".to_vec()));
+        })
+    }
+
+    #[test]
+    fn reports_every_mirror_failure() {
+        block_on(async {
+            let path = TargetPath::new("foo".into()).unwrap();
+
+            // Not found anywhere is still not found.
+            let repo = MirrorRepositoryBuilder::new()
+                .mirror(Mirror::new("primary", EphemeralRepository::<Json>::new()))
+                .mirror(Mirror::new("backup", EphemeralRepository::<Json>::new()))
+                .build()
+                .unwrap();
+            assert_eq!(fetch(&repo, &path).await, Err(Error::NotFound));
+
+            let repo = MirrorRepositoryBuilder::new()
+                .mirror(Mirror::new("primary", Offline))
+                .mirror(Mirror::new("backup", EphemeralRepository::<Json>::new()))
+                .build()
+                .unwrap();
+            assert_eq!(
+                fetch(&repo, &path).await,
+                Err(Error::MirrorsFailed(vec![
+                    ("primary".into(), Error::Opaque("connection refused".into())),
+                    ("backup".into(), Error::NotFound),
+                ]))
+            );
+        })
+    }
+
+    #[test]
+    fn builder_requires_metadata_and_target_mirrors() {
+        assert!(MirrorRepositoryBuilder::<Json>::new().build().is_err());
+        assert!(MirrorRepositoryBuilder::new()
+            .mirror(Mirror::new("primary", Offline).metadata_content(false))
+            .build()
+            .is_err());
+        assert!(MirrorRepositoryBuilder::new()
+            .mirror(Mirror::new("primary", Offline).target_content(false))
+            .build()
+            .is_err());
+    }
+}
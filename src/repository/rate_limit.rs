@@ -0,0 +1,157 @@
+//! Limiting the bandwidth used to download metadata and targets.
+
+use futures_io::AsyncRead;
+use futures_util::future::{BoxFuture, FutureExt};
+use futures_util::ready;
+use parking_lot::Mutex;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::util::thread_sleep;
+use crate::Result;
+
+/// Limits the rate at which bytes are read from a repository.
+///
+/// Clones of a `RateLimiter` share the same budget, so one limiter can cap the combined bandwidth
+/// of every download from one or more repositories, such as concurrent target downloads from
+/// [`Client::fetch_targets`](crate::client::Client::fetch_targets).
+///
+/// Reads are allowed to borrow up to a second's worth of bytes ahead of the budget, after which
+/// further reads wait until the budget has caught up.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    bytes_per_second: u32,
+    /// When the bytes read so far will have been paid for.
+    paid_until: Arc<Mutex<Instant>>,
+}
+
+impl RateLimiter {
+    /// Create a new `RateLimiter` that allows reading `bytes_per_second` bytes per second.
+    pub fn new(bytes_per_second: u32) -> Result<Self> {
+        if bytes_per_second == 0 {
+            return Err(Error::IllegalArgument(
+                "The rate limit must be greater than zero".into(),
+            ));
+        }
+
+        Ok(RateLimiter {
+            bytes_per_second,
+            paid_until: Arc::new(Mutex::new(Instant::now())),
+        })
+    }
+
+    /// The number of bytes per second this limiter allows.
+    pub fn bytes_per_second(&self) -> u32 {
+        self.bytes_per_second
+    }
+
+    /// Wrap `read` so that reading from it is limited by this limiter.
+    pub(crate) fn throttle<R>(&self, read: R) -> Throttle<R> {
+        Throttle {
+            inner: read,
+            limiter: self.clone(),
+            delay: None,
+        }
+    }
+
+    /// How long to wait before reading more bytes.
+    fn wait_time(&self) -> Duration {
+        self.paid_until
+            .lock()
+            .saturating_duration_since(Instant::now())
+    }
+
+    /// Pay for having read `len` bytes.
+    fn consume(&self, len: usize) {
+        let cost = Duration::from_secs_f64(len as f64 / f64::from(self.bytes_per_second));
+        let mut paid_until = self.paid_until.lock();
+        *paid_until = (*paid_until).max(Instant::now()) + cost;
+    }
+}
+
+/// Wraps an `AsyncRead` to limit how fast it is read from.
+pub(crate) struct Throttle<R> {
+    inner: R,
+    limiter: RateLimiter,
+    delay: Option<BoxFuture<'static, ()>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Throttle<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            if let Some(delay) = self.delay.as_mut() {
+                ready!(delay.poll_unpin(cx));
+                self.delay = None;
+            }
+
+            // Another reader sharing the limiter may have used up the budget in the meantime.
+            let wait = self.limiter.wait_time();
+            if wait == Duration::from_secs(0) {
+                break;
+            }
+            self.delay = Some(thread_sleep(wait));
+        }
+
+        // Never read more than a second's worth of bytes at once, so bursts stay bounded.
+        let len = buf.len().min(self.limiter.bytes_per_second as usize);
+        let read_bytes = ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf[..len]))?;
+        self.limiter.consume(read_bytes);
+
+        Poll::Ready(Ok(read_bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_executor::block_on;
+    use futures_util::io::AsyncReadExt;
+
+    #[test]
+    fn throttle_limits_read_rate() {
+        block_on(async {
+            let limiter = RateLimiter::new(10_000).unwrap();
+            let data = vec![0; 15_000];
+
+            // The first second's worth is read right away, the rest has to wait for the budget.
+            let start = Instant::now();
+            let mut buf = Vec::new();
+            limiter
+                .throttle(&data[..])
+                .read_to_end(&mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, data);
+            assert!(start.elapsed() >= Duration::from_secs(1));
+        })
+    }
+
+    #[test]
+    fn clones_share_budget() {
+        block_on(async {
+            let limiter = RateLimiter::new(1000).unwrap();
+            let other = limiter.clone();
+
+            let mut buf = [0; 500];
+            limiter
+                .throttle(&[0; 500][..])
+                .read_exact(&mut buf)
+                .await
+                .unwrap();
+            assert!(other.wait_time() > Duration::from_millis(400));
+        })
+    }
+
+    #[test]
+    fn rejects_zero_rate() {
+        assert!(RateLimiter::new(0).is_err());
+    }
+}
@@ -1,27 +1,143 @@
 //! Components needed to verify TUF metadata and targets.
 
 use chrono::offset::Utc;
-use log::info;
-use std::collections::{HashMap, HashSet};
+use chrono::DateTime;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::io::{self, Read};
 use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use crate::crypto::PublicKey;
+use crate::crypto::{KeyId, KeyIdPrefix, PublicKey, SafeReader, SchemePolicy};
 use crate::error::Error;
 use crate::interchange::DataInterchange;
 use crate::metadata::{
-    Delegation, Delegations, Metadata, MetadataPath, Role, RootMetadata, SignedMetadata,
-    SnapshotMetadata, TargetDescription, TargetsMetadata, TimestampMetadata, VirtualTargetPath,
+    Delegation, DelegationGraph, Delegations, Metadata, MetadataDescription, MetadataPath, Role,
+    RootMetadata, SignedMetadata, SnapshotMetadata, TargetDescription, TargetsMetadata,
+    TimestampMetadata, VirtualTargetPath,
 };
 use crate::Result;
 
+mod audit;
+mod clock;
+mod state;
+
+pub use self::audit::{AuditEvent, AuditHook};
+pub use self::clock::{Clock, FixedClock, SystemClock};
+
+pub(crate) use self::audit::SharedAuditHook;
+pub(crate) use self::clock::SharedClock;
+use self::state::{TrustState, TrustStateRef};
+
+/// Options that weaken the security guarantees of TUF verification.
+///
+/// These exist for integration tests and for forensic analysis of old repositories, and must never
+/// be used to verify metadata in production. Every option is disabled by default, and can only be
+/// enabled when the `danger-zone` feature is enabled.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DangerZone {
+    ignore_expirations: bool,
+}
+
+impl DangerZone {
+    /// Whether the expiration of metadata is ignored.
+    pub fn ignores_expirations(&self) -> bool {
+        self.ignore_expirations
+    }
+
+    /// **DANGER**: Accept metadata even after it has expired. This removes the protection against
+    /// freeze attacks.
+    #[cfg(feature = "danger-zone")]
+    pub fn ignore_expirations(mut self, ignore: bool) -> Self {
+        self.ignore_expirations = ignore;
+        self
+    }
+}
+
+/// The highest version of each role's metadata that has been trusted.
+///
+/// A `Tuf` only remembers the versions of the metadata it currently holds. Persisting these
+/// versions and restoring them with [`Tuf::set_minimum_versions`] lets rollback attacks be
+/// detected across restarts, even if the local copy of the metadata was tampered with or lost.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedVersions {
+    root: u32,
+    timestamp: u32,
+    snapshot: u32,
+    targets: u32,
+    #[serde(default)]
+    delegations: BTreeMap<MetadataPath, u32>,
+}
+
+impl TrustedVersions {
+    /// The highest trusted root version.
+    pub fn root(&self) -> u32 {
+        self.root
+    }
+
+    /// The highest trusted timestamp version.
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// The highest trusted snapshot version.
+    pub fn snapshot(&self) -> u32 {
+        self.snapshot
+    }
+
+    /// The highest trusted top level targets version.
+    pub fn targets(&self) -> u32 {
+        self.targets
+    }
+
+    /// The highest trusted version of a delegated role, or 0 if it was never trusted.
+    pub fn delegation(&self, role: &MetadataPath) -> u32 {
+        self.delegations.get(role).cloned().unwrap_or(0)
+    }
+
+    /// An immutable reference to the highest trusted versions of all delegated roles.
+    pub fn delegations(&self) -> &BTreeMap<MetadataPath, u32> {
+        &self.delegations
+    }
+
+    /// Combine two sets of versions by taking the highest version of each role.
+    pub fn merge(&self, other: &TrustedVersions) -> TrustedVersions {
+        let mut delegations = self.delegations.clone();
+        for (role, version) in &other.delegations {
+            let entry = delegations.entry(role.clone()).or_insert(0);
+            *entry = (*entry).max(*version);
+        }
+
+        TrustedVersions {
+            root: self.root.max(other.root),
+            timestamp: self.timestamp.max(other.timestamp),
+            snapshot: self.snapshot.max(other.snapshot),
+            targets: self.targets.max(other.targets),
+            delegations,
+        }
+    }
+}
+
 /// Contains trusted TUF metadata and can be used to verify other metadata and targets.
-#[derive(Debug)]
+///
+/// Cloning a `Tuf` is cheap relative to the size of the metadata, since the verified delegated
+/// targets metadata is shared between the clones.
+#[derive(Clone, Debug)]
 pub struct Tuf<D: DataInterchange> {
     root: RootMetadata,
     snapshot: Option<SnapshotMetadata>,
-    targets: Option<TargetsMetadata>,
+    targets: Option<Arc<TargetsMetadata>>,
     timestamp: Option<TimestampMetadata>,
-    delegations: HashMap<MetadataPath, TargetsMetadata>,
+    delegations: HashMap<MetadataPath, Arc<TargetsMetadata>>,
+    minimum_versions: TrustedVersions,
+    root_history: Option<Vec<RootMetadata>>,
+    danger_zone: DangerZone,
+    scheme_policy: SchemePolicy,
+    clock: SharedClock,
+    audit_hook: SharedAuditHook,
     interchange: PhantomData<D>,
 }
 
@@ -67,6 +183,63 @@ impl<D: DataInterchange> Tuf<D> {
             targets: None,
             timestamp: None,
             delegations: HashMap::new(),
+            minimum_versions: TrustedVersions::default(),
+            root_history: None,
+            danger_zone: DangerZone::default(),
+            scheme_policy: SchemePolicy::default(),
+            clock: SharedClock::default(),
+            audit_hook: SharedAuditHook::default(),
+            interchange: PhantomData,
+        })
+    }
+
+    /// Encode the currently trusted metadata and minimum versions in a compact binary format, so
+    /// they can be saved locally and restored with [`Tuf::from_trust_state`] without fetching or
+    /// verifying the metadata again. This is not TUF wire metadata: signatures are omitted and the
+    /// encoding is only meant to be read back by this crate.
+    ///
+    /// The `DangerZone`, `SchemePolicy`, `Clock` and `AuditHook` settings are configuration rather
+    /// than state, and are not included. Neither is the root history kept by
+    /// [`Tuf::set_keep_root_history`].
+    pub fn to_trust_state(&self) -> Result<Vec<u8>> {
+        TrustStateRef {
+            root: &self.root,
+            timestamp: self.timestamp.as_ref(),
+            snapshot: self.snapshot.as_ref(),
+            targets: self.targets.as_deref(),
+            delegations: self
+                .delegations
+                .iter()
+                .map(|(path, targets)| (path, &**targets))
+                .collect(),
+            minimum_versions: &self.minimum_versions,
+        }
+        .encode()
+    }
+
+    /// Restore a [`Tuf`] from bytes produced by [`Tuf::to_trust_state`].
+    ///
+    /// **WARNING**: The metadata is not verified again, so the bytes must come from storage that
+    /// is as trusted as the `Tuf` that produced them. Expired metadata is restored as is, and will
+    /// be rejected or refreshed by the usual update methods.
+    pub fn from_trust_state(bytes: &[u8]) -> Result<Self> {
+        let state = TrustState::decode(bytes)?;
+        Ok(Tuf {
+            root: state.root,
+            snapshot: state.snapshot,
+            targets: state.targets.map(Arc::new),
+            timestamp: state.timestamp,
+            delegations: state
+                .delegations
+                .into_iter()
+                .map(|(path, targets)| (path, Arc::new(targets)))
+                .collect(),
+            minimum_versions: state.minimum_versions,
+            root_history: None,
+            danger_zone: DangerZone::default(),
+            scheme_policy: SchemePolicy::default(),
+            clock: SharedClock::default(),
+            audit_hook: SharedAuditHook::default(),
             interchange: PhantomData,
         })
     }
@@ -83,6 +256,11 @@ impl<D: DataInterchange> Tuf<D> {
 
     /// An immutable reference to the optional targets metadata.
     pub fn targets(&self) -> Option<&TargetsMetadata> {
+        self.targets.as_deref()
+    }
+
+    /// The targets metadata, shared rather than copied, so that it can be kept around cheaply.
+    pub(crate) fn shared_targets(&self) -> Option<&Arc<TargetsMetadata>> {
         self.targets.as_ref()
     }
 
@@ -92,10 +270,232 @@ impl<D: DataInterchange> Tuf<D> {
     }
 
     /// An immutable reference to the delegated metadata.
-    pub fn delegations(&self) -> &HashMap<MetadataPath, TargetsMetadata> {
+    pub fn delegations(&self) -> &HashMap<MetadataPath, Arc<TargetsMetadata>> {
         &self.delegations
     }
 
+    /// The earliest expiration time of all the trusted metadata. Once this time has passed, at
+    /// least one piece of metadata must be refreshed before targets can be verified again.
+    pub fn next_expiration(&self) -> DateTime<Utc> {
+        let mut expires = *self.root.expires();
+        let others = self
+            .snapshot
+            .as_ref()
+            .map(|m| m.expires())
+            .into_iter()
+            .chain(self.targets.as_ref().map(|m| m.expires()))
+            .chain(self.timestamp.as_ref().map(|m| m.expires()))
+            .chain(self.delegations.values().map(|m| m.expires()));
+
+        for other in others {
+            if *other < expires {
+                expires = *other;
+            }
+        }
+
+        expires
+    }
+
+    /// Look up a trusted key by a prefix of its hex encoded `KeyId`, similar to how git resolves
+    /// abbreviated commit hashes. Both the root keys and the keys of every trusted delegation are
+    /// searched, and the prefix is matched case insensitively.
+    ///
+    /// Returns `Error::NotFound` if no key matches, and `Error::AmbiguousKeyId` if more than one
+    /// distinct key does.
+    pub fn find_key_by_prefix(&self, prefix: &str) -> Result<&PublicKey> {
+        let delegation_keys = self
+            .targets
+            .iter()
+            .chain(self.delegations.values())
+            .filter_map(|targets| targets.delegations())
+            .flat_map(|delegations| delegations.keys());
+
+        find_by_key_id_prefix(prefix, self.root.keys().iter().chain(delegation_keys))
+    }
+
+    /// The versions of the metadata currently trusted, combined with the minimum versions set by
+    /// [`Tuf::set_minimum_versions`].
+    pub fn trusted_versions(&self) -> TrustedVersions {
+        let current = TrustedVersions {
+            root: self.root.version(),
+            timestamp: self.current_timestamp_version(),
+            snapshot: self.current_snapshot_version(),
+            targets: self.current_targets_version(),
+            delegations: self
+                .delegations
+                .iter()
+                .map(|(role, targets)| (role.clone(), targets.version()))
+                .collect(),
+        };
+        current.merge(&self.minimum_versions)
+    }
+
+    /// The minimum versions that metadata must have to be accepted.
+    pub fn minimum_versions(&self) -> &TrustedVersions {
+        &self.minimum_versions
+    }
+
+    /// Reject any metadata older than `versions` as a rollback attack, even when no metadata for
+    /// that role is currently trusted. Metadata at exactly the minimum version is still accepted.
+    pub fn set_minimum_versions(&mut self, versions: TrustedVersions) {
+        self.minimum_versions = versions;
+    }
+
+    /// Start or stop keeping the chain of verified root metadata. When enabled, every root that
+    /// [`Tuf::update_root`] accepts is appended to the history, starting from the root trusted at
+    /// the time this is called. Disabling it discards the history.
+    pub fn set_keep_root_history(&mut self, keep: bool) {
+        if !keep {
+            self.root_history = None;
+        } else if self.root_history.is_none() {
+            self.root_history = Some(vec![self.root.clone()]);
+        }
+    }
+
+    /// The chain of verified root metadata, oldest first and ending with the current root, or
+    /// `None` if the history is not being kept.
+    pub fn root_history(&self) -> Option<&[RootMetadata]> {
+        self.root_history.as_deref()
+    }
+
+    /// Verify a historical `signed_root` against the root history, and return it once verified.
+    ///
+    /// The root must be signed by a threshold of its own root keys and, unless it is the oldest
+    /// root in the history, by a threshold of the root keys of the version before it, exactly as
+    /// when it was first accepted. It must also be identical to the root of the same version in
+    /// the history. Expiration is not checked, since historical roots have usually expired.
+    ///
+    /// Returns `Error::IllegalArgument` if the history is not being kept, and `Error::NotFound` if
+    /// the history does not contain a root of that version.
+    pub fn verify_historical_root(
+        &self,
+        signed_root: &SignedMetadata<D, RootMetadata>,
+    ) -> Result<RootMetadata> {
+        let history = self
+            .root_history
+            .as_ref()
+            .ok_or_else(|| Error::IllegalArgument("The root history is not being kept".into()))?;
+
+        let version = signed_root.parse_version_untrusted()?;
+        let index = history
+            .iter()
+            .position(|root| root.version() == version)
+            .ok_or(Error::NotFound)?;
+
+        if index > 0 {
+            let previous = &history[index - 1];
+            signed_root.verify_with_policy(
+                previous.root().threshold(),
+                previous.keys().iter().filter_map(|(k, v)| {
+                    if previous.root().key_ids().contains(k) {
+                        Some(v)
+                    } else {
+                        None
+                    }
+                }),
+                &self.scheme_policy,
+            )?;
+        }
+
+        let expected = &history[index];
+        let verified = signed_root.verify_with_policy(
+            expected.root().threshold(),
+            expected.keys().iter().filter_map(|(k, v)| {
+                if expected.root().key_ids().contains(k) {
+                    Some(v)
+                } else {
+                    None
+                }
+            }),
+            &self.scheme_policy,
+        )?;
+
+        if &verified != expected {
+            return Err(Error::VerificationFailure(format!(
+                "Root metadata version {} does not match the trusted root history",
+                version
+            )));
+        }
+
+        Ok(verified)
+    }
+
+    /// The `DangerZone` options in effect.
+    pub fn danger_zone(&self) -> &DangerZone {
+        &self.danger_zone
+    }
+
+    /// Replace the `DangerZone` options in effect.
+    pub fn set_danger_zone(&mut self, danger_zone: DangerZone) {
+        self.danger_zone = danger_zone;
+    }
+
+    /// The `SchemePolicy` that signatures on new metadata must satisfy.
+    pub fn scheme_policy(&self) -> &SchemePolicy {
+        &self.scheme_policy
+    }
+
+    /// Replace the `SchemePolicy` that signatures on new metadata must satisfy. Metadata that is
+    /// already trusted is not checked again.
+    pub fn set_scheme_policy(&mut self, scheme_policy: SchemePolicy) {
+        self.scheme_policy = scheme_policy;
+    }
+
+    /// The current time according to the `Clock` in effect.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Replace the `Clock` that expirations are checked against. Defaults to [`SystemClock`].
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = SharedClock(clock);
+    }
+
+    /// Replace the `AuditHook` that is told about every root rotation, rejected metadata and
+    /// verified target, or remove it with `None`. Defaults to `None`.
+    pub fn set_audit_hook(&mut self, hook: Option<Arc<dyn AuditHook>>) {
+        self.audit_hook = SharedAuditHook(hook);
+    }
+
+    /// Report that metadata for `role` was rejected with `error`, and return the error.
+    fn reject(&self, role: MetadataPath, error: Error) -> Error {
+        self.audit_hook.emit(AuditEvent::MetadataRejected {
+            role,
+            error: error.clone(),
+        });
+        error
+    }
+
+    /// Report that `target` was verified, along with the role that describes it.
+    pub(crate) fn audit_target_verified(&self, target: &VirtualTargetPath) {
+        let role = match self
+            .safe_targets_ref()
+            .and_then(|targets| self.resolve_target(targets, target))
+        {
+            Ok((role, _)) => role,
+            Err(_) => return,
+        };
+        self.audit_hook.emit(AuditEvent::TargetVerified {
+            target: target.clone(),
+            role,
+        });
+    }
+
+    /// Returns `true` if metadata that expires at `expires` should be treated as expired.
+    pub(crate) fn is_expired(&self, expires: &DateTime<Utc>) -> bool {
+        !self.danger_zone.ignore_expirations && expires <= &self.now()
+    }
+
+    fn check_minimum_version(&self, role: &str, version: u32, minimum: u32) -> Result<()> {
+        if version < minimum {
+            return Err(Error::VerificationFailure(format!(
+                "Attempted to roll back {} metadata at previously trusted version {} to {}.",
+                role, minimum, version
+            )));
+        }
+        Ok(())
+    }
+
     fn current_timestamp_version(&self) -> u32 {
         self.timestamp.as_ref().map(|t| t.version()).unwrap_or(0)
     }
@@ -114,11 +514,26 @@ impl<D: DataInterchange> Tuf<D> {
 
     /// Verify and update the root metadata.
     pub fn update_root(&mut self, signed_root: SignedMetadata<D, RootMetadata>) -> Result<bool> {
+        let from_version = self.root.version();
+        match self.try_update_root(signed_root) {
+            Ok(true) => {
+                self.audit_hook.emit(AuditEvent::RootRotated {
+                    from_version,
+                    to_version: self.root.version(),
+                });
+                Ok(true)
+            }
+            Ok(false) => Ok(false),
+            Err(e) => Err(self.reject(MetadataPath::from_role(&Role::Root), e)),
+        }
+    }
+
+    fn try_update_root(&mut self, signed_root: SignedMetadata<D, RootMetadata>) -> Result<bool> {
         let verified = {
             let old_root = &self.root;
 
             // First, check that the new root was signed by the old root.
-            let new_root = signed_root.verify(
+            let new_root = signed_root.verify_with_policy(
                 old_root.root().threshold(),
                 old_root.keys().iter().filter_map(|(k, v)| {
                     if old_root.root().key_ids().contains(k) {
@@ -127,6 +542,7 @@ impl<D: DataInterchange> Tuf<D> {
                         None
                     }
                 }),
+                &self.scheme_policy,
             )?;
 
             // Next, make sure the new root has a higher version than the old root.
@@ -145,7 +561,7 @@ impl<D: DataInterchange> Tuf<D> {
             }
 
             // Finally, make sure the new root was signed by the keys in the new root.
-            signed_root.verify(
+            signed_root.verify_with_policy(
                 new_root.root().threshold(),
                 new_root.keys().iter().filter_map(|(k, v)| {
                     if new_root.root().key_ids().contains(k) {
@@ -154,11 +570,15 @@ impl<D: DataInterchange> Tuf<D> {
                         None
                     }
                 }),
+                &self.scheme_policy,
             )?
         };
 
         self.purge_metadata();
 
+        if let Some(history) = self.root_history.as_mut() {
+            history.push(verified.clone());
+        }
         self.root = verified;
         Ok(true)
     }
@@ -170,11 +590,22 @@ impl<D: DataInterchange> Tuf<D> {
         &mut self,
         signed_timestamp: SignedMetadata<D, TimestampMetadata>,
     ) -> Result<Option<&TimestampMetadata>> {
+        match self.try_update_timestamp(signed_timestamp) {
+            Ok(true) => Ok(self.timestamp.as_ref()),
+            Ok(false) => Ok(None),
+            Err(e) => Err(self.reject(MetadataPath::from_role(&Role::Timestamp), e)),
+        }
+    }
+
+    fn try_update_timestamp(
+        &mut self,
+        signed_timestamp: SignedMetadata<D, TimestampMetadata>,
+    ) -> Result<bool> {
         let verified = {
             let root = &self.root;
 
             // First, make sure the root signed the metadata.
-            let timestamp = signed_timestamp.verify(
+            let timestamp = signed_timestamp.verify_with_policy(
                 root.timestamp().threshold(),
                 root.keys().iter().filter_map(|(k, v)| {
                     if root.timestamp().key_ids().contains(k) {
@@ -183,13 +614,23 @@ impl<D: DataInterchange> Tuf<D> {
                         None
                     }
                 }),
+                &self.scheme_policy,
             )?;
 
             // Next, make sure the timestamp hasn't expired.
-            if timestamp.expires() <= &Utc::now() {
+            if self.is_expired(timestamp.expires()) {
                 return Err(Error::ExpiredMetadata(Role::Timestamp));
             }
 
+            // Next, make sure neither the root nor the timestamp are older than what was
+            // previously trusted.
+            self.check_minimum_version("root", root.version(), self.minimum_versions.root)?;
+            self.check_minimum_version(
+                "timestamp",
+                timestamp.version(),
+                self.minimum_versions.timestamp,
+            )?;
+
             // Next, make sure the new metadata has a higher version than the old metadata.
             let current_version = self.current_timestamp_version();
 
@@ -200,7 +641,7 @@ impl<D: DataInterchange> Tuf<D> {
                     timestamp.version()
                 )));
             } else if timestamp.version() == current_version {
-                return Ok(None);
+                return Ok(false);
             }
 
             if self.current_snapshot_version() != timestamp.snapshot().version() {
@@ -211,19 +652,33 @@ impl<D: DataInterchange> Tuf<D> {
         };
 
         self.timestamp = Some(verified);
-        Ok(self.timestamp.as_ref())
+        Ok(true)
     }
 
     /// Verify and update the snapshot metadata.
     pub fn update_snapshot(
         &mut self,
         signed_snapshot: SignedMetadata<D, SnapshotMetadata>,
+    ) -> Result<bool> {
+        self.try_update_snapshot(signed_snapshot)
+            .map_err(|e| self.reject(MetadataPath::from_role(&Role::Snapshot), e))
+    }
+
+    fn try_update_snapshot(
+        &mut self,
+        signed_snapshot: SignedMetadata<D, SnapshotMetadata>,
     ) -> Result<bool> {
         let verified = {
             let root = self.safe_root_ref()?;
             let timestamp = self.safe_timestamp_ref()?;
             let current_version = self.current_snapshot_version();
 
+            self.check_minimum_version(
+                "snapshot",
+                timestamp.snapshot().version(),
+                self.minimum_versions.snapshot,
+            )?;
+
             if timestamp.snapshot().version() < current_version {
                 return Err(Error::VerificationFailure(format!(
                     "Attempted to roll back snapshot metadata at version {} to {}.",
@@ -234,7 +689,7 @@ impl<D: DataInterchange> Tuf<D> {
                 return Ok(false);
             }
 
-            let snapshot = signed_snapshot.verify(
+            let snapshot = signed_snapshot.verify_with_policy(
                 root.snapshot().threshold(),
                 self.root.keys().iter().filter_map(|(k, v)| {
                     if root.snapshot().key_ids().contains(k) {
@@ -243,6 +698,7 @@ impl<D: DataInterchange> Tuf<D> {
                         None
                     }
                 }),
+                &self.scheme_policy,
             )?;
 
             if snapshot.version() != timestamp.snapshot().version() {
@@ -305,6 +761,14 @@ impl<D: DataInterchange> Tuf<D> {
     pub fn update_targets(
         &mut self,
         signed_targets: SignedMetadata<D, TargetsMetadata>,
+    ) -> Result<bool> {
+        self.try_update_targets(signed_targets)
+            .map_err(|e| self.reject(MetadataPath::from_role(&Role::Targets), e))
+    }
+
+    fn try_update_targets(
+        &mut self,
+        signed_targets: SignedMetadata<D, TargetsMetadata>,
     ) -> Result<bool> {
         let verified = {
             let root = self.safe_root_ref()?;
@@ -320,6 +784,12 @@ impl<D: DataInterchange> Tuf<D> {
 
             let current_version = self.current_targets_version();
 
+            self.check_minimum_version(
+                "targets",
+                targets_description.version(),
+                self.minimum_versions.targets,
+            )?;
+
             if targets_description.version() < current_version {
                 return Err(Error::VerificationFailure(format!(
                     "Attempted to roll back targets metadata at version {} to {}.",
@@ -330,7 +800,7 @@ impl<D: DataInterchange> Tuf<D> {
                 return Ok(false);
             }
 
-            let targets = signed_targets.verify(
+            let targets = signed_targets.verify_with_policy(
                 root.targets().threshold(),
                 root.keys().iter().filter_map(|(k, v)| {
                     if root.targets().key_ids().contains(k) {
@@ -339,6 +809,7 @@ impl<D: DataInterchange> Tuf<D> {
                         None
                     }
                 }),
+                &self.scheme_policy,
             )?;
 
             if targets.version() != targets_description.version() {
@@ -350,224 +821,615 @@ impl<D: DataInterchange> Tuf<D> {
                 )));
             }
 
-            if targets.expires() <= &Utc::now() {
+            if self.is_expired(targets.expires()) {
                 return Err(Error::ExpiredMetadata(Role::Snapshot));
             }
 
             targets
         };
 
-        self.targets = Some(verified);
+        self.targets = Some(Arc::new(verified));
         Ok(true)
     }
 
-    /// Find the signing keys and metadata for the delegation given by `role`, as seen from the
-    /// point of view of `parent_role`.
-    fn find_delegation(
-        &self,
-        parent_role: &MetadataPath,
-        role: &MetadataPath,
-    ) -> Option<(Vec<&PublicKey>, &Delegation)> {
-        // Find the parent TargetsMetadata that is expected to refer to `role`.
-        let parent = {
-            if parent_role == &MetadataPath::from_role(&Role::Targets) {
-                if let Some(targets) = self.targets() {
-                    targets
-                } else {
-                    return None;
-                }
-            } else {
-                if let Some(targets) = self.delegations.get(parent_role) {
-                    targets
-                } else {
-                    return None;
+    /// Find the delegation of `role`, and the delegations metadata that lists it, by walking the
+    /// delegations of the trusted targets metadata. Fails if no verified role delegates to `role`,
+    /// or if several do, since which of them to trust depends on the target being looked up.
+    fn find_delegation(&self, role: &MetadataPath) -> Result<(&Delegations, &Delegation)> {
+        let mut found = None;
+        if let Some(delegations) = self.targets.as_ref().and_then(|t| t.delegations()) {
+            let mut visited = HashSet::new();
+            let mut stack = vec![(delegations, delegations.roles().iter())];
+            while let Some((delegations, roles)) = stack.last_mut() {
+                let delegations = *delegations;
+                let delegation = match roles.next() {
+                    Some(d) => d,
+                    None => {
+                        let _ = stack.pop();
+                        continue;
+                    }
+                };
+
+                if delegation.role() == role {
+                    if found.is_some() {
+                        return Err(Error::VerificationFailure(format!(
+                            "The delegated role {:?} is delegated to by more than one role, use \
+                             Tuf::find_target_description to verify it for a particular target",
+                            role
+                        )));
+                    }
+                    found = Some((delegations, delegation));
                 }
-            }
-        };
 
-        // Only consider targets metadata that define delegations.
-        let delegations = match parent.delegations() {
-            Some(d) => d,
-            None => return None,
-        };
+                if !visited.insert(delegation.role()) {
+                    continue;
+                }
 
-        for delegation in delegations.roles() {
-            if delegation.role() != role {
-                continue;
+                if let Some(child) = self
+                    .delegations
+                    .get(delegation.role())
+                    .and_then(|t| t.delegations())
+                {
+                    stack.push((child, child.roles().iter()));
+                }
             }
-
-            // Filter the delegations keys to just the ones for this delegation.
-            let authorized_keys = delegations
-                .keys()
-                .iter()
-                .filter_map(|(k, v)| {
-                    if delegation.key_ids().contains(k) {
-                        Some(v)
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-
-            return Some((authorized_keys, delegation));
         }
 
-        None
+        found.ok_or_else(|| {
+            Error::VerificationFailure(format!(
+                "The delegated role {:?} is not known to the base \
+                 targets metadata or any known delegated targets metadata",
+                role
+            ))
+        })
     }
 
     /// Verify and update a delegation metadata.
+    ///
+    /// The delegation is verified with the keys and threshold of the verified targets metadata
+    /// that delegates to `role`. Since no target is involved, the paths the role is trusted for
+    /// and terminating delegations are not considered, so this fails if more than one verified
+    /// role delegates to `role`. Use [`Tuf::find_target_description`] to fetch and verify
+    /// delegations along the path to a particular target instead.
     pub fn update_delegation(
         &mut self,
-        parent_role: &MetadataPath,
         role: &MetadataPath,
         signed_delegation: SignedMetadata<D, TargetsMetadata>,
     ) -> Result<bool> {
         let verified = {
-            let _ = self.safe_root_ref()?;
-            let snapshot = self.safe_snapshot_ref()?;
-            let targets = self.safe_targets_ref()?;
-            if targets.delegations().is_none() {
-                return Err(Error::VerificationFailure(
-                    "Delegations not authorized".into(),
-                ));
-            };
-
-            let delegation_description = match snapshot.meta().get(role) {
-                Some(d) => d,
-                None => {
-                    return Err(Error::VerificationFailure(format!(
-                        "The degated role {:?} was not present in the snapshot metadata.",
-                        role
-                    )));
-                }
-            };
-
-            let current_version = self.current_delegation_version(role);
+            let (delegations, delegation) = self
+                .find_delegation(role)
+                .map_err(|e| self.reject(role.clone(), e))?;
 
-            if delegation_description.version() < current_version {
-                return Err(Error::VerificationFailure(format!(
-                    "Snapshot metadata did listed delegation {:?} version as {} but current\
-                     version is {}",
-                    role,
-                    delegation_description.version(),
-                    current_version
-                )));
+            match self.verify_delegation(delegations, delegation, signed_delegation)? {
+                Some(verified) => verified,
+                None => return Ok(false),
             }
+        };
 
-            // FIXME(#279) update_delegation trusts tuf::Client to provide too much information,
-            // making this difficult to verify as correct.
+        let _ = self.delegations.insert(role.clone(), Arc::new(verified));
 
-            let (keys, delegation) =
-                self.find_delegation(parent_role, role)
-                    .ok_or(Error::VerificationFailure(format!(
-                        "The delegated role {:?} is not known to the base \
-                        targets metadata or any known delegated targets metadata",
-                        role
-                    )))?;
-            let delegation = signed_delegation.verify(delegation.threshold(), keys)?;
+        Ok(true)
+    }
 
-            if current_version == delegation_description.version() {
-                return Ok(false);
-            }
+    /// Verify `signed_delegation` as the metadata of the role delegated by `delegation`, which is
+    /// listed in `delegations`. Returns `None` if the delegation is already at the version listed
+    /// in the snapshot metadata.
+    fn verify_delegation(
+        &self,
+        delegations: &Delegations,
+        delegation: &Delegation,
+        signed_delegation: SignedMetadata<D, TargetsMetadata>,
+    ) -> Result<Option<TargetsMetadata>> {
+        self.check_delegation(delegations, delegation, signed_delegation)
+            .map_err(|e| self.reject(delegation.role().clone(), e))
+    }
+
+    fn check_delegation(
+        &self,
+        delegations: &Delegations,
+        delegation: &Delegation,
+        signed_delegation: SignedMetadata<D, TargetsMetadata>,
+    ) -> Result<Option<TargetsMetadata>> {
+        let _ = self.safe_root_ref()?;
+        let snapshot = self.safe_snapshot_ref()?;
+        let _ = self.safe_targets_ref()?;
+        let role = delegation.role();
 
-            if delegation.version() != delegation_description.version() {
+        let delegation_description = match snapshot.meta().get(role) {
+            Some(d) => d,
+            None => {
                 return Err(Error::VerificationFailure(format!(
-                    "The snapshot metadata reported that the delegation {:?} should be at \
-                     version {} but version {} was found instead.",
-                    role,
-                    delegation_description.version(),
-                    delegation.version(),
+                    "The degated role {:?} was not present in the snapshot metadata.",
+                    role
                 )));
             }
+        };
 
-            if delegation.expires() <= &Utc::now() {
-                // TODO this needs to be chagned to accept a MetadataPath and not Role
-                return Err(Error::ExpiredMetadata(Role::Targets));
-            }
+        let current_version = self.current_delegation_version(role);
+
+        self.check_minimum_version(
+            &role.to_string(),
+            delegation_description.version(),
+            self.minimum_versions.delegation(role),
+        )?;
+
+        if delegation_description.version() < current_version {
+            return Err(Error::VerificationFailure(format!(
+                "Snapshot metadata did listed delegation {:?} version as {} but current\
+                 version is {}",
+                role,
+                delegation_description.version(),
+                current_version
+            )));
+        }
 
-            delegation
-        };
+        // Filter the delegations keys to just the ones for this delegation.
+        let keys = delegations
+            .keys()
+            .iter()
+            .filter_map(|(k, v)| {
+                if delegation.key_ids().contains(k) {
+                    Some(v)
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let verified = signed_delegation.verify_with_policy(
+            delegation.threshold(),
+            keys,
+            &self.scheme_policy,
+        )?;
+
+        if current_version == delegation_description.version() {
+            return Ok(None);
+        }
+
+        if verified.version() != delegation_description.version() {
+            return Err(Error::VerificationFailure(format!(
+                "The snapshot metadata reported that the delegation {:?} should be at \
+                 version {} but version {} was found instead.",
+                role,
+                delegation_description.version(),
+                verified.version(),
+            )));
+        }
 
-        let _ = self.delegations.insert(role.clone(), verified);
+        if self.is_expired(verified.expires()) {
+            // TODO this needs to be chagned to accept a MetadataPath and not Role
+            return Err(Error::ExpiredMetadata(Role::Targets));
+        }
 
-        Ok(true)
+        Ok(Some(verified))
     }
 
-    /// Get a reference to the description needed to verify the target defined by the given
-    /// `VirtualTargetPath`. Returns an `Error` if the target is not defined in the trusted
-    /// metadata. This may mean the target exists somewhere in the metadata, but the chain of trust
-    /// to that target may be invalid or incomplete.
-    pub fn target_description(&self, target_path: &VirtualTargetPath) -> Result<TargetDescription> {
+    /// Find the description of `target_path`, fetching and verifying delegated targets metadata
+    /// as it is needed.
+    ///
+    /// Delegations are walked depth first from the top-level targets metadata, in the order they
+    /// are listed, skipping delegations that are not trusted for `target_path`. When the walk
+    /// reaches a role whose metadata has not been verified at the version listed in the snapshot
+    /// metadata, `fetch` is called with the role and its description in the snapshot metadata,
+    /// and the returned metadata is verified with the keys and threshold of the delegation the
+    /// walk followed to reach it. Delegations more than `max_depth` deep are not followed.
+    ///
+    /// A role that cannot be fetched or verified is skipped, unless it was delegated to by a
+    /// terminating delegation, in which case the search ends with the error.
+    pub async fn find_target_description<F, Fut>(
+        &mut self,
+        target_path: &VirtualTargetPath,
+        max_depth: u32,
+        mut fetch: F,
+    ) -> Result<TargetDescription>
+    where
+        F: FnMut(&MetadataPath, &MetadataDescription) -> Fut,
+        Fut: Future<Output = Result<SignedMetadata<D, TargetsMetadata>>>,
+    {
         let _ = self.safe_root_ref()?;
-        let _ = self.safe_snapshot_ref()?;
-        let targets = self.safe_targets_ref()?;
+        let snapshot = self.safe_snapshot_ref()?.clone();
+        let targets = self.safe_shared_targets()?.clone();
 
         if let Some(d) = targets.targets().get(target_path) {
             return Ok(d.clone());
         }
 
-        fn lookup<D: DataInterchange>(
-            tuf: &Tuf<D>,
-            default_terminate: bool,
-            current_depth: u32,
-            target_path: &VirtualTargetPath,
-            delegations: &Delegations,
-            parents: &[HashSet<VirtualTargetPath>],
-            visited: &mut HashSet<MetadataPath>,
-        ) -> (bool, Option<TargetDescription>) {
-            for delegation in delegations.roles() {
-                if visited.contains(delegation.role()) {
-                    return (delegation.terminating(), None);
-                }
-                let _ = visited.insert(delegation.role().clone());
-
-                let mut new_parents = parents.to_owned();
-                new_parents.push(delegation.paths().clone());
-
-                if current_depth > 0 && !target_path.matches_chain(&parents) {
-                    return (delegation.terminating(), None);
-                }
-
-                let targets = match tuf.delegations.get(delegation.role()) {
-                    Some(t) => t,
-                    None => return (delegation.terminating(), None),
-                };
-
-                if targets.expires() <= &Utc::now() {
-                    return (delegation.terminating(), None);
+        // The frames own their metadata, so that delegations can be updated during the walk.
+        let mut visited = HashSet::new();
+        let mut stack = vec![DelegationFrame {
+            targets,
+            chain: vec![],
+            terminating: false,
+            next_delegation: 0,
+        }];
+
+        while let Some(frame) = stack.last_mut() {
+            let delegation = match frame
+                .targets
+                .delegations()
+                .and_then(|d| d.roles().get(frame.next_delegation))
+            {
+                Some(d) => d.clone(),
+                None => {
+                    // Every delegation of this frame has been searched.
+                    if frame.terminating {
+                        return Err(Error::NotFound);
+                    }
+                    let _ = stack.pop();
+                    continue;
                 }
+            };
+            frame.next_delegation += 1;
 
-                if let Some(d) = targets.targets().get(target_path) {
-                    return (delegation.terminating(), Some(d.clone()));
-                }
+            let mut chain = frame.chain.clone();
+            chain.push(delegation.clone());
+            if !matches_chain(target_path, &chain.iter().collect::<Vec<_>>()) {
+                continue;
+            }
 
-                if let Some(d) = targets.delegations() {
-                    let mut new_parents = parents.to_vec();
-                    new_parents.push(delegation.paths().clone());
-                    let (term, res) = lookup(
-                        tuf,
-                        delegation.terminating(),
-                        current_depth + 1,
-                        target_path,
-                        d,
-                        &new_parents,
-                        visited,
-                    );
-                    if term {
-                        return (true, res);
-                    } else if res.is_some() {
-                        return (term, res);
+            let role = delegation.role();
+            let parent = frame.targets.clone();
+            let depth = stack.len() as u32;
+            if depth > max_depth {
+                warn!(
+                    "Walking the delegation graph would have exceeded the configured max \
+                     depth: {}",
+                    max_depth
+                );
+            } else if visited.insert(role.clone()) {
+                match self
+                    .fetch_delegation(&snapshot, &parent, &delegation, &mut fetch)
+                    .await
+                {
+                    Ok(targets) => {
+                        if let Some(d) = targets.targets().get(target_path) {
+                            return Ok(d.clone());
+                        }
+
+                        stack.push(DelegationFrame {
+                            targets,
+                            chain,
+                            terminating: delegation.terminating(),
+                            next_delegation: 0,
+                        });
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Failed to update delegated role {:?}: {:?}", role, e);
+                        if delegation.terminating() {
+                            return Err(e);
+                        }
                     }
                 }
             }
-            (default_terminate, None)
+
+            // A terminating delegation that is trusted for the target ends the search, even if
+            // its metadata could not be searched.
+            if delegation.terminating() {
+                return Err(Error::NotFound);
+            }
         }
 
-        match targets.delegations() {
-            Some(d) => {
+        Err(Error::NotFound)
+    }
+
+    /// Fetch and verify every delegated targets role reachable from the top-level targets
+    /// metadata, so that [`Tuf::all_targets`] covers the whole repository.
+    ///
+    /// Delegations are walked like in [`Tuf::find_target_description`], except that every
+    /// delegation is followed regardless of the paths it is trusted for. Roles that cannot be
+    /// fetched or verified are skipped, and delegations more than `max_depth` deep are not
+    /// followed.
+    pub async fn fetch_all_delegations<F, Fut>(&mut self, max_depth: u32, fetch: F) -> Result<()>
+    where
+        F: FnMut(&MetadataPath, &MetadataDescription) -> Fut,
+        Fut: Future<Output = Result<SignedMetadata<D, TargetsMetadata>>>,
+    {
+        let _ = self.walk_delegations(max_depth, |_| true, fetch).await?;
+        Ok(())
+    }
+
+    /// Fetch and verify the delegated targets roles named in `roles` ahead of time, so that
+    /// looking up the targets they list needs no further network access until the snapshot
+    /// metadata lists a new version of them.
+    ///
+    /// Delegations are walked like in [`Tuf::fetch_all_delegations`], but only delegations to
+    /// roles in `roles` are followed, so a role delegated by another delegated role is only
+    /// reached if that role is named too. Returns an error if any of `roles` cannot be reached,
+    /// fetched, or verified, after fetching the rest of them.
+    pub async fn fetch_delegations<F, Fut>(
+        &mut self,
+        roles: &[MetadataPath],
+        max_depth: u32,
+        fetch: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&MetadataPath, &MetadataDescription) -> Fut,
+        Fut: Future<Output = Result<SignedMetadata<D, TargetsMetadata>>>,
+    {
+        let (visited, mut failures) = self
+            .walk_delegations(max_depth, |role| roles.contains(role), fetch)
+            .await?;
+        if !failures.is_empty() {
+            let (_, e) = failures.remove(0);
+            return Err(e);
+        }
+
+        match roles.iter().find(|role| !visited.contains(*role)) {
+            Some(role) => Err(Error::VerificationFailure(format!(
+                "The delegated role {:?} is not delegated by the top-level targets metadata or \
+                 any of the other roles to fetch",
+                role
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Walk the delegations from the top-level targets metadata depth first, fetching and
+    /// verifying each role for which `follow` returns `true`. Returns the roles that were reached,
+    /// and those that could not be fetched or verified along with why.
+    async fn walk_delegations<P, F, Fut>(
+        &mut self,
+        max_depth: u32,
+        follow: P,
+        mut fetch: F,
+    ) -> Result<(HashSet<MetadataPath>, Vec<(MetadataPath, Error)>)>
+    where
+        P: Fn(&MetadataPath) -> bool,
+        F: FnMut(&MetadataPath, &MetadataDescription) -> Fut,
+        Fut: Future<Output = Result<SignedMetadata<D, TargetsMetadata>>>,
+    {
+        let _ = self.safe_root_ref()?;
+        let snapshot = self.safe_snapshot_ref()?.clone();
+        let targets = self.safe_shared_targets()?.clone();
+
+        let mut visited = HashSet::new();
+        let mut failures = Vec::new();
+        let mut stack = vec![(targets, 0)];
+        while let Some((parent, depth)) = stack.pop() {
+            let delegations = match parent.delegations() {
+                Some(d) => d.roles().clone(),
+                None => continue,
+            };
+
+            for delegation in &delegations {
+                let role = delegation.role();
+                if !follow(role) {
+                    continue;
+                }
+                if depth + 1 > max_depth {
+                    warn!(
+                        "Walking the delegation graph would have exceeded the configured max \
+                         depth: {}",
+                        max_depth
+                    );
+                    continue;
+                }
+                if !visited.insert(role.clone()) {
+                    continue;
+                }
+
+                match self
+                    .fetch_delegation(&snapshot, &parent, delegation, &mut fetch)
+                    .await
+                {
+                    Ok(targets) => stack.push((targets, depth + 1)),
+                    Err(e) => {
+                        warn!("Failed to update delegated role {:?}: {:?}", role, e);
+                        failures.push((role.clone(), e));
+                    }
+                }
+            }
+        }
+
+        Ok((visited, failures))
+    }
+
+    /// The verified metadata of the role delegated by `delegation`, which is listed in the
+    /// targets metadata `parent`. The metadata is requested from `fetch` unless it has already
+    /// been verified at the version listed in `snapshot`.
+    async fn fetch_delegation<F, Fut>(
+        &mut self,
+        snapshot: &SnapshotMetadata,
+        parent: &TargetsMetadata,
+        delegation: &Delegation,
+        fetch: &mut F,
+    ) -> Result<Arc<TargetsMetadata>>
+    where
+        F: FnMut(&MetadataPath, &MetadataDescription) -> Fut,
+        Fut: Future<Output = Result<SignedMetadata<D, TargetsMetadata>>>,
+    {
+        let role = delegation.role();
+        let description = snapshot.meta().get(role).ok_or(Error::NotFound)?;
+        let delegations = parent
+            .delegations()
+            .ok_or_else(|| Error::VerificationFailure("Delegations not authorized".into()))?;
+
+        if self.current_delegation_version(role) != description.version() {
+            let signed_delegation = fetch(role, description).await?;
+            if let Some(verified) =
+                self.verify_delegation(delegations, delegation, signed_delegation)?
+            {
+                let _ = self.delegations.insert(role.clone(), Arc::new(verified));
+            }
+        }
+
+        match self.delegations.get(role) {
+            Some(targets) if self.is_expired(targets.expires()) => {
+                Err(Error::ExpiredMetadata(Role::Targets))
+            }
+            Some(targets) => Ok(targets.clone()),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    /// Get a reference to the description needed to verify the target defined by the given
+    /// `VirtualTargetPath`. Returns an `Error` if the target is not defined in the trusted
+    /// metadata. This may mean the target exists somewhere in the metadata, but the chain of trust
+    /// to that target may be invalid or incomplete.
+    pub fn target_description(&self, target_path: &VirtualTargetPath) -> Result<TargetDescription> {
+        let _ = self.safe_root_ref()?;
+        let _ = self.safe_snapshot_ref()?;
+        let targets = self.safe_targets_ref()?;
+
+        self.resolve_target(targets, target_path)
+            .map(|(_, description)| description.clone())
+    }
+
+    /// Check that `read` holds the target at `target_path`, by comparing its length and every
+    /// supported hash with the trusted [`Tuf::target_description`] of the target.
+    ///
+    /// This does not need any network access, so it can be used to check a target that was
+    /// downloaded earlier, such as when it is installed. The trusted metadata must not have
+    /// expired, and any delegations needed to describe the target must have been verified
+    /// already.
+    pub fn verify_target<R: Read>(&self, target_path: &VirtualTargetPath, read: R) -> Result<()> {
+        let description = self.target_description(target_path)?;
+
+        let mut reader = SafeReader::new(read, description.length(), description.hashes())?;
+        match io::copy(&mut reader, &mut io::sink()) {
+            Ok(length) if length == description.length() => {
+                self.audit_target_verified(target_path);
+                Ok(())
+            }
+            Ok(length) => Err(Error::VerificationFailure(format!(
+                "Target {:?} should be {} bytes long but was {} bytes long",
+                target_path.value(),
+                description.length(),
+                length
+            ))),
+            Err(ref e)
+                if e.kind() == io::ErrorKind::InvalidData
+                    || e.kind() == io::ErrorKind::FileTooLarge =>
+            {
+                Err(Error::VerificationFailure(format!(
+                    "Target {:?} does not match its trusted description: {}",
+                    target_path.value(),
+                    e
+                )))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Iterate over every target that [`Tuf::target_description`] would return a description for,
+    /// in no particular order.
+    ///
+    /// This covers the top-level targets metadata and every delegated targets role that has been
+    /// verified so far. A target listed by a delegated role is only included if the chain of
+    /// delegations to that role trusts it for that path, and if no delegation with a higher
+    /// priority (including a terminating one) takes precedence. Delegated roles that have not been
+    /// fetched yet are not consulted.
+    pub fn all_targets(
+        &self,
+    ) -> Result<impl Iterator<Item = (VirtualTargetPath, &TargetDescription)> + '_> {
+        let _ = self.safe_root_ref()?;
+        let _ = self.safe_snapshot_ref()?;
+        let targets = self.safe_targets_ref()?;
+
+        let mut seen = HashSet::new();
+        Ok(targets
+            .targets()
+            .keys()
+            .chain(self.delegations.values().flat_map(|t| t.targets().keys()))
+            .filter(move |path| seen.insert(*path))
+            .filter_map(move |path| {
+                self.resolve_target(targets, path)
+                    .ok()
+                    .map(|(_, description)| (path.clone(), description))
+            }))
+    }
+
+    /// The graph of delegations from the trusted top-level targets metadata through every
+    /// delegated targets role verified so far. Roles that have not been fetched yet are included
+    /// but not walked.
+    pub fn delegation_graph(&self) -> Result<DelegationGraph> {
+        let targets = self.safe_targets_ref()?;
+        Ok(DelegationGraph::new(targets, |role| {
+            self.delegations.get(role).map(|t| &**t)
+        }))
+    }
+
+    /// Find the description of `target_path`, starting from the trusted top-level `targets`, along
+    /// with the role that lists it.
+    fn resolve_target<'a>(
+        &'a self,
+        targets: &'a TargetsMetadata,
+        target_path: &VirtualTargetPath,
+    ) -> Result<(MetadataPath, &'a TargetDescription)> {
+        if let Some(d) = targets.targets().get(target_path) {
+            return Ok((MetadataPath::from_role(&Role::Targets), d));
+        }
+
+        fn lookup<'a, D: DataInterchange>(
+            tuf: &'a Tuf<D>,
+            default_terminate: bool,
+            current_depth: u32,
+            target_path: &VirtualTargetPath,
+            delegations: &'a Delegations,
+            parents: &[&'a Delegation],
+            visited: &mut HashSet<MetadataPath>,
+        ) -> (bool, Option<(&'a MetadataPath, &'a TargetDescription)>) {
+            for delegation in delegations.roles() {
+                if visited.contains(delegation.role()) {
+                    return (delegation.terminating(), None);
+                }
+                let _ = visited.insert(delegation.role().clone());
+
+                let mut new_parents = parents.to_owned();
+                new_parents.push(delegation);
+
+                if current_depth > 0 && !matches_chain(target_path, parents) {
+                    return (delegation.terminating(), None);
+                }
+
+                let targets = match tuf.delegations.get(delegation.role()) {
+                    Some(t) => t,
+                    None => return (delegation.terminating(), None),
+                };
+
+                if tuf.is_expired(targets.expires()) {
+                    return (delegation.terminating(), None);
+                }
+
+                if matches_chain(target_path, &new_parents) {
+                    if let Some(d) = targets.targets().get(target_path) {
+                        return (delegation.terminating(), Some((delegation.role(), d)));
+                    }
+                }
+
+                if let Some(d) = targets.delegations() {
+                    let (term, res) = lookup(
+                        tuf,
+                        delegation.terminating(),
+                        current_depth + 1,
+                        target_path,
+                        d,
+                        &new_parents,
+                        visited,
+                    );
+                    if term {
+                        return (true, res);
+                    } else if res.is_some() {
+                        return (term, res);
+                    }
+                }
+
+                // A terminating delegation that is trusted for the target ends the search, even
+                // if neither it nor its delegations list the target.
+                if delegation.terminating() && matches_chain(target_path, &new_parents) {
+                    return (true, None);
+                }
+            }
+            (default_terminate, None)
+        }
+
+        match targets.delegations() {
+            Some(d) => {
                 let mut visited = HashSet::new();
                 lookup(self, false, 0, target_path, d, &[], &mut visited)
                     .1
+                    .map(|(role, description)| (role.clone(), description))
                     .ok_or_else(|| Error::TargetUnavailable)
             }
             None => Err(Error::TargetUnavailable),
@@ -583,7 +1445,7 @@ impl<D: DataInterchange> Tuf<D> {
 
     fn safe_root_ref(&self) -> Result<&RootMetadata> {
         let root = &self.root;
-        if root.expires() <= &Utc::now() {
+        if self.is_expired(root.expires()) {
             return Err(Error::ExpiredMetadata(Role::Root));
         }
         Ok(&root)
@@ -592,7 +1454,7 @@ impl<D: DataInterchange> Tuf<D> {
     fn safe_snapshot_ref(&self) -> Result<&SnapshotMetadata> {
         match self.snapshot {
             Some(ref snapshot) => {
-                if snapshot.expires() <= &Utc::now() {
+                if self.is_expired(snapshot.expires()) {
                     return Err(Error::ExpiredMetadata(Role::Snapshot));
                 }
                 Ok(snapshot)
@@ -602,9 +1464,13 @@ impl<D: DataInterchange> Tuf<D> {
     }
 
     fn safe_targets_ref(&self) -> Result<&TargetsMetadata> {
+        self.safe_shared_targets().map(|targets| &**targets)
+    }
+
+    fn safe_shared_targets(&self) -> Result<&Arc<TargetsMetadata>> {
         match self.targets {
             Some(ref targets) => {
-                if targets.expires() <= &Utc::now() {
+                if self.is_expired(targets.expires()) {
                     return Err(Error::ExpiredMetadata(Role::Targets));
                 }
                 Ok(targets)
@@ -615,7 +1481,7 @@ impl<D: DataInterchange> Tuf<D> {
     fn safe_timestamp_ref(&self) -> Result<&TimestampMetadata> {
         match self.timestamp {
             Some(ref timestamp) => {
-                if timestamp.expires() <= &Utc::now() {
+                if self.is_expired(timestamp.expires()) {
                     return Err(Error::ExpiredMetadata(Role::Timestamp));
                 }
                 Ok(timestamp)
@@ -625,18 +1491,75 @@ impl<D: DataInterchange> Tuf<D> {
     }
 }
 
+/// Whether `target_path` is trusted at the end of a chain of delegations. Each delegation must
+/// match the target, and the paths of each delegation by path must also be within the paths of
+/// the delegations by path before it.
+fn matches_chain(target_path: &VirtualTargetPath, chain: &[&Delegation]) -> bool {
+    let paths = chain
+        .iter()
+        .filter(|delegation| !delegation.paths().is_empty())
+        .map(|delegation| delegation.paths().clone())
+        .collect::<Vec<_>>();
+
+    !chain.is_empty()
+        && chain
+            .iter()
+            .all(|delegation| delegation.matches(target_path))
+        && (paths.is_empty() || target_path.matches_chain(&paths))
+}
+
+/// A targets metadata being searched by [`Tuf::find_target_description`].
+struct DelegationFrame {
+    targets: Arc<TargetsMetadata>,
+    /// The delegations followed to reach `targets`.
+    chain: Vec<Delegation>,
+    terminating: bool,
+    next_delegation: usize,
+}
+
+fn find_by_key_id_prefix<'a, I, T>(prefix: &str, candidates: I) -> Result<&'a T>
+where
+    I: IntoIterator<Item = (&'a KeyId, &'a T)>,
+{
+    let key_id_prefix = KeyIdPrefix::from_str(prefix)?;
+
+    // The same key can be trusted by several roles, so collect into a map to only count it once.
+    let matches = candidates
+        .into_iter()
+        .filter(|(key_id, _)| key_id_prefix.matches(key_id))
+        .collect::<BTreeMap<_, _>>();
+
+    if matches.len() > 1 {
+        return Err(Error::AmbiguousKeyId {
+            prefix: prefix.into(),
+            candidates: matches.keys().map(|k| (*k).clone()).collect(),
+        });
+    }
+
+    matches
+        .into_iter()
+        .next()
+        .map(|(_, value)| value)
+        .ok_or(Error::NotFound)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
     use crate::interchange::Json;
     use crate::metadata::{
-        RootMetadataBuilder, SnapshotMetadataBuilder, TargetsMetadataBuilder,
+        HashedBinsBuilder, RootMetadataBuilder, SnapshotMetadataBuilder, TargetsMetadataBuilder,
         TimestampMetadataBuilder,
     };
+    use chrono::Duration;
+    use futures_executor::block_on;
+    use futures_util::future;
     use lazy_static::lazy_static;
+    use maplit::{hashmap, hashset};
     use matches::assert_matches;
     use std::iter::once;
+    use std::str::FromStr;
 
     lazy_static! {
         static ref KEYS: Vec<PrivateKey> = {
@@ -717,6 +1640,83 @@ mod test {
         assert_eq!(tuf.update_root(root), Ok(false));
     }
 
+    #[test]
+    fn root_history() {
+        let root_signed_by = |version: u32, key: usize, signers: &[usize]| {
+            let mut root = RootMetadataBuilder::new()
+                .version(version)
+                .root_key(KEYS[key].public().clone())
+                .snapshot_key(KEYS[key].public().clone())
+                .targets_key(KEYS[key].public().clone())
+                .timestamp_key(KEYS[key].public().clone())
+                .signed::<Json>(&KEYS[signers[0]])
+                .unwrap();
+            for signer in &signers[1..] {
+                root.add_signature(&KEYS[*signer]).unwrap();
+            }
+            root
+        };
+        let root1 = root_signed_by(1, 0, &[0]);
+        let root2 = root_signed_by(2, 1, &[0, 1]);
+        let root3 = root_signed_by(3, 2, &[1, 2]);
+
+        let mut tuf = Tuf::from_trusted_root(root1.clone()).unwrap();
+        assert_eq!(tuf.root_history(), None);
+        assert_matches!(
+            tuf.verify_historical_root(&root1),
+            Err(Error::IllegalArgument(_))
+        );
+
+        tuf.set_keep_root_history(true);
+        assert_eq!(tuf.update_root(root2.clone()), Ok(true));
+        assert_eq!(tuf.update_root(root3.clone()), Ok(true));
+
+        let versions = tuf
+            .root_history()
+            .unwrap()
+            .iter()
+            .map(|root| root.version())
+            .collect::<Vec<_>>();
+        assert_eq!(versions, vec![1, 2, 3]);
+
+        for root in &[&root1, &root2, &root3] {
+            assert_eq!(
+                tuf.verify_historical_root(root),
+                Ok(root.assume_valid().unwrap())
+            );
+        }
+
+        // Version 2 without the signature of the version 1 keys.
+        assert_matches!(
+            tuf.verify_historical_root(&root_signed_by(2, 1, &[1])),
+            Err(Error::VerificationFailure(_))
+        );
+
+        // A properly signed version 2 that is not the one that was trusted.
+        let mut forged = RootMetadataBuilder::new()
+            .version(2)
+            .consistent_snapshot(true)
+            .root_key(KEYS[1].public().clone())
+            .snapshot_key(KEYS[1].public().clone())
+            .targets_key(KEYS[1].public().clone())
+            .timestamp_key(KEYS[1].public().clone())
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+        forged.add_signature(&KEYS[0]).unwrap();
+        assert_matches!(
+            tuf.verify_historical_root(&forged),
+            Err(Error::VerificationFailure(_))
+        );
+
+        assert_matches!(
+            tuf.verify_historical_root(&root_signed_by(4, 2, &[2])),
+            Err(Error::NotFound)
+        );
+
+        tuf.set_keep_root_history(false);
+        assert_eq!(tuf.root_history(), None);
+    }
+
     #[test]
     fn no_cross_sign_root_rotation() {
         let root = RootMetadataBuilder::new()
@@ -773,7 +1773,7 @@ mod test {
     }
 
     #[test]
-    fn bad_timestamp_update_wrong_key() {
+    fn expired_timestamp_update() {
         let root = RootMetadataBuilder::new()
             .root_key(KEYS[0].public().clone())
             .snapshot_key(KEYS[1].public().clone())
@@ -783,6 +1783,7 @@ mod test {
             .unwrap();
 
         let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        assert!(!tuf.danger_zone().ignores_expirations());
 
         let snapshot = SnapshotMetadataBuilder::new()
             .signed::<Json>(&KEYS[1])
@@ -791,132 +1792,315 @@ mod test {
         let timestamp =
             TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
                 .unwrap()
-                // sign it with the root key
-                .signed::<Json>(&KEYS[0])
+                .expires(Utc::now() - Duration::days(1))
+                .allow_expired(true)
+                .signed::<Json>(&KEYS[1])
                 .unwrap();
 
-        assert!(tuf.update_timestamp(timestamp).is_err())
+        assert_eq!(
+            tuf.update_timestamp(timestamp.clone()),
+            Err(Error::ExpiredMetadata(Role::Timestamp))
+        );
+
+        #[cfg(feature = "danger-zone")]
+        {
+            tuf.set_danger_zone(DangerZone::default().ignore_expirations(true));
+            assert_matches!(tuf.update_timestamp(timestamp), Ok(Some(_)));
+        }
     }
 
     #[test]
-    fn good_snapshot_update() {
+    fn expiration_checked_against_clock() {
         let root = RootMetadataBuilder::new()
             .root_key(KEYS[0].public().clone())
             .snapshot_key(KEYS[1].public().clone())
-            .targets_key(KEYS[2].public().clone())
-            .timestamp_key(KEYS[2].public().clone())
+            .targets_key(KEYS[1].public().clone())
+            .timestamp_key(KEYS[1].public().clone())
             .signed::<Json>(&KEYS[0])
             .unwrap();
 
         let mut tuf = Tuf::from_trusted_root(root).unwrap();
 
-        let snapshot = SnapshotMetadataBuilder::new().signed(&KEYS[1]).unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
 
+        let expires = Utc::now() - Duration::days(1);
         let timestamp =
             TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
                 .unwrap()
-                .signed::<Json>(&KEYS[2])
+                .expires(expires)
+                .allow_expired(true)
+                .signed::<Json>(&KEYS[1])
                 .unwrap();
 
-        tuf.update_timestamp(timestamp).unwrap();
-
-        assert_eq!(tuf.update_snapshot(snapshot.clone()), Ok(true));
+        // Already expired by the system clock, but not yet at the time reported by the clock.
+        tuf.set_clock(Arc::new(FixedClock::new(expires - Duration::hours(1))));
+        assert_matches!(tuf.update_timestamp(timestamp.clone()), Ok(Some(_)));
+        assert!(!tuf.is_expired(&expires));
 
-        // second update should do nothing
-        assert_eq!(tuf.update_snapshot(snapshot), Ok(false));
+        tuf.set_clock(Arc::new(FixedClock::new(expires + Duration::hours(1))));
+        assert!(tuf.is_expired(&expires));
+        assert_eq!(
+            tuf.update_timestamp(timestamp),
+            Err(Error::ExpiredMetadata(Role::Timestamp))
+        );
     }
 
     #[test]
-    fn bad_snapshot_update_wrong_key() {
+    fn next_expiration_tracks_earliest_metadata() {
         let root = RootMetadataBuilder::new()
             .root_key(KEYS[0].public().clone())
             .snapshot_key(KEYS[1].public().clone())
-            .targets_key(KEYS[2].public().clone())
-            .timestamp_key(KEYS[2].public().clone())
+            .targets_key(KEYS[1].public().clone())
+            .timestamp_key(KEYS[1].public().clone())
             .signed::<Json>(&KEYS[0])
             .unwrap();
 
         let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        assert_eq!(tuf.next_expiration(), *tuf.root().expires());
 
         let snapshot = SnapshotMetadataBuilder::new()
-            .signed::<Json>(&KEYS[2])
+            .signed::<Json>(&KEYS[1])
             .unwrap();
 
         let timestamp =
             TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
                 .unwrap()
-                // sign it with the targets key
-                .signed::<Json>(&KEYS[2])
+                .signed::<Json>(&KEYS[1])
                 .unwrap();
+        let parsed_timestamp = timestamp.assume_valid().unwrap();
 
         tuf.update_timestamp(timestamp).unwrap();
-
-        assert!(tuf.update_snapshot(snapshot).is_err());
+        assert_eq!(tuf.next_expiration(), *parsed_timestamp.expires());
     }
 
     #[test]
-    fn bad_snapshot_update_wrong_version() {
+    fn find_key_by_prefix() {
         let root = RootMetadataBuilder::new()
             .root_key(KEYS[0].public().clone())
             .snapshot_key(KEYS[1].public().clone())
-            .targets_key(KEYS[2].public().clone())
-            .timestamp_key(KEYS[2].public().clone())
+            .targets_key(KEYS[1].public().clone())
+            .timestamp_key(KEYS[1].public().clone())
             .signed::<Json>(&KEYS[0])
             .unwrap();
 
-        let mut tuf = Tuf::from_trusted_root(root).unwrap();
-
-        let snapshot = SnapshotMetadataBuilder::new()
-            .version(2)
-            .signed::<Json>(&KEYS[2])
-            .unwrap();
+        let tuf = Tuf::from_trusted_root(root).unwrap();
 
-        let timestamp =
-            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
-                .unwrap()
-                .signed::<Json>(&KEYS[2])
-                .unwrap();
+        // KEYS[0] has the key ID a9f3ebc9..., and KEYS[1] has the key ID fd7b7741...
+        assert_eq!(tuf.find_key_by_prefix("a9f3").unwrap(), KEYS[0].public());
+        assert_eq!(tuf.find_key_by_prefix("A9F3EB").unwrap(), KEYS[0].public());
+        assert_eq!(tuf.find_key_by_prefix("fd7b").unwrap(), KEYS[1].public());
 
-        tuf.update_timestamp(timestamp).unwrap();
+        assert_eq!(tuf.find_key_by_prefix("0000"), Err(Error::NotFound));
+        assert_matches!(tuf.find_key_by_prefix(""), Err(Error::IllegalArgument(_)));
+        assert_matches!(
+            tuf.find_key_by_prefix("a9g"),
+            Err(Error::IllegalArgument(_))
+        );
+    }
 
-        let snapshot = SnapshotMetadataBuilder::new()
-            .version(1)
-            .signed::<Json>(&KEYS[1])
-            .unwrap();
+    #[test]
+    fn find_by_key_id_prefix_ambiguous() {
+        let first = KeyId::from_str(&format!("ab12{}", "0".repeat(60))).unwrap();
+        let second = KeyId::from_str(&format!("ab13{}", "0".repeat(60))).unwrap();
+        let candidates = vec![(&first, &1), (&second, &2), (&first, &1)];
 
-        assert!(tuf.update_snapshot(snapshot).is_err());
+        assert_eq!(
+            find_by_key_id_prefix("ab1", candidates.clone()),
+            Err(Error::AmbiguousKeyId {
+                prefix: "ab1".into(),
+                candidates: vec![first.clone(), second.clone()],
+            })
+        );
+        assert_eq!(find_by_key_id_prefix("ab12", candidates.clone()), Ok(&1));
+        assert_eq!(find_by_key_id_prefix("ab13", candidates), Ok(&2));
     }
 
     #[test]
-    fn good_targets_update() {
+    fn minimum_versions_reject_rollback() {
         let root = RootMetadataBuilder::new()
             .root_key(KEYS[0].public().clone())
             .snapshot_key(KEYS[1].public().clone())
-            .targets_key(KEYS[2].public().clone())
-            .timestamp_key(KEYS[3].public().clone())
+            .targets_key(KEYS[1].public().clone())
+            .timestamp_key(KEYS[1].public().clone())
             .signed::<Json>(&KEYS[0])
             .unwrap();
 
-        let signed_targets = TargetsMetadataBuilder::new()
-            .signed::<Json>(&KEYS[2])
-            .unwrap();
-
         let snapshot = SnapshotMetadataBuilder::new()
-            .insert_metadata(&signed_targets, &[HashAlgorithm::Sha256])
-            .unwrap()
             .signed::<Json>(&KEYS[1])
             .unwrap();
-
         let timestamp =
             TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
                 .unwrap()
-                .signed::<Json>(&KEYS[3])
+                .signed::<Json>(&KEYS[1])
                 .unwrap();
 
-        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        let minimum = |timestamp| TrustedVersions {
+            root: 1,
+            timestamp,
+            ..TrustedVersions::default()
+        };
 
-        tuf.update_timestamp(timestamp).unwrap();
-        tuf.update_snapshot(snapshot).unwrap();
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        tuf.set_minimum_versions(minimum(2));
+        assert_matches!(
+            tuf.update_timestamp(timestamp.clone()),
+            Err(Error::VerificationFailure(_))
+        );
+        assert_eq!(tuf.trusted_versions(), minimum(2));
+
+        // Metadata at exactly the minimum version is accepted.
+        tuf.set_minimum_versions(minimum(1));
+        assert!(tuf.update_timestamp(timestamp).unwrap().is_some());
+        assert_eq!(tuf.trusted_versions().timestamp(), 1);
+    }
+
+    #[test]
+    fn bad_timestamp_update_wrong_key() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[1].public().clone())
+            .targets_key(KEYS[1].public().clone())
+            .timestamp_key(KEYS[1].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                // sign it with the root key
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        assert!(tuf.update_timestamp(timestamp).is_err())
+    }
+
+    #[test]
+    fn good_snapshot_update() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[1].public().clone())
+            .targets_key(KEYS[2].public().clone())
+            .timestamp_key(KEYS[2].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new().signed(&KEYS[1]).unwrap();
+
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[2])
+                .unwrap();
+
+        tuf.update_timestamp(timestamp).unwrap();
+
+        assert_eq!(tuf.update_snapshot(snapshot.clone()), Ok(true));
+
+        // second update should do nothing
+        assert_eq!(tuf.update_snapshot(snapshot), Ok(false));
+    }
+
+    #[test]
+    fn bad_snapshot_update_wrong_key() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[1].public().clone())
+            .targets_key(KEYS[2].public().clone())
+            .timestamp_key(KEYS[2].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .signed::<Json>(&KEYS[2])
+            .unwrap();
+
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                // sign it with the targets key
+                .signed::<Json>(&KEYS[2])
+                .unwrap();
+
+        tuf.update_timestamp(timestamp).unwrap();
+
+        assert!(tuf.update_snapshot(snapshot).is_err());
+    }
+
+    #[test]
+    fn bad_snapshot_update_wrong_version() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[1].public().clone())
+            .targets_key(KEYS[2].public().clone())
+            .timestamp_key(KEYS[2].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .version(2)
+            .signed::<Json>(&KEYS[2])
+            .unwrap();
+
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[2])
+                .unwrap();
+
+        tuf.update_timestamp(timestamp).unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .version(1)
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+
+        assert!(tuf.update_snapshot(snapshot).is_err());
+    }
+
+    #[test]
+    fn good_targets_update() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[1].public().clone())
+            .targets_key(KEYS[2].public().clone())
+            .timestamp_key(KEYS[3].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let signed_targets = TargetsMetadataBuilder::new()
+            .signed::<Json>(&KEYS[2])
+            .unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&signed_targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[3])
+                .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
 
         assert_eq!(tuf.update_targets(signed_targets.clone()), Ok(true));
 
@@ -924,6 +2108,646 @@ mod test {
         assert_eq!(tuf.update_targets(signed_targets), Ok(false));
     }
 
+    #[test]
+    fn verify_target_checks_length_and_hashes() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let path = VirtualTargetPath::new("firmware.bin".into()).unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .insert_target_from_reader(path.clone(), &b"firmware"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
+        tuf.update_targets(targets).unwrap();
+
+        assert_eq!(tuf.verify_target(&path, &b"firmware"[..]), Ok(()));
+        assert_matches!(
+            tuf.verify_target(&path, &b"fIrmware"[..]),
+            Err(Error::VerificationFailure(_))
+        );
+        assert_matches!(
+            tuf.verify_target(&path, &b"firmware!"[..]),
+            Err(Error::VerificationFailure(_))
+        );
+        assert_matches!(
+            tuf.verify_target(&path, &b"firm"[..]),
+            Err(Error::VerificationFailure(_))
+        );
+        assert_matches!(
+            tuf.verify_target(
+                &VirtualTargetPath::new("missing.bin".into()).unwrap(),
+                &b"firmware"[..]
+            ),
+            Err(Error::TargetUnavailable)
+        );
+    }
+
+    #[test]
+    fn audit_hook_reports_decisions() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        tuf.set_audit_hook(Some(Arc::new(move |event: &AuditEvent| {
+            recorded.lock().unwrap().push(event.clone())
+        })));
+
+        let mut root = RootMetadataBuilder::new()
+            .version(2)
+            .root_key(KEYS[1].public().clone())
+            .snapshot_key(KEYS[1].public().clone())
+            .targets_key(KEYS[1].public().clone())
+            .timestamp_key(KEYS[1].public().clone())
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+        root.add_signature(&KEYS[0]).unwrap();
+        tuf.update_root(root).unwrap();
+
+        let path = VirtualTargetPath::new("firmware.bin".into()).unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .insert_target_from_reader(path.clone(), &b"firmware"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+
+        // Signed with the key the rotation replaced.
+        let bad_timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+        let error = tuf.update_timestamp(bad_timestamp).unwrap_err();
+
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[1])
+                .unwrap();
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
+        tuf.update_targets(targets).unwrap();
+        tuf.verify_target(&path, &b"firmware"[..]).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                AuditEvent::RootRotated {
+                    from_version: 1,
+                    to_version: 2,
+                },
+                AuditEvent::MetadataRejected {
+                    role: MetadataPath::from_role(&Role::Timestamp),
+                    error,
+                },
+                AuditEvent::TargetVerified {
+                    target: path,
+                    role: MetadataPath::from_role(&Role::Targets),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn clones_share_delegations() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let delegated_path = MetadataPath::new("delegated").unwrap();
+        let delegations = Delegations::new(
+            once((KEYS[1].key_id().clone(), KEYS[1].public().clone())).collect(),
+            vec![Delegation::new(
+                delegated_path.clone(),
+                false,
+                1,
+                once(KEYS[1].key_id().clone()).collect(),
+                once(VirtualTargetPath::new("foo".into()).unwrap()).collect(),
+            )
+            .unwrap()],
+        )
+        .unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .delegations(delegations)
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let delegated_targets = TargetsMetadataBuilder::new()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_metadata_with_path(
+                delegated_path.to_string(),
+                &delegated_targets,
+                &[HashAlgorithm::Sha256],
+            )
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
+        tuf.update_targets(targets).unwrap();
+        assert_eq!(
+            tuf.update_delegation(&delegated_path, delegated_targets),
+            Ok(true)
+        );
+
+        let clone = tuf.clone();
+        assert!(Arc::ptr_eq(
+            &tuf.delegations()[&delegated_path],
+            &clone.delegations()[&delegated_path],
+        ));
+    }
+
+    #[test]
+    fn trust_state_round_trip() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let delegated_path = MetadataPath::new("delegated").unwrap();
+        let delegations = Delegations::new(
+            once((KEYS[1].key_id().clone(), KEYS[1].public().clone())).collect(),
+            vec![Delegation::new(
+                delegated_path.clone(),
+                false,
+                1,
+                once(KEYS[1].key_id().clone()).collect(),
+                once(VirtualTargetPath::new("foo".into()).unwrap()).collect(),
+            )
+            .unwrap()],
+        )
+        .unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .delegations(delegations)
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        // Custom metadata holds arbitrary JSON, which has to survive the binary encoding too.
+        let delegated_targets = TargetsMetadataBuilder::new()
+            .insert_target_description(
+                VirtualTargetPath::new("foo".into()).unwrap(),
+                TargetDescription::from_reader_with_custom(
+                    &b"foo"[..],
+                    &[HashAlgorithm::Sha256],
+                    once((
+                        "release".to_string(),
+                        serde_json::json!({ "channel": "stable", "build": 42 }),
+                    ))
+                    .collect(),
+                )
+                .unwrap(),
+            )
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_metadata_with_path(
+                delegated_path.to_string(),
+                &delegated_targets,
+                &[HashAlgorithm::Sha256],
+            )
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+
+        // A root-only state round trips too.
+        let restored = Tuf::<Json>::from_trust_state(&tuf.to_trust_state().unwrap()).unwrap();
+        assert_eq!(restored.root(), tuf.root());
+        assert_eq!(restored.timestamp(), None);
+
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
+        tuf.update_targets(targets).unwrap();
+        tuf.update_delegation(&delegated_path, delegated_targets)
+            .unwrap();
+        let minimum_versions = TrustedVersions {
+            root: 5,
+            ..TrustedVersions::default()
+        };
+        tuf.set_minimum_versions(minimum_versions.clone());
+
+        let state = tuf.to_trust_state().unwrap();
+        assert_eq!(state, tuf.clone().to_trust_state().unwrap());
+
+        let restored = Tuf::<Json>::from_trust_state(&state).unwrap();
+        assert_eq!(restored.root(), tuf.root());
+        assert_eq!(restored.timestamp(), tuf.timestamp());
+        assert_eq!(restored.snapshot(), tuf.snapshot());
+        assert_eq!(restored.targets(), tuf.targets());
+        assert_eq!(restored.delegations(), tuf.delegations());
+        assert_eq!(restored.minimum_versions(), &minimum_versions);
+        assert_eq!(restored.trusted_versions(), tuf.trusted_versions());
+
+        let foo = VirtualTargetPath::new("foo".into()).unwrap();
+        assert_eq!(
+            restored.target_description(&foo).unwrap(),
+            tuf.target_description(&foo).unwrap()
+        );
+
+        // Corrupted state is rejected rather than partially restored.
+        let mut corrupted = state.clone();
+        corrupted.truncate(state.len() - 1);
+        assert_matches!(
+            Tuf::<Json>::from_trust_state(&corrupted),
+            Err(Error::Encoding(_))
+        );
+    }
+
+    #[test]
+    fn hashed_bins_resolve_targets() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let paths = (0..32)
+            .map(|i| VirtualTargetPath::new(format!("file-{}", i)).unwrap())
+            .collect::<Vec<_>>();
+        let mut builder = HashedBinsBuilder::new(8).key(KEYS[1].public().clone());
+        for path in &paths {
+            builder = builder
+                .insert_target_from_reader(
+                    path.clone(),
+                    path.value().as_bytes(),
+                    &[HashAlgorithm::Sha256],
+                )
+                .unwrap();
+        }
+        let bins = builder.signed::<Json>(&KEYS[1]).unwrap();
+
+        let targets = TargetsMetadataBuilder::new()
+            .delegations(bins.delegations().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let mut snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap();
+        for bin in bins.bins() {
+            snapshot = snapshot
+                .insert_metadata_with_path(
+                    bin.role().to_string(),
+                    bin.metadata(),
+                    &[HashAlgorithm::Sha256],
+                )
+                .unwrap();
+        }
+        let snapshot = snapshot.signed::<Json>(&KEYS[0]).unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
+        tuf.update_targets(targets).unwrap();
+        for bin in bins.bins() {
+            tuf.update_delegation(bin.role(), bin.metadata().clone())
+                .unwrap();
+        }
+
+        for path in &paths {
+            let expected =
+                TargetDescription::from_reader(path.value().as_bytes(), &[HashAlgorithm::Sha256])
+                    .unwrap();
+            assert_eq!(tuf.target_description(path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn path_hash_prefix_delegation_only_trusted_for_matching_targets() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let inside = VirtualTargetPath::new("inside".into()).unwrap();
+        let outside = VirtualTargetPath::new("outside".into()).unwrap();
+        let hash = crate::crypto::calculate_hash(inside.value().as_bytes(), HashAlgorithm::Sha256);
+
+        let bin_path = MetadataPath::new("bin").unwrap();
+        let delegations = Delegations::new(
+            once((KEYS[1].key_id().clone(), KEYS[1].public().clone())).collect(),
+            vec![Delegation::with_path_hash_prefixes(
+                bin_path.clone(),
+                false,
+                1,
+                once(KEYS[1].key_id().clone()).collect(),
+                once(hash.to_hex()).collect(),
+            )
+            .unwrap()],
+        )
+        .unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .delegations(delegations)
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let bin = TargetsMetadataBuilder::new()
+            .insert_target_from_reader(inside.clone(), &b"inside"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_target_from_reader(outside.clone(), &b"outside"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_metadata_with_path("bin", &bin, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
+        tuf.update_targets(targets).unwrap();
+        tuf.update_delegation(&bin_path, bin).unwrap();
+
+        assert!(tuf.target_description(&inside).is_ok());
+        assert_matches!(
+            tuf.target_description(&outside),
+            Err(Error::TargetUnavailable)
+        );
+    }
+
+    #[test]
+    fn find_target_description_fetches_delegations_in_order() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let foo = VirtualTargetPath::new("foo".into()).unwrap();
+        let bar = VirtualTargetPath::new("bar".into()).unwrap();
+        let a_path = MetadataPath::new("a").unwrap();
+        let b_path = MetadataPath::new("b").unwrap();
+
+        let delegations = Delegations::new(
+            hashmap! {
+                KEYS[1].key_id().clone() => KEYS[1].public().clone(),
+                KEYS[2].key_id().clone() => KEYS[2].public().clone(),
+            },
+            vec![
+                Delegation::new(
+                    a_path.clone(),
+                    false,
+                    1,
+                    hashset!(KEYS[1].key_id().clone()),
+                    hashset!(foo.clone()),
+                )
+                .unwrap(),
+                Delegation::new(
+                    b_path.clone(),
+                    false,
+                    1,
+                    hashset!(KEYS[2].key_id().clone()),
+                    hashset!(foo.clone(), bar.clone()),
+                )
+                .unwrap(),
+            ],
+        )
+        .unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .delegations(delegations)
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let a = TargetsMetadataBuilder::new()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+        let b = TargetsMetadataBuilder::new()
+            .insert_target_from_reader(foo.clone(), &b"foo"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_target_from_reader(bar.clone(), &b"bar"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[2])
+            .unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_metadata_with_path("a", &a, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_metadata_with_path("b", &b, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
+        tuf.update_targets(targets).unwrap();
+
+        let metadata = hashmap! { a_path.clone() => a, b_path.clone() => b };
+        let mut fetched = vec![];
+        let mut find = |tuf: &mut Tuf<Json>, target: &VirtualTargetPath| {
+            block_on(tuf.find_target_description(target, 8, |role, _| {
+                fetched.push(role.clone());
+                future::ready(Ok(metadata[role].clone()))
+            }))
+        };
+
+        // Only "b" is trusted for "bar", so "a" is never fetched.
+        assert_matches!(find(&mut tuf, &bar), Ok(_));
+        // "a" is searched before "b", which has already been verified.
+        assert_matches!(find(&mut tuf, &foo), Ok(_));
+        assert_eq!(fetched, vec![b_path, a_path]);
+    }
+
+    #[test]
+    fn all_targets_only_yields_trusted_targets() {
+        let root = RootMetadataBuilder::new()
+            .root_key(KEYS[0].public().clone())
+            .snapshot_key(KEYS[0].public().clone())
+            .targets_key(KEYS[0].public().clone())
+            .timestamp_key(KEYS[0].public().clone())
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+
+        let path = |p: &str| VirtualTargetPath::new(p.into()).unwrap();
+        let a_path = MetadataPath::new("a").unwrap();
+        let b_path = MetadataPath::new("b").unwrap();
+        let delegations = Delegations::new(
+            hashmap! {
+                KEYS[1].key_id().clone() => KEYS[1].public().clone(),
+                KEYS[2].key_id().clone() => KEYS[2].public().clone(),
+            },
+            vec![
+                Delegation::new(
+                    a_path.clone(),
+                    true,
+                    1,
+                    once(KEYS[1].key_id().clone()).collect(),
+                    once(path("a/")).collect(),
+                )
+                .unwrap(),
+                Delegation::new(
+                    b_path.clone(),
+                    false,
+                    1,
+                    once(KEYS[2].key_id().clone()).collect(),
+                    vec![path("a/"), path("b/")].into_iter().collect(),
+                )
+                .unwrap(),
+            ],
+        )
+        .unwrap();
+        let targets = TargetsMetadataBuilder::new()
+            .insert_target_from_reader(path("top"), &b"top"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .delegations(delegations)
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let a = TargetsMetadataBuilder::new()
+            .insert_target_from_reader(path("a/1"), &b"a/1"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            // Outside of the paths delegated to "a".
+            .insert_target_from_reader(path("b/1"), &b"b/1"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[1])
+            .unwrap();
+        let b = TargetsMetadataBuilder::new()
+            // Shadowed by the terminating delegation to "a".
+            .insert_target_from_reader(path("a/2"), &b"a/2"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_target_from_reader(path("b/2"), &b"b/2"[..], &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[2])
+            .unwrap();
+
+        let snapshot = SnapshotMetadataBuilder::new()
+            .insert_metadata(&targets, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_metadata_with_path("a", &a, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .insert_metadata_with_path("b", &b, &[HashAlgorithm::Sha256])
+            .unwrap()
+            .signed::<Json>(&KEYS[0])
+            .unwrap();
+        let timestamp =
+            TimestampMetadataBuilder::from_snapshot(&snapshot, &[HashAlgorithm::Sha256])
+                .unwrap()
+                .signed::<Json>(&KEYS[0])
+                .unwrap();
+
+        let mut tuf = Tuf::from_trusted_root(root).unwrap();
+        assert!(tuf.all_targets().is_err());
+
+        tuf.update_timestamp(timestamp).unwrap();
+        tuf.update_snapshot(snapshot).unwrap();
+        tuf.update_targets(targets).unwrap();
+        assert_eq!(
+            tuf.all_targets()
+                .unwrap()
+                .map(|(p, _)| p)
+                .collect::<HashSet<_>>(),
+            hashset! { path("top") }
+        );
+
+        let loaded = |tuf: &Tuf<Json>| {
+            tuf.delegation_graph()
+                .unwrap()
+                .roles()
+                .iter()
+                .map(|r| r.loaded())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(loaded(&tuf), vec![true, false, false]);
+
+        tuf.update_delegation(&a_path, a).unwrap();
+        tuf.update_delegation(&b_path, b).unwrap();
+        assert_eq!(loaded(&tuf), vec![true, true, true]);
+
+        let all = tuf.all_targets().unwrap().collect::<Vec<_>>();
+        assert_eq!(
+            all.iter().map(|(p, _)| p.clone()).collect::<HashSet<_>>(),
+            hashset! { path("top"), path("a/1"), path("b/2") }
+        );
+        assert_eq!(all.len(), 3);
+        for (p, description) in all {
+            assert_eq!(&tuf.target_description(&p).unwrap(), description);
+        }
+        assert_matches!(
+            tuf.target_description(&path("a/2")),
+            Err(Error::TargetUnavailable)
+        );
+    }
+
     #[test]
     fn bad_targets_update_wrong_key() {
         let root = RootMetadataBuilder::new()
@@ -0,0 +1,89 @@
+//! Structured events describing the security decisions made while verifying metadata and targets.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::metadata::{MetadataPath, VirtualTargetPath};
+
+/// A security decision made by a [`Tuf`](super::Tuf) or a [`Client`](crate::client::Client).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AuditEvent {
+    /// A new root metadata was verified and is now trusted.
+    RootRotated {
+        /// The version of the previously trusted root metadata.
+        from_version: u32,
+        /// The version of the newly trusted root metadata.
+        to_version: u32,
+    },
+
+    /// Metadata failed verification and was not trusted.
+    MetadataRejected {
+        /// The role of the rejected metadata.
+        role: MetadataPath,
+        /// Why the metadata was rejected.
+        error: Error,
+    },
+
+    /// A target matched its trusted length and hashes.
+    TargetVerified {
+        /// The target that was verified.
+        target: VirtualTargetPath,
+        /// The targets role, either the top-level targets role or a delegated role, whose
+        /// description of the target it was verified against.
+        role: MetadataPath,
+    },
+}
+
+/// Receives an [`AuditEvent`] for every security decision, so that applications can keep a
+/// security audit log without parsing the `log` output of this crate.
+///
+/// Hooks are called synchronously while metadata and targets are verified, so they should return
+/// quickly, for example by sending the event to a channel.
+///
+/// Any `Fn(&AuditEvent)` closure is an `AuditHook`.
+pub trait AuditHook: Send + Sync {
+    /// Handle `event`.
+    fn on_event(&self, event: &AuditEvent);
+}
+
+impl<F> AuditHook for F
+where
+    F: Fn(&AuditEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &AuditEvent) {
+        self(event)
+    }
+}
+
+/// The optional `AuditHook` of a `Tuf` or a `Config`.
+#[derive(Clone, Default)]
+pub(crate) struct SharedAuditHook(pub(crate) Option<Arc<dyn AuditHook>>);
+
+impl SharedAuditHook {
+    pub(crate) fn emit(&self, event: AuditEvent) {
+        if let Some(ref hook) = self.0 {
+            hook.on_event(&event);
+        }
+    }
+}
+
+impl fmt::Debug for SharedAuditHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "SharedAuditHook(Some(..))"),
+            None => write!(f, "SharedAuditHook(None)"),
+        }
+    }
+}
+
+impl PartialEq for SharedAuditHook {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Sources of the current time used to check metadata expirations.
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use std::fmt;
+use std::sync::Arc;
+
+/// A source of the current time, against which a [`Tuf`](super::Tuf) checks whether metadata has
+/// expired.
+///
+/// The default is [`SystemClock`]. Devices without a reliable real-time clock can instead supply
+/// a time from a trusted source, such as a secure time service, and tests can use a
+/// [`FixedClock`] to check expirations deterministically.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// A `Clock` that reads the system time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A `Clock` that is stopped at a given time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedClock(DateTime<Utc>);
+
+impl FixedClock {
+    /// Create a new `FixedClock` that always reads `now`.
+    pub fn new(now: DateTime<Utc>) -> Self {
+        FixedClock(now)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// The `Clock` used by a `Tuf`.
+#[derive(Clone)]
+pub(crate) struct SharedClock(pub(crate) Arc<dyn Clock>);
+
+impl SharedClock {
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        self.0.now()
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        SharedClock(Arc::new(SystemClock))
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedClock(..)")
+    }
+}
+
+impl PartialEq for SharedClock {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
@@ -0,0 +1,71 @@
+//! A compact encoding of the verified state of a [`Tuf`](super::Tuf) for local persistence.
+//!
+//! The state is serialized with serde as CBOR. Unlike bincode or postcard, CBOR is self-describing,
+//! which the serde implementations of the metadata types need: they skip absent optional fields,
+//! flatten the delegated roles, and keep custom target metadata as arbitrary JSON values. Only the
+//! verified `signed` portion of the metadata is kept, so signatures and unknown fields of the wire
+//! format are dropped.
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::TrustedVersions;
+use crate::error::Error;
+use crate::metadata::{
+    MetadataPath, RootMetadata, SnapshotMetadata, TargetsMetadata, TimestampMetadata,
+};
+use crate::Result;
+
+/// The verified metadata held by a `Tuf`, borrowed so that it can be encoded without copying it.
+#[derive(Serialize)]
+pub(crate) struct TrustStateRef<'a> {
+    pub(crate) root: &'a RootMetadata,
+    pub(crate) timestamp: Option<&'a TimestampMetadata>,
+    pub(crate) snapshot: Option<&'a SnapshotMetadata>,
+    pub(crate) targets: Option<&'a TargetsMetadata>,
+    /// Sorted, so the same state always encodes to the same bytes.
+    pub(crate) delegations: BTreeMap<&'a MetadataPath, &'a TargetsMetadata>,
+    pub(crate) minimum_versions: &'a TrustedVersions,
+}
+
+impl TrustStateRef<'_> {
+    pub(crate) fn encode(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(self, &mut buf)
+            .map_err(|e| Error::Encoding(format!("Trust state: {}", e)))?;
+        Ok(buf)
+    }
+}
+
+/// The verified metadata held by a `Tuf`, as decoded from a [`TrustStateRef`].
+#[derive(Deserialize)]
+pub(crate) struct TrustState {
+    pub(crate) root: RootMetadata,
+    pub(crate) timestamp: Option<TimestampMetadata>,
+    pub(crate) snapshot: Option<SnapshotMetadata>,
+    pub(crate) targets: Option<TargetsMetadata>,
+    pub(crate) delegations: BTreeMap<MetadataPath, TargetsMetadata>,
+    pub(crate) minimum_versions: TrustedVersions,
+}
+
+impl TrustState {
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self> {
+        ciborium::from_reader(bytes).map_err(|e| Error::Encoding(format!("Trust state: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_rejects_other_data() {
+        assert!(TrustState::decode(b"").is_err());
+        assert!(TrustState::decode(b"TUFS\x01").is_err());
+
+        // Well formed CBOR without a root.
+        let mut buf = Vec::new();
+        ciborium::into_writer(&BTreeMap::<String, u32>::new(), &mut buf).unwrap();
+        assert!(TrustState::decode(&buf).is_err());
+    }
+}
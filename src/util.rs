@@ -1,13 +1,19 @@
 use futures_io::AsyncRead;
+use futures_util::future::BoxFuture;
+use futures_util::io::{copy, AllowStdIo};
 use futures_util::ready;
-use ring::digest;
-use std::io::{self, ErrorKind};
+use std::future::Future;
+use std::io::{self, ErrorKind, Write};
 use std::marker::Unpin;
+use std::path::Path;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
 use std::time::{Duration, Instant};
+use tempfile::NamedTempFile;
 
-use crate::crypto::{HashAlgorithm, HashValue};
+use crate::crypto::{HashAlgorithm, HashValue, SafeReader};
 use crate::Result;
 
 pub(crate) trait SafeAsyncRead: Sized {
@@ -42,7 +48,7 @@ where
         max_length: u64,
         hash_data: Option<(&HashAlgorithm, HashValue)>,
     ) -> Result<SafeReader<Self>> {
-        SafeReader::new(self, max_length, hash_data)
+        SafeReader::with_hash(self, max_length, hash_data)
     }
 }
 
@@ -104,86 +110,124 @@ impl<R: AsyncRead + Unpin> AsyncRead for EnforceMinimumBitrate<R> {
     }
 }
 
-/// Wrapper to verify a byte stream as it is read.
+/// Write `bytes` to `path` so that readers observe either the previous contents or all of
+/// `bytes`, even if the process or machine crashes part way through.
 ///
-/// Wraps an `AsyncRead` to ensure that the consumer can't read more than a capped maximum number of
-/// bytes. Also, when the underlying `AsyncRead` is fully consumed, the hash of the data is
-/// optionally calculated. If the calculated hash does not match the given hash, it will return an
-/// `Err`. Consumers of a `SafeReader` should purge and untrust all read bytes if this ever returns
-/// an `Err`.
-///
-/// It is **critical** that none of the bytes from this struct are used until it has been fully
-/// consumed as the data is untrusted.
-pub(crate) struct SafeReader<R> {
-    inner: R,
-    max_size: u64,
-    hasher: Option<(digest::Context, HashValue)>,
-    bytes_read: u64,
+/// The bytes go to a temporary file in the same directory, which is synced to disk before being
+/// renamed over `path`. On unix the directory is then synced so the rename itself is durable.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut temp_file = temp_file_beside(path)?;
+    temp_file.write_all(bytes)?;
+    persist_durably(temp_file, path)
 }
 
-impl<R: AsyncRead> SafeReader<R> {
-    /// Create a new `SafeReader`.
-    ///
-    /// The argument `hash_data` takes a `HashAlgorithm` and expected `HashValue`. The given
-    /// algorithm is used to hash the data as it is read. At the end of the stream, the digest is
-    /// calculated and compared against `HashValue`. If the two are not equal, it means the data
-    /// stream has been corrupted or tampered with in some way.
-    pub(crate) fn new(
-        read: R,
-        max_size: u64,
-        hash_data: Option<(&HashAlgorithm, HashValue)>,
-    ) -> Result<Self> {
-        let hasher = match hash_data {
-            Some((alg, value)) => Some((alg.digest_context()?, value)),
-            None => None,
-        };
+/// Like [`write_atomically`], but streams the contents from `read`. If reading fails part way
+/// through, the temporary file is removed and `path` is left untouched.
+pub(crate) async fn copy_atomically<R>(path: &Path, read: R) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut temp_file = AllowStdIo::new(temp_file_beside(path)?);
+    copy(read, &mut temp_file).await?;
+    persist_durably(temp_file.into_inner(), path)
+}
 
-        Ok(SafeReader {
-            inner: read,
-            max_size,
-            hasher,
-            bytes_read: 0,
-        })
+/// Rename the file at `from` over `to`, which must be in the same directory, so that the rename
+/// survives a crash.
+pub(crate) fn rename_durably(from: &Path, to: &Path) -> Result<()> {
+    std::fs::rename(from, to)?;
+
+    #[cfg(unix)]
+    std::fs::File::open(parent_dir(to))?.sync_all()?;
+
+    Ok(())
+}
+
+fn temp_file_beside(path: &Path) -> Result<NamedTempFile> {
+    // The temporary file must live on the same mountpoint as `path`, or the rename is not atomic.
+    Ok(NamedTempFile::new_in(parent_dir(path))?)
+}
+
+fn persist_durably(temp_file: NamedTempFile, path: &Path) -> Result<()> {
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(path)?;
+
+    #[cfg(unix)]
+    std::fs::File::open(parent_dir(path))?.sync_all()?;
+
+    Ok(())
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
     }
 }
 
-impl<R: AsyncRead + Unpin> AsyncRead for SafeReader<R> {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context,
-        buf: &mut [u8],
-    ) -> Poll<io::Result<usize>> {
-        let read_bytes = ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+/// Whether a `ThreadSleep` has finished, and the waker to call when it does.
+type SleepState = Arc<Mutex<(bool, Option<Waker>)>>;
+
+/// Wait for `delay` on a separate thread. Dropping the future before it finishes stops the
+/// thread early.
+pub(crate) fn thread_sleep(delay: Duration) -> BoxFuture<'static, ()> {
+    Box::pin(ThreadSleep {
+        delay,
+        state: None,
+        timer: None,
+    })
+}
 
-        if read_bytes == 0 {
-            if let Some((context, expected_hash)) = self.hasher.take() {
-                let generated_hash = context.finish();
-                if generated_hash.as_ref() != expected_hash.value() {
-                    return Poll::Ready(Err(io::Error::new(
-                        ErrorKind::InvalidData,
-                        "Calculated hash did not match the required hash.",
-                    )));
+struct ThreadSleep {
+    delay: Duration,
+    state: Option<SleepState>,
+    timer: Option<thread::Thread>,
+}
+
+impl Future for ThreadSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.state.is_none() {
+            let state: SleepState = Arc::new(Mutex::new((false, None)));
+            let timer_state = Arc::clone(&state);
+            let deadline = Instant::now() + self.delay;
+            let timer = thread::spawn(move || {
+                loop {
+                    let now = Instant::now();
+                    // The sleep is marked finished early when it is dropped.
+                    if timer_state.lock().unwrap().0 || now >= deadline {
+                        break;
+                    }
+                    thread::park_timeout(deadline - now);
                 }
-            }
 
-            return Poll::Ready(Ok(0));
+                let mut state = timer_state.lock().unwrap();
+                state.0 = true;
+                if let Some(waker) = state.1.take() {
+                    waker.wake();
+                }
+            });
+            self.timer = Some(timer.thread().clone());
+            self.state = Some(state);
         }
 
-        match self.bytes_read.checked_add(read_bytes as u64) {
-            Some(sum) if sum <= self.max_size => self.bytes_read = sum,
-            _ => {
-                return Poll::Ready(Err(io::Error::new(
-                    ErrorKind::InvalidData,
-                    "Read exceeded the maximum allowed bytes.",
-                )));
-            }
+        let mut state = self.state.as_ref().unwrap().lock().unwrap();
+        if state.0 {
+            Poll::Ready(())
+        } else {
+            state.1 = Some(cx.waker().clone());
+            Poll::Pending
         }
+    }
+}
 
-        if let Some((ref mut context, _)) = self.hasher {
-            context.update(&buf[..(read_bytes)]);
+impl Drop for ThreadSleep {
+    fn drop(&mut self) {
+        if let (Some(state), Some(timer)) = (&self.state, &self.timer) {
+            state.lock().unwrap().0 = true;
+            timer.unpark();
         }
-
-        Poll::Ready(Ok(read_bytes))
     }
 }
 
@@ -192,134 +236,44 @@ mod test {
     use super::*;
     use futures_executor::block_on;
     use futures_util::io::AsyncReadExt;
-    use ring::digest::SHA256;
 
     #[test]
-    fn valid_read() {
-        block_on(async {
-            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
-            let mut reader = SafeReader::new(bytes, bytes.len() as u64, None).unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_ok());
-            assert_eq!(buf, bytes);
-        })
+    fn thread_sleep_waits() {
+        let start = Instant::now();
+        block_on(thread_sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
     }
 
     #[test]
-    fn valid_read_large_data() {
-        block_on(async {
-            let bytes: &[u8] = &[0x00; 64 * 1024];
-            let mut reader = SafeReader::new(bytes, bytes.len() as u64, None).unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_ok());
-            assert_eq!(buf, bytes);
-        })
-    }
+    fn write_atomically_replaces_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo");
 
-    #[test]
-    fn valid_read_below_max_size() {
-        block_on(async {
-            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
-            let mut reader = SafeReader::new(bytes, (bytes.len() as u64) + 1, None).unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_ok());
-            assert_eq!(buf, bytes);
-        })
-    }
+        write_atomically(&path, b"first").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
 
-    #[test]
-    fn invalid_read_above_max_size() {
-        block_on(async {
-            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
-            let mut reader = SafeReader::new(bytes, (bytes.len() as u64) - 1, None).unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_err());
-        })
-    }
+        write_atomically(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
 
-    #[test]
-    fn invalid_read_above_max_size_large_data() {
-        block_on(async {
-            let bytes: &[u8] = &[0x00; 64 * 1024];
-            let mut reader = SafeReader::new(bytes, (bytes.len() as u64) - 1, None).unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_err());
-        })
+        // Only the target file is left behind.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
     }
 
     #[test]
-    fn valid_read_good_hash() {
+    fn copy_atomically_leaves_file_alone_on_error() {
         block_on(async {
-            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
-            let mut context = digest::Context::new(&SHA256);
-            context.update(&bytes);
-            let hash_value = HashValue::new(context.finish().as_ref().to_vec());
-            let mut reader = SafeReader::new(
-                bytes,
-                bytes.len() as u64,
-                Some((&HashAlgorithm::Sha256, hash_value)),
-            )
-            .unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_ok());
-            assert_eq!(buf, bytes);
-        })
-    }
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("foo");
 
-    #[test]
-    fn invalid_read_bad_hash() {
-        block_on(async {
-            let bytes: &[u8] = &[0x00, 0x01, 0x02, 0x03];
-            let mut context = digest::Context::new(&SHA256);
-            context.update(&bytes);
-            context.update(&[0xFF]); // evil bytes
-            let hash_value = HashValue::new(context.finish().as_ref().to_vec());
-            let mut reader = SafeReader::new(
-                bytes,
-                bytes.len() as u64,
-                Some((&HashAlgorithm::Sha256, hash_value)),
-            )
-            .unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_err());
-        })
-    }
+            copy_atomically(&path, &b"first"[..]).await.unwrap();
+            assert_eq!(std::fs::read(&path).unwrap(), b"first");
 
-    #[test]
-    fn valid_read_good_hash_large_data() {
-        block_on(async {
-            let bytes: &[u8] = &[0x00; 64 * 1024];
-            let mut context = digest::Context::new(&SHA256);
-            context.update(&bytes);
-            let hash_value = HashValue::new(context.finish().as_ref().to_vec());
-            let mut reader = SafeReader::new(
-                bytes,
-                bytes.len() as u64,
-                Some((&HashAlgorithm::Sha256, hash_value)),
-            )
-            .unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_ok());
-            assert_eq!(buf, bytes);
-        })
-    }
+            let read = SafeReader::with_hash(&b"too long"[..], 4, None).unwrap();
+            assert!(copy_atomically(&path, read).await.is_err());
+            assert_eq!(std::fs::read(&path).unwrap(), b"first");
 
-    #[test]
-    fn invalid_read_bad_hash_large_data() {
-        block_on(async {
-            let bytes: &[u8] = &[0x00; 64 * 1024];
-            let mut context = digest::Context::new(&SHA256);
-            context.update(&bytes);
-            context.update(&[0xFF]); // evil bytes
-            let hash_value = HashValue::new(context.finish().as_ref().to_vec());
-            let mut reader = SafeReader::new(
-                bytes,
-                bytes.len() as u64,
-                Some((&HashAlgorithm::Sha256, hash_value)),
-            )
-            .unwrap();
-            let mut buf = Vec::new();
-            assert!(reader.read_to_end(&mut buf).await.is_err());
+            // The temporary file was cleaned up.
+            assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
         })
     }
 
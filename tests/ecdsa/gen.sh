@@ -0,0 +1,51 @@
+#!/bin/bash
+set -eux
+
+cd "$(dirname "$0")"
+
+for curve in P-256 P-384; do
+    key="ecdsa-$(echo "$curve" | tr -d '-' | tr '[:upper:]' '[:lower:]')"
+    pk8="$key.pk8.der"
+    spki="$key.spki.der"
+
+    if [ ! -f "$pk8" ]; then
+        openssl genpkey -algorithm EC \
+                        -pkeyopt "ec_paramgen_curve:$curve" \
+                        -pkeyopt ec_param_enc:named_curve | \
+            openssl pkcs8 -topk8 -nocrypt -outform der -out "$pk8"
+    fi
+
+    openssl pkey -in "$pk8" \
+                 -inform der \
+                 -pubout \
+                 -outform der \
+                 -out "$spki"
+done
+
+# Public keys as python-tuf lists them: PEM encoded SPKI, keyed by the ID securesystemslib derives,
+# the SHA-256 of the key's canonical JSON. Canonical JSON only escapes `"` and `\`, so the newlines
+# of the PEM block are hashed as they are.
+for curve in p256 p384; do
+    key="ecdsa-$curve"
+    pem="$(openssl pkey -in "$key.spki.der" -inform der -pubin -pubout -outform pem)"
+    python3 - "$curve" "$pem" > "$key.python-tuf.json" <<'PY'
+import hashlib, json, sys
+curve, pem = sys.argv[1], sys.argv[2] + "\n"
+scheme = {"p256": "ecdsa-sha2-nistp256", "p384": "ecdsa-sha2-nistp384"}[curve]
+key = {
+    "keytype": scheme,
+    "scheme": scheme,
+    "keyid_hash_algorithms": ["sha256", "sha512"],
+    "keyval": {"public": pem},
+}
+def canonical(value):
+    if isinstance(value, dict):
+        items = sorted(value.items())
+        return "{" + ",".join(canonical(k) + ":" + canonical(v) for k, v in items) + "}"
+    if isinstance(value, list):
+        return "[" + ",".join(canonical(v) for v in value) + "]"
+    return '"' + value.replace("\\", "\\\\").replace('"', '\\"') + '"'
+keyid = hashlib.sha256(canonical(key).encode()).hexdigest()
+print(json.dumps({keyid: key}, indent=2, sort_keys=True))
+PY
+done
@@ -0,0 +1,20 @@
+#!/bin/bash
+set -eux
+
+cd "$(dirname "$0")"
+
+# The unencrypted keys predate this script. Only the encrypted copies are generated here.
+openssl pkcs8 -topk8 \
+              -in ed25519-1.pk8.der \
+              -inform der \
+              -v2 aes-256-cbc \
+              -v2prf hmacWithSHA256 \
+              -passout pass:hunter2 \
+              -outform der \
+              -out ed25519-1.pk8.enc.der
+
+# An OpenSSH key, and a copy of it protected with a passphrase (bcrypt KDF, aes256-ctr).
+rm -f ed25519-openssh ed25519-openssh.pub
+ssh-keygen -q -t ed25519 -N "" -C "tuf test key" -f ed25519-openssh
+cp ed25519-openssh ed25519-openssh.enc
+ssh-keygen -q -p -P "" -N hunter2 -f ed25519-openssh.enc
@@ -1,10 +1,12 @@
+use futures_executor::block_on;
+use futures_util::future;
 use maplit::hashmap;
 use matches::assert_matches;
 use std::iter::once;
 use tuf::crypto::{HashAlgorithm, PrivateKey, SignatureScheme};
 use tuf::interchange::Json;
 use tuf::metadata::{
-    Delegation, Delegations, MetadataDescription, MetadataPath, Role, RootMetadataBuilder,
+    Delegation, Delegations, MetadataDescription, MetadataPath, RootMetadataBuilder,
     SnapshotMetadataBuilder, TargetsMetadataBuilder, TimestampMetadataBuilder, VirtualTargetPath,
 };
 use tuf::Error;
@@ -98,12 +100,8 @@ fn simple_delegation() {
         .signed::<Json>(&delegation_key)
         .unwrap();
 
-    tuf.update_delegation(
-        &MetadataPath::from_role(&Role::Targets),
-        &MetadataPath::new("delegation").unwrap(),
-        delegation,
-    )
-    .unwrap();
+    tuf.update_delegation(&MetadataPath::new("delegation").unwrap(), delegation)
+        .unwrap();
 
     assert!(tuf
         .target_description(&VirtualTargetPath::new("foo".into()).unwrap())
@@ -207,12 +205,8 @@ fn nested_delegation() {
         .signed::<Json>(&delegation_a_key)
         .unwrap();
 
-    tuf.update_delegation(
-        &MetadataPath::from_role(&Role::Targets),
-        &MetadataPath::new("delegation-a").unwrap(),
-        delegation,
-    )
-    .unwrap();
+    tuf.update_delegation(&MetadataPath::new("delegation-a").unwrap(), delegation)
+        .unwrap();
 
     //// build delegation B ////
 
@@ -228,12 +222,8 @@ fn nested_delegation() {
         .signed::<Json>(&delegation_b_key)
         .unwrap();
 
-    tuf.update_delegation(
-        &MetadataPath::new("delegation-a").unwrap(),
-        &MetadataPath::new("delegation-b").unwrap(),
-        delegation,
-    )
-    .unwrap();
+    tuf.update_delegation(&MetadataPath::new("delegation-b").unwrap(), delegation)
+        .unwrap();
 
     assert!(tuf
         .target_description(&VirtualTargetPath::new("foo".into()).unwrap())
@@ -324,11 +314,7 @@ fn rejects_bad_delegation_signatures() {
         .unwrap();
 
     assert_matches!(
-        tuf.update_delegation(
-            &MetadataPath::from_role(&Role::Targets),
-            &MetadataPath::new("delegation").unwrap(),
-            delegation
-        ),
+        tuf.update_delegation(&MetadataPath::new("delegation").unwrap(), delegation),
         Err(Error::VerificationFailure(_))
     );
 
@@ -471,12 +457,8 @@ fn diamond_delegation() {
         .signed::<Json>(&delegation_a_key)
         .unwrap();
 
-    tuf.update_delegation(
-        &MetadataPath::from_role(&Role::Targets),
-        &MetadataPath::new("delegation-a").unwrap(),
-        delegation,
-    )
-    .unwrap();
+    tuf.update_delegation(&MetadataPath::new("delegation-a").unwrap(), delegation)
+        .unwrap();
 
     //// build delegation B ////
 
@@ -499,12 +481,8 @@ fn diamond_delegation() {
         .signed::<Json>(&delegation_b_key)
         .unwrap();
 
-    tuf.update_delegation(
-        &MetadataPath::from_role(&Role::Targets),
-        &MetadataPath::new("delegation-b").unwrap(),
-        delegation,
-    )
-    .unwrap();
+    tuf.update_delegation(&MetadataPath::new("delegation-b").unwrap(), delegation)
+        .unwrap();
 
     //// build delegation C ////
 
@@ -527,24 +505,24 @@ fn diamond_delegation() {
         .signed::<Json>(&delegation_c_key)
         .unwrap();
 
-    //// Verify delegation-c is valid, but only when updated through delegation-a.
-
-    tuf.update_delegation(
-        &MetadataPath::new("delegation-a").unwrap(),
-        &MetadataPath::new("delegation-c").unwrap(),
-        delegation.clone(),
-    )
-    .unwrap();
+    //// delegation-c is delegated to by both delegation-a and delegation-b, so which keys to
+    //// verify it with depends on the target.
 
     assert_matches!(
         tuf.update_delegation(
-            &MetadataPath::new("delegation-b").unwrap(),
             &MetadataPath::new("delegation-c").unwrap(),
-            delegation
+            delegation.clone()
         ),
         Err(Error::VerificationFailure(_))
     );
 
+    block_on(tuf.find_target_description(
+        &VirtualTargetPath::new("foo".into()).unwrap(),
+        8,
+        |_, _| future::ready(Ok(delegation.clone())),
+    ))
+    .unwrap();
+
     assert!(tuf
         .target_description(&VirtualTargetPath::new("foo".into()).unwrap())
         .is_ok());
@@ -274,6 +274,7 @@ async fn generate_repos(dir: &str, consistent_snapshot: bool) -> tuf::Result<()>
                 keys.insert("timestamp", json_keys.timestamp[1][0].to_private_key());
                 None
             }
+            Some(role) => unreachable!("no keys to rotate for {}", role),
             None => None,
         };
         update_root(
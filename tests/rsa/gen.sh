@@ -37,3 +37,24 @@ for key_size in 2048 4096; do
                   -out "$pk8" \
                   -nocrypt
 done
+
+# PEM copies of the PKCS#1 encodings of one of the keys.
+openssl rsa -in rsa-2048.der \
+            -inform der \
+            -traditional \
+            -out rsa-2048.pkcs1.pem
+
+openssl rsa -in rsa-2048.der \
+            -inform der \
+            -RSAPublicKey_out \
+            -out rsa-2048.pkcs1.pub.pem
+
+# An encrypted copy with the older PBES2 parameters, where the PRF is left at its default.
+openssl pkcs8 -topk8 \
+              -inform der \
+              -in rsa-2048.der \
+              -v2 aes-128-cbc \
+              -v2prf hmacWithSHA1 \
+              -passout pass:hunter2 \
+              -outform der \
+              -out rsa-2048.pk8.enc.der
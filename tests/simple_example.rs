@@ -101,7 +101,8 @@ where
     .await?;
     let _ = client.update().await?;
     let target_path = TargetPath::new("foo-bar".into())?;
-    client.fetch_target(&target_path).await
+    let _ = client.fetch_target(&target_path).await?;
+    Ok(())
 }
 
 async fn init_server<'a, T>(